@@ -19,7 +19,7 @@ fn main() -> Result<(), DriverError> {
 
     let n = 3;
     let cfg = LaunchConfig::for_num_elems(n);
-    unsafe { f.launch(cfg, (&mut b_dev, &a_dev, n as i32)) }?;
+    unsafe { f.launch(dev.default_stream(), cfg, (&mut b_dev, &a_dev, n as i32)) }?;
 
     let a_host_2 = dev.sync_reclaim(a_dev)?;
     let b_host = dev.sync_reclaim(b_dev)?;
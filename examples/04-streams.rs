@@ -24,7 +24,7 @@ fn main() -> Result<(), DriverError> {
     let f = dev.get_func("sin", "sin_kernel").unwrap();
 
     // we launch it differently too
-    unsafe { f.launch_on_stream(&stream, cfg, (&mut b_dev, &a_dev, n as i32)) }?;
+    unsafe { f.launch(&stream, cfg, (&mut b_dev, &a_dev, n as i32)) }?;
 
     // and we must join with the default work stream in order for copies
     // to work corrently.
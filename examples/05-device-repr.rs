@@ -46,7 +46,13 @@ fn main() -> Result<(), DriverError> {
     let f = dev.get_func("module", "my_custom_kernel").unwrap();
 
     // since MyCoolRustStruct implements DeviceRepr, we can pass it to launch.
-    unsafe { f.launch(LaunchConfig::for_num_elems(1), (thing,)) }?;
+    unsafe {
+        f.launch(
+            dev.default_stream(),
+            LaunchConfig::for_num_elems(1),
+            (thing,),
+        )
+    }?;
 
     Ok(())
 }
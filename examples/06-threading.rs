@@ -36,7 +36,7 @@ fn main() -> Result<(), DriverError> {
                     // without this, you'll get a CUDA_ERROR_INVALID_CONTEXT
                     dev.bind_to_thread()?;
                     let f = dev.get_func("kernel", "hello_world").unwrap();
-                    unsafe { f.launch(cfg, (i,)) }
+                    unsafe { f.launch(dev.default_stream(), cfg, (i,)) }
                 });
             }
         });
@@ -55,7 +55,7 @@ fn main() -> Result<(), DriverError> {
                     let dev = CudaDevice::new(0)?;
                     dev.load_ptx(ptx, "kernel", &["hello_world"])?;
                     let f = dev.get_func("kernel", "hello_world").unwrap();
-                    unsafe { f.launch(cfg, (i + 100,)) }
+                    unsafe { f.launch(dev.default_stream(), cfg, (i + 100,)) }
                 });
             }
         });
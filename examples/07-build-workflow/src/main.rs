@@ -59,7 +59,8 @@ fn main() -> Result<(), DriverError> {
 
     let f = gpu.get_func("my_module", "my_struct_kernel").unwrap();
 
-    unsafe { f.launch(LaunchConfig::for_num_elems(n as u32), (&gpu_my_structs, n)) }?;
+    let cfg = LaunchConfig::for_num_elems(n as u32);
+    unsafe { f.launch(gpu.default_stream(), cfg, (&gpu_my_structs, n)) }?;
 
     println!("Time taken to call kernel: {:.2?}", now.elapsed());
 
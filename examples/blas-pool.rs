@@ -0,0 +1,42 @@
+use cudarc::cublas::{CudaBlas, CudaBlasPool};
+use cudarc::driver::CudaDevice;
+use std::time::Instant;
+
+/// Compares creating a [CudaBlas] handle per request against checking one out of a
+/// [CudaBlasPool], with several threads making requests at once.
+fn main() {
+    const THREADS: usize = 8;
+    const REQUESTS: usize = 32;
+    let dev = CudaDevice::new(0).unwrap();
+
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..REQUESTS {
+                    drop(CudaBlas::new(dev.clone()).unwrap());
+                }
+            });
+        }
+    });
+    let unpooled = start.elapsed();
+
+    let pool = CudaBlasPool::new(&dev, 2).unwrap().with_max_handles(4);
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..REQUESTS {
+                    drop(pool.checkout().unwrap());
+                }
+            });
+        }
+    });
+    let pooled = start.elapsed();
+
+    println!(
+        "{} requests: new handle each {unpooled:?}, pooled {pooled:?} ({} handles)",
+        THREADS * REQUESTS,
+        pool.num_handles()
+    );
+}
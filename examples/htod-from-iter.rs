@@ -0,0 +1,29 @@
+use cudarc::driver::{CudaDevice, DriverError};
+use std::time::Instant;
+
+/// Compares uploading a generated dataset with [CudaDevice::htod_copy_from_iter()],
+/// which overlaps generating the data with copying it, against collecting it into
+/// a `Vec` first and then calling [CudaDevice::htod_copy()].
+fn main() -> Result<(), DriverError> {
+    const LEN: usize = 1 << 28;
+    let dev = CudaDevice::new(0)?;
+    let gen = || (0..LEN).map(|i| (i as f32).sqrt());
+
+    // warm up the allocator and the copy engines
+    drop(dev.htod_copy_from_iter(gen().take(1 << 20), 1 << 20)?);
+
+    let start = Instant::now();
+    let naive = dev.htod_copy(gen().collect())?;
+    dev.synchronize()?;
+    println!("collect + htod_copy:  {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let staged = dev.htod_copy_from_iter(gen(), LEN)?;
+    dev.synchronize()?;
+    println!("htod_copy_from_iter:  {:?}", start.elapsed());
+
+    let a = dev.dtoh_sync_copy(&naive.slice(LEN - 10..))?;
+    let b = dev.dtoh_sync_copy(&staged.slice(LEN - 10..))?;
+    assert_eq!(a, b);
+    Ok(())
+}
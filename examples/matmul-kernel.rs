@@ -47,7 +47,13 @@ fn main() -> Result<(), DriverError> {
         grid_dim: (1, 1, 1),
         shared_mem_bytes: 0,
     };
-    unsafe { f.launch(cfg, (&a_dev, &b_dev, &mut c_dev, 2i32)) }?;
+    unsafe {
+        f.launch(
+            dev.default_stream(),
+            cfg,
+            (&a_dev, &b_dev, &mut c_dev, 2i32),
+        )
+    }?;
 
     dev.dtoh_sync_copy_into(&c_dev, &mut c_host)?;
     println!("Found {:?} in {:?}", c_host, start.elapsed());
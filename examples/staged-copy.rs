@@ -0,0 +1,62 @@
+use cudarc::driver::{CudaDevice, DriverError};
+use std::time::{Duration, Instant};
+
+/// Compares copying 256MB of pageable memory to and from the device through the staging
+/// pool of [CudaDevice::set_staging_pool()] against leaving the copies to the driver.
+///
+/// Prints the bandwidth of each direction for both, and how much faster staging is:
+///
+/// ```text
+/// driver: htod <x> GB/s, dtoh <x> GB/s
+/// staged: htod <x> GB/s, dtoh <x> GB/s
+/// staged vs driver: htod <x>x, dtoh <x>x
+/// ```
+///
+/// The numbers depend on the PCIe link and on how fast the host copies between pageable
+/// and pinned memory, so run it on the machine in question.
+fn main() -> Result<(), DriverError> {
+    const LEN: usize = 1 << 26;
+    const ITERS: u32 = 5;
+    let dev = CudaDevice::new(0)?;
+    let host: Vec<f32> = (0..LEN).map(|i| i as f32).collect();
+    let mut out = vec![0.0f32; LEN];
+    let mut dst = dev.alloc_zeros::<f32>(LEN)?;
+
+    let mut time = |pool_bytes: usize| -> Result<(Duration, Duration), DriverError> {
+        dev.set_staging_pool(pool_bytes);
+        // warm up the pool and the copy engines
+        dev.htod_sync_copy_into(&host, &mut dst)?;
+        dev.dtoh_sync_copy_into(&dst, &mut out)?;
+
+        let start = Instant::now();
+        for _ in 0..ITERS {
+            dev.htod_sync_copy_into(&host, &mut dst)?;
+        }
+        let htod = start.elapsed() / ITERS;
+        let start = Instant::now();
+        for _ in 0..ITERS {
+            dev.dtoh_sync_copy_into(&dst, &mut out)?;
+        }
+        let dtoh = start.elapsed() / ITERS;
+        assert_eq!(out, host);
+        Ok((htod, dtoh))
+    };
+
+    let gb = (LEN * std::mem::size_of::<f32>()) as f64 / 1e9;
+    let mut bandwidths = Vec::new();
+    for (name, pool_bytes) in [("driver", 0), ("staged", 64 << 20)] {
+        let (htod, dtoh) = time(pool_bytes)?;
+        let (htod, dtoh) = (gb / htod.as_secs_f64(), gb / dtoh.as_secs_f64());
+        println!("{name}: htod {htod:.1} GB/s, dtoh {dtoh:.1} GB/s");
+        bandwidths.push((htod, dtoh));
+    }
+    let [(driver_htod, driver_dtoh), (staged_htod, staged_dtoh)] = bandwidths[..] else {
+        unreachable!()
+    };
+    println!(
+        "staged vs driver: htod {:.2}x, dtoh {:.2}x",
+        staged_htod / driver_htod,
+        staged_dtoh / driver_dtoh
+    );
+    Ok(())
+}
@@ -0,0 +1,253 @@
+//! Timing of device work with cuda events, for comparing kernel variants.
+//!
+//! [Bench] runs a closure enqueuing work on a stream a number of times after warming up,
+//! optionally flushing the L2 cache before each run, and summarizes the times in
+//! [BenchStats]:
+//!
+//! ```rust
+//! # use cudarc::{bench::Bench, driver::*};
+//! let dev = CudaDevice::new(0).unwrap();
+//! let mut x = dev.alloc_zeros::<f32>(1 << 20).unwrap();
+//! let stats = Bench::new(&dev)
+//!     .warmup(10)
+//!     .iters(100)
+//!     .flush_l2(true)
+//!     .run(|stream| dev.memset_zeros_on_stream(&mut x, stream))
+//!     .unwrap();
+//! stats.print("memset");
+//! println!("{:.1} GB/s", stats.bandwidth_gbps(4 << 20));
+//! ```
+
+use crate::driver::{result, sys, CudaDevice, CudaSlice, CudaStream, DriverError};
+
+use core::fmt;
+use std::sync::Arc;
+use std::vec::Vec;
+
+/// Runs device work repeatedly and times it, see the [module docs](self).
+#[derive(Debug)]
+pub struct Bench<'a> {
+    device: &'a Arc<CudaDevice>,
+    warmup: usize,
+    iters: usize,
+    flush_l2: bool,
+    scope: Option<&'a str>,
+}
+
+impl<'a> Bench<'a> {
+    /// 3 warmup runs and 20 timed runs, without flushing the L2 cache.
+    pub fn new(device: &'a Arc<CudaDevice>) -> Self {
+        Self {
+            device,
+            warmup: 3,
+            iters: 20,
+            flush_l2: false,
+            scope: None,
+        }
+    }
+
+    /// Untimed runs before the timed ones, e.g. to load modules and warm up caches.
+    pub fn warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Timed runs, at least 1.
+    pub fn iters(mut self, iters: usize) -> Self {
+        self.iters = iters.max(1);
+        self
+    }
+
+    /// Whether to overwrite a buffer twice the size of the L2 cache before each run, so
+    /// that runs read their inputs from device memory like a cold kernel would. The flush
+    /// isn't timed.
+    pub fn flush_l2(mut self, flush_l2: bool) -> Self {
+        self.flush_l2 = flush_l2;
+        self
+    }
+
+    /// Tags the launches made by the runs with `name`, like [CudaDevice::scope()], so
+    /// they can be told apart in [CudaDevice::recent_launches()].
+    pub fn scope(mut self, name: &'a str) -> Self {
+        self.scope = Some(name);
+        self
+    }
+
+    /// Runs `f` on a stream forked from the device's default stream, and times each run
+    /// between events recorded before and after it.
+    ///
+    /// `f` must only enqueue work on the stream it is given: the runs are enqueued one
+    /// after the other, and then waited for at once.
+    pub fn run<F>(self, mut f: F) -> Result<BenchStats, DriverError>
+    where
+        F: FnMut(&CudaStream) -> Result<(), DriverError>,
+    {
+        let _scope = self.scope.map(|name| self.device.scope(name));
+        // allocated before forking, and freed after the stream joins the default stream
+        let scratch = if self.flush_l2 {
+            Some(self.l2_scratch()?)
+        } else {
+            None
+        };
+        let stream = self.device.fork_default_stream()?;
+        let flush = |stream: &CudaStream| match &scratch {
+            Some(scratch) => unsafe {
+                result::memset_d8_async(scratch.cu_device_ptr, 0xff, scratch.len, stream.stream)
+            },
+            None => Ok(()),
+        };
+
+        for _ in 0..self.warmup {
+            flush(&stream)?;
+            f(&stream)?;
+        }
+
+        let mut events = Events(Vec::with_capacity(2 * self.iters));
+        for _ in 0..2 * self.iters {
+            events
+                .0
+                .push(result::event::create(sys::CUevent_flags::CU_EVENT_DEFAULT)?);
+        }
+        for run in events.0.chunks_exact(2) {
+            flush(&stream)?;
+            unsafe { result::event::record(run[0], stream.stream) }?;
+            f(&stream)?;
+            unsafe { result::event::record(run[1], stream.stream) }?;
+        }
+        let samples_us = events
+            .0
+            .chunks_exact(2)
+            .map(|run| unsafe {
+                result::event::synchronize(run[1])?;
+                result::event::elapsed(run[0], run[1]).map(|ms| ms as f64 * 1e3)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(BenchStats::from_samples(samples_us))
+    }
+
+    fn l2_scratch(&self) -> Result<CudaSlice<u8>, DriverError> {
+        let l2_bytes = self
+            .device
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_L2_CACHE_SIZE)?;
+        unsafe { self.device.alloc::<u8>(2 * (l2_bytes.max(1) as usize)) }
+    }
+}
+
+/// The events of the timed runs, destroyed on drop.
+struct Events(Vec<sys::CUevent>);
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        for event in self.0.drain(..) {
+            // the runs failed to enqueue, or have completed
+            unsafe { result::event::destroy(event) }.ok();
+        }
+    }
+}
+
+/// Times of the runs of a [Bench], in microseconds.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchStats {
+    pub median_us: f64,
+    pub mean_us: f64,
+    /// 95th percentile, by nearest rank.
+    pub p95_us: f64,
+    pub min_us: f64,
+    /// The time of each run, in the order they ran.
+    pub samples_us: Vec<f64>,
+}
+
+impl BenchStats {
+    /// Summarizes at least one sample.
+    pub fn from_samples(samples_us: Vec<f64>) -> Self {
+        assert!(!samples_us.is_empty());
+        let mut sorted = samples_us.clone();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        let median_us = if n % 2 == 1 {
+            sorted[n / 2]
+        } else {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        };
+        let p95 = (95 * n).div_ceil(100) - 1;
+        Self {
+            median_us,
+            mean_us: sorted.iter().sum::<f64>() / n as f64,
+            p95_us: sorted[p95],
+            min_us: sorted[0],
+            samples_us,
+        }
+    }
+
+    /// Bandwidth in GB/s of a run moving `num_bytes` in the median time.
+    pub fn bandwidth_gbps(&self, num_bytes: usize) -> f64 {
+        num_bytes as f64 / (self.median_us * 1e3)
+    }
+
+    /// Prints the stats on one line, prefixed by `name`.
+    #[cfg(feature = "std")]
+    pub fn print(&self, name: &str) {
+        std::println!("{name}: {self}");
+    }
+}
+
+impl fmt::Display for BenchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "median {:.2}us mean {:.2}us p95 {:.2}us min {:.2}us ({} runs)",
+            self.median_us,
+            self.mean_us,
+            self.p95_us,
+            self.min_us,
+            self.samples_us.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{format, vec};
+
+    #[test]
+    fn test_stats_from_samples() {
+        let stats = BenchStats::from_samples(vec![4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(stats.median_us, 2.5);
+        assert_eq!(stats.mean_us, 2.5);
+        assert_eq!(stats.p95_us, 4.0);
+        assert_eq!(stats.min_us, 1.0);
+        assert_eq!(stats.samples_us, [4.0, 1.0, 3.0, 2.0]);
+
+        let samples = (1..=100).map(|i| i as f64).collect();
+        let stats = BenchStats::from_samples(samples);
+        assert_eq!(stats.median_us, 50.5);
+        assert_eq!(stats.p95_us, 95.0);
+        assert_eq!(stats.bandwidth_gbps(101_000), 2.0);
+        assert_eq!(
+            format!("{stats}"),
+            "median 50.50us mean 50.50us p95 95.00us min 1.00us (100 runs)"
+        );
+    }
+
+    #[test]
+    fn test_bench_run() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut x = dev.alloc_zeros::<u32>(1 << 20).unwrap();
+        let mut runs = 0;
+        let stats = Bench::new(&dev)
+            .warmup(2)
+            .iters(5)
+            .flush_l2(true)
+            .run(|stream| {
+                runs += 1;
+                dev.memset_zeros_on_stream(&mut x, stream)
+            })
+            .unwrap();
+        assert_eq!(runs, 7);
+        assert_eq!(stats.samples_us.len(), 5);
+        assert!(stats.min_us > 0.0);
+        assert!(stats.min_us <= stats.median_us && stats.median_us <= stats.p95_us);
+    }
+}
@@ -0,0 +1,400 @@
+//! Helpers for compiling `.cu` files from a build script, see [Builder].
+//!
+//! Add cudarc as a build dependency with the `build` feature enabled, and in `build.rs`:
+//! ```no_run
+//! fn main() -> Result<(), cudarc::build::BuildError> {
+//!     cudarc::build::Builder::new()
+//!         .file("kernels/reduce.cu")
+//!         .arch_list(["sm_80", "sm_90"])
+//!         .define("BLOCK", "256")
+//!         .compile()
+//! }
+//! ```
+//! The compiled kernels can then be loaded with [crate::load_compiled_ptx!()]:
+//! ```ignore
+//! dev.load_ptx(cudarc::load_compiled_ptx!("reduce"), "reduce", &["reduce"])?;
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Which compiler [Builder::compile()] uses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compiler {
+    /// nvrtc if the `nvrtc` feature is enabled and the library can be loaded,
+    /// and otherwise nvcc.
+    #[default]
+    Auto,
+    /// The nvrtc library (requires the `nvrtc` feature).
+    Nvrtc,
+    /// The `nvcc` executable in `$NVCC`, or on the `PATH` if it isn't set.
+    Nvcc,
+}
+
+/// An error from [Builder::compile()].
+#[derive(Debug)]
+pub enum BuildError {
+    /// `OUT_DIR` isn't set, e.g. because this isn't running in a build script.
+    /// Use [Builder::out_dir()].
+    MissingOutDir,
+    /// No architecture was given to [Builder::arch_list()].
+    NoArch,
+    /// Reading a source or writing an output failed.
+    Io { path: PathBuf, err: std::io::Error },
+    /// nvrtc was requested but the `nvrtc` feature isn't enabled or the library couldn't be loaded.
+    NvrtcUnavailable,
+    /// Compiling `file` failed, `log` is the full compiler output.
+    Compile { file: PathBuf, log: String },
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::MissingOutDir => write!(f, "OUT_DIR is not set"),
+            BuildError::NoArch => write!(f, "no architecture given to `arch_list`"),
+            BuildError::Io { path, err } => write!(f, "{}: {err}", path.display()),
+            BuildError::NvrtcUnavailable => write!(f, "nvrtc is not available"),
+            BuildError::Compile { file, log } => {
+                write!(f, "failed to compile {}:\n{log}", file.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Compiles `.cu` files to `.ptx` in `OUT_DIR`, for use from build scripts.
+///
+/// For each file, writes `<stem>.ptx` targeting the virtual architecture of the
+/// lowest entry in [Builder::arch_list()], which the driver can JIT to any newer device.
+/// When compiling with nvcc, also writes `<stem>.fatbin` with SASS for every architecture.
+///
+/// Emits `cargo:rerun-if-changed` for every file.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    files: Vec<PathBuf>,
+    archs: Vec<String>,
+    defines: Vec<(String, Option<String>)>,
+    include_paths: Vec<PathBuf>,
+    flags: Vec<String>,
+    out_dir: Option<PathBuf>,
+    compiler: Compiler,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a `.cu` file to compile.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds several `.cu` files to compile.
+    pub fn files<P: AsRef<Path>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.files
+            .extend(paths.into_iter().map(|p| p.as_ref().to_path_buf()));
+        self
+    }
+
+    /// The real architectures to compile for, e.g. `["sm_80", "sm_90"]`.
+    pub fn arch_list<S: AsRef<str>>(mut self, archs: impl IntoIterator<Item = S>) -> Self {
+        self.archs = archs.into_iter().map(|a| a.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Defines the macro `name` to `value`, like `-Dname=value`.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines
+            .push((name.to_string(), Some(value.to_string())));
+        self
+    }
+
+    /// Defines the macro `name` without a value, like `-Dname`.
+    pub fn define_flag(mut self, name: &str) -> Self {
+        self.defines.push((name.to_string(), None));
+        self
+    }
+
+    /// Adds a directory to search for `#include`s. The directory of each file is always searched.
+    pub fn include_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.include_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Passes `flag` to the compiler as is.
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_string());
+        self
+    }
+
+    /// Where outputs are written, `OUT_DIR` by default.
+    pub fn out_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.out_dir = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Which compiler to use, [Compiler::Auto] by default.
+    pub fn compiler(mut self, compiler: Compiler) -> Self {
+        self.compiler = compiler;
+        self
+    }
+
+    /// Compiles every file, stopping at the first error.
+    pub fn compile(self) -> Result<(), BuildError> {
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or(BuildError::MissingOutDir)?,
+        };
+        let lowest = self
+            .archs
+            .iter()
+            .min_by_key(|a| arch_number(a))
+            .ok_or(BuildError::NoArch)?;
+        let compiler = match self.compiler {
+            Compiler::Auto if nvrtc_available() => Compiler::Nvrtc,
+            Compiler::Auto => Compiler::Nvcc,
+            Compiler::Nvrtc if !nvrtc_available() => return Err(BuildError::NvrtcUnavailable),
+            compiler => compiler,
+        };
+        println!("cargo:rerun-if-env-changed=NVCC");
+        for file in self.files.iter() {
+            println!("cargo:rerun-if-changed={}", file.display());
+            let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+            let ptx_path = out_dir.join(std::format!("{stem}.ptx"));
+            match compiler {
+                Compiler::Nvrtc => self.nvrtc(file, lowest, &ptx_path)?,
+                _ => {
+                    self.nvcc(
+                        file,
+                        &["--ptx", &std::format!("-arch={}", virtual_arch(lowest))],
+                        &ptx_path,
+                    )?;
+                    let mut args: Vec<String> = std::vec!["--fatbin".into()];
+                    for arch in self.archs.iter() {
+                        args.push(std::format!(
+                            "-gencode=arch={},code={arch}",
+                            virtual_arch(arch)
+                        ));
+                    }
+                    args.push(std::format!(
+                        "-gencode=arch={0},code={0}",
+                        virtual_arch(lowest)
+                    ));
+                    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                    self.nvcc(file, &args, &out_dir.join(std::format!("{stem}.fatbin")))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flags shared by nvcc and nvrtc.
+    fn common_flags(&self, file: &Path) -> Vec<String> {
+        let mut flags = Vec::new();
+        for (name, value) in self.defines.iter() {
+            match value {
+                Some(value) => flags.push(std::format!("-D{name}={value}")),
+                None => flags.push(std::format!("-D{name}")),
+            }
+        }
+        let dir = file.parent().filter(|p| !p.as_os_str().is_empty());
+        for path in dir
+            .into_iter()
+            .chain(self.include_paths.iter().map(|p| p.as_path()))
+        {
+            flags.push(std::format!("-I{}", path.display()));
+        }
+        flags.extend(self.flags.iter().cloned());
+        flags
+    }
+
+    #[cfg(feature = "nvrtc")]
+    fn nvrtc(&self, file: &Path, arch: &str, out: &Path) -> Result<(), BuildError> {
+        use crate::nvrtc::{compile_ptx_with_opts, CompileError, CompileOptions};
+
+        let src = std::fs::read_to_string(file).map_err(|err| BuildError::Io {
+            path: file.to_path_buf(),
+            err,
+        })?;
+        let mut options = self.common_flags(file);
+        options.push(std::format!("--gpu-architecture={}", virtual_arch(arch)));
+        let opts = CompileOptions {
+            options,
+            ..Default::default()
+        };
+        let ptx = compile_ptx_with_opts(src, opts).map_err(|e| BuildError::Compile {
+            file: file.to_path_buf(),
+            log: match e {
+                CompileError::CompileError { log, .. } => log.to_string_lossy().into_owned(),
+                e => std::format!("{e:?}"),
+            },
+        })?;
+        let src = ptx.to_src().map_err(|e| BuildError::Compile {
+            file: file.to_path_buf(),
+            log: std::format!("{e:?}"),
+        })?;
+        std::fs::write(out, src).map_err(|err| BuildError::Io {
+            path: out.to_path_buf(),
+            err,
+        })
+    }
+
+    #[cfg(not(feature = "nvrtc"))]
+    fn nvrtc(&self, _file: &Path, _arch: &str, _out: &Path) -> Result<(), BuildError> {
+        Err(BuildError::NvrtcUnavailable)
+    }
+
+    fn nvcc(&self, file: &Path, args: &[&str], out: &Path) -> Result<(), BuildError> {
+        let nvcc = std::env::var_os("NVCC").unwrap_or_else(|| "nvcc".into());
+        let output = Command::new(&nvcc)
+            .args(args)
+            .args(self.common_flags(file))
+            .arg("-o")
+            .arg(out)
+            .arg(file)
+            .output()
+            .map_err(|err| BuildError::Io {
+                path: PathBuf::from(nvcc),
+                err,
+            })?;
+        if !output.status.success() {
+            let mut log = String::from_utf8_lossy(&output.stdout).into_owned();
+            log.push_str(&String::from_utf8_lossy(&output.stderr));
+            return Err(BuildError::Compile {
+                file: file.to_path_buf(),
+                log,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `sm_80` -> `80`, unparsable architectures sort last.
+fn arch_number(arch: &str) -> u32 {
+    arch.rsplit('_')
+        .next()
+        .and_then(|n| {
+            n.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                .parse()
+                .ok()
+        })
+        .unwrap_or(u32::MAX)
+}
+
+/// `sm_80` -> `compute_80`.
+fn virtual_arch(arch: &str) -> String {
+    match arch.strip_prefix("sm_") {
+        Some(rest) => std::format!("compute_{rest}"),
+        None => arch.to_string(),
+    }
+}
+
+#[cfg(feature = "nvrtc")]
+fn nvrtc_available() -> bool {
+    crate::get_lib_name_candidates("nvrtc")
+        .iter()
+        .any(|name| unsafe { crate::nvrtc::sys::Lib::new(name) }.is_ok())
+}
+
+#[cfg(not(feature = "nvrtc"))]
+fn nvrtc_available() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arch_helpers() {
+        assert_eq!(arch_number("sm_80"), 80);
+        assert_eq!(arch_number("sm_90a"), 90);
+        assert_eq!(arch_number("compute_75"), 75);
+        assert_eq!(arch_number("native"), u32::MAX);
+        assert_eq!(virtual_arch("sm_86"), "compute_86");
+        assert_eq!(virtual_arch("compute_86"), "compute_86");
+    }
+
+    #[test]
+    fn test_common_flags() {
+        let builder = Builder::new()
+            .define("BLOCK", "256")
+            .define_flag("FAST")
+            .include_path("include")
+            .flag("-lineinfo");
+        assert_eq!(
+            builder.common_flags(Path::new("kernels/reduce.cu")),
+            [
+                "-DBLOCK=256",
+                "-DFAST",
+                "-Ikernels",
+                "-Iinclude",
+                "-lineinfo"
+            ]
+        );
+        assert_eq!(
+            builder.common_flags(Path::new("reduce.cu")),
+            ["-DBLOCK=256", "-DFAST", "-Iinclude", "-lineinfo"]
+        );
+    }
+
+    #[test]
+    fn test_compile_errors_before_compiling() {
+        let builder = Builder::new().file("reduce.cu").out_dir(".");
+        assert!(matches!(builder.compile(), Err(BuildError::NoArch)));
+    }
+
+    #[cfg(feature = "nvrtc")]
+    #[test]
+    #[ignore = "needs the nvrtc library"]
+    fn test_compile_nvrtc() {
+        let dir = std::env::temp_dir().join(std::format!("cudarc-build-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("defs.h"), "#define SCALE 2.0f\n").unwrap();
+        let src = dir.join("scale.cu");
+        std::fs::write(
+            &src,
+            "#include \"defs.h\"\nextern \"C\" __global__ void scale(float *x) { x[threadIdx.x] *= SCALE * BLOCK; }",
+        )
+        .unwrap();
+
+        Builder::new()
+            .file(&src)
+            .arch_list(["sm_90", "sm_70"])
+            .define("BLOCK", "4")
+            .out_dir(&dir)
+            .compiler(Compiler::Nvrtc)
+            .compile()
+            .unwrap();
+        let ptx = std::fs::read_to_string(dir.join("scale.ptx")).unwrap();
+        assert!(ptx.contains(".target sm_70"));
+        assert!(ptx.contains(".entry scale"));
+
+        std::fs::write(
+            &src,
+            "extern \"C\" __global__ void scale(float *x) { oops; }",
+        )
+        .unwrap();
+        let err = Builder::new()
+            .file(&src)
+            .arch_list(["sm_70"])
+            .out_dir(&dir)
+            .compiler(Compiler::Nvrtc)
+            .compile()
+            .unwrap_err();
+        match err {
+            BuildError::Compile { log, .. } => assert!(log.contains("oops"), "{log}"),
+            e => panic!("{e:?}"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
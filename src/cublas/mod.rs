@@ -1,9 +1,13 @@
 //! Wrappers around the [cublas API](https://docs.nvidia.com/cuda/cublas/index.html),
 //! in three levels. See crate documentation for description of each.
 
+#[cfg(feature = "std")]
+pub mod pool;
 pub mod result;
 pub mod safe;
 #[allow(warnings)]
 pub mod sys;
 
+#[cfg(feature = "std")]
+pub use pool::{CudaBlasPool, OnExhausted, PoolError, PooledBlas};
 pub use safe::*;
@@ -0,0 +1,307 @@
+//! A pool of [CudaBlas] handles for using cublas from many threads at once.
+
+use super::{result::CublasError, safe::CudaBlas};
+use crate::driver::{CudaDevice, CudaStream, DriverError};
+
+use std::{
+    ops::Deref,
+    sync::{Arc, Condvar, Mutex},
+    vec::Vec,
+};
+
+/// What [CudaBlasPool::checkout()] does when every handle is checked out and the pool
+/// can't grow any further.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OnExhausted {
+    /// Blocks until another thread returns a handle.
+    #[default]
+    Block,
+    /// Returns [PoolError::Exhausted].
+    Error,
+}
+
+/// Error from [CudaBlasPool].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    /// Creating a handle failed.
+    Cublas(CublasError),
+    /// Creating a handle's stream failed.
+    Driver(DriverError),
+    /// Every handle is checked out, with [OnExhausted::Error].
+    Exhausted,
+}
+
+impl From<CublasError> for PoolError {
+    fn from(value: CublasError) -> Self {
+        Self::Cublas(value)
+    }
+}
+
+impl From<DriverError> for PoolError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+impl std::fmt::Display for PoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cublas(e) => write!(f, "{e}"),
+            Self::Driver(e) => write!(f, "{e}"),
+            Self::Exhausted => write!(f, "all cublas handles in the pool are checked out"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// A handle and the stream its work is enqueued on.
+#[derive(Debug)]
+struct Entry {
+    // dropped before the stream it uses
+    blas: CudaBlas,
+    stream: CudaStream,
+}
+
+// an entry is only used by the thread it is checked out to
+unsafe impl Send for Entry {}
+
+impl Entry {
+    fn new(device: &Arc<CudaDevice>) -> Result<Self, PoolError> {
+        let stream = device.fork_default_stream()?;
+        let blas = CudaBlas::new(device.clone())?;
+        unsafe { blas.set_stream(Some(&stream)) }?;
+        Ok(Self { blas, stream })
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    idle: Vec<Entry>,
+    /// Number of handles created, including ones being created and checked out ones.
+    total: usize,
+}
+
+/// A pool of [CudaBlas] handles, each with its own non-blocking [CudaStream], so that
+/// threads (e.g. the request handlers of a server) don't pay to create a handle each time.
+///
+/// [CudaBlasPool::checkout()] returns a [PooledBlas], which derefs to [CudaBlas] and returns
+/// the handle to the pool on drop. The pool grows up to [CudaBlasPool::with_max_handles()]
+/// handles, after which it behaves according to [OnExhausted].
+///
+/// ```rust
+/// # use cudarc::{cublas::*, driver::*};
+/// let dev = CudaDevice::new(0).unwrap();
+/// let pool = CudaBlasPool::new(&dev, 4).unwrap().with_max_handles(8);
+/// std::thread::scope(|s| {
+///     for _ in 0..16 {
+///         s.spawn(|| {
+///             let blas = pool.checkout().unwrap();
+///             // enqueue work with `blas`, ordering copies with `blas.stream()`
+///         });
+///     }
+/// });
+/// ```
+#[derive(Debug)]
+pub struct CudaBlasPool {
+    device: Arc<CudaDevice>,
+    state: Mutex<State>,
+    returned: Condvar,
+    max_handles: usize,
+    on_exhausted: OnExhausted,
+}
+
+impl CudaBlasPool {
+    /// Creates `n` handles up front. The pool doesn't grow beyond them unless
+    /// [CudaBlasPool::with_max_handles()] is used.
+    pub fn new(device: &Arc<CudaDevice>, n: usize) -> Result<Self, PoolError> {
+        let idle = (0..n)
+            .map(|_| Entry::new(device))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            device: device.clone(),
+            state: Mutex::new(State { idle, total: n }),
+            returned: Condvar::new(),
+            max_handles: n,
+            on_exhausted: OnExhausted::default(),
+        })
+    }
+
+    /// Lets the pool create handles when all of them are checked out, up to `max_handles` in total.
+    pub fn with_max_handles(mut self, max_handles: usize) -> Self {
+        self.max_handles = max_handles;
+        self
+    }
+
+    /// Sets what [CudaBlasPool::checkout()] does once the pool is exhausted,
+    /// [OnExhausted::Block] by default.
+    pub fn with_on_exhausted(mut self, on_exhausted: OnExhausted) -> Self {
+        self.on_exhausted = on_exhausted;
+        self
+    }
+
+    /// The number of handles created so far.
+    pub fn num_handles(&self) -> usize {
+        self.state.lock().unwrap().total
+    }
+
+    /// The number of handles not checked out.
+    pub fn num_idle(&self) -> usize {
+        self.state.lock().unwrap().idle.len()
+    }
+
+    /// Takes a handle out of the pool, creating one if none are idle and the pool can grow.
+    pub fn checkout(&self) -> Result<PooledBlas<'_>, PoolError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(entry) = state.idle.pop() {
+                return Ok(PooledBlas {
+                    entry: Some(entry),
+                    pool: self,
+                });
+            }
+            if state.total < self.max_handles {
+                // create the handle without holding the lock
+                state.total += 1;
+                drop(state);
+                return match Entry::new(&self.device) {
+                    Ok(entry) => Ok(PooledBlas {
+                        entry: Some(entry),
+                        pool: self,
+                    }),
+                    Err(e) => {
+                        self.state.lock().unwrap().total -= 1;
+                        self.returned.notify_one();
+                        Err(e)
+                    }
+                };
+            }
+            match self.on_exhausted {
+                OnExhausted::Block => state = self.returned.wait(state).unwrap(),
+                OnExhausted::Error => return Err(PoolError::Exhausted),
+            }
+        }
+    }
+}
+
+/// A [CudaBlas] checked out of a [CudaBlasPool], returned to the pool on drop.
+#[derive(Debug)]
+pub struct PooledBlas<'a> {
+    entry: Option<Entry>,
+    pool: &'a CudaBlasPool,
+}
+
+impl PooledBlas<'_> {
+    /// The stream the handle's work is enqueued on. Use e.g. [CudaDevice::wait_for()]
+    /// to order copies on the default stream after it.
+    pub fn stream(&self) -> &CudaStream {
+        &self.entry.as_ref().unwrap().stream
+    }
+}
+
+impl Deref for PooledBlas<'_> {
+    type Target = CudaBlas;
+    fn deref(&self) -> &Self::Target {
+        &self.entry.as_ref().unwrap().blas
+    }
+}
+
+impl Drop for PooledBlas<'_> {
+    fn drop(&mut self) {
+        let entry = self.entry.take().unwrap();
+        // undo a `set_stream` by the user. a handle that can't be put back on its own
+        // stream is destroyed instead, so that a later checkout creates a new one.
+        let restored = unsafe { entry.blas.set_stream(Some(&entry.stream)) };
+        let mut state = self.pool.state.lock().unwrap_or_else(|e| e.into_inner());
+        match restored {
+            Ok(()) => state.idle.push(entry),
+            Err(_) => state.total -= 1,
+        }
+        drop(state);
+        self.pool.returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cublas::{sys, Gemm, GemmConfig};
+    use std::time::{Duration, Instant};
+
+    fn square_gemm(blas: &PooledBlas, dev: &Arc<CudaDevice>, n: usize) -> f32 {
+        let a = dev.htod_sync_copy(&std::vec![1.0f32; n * n]).unwrap();
+        let mut c = dev.alloc_zeros::<f32>(n * n).unwrap();
+        let cfg = GemmConfig {
+            transa: sys::cublasOperation_t::CUBLAS_OP_N,
+            transb: sys::cublasOperation_t::CUBLAS_OP_N,
+            m: n as i32,
+            n: n as i32,
+            k: n as i32,
+            alpha: 1.0,
+            lda: n as i32,
+            ldb: n as i32,
+            beta: 0.0,
+            ldc: n as i32,
+        };
+        // the memset of `c` is on the default stream
+        blas.stream().wait_for_default().unwrap();
+        unsafe { blas.gemm(cfg, &a, &a, &mut c) }.unwrap();
+        dev.wait_for(blas.stream()).unwrap();
+        dev.dtoh_sync_copy(&c).unwrap()[0]
+    }
+
+    #[test]
+    fn test_pool_error_when_exhausted() {
+        let dev = CudaDevice::new(0).unwrap();
+        let pool = CudaBlasPool::new(&dev, 1)
+            .unwrap()
+            .with_max_handles(2)
+            .with_on_exhausted(OnExhausted::Error);
+        let a = pool.checkout().unwrap();
+        let b = pool.checkout().unwrap();
+        assert_eq!(pool.num_handles(), 2);
+        assert_eq!(pool.checkout().unwrap_err(), PoolError::Exhausted);
+        assert_ne!(a.stream().stream, b.stream().stream);
+        drop(a);
+        assert_eq!(pool.num_idle(), 1);
+        assert_eq!(square_gemm(&pool.checkout().unwrap(), &dev, 8), 8.0);
+    }
+
+    #[test]
+    fn test_pool_blocks_until_returned() {
+        let dev = CudaDevice::new(0).unwrap();
+        let pool = CudaBlasPool::new(&dev, 1).unwrap();
+        let held = pool.checkout().unwrap();
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let start = Instant::now();
+                let _blas = pool.checkout().unwrap();
+                start.elapsed()
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            drop(held);
+            assert!(waiter.join().unwrap() >= Duration::from_millis(40));
+        });
+        assert_eq!(pool.num_handles(), 1);
+    }
+
+    #[test]
+    fn test_pool_multithreaded() {
+        const THREADS: usize = 8;
+        const REQUESTS: usize = 32;
+        let dev = CudaDevice::new(0).unwrap();
+        let pool = CudaBlasPool::new(&dev, 2).unwrap().with_max_handles(4);
+        std::thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for _ in 0..REQUESTS {
+                        let blas = pool.checkout().unwrap();
+                        assert_eq!(square_gemm(&blas, &dev, 16), 16.0);
+                    }
+                });
+            }
+        });
+        assert!(pool.num_handles() <= 4);
+        assert_eq!(pool.num_idle(), pool.num_handles());
+    }
+}
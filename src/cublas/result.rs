@@ -57,6 +57,91 @@ pub unsafe fn set_stream(
     lib().cublasSetStream_v2(handle, stream).result()
 }
 
+/// See [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasgetstream)
+///
+/// # Safety
+///
+/// `handle` must be valid.
+pub unsafe fn get_stream(handle: sys::cublasHandle_t) -> Result<sys::cudaStream_t, CublasError> {
+    let mut stream = MaybeUninit::uninit();
+    lib()
+        .cublasGetStream_v2(handle, stream.as_mut_ptr())
+        .result()?;
+    Ok(stream.assume_init())
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublassetmathmode)
+///
+/// # Safety
+///
+/// `handle` must be valid.
+pub unsafe fn set_math_mode(
+    handle: sys::cublasHandle_t,
+    mode: sys::cublasMath_t,
+) -> Result<(), CublasError> {
+    lib().cublasSetMathMode(handle, mode).result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasgetmathmode)
+///
+/// # Safety
+///
+/// `handle` must be valid.
+pub unsafe fn get_math_mode(handle: sys::cublasHandle_t) -> Result<sys::cublasMath_t, CublasError> {
+    let mut mode = MaybeUninit::uninit();
+    lib()
+        .cublasGetMathMode(handle, mode.as_mut_ptr())
+        .result()?;
+    Ok(mode.assume_init())
+}
+
+/// Sets whether cuBLAS may use routines with atomics, which are faster for some
+/// routines but not bitwise reproducible. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublassetatomicsmode)
+///
+/// # Safety
+///
+/// `handle` must be valid.
+pub unsafe fn set_atomics_mode(
+    handle: sys::cublasHandle_t,
+    mode: sys::cublasAtomicsMode_t,
+) -> Result<(), CublasError> {
+    lib().cublasSetAtomicsMode(handle, mode).result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasgetatomicsmode)
+///
+/// # Safety
+///
+/// `handle` must be valid.
+pub unsafe fn get_atomics_mode(
+    handle: sys::cublasHandle_t,
+) -> Result<sys::cublasAtomicsMode_t, CublasError> {
+    let mut mode = MaybeUninit::uninit();
+    lib()
+        .cublasGetAtomicsMode(handle, mode.as_mut_ptr())
+        .result()?;
+    Ok(mode.assume_init())
+}
+
+/// Sets the workspace cuBLAS uses instead of its default pool, until the next
+/// [set_stream()]. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublassetworkspace)
+///
+/// # Safety
+///
+/// `handle` must be valid, and `workspace` device memory of `num_bytes` that isn't used
+/// by anything else until the handle stops using it.
+pub unsafe fn set_workspace(
+    handle: sys::cublasHandle_t,
+    workspace: *mut c_void,
+    num_bytes: usize,
+) -> Result<(), CublasError> {
+    lib()
+        .cublasSetWorkspace_v2(handle, workspace, num_bytes)
+        .result()
+}
+
 /// Single precision matrix vector multiplication. See
 /// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublas-t-gemv)
 ///
@@ -230,6 +315,58 @@ pub unsafe fn dgemm(
         .result()
 }
 
+/// Single precision multiplication of a matrix with a diagonal matrix. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublas-t-dgmm)
+///
+/// # Safety
+///
+/// - `a`, `x`, and `c` must be valid device pointers that have not been freed.
+/// - the strides and sizes must be sized correctly
+/// - `c` may only alias `a` if `lda == ldc`
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn sdgmm(
+    handle: sys::cublasHandle_t,
+    mode: sys::cublasSideMode_t,
+    m: c_int,
+    n: c_int,
+    a: *const f32,
+    lda: c_int,
+    x: *const f32,
+    incx: c_int,
+    c: *mut f32,
+    ldc: c_int,
+) -> Result<(), CublasError> {
+    lib()
+        .cublasSdgmm(handle, mode, m, n, a, lda, x, incx, c, ldc)
+        .result()
+}
+
+/// Double precision multiplication of a matrix with a diagonal matrix. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublas-t-dgmm)
+///
+/// # Safety
+///
+/// - `a`, `x`, and `c` must be valid device pointers that have not been freed.
+/// - the strides and sizes must be sized correctly
+/// - `c` may only alias `a` if `lda == ldc`
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn ddgmm(
+    handle: sys::cublasHandle_t,
+    mode: sys::cublasSideMode_t,
+    m: c_int,
+    n: c_int,
+    a: *const f64,
+    lda: c_int,
+    x: *const f64,
+    incx: c_int,
+    c: *mut f64,
+    ldc: c_int,
+) -> Result<(), CublasError> {
+    lib()
+        .cublasDdgmm(handle, mode, m, n, a, lda, x, incx, c, ldc)
+        .result()
+}
+
 #[cfg(feature = "f16")]
 /// Half precision batched matmul. See
 /// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublas-t-gemmstridedbatched)
@@ -2,7 +2,10 @@
 #![allow(clippy::too_many_arguments)]
 
 use super::{result, result::CublasError, sys};
-use crate::driver::{CudaDevice, CudaStream, DevicePtr, DevicePtrMut};
+use crate::driver::{
+    safe::error_handler::{handle_error, log_library_error, OrHandle},
+    CudaDevice, CudaSlice, CudaStream, DevicePtr, DevicePtrMut,
+};
 use core::ffi::{c_int, c_longlong};
 use std::sync::Arc;
 
@@ -18,6 +21,10 @@ use std::sync::Arc;
 pub struct CudaBlas {
     pub(crate) handle: sys::cublasHandle_t,
     pub(crate) device: Arc<CudaDevice>,
+    /// The workspace of [CudaBlas::set_deterministic()], kept until the handle is destroyed
+    /// once allocated.
+    workspace: Option<CudaSlice<u8>>,
+    deterministic: bool,
 }
 
 unsafe impl Send for CudaBlas {}
@@ -25,10 +32,22 @@ unsafe impl Sync for CudaBlas {}
 
 impl CudaBlas {
     /// Creates a new cublas handle and sets the stream to the `device`'s stream.
+    ///
+    /// If the `device`'s context can't be bound to the thread, the [crate::driver::DriverError]
+    /// is passed to the [crate::driver::ErrorHandler] and `CUBLAS_STATUS_NOT_INITIALIZED` is
+    /// returned.
     pub fn new(device: Arc<CudaDevice>) -> Result<Self, CublasError> {
-        device.bind_to_thread().unwrap();
+        device.bind_to_thread().or_report(
+            "CudaBlas::new",
+            CublasError(sys::cublasStatus_t::CUBLAS_STATUS_NOT_INITIALIZED),
+        )?;
         let handle = result::create_handle()?;
-        let blas = Self { handle, device };
+        let blas = Self {
+            handle,
+            device,
+            workspace: None,
+            deterministic: false,
+        };
         unsafe { result::set_stream(handle, blas.device.stream as *mut _) }?;
         Ok(blas)
     }
@@ -44,13 +63,133 @@ impl CudaBlas {
     /// # Safety
     /// This is unsafe because you can end up scheduling multiple concurrent kernels that all
     /// write to the same memory address.
+    ///
+    /// With [CudaBlas::set_deterministic()], the handle keeps using its own workspace on the
+    /// new stream, so work on the previous stream must have completed, or be ordered before
+    /// work on the new one.
     pub unsafe fn set_stream(&self, opt_stream: Option<&CudaStream>) -> Result<(), CublasError> {
         match opt_stream {
             Some(s) => result::set_stream(self.handle, s.stream as *mut _),
             None => result::set_stream(self.handle, self.device.stream as *mut _),
+        }?;
+        // setting the stream resets the workspace to the default pool
+        match self.workspace.as_ref().filter(|_| self.deterministic) {
+            Some(workspace) => result::set_workspace(
+                self.handle,
+                *workspace.device_ptr() as *mut _,
+                workspace.len,
+            ),
+            None => Ok(()),
         }
     }
 
+    /// Makes the results of this handle bitwise reproducible from run to run, on devices
+    /// of the same architecture and number of SMs and with the same cuBLAS version. See
+    /// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#results-reproducibility)
+    ///
+    /// cuBLAS routines are deterministic by design, except for:
+    /// 1. Routines like `cublas<t>symv` that have faster variants with atomics, which this
+    ///    disallows with [result::set_atomics_mode()].
+    /// 2. A handle used on several streams, which may pick different internal workspaces.
+    ///    This gives the handle a workspace of its own, kept across [CudaBlas::set_stream()].
+    ///
+    /// [Gemm] and [Gemv] only use the default algorithms, which are deterministic like this,
+    /// including `CUBLAS_GEMM_DEFAULT` for [Gemm] of half types. Check algorithms passed to
+    /// [result::gemm_ex()] with [CudaBlas::check_gemm_algo()]. The math mode doesn't change
+    /// whether results are reproducible, but handles only agree on the same one, see
+    /// [CudaBlas::set_math_mode()].
+    ///
+    /// Disabling allows atomics and returns to the default workspace pool. The workspace
+    /// is freed with the handle, as work enqueued before may still use it. Fails with
+    /// [sys::cublasStatus_t::CUBLAS_STATUS_NOT_SUPPORTED] if the loaded cuBLAS can't set a
+    /// workspace, and with [sys::cublasStatus_t::CUBLAS_STATUS_ALLOC_FAILED] if the
+    /// workspace can't be allocated, after passing the [crate::driver::DriverError] to the
+    /// [crate::driver::ErrorHandler].
+    pub fn set_deterministic(&mut self, deterministic: bool) -> Result<(), CublasError> {
+        if !deterministic {
+            unsafe {
+                result::set_atomics_mode(
+                    self.handle,
+                    sys::cublasAtomicsMode_t::CUBLAS_ATOMICS_ALLOWED,
+                )
+            }?;
+            if self.deterministic {
+                // setting the stream again returns to the default pool
+                unsafe { result::set_stream(self.handle, result::get_stream(self.handle)?) }?;
+                self.deterministic = false;
+            }
+            return Ok(());
+        }
+
+        if unsafe { sys::lib() }.cublasSetWorkspace_v2.is_err() {
+            return Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_NOT_SUPPORTED,
+            ));
+        }
+        if self.workspace.is_none() {
+            // the sizes of cuBLAS' default pool
+            let num_bytes = match self.device.compute_capability() {
+                Ok((major, _)) if major < 9 => 4 << 20,
+                _ => 32 << 20,
+            };
+            match self.device.alloc_zeros::<u8>(num_bytes) {
+                Ok(workspace) => self.workspace = Some(workspace),
+                Err(err) => {
+                    handle_error(err, "CudaBlas::set_deterministic");
+                    return Err(CublasError(sys::cublasStatus_t::CUBLAS_STATUS_ALLOC_FAILED));
+                }
+            }
+        }
+        let workspace = self.workspace.as_ref().unwrap();
+        unsafe {
+            result::set_workspace(
+                self.handle,
+                *workspace.device_ptr() as *mut _,
+                workspace.len,
+            )?;
+            result::set_atomics_mode(
+                self.handle,
+                sys::cublasAtomicsMode_t::CUBLAS_ATOMICS_NOT_ALLOWED,
+            )?;
+        }
+        self.deterministic = true;
+        Ok(())
+    }
+
+    /// Whether [CudaBlas::set_deterministic()] is enabled.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Fails with [sys::cublasStatus_t::CUBLAS_STATUS_NOT_SUPPORTED] if `algo` isn't one of
+    /// the default algorithms while [CudaBlas::set_deterministic()] is enabled. The numbered
+    /// algorithms are deprecated hints, which aren't covered by cuBLAS' reproducibility
+    /// guarantee.
+    pub fn check_gemm_algo(&self, algo: sys::cublasGemmAlgo_t) -> Result<(), CublasError> {
+        match !self.deterministic || is_default_algo(algo) {
+            true => Ok(()),
+            false => Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_NOT_SUPPORTED,
+            )),
+        }
+    }
+
+    /// Sets the handle's math mode.
+    /// ref: <https://docs.nvidia.com/cuda/cublas/#cublassetmathmode>
+    ///
+    /// E.g. `CUBLAS_PEDANTIC_MATH` computes in the precision of the types only, while
+    /// `CUBLAS_TF32_TENSOR_OP_MATH` lets f32 [Gemm]s use tf32 tensor cores. Each mode
+    /// gives different results, so handles whose results should match need the same one.
+    pub fn set_math_mode(&self, mode: sys::cublasMath_t) -> Result<(), CublasError> {
+        unsafe { result::set_math_mode(self.handle, mode) }
+    }
+
+    /// Get the handle's current math mode.
+    /// ref: <https://docs.nvidia.com/cuda/cublas/#cublasgetmathmode>
+    pub fn get_math_mode(&self) -> Result<sys::cublasMath_t, CublasError> {
+        unsafe { result::get_math_mode(self.handle) }
+    }
+
     /// Set the handle's pointer mode.
     /// ref: <https://docs.nvidia.com/cuda/cublas/#cublassetpointermode>
     ///
@@ -87,11 +226,23 @@ impl Drop for CudaBlas {
     fn drop(&mut self) {
         let handle = std::mem::replace(&mut self.handle, std::ptr::null_mut());
         if !handle.is_null() {
-            unsafe { result::destroy_handle(handle) }.unwrap();
+            // cublasDestroy synchronizes the device, so the workspace isn't used anymore
+            // when it is freed after this
+            if let Err(err) = unsafe { result::destroy_handle(handle) } {
+                log_library_error(&err, "CudaBlas::drop");
+            }
         }
     }
 }
 
+fn is_default_algo(algo: sys::cublasGemmAlgo_t) -> bool {
+    matches!(
+        algo,
+        sys::cublasGemmAlgo_t::CUBLAS_GEMM_DEFAULT
+            | sys::cublasGemmAlgo_t::CUBLAS_GEMM_DEFAULT_TENSOR_OP
+    )
+}
+
 /// Configuration for [Gemv]
 #[derive(Debug, Copy, Clone)]
 pub struct GemvConfig<T> {
@@ -491,11 +642,247 @@ impl Gemm<f64> for CudaBlas {
     }
 }
 
+/// Configuration for [Dgmm]
+#[derive(Debug, Copy, Clone)]
+pub struct DgmmConfig {
+    /// [sys::cublasSideMode_t::CUBLAS_SIDE_LEFT] computes `diag(x) * a`, scaling the rows of
+    /// the column major `a`, and [sys::cublasSideMode_t::CUBLAS_SIDE_RIGHT] computes
+    /// `a * diag(x)`, scaling its columns.
+    pub side: sys::cublasSideMode_t,
+    pub m: c_int,
+    pub n: c_int,
+    pub lda: c_int,
+    /// The stride between elements of `x`. A negative stride reads `x` back to front.
+    pub incx: c_int,
+    pub ldc: c_int,
+}
+
+impl DgmmConfig {
+    /// Checks that `x` has an element for each row (left side) or column (right side) of
+    /// `a`, `incx` apart.
+    fn check_x(&self, x_len: usize) -> Result<(), CublasError> {
+        let invalid = CublasError(sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE);
+        let diag_len = match self.side {
+            sys::cublasSideMode_t::CUBLAS_SIDE_LEFT => self.m,
+            sys::cublasSideMode_t::CUBLAS_SIDE_RIGHT => self.n,
+        };
+        let diag_len = usize::try_from(diag_len).map_err(|_| invalid)?;
+        if diag_len == 0 {
+            return Ok(());
+        }
+        let needed = (diag_len - 1)
+            .checked_mul(self.incx.unsigned_abs() as usize)
+            .and_then(|n| n.checked_add(1))
+            .ok_or(invalid)?;
+        if x_len < needed {
+            return Err(invalid);
+        }
+        Ok(())
+    }
+}
+
+/// Multiplication of a matrix with a diagonal matrix, with elements of type `T`. Scales the
+/// rows or columns of a matrix, e.g. for the postprocessing of normalization layers.
+pub trait Dgmm<T> {
+    /// Computes `c = diag(x) * a` or `c = a * diag(x)`, see [DgmmConfig::side]. See
+    /// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublas-t-dgmm)
+    ///
+    /// Returns [sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE] if `x` is too short for
+    /// `incx`.
+    ///
+    /// # Safety
+    /// This is unsafe because improper arguments may lead to invalid
+    /// memory accesses.
+    unsafe fn dgmm<A: DevicePtr<T>, X: DevicePtr<T>, C: DevicePtrMut<T>>(
+        &self,
+        cfg: DgmmConfig,
+        a: &A,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError>;
+
+    /// [Dgmm::dgmm()] with `c` as `a`, which cublas permits. [DgmmConfig::lda] is ignored,
+    /// `c` is read with [DgmmConfig::ldc].
+    ///
+    /// # Safety
+    /// This is unsafe because improper arguments may lead to invalid
+    /// memory accesses.
+    unsafe fn dgmm_in_place<X: DevicePtr<T>, C: DevicePtrMut<T>>(
+        &self,
+        cfg: DgmmConfig,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError>;
+
+    /// Multiplies each row `i` of the row major `a` of shape `(rows, cols)` with `x[i]`,
+    /// writing the result to `c`.
+    ///
+    /// Returns [sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE] unless `a` and `c` have
+    /// `rows * cols` elements and `x` has `rows`.
+    fn scale_rows<A: DevicePtr<T>, X: DevicePtr<T>, C: DevicePtrMut<T>>(
+        &self,
+        (rows, cols): (usize, usize),
+        a: &A,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError> {
+        // row major `(rows, cols)` is column major `(cols, rows)`, whose columns are the rows
+        let cfg = row_major_dgmm(sys::cublasSideMode_t::CUBLAS_SIDE_RIGHT, (rows, cols))?;
+        if a.len() != rows * cols || c.len() != rows * cols || x.len() != rows {
+            return Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE,
+            ));
+        }
+        match cfg {
+            Some(cfg) => unsafe { self.dgmm(cfg, a, x, c) },
+            None => Ok(()),
+        }
+    }
+
+    /// Multiplies each column `j` of the row major `a` of shape `(rows, cols)` with `x[j]`,
+    /// writing the result to `c`.
+    ///
+    /// Returns [sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE] unless `a` and `c` have
+    /// `rows * cols` elements and `x` has `cols`.
+    fn scale_cols<A: DevicePtr<T>, X: DevicePtr<T>, C: DevicePtrMut<T>>(
+        &self,
+        (rows, cols): (usize, usize),
+        a: &A,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError> {
+        let cfg = row_major_dgmm(sys::cublasSideMode_t::CUBLAS_SIDE_LEFT, (rows, cols))?;
+        if a.len() != rows * cols || c.len() != rows * cols || x.len() != cols {
+            return Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE,
+            ));
+        }
+        match cfg {
+            Some(cfg) => unsafe { self.dgmm(cfg, a, x, c) },
+            None => Ok(()),
+        }
+    }
+}
+
+/// The [DgmmConfig] for a contiguous row major matrix, or `None` if it is empty.
+fn row_major_dgmm(
+    side: sys::cublasSideMode_t,
+    (rows, cols): (usize, usize),
+) -> Result<Option<DgmmConfig>, CublasError> {
+    let invalid = CublasError(sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE);
+    let m = c_int::try_from(cols).map_err(|_| invalid)?;
+    let n = c_int::try_from(rows).map_err(|_| invalid)?;
+    if m == 0 || n == 0 {
+        return Ok(None);
+    }
+    Ok(Some(DgmmConfig {
+        side,
+        m,
+        n,
+        lda: m,
+        incx: 1,
+        ldc: m,
+    }))
+}
+
+impl Dgmm<f32> for CudaBlas {
+    unsafe fn dgmm<A: DevicePtr<f32>, X: DevicePtr<f32>, C: DevicePtrMut<f32>>(
+        &self,
+        cfg: DgmmConfig,
+        a: &A,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError> {
+        cfg.check_x(x.len())?;
+        result::sdgmm(
+            self.handle,
+            cfg.side,
+            cfg.m,
+            cfg.n,
+            *a.device_ptr() as *const _,
+            cfg.lda,
+            *x.device_ptr() as *const _,
+            cfg.incx,
+            *c.device_ptr_mut() as *mut _,
+            cfg.ldc,
+        )
+    }
+
+    unsafe fn dgmm_in_place<X: DevicePtr<f32>, C: DevicePtrMut<f32>>(
+        &self,
+        cfg: DgmmConfig,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError> {
+        cfg.check_x(x.len())?;
+        let c = *c.device_ptr_mut();
+        result::sdgmm(
+            self.handle,
+            cfg.side,
+            cfg.m,
+            cfg.n,
+            c as *const _,
+            cfg.ldc,
+            *x.device_ptr() as *const _,
+            cfg.incx,
+            c as *mut _,
+            cfg.ldc,
+        )
+    }
+}
+
+impl Dgmm<f64> for CudaBlas {
+    unsafe fn dgmm<A: DevicePtr<f64>, X: DevicePtr<f64>, C: DevicePtrMut<f64>>(
+        &self,
+        cfg: DgmmConfig,
+        a: &A,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError> {
+        cfg.check_x(x.len())?;
+        result::ddgmm(
+            self.handle,
+            cfg.side,
+            cfg.m,
+            cfg.n,
+            *a.device_ptr() as *const _,
+            cfg.lda,
+            *x.device_ptr() as *const _,
+            cfg.incx,
+            *c.device_ptr_mut() as *mut _,
+            cfg.ldc,
+        )
+    }
+
+    unsafe fn dgmm_in_place<X: DevicePtr<f64>, C: DevicePtrMut<f64>>(
+        &self,
+        cfg: DgmmConfig,
+        x: &X,
+        c: &mut C,
+    ) -> Result<(), CublasError> {
+        cfg.check_x(x.len())?;
+        let c = *c.device_ptr_mut();
+        result::ddgmm(
+            self.handle,
+            cfg.side,
+            cfg.m,
+            cfg.n,
+            c as *const _,
+            cfg.ldc,
+            *x.device_ptr() as *const _,
+            cfg.incx,
+            c as *mut _,
+            cfg.ldc,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::needless_range_loop)]
 
     use super::*;
+    use std::{vec, vec::Vec};
 
     fn gemv_truth<T, const M: usize, const N: usize>(
         alpha: T,
@@ -897,6 +1284,130 @@ mod tests {
         }
     }
 
+    /// `c = diag(x) * a` or `c = a * diag(x)` for a column major `a`, following the blas
+    /// convention for negative `incx`.
+    fn dgmm_truth<T: Copy + std::ops::Mul<T, Output = T>>(
+        left: bool,
+        (m, n): (usize, usize),
+        a: &[T],
+        lda: usize,
+        x: &[T],
+        incx: isize,
+        c: &mut [T],
+        ldc: usize,
+    ) {
+        let diag_len = if left { m } else { n };
+        let x_at = |k: usize| {
+            if incx >= 0 {
+                x[k * incx as usize]
+            } else {
+                x[(diag_len - 1 - k) * incx.unsigned_abs()]
+            }
+        };
+        for j in 0..n {
+            for i in 0..m {
+                let d = if left { x_at(i) } else { x_at(j) };
+                c[i + j * ldc] = a[i + j * lda] * d;
+            }
+        }
+    }
+
+    #[test]
+    fn test_dgmm() {
+        let dev = CudaDevice::new(0).unwrap();
+        let blas = CudaBlas::new(dev.clone()).unwrap();
+        let (m, n, lda, ldc) = (3, 4, 5, 3);
+        let a: Vec<f32> = (0..lda * n).map(|i| i as f32 * 0.5 - 3.0).collect();
+        let x: Vec<f32> = (0..8).map(|i| i as f32 + 1.0).collect();
+        let a_dev = dev.htod_sync_copy(&a).unwrap();
+        let x_dev = dev.htod_sync_copy(&x).unwrap();
+
+        for (side, incx) in [
+            (sys::cublasSideMode_t::CUBLAS_SIDE_LEFT, 2),
+            (sys::cublasSideMode_t::CUBLAS_SIDE_RIGHT, 2),
+            (sys::cublasSideMode_t::CUBLAS_SIDE_RIGHT, -1),
+        ] {
+            let left = side == sys::cublasSideMode_t::CUBLAS_SIDE_LEFT;
+            let mut expected = vec![0.0; ldc * n];
+            dgmm_truth(left, (m, n), &a, lda, &x, incx as isize, &mut expected, ldc);
+            let mut c_dev = dev.alloc_zeros::<f32>(ldc * n).unwrap();
+            let cfg = DgmmConfig {
+                side,
+                m: m as i32,
+                n: n as i32,
+                lda: lda as i32,
+                incx,
+                ldc: ldc as i32,
+            };
+            unsafe { blas.dgmm(cfg, &a_dev, &x_dev, &mut c_dev) }.unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&c_dev).unwrap(), expected);
+        }
+
+        // 3 rows at a stride of 4 need 9 elements
+        let cfg = DgmmConfig {
+            side: sys::cublasSideMode_t::CUBLAS_SIDE_LEFT,
+            m: m as i32,
+            n: n as i32,
+            lda: lda as i32,
+            incx: 4,
+            ldc: ldc as i32,
+        };
+        let mut c_dev = dev.alloc_zeros::<f32>(ldc * n).unwrap();
+        assert_eq!(
+            unsafe { blas.dgmm(cfg, &a_dev, &x_dev, &mut c_dev) },
+            Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE
+            ))
+        );
+    }
+
+    #[test]
+    fn test_dgmm_in_place_and_scale() {
+        let dev = CudaDevice::new(0).unwrap();
+        let blas = CudaBlas::new(dev.clone()).unwrap();
+        let (m, n, ld) = (4, 3, 6);
+        let a: Vec<f64> = (0..ld * n).map(|i| i as f64 - 5.0).collect();
+        let x: Vec<f64> = [0.5, -2.0, 3.0, 1.5].into();
+        let mut expected = a.clone();
+        dgmm_truth(true, (m, n), &a, ld, &x, 1, &mut expected, ld);
+
+        let mut c_dev = dev.htod_sync_copy(&a).unwrap();
+        let x_dev = dev.htod_sync_copy(&x).unwrap();
+        let cfg = DgmmConfig {
+            side: sys::cublasSideMode_t::CUBLAS_SIDE_LEFT,
+            m: m as i32,
+            n: n as i32,
+            lda: 0,
+            incx: 1,
+            ldc: ld as i32,
+        };
+        unsafe { blas.dgmm_in_place(cfg, &x_dev, &mut c_dev) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&c_dev).unwrap(), expected);
+
+        // row major (2, 3)
+        let a = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let a_dev = dev.htod_sync_copy(&a).unwrap();
+        let mut c_dev = dev.alloc_zeros::<f32>(6).unwrap();
+        let rows = dev.htod_sync_copy(&[10.0f32, -1.0]).unwrap();
+        blas.scale_rows((2, 3), &a_dev, &rows, &mut c_dev).unwrap();
+        assert_eq!(
+            dev.dtoh_sync_copy(&c_dev).unwrap(),
+            [10.0, 20.0, 30.0, -4.0, -5.0, -6.0]
+        );
+        let cols = dev.htod_sync_copy(&[1.0f32, 0.0, 2.0]).unwrap();
+        blas.scale_cols((2, 3), &a_dev, &cols, &mut c_dev).unwrap();
+        assert_eq!(
+            dev.dtoh_sync_copy(&c_dev).unwrap(),
+            [1.0, 0.0, 6.0, 4.0, 0.0, 12.0]
+        );
+        assert_eq!(
+            blas.scale_rows((2, 3), &a_dev, &cols, &mut c_dev),
+            Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_INVALID_VALUE
+            ))
+        );
+    }
+
     #[test]
     fn cublas_pointer_mode() {
         let dev = CudaDevice::new(0).unwrap();
@@ -915,4 +1426,84 @@ mod tests {
             "We have set the mode to use device pointers"
         );
     }
+
+    #[test]
+    fn test_deterministic_gemm_on_several_streams() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut blas = CudaBlas::new(dev.clone()).unwrap();
+        assert!(!blas.is_deterministic());
+        blas.set_deterministic(true).unwrap();
+        assert!(blas.is_deterministic());
+        assert_eq!(
+            unsafe { result::get_atomics_mode(blas.handle) },
+            Ok(sys::cublasAtomicsMode_t::CUBLAS_ATOMICS_NOT_ALLOWED)
+        );
+
+        const N: usize = 512;
+        let host: std::vec::Vec<f32> = (0..N * N)
+            .map(|i| ((i * 7919) % 1009) as f32 / 1009.0 - 0.5)
+            .collect();
+        let a = dev.htod_sync_copy(&host).unwrap();
+        let mut c = dev.alloc_zeros::<f32>(N * N).unwrap();
+        let cfg = GemmConfig {
+            transa: sys::cublasOperation_t::CUBLAS_OP_N,
+            transb: sys::cublasOperation_t::CUBLAS_OP_T,
+            m: N as i32,
+            n: N as i32,
+            k: N as i32,
+            alpha: 1.0,
+            lda: N as i32,
+            ldb: N as i32,
+            beta: 0.0,
+            ldc: N as i32,
+        };
+        let streams = [
+            dev.fork_default_stream().unwrap(),
+            dev.fork_default_stream().unwrap(),
+        ];
+        let mut first = None;
+        for i in 0..100 {
+            unsafe {
+                blas.set_stream(Some(&streams[i % 2])).unwrap();
+                blas.gemm(cfg, &a, &a, &mut c).unwrap();
+            }
+            dev.synchronize().unwrap();
+            let out = dev.dtoh_sync_copy(&c).unwrap();
+            let bits: std::vec::Vec<u32> = out.iter().map(|v| v.to_bits()).collect();
+            match &first {
+                None => first = Some(bits),
+                Some(first) => assert!(*first == bits, "run {i} differs"),
+            }
+        }
+
+        assert!(blas
+            .check_gemm_algo(sys::cublasGemmAlgo_t::CUBLAS_GEMM_DEFAULT_TENSOR_OP)
+            .is_ok());
+        assert_eq!(
+            blas.check_gemm_algo(sys::cublasGemmAlgo_t::CUBLAS_GEMM_ALGO5),
+            Err(CublasError(
+                sys::cublasStatus_t::CUBLAS_STATUS_NOT_SUPPORTED
+            ))
+        );
+        blas.set_math_mode(sys::cublasMath_t::CUBLAS_PEDANTIC_MATH)
+            .unwrap();
+        assert_eq!(
+            blas.get_math_mode(),
+            Ok(sys::cublasMath_t::CUBLAS_PEDANTIC_MATH)
+        );
+
+        unsafe { blas.set_stream(None) }.unwrap();
+        blas.set_deterministic(false).unwrap();
+        assert!(!blas.is_deterministic());
+        assert!(blas
+            .check_gemm_algo(sys::cublasGemmAlgo_t::CUBLAS_GEMM_ALGO5)
+            .is_ok());
+        // the workspace is reused
+        blas.set_deterministic(true).unwrap();
+        blas.set_deterministic(false).unwrap();
+        assert_eq!(
+            unsafe { result::get_atomics_mode(blas.handle) },
+            Ok(sys::cublasAtomicsMode_t::CUBLAS_ATOMICS_ALLOWED)
+        );
+    }
 }
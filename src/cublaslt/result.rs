@@ -269,3 +269,87 @@ pub unsafe fn matmul(
         )
         .result()
 }
+
+/// Creates a matrix transform descriptor. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasltmatrixtransformdesccreate)
+pub fn create_matrix_transform_desc(
+    scale_type: sys::cudaDataType,
+) -> Result<sys::cublasLtMatrixTransformDesc_t, CublasError> {
+    let mut transform_desc = MaybeUninit::uninit();
+    unsafe {
+        lib()
+            .cublasLtMatrixTransformDescCreate(transform_desc.as_mut_ptr(), scale_type)
+            .result()?;
+        Ok(transform_desc.assume_init())
+    }
+}
+
+/// Sets the value of the specified attribute belonging to a previously created matrix transform
+/// descriptor. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasltmatrixtransformdescsetattribute)
+///
+/// # Safety
+/// `transform_desc` must not have been freed already.
+pub unsafe fn set_matrix_transform_desc_attribute(
+    transform_desc: sys::cublasLtMatrixTransformDesc_t,
+    attr: sys::cublasLtMatrixTransformDescAttributes_t,
+    buf: *const c_void,
+    buf_size: usize,
+) -> Result<(), CublasError> {
+    lib()
+        .cublasLtMatrixTransformDescSetAttribute(transform_desc, attr, buf, buf_size)
+        .result()
+}
+
+/// Destroys a matrix transform descriptor previously created with
+/// [create_matrix_transform_desc()]. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasltmatrixtransformdescdestroy)
+///
+/// # Safety
+///
+/// `transform_desc` must not have been freed already.
+pub unsafe fn destroy_matrix_transform_desc(
+    transform_desc: sys::cublasLtMatrixTransformDesc_t,
+) -> Result<(), CublasError> {
+    lib()
+        .cublasLtMatrixTransformDescDestroy(transform_desc)
+        .result()
+}
+
+/// Computes C = alpha * transformation(A) + beta * transformation(B), e.g. to convert
+/// a matrix between orders. See
+/// [nvidia docs](https://docs.nvidia.com/cuda/cublas/index.html#cublasltmatrixtransform)
+///
+/// # Safety
+/// All the sys objects can't have been freed already, and the pointers must hold the
+/// matrices of their layouts. `b` and `b_layout` may be null if `beta` is zero.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn matrix_transform(
+    handle: sys::cublasLtHandle_t,
+    transform_desc: sys::cublasLtMatrixTransformDesc_t,
+    alpha: *const c_void,
+    a: *const c_void,
+    a_layout: sys::cublasLtMatrixLayout_t,
+    beta: *const c_void,
+    b: *const c_void,
+    b_layout: sys::cublasLtMatrixLayout_t,
+    c: *mut c_void,
+    c_layout: sys::cublasLtMatrixLayout_t,
+    stream: sys::cudaStream_t,
+) -> Result<(), CublasError> {
+    lib()
+        .cublasLtMatrixTransform(
+            handle,
+            transform_desc,
+            alpha,
+            a,
+            a_layout,
+            beta,
+            b,
+            b_layout,
+            c,
+            c_layout,
+            stream,
+        )
+        .result()
+}
@@ -2,11 +2,15 @@
 
 use super::{result, result::CublasError, sys};
 use crate::cublaslt::result::set_matrix_layout_attribute;
+use crate::driver::safe::error_handler::{log_library_error, OrHandle};
 use crate::driver::sys::{CUdevice_attribute, CUdeviceptr, CUstream};
-use crate::driver::{CudaDevice, CudaSlice, DevicePtr, DevicePtrMut, DriverError};
+use crate::driver::{
+    CudaDevice, CudaSlice, DevicePtr, DevicePtrMut, DeviceRepr, DriverError, LaunchAsync,
+    LaunchConfig,
+};
 use core::ffi::c_int;
 use core::mem;
-use std::sync::Arc;
+use std::{string::String, sync::Arc};
 
 /// Wrapper around [sys::cublasLtHandle_t]
 ///
@@ -30,8 +34,11 @@ unsafe impl Sync for CudaBlasLT {}
 impl CudaBlasLT {
     /// Creates a new cublasLt handle.
     pub fn new(device: Arc<CudaDevice>) -> Result<Self, CublasError> {
+        let workspace = Workspace::new(device.clone()).or_report(
+            "CudaBlasLT::new",
+            CublasError(sys::cublasStatus_t::CUBLAS_STATUS_NOT_INITIALIZED),
+        )?;
         let handle = result::create_handle()?;
-        let workspace = Workspace::new(device.clone()).unwrap();
 
         Ok(Self {
             handle,
@@ -451,6 +458,530 @@ impl Matmul<half::bf16> for CudaBlasLT {
     }
 }
 
+/// Layouts of the int8 operands of [Int8Matmul].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Int8Layout {
+    /// Row major weights and inputs, as they are usually stored. `k` and `m` must be
+    /// multiples of 4.
+    Row,
+    /// Weights in [sys::cublasLtOrder_t::CUBLASLT_ORDER_COL32] and inputs in
+    /// [sys::cublasLtOrder_t::CUBLASLT_ORDER_COL4_4R2_8C], see
+    /// [CudaBlasLT::transform_to_ordered()]. Needs compute capability 7.2 (Turing) or
+    /// newer.
+    Col32Turing,
+    /// Weights in [sys::cublasLtOrder_t::CUBLASLT_ORDER_COL32] and inputs in
+    /// [sys::cublasLtOrder_t::CUBLASLT_ORDER_COL32_2R_4R4], see
+    /// [CudaBlasLT::transform_to_ordered()]. Needs compute capability 8.0 (Ampere) or
+    /// newer.
+    Col32Ampere,
+}
+
+impl Int8Layout {
+    /// The orders of the weights and the inputs, or `None` for [Int8Layout::Row].
+    fn orders(&self) -> Option<(sys::cublasLtOrder_t, sys::cublasLtOrder_t)> {
+        use sys::cublasLtOrder_t::*;
+        match self {
+            Self::Row => None,
+            Self::Col32Turing => Some((CUBLASLT_ORDER_COL32, CUBLASLT_ORDER_COL4_4R2_8C)),
+            Self::Col32Ampere => Some((CUBLASLT_ORDER_COL32, CUBLASLT_ORDER_COL32_2R_4R4)),
+        }
+    }
+
+    fn min_compute_capability(&self) -> (i32, i32) {
+        match self {
+            Self::Row => (6, 1),
+            Self::Col32Turing => (7, 2),
+            Self::Col32Ampere => (8, 0),
+        }
+    }
+}
+
+/// The leading dimension and number of elements of a `rows` x `cols` matrix in `order`, or
+/// `None` if they overflow.
+pub fn ordered_size(rows: u64, cols: u64, order: sys::cublasLtOrder_t) -> Option<(i64, usize)> {
+    use sys::cublasLtOrder_t::*;
+    let (ld, len) = match order {
+        CUBLASLT_ORDER_COL => (rows, rows.checked_mul(cols)?),
+        CUBLASLT_ORDER_ROW => (cols, rows.checked_mul(cols)?),
+        _ => {
+            let rows = match order {
+                CUBLASLT_ORDER_COL4_4R2_8C => rows.checked_next_multiple_of(8)?,
+                CUBLASLT_ORDER_COL32_2R_4R4 => rows.checked_next_multiple_of(32)?,
+                _ => rows,
+            };
+            let ld = rows.checked_mul(32)?;
+            (ld, ld.checked_mul(cols.div_ceil(32))?)
+        }
+    };
+    Some((i64::try_from(ld).ok()?, usize::try_from(len).ok()?))
+}
+
+/// Error from [Int8Matmul] and [CudaBlasLT::transform_to_ordered()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Int8MatmulError {
+    Cublas(CublasError),
+    Driver(DriverError),
+    /// `dim` must be a multiple of `multiple` with the layout.
+    Dim {
+        dim: &'static str,
+        value: u64,
+        multiple: u64,
+    },
+    /// The device pointer of `operand` is not aligned to `align` bytes.
+    Misaligned {
+        operand: &'static str,
+        align: u64,
+    },
+    /// The number of elements of `operand` overflows `usize`.
+    Overflow {
+        operand: &'static str,
+    },
+    /// `operand` doesn't have the number of elements the dims and layout need.
+    Len {
+        operand: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// The device's compute capability is too old for the layout.
+    Layout {
+        layout: Int8Layout,
+        compute_capability: (i32, i32),
+    },
+}
+
+impl From<CublasError> for Int8MatmulError {
+    fn from(value: CublasError) -> Self {
+        Self::Cublas(value)
+    }
+}
+
+impl From<DriverError> for Int8MatmulError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for Int8MatmulError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cublas(e) => write!(f, "{e}"),
+            Self::Driver(e) => write!(f, "{e}"),
+            Self::Dim {
+                dim,
+                value,
+                multiple,
+            } => write!(f, "{dim} = {value} must be a multiple of {multiple}"),
+            Self::Misaligned { operand, align } => {
+                write!(f, "`{operand}` must be aligned to {align} bytes")
+            }
+            Self::Overflow { operand } => {
+                write!(f, "the number of elements of `{operand}` overflows usize")
+            }
+            Self::Len {
+                operand,
+                expected,
+                found,
+            } => write!(f, "`{operand}` has {found} elements instead of {expected}"),
+            Self::Layout {
+                layout,
+                compute_capability: (major, minor),
+            } => {
+                let (min_major, min_minor) = layout.min_compute_capability();
+                write!(
+                    f,
+                    "{layout:?} needs compute capability {min_major}.{min_minor}, the device has {major}.{minor}"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Int8MatmulError {}
+
+/// Output element types of [Int8Matmul].
+///
+/// # Safety
+/// [Int8Output::NAME] must be the name of the dequantize kernel writing `Self`.
+pub unsafe trait Int8Output: DeviceRepr {
+    const NAME: &'static str;
+}
+
+unsafe impl Int8Output for f32 {
+    const NAME: &'static str = "f32";
+}
+
+#[cfg(feature = "f16")]
+unsafe impl Int8Output for half::f16 {
+    const NAME: &'static str = "f16";
+}
+
+const DEQUANT_MODULE: &str = "cudarc_int8_dequant";
+
+const DEQUANT_KERNEL_NAMES: &[&str] = &["dequant_f32", "dequant_f16"];
+
+/// Scales the `m` x `n` column major i32 accumulators, or COL32 ones, by the per-channel
+/// weight scales and the inputs scales, and adds the bias.
+fn dequant_src() -> String {
+    let mut src = String::new();
+    for (name, word, narrow) in [
+        ("f32", "float", "v"),
+        (
+            "f16",
+            "unsigned short",
+            "[&] { unsigned short h; asm(\"cvt.rn.f16.f32 %0, %1;\" : \"=h\"(h) : \"f\"(v)); return h; }()",
+        ),
+    ] {
+        src.push_str(&std::format!(
+            "
+extern \"C\" __global__ void dequant_{name}(const int *acc, const size_t m, const size_t n, const int col32, const float *w_scales, const float *x_scales, const int per_row_x, const float *bias, {word} *out) {{
+    for (size_t idx = (size_t)blockIdx.x * blockDim.x + threadIdx.x; idx < m * n; idx += (size_t)blockDim.x * gridDim.x) {{
+        const size_t i = idx % m, j = idx / m;
+        const size_t src = col32 ? (j / 32) * 32 * m + i * 32 + j % 32 : idx;
+        float v = (float)acc[src] * w_scales[i] * x_scales[per_row_x ? j : 0];
+        if (bias) {{
+            v += bias[i];
+        }}
+        out[idx] = {narrow};
+    }}
+}}"
+        ));
+    }
+    src
+}
+
+/// A quantized linear layer `y = dequant(x * w^T) + bias`: `x` holds `n` rows of `k` int8
+/// inputs, `w` holds `m` rows of `k` int8 weights, and `y` gets `n` rows of `m` outputs,
+/// in f32 or f16. The product is accumulated in i32 by cublasLt, then each output
+/// `y[j][i]` is scaled by `w_scales[i]` of its channel and `x_scales[j]` of its row (or
+/// `x_scales[0]` for all rows), and `bias[i]` is added.
+///
+/// With [Int8Layout::Row] `x` and `w` are row major. The other layouts use the faster
+/// kernels of newer GPUs, and need `w` and `x` converted once with
+/// [CudaBlasLT::transform_to_ordered()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Int8Matmul {
+    pub m: u64,
+    pub n: u64,
+    pub k: u64,
+    pub layout: Int8Layout,
+}
+
+impl Int8Matmul {
+    /// Checks the compute capability, dims, lengths and alignment of the operands of
+    /// [Int8Matmul::launch()].
+    #[allow(clippy::too_many_arguments)]
+    pub fn validate<W, X, S, Sx, B, Y, T>(
+        &self,
+        blas: &CudaBlasLT,
+        w: &W,
+        x: &X,
+        w_scales: &S,
+        x_scales: &Sx,
+        bias: Option<&B>,
+        y: &Y,
+    ) -> Result<(), Int8MatmulError>
+    where
+        W: DevicePtr<i8>,
+        X: DevicePtr<i8>,
+        S: DevicePtr<f32>,
+        Sx: DevicePtr<f32>,
+        B: DevicePtr<f32>,
+        Y: DevicePtr<T>,
+    {
+        let compute_capability = blas.device.compute_capability()?;
+        if compute_capability < self.layout.min_compute_capability() {
+            return Err(Int8MatmulError::Layout {
+                layout: self.layout,
+                compute_capability,
+            });
+        }
+        let (w_order, x_order) = match self.layout.orders() {
+            None => {
+                for (dim, value) in [("k", self.k), ("m", self.m)] {
+                    if value % 4 != 0 {
+                        return Err(Int8MatmulError::Dim {
+                            dim,
+                            value,
+                            multiple: 4,
+                        });
+                    }
+                }
+                let row = sys::cublasLtOrder_t::CUBLASLT_ORDER_ROW;
+                (row, row)
+            }
+            Some(orders) => orders,
+        };
+        let len = |operand, rows, cols, order| {
+            ordered_size(rows, cols, order)
+                .map(|(_, len)| len)
+                .ok_or(Int8MatmulError::Overflow { operand })
+        };
+        let w_len = len("w", self.m, self.k, w_order)?;
+        let x_len = len("x", self.n, self.k, x_order)?;
+        let y_len = len(
+            "y",
+            self.n,
+            self.m,
+            sys::cublasLtOrder_t::CUBLASLT_ORDER_ROW,
+        )?;
+        let check_len = |operand, expected, found| {
+            if expected != found {
+                return Err(Int8MatmulError::Len {
+                    operand,
+                    expected,
+                    found,
+                });
+            }
+            Ok(())
+        };
+        check_len("w", w_len, w.len())?;
+        check_len("x", x_len, x.len())?;
+        check_len("w_scales", self.m as usize, w_scales.len())?;
+        if x_scales.len() != 1 {
+            check_len("x_scales", self.n as usize, x_scales.len())?;
+        }
+        if let Some(bias) = bias {
+            check_len("bias", self.m as usize, bias.len())?;
+        }
+        check_len("y", y_len, y.len())?;
+        let f32_align = mem::align_of::<f32>() as u64;
+        let mut operands = std::vec![
+            ("w", *w.device_ptr(), 16),
+            ("x", *x.device_ptr(), 16),
+            ("w_scales", *w_scales.device_ptr(), f32_align),
+            ("x_scales", *x_scales.device_ptr(), f32_align),
+            ("y", *y.device_ptr(), mem::align_of::<T>() as u64),
+        ];
+        if let Some(bias) = bias {
+            operands.push(("bias", *bias.device_ptr(), f32_align));
+        }
+        for (operand, ptr, align) in operands {
+            if ptr % align != 0 {
+                return Err(Int8MatmulError::Misaligned { operand, align });
+            }
+        }
+        Ok(())
+    }
+
+    /// Launches the matmul and the dequantization on the device's default stream, after
+    /// [Int8Matmul::validate()].
+    #[allow(clippy::too_many_arguments)]
+    pub fn launch<W, X, S, Sx, B, Y, T>(
+        &self,
+        blas: &CudaBlasLT,
+        w: &W,
+        x: &X,
+        w_scales: &S,
+        x_scales: &Sx,
+        bias: Option<&B>,
+        y: &mut Y,
+    ) -> Result<(), Int8MatmulError>
+    where
+        W: DevicePtr<i8>,
+        X: DevicePtr<i8>,
+        S: DevicePtr<f32>,
+        Sx: DevicePtr<f32>,
+        B: DevicePtr<f32>,
+        Y: DevicePtr<T> + DevicePtrMut<T>,
+        T: Int8Output,
+    {
+        self.validate(blas, w, x, w_scales, x_scales, bias, y)?;
+        let i8_type = sys::cudaDataType_t::CUDA_R_8I;
+        let i32_type = sys::cudaDataType_t::CUDA_R_32I;
+        let (w_layout, x_layout, acc_layout, acc_len) = match self.layout.orders() {
+            None => (
+                MatrixLayout::new(i8_type, self.k, self.m, self.k as i64)?,
+                MatrixLayout::new(i8_type, self.k, self.n, self.k as i64)?,
+                MatrixLayout::new(i32_type, self.m, self.n, self.m as i64)?,
+                (self.m * self.n) as usize,
+            ),
+            Some((w_order, x_order)) => {
+                let acc_order = sys::cublasLtOrder_t::CUBLASLT_ORDER_COL32;
+                let (acc_ld, acc_len) = ordered_size(self.m, self.n, acc_order)
+                    .ok_or(Int8MatmulError::Overflow { operand: "acc" })?;
+                (
+                    MatrixLayout::new_ordered(i8_type, self.m, self.k, w_order)?,
+                    MatrixLayout::new_ordered(i8_type, self.n, self.k, x_order)?,
+                    MatrixLayout::new(i32_type, self.m, self.n, acc_ld)?.with_order(acc_order)?,
+                    acc_len,
+                )
+            }
+        };
+        let mut acc = unsafe { blas.device.alloc::<i32>(acc_len) }?;
+
+        let matmul_desc = MatmulDesc::new(sys::cublasComputeType_t::CUBLAS_COMPUTE_32I, i32_type)?;
+        // the weights are transposed from the row major layout, the ordered inputs are
+        // stored `n` x `k`
+        let row = self.layout == Int8Layout::Row;
+        matmul_desc.set_transpose(row, Matrix::A)?;
+        matmul_desc.set_transpose(!row, Matrix::B)?;
+        let matmul_pref = MatmulPref::new()?;
+        matmul_pref.set_workspace_size(blas.workspace.size)?;
+        let (alpha, beta) = (1i32, 0i32);
+        unsafe {
+            let heuristic = result::get_matmul_algo_heuristic(
+                blas.handle,
+                matmul_desc.handle,
+                w_layout.handle,
+                x_layout.handle,
+                acc_layout.handle,
+                acc_layout.handle,
+                matmul_pref.handle,
+            )?;
+            result::matmul(
+                blas.handle,
+                matmul_desc.handle,
+                (&alpha) as *const _ as *const _,
+                (&beta) as *const _ as *const _,
+                *w.device_ptr() as *const _,
+                w_layout.handle,
+                *x.device_ptr() as *const _,
+                x_layout.handle,
+                *acc.device_ptr_mut() as *const _,
+                acc_layout.handle,
+                *acc.device_ptr_mut() as *mut _,
+                acc_layout.handle,
+                (&heuristic.algo) as *const _,
+                *blas.workspace.buffer.device_ptr() as *const CUdeviceptr as *mut _,
+                blas.workspace.size,
+                blas.device.stream as *mut _,
+            )?;
+        }
+
+        let dev = &blas.device;
+        let f = dev.load_builtin(
+            DEQUANT_MODULE,
+            dequant_src,
+            DEQUANT_KERNEL_NAMES,
+            &std::format!("dequant_{}", T::NAME),
+        )?;
+        let num_elems = self.m * self.n;
+        let cfg = LaunchConfig {
+            grid_dim: (num_elems.div_ceil(256).min(65_535) as u32, 1, 1),
+            block_dim: (256, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let params = (
+            &acc,
+            self.m as usize,
+            self.n as usize,
+            !row as i32,
+            *w_scales.device_ptr(),
+            *x_scales.device_ptr(),
+            (x_scales.len() != 1) as i32,
+            bias.map_or(0, |b| *b.device_ptr()),
+            *y.device_ptr_mut(),
+        );
+        unsafe { f.launch(dev.default_stream(), cfg, params) }?;
+        Ok(())
+    }
+}
+
+impl MatrixLayout {
+    /// A `rows` x `cols` layout in `order`, see [ordered_size()].
+    fn new_ordered(
+        matrix_type: sys::cudaDataType,
+        rows: u64,
+        cols: u64,
+        order: sys::cublasLtOrder_t,
+    ) -> Result<Self, Int8MatmulError> {
+        let (ld, _) = ordered_size(rows, cols, order)
+            .ok_or(Int8MatmulError::Overflow { operand: "layout" })?;
+        Ok(Self::new(matrix_type, rows, cols, ld)?.with_order(order)?)
+    }
+
+    fn with_order(self, order: sys::cublasLtOrder_t) -> Result<Self, CublasError> {
+        let order = order as i32;
+        unsafe {
+            set_matrix_layout_attribute(
+                self.handle,
+                sys::cublasLtMatrixLayoutAttribute_t::CUBLASLT_MATRIX_LAYOUT_ORDER,
+                (&order) as *const _ as *const _,
+                mem::size_of::<i32>(),
+            )?;
+        }
+        Ok(self)
+    }
+}
+
+/// MatrixTransformDesc helper type
+struct MatrixTransformDesc {
+    handle: sys::cublasLtMatrixTransformDesc_t,
+}
+
+impl Drop for MatrixTransformDesc {
+    fn drop(&mut self) {
+        if let Err(err) = unsafe { result::destroy_matrix_transform_desc(self.handle) } {
+            log_library_error(&err, "MatrixTransformDesc::drop");
+        }
+    }
+}
+
+impl CudaBlasLT {
+    /// Converts `src`, `rows` rows of `cols` row major elements, to a `rows` x `cols`
+    /// matrix in `order`, e.g. the weights and inputs of [Int8Matmul] for the ordered
+    /// [Int8Layout]s. The result has the number of elements of [ordered_size()], padded
+    /// with zeros.
+    pub fn transform_to_ordered<Src: DevicePtr<i8>>(
+        &self,
+        src: &Src,
+        rows: u64,
+        cols: u64,
+        order: sys::cublasLtOrder_t,
+    ) -> Result<CudaSlice<i8>, Int8MatmulError> {
+        let len = |operand, order| {
+            ordered_size(rows, cols, order)
+                .map(|(_, len)| len)
+                .ok_or(Int8MatmulError::Overflow { operand })
+        };
+        let src_len = len("src", sys::cublasLtOrder_t::CUBLASLT_ORDER_ROW)?;
+        let dst_len = len("dst", order)?;
+        if src.len() != src_len {
+            return Err(Int8MatmulError::Len {
+                operand: "src",
+                expected: src_len,
+                found: src.len(),
+            });
+        }
+        let i8_type = sys::cudaDataType_t::CUDA_R_8I;
+        // row major is column major `cols` x `rows`, transposed by the transform
+        let src_layout = MatrixLayout::new(i8_type, cols, rows, cols as i64)?;
+        let dst_layout = MatrixLayout::new_ordered(i8_type, rows, cols, order)?;
+        let mut dst = self.device.alloc_zeros::<i8>(dst_len)?;
+
+        let desc = MatrixTransformDesc {
+            handle: result::create_matrix_transform_desc(sys::cudaDataType_t::CUDA_R_32F)?,
+        };
+        // 1 == T
+        let transpose = 1i32;
+        let (alpha, beta) = (1.0f32, 0.0f32);
+        unsafe {
+            result::set_matrix_transform_desc_attribute(
+                desc.handle,
+                sys::cublasLtMatrixTransformDescAttributes_t::CUBLASLT_MATRIX_TRANSFORM_DESC_TRANSA,
+                (&transpose) as *const _ as *const _,
+                mem::size_of::<i32>(),
+            )?;
+            result::matrix_transform(
+                self.handle,
+                desc.handle,
+                (&alpha) as *const _ as *const _,
+                *src.device_ptr() as *const _,
+                src_layout.handle,
+                (&beta) as *const _ as *const _,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                *dst.device_ptr_mut() as *mut _,
+                dst_layout.handle,
+                self.device.stream as *mut _,
+            )?;
+        }
+        Ok(dst)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::needless_range_loop)]
@@ -458,6 +989,7 @@ mod tests {
     use super::sys::lib;
     use super::*;
     use std::ffi::CString;
+    use std::vec::Vec;
 
     fn matmul_truth<T, const M: usize, const N: usize, const K: usize>(
         alpha: T,
@@ -743,4 +1275,184 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_ordered_size() {
+        use sys::cublasLtOrder_t::*;
+        assert_eq!(ordered_size(3, 5, CUBLASLT_ORDER_COL), Some((3, 15)));
+        assert_eq!(ordered_size(3, 5, CUBLASLT_ORDER_ROW), Some((5, 15)));
+        assert_eq!(ordered_size(3, 40, CUBLASLT_ORDER_COL32), Some((96, 192)));
+        assert_eq!(
+            ordered_size(3, 40, CUBLASLT_ORDER_COL4_4R2_8C),
+            Some((256, 512))
+        );
+        assert_eq!(
+            ordered_size(33, 32, CUBLASLT_ORDER_COL32_2R_4R4),
+            Some((2048, 2048))
+        );
+        assert_eq!(ordered_size(1 << 32, 1 << 32, CUBLASLT_ORDER_ROW), None);
+        assert_eq!(ordered_size(u64::MAX, 1, CUBLASLT_ORDER_COL32_2R_4R4), None);
+        assert_eq!(ordered_size(1 << 60, 32, CUBLASLT_ORDER_COL32), None);
+    }
+
+    /// Symmetric quantization of each row of `cols` values, returning the scale of each row.
+    fn quantize_rows(x: &[f32], cols: usize) -> (Vec<i8>, Vec<f32>) {
+        let mut q = Vec::with_capacity(x.len());
+        let mut scales = Vec::new();
+        for row in x.chunks(cols) {
+            let scale = row.iter().fold(0.0f32, |m, v| m.max(v.abs())) / 127.0;
+            q.extend(row.iter().map(|v| (v / scale).round() as i8));
+            scales.push(scale);
+        }
+        (q, scales)
+    }
+
+    #[test]
+    fn test_int8_matmul() {
+        let dev = CudaDevice::new(0).unwrap();
+        let blas = CudaBlasLT::new(dev.clone()).unwrap();
+        let (m, n, k) = (48, 5, 64);
+        let w: Vec<f32> = (0..m * k)
+            .map(|i| ((i * 37 % 101) as f32 - 50.0) / 25.0)
+            .collect();
+        let x: Vec<f32> = (0..n * k)
+            .map(|i| ((i * 53 % 89) as f32 - 44.0) / 30.0)
+            .collect();
+        let bias: Vec<f32> = (0..m).map(|i| i as f32 / 8.0 - 3.0).collect();
+        let mut expected = std::vec![0.0f32; n * m];
+        let mut max_abs = 0.0f32;
+        for j in 0..n {
+            for i in 0..m {
+                let dot: f32 = (0..k).map(|c| x[j * k + c] * w[i * k + c]).sum();
+                expected[j * m + i] = dot + bias[i];
+                max_abs = max_abs.max(expected[j * m + i].abs());
+            }
+        }
+
+        let (wq, w_scales) = quantize_rows(&w, k);
+        let (xq, x_scales) = quantize_rows(&x, k);
+        let w_dev = dev.htod_copy(wq).unwrap();
+        let x_dev = dev.htod_copy(xq).unwrap();
+        let w_scales = dev.htod_copy(w_scales).unwrap();
+        let x_scales = dev.htod_copy(x_scales).unwrap();
+        let bias = dev.htod_copy(bias).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(n * m).unwrap();
+        let check = |y: &CudaSlice<f32>| {
+            for (found, expected) in dev.dtoh_sync_copy(y).unwrap().iter().zip(&expected) {
+                assert!(
+                    (found - expected).abs() <= 0.02 * max_abs,
+                    "found={found}, expected={expected}"
+                );
+            }
+        };
+
+        let op = Int8Matmul {
+            m: m as u64,
+            n: n as u64,
+            k: k as u64,
+            layout: Int8Layout::Row,
+        };
+        op.launch(
+            &blas,
+            &w_dev,
+            &x_dev,
+            &w_scales,
+            &x_scales,
+            Some(&bias),
+            &mut y,
+        )
+        .unwrap();
+        check(&y);
+
+        let cc = dev.compute_capability().unwrap();
+        for layout in [Int8Layout::Col32Turing, Int8Layout::Col32Ampere] {
+            let op = Int8Matmul { layout, ..op };
+            let (w_order, x_order) = layout.orders().unwrap();
+            let w_ordered = blas
+                .transform_to_ordered(&w_dev, m as u64, k as u64, w_order)
+                .unwrap();
+            let x_ordered = blas
+                .transform_to_ordered(&x_dev, n as u64, k as u64, x_order)
+                .unwrap();
+            let res = op.launch(
+                &blas,
+                &w_ordered,
+                &x_ordered,
+                &w_scales,
+                &x_scales,
+                Some(&bias),
+                &mut y,
+            );
+            if cc < layout.min_compute_capability() {
+                assert!(matches!(res, Err(Int8MatmulError::Layout { .. })));
+                continue;
+            }
+            res.unwrap();
+            check(&y);
+        }
+
+        // the row major layout needs k to be a multiple of 4
+        let op = Int8Matmul { k: 62, ..op };
+        assert_eq!(
+            op.validate(&blas, &w_dev, &x_dev, &w_scales, &x_scales, Some(&bias), &y),
+            Err(Int8MatmulError::Dim {
+                dim: "k",
+                value: 62,
+                multiple: 4
+            })
+        );
+        let op = Int8Matmul { n: 4, ..op };
+        let op = Int8Matmul { k: 64, ..op };
+        assert_eq!(
+            op.validate(
+                &blas,
+                &w_dev,
+                &x_dev,
+                &w_scales,
+                &x_scales,
+                None::<&CudaSlice<f32>>,
+                &y
+            ),
+            Err(Int8MatmulError::Len {
+                operand: "x",
+                expected: 4 * 64,
+                found: 5 * 64
+            })
+        );
+
+        // a valid length one byte off
+        let op = Int8Matmul { n: 5, ..op };
+        let w_padded = dev.alloc_zeros::<i8>(m * k + 1).unwrap();
+        assert_eq!(
+            op.validate(
+                &blas,
+                &w_padded.slice(1..),
+                &x_dev,
+                &w_scales,
+                &x_scales,
+                Some(&bias),
+                &y
+            ),
+            Err(Int8MatmulError::Misaligned {
+                operand: "w",
+                align: 16
+            })
+        );
+        let op = Int8Matmul { m: 1 << 62, ..op };
+        assert_eq!(
+            op.validate(&blas, &w_dev, &x_dev, &w_scales, &x_scales, Some(&bias), &y),
+            Err(Int8MatmulError::Overflow { operand: "w" })
+        );
+        #[cfg(feature = "std")]
+        assert_eq!(
+            std::format!(
+                "{}",
+                Int8MatmulError::Misaligned {
+                    operand: "x",
+                    align: 16
+                }
+            ),
+            "`x` must be aligned to 16 bytes"
+        );
+    }
 }
@@ -1,11 +1,15 @@
 //! A thin wrapper around [sys] providing [Result]s with [CudnnError].
 
 use std::mem::MaybeUninit;
+use std::vec::Vec;
 
 use super::sys::{self, lib};
 
 pub type CudnnResult<T> = Result<T, CudnnError>;
 
+/// The maximum number of dimensions of a descriptor (`CUDNN_DIM_MAX`).
+const DIM_MAX: usize = 8;
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct CudnnError(pub sys::cudnnStatus_t);
 
@@ -120,6 +124,18 @@ pub unsafe fn set_stream(
     lib().cudnnSetStream(handle, stream).result()
 }
 
+/// Gets the stream cuDNN uses. See
+/// [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetStream)
+///
+/// # Safety
+///
+/// `handle` must be valid.
+pub unsafe fn get_stream(handle: sys::cudnnHandle_t) -> Result<sys::cudaStream_t, CudnnError> {
+    let mut stream = MaybeUninit::uninit();
+    lib().cudnnGetStream(handle, stream.as_mut_ptr()).result()?;
+    Ok(stream.assume_init())
+}
+
 /// Allocates a new tensor descriptor.
 /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCreateTensorDescriptor)
 pub fn create_tensor_descriptor() -> Result<sys::cudnnTensorDescriptor_t, CudnnError> {
@@ -192,6 +208,73 @@ pub unsafe fn set_tensornd_descriptor(
         .result()
 }
 
+/// Sets data on a tensor descriptor, with packed strides for `format`. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetTensorNdDescriptorEx)
+///
+/// # Safety
+/// `tensor_desc` must have been created with [create_tensor_descriptor], and
+/// NOT freed by [destroy_tensor_descriptor]
+pub unsafe fn set_tensornd_descriptor_ex(
+    tensor_desc: sys::cudnnTensorDescriptor_t,
+    format: sys::cudnnTensorFormat_t,
+    data_type: sys::cudnnDataType_t,
+    num_dims: ::std::os::raw::c_int,
+    dims: *const ::std::os::raw::c_int,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSetTensorNdDescriptorEx(tensor_desc, format, data_type, num_dims, dims)
+        .result()
+}
+
+/// Returns the `(data_type, dims, strides)` of a tensor descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetTensorNdDescriptor)
+///
+/// # Safety
+/// `tensor_desc` must have been set and NOT already freed.
+pub unsafe fn get_tensornd_descriptor(
+    tensor_desc: sys::cudnnTensorDescriptor_t,
+) -> Result<
+    (
+        sys::cudnnDataType_t,
+        Vec<std::ffi::c_int>,
+        Vec<std::ffi::c_int>,
+    ),
+    CudnnError,
+> {
+    let mut data_type = MaybeUninit::uninit();
+    let mut nb_dims = 0;
+    let mut dims = [0; DIM_MAX];
+    let mut strides = [0; DIM_MAX];
+    lib()
+        .cudnnGetTensorNdDescriptor(
+            tensor_desc,
+            DIM_MAX as std::ffi::c_int,
+            data_type.as_mut_ptr(),
+            &mut nb_dims,
+            dims.as_mut_ptr(),
+            strides.as_mut_ptr(),
+        )
+        .result()?;
+    let n = nb_dims as usize;
+    Ok((
+        data_type.assume_init(),
+        dims[..n].to_vec(),
+        strides[..n].to_vec(),
+    ))
+}
+
+/// Returns the number of bytes of memory described by a tensor descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetTensorSizeInBytes)
+///
+/// # Safety
+/// `tensor_desc` must have been set and NOT already freed.
+pub unsafe fn get_tensor_size_in_bytes(
+    tensor_desc: sys::cudnnTensorDescriptor_t,
+) -> Result<usize, CudnnError> {
+    let mut size = 0;
+    lib()
+        .cudnnGetTensorSizeInBytes(tensor_desc, &mut size)
+        .result()?;
+    Ok(size)
+}
+
 /// Destroys a tensor descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroyTensorDescriptor)
 ///
 /// # Safety
@@ -246,6 +329,55 @@ pub unsafe fn set_filternd_descriptor(
         .result()
 }
 
+/// Returns the `(data_type, format, dims)` of a filter descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetFilterNdDescriptor)
+///
+/// # Safety
+/// `filter_desc` must have been set and NOT already freed.
+pub unsafe fn get_filternd_descriptor(
+    filter_desc: sys::cudnnFilterDescriptor_t,
+) -> Result<
+    (
+        sys::cudnnDataType_t,
+        sys::cudnnTensorFormat_t,
+        Vec<std::ffi::c_int>,
+    ),
+    CudnnError,
+> {
+    let mut data_type = MaybeUninit::uninit();
+    let mut format = MaybeUninit::uninit();
+    let mut nb_dims = 0;
+    let mut dims = [0; DIM_MAX];
+    lib()
+        .cudnnGetFilterNdDescriptor(
+            filter_desc,
+            DIM_MAX as std::ffi::c_int,
+            data_type.as_mut_ptr(),
+            format.as_mut_ptr(),
+            &mut nb_dims,
+            dims.as_mut_ptr(),
+        )
+        .result()?;
+    Ok((
+        data_type.assume_init(),
+        format.assume_init(),
+        dims[..nb_dims as usize].to_vec(),
+    ))
+}
+
+/// Returns the number of bytes of memory described by a filter descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetFilterSizeInBytes)
+///
+/// # Safety
+/// `filter_desc` must have been set and NOT already freed.
+pub unsafe fn get_filter_size_in_bytes(
+    filter_desc: sys::cudnnFilterDescriptor_t,
+) -> Result<usize, CudnnError> {
+    let mut size = 0;
+    lib()
+        .cudnnGetFilterSizeInBytes(filter_desc, &mut size)
+        .result()?;
+    Ok(size)
+}
+
 /// Destroys a filter descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroyFilterDescriptor)
 ///
 /// # Safety
@@ -349,6 +481,100 @@ pub unsafe fn set_convolution_group_count(
         .result()
 }
 
+/// The parameters of a convolution descriptor, see [get_convolutionnd_descriptor].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConvolutionNdParams {
+    pub pads: Vec<std::ffi::c_int>,
+    pub strides: Vec<std::ffi::c_int>,
+    pub dilations: Vec<std::ffi::c_int>,
+    pub mode: sys::cudnnConvolutionMode_t,
+    pub compute_type: sys::cudnnDataType_t,
+}
+
+/// Returns the parameters of a convolution descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionNdDescriptor)
+///
+/// # Safety
+/// `desc` must have been set and NOT already freed.
+pub unsafe fn get_convolutionnd_descriptor(
+    desc: sys::cudnnConvolutionDescriptor_t,
+) -> Result<ConvolutionNdParams, CudnnError> {
+    let mut len = 0;
+    let mut pads = [0; DIM_MAX];
+    let mut strides = [0; DIM_MAX];
+    let mut dilations = [0; DIM_MAX];
+    let mut mode = MaybeUninit::uninit();
+    let mut compute_type = MaybeUninit::uninit();
+    lib()
+        .cudnnGetConvolutionNdDescriptor(
+            desc,
+            DIM_MAX as std::ffi::c_int,
+            &mut len,
+            pads.as_mut_ptr(),
+            strides.as_mut_ptr(),
+            dilations.as_mut_ptr(),
+            mode.as_mut_ptr(),
+            compute_type.as_mut_ptr(),
+        )
+        .result()?;
+    let n = len as usize;
+    Ok(ConvolutionNdParams {
+        pads: pads[..n].to_vec(),
+        strides: strides[..n].to_vec(),
+        dilations: dilations[..n].to_vec(),
+        mode: mode.assume_init(),
+        compute_type: compute_type.assume_init(),
+    })
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionMathType)
+/// # Safety
+/// `desc` must NOT have been freed already
+pub unsafe fn get_convolution_math_type(
+    desc: sys::cudnnConvolutionDescriptor_t,
+) -> Result<sys::cudnnMathType_t, CudnnError> {
+    let mut math_type = MaybeUninit::uninit();
+    lib()
+        .cudnnGetConvolutionMathType(desc, math_type.as_mut_ptr())
+        .result()?;
+    Ok(math_type.assume_init())
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionGroupCount)
+/// # Safety
+/// `desc` must NOT have been freed already
+pub unsafe fn get_convolution_group_count(
+    desc: sys::cudnnConvolutionDescriptor_t,
+) -> Result<std::ffi::c_int, CudnnError> {
+    let mut group_count = 0;
+    lib()
+        .cudnnGetConvolutionGroupCount(desc, &mut group_count)
+        .result()?;
+    Ok(group_count)
+}
+
+/// Returns the dims of the output of a forward convolution with `num_dims` dimensions. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionNdForwardOutputDim)
+///
+/// # Safety
+/// The descriptors must have been set and NOT already freed.
+pub unsafe fn get_convolutionnd_forward_output_dim(
+    desc: sys::cudnnConvolutionDescriptor_t,
+    input_desc: sys::cudnnTensorDescriptor_t,
+    filter_desc: sys::cudnnFilterDescriptor_t,
+    num_dims: usize,
+) -> Result<Vec<std::ffi::c_int>, CudnnError> {
+    let mut dims = [0; DIM_MAX];
+    lib()
+        .cudnnGetConvolutionNdForwardOutputDim(
+            desc,
+            input_desc,
+            filter_desc,
+            num_dims.min(DIM_MAX) as std::ffi::c_int,
+            dims.as_mut_ptr(),
+        )
+        .result()?;
+    Ok(dims[..num_dims.min(DIM_MAX)].to_vec())
+}
+
 /// Destroys a descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroyConvolutionDescriptor).
 /// # Safety
 /// `desc` must NOT have been already freed.
@@ -388,6 +614,47 @@ pub unsafe fn get_convolution_forward_algorithm(
         .result()
 }
 
+/// Times every forward algorithm on the given buffers, using at most
+/// `work_space_size_in_bytes` of workspace. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnFindConvolutionForwardAlgorithmEx)
+///
+/// # Safety
+/// - All handles & descriptors must still be allocated.
+/// - The pointers must point to valid memory of the sizes in the descriptors. `y` is overwritten.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn find_convolution_forward_algorithm_ex(
+    handle: sys::cudnnHandle_t,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    w_desc: sys::cudnnFilterDescriptor_t,
+    w: *const std::ffi::c_void,
+    conv_desc: sys::cudnnConvolutionDescriptor_t,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *mut std::ffi::c_void,
+    requested_algo_count: std::ffi::c_int,
+    returned_algo_count: *mut std::ffi::c_int,
+    perf_results: *mut sys::cudnnConvolutionFwdAlgoPerf_t,
+    work_space: *mut std::ffi::c_void,
+    work_space_size_in_bytes: usize,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnFindConvolutionForwardAlgorithmEx(
+            handle,
+            x_desc,
+            x,
+            w_desc,
+            w,
+            conv_desc,
+            y_desc,
+            y,
+            requested_algo_count,
+            returned_algo_count,
+            perf_results,
+            work_space,
+            work_space_size_in_bytes,
+        )
+        .result()
+}
+
 /// Returns size in **bytes**. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionForwardWorkspaceSize)
 /// # Safety
 /// - All handles & descriptors must still be allocated.
@@ -458,6 +725,59 @@ pub unsafe fn convolution_forward(
         .result()
 }
 
+/// Launches the fused convolution, bias add and activation forward kernel.
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnConvolutionBiasActivationForward).
+///
+/// # Safety
+/// - handles and descriptors must still be allocated
+/// - all pointers must be valid data pointers
+/// - the format of descriptors should match the data allocated
+///   in the pointers.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn convolution_bias_activation_forward(
+    handle: sys::cudnnHandle_t,
+    alpha1: *const ::core::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const ::core::ffi::c_void,
+    w_desc: sys::cudnnFilterDescriptor_t,
+    w: *const ::core::ffi::c_void,
+    conv_desc: sys::cudnnConvolutionDescriptor_t,
+    algo: sys::cudnnConvolutionFwdAlgo_t,
+    work_space: *mut ::core::ffi::c_void,
+    work_space_size_in_bytes: usize,
+    alpha2: *const ::core::ffi::c_void,
+    z_desc: sys::cudnnTensorDescriptor_t,
+    z: *const ::core::ffi::c_void,
+    bias_desc: sys::cudnnTensorDescriptor_t,
+    bias: *const ::core::ffi::c_void,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *mut ::core::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnConvolutionBiasActivationForward(
+            handle,
+            alpha1,
+            x_desc,
+            x,
+            w_desc,
+            w,
+            conv_desc,
+            algo,
+            work_space,
+            work_space_size_in_bytes,
+            alpha2,
+            z_desc,
+            z,
+            bias_desc,
+            bias,
+            activation_desc,
+            y_desc,
+            y,
+        )
+        .result()
+}
+
 /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionBackwardDataAlgorithm_v7)
 ///
 /// # Safety
@@ -488,6 +808,47 @@ pub unsafe fn get_convolution_backward_data_algorithm(
         .result()
 }
 
+/// Times every backward data algorithm on the given buffers, using at most
+/// `work_space_size_in_bytes` of workspace. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnFindConvolutionBackwardDataAlgorithmEx)
+///
+/// # Safety
+/// - All handles & descriptors must still be allocated.
+/// - The pointers must point to valid memory of the sizes in the descriptors. `dx` is overwritten.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn find_convolution_backward_data_algorithm_ex(
+    handle: sys::cudnnHandle_t,
+    w_desc: sys::cudnnFilterDescriptor_t,
+    w: *const std::ffi::c_void,
+    dy_desc: sys::cudnnTensorDescriptor_t,
+    dy: *const std::ffi::c_void,
+    conv_desc: sys::cudnnConvolutionDescriptor_t,
+    dx_desc: sys::cudnnTensorDescriptor_t,
+    dx: *mut std::ffi::c_void,
+    requested_algo_count: std::ffi::c_int,
+    returned_algo_count: *mut std::ffi::c_int,
+    perf_results: *mut sys::cudnnConvolutionBwdDataAlgoPerf_t,
+    work_space: *mut std::ffi::c_void,
+    work_space_size_in_bytes: usize,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnFindConvolutionBackwardDataAlgorithmEx(
+            handle,
+            w_desc,
+            w,
+            dy_desc,
+            dy,
+            conv_desc,
+            dx_desc,
+            dx,
+            requested_algo_count,
+            returned_algo_count,
+            perf_results,
+            work_space,
+            work_space_size_in_bytes,
+        )
+        .result()
+}
+
 /// Returns size in **bytes**. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionBackwardDataWorkspaceSize)
 /// # Safety
 /// - All handles & descriptors must still be allocated.
@@ -588,6 +949,47 @@ pub unsafe fn get_convolution_backward_filter_algorithm(
         .result()
 }
 
+/// Times every backward filter algorithm on the given buffers, using at most
+/// `work_space_size_in_bytes` of workspace. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnFindConvolutionBackwardFilterAlgorithmEx)
+///
+/// # Safety
+/// - All handles & descriptors must still be allocated.
+/// - The pointers must point to valid memory of the sizes in the descriptors. `dw` is overwritten.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn find_convolution_backward_filter_algorithm_ex(
+    handle: sys::cudnnHandle_t,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    dy_desc: sys::cudnnTensorDescriptor_t,
+    dy: *const std::ffi::c_void,
+    conv_desc: sys::cudnnConvolutionDescriptor_t,
+    dw_desc: sys::cudnnFilterDescriptor_t,
+    dw: *mut std::ffi::c_void,
+    requested_algo_count: std::ffi::c_int,
+    returned_algo_count: *mut std::ffi::c_int,
+    perf_results: *mut sys::cudnnConvolutionBwdFilterAlgoPerf_t,
+    work_space: *mut std::ffi::c_void,
+    work_space_size_in_bytes: usize,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnFindConvolutionBackwardFilterAlgorithmEx(
+            handle,
+            x_desc,
+            x,
+            dy_desc,
+            dy,
+            conv_desc,
+            dw_desc,
+            dw,
+            requested_algo_count,
+            returned_algo_count,
+            perf_results,
+            work_space,
+            work_space_size_in_bytes,
+        )
+        .result()
+}
+
 /// Returns size in **bytes**.
 /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetConvolutionBackwardFilterWorkspaceSize)
 /// # Safety
@@ -790,3 +1192,825 @@ pub unsafe fn reduce_tensor(
         )
         .result()
 }
+
+/// Creates an activation descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCreateActivationDescriptor)
+pub fn create_activation_descriptor() -> Result<sys::cudnnActivationDescriptor_t, CudnnError> {
+    let mut desc = MaybeUninit::uninit();
+    unsafe {
+        lib()
+            .cudnnCreateActivationDescriptor(desc.as_mut_ptr())
+            .result()?;
+        Ok(desc.assume_init())
+    }
+}
+
+/// Sets the parameters of an activation descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetActivationDescriptor)
+///
+/// # Safety
+/// `activation_desc` must have been created with [create_activation_descriptor], and NOT freed by [destroy_activation_descriptor]
+pub unsafe fn set_activation_descriptor(
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    mode: sys::cudnnActivationMode_t,
+    relu_nan_opt: sys::cudnnNanPropagation_t,
+    coef: f64,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSetActivationDescriptor(activation_desc, mode, relu_nan_opt, coef)
+        .result()
+}
+
+/// Returns the mode, NaN propagation and coefficient of an activation descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetActivationDescriptor)
+///
+/// # Safety
+/// `activation_desc` must have been created with [create_activation_descriptor], and NOT freed by [destroy_activation_descriptor]
+pub unsafe fn get_activation_descriptor(
+    activation_desc: sys::cudnnActivationDescriptor_t,
+) -> Result<(sys::cudnnActivationMode_t, sys::cudnnNanPropagation_t, f64), CudnnError> {
+    let mut mode = MaybeUninit::uninit();
+    let mut relu_nan_opt = MaybeUninit::uninit();
+    let mut coef = 0.0;
+    lib()
+        .cudnnGetActivationDescriptor(
+            activation_desc,
+            mode.as_mut_ptr(),
+            relu_nan_opt.as_mut_ptr(),
+            &mut coef,
+        )
+        .result()?;
+    Ok((mode.assume_init(), relu_nan_opt.assume_init(), coef))
+}
+
+/// Destroys an activation descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroyActivationDescriptor)
+///
+/// # Safety
+/// `activation_desc` must NOT have been freed already.
+pub unsafe fn destroy_activation_descriptor(
+    activation_desc: sys::cudnnActivationDescriptor_t,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnDestroyActivationDescriptor(activation_desc)
+        .result()
+}
+
+/// Whether the `cudnnNormalization*` API is available, which needs cuDNN >= 8.0.
+pub fn is_normalization_supported() -> bool {
+    get_version() >= 8000 && unsafe { lib() }.cudnnNormalizationForwardTraining.is_ok()
+}
+
+/// Creates an LRN descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCreateLRNDescriptor)
+pub fn create_lrn_descriptor() -> Result<sys::cudnnLRNDescriptor_t, CudnnError> {
+    let mut desc = MaybeUninit::uninit();
+    unsafe {
+        lib().cudnnCreateLRNDescriptor(desc.as_mut_ptr()).result()?;
+        Ok(desc.assume_init())
+    }
+}
+
+/// Sets the parameters of an LRN descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetLRNDescriptor)
+///
+/// # Safety
+/// `norm_desc` must have been created with [create_lrn_descriptor], and NOT freed by [destroy_lrn_descriptor]
+pub unsafe fn set_lrn_descriptor(
+    norm_desc: sys::cudnnLRNDescriptor_t,
+    lrn_n: std::ffi::c_uint,
+    lrn_alpha: f64,
+    lrn_beta: f64,
+    lrn_k: f64,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSetLRNDescriptor(norm_desc, lrn_n, lrn_alpha, lrn_beta, lrn_k)
+        .result()
+}
+
+/// Destroys an LRN descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroyLRNDescriptor)
+///
+/// # Safety
+/// `norm_desc` must NOT have been freed already.
+pub unsafe fn destroy_lrn_descriptor(
+    norm_desc: sys::cudnnLRNDescriptor_t,
+) -> Result<(), CudnnError> {
+    lib().cudnnDestroyLRNDescriptor(norm_desc).result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnLRNCrossChannelForward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn lrn_cross_channel_forward(
+    handle: sys::cudnnHandle_t,
+    norm_desc: sys::cudnnLRNDescriptor_t,
+    lrn_mode: sys::cudnnLRNMode_t,
+    alpha: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    beta: *const std::ffi::c_void,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnLRNCrossChannelForward(
+            handle, norm_desc, lrn_mode, alpha, x_desc, x, beta, y_desc, y,
+        )
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnLRNCrossChannelBackward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn lrn_cross_channel_backward(
+    handle: sys::cudnnHandle_t,
+    norm_desc: sys::cudnnLRNDescriptor_t,
+    lrn_mode: sys::cudnnLRNMode_t,
+    alpha: *const std::ffi::c_void,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *const std::ffi::c_void,
+    dy_desc: sys::cudnnTensorDescriptor_t,
+    dy: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    beta: *const std::ffi::c_void,
+    dx_desc: sys::cudnnTensorDescriptor_t,
+    dx: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnLRNCrossChannelBackward(
+            handle, norm_desc, lrn_mode, alpha, y_desc, y, dy_desc, dy, x_desc, x, beta, dx_desc,
+            dx,
+        )
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetNormalizationForwardTrainingWorkspaceSize)
+///
+/// # Safety
+/// Handle and descriptors must be valid.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn get_normalization_forward_training_workspace_size(
+    handle: sys::cudnnHandle_t,
+    mode: sys::cudnnNormMode_t,
+    norm_ops: sys::cudnnNormOps_t,
+    algo: sys::cudnnNormAlgo_t,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    z_desc: sys::cudnnTensorDescriptor_t,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    norm_scale_bias_desc: sys::cudnnTensorDescriptor_t,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    norm_mean_var_desc: sys::cudnnTensorDescriptor_t,
+    group_cnt: std::ffi::c_int,
+) -> Result<usize, CudnnError> {
+    let mut size_in_bytes = 0;
+    lib()
+        .cudnnGetNormalizationForwardTrainingWorkspaceSize(
+            handle,
+            mode,
+            norm_ops,
+            algo,
+            x_desc,
+            z_desc,
+            y_desc,
+            norm_scale_bias_desc,
+            activation_desc,
+            norm_mean_var_desc,
+            &mut size_in_bytes,
+            group_cnt,
+        )
+        .result()?;
+    Ok(size_in_bytes)
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetNormalizationBackwardWorkspaceSize)
+///
+/// # Safety
+/// Handle and descriptors must be valid.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn get_normalization_backward_workspace_size(
+    handle: sys::cudnnHandle_t,
+    mode: sys::cudnnNormMode_t,
+    norm_ops: sys::cudnnNormOps_t,
+    algo: sys::cudnnNormAlgo_t,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    dy_desc: sys::cudnnTensorDescriptor_t,
+    dz_desc: sys::cudnnTensorDescriptor_t,
+    dx_desc: sys::cudnnTensorDescriptor_t,
+    d_norm_scale_bias_desc: sys::cudnnTensorDescriptor_t,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    norm_mean_var_desc: sys::cudnnTensorDescriptor_t,
+    group_cnt: std::ffi::c_int,
+) -> Result<usize, CudnnError> {
+    let mut size_in_bytes = 0;
+    lib()
+        .cudnnGetNormalizationBackwardWorkspaceSize(
+            handle,
+            mode,
+            norm_ops,
+            algo,
+            x_desc,
+            y_desc,
+            dy_desc,
+            dz_desc,
+            dx_desc,
+            d_norm_scale_bias_desc,
+            activation_desc,
+            norm_mean_var_desc,
+            &mut size_in_bytes,
+            group_cnt,
+        )
+        .result()?;
+    Ok(size_in_bytes)
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetNormalizationTrainingReserveSpaceSize)
+///
+/// # Safety
+/// Handle and descriptors must be valid.
+pub unsafe fn get_normalization_training_reserve_space_size(
+    handle: sys::cudnnHandle_t,
+    mode: sys::cudnnNormMode_t,
+    norm_ops: sys::cudnnNormOps_t,
+    algo: sys::cudnnNormAlgo_t,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    group_cnt: std::ffi::c_int,
+) -> Result<usize, CudnnError> {
+    let mut size_in_bytes = 0;
+    lib()
+        .cudnnGetNormalizationTrainingReserveSpaceSize(
+            handle,
+            mode,
+            norm_ops,
+            algo,
+            activation_desc,
+            x_desc,
+            &mut size_in_bytes,
+            group_cnt,
+        )
+        .result()?;
+    Ok(size_in_bytes)
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnNormalizationForwardInference)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn normalization_forward_inference(
+    handle: sys::cudnnHandle_t,
+    mode: sys::cudnnNormMode_t,
+    norm_ops: sys::cudnnNormOps_t,
+    algo: sys::cudnnNormAlgo_t,
+    alpha: *const std::ffi::c_void,
+    beta: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    norm_scale_bias_desc: sys::cudnnTensorDescriptor_t,
+    norm_scale: *const std::ffi::c_void,
+    norm_bias: *const std::ffi::c_void,
+    norm_mean_var_desc: sys::cudnnTensorDescriptor_t,
+    estimated_mean: *const std::ffi::c_void,
+    estimated_variance: *const std::ffi::c_void,
+    z_desc: sys::cudnnTensorDescriptor_t,
+    z: *const std::ffi::c_void,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *mut std::ffi::c_void,
+    epsilon: f64,
+    group_cnt: std::ffi::c_int,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnNormalizationForwardInference(
+            handle,
+            mode,
+            norm_ops,
+            algo,
+            alpha,
+            beta,
+            x_desc,
+            x,
+            norm_scale_bias_desc,
+            norm_scale,
+            norm_bias,
+            norm_mean_var_desc,
+            estimated_mean,
+            estimated_variance,
+            z_desc,
+            z,
+            activation_desc,
+            y_desc,
+            y,
+            epsilon,
+            group_cnt,
+        )
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnNormalizationForwardTraining)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn normalization_forward_training(
+    handle: sys::cudnnHandle_t,
+    mode: sys::cudnnNormMode_t,
+    norm_ops: sys::cudnnNormOps_t,
+    algo: sys::cudnnNormAlgo_t,
+    alpha: *const std::ffi::c_void,
+    beta: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    norm_scale_bias_desc: sys::cudnnTensorDescriptor_t,
+    norm_scale: *const std::ffi::c_void,
+    norm_bias: *const std::ffi::c_void,
+    exponential_average_factor: f64,
+    norm_mean_var_desc: sys::cudnnTensorDescriptor_t,
+    result_running_mean: *mut std::ffi::c_void,
+    result_running_variance: *mut std::ffi::c_void,
+    epsilon: f64,
+    result_save_mean: *mut std::ffi::c_void,
+    result_save_inv_variance: *mut std::ffi::c_void,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    z_desc: sys::cudnnTensorDescriptor_t,
+    z: *const std::ffi::c_void,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *mut std::ffi::c_void,
+    workspace: *mut std::ffi::c_void,
+    workspace_size_in_bytes: usize,
+    reserve_space: *mut std::ffi::c_void,
+    reserve_space_size_in_bytes: usize,
+    group_cnt: std::ffi::c_int,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnNormalizationForwardTraining(
+            handle,
+            mode,
+            norm_ops,
+            algo,
+            alpha,
+            beta,
+            x_desc,
+            x,
+            norm_scale_bias_desc,
+            norm_scale,
+            norm_bias,
+            exponential_average_factor,
+            norm_mean_var_desc,
+            result_running_mean,
+            result_running_variance,
+            epsilon,
+            result_save_mean,
+            result_save_inv_variance,
+            activation_desc,
+            z_desc,
+            z,
+            y_desc,
+            y,
+            workspace,
+            workspace_size_in_bytes,
+            reserve_space,
+            reserve_space_size_in_bytes,
+            group_cnt,
+        )
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnNormalizationBackward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn normalization_backward(
+    handle: sys::cudnnHandle_t,
+    mode: sys::cudnnNormMode_t,
+    norm_ops: sys::cudnnNormOps_t,
+    algo: sys::cudnnNormAlgo_t,
+    alpha_data_diff: *const std::ffi::c_void,
+    beta_data_diff: *const std::ffi::c_void,
+    alpha_param_diff: *const std::ffi::c_void,
+    beta_param_diff: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *const std::ffi::c_void,
+    dy_desc: sys::cudnnTensorDescriptor_t,
+    dy: *const std::ffi::c_void,
+    dz_desc: sys::cudnnTensorDescriptor_t,
+    dz: *mut std::ffi::c_void,
+    dx_desc: sys::cudnnTensorDescriptor_t,
+    dx: *mut std::ffi::c_void,
+    d_norm_scale_bias_desc: sys::cudnnTensorDescriptor_t,
+    norm_scale: *const std::ffi::c_void,
+    norm_bias: *const std::ffi::c_void,
+    d_norm_scale: *mut std::ffi::c_void,
+    d_norm_bias: *mut std::ffi::c_void,
+    epsilon: f64,
+    norm_mean_var_desc: sys::cudnnTensorDescriptor_t,
+    saved_mean: *const std::ffi::c_void,
+    saved_inv_variance: *const std::ffi::c_void,
+    activation_desc: sys::cudnnActivationDescriptor_t,
+    workspace: *mut std::ffi::c_void,
+    workspace_size_in_bytes: usize,
+    reserve_space: *mut std::ffi::c_void,
+    reserve_space_size_in_bytes: usize,
+    group_cnt: std::ffi::c_int,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnNormalizationBackward(
+            handle,
+            mode,
+            norm_ops,
+            algo,
+            alpha_data_diff,
+            beta_data_diff,
+            alpha_param_diff,
+            beta_param_diff,
+            x_desc,
+            x,
+            y_desc,
+            y,
+            dy_desc,
+            dy,
+            dz_desc,
+            dz,
+            dx_desc,
+            dx,
+            d_norm_scale_bias_desc,
+            norm_scale,
+            norm_bias,
+            d_norm_scale,
+            d_norm_bias,
+            epsilon,
+            norm_mean_var_desc,
+            saved_mean,
+            saved_inv_variance,
+            activation_desc,
+            workspace,
+            workspace_size_in_bytes,
+            reserve_space,
+            reserve_space_size_in_bytes,
+            group_cnt,
+        )
+        .result()
+}
+
+/// Whether the `cudnnCTCLoss_v8` API is available, which takes the labels and lengths in
+/// device memory and needs cuDNN >= 8.0. Otherwise [ctc_loss] has to be used, which takes
+/// them in host memory.
+pub fn is_ctc_loss_v8_supported() -> bool {
+    get_version() >= 8000 && unsafe { lib() }.cudnnCTCLoss_v8.is_ok()
+}
+
+/// Creates a CTC loss descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCreateCTCLossDescriptor)
+pub fn create_ctc_loss_descriptor() -> Result<sys::cudnnCTCLossDescriptor_t, CudnnError> {
+    let mut desc = MaybeUninit::uninit();
+    unsafe {
+        lib()
+            .cudnnCreateCTCLossDescriptor(desc.as_mut_ptr())
+            .result()?;
+        Ok(desc.assume_init())
+    }
+}
+
+/// Sets the parameters of a CTC loss descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetCTCLossDescriptor_v8)
+///
+/// # Safety
+/// `ctc_loss_desc` must have been created with [create_ctc_loss_descriptor], and NOT freed by [destroy_ctc_loss_descriptor]
+pub unsafe fn set_ctc_loss_descriptor_v8(
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+    comp_type: sys::cudnnDataType_t,
+    norm_mode: sys::cudnnLossNormalizationMode_t,
+    grad_mode: sys::cudnnNanPropagation_t,
+    max_label_length: std::ffi::c_int,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSetCTCLossDescriptor_v8(
+            ctc_loss_desc,
+            comp_type,
+            norm_mode,
+            grad_mode,
+            max_label_length,
+        )
+        .result()
+}
+
+/// Sets the parameters of a CTC loss descriptor for [ctc_loss], which has no maximum label
+/// length. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetCTCLossDescriptorEx)
+///
+/// # Safety
+/// `ctc_loss_desc` must have been created with [create_ctc_loss_descriptor], and NOT freed by [destroy_ctc_loss_descriptor]
+pub unsafe fn set_ctc_loss_descriptor_ex(
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+    comp_type: sys::cudnnDataType_t,
+    norm_mode: sys::cudnnLossNormalizationMode_t,
+    grad_mode: sys::cudnnNanPropagation_t,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSetCTCLossDescriptorEx(ctc_loss_desc, comp_type, norm_mode, grad_mode)
+        .result()
+}
+
+/// Destroys a CTC loss descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroyCTCLossDescriptor)
+///
+/// # Safety
+/// `ctc_loss_desc` must NOT have been freed already.
+pub unsafe fn destroy_ctc_loss_descriptor(
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+) -> Result<(), CudnnError> {
+    lib().cudnnDestroyCTCLossDescriptor(ctc_loss_desc).result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetCTCLossWorkspaceSize_v8)
+///
+/// # Safety
+/// Handle and descriptors must be valid.
+pub unsafe fn get_ctc_loss_workspace_size_v8(
+    handle: sys::cudnnHandle_t,
+    algo: sys::cudnnCTCLossAlgo_t,
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+    probs_desc: sys::cudnnTensorDescriptor_t,
+    gradients_desc: sys::cudnnTensorDescriptor_t,
+) -> Result<usize, CudnnError> {
+    let mut size_in_bytes = [0];
+    lib()
+        .cudnnGetCTCLossWorkspaceSize_v8(
+            handle,
+            algo,
+            ctc_loss_desc,
+            probs_desc,
+            gradients_desc,
+            size_in_bytes.as_mut_ptr(),
+        )
+        .result()?;
+    Ok(size_in_bytes[0])
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCTCLoss_v8)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - `labels`, `label_lengths` and `input_lengths` must be in device memory.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn ctc_loss_v8(
+    handle: sys::cudnnHandle_t,
+    algo: sys::cudnnCTCLossAlgo_t,
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+    probs_desc: sys::cudnnTensorDescriptor_t,
+    probs: *const std::ffi::c_void,
+    labels: *const std::ffi::c_int,
+    label_lengths: *const std::ffi::c_int,
+    input_lengths: *const std::ffi::c_int,
+    costs: *mut std::ffi::c_void,
+    gradients_desc: sys::cudnnTensorDescriptor_t,
+    gradients: *mut std::ffi::c_void,
+    workspace_size_in_bytes: usize,
+    workspace: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnCTCLoss_v8(
+            handle,
+            algo,
+            ctc_loss_desc,
+            probs_desc,
+            probs,
+            labels,
+            label_lengths,
+            input_lengths,
+            costs,
+            gradients_desc,
+            gradients,
+            workspace_size_in_bytes,
+            workspace,
+        )
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetCTCLossWorkspaceSize)
+///
+/// # Safety
+/// - Handle and descriptors must be valid.
+/// - `labels`, `label_lengths` and `input_lengths` must be in host memory.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn get_ctc_loss_workspace_size(
+    handle: sys::cudnnHandle_t,
+    probs_desc: sys::cudnnTensorDescriptor_t,
+    gradients_desc: sys::cudnnTensorDescriptor_t,
+    labels: *const std::ffi::c_int,
+    label_lengths: *const std::ffi::c_int,
+    input_lengths: *const std::ffi::c_int,
+    algo: sys::cudnnCTCLossAlgo_t,
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+) -> Result<usize, CudnnError> {
+    let mut size_in_bytes = [0];
+    lib()
+        .cudnnGetCTCLossWorkspaceSize(
+            handle,
+            probs_desc,
+            gradients_desc,
+            labels,
+            label_lengths,
+            input_lengths,
+            algo,
+            ctc_loss_desc,
+            size_in_bytes.as_mut_ptr(),
+        )
+        .result()?;
+    Ok(size_in_bytes[0])
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCTCLoss)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - `labels`, `label_lengths` and `input_lengths` must be in host memory.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn ctc_loss(
+    handle: sys::cudnnHandle_t,
+    probs_desc: sys::cudnnTensorDescriptor_t,
+    probs: *const std::ffi::c_void,
+    labels: *const std::ffi::c_int,
+    label_lengths: *const std::ffi::c_int,
+    input_lengths: *const std::ffi::c_int,
+    costs: *mut std::ffi::c_void,
+    gradients_desc: sys::cudnnTensorDescriptor_t,
+    gradients: *mut std::ffi::c_void,
+    algo: sys::cudnnCTCLossAlgo_t,
+    ctc_loss_desc: sys::cudnnCTCLossDescriptor_t,
+    workspace: *mut std::ffi::c_void,
+    workspace_size_in_bytes: usize,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnCTCLoss(
+            handle,
+            probs_desc,
+            probs,
+            labels,
+            label_lengths,
+            input_lengths,
+            costs,
+            gradients_desc,
+            gradients,
+            algo,
+            ctc_loss_desc,
+            workspace,
+            workspace_size_in_bytes,
+        )
+        .result()
+}
+
+/// Creates a spatial transformer descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnCreateSpatialTransformerDescriptor)
+pub fn create_spatial_transformer_descriptor(
+) -> Result<sys::cudnnSpatialTransformerDescriptor_t, CudnnError> {
+    let mut desc = MaybeUninit::uninit();
+    unsafe {
+        lib()
+            .cudnnCreateSpatialTransformerDescriptor(desc.as_mut_ptr())
+            .result()?;
+        Ok(desc.assume_init())
+    }
+}
+
+/// Sets the sampler and output dimensions of a spatial transformer descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetSpatialTransformerNdDescriptor)
+///
+/// # Safety
+/// - `st_desc` must have been created with [create_spatial_transformer_descriptor], and NOT
+///   freed by [destroy_spatial_transformer_descriptor]
+/// - `dim_a` must point to `nb_dims` dimensions.
+pub unsafe fn set_spatial_transformer_nd_descriptor(
+    st_desc: sys::cudnnSpatialTransformerDescriptor_t,
+    sampler_type: sys::cudnnSamplerType_t,
+    data_type: sys::cudnnDataType_t,
+    nb_dims: std::ffi::c_int,
+    dim_a: *const std::ffi::c_int,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSetSpatialTransformerNdDescriptor(st_desc, sampler_type, data_type, nb_dims, dim_a)
+        .result()
+}
+
+/// Destroys a spatial transformer descriptor. See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnDestroySpatialTransformerDescriptor)
+///
+/// # Safety
+/// `st_desc` must NOT have been freed already.
+pub unsafe fn destroy_spatial_transformer_descriptor(
+    st_desc: sys::cudnnSpatialTransformerDescriptor_t,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnDestroySpatialTransformerDescriptor(st_desc)
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSpatialTfGridGeneratorForward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - `theta` must hold `n * 2 * 3` and `grid` `n * h * w * 2` elements of the data type of
+///   `st_desc`, for its output dimensions.
+pub unsafe fn spatial_tf_grid_generator_forward(
+    handle: sys::cudnnHandle_t,
+    st_desc: sys::cudnnSpatialTransformerDescriptor_t,
+    theta: *const std::ffi::c_void,
+    grid: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSpatialTfGridGeneratorForward(handle, st_desc, theta, grid)
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSpatialTfGridGeneratorBackward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - `dgrid` must hold `n * h * w * 2` and `dtheta` `n * 2 * 3` elements of the data type of
+///   `st_desc`, for its output dimensions.
+pub unsafe fn spatial_tf_grid_generator_backward(
+    handle: sys::cudnnHandle_t,
+    st_desc: sys::cudnnSpatialTransformerDescriptor_t,
+    dgrid: *const std::ffi::c_void,
+    dtheta: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSpatialTfGridGeneratorBackward(handle, st_desc, dgrid, dtheta)
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSpatialTfSamplerForward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn spatial_tf_sampler_forward(
+    handle: sys::cudnnHandle_t,
+    st_desc: sys::cudnnSpatialTransformerDescriptor_t,
+    alpha: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    grid: *const std::ffi::c_void,
+    beta: *const std::ffi::c_void,
+    y_desc: sys::cudnnTensorDescriptor_t,
+    y: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSpatialTfSamplerForward(handle, st_desc, alpha, x_desc, x, grid, beta, y_desc, y)
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSpatialTfSamplerBackward)
+///
+/// # Safety
+/// - All data must be properly allocated and not freed.
+/// - The descriptors must be the same data type as the pointers
+/// - Misuse of this function could result in out of bounds memory accesses.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn spatial_tf_sampler_backward(
+    handle: sys::cudnnHandle_t,
+    st_desc: sys::cudnnSpatialTransformerDescriptor_t,
+    alpha: *const std::ffi::c_void,
+    x_desc: sys::cudnnTensorDescriptor_t,
+    x: *const std::ffi::c_void,
+    beta: *const std::ffi::c_void,
+    dx_desc: sys::cudnnTensorDescriptor_t,
+    dx: *mut std::ffi::c_void,
+    alpha_dgrid: *const std::ffi::c_void,
+    dy_desc: sys::cudnnTensorDescriptor_t,
+    dy: *const std::ffi::c_void,
+    grid: *const std::ffi::c_void,
+    beta_dgrid: *const std::ffi::c_void,
+    dgrid: *mut std::ffi::c_void,
+) -> Result<(), CudnnError> {
+    lib()
+        .cudnnSpatialTfSamplerBackward(
+            handle,
+            st_desc,
+            alpha,
+            x_desc,
+            x,
+            beta,
+            dx_desc,
+            dx,
+            alpha_dgrid,
+            dy_desc,
+            dy,
+            grid,
+            beta_dgrid,
+            dgrid,
+        )
+        .result()
+}
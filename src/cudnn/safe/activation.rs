@@ -0,0 +1,54 @@
+use super::core::*;
+use crate::cudnn::{result, result::CudnnError, sys};
+
+use std::{marker::PhantomData, sync::Arc};
+
+/// An activation descriptor, e.g. for [super::ConvBiasActivationForward]. Create with
+/// [`Cudnn::create_activation()`].
+#[derive(Debug)]
+pub struct ActivationDescriptor<T> {
+    pub(crate) desc: sys::cudnnActivationDescriptor_t,
+    #[allow(unused)]
+    pub(crate) handle: Arc<Cudnn>,
+    pub(crate) marker: PhantomData<T>,
+}
+
+impl Cudnn {
+    /// Creates an activation descriptor applying `mode`. `coef` is the clipping threshold of
+    /// [sys::cudnnActivationMode_t::CUDNN_ACTIVATION_CLIPPED_RELU] and the alpha of
+    /// [sys::cudnnActivationMode_t::CUDNN_ACTIVATION_ELU], and is ignored otherwise.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetActivationDescriptor)
+    pub fn create_activation<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        mode: sys::cudnnActivationMode_t,
+        nan_opt: sys::cudnnNanPropagation_t,
+        coef: f64,
+    ) -> Result<ActivationDescriptor<T>, CudnnError> {
+        let desc = result::create_activation_descriptor()?;
+        let desc = ActivationDescriptor {
+            desc,
+            handle: self.clone(),
+            marker: PhantomData,
+        };
+        unsafe { result::set_activation_descriptor(desc.desc, mode, nan_opt, coef) }?;
+        Ok(desc)
+    }
+}
+
+impl<T> ActivationDescriptor<T> {
+    /// The activation mode set in [Cudnn::create_activation()].
+    pub fn mode(&self) -> Result<sys::cudnnActivationMode_t, CudnnError> {
+        let (mode, _, _) = unsafe { result::get_activation_descriptor(self.desc) }?;
+        Ok(mode)
+    }
+}
+
+impl<T> Drop for ActivationDescriptor<T> {
+    fn drop(&mut self) {
+        let desc = std::mem::replace(&mut self.desc, std::ptr::null_mut());
+        if !desc.is_null() {
+            unsafe { result::destroy_activation_descriptor(desc) }.unwrap()
+        }
+    }
+}
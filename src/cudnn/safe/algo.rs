@@ -0,0 +1,820 @@
+use super::core::Cudnn;
+use crate::{
+    cudnn::{result, result::CudnnError, sys},
+    driver::{safe::error_handler::OrHandle, CudaSlice, DevicePtr, DeviceSlice},
+};
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+#[cfg(feature = "std")]
+use crate::nvrtc::cache;
+
+use std::{collections::BTreeMap, string::String, vec, vec::Vec};
+
+/// The timing of one convolution algorithm, as measured by the `find_algorithm` methods
+/// of [super::ConvForward], [super::ConvBackwardData] and [super::ConvBackwardFilter].
+/// The `pick_algorithm_with` methods return one too, from the heuristics, whose
+/// [PerfResult::time_ms] isn't a measurement.
+///
+/// To reproduce the timing, set [PerfResult::math_type] on the conv descriptor with
+/// [super::ConvDescriptor::set_math_type()] before launching with [PerfResult::algo].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerfResult<A> {
+    pub algo: A,
+    /// Execution time in milliseconds.
+    pub time_ms: f32,
+    /// Workspace the algorithm needs, in bytes.
+    pub workspace_size: usize,
+    pub math_type: sys::cudnnMathType_t,
+    pub deterministic: bool,
+}
+
+/// Successfully timed algorithms, fastest first.
+pub type PerfResults<A> = Vec<PerfResult<A>>;
+
+/// Whether the `pick_algorithm_with`, `find_algorithm_with` and `find_algorithm_cached_with`
+/// methods of [super::ConvForward], [super::ConvBackwardData] and [super::ConvBackwardFilter]
+/// may pick algorithms whose results vary from run to run, e.g. because they accumulate
+/// with atomics.
+///
+/// Winograd-nonfused algorithms never count as deterministic, as some cudnn versions
+/// report them so although their results aren't reproducible.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DeterminismPolicy {
+    /// The first algorithm of the heuristics, deterministic or not.
+    #[default]
+    Any,
+    /// The first deterministic algorithm, or the first algorithm if none is.
+    Prefer,
+    /// The first deterministic algorithm, or
+    /// [PickAlgorithmError::NoDeterministicAlgorithm] if none is.
+    Require,
+}
+
+impl From<crate::driver::Determinism> for DeterminismPolicy {
+    /// [crate::driver::Determinism::Bitwise] requires deterministic algorithms.
+    fn from(value: crate::driver::Determinism) -> Self {
+        match value {
+            crate::driver::Determinism::Fast => Self::Any,
+            crate::driver::Determinism::Bitwise => Self::Require,
+        }
+    }
+}
+
+impl DeterminismPolicy {
+    /// Picks from `perfs`, the successful results of the heuristics or of a search, best
+    /// first.
+    pub(crate) fn select<A: ConvAlgo>(
+        self,
+        perfs: &[PerfResult<A>],
+    ) -> Result<PerfResult<A>, PickAlgorithmError<A>> {
+        if perfs.is_empty() {
+            return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED).into());
+        }
+        self.filter(perfs.to_vec()).first().copied().ok_or_else(|| {
+            PickAlgorithmError::NoDeterministicAlgorithm {
+                excluded: perfs.to_vec(),
+            }
+        })
+    }
+
+    /// Drops the algorithms of `perfs` the policy excludes, and with
+    /// [DeterminismPolicy::Prefer] moves the deterministic ones first, keeping their order
+    /// otherwise.
+    pub(crate) fn filter<A: ConvAlgo>(self, mut perfs: PerfResults<A>) -> PerfResults<A> {
+        let deterministic =
+            |perf: &PerfResult<A>| perf.deterministic && perf.algo != A::WINOGRAD_NONFUSED;
+        match self {
+            Self::Any => (),
+            Self::Prefer => perfs.sort_by_key(|perf| !deterministic(perf)),
+            Self::Require => perfs.retain(deterministic),
+        }
+        perfs
+    }
+}
+
+/// Error from the `pick_algorithm_with` methods of [super::ConvForward],
+/// [super::ConvBackwardData] and [super::ConvBackwardFilter].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PickAlgorithmError<A> {
+    /// Error returned by cudnn, or [sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED] if no
+    /// algorithm supports the descriptors.
+    Cudnn(CudnnError),
+    /// [DeterminismPolicy::Require] excluded every algorithm the heuristics returned.
+    NoDeterministicAlgorithm {
+        /// The excluded algorithms, best first.
+        excluded: PerfResults<A>,
+    },
+}
+
+impl<A> From<CudnnError> for PickAlgorithmError<A> {
+    fn from(value: CudnnError) -> Self {
+        Self::Cudnn(value)
+    }
+}
+
+impl<A> PickAlgorithmError<A> {
+    /// The error of the methods without a policy, i.e. with [DeterminismPolicy::Any], which
+    /// never excludes every algorithm.
+    pub(crate) fn into_cudnn(self) -> CudnnError {
+        match self {
+            Self::Cudnn(e) => e,
+            Self::NoDeterministicAlgorithm { .. } => {
+                CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A: std::fmt::Debug> std::fmt::Display for PickAlgorithmError<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cudnn(e) => write!(f, "{e}"),
+            Self::NoDeterministicAlgorithm { excluded } => {
+                let algos: Vec<_> = excluded.iter().map(|perf| &perf.algo).collect();
+                write!(
+                    f,
+                    "no deterministic convolution algorithm supports the descriptors, excluded {algos:?}"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A: std::fmt::Debug> std::error::Error for PickAlgorithmError<A> {}
+
+/// Round trips the cudnn enums stored in an [AlgoCache] through `u32`.
+pub(crate) trait RawEnum: Copy + Sized + 'static {
+    const ALL: &'static [Self];
+    fn to_raw(self) -> u32;
+    fn from_raw(raw: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|v| v.to_raw() == raw)
+    }
+}
+
+macro_rules! raw_enum {
+    ($Enum:ty, [$($Variant:ident),* $(,)?]) => {
+        impl RawEnum for $Enum {
+            const ALL: &'static [Self] = &[$(<$Enum>::$Variant),*];
+            fn to_raw(self) -> u32 {
+                self as u32
+            }
+        }
+    };
+}
+
+raw_enum!(
+    sys::cudnnConvolutionFwdAlgo_t,
+    [
+        CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_GEMM,
+        CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_PRECOMP_GEMM,
+        CUDNN_CONVOLUTION_FWD_ALGO_GEMM,
+        CUDNN_CONVOLUTION_FWD_ALGO_DIRECT,
+        CUDNN_CONVOLUTION_FWD_ALGO_FFT,
+        CUDNN_CONVOLUTION_FWD_ALGO_FFT_TILING,
+        CUDNN_CONVOLUTION_FWD_ALGO_WINOGRAD,
+        CUDNN_CONVOLUTION_FWD_ALGO_WINOGRAD_NONFUSED,
+    ]
+);
+raw_enum!(
+    sys::cudnnConvolutionBwdDataAlgo_t,
+    [
+        CUDNN_CONVOLUTION_BWD_DATA_ALGO_0,
+        CUDNN_CONVOLUTION_BWD_DATA_ALGO_1,
+        CUDNN_CONVOLUTION_BWD_DATA_ALGO_FFT,
+        CUDNN_CONVOLUTION_BWD_DATA_ALGO_FFT_TILING,
+        CUDNN_CONVOLUTION_BWD_DATA_ALGO_WINOGRAD,
+        CUDNN_CONVOLUTION_BWD_DATA_ALGO_WINOGRAD_NONFUSED,
+    ]
+);
+raw_enum!(
+    sys::cudnnConvolutionBwdFilterAlgo_t,
+    [
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_0,
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_1,
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_FFT,
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_3,
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_WINOGRAD,
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_WINOGRAD_NONFUSED,
+        CUDNN_CONVOLUTION_BWD_FILTER_ALGO_FFT_TILING,
+    ]
+);
+raw_enum!(
+    sys::cudnnMathType_t,
+    [
+        CUDNN_DEFAULT_MATH,
+        CUDNN_TENSOR_OP_MATH,
+        CUDNN_TENSOR_OP_MATH_ALLOW_CONVERSION,
+        CUDNN_FMA_MATH,
+    ]
+);
+
+/// The convolution algorithm enums, for [DeterminismPolicy::select()].
+pub(crate) trait ConvAlgo: RawEnum + PartialEq {
+    /// Excluded from the deterministic algorithms.
+    const WINOGRAD_NONFUSED: Self;
+}
+
+impl ConvAlgo for sys::cudnnConvolutionFwdAlgo_t {
+    const WINOGRAD_NONFUSED: Self = Self::CUDNN_CONVOLUTION_FWD_ALGO_WINOGRAD_NONFUSED;
+}
+impl ConvAlgo for sys::cudnnConvolutionBwdDataAlgo_t {
+    const WINOGRAD_NONFUSED: Self = Self::CUDNN_CONVOLUTION_BWD_DATA_ALGO_WINOGRAD_NONFUSED;
+}
+impl ConvAlgo for sys::cudnnConvolutionBwdFilterAlgo_t {
+    const WINOGRAD_NONFUSED: Self = Self::CUDNN_CONVOLUTION_BWD_FILTER_ALGO_WINOGRAD_NONFUSED;
+}
+
+/// The cudnn `*AlgoPerf_t` structs, for [search_algorithms()].
+pub(crate) trait AlgoPerf: Copy + Default {
+    type Algo: ConvAlgo;
+    fn status(&self) -> sys::cudnnStatus_t;
+}
+
+macro_rules! perf_result_from {
+    ($Perf:ty, $Algo:ty) => {
+        impl AlgoPerf for $Perf {
+            type Algo = $Algo;
+            fn status(&self) -> sys::cudnnStatus_t {
+                self.status
+            }
+        }
+
+        impl From<&$Perf> for PerfResult<$Algo> {
+            fn from(perf: &$Perf) -> Self {
+                Self {
+                    algo: perf.algo,
+                    time_ms: perf.time,
+                    workspace_size: perf.memory,
+                    math_type: perf.mathType,
+                    deterministic: perf.determinism == sys::cudnnDeterminism_t::CUDNN_DETERMINISTIC,
+                }
+            }
+        }
+    };
+}
+
+perf_result_from!(
+    sys::cudnnConvolutionFwdAlgoPerf_t,
+    sys::cudnnConvolutionFwdAlgo_t
+);
+perf_result_from!(
+    sys::cudnnConvolutionBwdDataAlgoPerf_t,
+    sys::cudnnConvolutionBwdDataAlgo_t
+);
+perf_result_from!(
+    sys::cudnnConvolutionBwdFilterAlgoPerf_t,
+    sys::cudnnConvolutionBwdFilterAlgo_t
+);
+
+/// Which convolution operation an [AlgoCache] entry is for.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ConvDirection {
+    Forward,
+    BackwardData,
+    BackwardFilter,
+}
+
+/// Everything the result of an algorithm search depends on.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct AlgoKey {
+    direction: ConvDirection,
+    device: String,
+    cudnn_version: usize,
+    workspace_limit: usize,
+    policy: DeterminismPolicy,
+    /// data type, dims and strides of the image tensor(s)
+    tensors: [(u32, Vec<i32>, Vec<i32>); 2],
+    /// data type, format and dims of the filter
+    filter: (u32, u32, Vec<i32>),
+    /// pads, strides, dilations, mode, compute type, group count and math type
+    conv: (Vec<i32>, Vec<i32>, Vec<i32>, u32, u32, i32, u32),
+}
+
+impl AlgoKey {
+    pub(crate) fn new(
+        direction: ConvDirection,
+        cudnn: &Cudnn,
+        conv: sys::cudnnConvolutionDescriptor_t,
+        [x, y]: [sys::cudnnTensorDescriptor_t; 2],
+        w: sys::cudnnFilterDescriptor_t,
+        workspace_limit: usize,
+        policy: DeterminismPolicy,
+    ) -> Result<Self, CudnnError> {
+        let tensor = |desc| {
+            unsafe { result::get_tensornd_descriptor(desc) }
+                .map(|(dtype, dims, strides)| (dtype as u32, dims, strides))
+        };
+        let (w_dtype, w_format, w_dims) = unsafe { result::get_filternd_descriptor(w) }?;
+        let params = unsafe { result::get_convolutionnd_descriptor(conv) }?;
+        let groups = unsafe { result::get_convolution_group_count(conv) }?;
+        let math_type = unsafe { result::get_convolution_math_type(conv) }?;
+        Ok(Self {
+            direction,
+            device: cudnn
+                .device
+                .name()
+                .map_err(|_| CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_INTERNAL_ERROR))?,
+            cudnn_version: result::get_version(),
+            workspace_limit,
+            policy,
+            tensors: [tensor(x)?, tensor(y)?],
+            filter: (w_dtype as u32, w_format as u32, w_dims),
+            conv: (
+                params.pads,
+                params.strides,
+                params.dilations,
+                params.mode as u32,
+                params.compute_type as u32,
+                groups,
+                math_type.to_raw(),
+            ),
+        })
+    }
+}
+
+/// The serialized form of a [PerfResult].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CachedAlgo {
+    algo: u32,
+    time_ms: f32,
+    workspace_size: usize,
+    math_type: u32,
+    deterministic: bool,
+}
+
+/// Caches the fastest algorithm found by the `find_algorithm_cached` methods of [super::ConvForward],
+/// [super::ConvBackwardData] and [super::ConvBackwardFilter], keyed on the descriptors,
+/// the device name, the cudnn version, the workspace limit and the [DeterminismPolicy].
+///
+/// Use [AlgoCache::global()] for a process wide cache. The cache can be saved to a file with
+/// [AlgoCache::save()] and loaded back with [AlgoCache::load()] and [AlgoCache::extend()],
+/// or with the `serde` feature, so that servers don't repeat the search on startup.
+#[derive(Debug, Default)]
+pub struct AlgoCache {
+    entries: RwLock<BTreeMap<AlgoKey, CachedAlgo>>,
+}
+
+static GLOBAL: AlgoCache = AlgoCache::new();
+
+impl AlgoCache {
+    pub const fn new() -> Self {
+        Self {
+            entries: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// The process wide cache.
+    pub fn global() -> &'static Self {
+        &GLOBAL
+    }
+
+    /// The number of cached searches.
+    pub fn len(&self) -> usize {
+        let entries = self.entries.read();
+        #[cfg(not(feature = "no-std"))]
+        let entries = entries.unwrap();
+        entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        #[allow(unused_mut)]
+        let mut entries = self.entries.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut entries = entries.unwrap();
+        entries.clear();
+    }
+
+    /// Adds all entries of `other`, replacing any entries with the same key.
+    pub fn extend(&self, other: &AlgoCache) {
+        let other = other.snapshot();
+        #[allow(unused_mut)]
+        let mut entries = self.entries.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut entries = entries.unwrap();
+        entries.extend(other);
+    }
+
+    fn snapshot(&self) -> Vec<(AlgoKey, CachedAlgo)> {
+        let entries = self.entries.read();
+        #[cfg(not(feature = "no-std"))]
+        let entries = entries.unwrap();
+        entries.iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+
+    /// Returns the cached result for `key`, or runs `search` and caches its fastest result
+    /// that the policy of `key` allows.
+    pub(crate) fn get_or_find<A: ConvAlgo>(
+        &self,
+        key: AlgoKey,
+        search: impl FnOnce() -> Result<PerfResults<A>, CudnnError>,
+    ) -> Result<PerfResult<A>, PickAlgorithmError<A>> {
+        {
+            let entries = self.entries.read();
+            #[cfg(not(feature = "no-std"))]
+            let entries = entries.unwrap();
+            if let Some(cached) = entries.get(&key) {
+                if let (Some(algo), Some(math_type)) = (
+                    A::from_raw(cached.algo),
+                    sys::cudnnMathType_t::from_raw(cached.math_type),
+                ) {
+                    return Ok(PerfResult {
+                        algo,
+                        time_ms: cached.time_ms,
+                        workspace_size: cached.workspace_size,
+                        math_type,
+                        deterministic: cached.deterministic,
+                    });
+                }
+            }
+        }
+
+        let best = key.policy.select(&search()?)?;
+        #[allow(unused_mut)]
+        let mut entries = self.entries.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut entries = entries.unwrap();
+        entries.insert(
+            key,
+            CachedAlgo {
+                algo: best.algo.to_raw(),
+                time_ms: best.time_ms,
+                workspace_size: best.workspace_size,
+                math_type: best.math_type.to_raw(),
+                deterministic: best.deterministic,
+            },
+        );
+        Ok(best)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for AlgoCache {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.snapshot())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AlgoCache {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries: Vec<(AlgoKey, CachedAlgo)> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(Self {
+            entries: RwLock::new(entries.into_iter().collect()),
+        })
+    }
+}
+
+impl Cudnn {
+    /// Allocates device memory for an algorithm search. Returns `None` for 0 bytes.
+    ///
+    /// Running out of memory is `CUDNN_STATUS_ALLOC_FAILED`. Other errors are passed to the
+    /// [crate::driver::ErrorHandler] and `CUDNN_STATUS_INTERNAL_ERROR` is returned.
+    pub(crate) fn alloc_scratch(
+        &self,
+        num_bytes: usize,
+    ) -> Result<Option<CudaSlice<u8>>, CudnnError> {
+        if num_bytes == 0 {
+            return Ok(None);
+        }
+        let scratch = self.device.alloc_zeros::<u8>(num_bytes);
+        if let Err(e) = &scratch {
+            if e.code() == crate::driver::sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY {
+                return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_ALLOC_FAILED));
+            }
+        }
+        scratch.map(Some).or_report(
+            "Cudnn::alloc_scratch",
+            CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_INTERNAL_ERROR),
+        )
+    }
+}
+
+/// The body of the `find_algorithm` methods of [super::ConvForward],
+/// [super::ConvBackwardData] and [super::ConvBackwardFilter].
+///
+/// Allocates zeroed scratch buffers of `operand_bytes` for the three operands, and a
+/// workspace for the algorithm that needs the most, capped at `workspace_limit`. Then
+/// `find` runs the search with the operand pointers, the workspace, and room for a result
+/// per algorithm. Returns the algorithms that succeeded, fastest first.
+pub(crate) fn search_algorithms<P: AlgoPerf>(
+    cudnn: &Cudnn,
+    operand_bytes: [usize; 3],
+    workspace_limit: usize,
+    workspace_size: impl Fn(P::Algo) -> Result<usize, CudnnError>,
+    find: impl FnOnce(
+        [*mut std::ffi::c_void; 3],
+        (*mut std::ffi::c_void, usize),
+        &mut [P],
+        &mut std::ffi::c_int,
+    ) -> Result<(), CudnnError>,
+) -> Result<PerfResults<P::Algo>, CudnnError>
+where
+    for<'p> PerfResult<P::Algo>: From<&'p P>,
+{
+    let [a, b, c] = operand_bytes;
+    let operands = [
+        cudnn.alloc_scratch(a)?,
+        cudnn.alloc_scratch(b)?,
+        cudnn.alloc_scratch(c)?,
+    ];
+    let workspace = cudnn.alloc_scratch(max_workspace(workspace_limit, workspace_size))?;
+    let mut returned_count = 0;
+    let mut perf_results = vec![P::default(); P::Algo::ALL.len()];
+    find(
+        [
+            scratch_ptr(&operands[0]),
+            scratch_ptr(&operands[1]),
+            scratch_ptr(&operands[2]),
+        ],
+        (scratch_ptr(&workspace), scratch_len(&workspace)),
+        &mut perf_results,
+        &mut returned_count,
+    )?;
+    Ok(perf_results[..returned_count as usize]
+        .iter()
+        .filter(|perf| perf.status() == sys::cudnnStatus_t::CUDNN_STATUS_SUCCESS)
+        .map(PerfResult::from)
+        .collect())
+}
+
+/// The body of the `pick_algorithm_with` methods of [super::ConvForward],
+/// [super::ConvBackwardData] and [super::ConvBackwardFilter].
+///
+/// `get` runs the heuristics with room for a result per algorithm. Returns the algorithms
+/// that support the descriptors, best first.
+pub(crate) fn heuristic_algorithms<P: AlgoPerf>(
+    get: impl FnOnce(&mut [P], &mut std::ffi::c_int) -> Result<(), CudnnError>,
+) -> Result<PerfResults<P::Algo>, CudnnError>
+where
+    for<'p> PerfResult<P::Algo>: From<&'p P>,
+{
+    let mut returned_count = 0;
+    let mut perf_results = vec![P::default(); P::Algo::ALL.len()];
+    get(&mut perf_results, &mut returned_count)?;
+    Ok(perf_results[..returned_count as usize]
+        .iter()
+        .filter(|perf| perf.status() == sys::cudnnStatus_t::CUDNN_STATUS_SUCCESS)
+        .map(PerfResult::from)
+        .collect())
+}
+
+/// The largest workspace any of `algos` needs, capped at `limit`.
+fn max_workspace<A: RawEnum>(
+    limit: usize,
+    workspace_size: impl Fn(A) -> Result<usize, CudnnError>,
+) -> usize {
+    A::ALL
+        .iter()
+        .filter_map(|&algo| workspace_size(algo).ok())
+        .max()
+        .unwrap_or(0)
+        .min(limit)
+}
+
+/// The device pointer of a buffer from [Cudnn::alloc_scratch()], or null.
+fn scratch_ptr(buf: &Option<CudaSlice<u8>>) -> *mut std::ffi::c_void {
+    buf.as_ref()
+        .map_or(std::ptr::null_mut(), |b| *b.device_ptr() as *mut _)
+}
+
+fn scratch_len(buf: &Option<CudaSlice<u8>>) -> usize {
+    buf.as_ref().map_or(0, |b| b.len())
+}
+
+#[cfg(feature = "std")]
+impl AlgoCache {
+    /// Adds the entries to the file at `path`, keeping the entries already in it unless
+    /// they have the same key, so that processes sharing the file accumulate their
+    /// searches.
+    ///
+    /// The file is locked while it's updated, and replaced atomically with a checksum like
+    /// the entries of [crate::nvrtc::PtxCache]. A corrupted file is overwritten.
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let _lock =
+            cache::EntryLock::acquire(&cache::lock_path(path), cache::DEFAULT_LOCK_TIMEOUT)?;
+        let mut entries: BTreeMap<AlgoKey, CachedAlgo> = cache::read_entry(path)
+            .and_then(|payload| decode_entries(&payload))
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        entries.extend(self.snapshot());
+        cache::write_entry(path, &encode_entries(entries.iter()))
+    }
+
+    /// Reads a file written by [AlgoCache::save()].
+    ///
+    /// Returns [std::io::ErrorKind::InvalidData] if it's truncated or corrupted.
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let entry = std::fs::read(path)?;
+        let entries = cache::decode_entry(&entry)
+            .and_then(decode_entries)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidData))?;
+        Ok(Self {
+            entries: RwLock::new(entries.into_iter().collect()),
+        })
+    }
+
+    /// Checks the file at `path` like [crate::nvrtc::PtxCache::verify()]: removes it if it's
+    /// truncated or corrupted, and the temporary files of [AlgoCache::save()] next to it
+    /// that are older than the lock timeout.
+    pub fn verify<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<cache::CacheReport> {
+        let path = path.as_ref();
+        let mut report = cache::CacheReport::default();
+        for tmp in tmp_files(path)? {
+            if cache::is_stale(&tmp, cache::DEFAULT_LOCK_TIMEOUT)
+                && std::fs::remove_file(&tmp).is_ok()
+            {
+                report.stale += 1;
+            }
+        }
+        if path.exists() {
+            let is_valid = |payload: &[u8]| decode_entries(payload).is_some();
+            cache::verify_entry(path, cache::DEFAULT_LOCK_TIMEOUT, is_valid, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    /// Removes the file at `path` and the temporary files of [AlgoCache::save()] next to it,
+    /// returning whether there was one. Its `.lock` file is kept, so that it still excludes
+    /// the processes saving at the same time.
+    pub fn purge<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<bool> {
+        let path = path.as_ref();
+        let _lock =
+            cache::EntryLock::acquire(&cache::lock_path(path), cache::DEFAULT_LOCK_TIMEOUT)?;
+        for tmp in tmp_files(path)? {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        match std::fs::remove_file(path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The files [cache::write_entry()] writes before renaming them to `path`.
+#[cfg(feature = "std")]
+fn tmp_files(path: &std::path::Path) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        std::path::Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = std::format!("{}.", name.to_string_lossy());
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let file = entry?.path();
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        if file_name.starts_with(&prefix) && file_name.ends_with(".tmp") {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+/// Little endian, with the length of strings and vectors first.
+#[cfg(feature = "std")]
+fn encode_entries<'a>(
+    entries: impl ExactSizeIterator<Item = (&'a AlgoKey, &'a CachedAlgo)>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    let u32 = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+    let u64 = |out: &mut Vec<u8>, v: u64| out.extend_from_slice(&v.to_le_bytes());
+    let ints = |out: &mut Vec<u8>, v: &[i32]| {
+        u64(out, v.len() as u64);
+        v.iter().for_each(|&i| u32(out, i as u32));
+    };
+    u64(&mut out, entries.len() as u64);
+    for (key, algo) in entries {
+        u32(&mut out, key.direction as u32);
+        u64(&mut out, key.device.len() as u64);
+        out.extend_from_slice(key.device.as_bytes());
+        u64(&mut out, key.cudnn_version as u64);
+        u64(&mut out, key.workspace_limit as u64);
+        u32(&mut out, key.policy as u32);
+        for (dtype, dims, strides) in &key.tensors {
+            u32(&mut out, *dtype);
+            ints(&mut out, dims);
+            ints(&mut out, strides);
+        }
+        let (dtype, format, dims) = &key.filter;
+        u32(&mut out, *dtype);
+        u32(&mut out, *format);
+        ints(&mut out, dims);
+        let (pads, strides, dilations, mode, compute_type, groups, math_type) = &key.conv;
+        ints(&mut out, pads);
+        ints(&mut out, strides);
+        ints(&mut out, dilations);
+        u32(&mut out, *mode);
+        u32(&mut out, *compute_type);
+        u32(&mut out, *groups as u32);
+        u32(&mut out, *math_type);
+
+        u32(&mut out, algo.algo);
+        u32(&mut out, algo.time_ms.to_bits());
+        u64(&mut out, algo.workspace_size as u64);
+        u32(&mut out, algo.math_type);
+        u32(&mut out, algo.deterministic as u32);
+    }
+    out
+}
+
+/// The inverse of [encode_entries()], or `None` if `bytes` isn't a valid encoding.
+#[cfg(feature = "std")]
+fn decode_entries(mut bytes: &[u8]) -> Option<Vec<(AlgoKey, CachedAlgo)>> {
+    fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+        let (head, rest) = bytes.split_first_chunk::<N>()?;
+        *bytes = rest;
+        Some(*head)
+    }
+    let u32 = |bytes: &mut &[u8]| take::<4>(bytes).map(u32::from_le_bytes);
+    let u64 = |bytes: &mut &[u8]| take::<8>(bytes).map(u64::from_le_bytes);
+    let ints = |bytes: &mut &[u8]| -> Option<Vec<i32>> {
+        let len = usize::try_from(u64(bytes)?).ok()?;
+        if len > bytes.len() / 4 {
+            return None;
+        }
+        (0..len).map(|_| u32(bytes).map(|i| i as i32)).collect()
+    };
+    let bytes = &mut bytes;
+    let len = u64(bytes)?;
+    let mut entries = Vec::new();
+    for _ in 0..len {
+        let direction = match u32(bytes)? {
+            0 => ConvDirection::Forward,
+            1 => ConvDirection::BackwardData,
+            2 => ConvDirection::BackwardFilter,
+            _ => return None,
+        };
+        let device_len = usize::try_from(u64(bytes)?).ok()?;
+        let (device, rest) = bytes.split_at_checked(device_len)?;
+        *bytes = rest;
+        let device = String::from_utf8(device.to_vec()).ok()?;
+        let cudnn_version = usize::try_from(u64(bytes)?).ok()?;
+        let workspace_limit = usize::try_from(u64(bytes)?).ok()?;
+        let policy = match u32(bytes)? {
+            0 => DeterminismPolicy::Any,
+            1 => DeterminismPolicy::Prefer,
+            2 => DeterminismPolicy::Require,
+            _ => return None,
+        };
+        let mut tensor = || Some((u32(bytes)?, ints(bytes)?, ints(bytes)?));
+        let tensors = [tensor()?, tensor()?];
+        let filter = (u32(bytes)?, u32(bytes)?, ints(bytes)?);
+        let conv = (
+            ints(bytes)?,
+            ints(bytes)?,
+            ints(bytes)?,
+            u32(bytes)?,
+            u32(bytes)?,
+            u32(bytes)? as i32,
+            u32(bytes)?,
+        );
+        let key = AlgoKey {
+            direction,
+            device,
+            cudnn_version,
+            workspace_limit,
+            policy,
+            tensors,
+            filter,
+            conv,
+        };
+        let algo = CachedAlgo {
+            algo: u32(bytes)?,
+            time_ms: f32::from_bits(u32(bytes)?),
+            workspace_size: usize::try_from(u64(bytes)?).ok()?,
+            math_type: u32(bytes)?,
+            deterministic: match u32(bytes)? {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+        };
+        entries.push((key, algo));
+    }
+    bytes.is_empty().then_some(entries)
+}
@@ -1,10 +1,16 @@
+use super::activation::ActivationDescriptor;
+use super::algo::{
+    heuristic_algorithms, search_algorithms, AlgoCache, AlgoKey, ConvDirection, DeterminismPolicy,
+    PickAlgorithmError,
+};
 use super::core::*;
+use super::{PerfResult, PerfResults};
 use crate::{
     cudnn::{result, result::CudnnError, sys},
     driver::{DevicePtr, DevicePtrMut},
 };
 
-use std::{marker::PhantomData, sync::Arc};
+use std::{marker::PhantomData, sync::Arc, vec::Vec};
 
 /// A descriptor of the filters for conv operation. Create with [`Cudnn::create_4d_filter()`]
 #[derive(Debug)]
@@ -234,6 +240,128 @@ impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvForward<'a, X
         Ok(perf_results[0].algo)
     }
 
+    /// Like [ConvForward::pick_algorithm()], but skips the algorithms `policy` excludes, and
+    /// returns the math type, determinism and workspace size of the one it picked.
+    pub fn pick_algorithm_with(
+        &self,
+        policy: DeterminismPolicy,
+    ) -> Result<
+        PerfResult<sys::cudnnConvolutionFwdAlgo_t>,
+        PickAlgorithmError<sys::cudnnConvolutionFwdAlgo_t>,
+    > {
+        let perfs = heuristic_algorithms(|perf_results, returned_count| unsafe {
+            result::get_convolution_forward_algorithm(
+                self.conv.handle.handle,
+                self.x.desc,
+                self.w.desc,
+                self.conv.desc,
+                self.y.desc,
+                perf_results.len() as std::ffi::c_int,
+                returned_count,
+                perf_results.as_mut_ptr(),
+            )
+        })?;
+        policy.select(&perfs)
+    }
+
+    /// Runs every algorithm with `find_convolution_forward_algorithm_ex` and returns the ones that
+    /// succeeded, fastest first. This is much slower than [ConvForward::pick_algorithm()],
+    /// see [ConvForward::find_algorithm_cached()] to only search once.
+    ///
+    /// The search runs on zeroed scratch buffers allocated for the descriptors, and a workspace
+    /// of at most `workspace_limit` bytes. Algorithms that need more workspace are skipped.
+    pub fn find_algorithm(
+        &self,
+        workspace_limit: usize,
+    ) -> Result<PerfResults<sys::cudnnConvolutionFwdAlgo_t>, CudnnError> {
+        self.find_algorithm_with(workspace_limit, DeterminismPolicy::Any)
+    }
+
+    /// Like [ConvForward::find_algorithm()], but drops the algorithms `policy` excludes, and
+    /// with [DeterminismPolicy::Prefer] returns the deterministic ones first.
+    pub fn find_algorithm_with(
+        &self,
+        workspace_limit: usize,
+        policy: DeterminismPolicy,
+    ) -> Result<PerfResults<sys::cudnnConvolutionFwdAlgo_t>, CudnnError> {
+        search_algorithms(
+            &self.conv.handle,
+            [
+                unsafe { result::get_tensor_size_in_bytes(self.x.desc) }?,
+                unsafe { result::get_filter_size_in_bytes(self.w.desc) }?,
+                unsafe { result::get_tensor_size_in_bytes(self.y.desc) }?,
+            ],
+            workspace_limit,
+            |algo| self.get_workspace_size(algo),
+            |[x, w, y], (workspace, workspace_len), perf_results, returned_count| unsafe {
+                result::find_convolution_forward_algorithm_ex(
+                    self.conv.handle.handle,
+                    self.x.desc,
+                    x,
+                    self.w.desc,
+                    w,
+                    self.conv.desc,
+                    self.y.desc,
+                    y,
+                    perf_results.len() as std::ffi::c_int,
+                    returned_count,
+                    perf_results.as_mut_ptr(),
+                    workspace,
+                    workspace_len,
+                )
+            },
+        )
+        .map(|perfs| policy.filter(perfs))
+    }
+
+    /// Like [ConvForward::find_algorithm()], but returns the fastest algorithm from `cache`
+    /// if the same search ran before, e.g. with [AlgoCache::global()].
+    pub fn find_algorithm_cached(
+        &self,
+        workspace_limit: usize,
+        cache: &AlgoCache,
+    ) -> Result<PerfResult<sys::cudnnConvolutionFwdAlgo_t>, CudnnError> {
+        self.find_algorithm_cached_with(workspace_limit, cache, DeterminismPolicy::Any)
+            .map_err(PickAlgorithmError::into_cudnn)
+    }
+
+    /// Like [ConvForward::find_algorithm_cached()], but returns the fastest algorithm `policy`
+    /// allows. Results are cached per policy.
+    pub fn find_algorithm_cached_with(
+        &self,
+        workspace_limit: usize,
+        cache: &AlgoCache,
+        policy: DeterminismPolicy,
+    ) -> Result<
+        PerfResult<sys::cudnnConvolutionFwdAlgo_t>,
+        PickAlgorithmError<sys::cudnnConvolutionFwdAlgo_t>,
+    > {
+        let key = AlgoKey::new(
+            ConvDirection::Forward,
+            &self.conv.handle,
+            self.conv.desc,
+            [self.x.desc, self.y.desc],
+            self.w.desc,
+            workspace_limit,
+            policy,
+        )?;
+        cache.get_or_find(key, || self.find_algorithm(workspace_limit))
+    }
+
+    /// The dims of the output for the input and filter descriptors, in NC... order, e.g.
+    /// `[n, k, d, h, w]` for a 3d convolution. `y` isn't used, so can be any descriptor.
+    pub fn output_dims(&self) -> Result<Vec<std::ffi::c_int>, CudnnError> {
+        let (_, dims, _) = unsafe { result::get_tensornd_descriptor(self.x.desc) }?;
+        unsafe {
+            result::get_convolutionnd_forward_output_dim(
+                self.conv.desc,
+                self.x.desc,
+                self.w.desc,
+                dims.len(),
+            )
+        }
+    }
+
     /// Returns size in **bytes** to execute the selected algorithm.
     pub fn get_workspace_size(
         &self,
@@ -302,6 +430,170 @@ impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvForward<'a, X
     }
 }
 
+/// The fused convolution forward operation `y = act(alpha1 * conv(x, w) + alpha2 * z + bias)`,
+/// e.g. a convolution followed by a bias add, an optional residual add and a ReLU for
+/// inference. Pass in references to descriptors directly, and then call:
+/// 1. [`ConvBiasActivationForward::conv()`] to pick an algorithm and get the workspace size
+///    of the convolution, like for [ConvForward]
+/// 2. [`ConvBiasActivationForward::launch()`] to execute it
+///
+/// cudnn only fuses [sys::cudnnActivationMode_t::CUDNN_ACTIVATION_RELU], and
+/// [sys::cudnnActivationMode_t::CUDNN_ACTIVATION_IDENTITY] with
+/// [sys::cudnnConvolutionFwdAlgo_t::CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_PRECOMP_GEMM], see
+/// [ConvBiasActivationForward::validate()].
+#[derive(Debug)]
+pub struct ConvBiasActivationForward<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> {
+    /// Conv parameters
+    pub conv: &'a ConvDescriptor<C>,
+    /// Activation applied to the sum
+    pub act: &'a ActivationDescriptor<Y>,
+    /// Input tensor descriptor
+    pub x: &'a TensorDescriptor<X>,
+    /// Filter descriptor
+    pub w: &'a FilterDescriptor<X>,
+    /// Residual tensor descriptor, with the same dims as `y`. Use `y` itself without a
+    /// residual.
+    pub z: &'a TensorDescriptor<Y>,
+    /// Bias descriptor, of dims `[1, k, 1, 1]` for `k` output channels
+    pub bias: &'a TensorDescriptor<Y>,
+    /// Output tensor descriptor
+    pub y: &'a TensorDescriptor<Y>,
+}
+
+impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType>
+    ConvBiasActivationForward<'a, X, C, Y>
+{
+    /// The convolution without the bias and activation, to pick an algorithm and get its
+    /// workspace size.
+    pub fn conv(&self) -> ConvForward<'a, X, C, Y> {
+        ConvForward {
+            conv: self.conv,
+            x: self.x,
+            w: self.w,
+            y: self.y,
+        }
+    }
+
+    /// Checks that cudnn can fuse the activation with `algo`, returning
+    /// [ConvError::Activation] if not, and that the bias has one value per output channel,
+    /// returning [ConvError::Bias] if not.
+    pub fn validate_fusion(&self, algo: sys::cudnnConvolutionFwdAlgo_t) -> Result<(), ConvError> {
+        check_fused_activation(self.act.mode()?, algo)?;
+        let (_, y_dims, _) = unsafe { result::get_tensornd_descriptor(self.y.desc) }?;
+        let (_, bias_dims, _) = unsafe { result::get_tensornd_descriptor(self.bias.desc) }?;
+        let output_channels = y_dims.get(1).copied().unwrap_or(0);
+        let bias_len = bias_dims.iter().product();
+        if bias_dims.get(1) != Some(&output_channels) || bias_len != output_channels {
+            return Err(ConvError::Bias {
+                output_channels,
+                bias_len,
+            });
+        }
+        Ok(())
+    }
+
+    /// [ConvBiasActivationForward::validate_fusion()] and [ConvForward::validate()] of the
+    /// convolution.
+    pub fn validate(&self, algo: sys::cudnnConvolutionFwdAlgo_t) -> Result<(), ConvError> {
+        self.validate_fusion(algo)?;
+        self.conv().validate()
+    }
+
+    /// Launches the operation.
+    ///
+    /// - `src` is the input tensor
+    /// - `filter` is the convolution kernels
+    /// - `z` is the residual scaled by `alpha2`, `None` to use the current contents of `y`
+    ///   like the `beta` of [ConvForward::launch()]
+    /// - `bias` is added to each output channel
+    /// - `y` is the output
+    ///
+    /// Returns [ConvError::Activation] or [ConvError::Bias] instead of launching if
+    /// [ConvBiasActivationForward::validate_fusion()] fails.
+    ///
+    /// # Safety
+    /// The src/filter/z/bias/y arguments must match the data type/layout specified in the
+    /// descriptors in `self`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch<Workspace, Src, Filter, Z, Bias, Dst>(
+        &self,
+        algo: sys::cudnnConvolutionFwdAlgo_t,
+        workspace: Option<&mut Workspace>,
+        (alpha1, alpha2): (Y, Y),
+        src: &Src,
+        filter: &Filter,
+        z: Option<&Z>,
+        bias: &Bias,
+        y: &mut Dst,
+    ) -> Result<(), ConvError>
+    where
+        Workspace: DevicePtrMut<u8>,
+        Src: DevicePtr<X>,
+        Filter: DevicePtr<X>,
+        Z: DevicePtr<Y>,
+        Bias: DevicePtr<Y>,
+        Dst: DevicePtrMut<Y>,
+    {
+        self.validate_fusion(algo)?;
+        let (num_bytes, workspace_ptr) = match workspace {
+            Some(w) => (
+                w.num_bytes(),
+                *w.device_ptr_mut() as *mut u8 as *mut std::ffi::c_void,
+            ),
+            None => (0, std::ptr::null_mut()),
+        };
+        let y_ptr = *y.device_ptr_mut() as *mut Y as *mut std::ffi::c_void;
+        let (z_desc, z_ptr) = match z {
+            Some(z) => (
+                self.z.desc,
+                *z.device_ptr() as *const Y as *const std::ffi::c_void,
+            ),
+            None => (self.y.desc, y_ptr as *const std::ffi::c_void),
+        };
+        let alpha1 = alpha1.into_scaling_parameter();
+        let alpha2 = alpha2.into_scaling_parameter();
+        result::convolution_bias_activation_forward(
+            self.conv.handle.handle,
+            (&alpha1) as *const Y::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *src.device_ptr() as *const X as *const std::ffi::c_void,
+            self.w.desc,
+            *filter.device_ptr() as *const X as *const std::ffi::c_void,
+            self.conv.desc,
+            algo,
+            workspace_ptr,
+            num_bytes,
+            (&alpha2) as *const Y::Scalar as *const std::ffi::c_void,
+            z_desc,
+            z_ptr,
+            self.bias.desc,
+            *bias.device_ptr() as *const Y as *const std::ffi::c_void,
+            self.act.desc,
+            self.y.desc,
+            y_ptr,
+        )?;
+        Ok(())
+    }
+}
+
+/// Checks the constraints of `cudnnConvolutionBiasActivationForward` on its activation.
+fn check_fused_activation(
+    mode: sys::cudnnActivationMode_t,
+    algo: sys::cudnnConvolutionFwdAlgo_t,
+) -> Result<(), ConvError> {
+    use sys::cudnnActivationMode_t::*;
+    match mode {
+        CUDNN_ACTIVATION_RELU => Ok(()),
+        CUDNN_ACTIVATION_IDENTITY
+            if algo
+                == sys::cudnnConvolutionFwdAlgo_t::CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_PRECOMP_GEMM =>
+        {
+            Ok(())
+        }
+        _ => Err(ConvError::Activation { mode, algo }),
+    }
+}
+
 /// The convolution backward operation for the input tensor. Pass in references to descriptors
 /// directly, and then call:
 /// 1. [`ConvBackwardData::pick_algorithm()`] to use cudnn heuristics to select the algorithm
@@ -348,6 +640,114 @@ impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvBackwardData<
         Ok(perf_results[0].algo)
     }
 
+    /// Like [ConvBackwardData::pick_algorithm()], but skips the algorithms `policy` excludes, and
+    /// returns the math type, determinism and workspace size of the one it picked.
+    pub fn pick_algorithm_with(
+        &self,
+        policy: DeterminismPolicy,
+    ) -> Result<
+        PerfResult<sys::cudnnConvolutionBwdDataAlgo_t>,
+        PickAlgorithmError<sys::cudnnConvolutionBwdDataAlgo_t>,
+    > {
+        let perfs = heuristic_algorithms(|perf_results, returned_count| unsafe {
+            result::get_convolution_backward_data_algorithm(
+                self.conv.handle.handle,
+                self.w.desc,
+                self.dy.desc,
+                self.conv.desc,
+                self.dx.desc,
+                perf_results.len() as std::ffi::c_int,
+                returned_count,
+                perf_results.as_mut_ptr(),
+            )
+        })?;
+        policy.select(&perfs)
+    }
+
+    /// Runs every algorithm with `find_convolution_backward_data_algorithm_ex` and returns the ones that
+    /// succeeded, fastest first. This is much slower than [ConvBackwardData::pick_algorithm()],
+    /// see [ConvBackwardData::find_algorithm_cached()] to only search once.
+    ///
+    /// The search runs on zeroed scratch buffers allocated for the descriptors, and a workspace
+    /// of at most `workspace_limit` bytes. Algorithms that need more workspace are skipped.
+    pub fn find_algorithm(
+        &self,
+        workspace_limit: usize,
+    ) -> Result<PerfResults<sys::cudnnConvolutionBwdDataAlgo_t>, CudnnError> {
+        self.find_algorithm_with(workspace_limit, DeterminismPolicy::Any)
+    }
+
+    /// Like [ConvBackwardData::find_algorithm()], but drops the algorithms `policy` excludes, and
+    /// with [DeterminismPolicy::Prefer] returns the deterministic ones first.
+    pub fn find_algorithm_with(
+        &self,
+        workspace_limit: usize,
+        policy: DeterminismPolicy,
+    ) -> Result<PerfResults<sys::cudnnConvolutionBwdDataAlgo_t>, CudnnError> {
+        search_algorithms(
+            &self.conv.handle,
+            [
+                unsafe { result::get_filter_size_in_bytes(self.w.desc) }?,
+                unsafe { result::get_tensor_size_in_bytes(self.dy.desc) }?,
+                unsafe { result::get_tensor_size_in_bytes(self.dx.desc) }?,
+            ],
+            workspace_limit,
+            |algo| self.get_workspace_size(algo),
+            |[w, dy, dx], (workspace, workspace_len), perf_results, returned_count| unsafe {
+                result::find_convolution_backward_data_algorithm_ex(
+                    self.conv.handle.handle,
+                    self.w.desc,
+                    w,
+                    self.dy.desc,
+                    dy,
+                    self.conv.desc,
+                    self.dx.desc,
+                    dx,
+                    perf_results.len() as std::ffi::c_int,
+                    returned_count,
+                    perf_results.as_mut_ptr(),
+                    workspace,
+                    workspace_len,
+                )
+            },
+        )
+        .map(|perfs| policy.filter(perfs))
+    }
+
+    /// Like [ConvBackwardData::find_algorithm()], but returns the fastest algorithm from `cache`
+    /// if the same search ran before, e.g. with [AlgoCache::global()].
+    pub fn find_algorithm_cached(
+        &self,
+        workspace_limit: usize,
+        cache: &AlgoCache,
+    ) -> Result<PerfResult<sys::cudnnConvolutionBwdDataAlgo_t>, CudnnError> {
+        self.find_algorithm_cached_with(workspace_limit, cache, DeterminismPolicy::Any)
+            .map_err(PickAlgorithmError::into_cudnn)
+    }
+
+    /// Like [ConvBackwardData::find_algorithm_cached()], but returns the fastest algorithm `policy`
+    /// allows. Results are cached per policy.
+    pub fn find_algorithm_cached_with(
+        &self,
+        workspace_limit: usize,
+        cache: &AlgoCache,
+        policy: DeterminismPolicy,
+    ) -> Result<
+        PerfResult<sys::cudnnConvolutionBwdDataAlgo_t>,
+        PickAlgorithmError<sys::cudnnConvolutionBwdDataAlgo_t>,
+    > {
+        let key = AlgoKey::new(
+            ConvDirection::BackwardData,
+            &self.conv.handle,
+            self.conv.desc,
+            [self.dx.desc, self.dy.desc],
+            self.w.desc,
+            workspace_limit,
+            policy,
+        )?;
+        cache.get_or_find(key, || self.find_algorithm(workspace_limit))
+    }
+
     /// Returns size in **bytes** to execute the selected algorithm.
     pub fn get_workspace_size(
         &self,
@@ -462,6 +862,114 @@ impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvBackwardFilte
         Ok(perf_results[0].algo)
     }
 
+    /// Like [ConvBackwardFilter::pick_algorithm()], but skips the algorithms `policy` excludes, and
+    /// returns the math type, determinism and workspace size of the one it picked.
+    pub fn pick_algorithm_with(
+        &self,
+        policy: DeterminismPolicy,
+    ) -> Result<
+        PerfResult<sys::cudnnConvolutionBwdFilterAlgo_t>,
+        PickAlgorithmError<sys::cudnnConvolutionBwdFilterAlgo_t>,
+    > {
+        let perfs = heuristic_algorithms(|perf_results, returned_count| unsafe {
+            result::get_convolution_backward_filter_algorithm(
+                self.conv.handle.handle,
+                self.x.desc,
+                self.dy.desc,
+                self.conv.desc,
+                self.dw.desc,
+                perf_results.len() as std::ffi::c_int,
+                returned_count,
+                perf_results.as_mut_ptr(),
+            )
+        })?;
+        policy.select(&perfs)
+    }
+
+    /// Runs every algorithm with `find_convolution_backward_filter_algorithm_ex` and returns the ones that
+    /// succeeded, fastest first. This is much slower than [ConvBackwardFilter::pick_algorithm()],
+    /// see [ConvBackwardFilter::find_algorithm_cached()] to only search once.
+    ///
+    /// The search runs on zeroed scratch buffers allocated for the descriptors, and a workspace
+    /// of at most `workspace_limit` bytes. Algorithms that need more workspace are skipped.
+    pub fn find_algorithm(
+        &self,
+        workspace_limit: usize,
+    ) -> Result<PerfResults<sys::cudnnConvolutionBwdFilterAlgo_t>, CudnnError> {
+        self.find_algorithm_with(workspace_limit, DeterminismPolicy::Any)
+    }
+
+    /// Like [ConvBackwardFilter::find_algorithm()], but drops the algorithms `policy` excludes, and
+    /// with [DeterminismPolicy::Prefer] returns the deterministic ones first.
+    pub fn find_algorithm_with(
+        &self,
+        workspace_limit: usize,
+        policy: DeterminismPolicy,
+    ) -> Result<PerfResults<sys::cudnnConvolutionBwdFilterAlgo_t>, CudnnError> {
+        search_algorithms(
+            &self.conv.handle,
+            [
+                unsafe { result::get_tensor_size_in_bytes(self.x.desc) }?,
+                unsafe { result::get_tensor_size_in_bytes(self.dy.desc) }?,
+                unsafe { result::get_filter_size_in_bytes(self.dw.desc) }?,
+            ],
+            workspace_limit,
+            |algo| self.get_workspace_size(algo),
+            |[x, dy, dw], (workspace, workspace_len), perf_results, returned_count| unsafe {
+                result::find_convolution_backward_filter_algorithm_ex(
+                    self.conv.handle.handle,
+                    self.x.desc,
+                    x,
+                    self.dy.desc,
+                    dy,
+                    self.conv.desc,
+                    self.dw.desc,
+                    dw,
+                    perf_results.len() as std::ffi::c_int,
+                    returned_count,
+                    perf_results.as_mut_ptr(),
+                    workspace,
+                    workspace_len,
+                )
+            },
+        )
+        .map(|perfs| policy.filter(perfs))
+    }
+
+    /// Like [ConvBackwardFilter::find_algorithm()], but returns the fastest algorithm from `cache`
+    /// if the same search ran before, e.g. with [AlgoCache::global()].
+    pub fn find_algorithm_cached(
+        &self,
+        workspace_limit: usize,
+        cache: &AlgoCache,
+    ) -> Result<PerfResult<sys::cudnnConvolutionBwdFilterAlgo_t>, CudnnError> {
+        self.find_algorithm_cached_with(workspace_limit, cache, DeterminismPolicy::Any)
+            .map_err(PickAlgorithmError::into_cudnn)
+    }
+
+    /// Like [ConvBackwardFilter::find_algorithm_cached()], but returns the fastest algorithm `policy`
+    /// allows. Results are cached per policy.
+    pub fn find_algorithm_cached_with(
+        &self,
+        workspace_limit: usize,
+        cache: &AlgoCache,
+        policy: DeterminismPolicy,
+    ) -> Result<
+        PerfResult<sys::cudnnConvolutionBwdFilterAlgo_t>,
+        PickAlgorithmError<sys::cudnnConvolutionBwdFilterAlgo_t>,
+    > {
+        let key = AlgoKey::new(
+            ConvDirection::BackwardFilter,
+            &self.conv.handle,
+            self.conv.desc,
+            [self.x.desc, self.dy.desc],
+            self.dw.desc,
+            workspace_limit,
+            policy,
+        )?;
+        cache.get_or_find(key, || self.find_algorithm(workspace_limit))
+    }
+
     /// Returns size in **bytes** to execute the selected algorithm.
     pub fn get_workspace_size(
         &self,
@@ -525,3 +1033,358 @@ impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvBackwardFilte
         )
     }
 }
+
+impl<T> ConvDescriptor<T> {
+    /// The math type set with [ConvDescriptor::set_math_type()],
+    /// [sys::cudnnMathType_t::CUDNN_DEFAULT_MATH] by default.
+    pub fn math_type(&self) -> Result<sys::cudnnMathType_t, CudnnError> {
+        unsafe { result::get_convolution_math_type(self.desc) }
+    }
+
+    /// The group count set with [ConvDescriptor::set_group_count()], 1 by default. With
+    /// `g` groups, the `C` input channels and `K` filters are split into `g` groups each,
+    /// and the filters have `C / g` channels, e.g. `g = C = K` for a depthwise convolution.
+    pub fn group_count(&self) -> Result<std::ffi::c_int, CudnnError> {
+        unsafe { result::get_convolution_group_count(self.desc) }
+    }
+}
+
+/// A combination of data types and layouts cudnn supports for a convolution.
+/// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnConvolutionForward)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConvConfig {
+    /// Data type of the input and the filter.
+    pub x: sys::cudnnDataType_t,
+    /// Data type of the [ConvDescriptor].
+    pub compute: sys::cudnnDataType_t,
+    /// Data type of the output.
+    pub y: sys::cudnnDataType_t,
+    /// The layouts the input, filter and output may have.
+    pub formats: &'static [sys::cudnnTensorFormat_t],
+}
+
+const ANY_FORMAT: &[sys::cudnnTensorFormat_t] = &[
+    sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+    sys::cudnnTensorFormat_t::CUDNN_TENSOR_NHWC,
+];
+const NHWC: &[sys::cudnnTensorFormat_t] = &[sys::cudnnTensorFormat_t::CUDNN_TENSOR_NHWC];
+const NCHW_VECT_C: &[sys::cudnnTensorFormat_t] =
+    &[sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW_VECT_C];
+
+macro_rules! conv_config {
+    ($x:tt, $compute:tt, $y:tt, $formats:expr) => {
+        ConvConfig {
+            x: sys::cudnnDataType_t::$x,
+            compute: sys::cudnnDataType_t::$compute,
+            y: sys::cudnnDataType_t::$y,
+            formats: $formats,
+        }
+    };
+}
+
+/// The float configurations, which every direction supports, followed by `extra`.
+macro_rules! float_configs {
+    ($($extra:expr),* $(,)?) => {
+        &[
+            conv_config!(CUDNN_DATA_HALF, CUDNN_DATA_HALF, CUDNN_DATA_HALF, ANY_FORMAT),
+            conv_config!(CUDNN_DATA_HALF, CUDNN_DATA_FLOAT, CUDNN_DATA_HALF, ANY_FORMAT),
+            conv_config!(CUDNN_DATA_BFLOAT16, CUDNN_DATA_FLOAT, CUDNN_DATA_BFLOAT16, ANY_FORMAT),
+            conv_config!(CUDNN_DATA_FLOAT, CUDNN_DATA_FLOAT, CUDNN_DATA_FLOAT, ANY_FORMAT),
+            conv_config!(CUDNN_DATA_DOUBLE, CUDNN_DATA_DOUBLE, CUDNN_DATA_DOUBLE, ANY_FORMAT),
+            $($extra),*
+        ]
+    };
+}
+
+/// The configurations supported by the backward convolutions.
+const BACKWARD_CONFIGS: &[ConvConfig] = float_configs!();
+
+/// The configurations supported by [ConvForward], the backward ones plus the int8 ones.
+const FORWARD_CONFIGS: &[ConvConfig] = float_configs!(
+    conv_config!(CUDNN_DATA_INT8, CUDNN_DATA_INT32, CUDNN_DATA_INT8, NHWC),
+    conv_config!(CUDNN_DATA_INT8, CUDNN_DATA_INT32, CUDNN_DATA_FLOAT, NHWC),
+    conv_config!(
+        CUDNN_DATA_INT8x4,
+        CUDNN_DATA_INT32,
+        CUDNN_DATA_INT8x4,
+        NCHW_VECT_C
+    ),
+    conv_config!(
+        CUDNN_DATA_INT8x4,
+        CUDNN_DATA_INT32,
+        CUDNN_DATA_FLOAT,
+        NCHW_VECT_C
+    ),
+    conv_config!(
+        CUDNN_DATA_INT8x32,
+        CUDNN_DATA_INT32,
+        CUDNN_DATA_INT8x32,
+        NCHW_VECT_C
+    ),
+);
+
+/// A convolution whose data types and layouts cudnn doesn't support,
+/// returned by e.g. [ConvForward::validate()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedConv {
+    /// Data type and layout of the input.
+    pub x: (sys::cudnnDataType_t, sys::cudnnTensorFormat_t),
+    /// Data type and layout of the filter.
+    pub w: (sys::cudnnDataType_t, sys::cudnnTensorFormat_t),
+    /// Data type and layout of the output.
+    pub y: (sys::cudnnDataType_t, sys::cudnnTensorFormat_t),
+    /// Data type of the [ConvDescriptor].
+    pub compute: sys::cudnnDataType_t,
+    /// Math type of the [ConvDescriptor].
+    pub math_type: sys::cudnnMathType_t,
+    /// The configurations this convolution supports.
+    pub supported: &'static [ConvConfig],
+}
+
+impl UnsupportedConv {
+    fn matches_any(&self) -> bool {
+        self.supported.iter().any(|config| {
+            config.x == self.x.0
+                && config.x == self.w.0
+                && config.compute == self.compute
+                && config.y == self.y.0
+                && [self.x.1, self.w.1, self.y.1]
+                    .iter()
+                    .all(|format| config.formats.contains(format))
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for UnsupportedConv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unsupported convolution with x {:?}, w {:?}, y {:?}, compute {:?} and {:?}. Supported (x/w, compute, y, formats):",
+            self.x, self.w, self.y, self.compute, self.math_type
+        )?;
+        for config in self.supported {
+            write!(
+                f,
+                " ({:?}, {:?}, {:?}, {:?})",
+                config.x, config.compute, config.y, config.formats
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnsupportedConv {}
+
+/// Error from validating a convolution, see [ConvForward::validate()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvError {
+    /// Error returned by cudnn.
+    Cudnn(CudnnError),
+    /// The data types and layouts are not supported.
+    Unsupported(UnsupportedConv),
+    /// The channels don't match the filters or can't be split into the
+    /// [ConvDescriptor::group_count()] groups.
+    Groups {
+        /// Channels of the input.
+        input_channels: std::ffi::c_int,
+        /// Channels of the output.
+        output_channels: std::ffi::c_int,
+        /// Number of filters.
+        filters: std::ffi::c_int,
+        /// Channels of each filter.
+        filter_channels: std::ffi::c_int,
+        group_count: std::ffi::c_int,
+    },
+    /// The activation of a [ConvBiasActivationForward] can't be fused with the algorithm:
+    /// cudnn fuses [sys::cudnnActivationMode_t::CUDNN_ACTIVATION_RELU] with any algorithm,
+    /// [sys::cudnnActivationMode_t::CUDNN_ACTIVATION_IDENTITY] only with
+    /// [sys::cudnnConvolutionFwdAlgo_t::CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_PRECOMP_GEMM] and
+    /// no other activation.
+    Activation {
+        mode: sys::cudnnActivationMode_t,
+        algo: sys::cudnnConvolutionFwdAlgo_t,
+    },
+    /// The bias of a [ConvBiasActivationForward] doesn't have dims `[1, k, 1, 1]` for the
+    /// `k` output channels.
+    Bias {
+        output_channels: std::ffi::c_int,
+        /// Number of elements of the bias.
+        bias_len: std::ffi::c_int,
+    },
+}
+
+impl From<CudnnError> for ConvError {
+    fn from(value: CudnnError) -> Self {
+        Self::Cudnn(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for ConvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cudnn(e) => write!(f, "{e}"),
+            Self::Unsupported(e) => write!(f, "{e}"),
+            Self::Groups {
+                input_channels,
+                output_channels,
+                filters,
+                filter_channels,
+                group_count,
+            } => write!(
+                f,
+                "convolution with {group_count} groups needs {} input channels and as many output \
+                 channels as filters, divisible by the group count, got {input_channels} input \
+                 channels, {output_channels} output channels and {filters} filters of {filter_channels} channels",
+                filter_channels * group_count,
+            ),
+            Self::Activation { mode, algo } => write!(
+                f,
+                "fused convolution activations must be CUDNN_ACTIVATION_RELU, or \
+                 CUDNN_ACTIVATION_IDENTITY with CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_PRECOMP_GEMM, \
+                 got {mode:?} with {algo:?}"
+            ),
+            Self::Bias {
+                output_channels,
+                bias_len,
+            } => write!(
+                f,
+                "fused convolution bias must be [1, {output_channels}, 1, 1], one value per \
+                 output channel, got {bias_len} values"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConvError {}
+
+/// The layout of a tensor descriptor, which cudnn doesn't store, so is recovered from its
+/// data type and strides.
+fn tensor_format(
+    desc: sys::cudnnTensorDescriptor_t,
+) -> Result<(sys::cudnnDataType_t, sys::cudnnTensorFormat_t), CudnnError> {
+    let (data_type, dims, strides) = unsafe { result::get_tensornd_descriptor(desc) }?;
+    let format = match data_type {
+        sys::cudnnDataType_t::CUDNN_DATA_INT8x4 | sys::cudnnDataType_t::CUDNN_DATA_INT8x32 => {
+            sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW_VECT_C
+        }
+        _ if dims.len() >= 4 && (strides[1] == 1 || dims[1] == 1) => {
+            sys::cudnnTensorFormat_t::CUDNN_TENSOR_NHWC
+        }
+        _ => sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+    };
+    Ok((data_type, format))
+}
+
+/// Checks that the `[k, c / g, ...]` filters split the channels of `x` and `y` into
+/// `group_count` groups.
+fn check_groups(
+    group_count: std::ffi::c_int,
+    [x, y]: [sys::cudnnTensorDescriptor_t; 2],
+    w_dims: &[std::ffi::c_int],
+) -> Result<(), ConvError> {
+    let channels = |desc| -> Result<std::ffi::c_int, CudnnError> {
+        let (_, dims, _) = unsafe { result::get_tensornd_descriptor(desc) }?;
+        Ok(dims.get(1).copied().unwrap_or(0))
+    };
+    let (input_channels, output_channels) = (channels(x)?, channels(y)?);
+    let (filters, filter_channels) = match w_dims {
+        [k, c, ..] => (*k, *c),
+        _ => (0, 0),
+    };
+    if group_count < 1
+        || input_channels != filter_channels * group_count
+        || filters % group_count != 0
+        || output_channels != filters
+    {
+        return Err(ConvError::Groups {
+            input_channels,
+            output_channels,
+            filters,
+            filter_channels,
+            group_count,
+        });
+    }
+    Ok(())
+}
+
+impl<C> ConvDescriptor<C> {
+    /// Checks the data types and layouts against `supported`, and then asks cudnn whether any
+    /// algorithm supports them with `pick_algorithm`.
+    fn validate<A>(
+        &self,
+        [x, y]: [sys::cudnnTensorDescriptor_t; 2],
+        w: sys::cudnnFilterDescriptor_t,
+        supported: &'static [ConvConfig],
+        pick_algorithm: impl FnOnce() -> Result<A, CudnnError>,
+    ) -> Result<(), ConvError> {
+        let (w_type, w_format, w_dims) = unsafe { result::get_filternd_descriptor(w) }?;
+        check_groups(self.group_count()?, [x, y], &w_dims)?;
+        let conv = unsafe { result::get_convolutionnd_descriptor(self.desc) }?;
+        let unsupported = UnsupportedConv {
+            x: tensor_format(x)?,
+            w: (w_type, w_format),
+            y: tensor_format(y)?,
+            compute: conv.compute_type,
+            math_type: self.math_type()?,
+            supported,
+        };
+        // bf16 convolutions need cudnn 8.1
+        let needs_newer_cudnn =
+            w_type == sys::cudnnDataType_t::CUDNN_DATA_BFLOAT16 && result::get_version() < 8100;
+        if needs_newer_cudnn || !unsupported.matches_any() {
+            return Err(ConvError::Unsupported(unsupported));
+        }
+        match pick_algorithm() {
+            Ok(_) => Ok(()),
+            Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED)) => {
+                Err(ConvError::Unsupported(unsupported))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvForward<'a, X, C, Y> {
+    /// Checks that cudnn supports the data types and layouts of the descriptors, returning
+    /// [ConvError::Unsupported] listing the supported ones if not, and that the channels
+    /// match the filters and [ConvDescriptor::group_count()], returning [ConvError::Groups]
+    /// if not. Int8 convolutions need
+    /// [sys::cudnnTensorFormat_t::CUDNN_TENSOR_NHWC] tensors and filters, and [Int8x4]/[Int8x32]
+    /// ones need [sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW_VECT_C].
+    pub fn validate(&self) -> Result<(), ConvError> {
+        self.conv.validate(
+            [self.x.desc, self.y.desc],
+            self.w.desc,
+            FORWARD_CONFIGS,
+            || self.pick_algorithm(),
+        )
+    }
+}
+
+impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvBackwardData<'a, X, C, Y> {
+    /// Like [ConvForward::validate()]. Int8 backward convolutions are not supported.
+    pub fn validate(&self) -> Result<(), ConvError> {
+        self.conv.validate(
+            [self.dx.desc, self.dy.desc],
+            self.w.desc,
+            BACKWARD_CONFIGS,
+            || self.pick_algorithm(),
+        )
+    }
+}
+
+impl<'a, X: CudnnDataType, C: CudnnDataType, Y: CudnnDataType> ConvBackwardFilter<'a, X, C, Y> {
+    /// Like [ConvForward::validate()]. Int8 backward convolutions are not supported.
+    pub fn validate(&self) -> Result<(), ConvError> {
+        self.conv.validate(
+            [self.x.desc, self.dy.desc],
+            self.dw.desc,
+            BACKWARD_CONFIGS,
+            || self.pick_algorithm(),
+        )
+    }
+}
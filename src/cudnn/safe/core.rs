@@ -1,6 +1,6 @@
 use crate::{
     cudnn::{result, result::CudnnError, sys},
-    driver::{CudaDevice, CudaStream},
+    driver::{safe::error_handler::OrHandle, CudaDevice, CudaStream, DeviceRepr, ValidAsZeroBits},
 };
 
 use std::{marker::PhantomData, sync::Arc};
@@ -16,9 +16,16 @@ pub struct Cudnn {
 
 impl Cudnn {
     /// Creates a new cudnn handle and sets the stream to the `device`'s stream.
+    ///
+    /// If the `device`'s context can't be bound to the thread, the [crate::driver::DriverError]
+    /// is passed to the [crate::driver::ErrorHandler] and `CUDNN_STATUS_NOT_INITIALIZED` is
+    /// returned.
     #[allow(clippy::arc_with_non_send_sync)]
     pub fn new(device: Arc<CudaDevice>) -> Result<Arc<Self>, CudnnError> {
-        device.bind_to_thread().unwrap();
+        device.bind_to_thread().or_report(
+            "Cudnn::new",
+            CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_NOT_INITIALIZED),
+        )?;
         let handle = result::create_handle()?;
         unsafe { result::set_stream(handle, device.stream as *mut _) }?;
         Ok(Arc::new(Self { handle, device }))
@@ -70,11 +77,24 @@ macro_rules! cudnn_dtype {
             }
         }
     };
+    ($RustTy:ty, $CudnnTy:tt, |$x:ident| $to_f32:expr) => {
+        impl CudnnDataType for $RustTy {
+            const DATA_TYPE: sys::cudnnDataType_t = sys::cudnnDataType_t::$CudnnTy;
+            type Scalar = f32;
+            fn into_scaling_parameter(self) -> Self::Scalar {
+                let $x = self;
+                $to_f32
+            }
+        }
+    };
 }
 
 cudnn_dtype!(f32, CUDNN_DATA_FLOAT);
 cudnn_dtype!(f64, CUDNN_DATA_DOUBLE);
-cudnn_dtype!(i8, CUDNN_DATA_INT8);
+// the scaling parameters of int8 convolutions are floats
+cudnn_dtype!(i8, CUDNN_DATA_INT8, |x| x as f32);
+cudnn_dtype!(Int8x4, CUDNN_DATA_INT8x4, |x| x.0[0] as f32);
+cudnn_dtype!(Int8x32, CUDNN_DATA_INT8x32, |x| x.0[0] as f32);
 cudnn_dtype!(i32, CUDNN_DATA_INT32);
 cudnn_dtype!(i64, CUDNN_DATA_INT64);
 cudnn_dtype!(u8, CUDNN_DATA_UINT8);
@@ -98,6 +118,26 @@ impl CudnnDataType for half::bf16 {
     }
 }
 
+/// 4 `i8`s packed together, for [sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW_VECT_C] tensors
+/// with [sys::cudnnDataType_t::CUDNN_DATA_INT8x4]. The `C` dimension of the descriptor counts
+/// `i8`s, so is 4 times the number of [Int8x4]s.
+///
+/// As a scaling parameter (alpha/beta) only the first lane is used.
+#[repr(C, align(4))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Int8x4(pub [i8; 4]);
+
+/// 32 `i8`s packed together, for [sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW_VECT_C] tensors
+/// with [sys::cudnnDataType_t::CUDNN_DATA_INT8x32], see [Int8x4].
+#[repr(C, align(32))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Int8x32(pub [i8; 32]);
+
+unsafe impl DeviceRepr for Int8x4 {}
+unsafe impl ValidAsZeroBits for Int8x4 {}
+unsafe impl DeviceRepr for Int8x32 {}
+unsafe impl ValidAsZeroBits for Int8x32 {}
+
 /// A descriptor of a tensor. Create with:
 /// 1. [`Cudnn::create_4d_tensor()`]
 /// 2. [`Cudnn::create_4d_tensor_ex()`]
@@ -168,6 +208,41 @@ impl Cudnn {
         }?;
         Ok(desc)
     }
+
+    /// Creates an nd (at LEAST 4d) tensor descriptor with packed strides for `format`, e.g.
+    /// NCDHW or NDHWC for 5 dims. `dims` are in NC... order for either format.
+    pub fn create_nd_tensor_ex<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        format: sys::cudnnTensorFormat_t,
+        dims: &[std::ffi::c_int],
+    ) -> Result<TensorDescriptor<T>, CudnnError> {
+        assert!(dims.len() >= 4);
+        let desc = result::create_tensor_descriptor()?;
+        let desc = TensorDescriptor {
+            desc,
+            handle: self.clone(),
+            marker: PhantomData,
+        };
+        unsafe {
+            result::set_tensornd_descriptor_ex(
+                desc.desc,
+                format,
+                T::DATA_TYPE,
+                dims.len() as std::ffi::c_int,
+                dims.as_ptr(),
+            )
+        }?;
+        Ok(desc)
+    }
+
+    /// Creates a 5d tensor descriptor, see [Cudnn::create_nd_tensor_ex()].
+    pub fn create_5d_tensor<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        format: sys::cudnnTensorFormat_t,
+        dims: [std::ffi::c_int; 5],
+    ) -> Result<TensorDescriptor<T>, CudnnError> {
+        self.create_nd_tensor_ex(format, &dims)
+    }
 }
 
 impl<T> Drop for TensorDescriptor<T> {
@@ -0,0 +1,445 @@
+use super::core::*;
+use crate::{
+    cudnn::{result, result::CudnnError, sys},
+    driver::{DevicePtr, DevicePtrMut, DriverError},
+};
+
+use std::{marker::PhantomData, sync::Arc};
+
+/// The longest label cudnn supports in a CTC loss.
+pub const CTC_MAX_LABEL_LENGTH: std::ffi::c_int = 256;
+
+/// A CTC loss descriptor. Create with [`Cudnn::create_ctc_loss()`]
+#[derive(Debug)]
+pub struct CtcLossDescriptor<T> {
+    pub(crate) desc: sys::cudnnCTCLossDescriptor_t,
+    pub(crate) max_label_length: std::ffi::c_int,
+    /// Whether the descriptor is for `cudnnCTCLoss_v8`, see [result::is_ctc_loss_v8_supported()].
+    pub(crate) v8: bool,
+    #[allow(unused)]
+    pub(crate) handle: Arc<Cudnn>,
+    pub(crate) marker: PhantomData<T>,
+}
+
+impl Cudnn {
+    /// Creates a CTC loss descriptor, for labels of at most `max_label_length` (which can't be
+    /// more than [CTC_MAX_LABEL_LENGTH]).
+    ///
+    /// With [sys::cudnnLossNormalizationMode_t::CUDNN_LOSS_NORMALIZATION_SOFTMAX] the probs
+    /// passed to [CtcLoss] are unnormalized activations that cudnn applies a softmax to, and the
+    /// gradients are with respect to them. With
+    /// [sys::cudnnLossNormalizationMode_t::CUDNN_LOSS_NORMALIZATION_NONE] they must already be
+    /// probabilities.
+    ///
+    /// Uses `cudnnCTCLoss_v8` if [result::is_ctc_loss_v8_supported()], and the older API
+    /// otherwise.
+    pub fn create_ctc_loss<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        norm_mode: sys::cudnnLossNormalizationMode_t,
+        nan_opt: sys::cudnnNanPropagation_t,
+        max_label_length: std::ffi::c_int,
+    ) -> Result<CtcLossDescriptor<T>, CudnnError> {
+        if !(1..=CTC_MAX_LABEL_LENGTH).contains(&max_label_length) {
+            return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM));
+        }
+        let desc = result::create_ctc_loss_descriptor()?;
+        let desc = CtcLossDescriptor {
+            desc,
+            max_label_length,
+            v8: result::is_ctc_loss_v8_supported(),
+            handle: self.clone(),
+            marker: PhantomData,
+        };
+        unsafe {
+            if desc.v8 {
+                result::set_ctc_loss_descriptor_v8(
+                    desc.desc,
+                    T::DATA_TYPE,
+                    norm_mode,
+                    nan_opt,
+                    max_label_length,
+                )
+            } else {
+                result::set_ctc_loss_descriptor_ex(desc.desc, T::DATA_TYPE, norm_mode, nan_opt)
+            }
+        }?;
+        Ok(desc)
+    }
+
+    /// Creates the `[max_time, batch_size, alphabet_size]` tensor descriptor of the probs and
+    /// gradients of a [CtcLoss], with the alphabet being contiguous. Label 0 is the blank.
+    pub fn create_ctc_probs_tensor<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        [max_time, batch_size, alphabet_size]: [std::ffi::c_int; 3],
+    ) -> Result<TensorDescriptor<T>, CudnnError> {
+        let dims = [max_time, batch_size, alphabet_size];
+        let strides = [batch_size * alphabet_size, alphabet_size, 1];
+        let desc = result::create_tensor_descriptor()?;
+        let desc = TensorDescriptor {
+            desc,
+            handle: self.clone(),
+            marker: PhantomData,
+        };
+        unsafe {
+            result::set_tensornd_descriptor(
+                desc.desc,
+                T::DATA_TYPE,
+                dims.len() as std::ffi::c_int,
+                dims.as_ptr(),
+                strides.as_ptr(),
+            )
+        }?;
+        Ok(desc)
+    }
+}
+
+impl<T> CtcLossDescriptor<T> {
+    /// The longest label this descriptor was created for.
+    pub fn max_label_length(&self) -> std::ffi::c_int {
+        self.max_label_length
+    }
+}
+
+impl<T> Drop for CtcLossDescriptor<T> {
+    fn drop(&mut self) {
+        let desc = std::mem::replace(&mut self.desc, std::ptr::null_mut());
+        if !desc.is_null() {
+            unsafe { result::destroy_ctc_loss_descriptor(desc) }.unwrap()
+        }
+    }
+}
+
+/// Error from a [CtcLoss], which checks the labels and lengths on the host before calling
+/// cudnn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CtcError {
+    /// Error returned by cudnn.
+    Cudnn(CudnnError),
+    /// Error copying the labels and lengths to the device.
+    Driver(DriverError),
+    /// `label_lengths` or `input_lengths` doesn't have one length per batch item of the probs.
+    BatchMismatch {
+        batch_size: usize,
+        label_lengths: usize,
+        input_lengths: usize,
+    },
+    /// `labels` isn't the concatenation of `label_lengths` labels.
+    LabelsMismatch { expected: usize, found: usize },
+    /// A label is longer than the [CtcLossDescriptor::max_label_length()].
+    LabelTooLong {
+        batch: usize,
+        length: std::ffi::c_int,
+        max: std::ffi::c_int,
+    },
+    /// An input length is 0, or longer than the time dimension of the probs.
+    InvalidInputLength {
+        batch: usize,
+        length: std::ffi::c_int,
+        max_time: std::ffi::c_int,
+    },
+    /// A label is the blank (0), or outside of the alphabet.
+    InvalidLabel {
+        index: usize,
+        label: std::ffi::c_int,
+        alphabet_size: std::ffi::c_int,
+    },
+}
+
+impl From<CudnnError> for CtcError {
+    fn from(value: CudnnError) -> Self {
+        Self::Cudnn(value)
+    }
+}
+
+impl From<DriverError> for CtcError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for CtcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cudnn(e) => write!(f, "{e}"),
+            Self::Driver(e) => write!(f, "{e}"),
+            Self::BatchMismatch {
+                batch_size,
+                label_lengths,
+                input_lengths,
+            } => write!(
+                f,
+                "expected {batch_size} lengths, found {label_lengths} label lengths and {input_lengths} input lengths"
+            ),
+            Self::LabelsMismatch { expected, found } => {
+                write!(f, "expected {expected} labels, found {found}")
+            }
+            Self::LabelTooLong { batch, length, max } => write!(
+                f,
+                "label {batch} has length {length}, but the maximum is {max}"
+            ),
+            Self::InvalidInputLength {
+                batch,
+                length,
+                max_time,
+            } => write!(
+                f,
+                "input {batch} has length {length}, which must be in 1..={max_time}"
+            ),
+            Self::InvalidLabel {
+                index,
+                label,
+                alphabet_size,
+            } => write!(
+                f,
+                "label {label} at {index} must be in 1..{alphabet_size}, 0 is the blank"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CtcError {}
+
+/// A CTC loss operation. Pass in fields directly, and then call launch.
+///
+/// The labels of the whole batch are passed concatenated, along with the length of each
+/// label and the number of timesteps of each input.
+pub struct CtcLoss<'a, T: CudnnDataType> {
+    /// The CTC loss descriptor.
+    pub ctc: &'a CtcLossDescriptor<T>,
+    /// The `[max_time, batch_size, alphabet_size]` probs (and gradients) tensor, see
+    /// [Cudnn::create_ctc_probs_tensor()].
+    pub probs: &'a TensorDescriptor<T>,
+}
+
+impl<'a, T: CudnnDataType> CtcLoss<'a, T> {
+    /// Checks the labels and lengths against the descriptors, and the constraints cudnn puts
+    /// on them. Called by the launch methods.
+    pub fn validate(
+        &self,
+        labels: &[std::ffi::c_int],
+        label_lengths: &[std::ffi::c_int],
+        input_lengths: &[std::ffi::c_int],
+    ) -> Result<(), CtcError> {
+        let (_, dims, _) = unsafe { result::get_tensornd_descriptor(self.probs.desc) }?;
+        let (max_time, batch_size, alphabet_size) = (dims[0], dims[1] as usize, dims[2]);
+        if label_lengths.len() != batch_size || input_lengths.len() != batch_size {
+            return Err(CtcError::BatchMismatch {
+                batch_size,
+                label_lengths: label_lengths.len(),
+                input_lengths: input_lengths.len(),
+            });
+        }
+        for (batch, (&label_length, &input_length)) in
+            label_lengths.iter().zip(input_lengths).enumerate()
+        {
+            // the older api has no maximum in the descriptor, but the kernels have the same limit
+            if !(0..=self.ctc.max_label_length).contains(&label_length) {
+                return Err(CtcError::LabelTooLong {
+                    batch,
+                    length: label_length,
+                    max: self.ctc.max_label_length,
+                });
+            }
+            if !(1..=max_time).contains(&input_length) {
+                return Err(CtcError::InvalidInputLength {
+                    batch,
+                    length: input_length,
+                    max_time,
+                });
+            }
+        }
+        let expected = label_lengths.iter().map(|&l| l as usize).sum();
+        if labels.len() != expected {
+            return Err(CtcError::LabelsMismatch {
+                expected,
+                found: labels.len(),
+            });
+        }
+        if let Some((index, &label)) = labels
+            .iter()
+            .enumerate()
+            .find(|(_, &l)| !(1..alphabet_size).contains(&l))
+        {
+            return Err(CtcError::InvalidLabel {
+                index,
+                label,
+                alphabet_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Gets the size of the workspace for this operation. The older cudnn API needs the labels
+    /// and lengths to compute it, the newer one ignores them.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetCTCLossWorkspaceSize_v8)
+    pub fn get_workspace_size(
+        &self,
+        algo: sys::cudnnCTCLossAlgo_t,
+        labels: &[std::ffi::c_int],
+        label_lengths: &[std::ffi::c_int],
+        input_lengths: &[std::ffi::c_int],
+    ) -> Result<usize, CtcError> {
+        self.validate(labels, label_lengths, input_lengths)?;
+        let size = unsafe {
+            if self.ctc.v8 {
+                result::get_ctc_loss_workspace_size_v8(
+                    self.ctc.handle.handle,
+                    algo,
+                    self.ctc.desc,
+                    self.probs.desc,
+                    self.probs.desc,
+                )
+            } else {
+                result::get_ctc_loss_workspace_size(
+                    self.ctc.handle.handle,
+                    self.probs.desc,
+                    self.probs.desc,
+                    labels.as_ptr(),
+                    label_lengths.as_ptr(),
+                    input_lengths.as_ptr(),
+                    algo,
+                    self.ctc.desc,
+                )
+            }
+        }?;
+        Ok(size)
+    }
+
+    /// Computes the loss of each batch item into `costs`, without the gradients.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`, and `costs` must have `batch_size` elements.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch<Workspace, P, C>(
+        &self,
+        algo: sys::cudnnCTCLossAlgo_t,
+        workspace: &mut Workspace,
+        probs: &P,
+        labels: &[std::ffi::c_int],
+        label_lengths: &[std::ffi::c_int],
+        input_lengths: &[std::ffi::c_int],
+        costs: &mut C,
+    ) -> Result<(), CtcError>
+    where
+        Workspace: DevicePtrMut<u8>,
+        P: DevicePtr<T>,
+        C: DevicePtrMut<T>,
+    {
+        self.launch_impl(
+            algo,
+            workspace,
+            probs,
+            (labels, label_lengths, input_lengths),
+            costs,
+            std::ptr::null_mut(),
+        )
+    }
+
+    /// Computes the loss of each batch item into `costs`, and its gradients with respect to
+    /// the probs into `gradients`.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`, `costs` must have `batch_size` elements and `gradients` as many
+    /// as `probs`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch_with_gradients<Workspace, P, C, G>(
+        &self,
+        algo: sys::cudnnCTCLossAlgo_t,
+        workspace: &mut Workspace,
+        probs: &P,
+        labels: &[std::ffi::c_int],
+        label_lengths: &[std::ffi::c_int],
+        input_lengths: &[std::ffi::c_int],
+        costs: &mut C,
+        gradients: &mut G,
+    ) -> Result<(), CtcError>
+    where
+        Workspace: DevicePtrMut<u8>,
+        P: DevicePtr<T>,
+        C: DevicePtrMut<T>,
+        G: DevicePtrMut<T>,
+    {
+        self.launch_impl(
+            algo,
+            workspace,
+            probs,
+            (labels, label_lengths, input_lengths),
+            costs,
+            *gradients.device_ptr_mut() as *mut _,
+        )
+    }
+
+    unsafe fn launch_impl<Workspace, P, C>(
+        &self,
+        algo: sys::cudnnCTCLossAlgo_t,
+        workspace: &mut Workspace,
+        probs: &P,
+        (labels, label_lengths, input_lengths): (
+            &[std::ffi::c_int],
+            &[std::ffi::c_int],
+            &[std::ffi::c_int],
+        ),
+        costs: &mut C,
+        gradients: *mut std::ffi::c_void,
+    ) -> Result<(), CtcError>
+    where
+        Workspace: DevicePtrMut<u8>,
+        P: DevicePtr<T>,
+        C: DevicePtrMut<T>,
+    {
+        self.validate(labels, label_lengths, input_lengths)?;
+        let handle = &self.ctc.handle;
+        if self.ctc.v8 {
+            // read by the kernels, so they have to be on the device
+            let dev = &handle.device;
+            let labels = dev.htod_sync_copy(labels)?;
+            let label_lengths = dev.htod_sync_copy(label_lengths)?;
+            let input_lengths = dev.htod_sync_copy(input_lengths)?;
+            let stream = result::get_stream(handle.handle)? as crate::driver::sys::CUstream;
+            result::ctc_loss_v8(
+                handle.handle,
+                algo,
+                self.ctc.desc,
+                self.probs.desc,
+                *probs.device_ptr() as *const _,
+                *labels.device_ptr() as *const _,
+                *label_lengths.device_ptr() as *const _,
+                *input_lengths.device_ptr() as *const _,
+                *costs.device_ptr_mut() as *mut _,
+                self.probs.desc,
+                gradients,
+                workspace.num_bytes(),
+                *workspace.device_ptr_mut() as *mut _,
+            )?;
+            // the copies are freed on the device's stream, which their drops order after the
+            // loss on the handle's stream
+            if stream != dev.stream {
+                labels.last_write.record(stream)?;
+                label_lengths.last_write.record(stream)?;
+                input_lengths.last_write.record(stream)?;
+            }
+        } else {
+            result::ctc_loss(
+                handle.handle,
+                self.probs.desc,
+                *probs.device_ptr() as *const _,
+                labels.as_ptr(),
+                label_lengths.as_ptr(),
+                input_lengths.as_ptr(),
+                *costs.device_ptr_mut() as *mut _,
+                self.probs.desc,
+                gradients,
+                algo,
+                self.ctc.desc,
+                *workspace.device_ptr_mut() as *mut _,
+                workspace.num_bytes(),
+            )?;
+        }
+        Ok(())
+    }
+}
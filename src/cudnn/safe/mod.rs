@@ -6,20 +6,70 @@
 //! 2. Allocate filter descriptors with [`Cudnn::create_4d_filter()`]
 //! 3. Allocate conv descriptors with [`Cudnn::create_conv2d()`]
 //! 4. Instantiate one of the following algorithms with the descriptors:
-//!     a. [`Conv2dForward`]
-//!     b. [`Conv2dBackwardData`] for computing gradient of image
-//!     c. [`Conv2dBackwardFilter`] for computing gradient of filters
+//!    a. [`Conv2dForward`]
+//!    b. [`Conv2dBackwardData`] for computing gradient of image
+//!    c. [`Conv2dBackwardFilter`] for computing gradient of filters
 //! 5. Call the `pick_algorithm` method of the struct. Specify the number of options to compare with a const generic.
+//!    Or, to only pick deterministic algorithms and see which one was picked, call
+//!    `pick_algorithm_with` with a [`DeterminismPolicy`].
+//!    Or, to time the algorithms instead of using heuristics, call `find_algorithm`, or
+//!    `find_algorithm_cached` with an [`AlgoCache`], or their `_with` variants taking a
+//!    [`DeterminismPolicy`].
 //! 6. Call the `get_workspace_size` method of the struct.
 //! 7. Re-allocate the workspace to the appropriate size.
 //! 8. Call the `launch` method of the struct.
 //!
+//! Call the `validate` method of the struct to check that cudnn supports the data types and
+//! layouts of the descriptors, and that the channels fit the filters. Int8 convolutions need
+//! NHWC tensors and filters, and [`Int8x4`]/[`Int8x32`] ones need NCHW_VECT_C; see
+//! [`ConvDescriptor::set_math_type()`] to use tensor cores.
+//!
+//! For grouped and depthwise convolutions, see [`ConvDescriptor::set_group_count()`]. 3d
+//! convolutions take 5d descriptors from [`Cudnn::create_5d_tensor()`],
+//! [`Cudnn::create_5d_filter()`] and [`Cudnn::create_convnd()`].
+//!
 //! # Reductions
+//!
+//! # Normalization
+//!
+//! 1. [`LrnForward`] and [`LrnBackward`] with an LRN descriptor from [`Cudnn::create_lrn()`]
+//! 2. [`NormalizationForward`] and [`NormalizationBackward`] for batch/instance/group
+//!    normalization, which need cuDNN >= 8.0
+//!
+//! # CTC loss
+//!
+//! 1. Allocate a CTC loss descriptor with [`Cudnn::create_ctc_loss()`]
+//! 2. Allocate the probs descriptor with [`Cudnn::create_ctc_probs_tensor()`]
+//! 3. Instantiate [`CtcLoss`] with them, and call its `get_workspace_size` method.
+//! 4. Call `launch`, or `launch_with_gradients` for training.
+//!
+//! The labels and lengths are passed as host slices and checked before calling cudnn; they
+//! are copied to the device for cuDNN >= 8.0, which reads them there.
+//!
+//! # Spatial transformers
+//!
+//! 1. Allocate a spatial transformer descriptor with [`Cudnn::create_spatial_transformer()`]
+//!    for the dimensions of the output
+//! 2. Compute the sampling grid from affine matrices with
+//!    [`SpatialTransformerDescriptor::generate_grid()`]
+//! 3. Instantiate [`SpatialTfSamplerForward`] with the descriptors, and call `launch`.
+//!    [`SpatialTfSamplerBackward`] and
+//!    [`SpatialTransformerDescriptor::generate_grid_backward()`] compute the gradients.
+//!
+//! For nearest sampling, or border and reflection padding, see
+//! [`crate::driver::CudaDevice::grid_sample()`].
 
+mod activation;
+mod algo;
 mod conv;
 mod core;
+mod ctc;
+mod norm;
 mod reduce;
+mod spatial_tf;
 
+pub use self::activation::ActivationDescriptor;
+pub use self::algo::{AlgoCache, DeterminismPolicy, PerfResult, PerfResults, PickAlgorithmError};
 #[allow(deprecated)]
 pub use self::conv::{
     // Deprecated APIs
@@ -30,20 +80,34 @@ pub use self::conv::{
     // Current APIs
     ConvBackwardData,
     ConvBackwardFilter,
+    ConvBiasActivationForward,
+    ConvConfig,
     ConvDescriptor,
+    ConvError,
     ConvForward,
     FilterDescriptor,
+    UnsupportedConv,
+};
+pub use self::core::{Cudnn, CudnnDataType, Int8x32, Int8x4, TensorDescriptor};
+pub use self::ctc::{CtcError, CtcLoss, CtcLossDescriptor, CTC_MAX_LABEL_LENGTH};
+pub use self::norm::{
+    LrnBackward, LrnDescriptor, LrnForward, MeanVar, NormalizationBackward, NormalizationForward,
 };
-pub use self::core::{Cudnn, CudnnDataType, TensorDescriptor};
 pub use self::reduce::{FlatIndices, NoIndices, ReduceTensor, ReductionDescriptor};
+pub use self::spatial_tf::{
+    SpatialTfSamplerBackward, SpatialTfSamplerForward, SpatialTransformerDescriptor,
+};
 pub use super::result::CudnnError;
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{cudnn, driver::CudaDevice};
+    use crate::{
+        cudnn,
+        driver::{CudaDevice, CudaSlice},
+    };
     #[cfg(feature = "no-std")]
-    use no_std_compat::vec;
+    use no_std_compat::{vec, vec::Vec};
 
     #[test]
     fn test_create_descriptors() -> Result<(), CudnnError> {
@@ -133,6 +197,310 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_conv2d_pick_algorithms_with_policy() -> Result<(), CudnnError> {
+        let cudnn = Cudnn::new(CudaDevice::new(0).unwrap())?;
+
+        let conv = cudnn.create_conv2d::<f32>(
+            [0; 2],
+            [1; 2],
+            [1; 2],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        let x = cudnn.create_4d_tensor::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [2, 8, 16, 16],
+        )?;
+        let filter = cudnn.create_4d_filter::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [4, 8, 3, 3],
+        )?;
+        let y = cudnn.create_4d_tensor::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [2, 4, 14, 14],
+        )?;
+
+        {
+            let op = ConvForward {
+                conv: &conv,
+                x: &x,
+                w: &filter,
+                y: &y,
+            };
+            let any = op.pick_algorithm_with(DeterminismPolicy::Any).unwrap();
+            assert_eq!(any.algo, op.pick_algorithm()?);
+            let perf = op.pick_algorithm_with(DeterminismPolicy::Require).unwrap();
+            assert!(perf.deterministic);
+            assert_ne!(
+                perf.algo,
+                cudnn::sys::cudnnConvolutionFwdAlgo_t::CUDNN_CONVOLUTION_FWD_ALGO_WINOGRAD_NONFUSED
+            );
+        }
+
+        {
+            let op = ConvBackwardData {
+                conv: &conv,
+                dx: &x,
+                w: &filter,
+                dy: &y,
+            };
+            let any = op.pick_algorithm_with(DeterminismPolicy::Any).unwrap();
+            assert_eq!(any.algo, op.pick_algorithm()?);
+            let perf = op.pick_algorithm_with(DeterminismPolicy::Require).unwrap();
+            assert!(perf.deterministic);
+            assert_ne!(
+                perf.algo,
+                cudnn::sys::cudnnConvolutionBwdDataAlgo_t::CUDNN_CONVOLUTION_BWD_DATA_ALGO_WINOGRAD_NONFUSED
+            );
+        }
+
+        {
+            let op = ConvBackwardFilter {
+                conv: &conv,
+                x: &x,
+                dw: &filter,
+                dy: &y,
+            };
+            let any = op.pick_algorithm_with(DeterminismPolicy::Any).unwrap();
+            assert_eq!(any.algo, op.pick_algorithm()?);
+            let perf = op.pick_algorithm_with(DeterminismPolicy::Require).unwrap();
+            assert_eq!(op.pick_algorithm_with(DeterminismPolicy::Prefer), Ok(perf));
+            assert!(perf.deterministic);
+            assert_ne!(
+                perf.algo,
+                cudnn::sys::cudnnConvolutionBwdFilterAlgo_t::CUDNN_CONVOLUTION_BWD_FILTER_ALGO_WINOGRAD_NONFUSED
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_determinism_policy_select() {
+        use cudnn::sys::cudnnConvolutionBwdFilterAlgo_t as Algo;
+        let perf = |algo, deterministic| PerfResult {
+            algo,
+            time_ms: -1.0,
+            workspace_size: 0,
+            math_type: cudnn::sys::cudnnMathType_t::CUDNN_DEFAULT_MATH,
+            deterministic,
+        };
+        let perfs = [
+            perf(Algo::CUDNN_CONVOLUTION_BWD_FILTER_ALGO_0, false),
+            perf(
+                Algo::CUDNN_CONVOLUTION_BWD_FILTER_ALGO_WINOGRAD_NONFUSED,
+                true,
+            ),
+            perf(Algo::CUDNN_CONVOLUTION_BWD_FILTER_ALGO_1, true),
+        ];
+        assert_eq!(DeterminismPolicy::Any.select(&perfs), Ok(perfs[0]));
+        assert_eq!(DeterminismPolicy::Prefer.select(&perfs), Ok(perfs[2]));
+        assert_eq!(DeterminismPolicy::Require.select(&perfs), Ok(perfs[2]));
+
+        let perfs = &perfs[..2];
+        assert_eq!(DeterminismPolicy::Prefer.select(perfs), Ok(perfs[0]));
+        assert_eq!(
+            DeterminismPolicy::Require.select(perfs),
+            Err(PickAlgorithmError::NoDeterministicAlgorithm {
+                excluded: perfs.to_vec()
+            })
+        );
+        assert_eq!(
+            DeterminismPolicy::Require.select::<Algo>(&[]),
+            Err(PickAlgorithmError::Cudnn(CudnnError(
+                cudnn::sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED
+            )))
+        );
+        assert_eq!(
+            DeterminismPolicy::from(crate::driver::Determinism::Bitwise),
+            DeterminismPolicy::Require
+        );
+
+        let perfs = std::vec![
+            perfs[0],
+            perfs[1],
+            perf(Algo::CUDNN_CONVOLUTION_BWD_FILTER_ALGO_1, true)
+        ];
+        assert_eq!(DeterminismPolicy::Any.filter(perfs.clone()), perfs);
+        assert_eq!(
+            DeterminismPolicy::Prefer.filter(perfs.clone()),
+            [perfs[2], perfs[0], perfs[1]]
+        );
+        assert_eq!(DeterminismPolicy::Require.filter(perfs.clone()), [perfs[2]]);
+    }
+
+    #[test]
+    fn test_alloc_scratch() -> Result<(), CudnnError> {
+        let cudnn = Cudnn::new(CudaDevice::new(0).unwrap())?;
+        assert!(cudnn.alloc_scratch(0)?.is_none());
+        assert!(cudnn.alloc_scratch(16)?.is_some());
+        assert_eq!(
+            cudnn.alloc_scratch(1 << 60).unwrap_err(),
+            CudnnError(cudnn::sys::cudnnStatus_t::CUDNN_STATUS_ALLOC_FAILED)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_algorithm_with_policy() -> Result<(), CudnnError> {
+        let cudnn = Cudnn::new(CudaDevice::new(0).unwrap())?;
+        let conv = cudnn.create_conv2d::<f32>(
+            [1; 2],
+            [1; 2],
+            [1; 2],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        let x = cudnn.create_4d_tensor::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [4, 8, 16, 16],
+        )?;
+        let dw = cudnn.create_4d_filter::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [8, 8, 3, 3],
+        )?;
+        let dy = cudnn.create_4d_tensor::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [4, 8, 16, 16],
+        )?;
+        const LIMIT: usize = 1 << 20;
+        let op = ConvBackwardFilter {
+            conv: &conv,
+            x: &x,
+            dw: &dw,
+            dy: &dy,
+        };
+
+        let all = op.find_algorithm_with(LIMIT, DeterminismPolicy::Any)?;
+        assert_eq!(all.len(), op.find_algorithm(LIMIT)?.len());
+        let deterministic = op.find_algorithm_with(LIMIT, DeterminismPolicy::Require)?;
+        assert!(!deterministic.is_empty());
+        assert!(deterministic.iter().all(|p| p.deterministic));
+        let preferred = op.find_algorithm_with(LIMIT, DeterminismPolicy::Prefer)?;
+        assert!(preferred[0].deterministic);
+
+        // every policy has its own entry
+        let cache = AlgoCache::new();
+        let any = op.find_algorithm_cached(LIMIT, &cache)?;
+        let required = op
+            .find_algorithm_cached_with(LIMIT, &cache, DeterminismPolicy::Require)
+            .unwrap();
+        assert!(required.deterministic);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(op.find_algorithm_cached(LIMIT, &cache)?, any);
+        assert_eq!(
+            op.find_algorithm_cached_with(LIMIT, &cache, DeterminismPolicy::Require),
+            Ok(required)
+        );
+        assert_eq!(cache.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conv2d_find_algorithms() -> Result<(), CudnnError> {
+        let cudnn = Cudnn::new(CudaDevice::new(0).unwrap())?;
+
+        let conv = cudnn.create_conv2d::<f32>(
+            [1; 2],
+            [1; 2],
+            [1; 2],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        let x = cudnn.create_4d_tensor::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [8, 16, 32, 32],
+        )?;
+        let filter = cudnn.create_4d_filter::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [32, 16, 3, 3],
+        )?;
+        let y = cudnn.create_4d_tensor::<f32>(
+            cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW,
+            [8, 32, 32, 32],
+        )?;
+        const LIMIT: usize = 1 << 20;
+
+        let op = ConvForward {
+            conv: &conv,
+            x: &x,
+            w: &filter,
+            y: &y,
+        };
+        let perfs = op.find_algorithm(LIMIT)?;
+        assert!(!perfs.is_empty());
+        assert!(perfs.iter().all(|p| p.workspace_size <= LIMIT));
+        assert!(perfs.windows(2).all(|w| w[0].time_ms <= w[1].time_ms));
+        assert!(op.find_algorithm(0)?.iter().all(|p| p.workspace_size == 0));
+
+        let cache = AlgoCache::new();
+        let best = op.find_algorithm_cached(LIMIT, &cache)?;
+        assert_eq!(cache.len(), 1);
+        assert_eq!(op.find_algorithm_cached(LIMIT, &cache)?, best);
+        assert_eq!(cache.len(), 1);
+        op.find_algorithm_cached(0, &cache)?;
+        assert_eq!(cache.len(), 2);
+
+        let op = ConvBackwardData {
+            conv: &conv,
+            dx: &x,
+            w: &filter,
+            dy: &y,
+        };
+        assert!(!op.find_algorithm(LIMIT)?.is_empty());
+        op.find_algorithm_cached(LIMIT, &cache)?;
+        assert_eq!(cache.len(), 3);
+
+        let op = ConvBackwardFilter {
+            conv: &conv,
+            x: &x,
+            dw: &filter,
+            dy: &y,
+        };
+        assert!(!op.find_algorithm(LIMIT)?.is_empty());
+        op.find_algorithm_cached(LIMIT, &cache)?;
+        assert_eq!(cache.len(), 4);
+
+        let global = AlgoCache::global();
+        global.extend(&cache);
+        assert!(global.len() >= 4);
+
+        #[cfg(feature = "std")]
+        {
+            let path =
+                std::env::temp_dir().join(std::format!("cudarc-algo-cache-{}", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            cache.save(&path).unwrap();
+            let loaded = AlgoCache::load(&path).unwrap();
+            assert_eq!(loaded.len(), 4);
+            assert_eq!(
+                op.find_algorithm_cached(LIMIT, &loaded)?,
+                op.find_algorithm_cached(LIMIT, &cache)?
+            );
+            assert_eq!(loaded.len(), 4);
+
+            // saving keeps the entries of other processes
+            let other = AlgoCache::new();
+            op.find_algorithm_cached(0, &other)?;
+            other.save(&path).unwrap();
+            assert_eq!(AlgoCache::load(&path).unwrap().len(), 5);
+
+            let report = AlgoCache::verify(&path).unwrap();
+            assert_eq!((report.valid, report.corrupt), (1, 0));
+            let bytes = std::fs::read(&path).unwrap();
+            std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+            let err = AlgoCache::load(&path).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            let report = AlgoCache::verify(&path).unwrap();
+            assert_eq!((report.valid, report.corrupt), (0, 1));
+            assert!(!path.exists());
+
+            cache.save(&path).unwrap();
+            assert!(AlgoCache::purge(&path).unwrap());
+            assert!(!AlgoCache::purge(&path).unwrap());
+            assert!(!path.exists());
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_conv1d() -> Result<(), CudnnError> {
         let dev = CudaDevice::new(0).unwrap();
@@ -251,6 +619,369 @@ mod tests {
         Ok(())
     }
 
+    /// A stride 1 cross-correlation of a `[n, c, d, h, w]` input with `[k, c / groups, kd, kh,
+    /// kw]` filters, zero-padded by `pad`.
+    fn conv3d_ref(
+        x: &[f32],
+        [n, c, d, h, w]: [usize; 5],
+        f: &[f32],
+        [k, cg, kd, kh, kw]: [usize; 5],
+        pad: [usize; 3],
+        groups: usize,
+    ) -> (Vec<f32>, [usize; 5]) {
+        let [od, oh, ow] = [
+            d + 2 * pad[0] + 1 - kd,
+            h + 2 * pad[1] + 1 - kh,
+            w + 2 * pad[2] + 1 - kw,
+        ];
+        let mut y = vec![0.0; n * k * od * oh * ow];
+        let kg = k / groups;
+        for (i, out) in y.iter_mut().enumerate() {
+            let [b, o, z, r, q] = [
+                i / (k * od * oh * ow),
+                i / (od * oh * ow) % k,
+                i / (oh * ow) % od,
+                i / ow % oh,
+                i % ow,
+            ];
+            for ci in 0..cg {
+                let ch = o / kg * cg + ci;
+                for (dz, dr, dq) in (0..kd)
+                    .flat_map(|dz| (0..kh).flat_map(move |dr| (0..kw).map(move |dq| (dz, dr, dq))))
+                {
+                    let (iz, ir, iq) = (z + dz, r + dr, q + dq);
+                    if iz < pad[0] || ir < pad[1] || iq < pad[2] {
+                        continue;
+                    }
+                    let (iz, ir, iq) = (iz - pad[0], ir - pad[1], iq - pad[2]);
+                    if iz >= d || ir >= h || iq >= w {
+                        continue;
+                    }
+                    let xv = x[(((b * c + ch) * d + iz) * h + ir) * w + iq];
+                    let fv = f[(((o * cg + ci) * kd + dz) * kh + dr) * kw + dq];
+                    *out += xv * fv;
+                }
+            }
+        }
+        (y, [n, k, od, oh, ow])
+    }
+
+    #[test]
+    fn test_depthwise_conv2d() -> Result<(), ConvError> {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone())?;
+        let nchw = cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW;
+
+        let mut conv = cudnn.create_conv2d::<f32>(
+            [1; 2],
+            [1; 2],
+            [1; 2],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        assert_eq!(conv.group_count()?, 1);
+        conv.set_group_count(4)?;
+        assert_eq!(conv.group_count()?, 4);
+
+        // small integers, so that the sums are exact
+        let x_host: Vec<f32> = (0..2 * 4 * 6 * 6).map(|i| (i % 7) as f32 - 3.0).collect();
+        let w_host: Vec<f32> = (0..4 * 3 * 3).map(|i| (i % 5) as f32 - 2.0).collect();
+        let x_desc = cudnn.create_4d_tensor::<f32>(nchw, [2, 4, 6, 6])?;
+        let w_desc = cudnn.create_4d_filter::<f32>(nchw, [4, 1, 3, 3])?;
+        let y_desc = cudnn.create_4d_tensor::<f32>(nchw, [2, 4, 6, 6])?;
+        let op = ConvForward {
+            conv: &conv,
+            x: &x_desc,
+            w: &w_desc,
+            y: &y_desc,
+        };
+        op.validate()?;
+        assert_eq!(op.output_dims()?, [2, 4, 6, 6]);
+
+        let x = dev.htod_copy(x_host.clone()).unwrap();
+        let w = dev.htod_copy(w_host.clone()).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(2 * 4 * 6 * 6).unwrap();
+        let algo = op.pick_algorithm()?;
+        let mut workspace = dev.alloc_zeros::<u8>(op.get_workspace_size(algo)?).unwrap();
+        unsafe { op.launch(algo, Some(&mut workspace), (1.0, 0.0), &x, &w, &mut y) }?;
+        let (expected, _) = conv3d_ref(
+            &x_host,
+            [2, 4, 1, 6, 6],
+            &w_host,
+            [4, 1, 1, 3, 3],
+            [0, 1, 1],
+            4,
+        );
+        assert_eq!(dev.sync_reclaim(y).unwrap(), expected);
+
+        // the filters need 1 channel each with 4 groups
+        let w_desc = cudnn.create_4d_filter::<f32>(nchw, [4, 2, 3, 3])?;
+        let op = ConvForward {
+            conv: &conv,
+            x: &x_desc,
+            w: &w_desc,
+            y: &y_desc,
+        };
+        match op.validate() {
+            Err(ConvError::Groups {
+                input_channels: 4,
+                filter_channels: 2,
+                group_count: 4,
+                ..
+            }) => (),
+            r => panic!("{r:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouped_conv3d() -> Result<(), ConvError> {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone())?;
+        let ncdhw = cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW;
+
+        let mut conv = cudnn.create_convnd::<f32>(
+            &[1, 0, 1],
+            &[1; 3],
+            &[1; 3],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        conv.set_group_count(2)?;
+
+        let x_dims = [1, 4, 5, 6, 7];
+        let w_dims = [4, 2, 3, 3, 3];
+        let x_host: Vec<f32> = (0..4 * 5 * 6 * 7).map(|i| (i % 7) as f32 - 3.0).collect();
+        let w_host: Vec<f32> = (0..4 * 2 * 27).map(|i| (i % 5) as f32 - 2.0).collect();
+        let (expected, y_dims) = conv3d_ref(&x_host, x_dims, &w_host, w_dims, [1, 0, 1], 2);
+        assert_eq!(y_dims, [1, 4, 5, 4, 7]);
+
+        let x_desc = cudnn.create_5d_tensor::<f32>(ncdhw, x_dims.map(|d| d as i32))?;
+        let w_desc = cudnn.create_5d_filter::<f32>(ncdhw, w_dims.map(|d| d as i32))?;
+        let y_desc = cudnn.create_5d_tensor::<f32>(ncdhw, y_dims.map(|d| d as i32))?;
+        let op = ConvForward {
+            conv: &conv,
+            x: &x_desc,
+            w: &w_desc,
+            y: &y_desc,
+        };
+        op.validate()?;
+        assert_eq!(op.output_dims()?, y_dims.map(|d| d as i32));
+
+        let x = dev.htod_copy(x_host).unwrap();
+        let w = dev.htod_copy(w_host).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(expected.len()).unwrap();
+        let algo = op.pick_algorithm()?;
+        let mut workspace = dev.alloc_zeros::<u8>(op.get_workspace_size(algo)?).unwrap();
+        unsafe { op.launch(algo, Some(&mut workspace), (1.0, 0.0), &x, &w, &mut y) }?;
+        assert_eq!(dev.sync_reclaim(y).unwrap(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_conv_bias_activation() -> Result<(), ConvError> {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone())?;
+        let nchw = cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW;
+        let precomp =
+            cudnn::sys::cudnnConvolutionFwdAlgo_t::CUDNN_CONVOLUTION_FWD_ALGO_IMPLICIT_PRECOMP_GEMM;
+
+        let conv = cudnn.create_conv2d::<f32>(
+            [1; 2],
+            [1; 2],
+            [1; 2],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        let relu = cudnn.create_activation::<f32>(
+            cudnn::sys::cudnnActivationMode_t::CUDNN_ACTIVATION_RELU,
+            cudnn::sys::cudnnNanPropagation_t::CUDNN_NOT_PROPAGATE_NAN,
+            0.0,
+        )?;
+        let x_host: Vec<f32> = (0..2 * 3 * 5 * 5).map(|i| (i % 7) as f32 - 3.0).collect();
+        let w_host: Vec<f32> = (0..4 * 3 * 3 * 3).map(|i| (i % 5) as f32 - 2.0).collect();
+        let z_host: Vec<f32> = (0..2 * 4 * 5 * 5).map(|i| (i % 3) as f32).collect();
+        let bias_host = vec![-2.0f32, 0.0, 1.0, 3.0];
+        let x_desc = cudnn.create_4d_tensor::<f32>(nchw, [2, 3, 5, 5])?;
+        let w_desc = cudnn.create_4d_filter::<f32>(nchw, [4, 3, 3, 3])?;
+        let y_desc = cudnn.create_4d_tensor::<f32>(nchw, [2, 4, 5, 5])?;
+        let bias_desc = cudnn.create_4d_tensor::<f32>(nchw, [1, 4, 1, 1])?;
+        let op = ConvBiasActivationForward {
+            conv: &conv,
+            act: &relu,
+            x: &x_desc,
+            w: &w_desc,
+            z: &y_desc,
+            bias: &bias_desc,
+            y: &y_desc,
+        };
+        op.validate(precomp)?;
+
+        let x = dev.htod_copy(x_host.clone()).unwrap();
+        let w = dev.htod_copy(w_host.clone()).unwrap();
+        let z = dev.htod_copy(z_host.clone()).unwrap();
+        let bias = dev.htod_copy(bias_host.clone()).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(2 * 4 * 5 * 5).unwrap();
+        let mut workspace = dev
+            .alloc_zeros::<u8>(op.conv().get_workspace_size(precomp)?.max(1))
+            .unwrap();
+        unsafe {
+            op.launch(
+                precomp,
+                Some(&mut workspace),
+                (1.0, 2.0),
+                &x,
+                &w,
+                Some(&z),
+                &bias,
+                &mut y,
+            )
+        }?;
+        let (conv_ref, _) = conv3d_ref(
+            &x_host,
+            [2, 3, 1, 5, 5],
+            &w_host,
+            [4, 3, 1, 3, 3],
+            [0, 1, 1],
+            1,
+        );
+        let expected: Vec<f32> = conv_ref
+            .iter()
+            .zip(&z_host)
+            .enumerate()
+            .map(|(i, (c, z))| (c + 2.0 * z + bias_host[i / 25 % 4]).max(0.0))
+            .collect();
+        assert!(expected.contains(&0.0));
+        assert_eq!(dev.dtoh_sync_copy(&y).unwrap(), expected);
+
+        // identity needs the implicit precomp gemm algorithm
+        let identity = cudnn.create_activation::<f32>(
+            cudnn::sys::cudnnActivationMode_t::CUDNN_ACTIVATION_IDENTITY,
+            cudnn::sys::cudnnNanPropagation_t::CUDNN_NOT_PROPAGATE_NAN,
+            0.0,
+        )?;
+        let op = ConvBiasActivationForward {
+            act: &identity,
+            ..op
+        };
+        op.validate(precomp)?;
+        let gemm = cudnn::sys::cudnnConvolutionFwdAlgo_t::CUDNN_CONVOLUTION_FWD_ALGO_GEMM;
+        let res = unsafe {
+            op.launch(
+                gemm,
+                Some(&mut workspace),
+                (1.0, 0.0),
+                &x,
+                &w,
+                None::<&CudaSlice<f32>>,
+                &bias,
+                &mut y,
+            )
+        };
+        assert_eq!(
+            res,
+            Err(ConvError::Activation {
+                mode: cudnn::sys::cudnnActivationMode_t::CUDNN_ACTIVATION_IDENTITY,
+                algo: gemm,
+            })
+        );
+
+        let bad_bias = cudnn.create_4d_tensor::<f32>(nchw, [1, 4, 5, 5])?;
+        let op = ConvBiasActivationForward {
+            bias: &bad_bias,
+            ..op
+        };
+        assert_eq!(
+            op.validate_fusion(precomp),
+            Err(ConvError::Bias {
+                output_channels: 4,
+                bias_len: 100
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_int8_conv_layouts() -> Result<(), ConvError> {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone())?;
+
+        let mut conv = cudnn.create_conv2d::<i32>(
+            [0; 2],
+            [1; 2],
+            [1; 2],
+            cudnn::sys::cudnnConvolutionMode_t::CUDNN_CROSS_CORRELATION,
+        )?;
+        conv.set_math_type(cudnn::sys::cudnnMathType_t::CUDNN_FMA_MATH)?;
+        assert_eq!(
+            conv.math_type()?,
+            cudnn::sys::cudnnMathType_t::CUDNN_FMA_MATH
+        );
+
+        let nchw = cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW;
+        let nhwc = cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NHWC;
+        let x_desc = cudnn.create_4d_tensor::<i8>(nchw, [2, 4, 5, 5])?;
+        let w_desc = cudnn.create_4d_filter::<i8>(nchw, [8, 4, 3, 3])?;
+        let y_desc = cudnn.create_4d_tensor::<f32>(nchw, [2, 8, 3, 3])?;
+        let op = ConvForward {
+            conv: &conv,
+            x: &x_desc,
+            w: &w_desc,
+            y: &y_desc,
+        };
+        match op.validate() {
+            Err(ConvError::Unsupported(e)) => {
+                assert_eq!(e.x, (cudnn::sys::cudnnDataType_t::CUDNN_DATA_INT8, nchw));
+                assert_eq!(e.compute, cudnn::sys::cudnnDataType_t::CUDNN_DATA_INT32);
+                assert!(e.supported.iter().any(|c| c.formats == [nhwc]));
+            }
+            r => panic!("{r:?}"),
+        }
+
+        let x_desc = cudnn.create_4d_tensor::<i8>(nhwc, [2, 4, 5, 5])?;
+        let w_desc = cudnn.create_4d_filter::<i8>(nhwc, [8, 4, 3, 3])?;
+        let y_desc = cudnn.create_4d_tensor::<f32>(nhwc, [2, 8, 3, 3])?;
+        let op = ConvForward {
+            conv: &conv,
+            x: &x_desc,
+            w: &w_desc,
+            y: &y_desc,
+        };
+        op.validate()?;
+
+        let x = dev.htod_copy(vec![1i8; 2 * 4 * 5 * 5]).unwrap();
+        let w = dev.htod_copy(vec![2i8; 8 * 4 * 3 * 3]).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(2 * 8 * 3 * 3).unwrap();
+        let algo = op.pick_algorithm()?;
+        let mut workspace = dev.alloc_zeros::<u8>(op.get_workspace_size(algo)?).unwrap();
+        unsafe { op.launch(algo, Some(&mut workspace), (1.0, 0.0), &x, &w, &mut y) }?;
+        let y = dev.sync_reclaim(y).unwrap();
+        assert!(y.iter().all(|&v| v == 4.0 * 9.0 * 2.0));
+
+        // i8 in, i8 out
+        let y_desc = cudnn.create_4d_tensor::<i8>(nhwc, [2, 8, 3, 3])?;
+        let op = ConvForward {
+            conv: &conv,
+            x: &x_desc,
+            w: &w_desc,
+            y: &y_desc,
+        };
+        op.validate()?;
+        let mut y = dev.alloc_zeros::<i8>(2 * 8 * 3 * 3).unwrap();
+        let algo = op.pick_algorithm()?;
+        let mut workspace = dev.alloc_zeros::<u8>(op.get_workspace_size(algo)?).unwrap();
+        unsafe { op.launch(algo, Some(&mut workspace), (1, 0), &x, &w, &mut y) }?;
+        let y = dev.sync_reclaim(y).unwrap();
+        assert!(y.iter().all(|&v| v == 4 * 9 * 2));
+
+        // no int8 backward convolutions
+        let op = ConvBackwardData {
+            conv: &conv,
+            dx: &x_desc,
+            w: &w_desc,
+            dy: &y_desc,
+        };
+        assert!(matches!(op.validate(), Err(ConvError::Unsupported(_))));
+        Ok(())
+    }
+
     #[test]
     fn test_reduction() {
         let dev = CudaDevice::new(0).unwrap();
@@ -288,4 +1019,365 @@ mod tests {
         assert_eq!(c_host.len(), 1);
         assert_eq!(c_host[0], 21.0);
     }
+
+    #[test]
+    fn test_lrn_forward() {
+        let dev = CudaDevice::new(0).unwrap();
+        let x = dev.htod_copy(std::vec![1.0f32, 2.0, 3.0]).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(3).unwrap();
+
+        let cudnn = Cudnn::new(dev.clone()).unwrap();
+        let lrn = cudnn.create_lrn::<f32>(1, 1.0, 1.0, 1.0).unwrap();
+        let desc = cudnn
+            .create_4d_tensor_ex::<f32>([1, 3, 1, 1], [3, 1, 1, 1])
+            .unwrap();
+        let op = LrnForward {
+            lrn: &lrn,
+            x: &desc,
+            y: &desc,
+        };
+        unsafe { op.launch((1.0, 0.0), &x, &mut y) }.unwrap();
+
+        // with n = 1 each channel is x / (1 + x^2)
+        let y_host = dev.sync_reclaim(y).unwrap();
+        for (y, x) in y_host.iter().zip([1.0f32, 2.0, 3.0]) {
+            assert!((y - x / (1.0 + x * x)).abs() < 1e-5, "{y}");
+        }
+    }
+
+    #[test]
+    fn test_normalization_inference() {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone()).unwrap();
+        if !cudnn::result::is_normalization_supported() {
+            return;
+        }
+
+        let x = dev.htod_copy(std::vec![1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        let scale = dev.htod_copy(std::vec![1.0f32, 2.0]).unwrap();
+        let bias = dev.htod_copy(std::vec![0.0f32, 1.0]).unwrap();
+        let mean = dev.htod_copy(std::vec![1.0f32, 3.0]).unwrap();
+        let var = dev.htod_copy(std::vec![1.0f32, 4.0]).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(4).unwrap();
+
+        let x_desc = cudnn
+            .create_4d_tensor_ex::<f32>([1, 2, 2, 1], [4, 2, 1, 1])
+            .unwrap();
+        let param_desc = cudnn
+            .create_4d_tensor_ex::<f32>([1, 2, 1, 1], [2, 1, 1, 1])
+            .unwrap();
+        let op = NormalizationForward {
+            mode: cudnn::sys::cudnnNormMode_t::CUDNN_NORM_PER_CHANNEL,
+            algo: cudnn::sys::cudnnNormAlgo_t::CUDNN_NORM_ALGO_STANDARD,
+            x: &x_desc,
+            y: &x_desc,
+            scale_bias: &param_desc,
+            mean_var: &param_desc,
+            group_count: 1,
+        };
+        unsafe { op.launch_inference((1.0, 0.0), &x, &scale, &bias, &mean, &var, 1e-5, &mut y) }
+            .unwrap();
+        let y_host = dev.sync_reclaim(y).unwrap();
+        let expected = [0.0f32, 1.0, 1.0, 2.0];
+        for (y, e) in y_host.iter().zip(expected) {
+            assert!((y - e).abs() < 1e-4, "{y_host:?}");
+        }
+
+        // per channel parameters must be 1xCx1x1
+        let bad_desc = cudnn
+            .create_4d_tensor_ex::<f32>([1, 2, 2, 1], [4, 2, 1, 1])
+            .unwrap();
+        let op = NormalizationForward {
+            scale_bias: &bad_desc,
+            ..op
+        };
+        assert_eq!(
+            op.validate(),
+            Err(CudnnError(
+                cudnn::sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM
+            ))
+        );
+    }
+
+    /// `-log p(label | x)` with the forward algorithm over the label interleaved with blanks,
+    /// where `log_probs` is `[time][alphabet]`.
+    fn ctc_loss_reference(log_probs: &[[f32; 3]], label: &[i32]) -> f32 {
+        let log_add = |a: f32, b: f32| {
+            let m = a.max(b);
+            if m == f32::NEG_INFINITY {
+                m
+            } else {
+                m + ((a - m).exp() + (b - m).exp()).ln()
+            }
+        };
+        let mut ext = std::vec![0];
+        for &l in label {
+            ext.extend([l as usize, 0]);
+        }
+        let mut alpha = std::vec![f32::NEG_INFINITY; ext.len()];
+        alpha[0] = log_probs[0][0];
+        alpha[1] = log_probs[0][ext[1]];
+        for lp in &log_probs[1..] {
+            let prev = alpha.clone();
+            for s in 0..ext.len() {
+                let mut a = prev[s];
+                if s >= 1 {
+                    a = log_add(a, prev[s - 1]);
+                }
+                if s >= 2 && ext[s] != 0 && ext[s] != ext[s - 2] {
+                    a = log_add(a, prev[s - 2]);
+                }
+                alpha[s] = a + lp[ext[s]];
+            }
+        }
+        -log_add(alpha[ext.len() - 1], alpha[ext.len() - 2])
+    }
+
+    #[test]
+    fn test_ctc_loss() {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone()).unwrap();
+
+        // [time][batch][alphabet], label 0 is the blank
+        let logits: [[[f32; 3]; 2]; 3] = [
+            [[0.5, 1.0, -0.5], [0.1, 0.7, 0.2]],
+            [[1.5, -1.0, 0.3], [0.9, 0.0, -0.4]],
+            [[-0.2, 0.4, 1.1], [0.3, 1.2, 0.6]],
+        ];
+        let log_softmax = |x: [f32; 3]| {
+            let lse = x.iter().map(|v| v.exp()).sum::<f32>().ln();
+            x.map(|v| v - lse)
+        };
+        let log_probs = |n: usize| -> std::vec::Vec<[f32; 3]> {
+            logits.iter().map(|t| log_softmax(t[n])).collect()
+        };
+        let labels = [1, 2, 1, 1];
+        let label_lengths = [2, 2];
+        let input_lengths = [3, 3];
+        let expected = [
+            ctc_loss_reference(&log_probs(0), &[1, 2]),
+            // "1 1" in 3 timesteps has the single alignment 1, blank, 1
+            -(log_probs(1)[0][1] + log_probs(1)[1][0] + log_probs(1)[2][1]),
+        ];
+        assert!((ctc_loss_reference(&log_probs(1), &[1, 1]) - expected[1]).abs() < 1e-5);
+
+        let ctc = cudnn
+            .create_ctc_loss::<f32>(
+                cudnn::sys::cudnnLossNormalizationMode_t::CUDNN_LOSS_NORMALIZATION_SOFTMAX,
+                cudnn::sys::cudnnNanPropagation_t::CUDNN_NOT_PROPAGATE_NAN,
+                4,
+            )
+            .unwrap();
+        let probs_desc = cudnn.create_ctc_probs_tensor::<f32>([3, 2, 3]).unwrap();
+        let op = CtcLoss {
+            ctc: &ctc,
+            probs: &probs_desc,
+        };
+        let algo = cudnn::sys::cudnnCTCLossAlgo_t::CUDNN_CTC_LOSS_ALGO_DETERMINISTIC;
+
+        let probs = dev
+            .htod_copy(logits.iter().flatten().flatten().copied().collect())
+            .unwrap();
+        let mut costs = dev.alloc_zeros::<f32>(2).unwrap();
+        let mut grads = dev.alloc_zeros::<f32>(18).unwrap();
+        let workspace_size = op
+            .get_workspace_size(algo, &labels, &label_lengths, &input_lengths)
+            .unwrap();
+        let mut workspace = dev.alloc_zeros::<u8>(workspace_size.max(1)).unwrap();
+        unsafe {
+            op.launch_with_gradients(
+                algo,
+                &mut workspace,
+                &probs,
+                &labels,
+                &label_lengths,
+                &input_lengths,
+                &mut costs,
+                &mut grads,
+            )
+        }
+        .unwrap();
+        let costs_host = dev.dtoh_sync_copy(&costs).unwrap();
+        for (cost, expected) in costs_host.iter().zip(expected) {
+            assert!((cost - expected).abs() < 1e-4, "{cost} != {expected}");
+        }
+        // softmax minus the alignment posteriors, which both sum to 1 over the alphabet
+        let grads_host = dev.dtoh_sync_copy(&grads).unwrap();
+        for g in grads_host.chunks(3) {
+            assert!(g.iter().sum::<f32>().abs() < 1e-4, "{g:?}");
+        }
+
+        let mut costs = dev.alloc_zeros::<f32>(2).unwrap();
+        unsafe {
+            op.launch(
+                algo,
+                &mut workspace,
+                &probs,
+                &labels,
+                &label_lengths,
+                &input_lengths,
+                &mut costs,
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&costs).unwrap(), costs_host);
+
+        // on another stream, the device copies of the labels are freed after the loss
+        let stream = dev.fork_default_stream().unwrap();
+        unsafe { cudnn.set_stream(Some(&stream)) }.unwrap();
+        let mut costs = dev.alloc_zeros::<f32>(2).unwrap();
+        stream.wait_for_default().unwrap();
+        unsafe {
+            op.launch(
+                algo,
+                &mut workspace,
+                &probs,
+                &labels,
+                &label_lengths,
+                &input_lengths,
+                &mut costs,
+            )
+        }
+        .unwrap();
+        dev.wait_for(&stream).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&costs).unwrap(), costs_host);
+        unsafe { cudnn.set_stream(None) }.unwrap();
+
+        assert_eq!(
+            op.validate(&[1, 3, 1, 1], &label_lengths, &input_lengths),
+            Err(CtcError::InvalidLabel {
+                index: 1,
+                label: 3,
+                alphabet_size: 3
+            })
+        );
+        assert_eq!(
+            op.validate(&labels, &label_lengths, &[3, 4]),
+            Err(CtcError::InvalidInputLength {
+                batch: 1,
+                length: 4,
+                max_time: 3
+            })
+        );
+        assert_eq!(
+            op.validate(&[1; 6], &[1, 5], &input_lengths),
+            Err(CtcError::LabelTooLong {
+                batch: 1,
+                length: 5,
+                max: 4
+            })
+        );
+        assert_eq!(
+            op.validate(&labels[..3], &label_lengths, &input_lengths),
+            Err(CtcError::LabelsMismatch {
+                expected: 4,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_spatial_transformer() {
+        let dev = CudaDevice::new(0).unwrap();
+        let cudnn = Cudnn::new(dev.clone()).unwrap();
+        let (in_h, in_w, out_h, out_w) = (4, 5, 3, 4);
+        let x_host: std::vec::Vec<f32> = (0..in_h * in_w).map(|i| (i * 7 % 11) as f32).collect();
+        let theta_host = [0.8f32, 0.1, 0.05, -0.1, 0.9, -0.1];
+
+        let st = cudnn
+            .create_spatial_transformer::<f32>([1, 1, out_h as i32, out_w as i32])
+            .unwrap();
+        let theta = dev.htod_copy(theta_host.to_vec()).unwrap();
+        let mut grid = dev.alloc_zeros::<f32>(st.grid_len()).unwrap();
+        st.generate_grid(&theta, &mut grid).unwrap();
+        let mut short = dev.alloc_zeros::<f32>(st.grid_len() - 1).unwrap();
+        assert_eq!(
+            st.generate_grid(&theta, &mut short),
+            Err(CudnnError(
+                cudnn::sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM
+            ))
+        );
+
+        // the affine grid, and bilinear sampling with -1 and 1 at the corner pixels
+        let linspace = |i: usize, n: usize| -1.0 + 2.0 * i as f32 / (n - 1) as f32;
+        let sample = |img: &[f32], gx: f32, gy: f32| {
+            let px = (gx + 1.0) / 2.0 * (in_w - 1) as f32;
+            let py = (gy + 1.0) / 2.0 * (in_h - 1) as f32;
+            let (x0, y0) = (px.floor(), py.floor());
+            let mut acc = 0.0;
+            for (xi, wx) in [(x0, 1.0 - (px - x0)), (x0 + 1.0, px - x0)] {
+                for (yi, wy) in [(y0, 1.0 - (py - y0)), (y0 + 1.0, py - y0)] {
+                    if xi >= 0.0 && yi >= 0.0 && xi < in_w as f32 && yi < in_h as f32 {
+                        acc += wx * wy * img[yi as usize * in_w + xi as usize];
+                    }
+                }
+            }
+            acc
+        };
+        let mut grid_expected = std::vec::Vec::new();
+        let mut y_expected = std::vec::Vec::new();
+        let mut weight_sum = 0.0;
+        let ones = std::vec![1.0; in_h * in_w];
+        for i in 0..out_h {
+            for j in 0..out_w {
+                let (xt, yt) = (linspace(j, out_w), linspace(i, out_h));
+                let gx = theta_host[0] * xt + theta_host[1] * yt + theta_host[2];
+                let gy = theta_host[3] * xt + theta_host[4] * yt + theta_host[5];
+                grid_expected.extend([gx, gy]);
+                y_expected.push(sample(&x_host, gx, gy));
+                weight_sum += sample(&ones, gx, gy);
+            }
+        }
+        let grid_host = dev.dtoh_sync_copy(&grid).unwrap();
+        for (g, e) in grid_host.iter().zip(grid_expected.iter()) {
+            assert!((g - e).abs() < 1e-5, "{grid_host:?}");
+        }
+
+        let x = dev.htod_copy(x_host.clone()).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(out_h * out_w).unwrap();
+        let nchw = cudnn::sys::cudnnTensorFormat_t::CUDNN_TENSOR_NCHW;
+        let x_desc = cudnn
+            .create_4d_tensor::<f32>(nchw, [1, 1, in_h as i32, in_w as i32])
+            .unwrap();
+        let y_desc = cudnn
+            .create_4d_tensor::<f32>(nchw, [1, 1, out_h as i32, out_w as i32])
+            .unwrap();
+        let op = SpatialTfSamplerForward {
+            st: &st,
+            x: &x_desc,
+            y: &y_desc,
+        };
+        unsafe { op.launch((1.0, 0.0), &x, &grid, &mut y) }.unwrap();
+        let y_host = dev.sync_reclaim(y).unwrap();
+        for (y, e) in y_host.iter().zip(y_expected.iter()) {
+            assert!((y - e).abs() < 1e-4, "{y_host:?} vs {y_expected:?}");
+        }
+
+        let dy = dev.htod_copy(std::vec![1.0f32; out_h * out_w]).unwrap();
+        let mut dx = dev.alloc_zeros::<f32>(in_h * in_w).unwrap();
+        let mut dgrid = dev.alloc_zeros::<f32>(st.grid_len()).unwrap();
+        let op = SpatialTfSamplerBackward {
+            st: &st,
+            x: &x_desc,
+            dx: &x_desc,
+            dy: &y_desc,
+        };
+        unsafe { op.launch((1.0, 0.0), (1.0, 0.0), &x, &mut dx, &dy, &grid, &mut dgrid) }.unwrap();
+        // every output pixel spreads its weights over the pixels it reads within the input
+        let dx_sum: f32 = dev.dtoh_sync_copy(&dx).unwrap().iter().sum();
+        assert!(
+            (dx_sum - weight_sum).abs() < 1e-3,
+            "{dx_sum} vs {weight_sum}"
+        );
+
+        // for a gradient of 1 at every point, dtheta is the sum of (x, y, 1) over the grid
+        let ones = dev.htod_copy(std::vec![1.0f32; st.grid_len()]).unwrap();
+        let mut dtheta = dev.alloc_zeros::<f32>(st.theta_len()).unwrap();
+        st.generate_grid_backward(&ones, &mut dtheta).unwrap();
+        let dtheta = dev.sync_reclaim(dtheta).unwrap();
+        let n = (out_h * out_w) as f32;
+        for (d, e) in dtheta.iter().zip([0.0, 0.0, n, 0.0, 0.0, n]) {
+            assert!((d - e).abs() < 1e-4, "{dtheta:?}");
+        }
+    }
 }
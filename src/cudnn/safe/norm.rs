@@ -0,0 +1,533 @@
+use super::core::*;
+use crate::{
+    cudnn::{result, result::CudnnError, sys},
+    driver::{DevicePtr, DevicePtrMut},
+};
+
+use std::{marker::PhantomData, sync::Arc};
+
+/// A local response normalization descriptor. Create with [`Cudnn::create_lrn()`].
+#[derive(Debug)]
+pub struct LrnDescriptor<T> {
+    pub(crate) desc: sys::cudnnLRNDescriptor_t,
+    #[allow(unused)]
+    pub(crate) handle: Arc<Cudnn>,
+    pub(crate) marker: PhantomData<T>,
+}
+
+impl Cudnn {
+    /// Creates an LRN descriptor computing `x / (k + alpha / n * sum(x^2))^beta`, where the sum
+    /// is over a window of `n` neighbouring channels.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetLRNDescriptor)
+    /// for the allowed ranges of the parameters.
+    pub fn create_lrn<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        n: u32,
+        alpha: f64,
+        beta: f64,
+        k: f64,
+    ) -> Result<LrnDescriptor<T>, CudnnError> {
+        let desc = result::create_lrn_descriptor()?;
+        let desc = LrnDescriptor {
+            desc,
+            handle: self.clone(),
+            marker: PhantomData,
+        };
+        unsafe { result::set_lrn_descriptor(desc.desc, n, alpha, beta, k) }?;
+        Ok(desc)
+    }
+}
+
+impl<T> Drop for LrnDescriptor<T> {
+    fn drop(&mut self) {
+        let desc = std::mem::replace(&mut self.desc, std::ptr::null_mut());
+        if !desc.is_null() {
+            unsafe { result::destroy_lrn_descriptor(desc) }.unwrap()
+        }
+    }
+}
+
+/// The cross channel LRN forward operation. Pass in fields directly, and then call launch.
+pub struct LrnForward<'a, T: CudnnDataType> {
+    /// The LRN descriptor.
+    pub lrn: &'a LrnDescriptor<T>,
+    /// The input tensor.
+    pub x: &'a TensorDescriptor<T>,
+    /// The output tensor.
+    pub y: &'a TensorDescriptor<T>,
+}
+
+impl<'a, T: CudnnDataType> LrnForward<'a, T> {
+    /// Launches the operation.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`.
+    pub unsafe fn launch<X, Y>(
+        &self,
+        (alpha, beta): (T, T),
+        x: &X,
+        y: &mut Y,
+    ) -> Result<(), CudnnError>
+    where
+        X: DevicePtr<T>,
+        Y: DevicePtrMut<T>,
+    {
+        let alpha = alpha.into_scaling_parameter();
+        let beta = beta.into_scaling_parameter();
+        result::lrn_cross_channel_forward(
+            self.lrn.handle.handle,
+            self.lrn.desc,
+            sys::cudnnLRNMode_t::CUDNN_LRN_CROSS_CHANNEL_DIM1,
+            (&alpha) as *const T::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            (&beta) as *const T::Scalar as *const std::ffi::c_void,
+            self.y.desc,
+            *y.device_ptr_mut() as *mut _,
+        )
+    }
+}
+
+/// The cross channel LRN backward operation. Pass in fields directly, and then call launch.
+pub struct LrnBackward<'a, T: CudnnDataType> {
+    /// The LRN descriptor.
+    pub lrn: &'a LrnDescriptor<T>,
+    /// The output of the forward pass.
+    pub y: &'a TensorDescriptor<T>,
+    /// The gradient of `y`.
+    pub dy: &'a TensorDescriptor<T>,
+    /// The input of the forward pass.
+    pub x: &'a TensorDescriptor<T>,
+    /// The gradient of `x`.
+    pub dx: &'a TensorDescriptor<T>,
+}
+
+impl<'a, T: CudnnDataType> LrnBackward<'a, T> {
+    /// Launches the operation.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`.
+    pub unsafe fn launch<Y, Dy, X, Dx>(
+        &self,
+        (alpha, beta): (T, T),
+        y: &Y,
+        dy: &Dy,
+        x: &X,
+        dx: &mut Dx,
+    ) -> Result<(), CudnnError>
+    where
+        Y: DevicePtr<T>,
+        Dy: DevicePtr<T>,
+        X: DevicePtr<T>,
+        Dx: DevicePtrMut<T>,
+    {
+        let alpha = alpha.into_scaling_parameter();
+        let beta = beta.into_scaling_parameter();
+        result::lrn_cross_channel_backward(
+            self.lrn.handle.handle,
+            self.lrn.desc,
+            sys::cudnnLRNMode_t::CUDNN_LRN_CROSS_CHANNEL_DIM1,
+            (&alpha) as *const T::Scalar as *const std::ffi::c_void,
+            self.y.desc,
+            *y.device_ptr() as *const _,
+            self.dy.desc,
+            *dy.device_ptr() as *const _,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            (&beta) as *const T::Scalar as *const std::ffi::c_void,
+            self.dx.desc,
+            *dx.device_ptr_mut() as *mut _,
+        )
+    }
+}
+
+/// A pair of mean & variance buffers, laid out as described by the `mean_var` descriptor
+/// of [NormalizationForward] or [NormalizationBackward].
+pub struct MeanVar<'a, P> {
+    /// The mean.
+    pub mean: &'a mut dyn DevicePtrMut<P>,
+    /// The variance, or the inverse variance for the saved statistics.
+    pub var: &'a mut dyn DevicePtrMut<P>,
+}
+
+/// Returns [sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED] if the `cudnnNormalization*` api is
+/// not available, and [sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM] if a param doesn't have the
+/// shape `mode` requires for a per-channel/per-activation parameter of `x`.
+///
+/// - [sys::cudnnNormMode_t::CUDNN_NORM_PER_CHANNEL] requires `1xCx1x1`
+/// - [sys::cudnnNormMode_t::CUDNN_NORM_PER_ACTIVATION] requires `1xCxHxW`
+fn check_norm_params<T, P>(
+    mode: sys::cudnnNormMode_t,
+    x: &TensorDescriptor<T>,
+    params: &[&TensorDescriptor<P>],
+) -> Result<(), CudnnError> {
+    if !result::is_normalization_supported() {
+        return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_NOT_SUPPORTED));
+    }
+    let (_, x_dims, _) = unsafe { result::get_tensornd_descriptor(x.desc) }?;
+    for param in params {
+        let (_, dims, _) = unsafe { result::get_tensornd_descriptor(param.desc) }?;
+        let valid = dims.len() == x_dims.len()
+            && dims[0] == 1
+            && dims[1] == x_dims[1]
+            && dims[2..]
+                .iter()
+                .zip(&x_dims[2..])
+                .all(|(&d, &x)| match mode {
+                    sys::cudnnNormMode_t::CUDNN_NORM_PER_CHANNEL => d == 1,
+                    sys::cudnnNormMode_t::CUDNN_NORM_PER_ACTIVATION => d == x,
+                });
+        if !valid {
+            return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM));
+        }
+    }
+    Ok(())
+}
+
+/// The forward normalization operation (`cudnnNormalizationForward*`), which needs cuDNN >= 8.0.
+/// Pass in fields directly, and then call [NormalizationForward::launch_inference()] or
+/// [NormalizationForward::launch_training()].
+///
+/// `P` is the type of the scale/bias & mean/var parameters, which is `f32` for f16/bf16 data
+/// and otherwise the same as `T`.
+///
+/// With [sys::cudnnNormMode_t::CUDNN_NORM_PER_CHANNEL] this is batch normalization. Instance
+/// normalization is batch normalization of `x` viewed as `1 x (N*C) x H x W`, and group
+/// normalization of `x` viewed as `1 x (N*G) x (C/G) x (H*W)`. In both cases the parameter
+/// descriptors must match the view, so a per-channel affine transform of the original `x`
+/// is applied separately.
+pub struct NormalizationForward<'a, T: CudnnDataType, P: CudnnDataType> {
+    /// Whether the parameters are per channel or per activation.
+    pub mode: sys::cudnnNormMode_t,
+    /// The algorithm to use.
+    pub algo: sys::cudnnNormAlgo_t,
+    /// The input tensor.
+    pub x: &'a TensorDescriptor<T>,
+    /// The output tensor.
+    pub y: &'a TensorDescriptor<T>,
+    /// Describes both the scale & the bias.
+    pub scale_bias: &'a TensorDescriptor<P>,
+    /// Describes the running and saved mean & variance.
+    pub mean_var: &'a TensorDescriptor<P>,
+    /// Currently cuDNN only supports `1`.
+    pub group_count: std::ffi::c_int,
+}
+
+impl<'a, T: CudnnDataType, P: CudnnDataType> NormalizationForward<'a, T, P> {
+    /// Checks the normalization api is available and the shapes of [Self::scale_bias] and [Self::mean_var].
+    pub fn validate(&self) -> Result<(), CudnnError> {
+        check_norm_params(self.mode, self.x, &[self.scale_bias, self.mean_var])
+    }
+
+    /// Gets the size of the workspace for [NormalizationForward::launch_training()].
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetNormalizationForwardTrainingWorkspaceSize)
+    pub fn get_workspace_size(&self) -> Result<usize, CudnnError> {
+        self.validate()?;
+        unsafe {
+            result::get_normalization_forward_training_workspace_size(
+                self.x.handle.handle,
+                self.mode,
+                sys::cudnnNormOps_t::CUDNN_NORM_OPS_NORM,
+                self.algo,
+                self.x.desc,
+                std::ptr::null_mut(),
+                self.y.desc,
+                self.scale_bias.desc,
+                std::ptr::null_mut(),
+                self.mean_var.desc,
+                self.group_count,
+            )
+        }
+    }
+
+    /// Gets the size of the reserve space, which [NormalizationForward::launch_training()] fills
+    /// and [NormalizationBackward::launch()] consumes.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetNormalizationTrainingReserveSpaceSize)
+    pub fn get_reserve_space_size(&self) -> Result<usize, CudnnError> {
+        self.validate()?;
+        unsafe {
+            result::get_normalization_training_reserve_space_size(
+                self.x.handle.handle,
+                self.mode,
+                sys::cudnnNormOps_t::CUDNN_NORM_OPS_NORM,
+                self.algo,
+                std::ptr::null_mut(),
+                self.x.desc,
+                self.group_count,
+            )
+        }
+    }
+
+    /// Normalizes `x` with the estimated `mean` & `var`.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch_inference<X, Y, Scale, Bias, Mean, Var>(
+        &self,
+        (alpha, beta): (T, T),
+        x: &X,
+        scale: &Scale,
+        bias: &Bias,
+        mean: &Mean,
+        var: &Var,
+        epsilon: f64,
+        y: &mut Y,
+    ) -> Result<(), CudnnError>
+    where
+        X: DevicePtr<T>,
+        Y: DevicePtrMut<T>,
+        Scale: DevicePtr<P>,
+        Bias: DevicePtr<P>,
+        Mean: DevicePtr<P>,
+        Var: DevicePtr<P>,
+    {
+        self.validate()?;
+        let alpha = alpha.into_scaling_parameter();
+        let beta = beta.into_scaling_parameter();
+        result::normalization_forward_inference(
+            self.x.handle.handle,
+            self.mode,
+            sys::cudnnNormOps_t::CUDNN_NORM_OPS_NORM,
+            self.algo,
+            (&alpha) as *const T::Scalar as *const std::ffi::c_void,
+            (&beta) as *const T::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            self.scale_bias.desc,
+            *scale.device_ptr() as *const _,
+            *bias.device_ptr() as *const _,
+            self.mean_var.desc,
+            *mean.device_ptr() as *const _,
+            *var.device_ptr() as *const _,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            self.y.desc,
+            *y.device_ptr_mut() as *mut _,
+            epsilon,
+            self.group_count,
+        )
+    }
+
+    /// Normalizes `x` with the statistics of the batch.
+    ///
+    /// - `running` is updated with `exponential_average_factor` if given.
+    /// - `saved` receives the batch mean & inverse variance if given, which speeds up
+    ///   [NormalizationBackward::launch()].
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch_training<Workspace, Reserve, X, Y, Scale, Bias>(
+        &self,
+        workspace: &mut Workspace,
+        reserve: &mut Reserve,
+        (alpha, beta): (T, T),
+        x: &X,
+        scale: &Scale,
+        bias: &Bias,
+        running: Option<(MeanVar<P>, f64)>,
+        saved: Option<MeanVar<P>>,
+        epsilon: f64,
+        y: &mut Y,
+    ) -> Result<(), CudnnError>
+    where
+        Workspace: DevicePtrMut<u8>,
+        Reserve: DevicePtrMut<u8>,
+        X: DevicePtr<T>,
+        Y: DevicePtrMut<T>,
+        Scale: DevicePtr<P>,
+        Bias: DevicePtr<P>,
+    {
+        self.validate()?;
+        let alpha = alpha.into_scaling_parameter();
+        let beta = beta.into_scaling_parameter();
+        let (running_mean, running_var, factor) = match running {
+            Some((mv, factor)) => (
+                *mv.mean.device_ptr_mut() as *mut _,
+                *mv.var.device_ptr_mut() as *mut _,
+                factor,
+            ),
+            None => (std::ptr::null_mut(), std::ptr::null_mut(), 0.0),
+        };
+        let (saved_mean, saved_inv_var) = match saved {
+            Some(mv) => (
+                *mv.mean.device_ptr_mut() as *mut _,
+                *mv.var.device_ptr_mut() as *mut _,
+            ),
+            None => (std::ptr::null_mut(), std::ptr::null_mut()),
+        };
+        result::normalization_forward_training(
+            self.x.handle.handle,
+            self.mode,
+            sys::cudnnNormOps_t::CUDNN_NORM_OPS_NORM,
+            self.algo,
+            (&alpha) as *const T::Scalar as *const std::ffi::c_void,
+            (&beta) as *const T::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            self.scale_bias.desc,
+            *scale.device_ptr() as *const _,
+            *bias.device_ptr() as *const _,
+            factor,
+            self.mean_var.desc,
+            running_mean,
+            running_var,
+            epsilon,
+            saved_mean,
+            saved_inv_var,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            self.y.desc,
+            *y.device_ptr_mut() as *mut _,
+            *workspace.device_ptr_mut() as *mut std::ffi::c_void,
+            workspace.num_bytes(),
+            *reserve.device_ptr_mut() as *mut std::ffi::c_void,
+            reserve.num_bytes(),
+            self.group_count,
+        )
+    }
+}
+
+/// The backward normalization operation (`cudnnNormalizationBackward`), which needs cuDNN >= 8.0.
+/// Pass in fields directly, and then call launch. See [NormalizationForward] for the
+/// meaning of the fields.
+pub struct NormalizationBackward<'a, T: CudnnDataType, P: CudnnDataType> {
+    /// Whether the parameters are per channel or per activation.
+    pub mode: sys::cudnnNormMode_t,
+    /// The algorithm to use.
+    pub algo: sys::cudnnNormAlgo_t,
+    /// The input of the forward pass.
+    pub x: &'a TensorDescriptor<T>,
+    /// The gradient of the output.
+    pub dy: &'a TensorDescriptor<T>,
+    /// The gradient of `x`.
+    pub dx: &'a TensorDescriptor<T>,
+    /// Describes the scale, the bias, and their gradients.
+    pub scale_bias: &'a TensorDescriptor<P>,
+    /// Describes the saved mean & inverse variance.
+    pub mean_var: &'a TensorDescriptor<P>,
+    /// Currently cuDNN only supports `1`.
+    pub group_count: std::ffi::c_int,
+}
+
+impl<'a, T: CudnnDataType, P: CudnnDataType> NormalizationBackward<'a, T, P> {
+    /// Checks the normalization api is available and the shapes of [Self::scale_bias] and [Self::mean_var].
+    pub fn validate(&self) -> Result<(), CudnnError> {
+        check_norm_params(self.mode, self.x, &[self.scale_bias, self.mean_var])
+    }
+
+    /// Gets the size of the workspace for this operation.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnGetNormalizationBackwardWorkspaceSize)
+    pub fn get_workspace_size(&self) -> Result<usize, CudnnError> {
+        self.validate()?;
+        unsafe {
+            result::get_normalization_backward_workspace_size(
+                self.x.handle.handle,
+                self.mode,
+                sys::cudnnNormOps_t::CUDNN_NORM_OPS_NORM,
+                self.algo,
+                self.x.desc,
+                std::ptr::null_mut(),
+                self.dy.desc,
+                std::ptr::null_mut(),
+                self.dx.desc,
+                self.scale_bias.desc,
+                std::ptr::null_mut(),
+                self.mean_var.desc,
+                self.group_count,
+            )
+        }
+    }
+
+    /// Computes the gradients of `x`, the scale, and the bias. `saved` is the mean & inverse
+    /// variance saved by [NormalizationForward::launch_training()], and is recomputed if not given.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch<Workspace, Reserve, X, Dy, Dx, Scale, DScale, DBias>(
+        &self,
+        workspace: &mut Workspace,
+        reserve: &mut Reserve,
+        (alpha_data, beta_data): (T, T),
+        (alpha_param, beta_param): (T, T),
+        x: &X,
+        dy: &Dy,
+        scale: &Scale,
+        saved: Option<(&dyn DevicePtr<P>, &dyn DevicePtr<P>)>,
+        epsilon: f64,
+        dx: &mut Dx,
+        d_scale: &mut DScale,
+        d_bias: &mut DBias,
+    ) -> Result<(), CudnnError>
+    where
+        Workspace: DevicePtrMut<u8>,
+        Reserve: DevicePtrMut<u8>,
+        X: DevicePtr<T>,
+        Dy: DevicePtr<T>,
+        Dx: DevicePtrMut<T>,
+        Scale: DevicePtr<P>,
+        DScale: DevicePtrMut<P>,
+        DBias: DevicePtrMut<P>,
+    {
+        self.validate()?;
+        let alpha_data = alpha_data.into_scaling_parameter();
+        let beta_data = beta_data.into_scaling_parameter();
+        let alpha_param = alpha_param.into_scaling_parameter();
+        let beta_param = beta_param.into_scaling_parameter();
+        let (saved_mean, saved_inv_var) = match saved {
+            Some((mean, inv_var)) => (
+                *mean.device_ptr() as *const _,
+                *inv_var.device_ptr() as *const _,
+            ),
+            None => (std::ptr::null(), std::ptr::null()),
+        };
+        result::normalization_backward(
+            self.x.handle.handle,
+            self.mode,
+            sys::cudnnNormOps_t::CUDNN_NORM_OPS_NORM,
+            self.algo,
+            (&alpha_data) as *const T::Scalar as *const std::ffi::c_void,
+            (&beta_data) as *const T::Scalar as *const std::ffi::c_void,
+            (&alpha_param) as *const T::Scalar as *const std::ffi::c_void,
+            (&beta_param) as *const T::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            self.dy.desc,
+            *dy.device_ptr() as *const _,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            self.dx.desc,
+            *dx.device_ptr_mut() as *mut _,
+            self.scale_bias.desc,
+            *scale.device_ptr() as *const _,
+            std::ptr::null(),
+            *d_scale.device_ptr_mut() as *mut _,
+            *d_bias.device_ptr_mut() as *mut _,
+            epsilon,
+            self.mean_var.desc,
+            saved_mean,
+            saved_inv_var,
+            std::ptr::null_mut(),
+            *workspace.device_ptr_mut() as *mut std::ffi::c_void,
+            workspace.num_bytes(),
+            *reserve.device_ptr_mut() as *mut std::ffi::c_void,
+            reserve.num_bytes(),
+            self.group_count,
+        )
+    }
+}
@@ -0,0 +1,248 @@
+use super::core::*;
+use crate::{
+    cudnn::{result, result::CudnnError, sys},
+    driver::{DevicePtr, DevicePtrMut},
+};
+
+use std::{ffi::c_int, marker::PhantomData, sync::Arc};
+
+/// A spatial transformer descriptor, holding the NCHW dimensions of the output of the
+/// sampler. Create with [`Cudnn::create_spatial_transformer()`].
+///
+/// The sampling grid has `n * h * w` points of `(x, y)` coordinates, with `-1` and `1` the
+/// centers of the first and last pixels of the input along each axis. The sampler
+/// interpolates bilinearly, and reads zeros outside of the input.
+#[derive(Debug)]
+pub struct SpatialTransformerDescriptor<T> {
+    pub(crate) desc: sys::cudnnSpatialTransformerDescriptor_t,
+    pub(crate) dims: [c_int; 4],
+    #[allow(unused)]
+    pub(crate) handle: Arc<Cudnn>,
+    pub(crate) marker: PhantomData<T>,
+}
+
+impl Cudnn {
+    /// Creates a bilinear spatial transformer producing outputs of the NCHW dimensions
+    /// `dims`.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSetSpatialTransformerNdDescriptor)
+    pub fn create_spatial_transformer<T: CudnnDataType>(
+        self: &Arc<Cudnn>,
+        dims: [c_int; 4],
+    ) -> Result<SpatialTransformerDescriptor<T>, CudnnError> {
+        let desc = result::create_spatial_transformer_descriptor()?;
+        let desc = SpatialTransformerDescriptor {
+            desc,
+            dims,
+            handle: self.clone(),
+            marker: PhantomData,
+        };
+        unsafe {
+            result::set_spatial_transformer_nd_descriptor(
+                desc.desc,
+                sys::cudnnSamplerType_t::CUDNN_SAMPLER_BILINEAR,
+                T::DATA_TYPE,
+                4,
+                dims.as_ptr(),
+            )
+        }?;
+        Ok(desc)
+    }
+}
+
+impl<T> SpatialTransformerDescriptor<T> {
+    /// The NCHW dimensions of the output.
+    pub fn dims(&self) -> [c_int; 4] {
+        self.dims
+    }
+
+    /// The number of elements of `theta`, the `n` 2x3 affine matrices.
+    pub fn theta_len(&self) -> usize {
+        self.dims[0] as usize * 6
+    }
+
+    /// The number of elements of the sampling grid, `n * h * w * 2`.
+    pub fn grid_len(&self) -> usize {
+        let [n, _, h, w] = self.dims;
+        n as usize * h as usize * w as usize * 2
+    }
+}
+
+impl<T: CudnnDataType> SpatialTransformerDescriptor<T> {
+    /// Fills `grid` with the points of the output pixels transformed by the affine matrices
+    /// `theta`, i.e. `theta[i] * (x, y, 1)` for `x` and `y` evenly spaced from `-1` to `1`
+    /// along the output width and height.
+    ///
+    /// Returns [sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM] if `theta` doesn't have
+    /// [Self::theta_len()] or `grid` [Self::grid_len()] elements.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSpatialTfGridGeneratorForward)
+    pub fn generate_grid<Theta, Grid>(
+        &self,
+        theta: &Theta,
+        grid: &mut Grid,
+    ) -> Result<(), CudnnError>
+    where
+        Theta: DevicePtr<T>,
+        Grid: DevicePtrMut<T>,
+    {
+        if theta.len() != self.theta_len() || grid.len() != self.grid_len() {
+            return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM));
+        }
+        unsafe {
+            result::spatial_tf_grid_generator_forward(
+                self.handle.handle,
+                self.desc,
+                *theta.device_ptr() as *const _,
+                *grid.device_ptr_mut() as *mut _,
+            )
+        }
+    }
+
+    /// Computes the gradient of `theta` from the gradient of the grid of
+    /// [Self::generate_grid()].
+    ///
+    /// Returns [sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM] if `dgrid` doesn't have
+    /// [Self::grid_len()] or `dtheta` [Self::theta_len()] elements.
+    ///
+    /// See [nvidia docs](https://docs.nvidia.com/deeplearning/cudnn/api/index.html#cudnnSpatialTfGridGeneratorBackward)
+    pub fn generate_grid_backward<Dgrid, Dtheta>(
+        &self,
+        dgrid: &Dgrid,
+        dtheta: &mut Dtheta,
+    ) -> Result<(), CudnnError>
+    where
+        Dgrid: DevicePtr<T>,
+        Dtheta: DevicePtrMut<T>,
+    {
+        if dgrid.len() != self.grid_len() || dtheta.len() != self.theta_len() {
+            return Err(CudnnError(sys::cudnnStatus_t::CUDNN_STATUS_BAD_PARAM));
+        }
+        unsafe {
+            result::spatial_tf_grid_generator_backward(
+                self.handle.handle,
+                self.desc,
+                *dgrid.device_ptr() as *const _,
+                *dtheta.device_ptr_mut() as *mut _,
+            )
+        }
+    }
+}
+
+impl<T> Drop for SpatialTransformerDescriptor<T> {
+    fn drop(&mut self) {
+        let desc = std::mem::replace(&mut self.desc, std::ptr::null_mut());
+        if !desc.is_null() {
+            unsafe { result::destroy_spatial_transformer_descriptor(desc) }.unwrap()
+        }
+    }
+}
+
+/// Samples `x` at the points of a grid, e.g. from
+/// [SpatialTransformerDescriptor::generate_grid()]. Pass in fields directly, and then call
+/// launch.
+pub struct SpatialTfSamplerForward<'a, T: CudnnDataType> {
+    /// The spatial transformer descriptor.
+    pub st: &'a SpatialTransformerDescriptor<T>,
+    /// The input tensor, with the same `n` and `c` as the output.
+    pub x: &'a TensorDescriptor<T>,
+    /// The output tensor, with the dimensions of `st`.
+    pub y: &'a TensorDescriptor<T>,
+}
+
+impl<'a, T: CudnnDataType> SpatialTfSamplerForward<'a, T> {
+    /// Launches the operation.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`, and `grid` must have
+    /// [SpatialTransformerDescriptor::grid_len()] elements.
+    pub unsafe fn launch<X, Grid, Y>(
+        &self,
+        (alpha, beta): (T, T),
+        x: &X,
+        grid: &Grid,
+        y: &mut Y,
+    ) -> Result<(), CudnnError>
+    where
+        X: DevicePtr<T>,
+        Grid: DevicePtr<T>,
+        Y: DevicePtrMut<T>,
+    {
+        let alpha = alpha.into_scaling_parameter();
+        let beta = beta.into_scaling_parameter();
+        result::spatial_tf_sampler_forward(
+            self.st.handle.handle,
+            self.st.desc,
+            (&alpha) as *const T::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            *grid.device_ptr() as *const _,
+            (&beta) as *const T::Scalar as *const std::ffi::c_void,
+            self.y.desc,
+            *y.device_ptr_mut() as *mut _,
+        )
+    }
+}
+
+/// The gradients of [SpatialTfSamplerForward] for both its input and grid. Pass in fields
+/// directly, and then call launch.
+pub struct SpatialTfSamplerBackward<'a, T: CudnnDataType> {
+    /// The spatial transformer descriptor.
+    pub st: &'a SpatialTransformerDescriptor<T>,
+    /// The input of the forward pass.
+    pub x: &'a TensorDescriptor<T>,
+    /// The gradient of `x`.
+    pub dx: &'a TensorDescriptor<T>,
+    /// The gradient of the output of the forward pass.
+    pub dy: &'a TensorDescriptor<T>,
+}
+
+impl<'a, T: CudnnDataType> SpatialTfSamplerBackward<'a, T> {
+    /// Launches the operation, blending the gradient of `x` into `dx` with `(alpha, beta)`
+    /// and the one of the grid into `dgrid` with `(alpha_dgrid, beta_dgrid)`.
+    ///
+    /// # Safety
+    /// The arguments must match the data type/layout specified in the
+    /// descriptors in `self`, and `grid` and `dgrid` must have
+    /// [SpatialTransformerDescriptor::grid_len()] elements.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn launch<X, Dx, Dy, Grid, Dgrid>(
+        &self,
+        (alpha, beta): (T, T),
+        (alpha_dgrid, beta_dgrid): (T, T),
+        x: &X,
+        dx: &mut Dx,
+        dy: &Dy,
+        grid: &Grid,
+        dgrid: &mut Dgrid,
+    ) -> Result<(), CudnnError>
+    where
+        X: DevicePtr<T>,
+        Dx: DevicePtrMut<T>,
+        Dy: DevicePtr<T>,
+        Grid: DevicePtr<T>,
+        Dgrid: DevicePtrMut<T>,
+    {
+        let alpha = alpha.into_scaling_parameter();
+        let beta = beta.into_scaling_parameter();
+        let alpha_dgrid = alpha_dgrid.into_scaling_parameter();
+        let beta_dgrid = beta_dgrid.into_scaling_parameter();
+        result::spatial_tf_sampler_backward(
+            self.st.handle.handle,
+            self.st.desc,
+            (&alpha) as *const T::Scalar as *const std::ffi::c_void,
+            self.x.desc,
+            *x.device_ptr() as *const _,
+            (&beta) as *const T::Scalar as *const std::ffi::c_void,
+            self.dx.desc,
+            *dx.device_ptr_mut() as *mut _,
+            (&alpha_dgrid) as *const T::Scalar as *const std::ffi::c_void,
+            self.dy.desc,
+            *dy.device_ptr() as *const _,
+            *grid.device_ptr() as *const _,
+            (&beta_dgrid) as *const T::Scalar as *const std::ffi::c_void,
+            *dgrid.device_ptr_mut() as *mut _,
+        )
+    }
+}
@@ -0,0 +1,14 @@
+//! Wrappers around the [cuFile API](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html)
+//! of GPUDirect Storage in three levels. See crate documentation for description of each.
+//!
+//! [CuFile::read_into()] reads files straight into a [crate::driver::CudaSlice]. Where the
+//! `nvidia-fs` driver isn't available, it falls back to reading into pinned host memory
+//! and copying that to the device, see [CuFileDriver::is_gds_enabled()].
+
+pub mod result;
+pub mod safe;
+#[allow(warnings)]
+pub mod sys;
+
+pub use result::CuFileError;
+pub use safe::*;
@@ -0,0 +1,155 @@
+use super::sys::{self, lib};
+use crate::driver::DriverError;
+
+/// An error of a cuFile call, or of reading a file without it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CuFileError {
+    /// An error returned by cuFile. `cu_err` is the driver's error if `code` is
+    /// [sys::CU_FILE_CUDA_DRIVER_ERROR], and 0 otherwise.
+    CuFile {
+        code: sys::CUfileOpError,
+        cu_err: core::ffi::c_int,
+    },
+    /// Opening or reading the file failed.
+    Io(std::io::ErrorKind),
+    /// An error of the driver while copying the file to the device.
+    Driver(DriverError),
+    /// `len` bytes were to be read into a slice of `capacity` bytes.
+    OutOfBounds { len: usize, capacity: usize },
+}
+
+impl sys::CUfileError_t {
+    /// Transforms into a [Result] of [CuFileError]
+    pub fn result(self) -> Result<(), CuFileError> {
+        match self.err {
+            sys::CU_FILE_SUCCESS => Ok(()),
+            code => Err(CuFileError::CuFile {
+                code,
+                cu_err: self.cu_err,
+            }),
+        }
+    }
+}
+
+impl From<DriverError> for CuFileError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+impl From<std::io::Error> for CuFileError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value.kind())
+    }
+}
+
+impl std::fmt::Display for CuFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for CuFileError {}
+
+/// Opens the cuFile driver, which fails if the `nvidia-fs` kernel module isn't loaded
+/// and compatibility mode is disabled. See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufiledriveropen)
+pub fn driver_open() -> Result<(), CuFileError> {
+    match unsafe { sys::try_lib() } {
+        Some(lib) => unsafe { lib.cuFileDriverOpen() }.result(),
+        None => Err(CuFileError::CuFile {
+            code: sys::CU_FILE_PLATFORM_NOT_SUPPORTED,
+            cu_err: 0,
+        }),
+    }
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufiledriverclose)
+///
+/// # Safety
+/// Files and buffers registered with the driver must have been deregistered.
+pub unsafe fn driver_close() -> Result<(), CuFileError> {
+    lib().cuFileDriverClose().result()
+}
+
+/// Registers the file descriptor `fd` with cuFile. See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufilehandleregister)
+///
+/// # Safety
+/// The driver must be open, and `fd` an open file, which must not be closed before the
+/// handle is passed to [handle_deregister()].
+pub unsafe fn handle_register(fd: core::ffi::c_int) -> Result<sys::CUfileHandle_t, CuFileError> {
+    let mut descr = sys::CUfileDescr_t {
+        type_: sys::CU_FILE_HANDLE_TYPE_OPAQUE_FD,
+        handle: sys::CUfileDescr_t__bindgen_ty_1 { fd },
+        fs_ops: std::ptr::null(),
+    };
+    let mut handle = std::ptr::null_mut();
+    lib()
+        .cuFileHandleRegister(&mut handle, &mut descr)
+        .result()?;
+    Ok(handle)
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufilehandlederegister)
+///
+/// # Safety
+/// `handle` must have been returned by [handle_register()], and not be deregistered already.
+pub unsafe fn handle_deregister(handle: sys::CUfileHandle_t) {
+    lib().cuFileHandleDeregister(handle)
+}
+
+/// Registers `len` bytes of device memory at `dev_ptr` for reads. See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufilebufregister)
+///
+/// # Safety
+/// The driver must be open, and the memory must stay allocated until it is passed to
+/// [buf_deregister()].
+pub unsafe fn buf_register(
+    dev_ptr: crate::driver::sys::CUdeviceptr,
+    len: usize,
+) -> Result<(), CuFileError> {
+    lib()
+        .cuFileBufRegister(dev_ptr as *const core::ffi::c_void, len, 0)
+        .result()
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufilebufderegister)
+///
+/// # Safety
+/// `dev_ptr` must have been registered by [buf_register()].
+pub unsafe fn buf_deregister(dev_ptr: crate::driver::sys::CUdeviceptr) -> Result<(), CuFileError> {
+    lib()
+        .cuFileBufDeregister(dev_ptr as *const core::ffi::c_void)
+        .result()
+}
+
+/// Reads up to `len` bytes at `file_offset` of the file into the device memory at
+/// `dev_ptr + dev_offset`, returning the number of bytes read, which is less than `len` at
+/// the end of the file. See [nvidia docs](https://docs.nvidia.com/gpudirect-storage/api-reference-guide/index.html#cufileread)
+///
+/// # Safety
+/// `handle` must be registered, `dev_ptr` the start of an allocation or of a registered
+/// buffer, which must hold `dev_offset + len` bytes, and the context of its device must
+/// be current.
+pub unsafe fn read(
+    handle: sys::CUfileHandle_t,
+    dev_ptr: crate::driver::sys::CUdeviceptr,
+    len: usize,
+    file_offset: u64,
+    dev_offset: usize,
+) -> Result<usize, CuFileError> {
+    let ret = lib().cuFileRead(
+        handle,
+        dev_ptr as *mut core::ffi::c_void,
+        len,
+        file_offset as i64,
+        dev_offset as i64,
+    );
+    match ret {
+        n if n >= 0 => Ok(n as usize),
+        // errno is set
+        -1 => Err(std::io::Error::last_os_error().into()),
+        n => Err(CuFileError::CuFile {
+            code: -n as sys::CUfileOpError,
+            cu_err: 0,
+        }),
+    }
+}
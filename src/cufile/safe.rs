@@ -0,0 +1,549 @@
+use super::{result, result::CuFileError, sys};
+use crate::driver::{
+    safe::error_handler::{handle_error, log_library_error},
+    CudaSlice, PinnedBuffer, WorkToken,
+};
+
+use std::{
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::{Arc, Mutex, Weak},
+    thread::JoinHandle,
+    vec::Vec,
+};
+
+/// The bytes read into each pinned staging buffer of the fallback path.
+const STAGING_BYTES: usize = 8 << 20;
+
+/// The cuFile driver, shared by the [CuFile]s and [RegisteredBuffer]s created with it.
+///
+/// Reads go straight from storage to the device through GPUDirect Storage if the
+/// `nvidia-fs` driver is available, see [CuFileDriver::is_gds_enabled()]. Otherwise they fall
+/// back to `pread` into pinned host memory and copies to the device, with the same API.
+#[derive(Debug)]
+pub struct CuFileDriver {
+    gds: bool,
+    gds_error: Option<CuFileError>,
+}
+
+impl CuFileDriver {
+    /// Opens the driver of the process, or returns it if it is open already. Uses the
+    /// fallback path if cuFile isn't installed or can't be opened, see
+    /// [CuFileDriver::gds_error()].
+    pub fn open() -> Arc<Self> {
+        static DRIVER: Mutex<Weak<CuFileDriver>> = Mutex::new(Weak::new());
+        let mut shared = DRIVER.lock().unwrap();
+        if let Some(driver) = shared.upgrade() {
+            return driver;
+        }
+        let driver = Arc::new(match result::driver_open() {
+            Ok(()) => Self {
+                gds: true,
+                gds_error: None,
+            },
+            Err(e) => Self {
+                gds: false,
+                gds_error: Some(e),
+            },
+        });
+        *shared = Arc::downgrade(&driver);
+        driver
+    }
+
+    /// A driver that always uses the fallback path, even if GPUDirect Storage is available.
+    pub fn fallback() -> Arc<Self> {
+        Arc::new(Self {
+            gds: false,
+            gds_error: None,
+        })
+    }
+
+    /// Whether reads go through GPUDirect Storage, rather than the fallback path.
+    pub fn is_gds_enabled(&self) -> bool {
+        self.gds
+    }
+
+    /// Why [CuFileDriver::open()] fell back, if it did.
+    pub fn gds_error(&self) -> Option<CuFileError> {
+        self.gds_error.clone()
+    }
+
+    /// Registers `slice` with cuFile for repeated reads with [CuFile::read_into()], which
+    /// saves cuFile from bouncing them through its own buffers. Does nothing on the fallback
+    /// path.
+    pub fn register_buffer(
+        self: &Arc<Self>,
+        slice: CudaSlice<u8>,
+    ) -> Result<RegisteredBuffer, CuFileError> {
+        if self.gds {
+            let _ctx = slice.device.bind_scoped()?;
+            unsafe { result::buf_register(slice.cu_device_ptr, slice.len) }?;
+        }
+        Ok(RegisteredBuffer {
+            slice: Some(slice),
+            driver: self.clone(),
+        })
+    }
+}
+
+impl Drop for CuFileDriver {
+    fn drop(&mut self) {
+        if self.gds {
+            or_log(unsafe { result::driver_close() }, "CuFileDriver::drop");
+        }
+    }
+}
+
+/// Reports an error that can't be returned, e.g. from a `Drop` impl. Errors of the cuda
+/// driver go to the [crate::driver::ErrorHandler], and cuFile's own ones are logged.
+fn or_log(res: Result<(), CuFileError>, op: &'static str) {
+    match res {
+        Ok(()) => {}
+        Err(CuFileError::Driver(err)) => handle_error(err, op),
+        Err(err) => log_library_error(&err, op),
+    }
+}
+
+/// A file opened for reads into [CudaSlice]s, see [CuFileDriver].
+#[derive(Debug)]
+pub struct CuFile {
+    driver: Arc<CuFileDriver>,
+    file: std::fs::File,
+    /// Registered with cuFile, `None` on the fallback path.
+    handle: Option<sys::CUfileHandle_t>,
+    /// Reused by fallback reads, filled alternately.
+    staging: Mutex<Vec<PinnedBuffer<u8>>>,
+}
+
+unsafe impl Send for CuFile {}
+unsafe impl Sync for CuFile {}
+
+impl CuFile {
+    /// Opens `path` for reading with [CuFileDriver::open()]. `flags` are added to the flags
+    /// the file is opened with, e.g. `O_DIRECT`, which GPUDirect Storage needs unless cuFile
+    /// runs in compatibility mode. With `O_DIRECT`, the fallback path needs offsets and
+    /// lengths aligned to the block size of the file system.
+    pub fn open(path: impl AsRef<Path>, flags: core::ffi::c_int) -> Result<Self, CuFileError> {
+        Self::open_with(&CuFileDriver::open(), path, flags)
+    }
+
+    /// Like [CuFile::open()], with `driver`. If the file can't be registered with cuFile,
+    /// e.g. because its file system isn't supported, reads of it use the fallback path.
+    pub fn open_with(
+        driver: &Arc<CuFileDriver>,
+        path: impl AsRef<Path>,
+        flags: core::ffi::c_int,
+    ) -> Result<Self, CuFileError> {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, flags);
+        #[cfg(not(unix))]
+        let _ = flags;
+        let file = options.open(path)?;
+
+        #[cfg(unix)]
+        let handle = match driver.gds {
+            true => {
+                let fd = std::os::unix::io::AsRawFd::as_raw_fd(&file);
+                unsafe { result::handle_register(fd) }.ok()
+            }
+            false => None,
+        };
+        #[cfg(not(unix))]
+        let handle = None;
+
+        Ok(Self {
+            driver: driver.clone(),
+            file,
+            handle,
+            staging: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Whether reads of this file go through GPUDirect Storage.
+    pub fn is_gds_enabled(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// The driver the file was opened with.
+    pub fn driver(&self) -> &Arc<CuFileDriver> {
+        &self.driver
+    }
+
+    /// Reads `len` bytes at `file_offset` into the start of `slice`, and returns the number
+    /// of bytes read, which is less than `len` if the file ends first.
+    ///
+    /// Waits for the work enqueued on the device's stream before the read, and for the read
+    /// to complete, so `slice` holds the bytes on return.
+    pub fn read_into(
+        &self,
+        slice: &mut CudaSlice<u8>,
+        file_offset: u64,
+        len: usize,
+    ) -> Result<usize, CuFileError> {
+        if len > slice.len {
+            return Err(CuFileError::OutOfBounds {
+                len,
+                capacity: slice.len,
+            });
+        }
+        let dev = slice.device.clone();
+        let _ctx = dev.bind_scoped()?;
+        dev.wait_for_write(dev.stream, slice)?;
+        match self.handle {
+            Some(handle) => {
+                dev.synchronize()?;
+                let mut done = 0;
+                while done < len {
+                    let n = unsafe {
+                        result::read(
+                            handle,
+                            slice.cu_device_ptr,
+                            len - done,
+                            file_offset + done as u64,
+                            done,
+                        )
+                    }?;
+                    if n == 0 {
+                        break;
+                    }
+                    done += n;
+                }
+                Ok(done)
+            }
+            None => self.read_staged(slice, file_offset, len),
+        }
+    }
+
+    /// Reads each `(slice, file_offset, len)` of `reads` with [CuFile::read_into()], and
+    /// returns the number of bytes read into each.
+    pub fn read_batch(
+        &self,
+        reads: &mut [(&mut CudaSlice<u8>, u64, usize)],
+    ) -> Result<Vec<usize>, CuFileError> {
+        reads
+            .iter_mut()
+            .map(|(slice, file_offset, len)| self.read_into(slice, *file_offset, *len))
+            .collect()
+    }
+
+    /// Like [CuFile::read_batch()], on a thread of its own. The slices are handed back by
+    /// [PendingReads::wait()], with the number of bytes read into each.
+    ///
+    /// This isn't cuFile's stream-ordered `cuFileReadAsync`: a new OS thread does the reads
+    /// one after the other, blocking in each like [CuFile::read_into()] does, so that only
+    /// the calling thread is free in the meantime.
+    pub fn read_batch_async(
+        self: &Arc<Self>,
+        reads: Vec<(CudaSlice<u8>, u64, usize)>,
+    ) -> PendingReads {
+        let file = self.clone();
+        let thread = std::thread::spawn(move || {
+            reads
+                .into_iter()
+                .map(|(mut slice, file_offset, len)| {
+                    let n = file.read_into(&mut slice, file_offset, len)?;
+                    Ok((slice, n))
+                })
+                .collect()
+        });
+        PendingReads {
+            thread: Some(thread),
+        }
+    }
+
+    /// The fallback path: `pread`s chunks into two pinned buffers in turn, each copied to
+    /// the device while the next one is read.
+    fn read_staged(
+        &self,
+        slice: &mut CudaSlice<u8>,
+        file_offset: u64,
+        len: usize,
+    ) -> Result<usize, CuFileError> {
+        let dev = slice.device.clone();
+        let chunk = STAGING_BYTES.min(len).max(1);
+        let mut staging = self.staging.lock().unwrap();
+        let reusable = staging
+            .first()
+            .is_some_and(|buf| Arc::ptr_eq(&buf.device(), &dev) && buf.len() >= chunk);
+        if !reusable {
+            staging.clear();
+            for _ in 0..2 {
+                staging.push(dev.alloc_pinned::<u8>(chunk)?);
+            }
+        }
+
+        let mut copies: [Option<WorkToken>; 2] = [None, None];
+        let mut done = 0;
+        let mut i = 0;
+        while done < len {
+            // the copy out of the buffer has to finish before it's filled again
+            if let Some(copy) = copies[i].take() {
+                copy.wait()?;
+            }
+            let want = chunk.min(len - done);
+            let buf = &mut staging[i].as_mut_slice()[..want];
+            let got = read_at(&self.file, buf, file_offset + done as u64)?;
+            if got == 0 {
+                break;
+            }
+            unsafe {
+                crate::driver::result::memcpy_htod_async(
+                    slice.cu_device_ptr + done as u64,
+                    &buf[..got],
+                    dev.stream,
+                )
+            }?;
+            copies[i] = Some(dev.token()?);
+            done += got;
+            i = 1 - i;
+            if got < want {
+                break;
+            }
+        }
+        dev.record_write(dev.stream, slice)?;
+        for copy in copies.into_iter().flatten() {
+            copy.wait()?;
+        }
+        Ok(done)
+    }
+}
+
+impl Drop for CuFile {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            unsafe { result::handle_deregister(handle) };
+        }
+    }
+}
+
+/// Reads as much of `buf` as the file holds at `offset`.
+fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    let mut done = 0;
+    while done < buf.len() {
+        #[cfg(unix)]
+        let n = std::os::unix::fs::FileExt::read_at(file, &mut buf[done..], offset + done as u64);
+        #[cfg(windows)]
+        let n =
+            std::os::windows::fs::FileExt::seek_read(file, &mut buf[done..], offset + done as u64);
+        match n {
+            Ok(0) => break,
+            Ok(n) => done += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(done)
+}
+
+/// Each slice of a batch, with the number of bytes read into it.
+type BatchReads = Result<Vec<(CudaSlice<u8>, usize)>, CuFileError>;
+
+/// Reads started by [CuFile::read_batch_async()] on a thread of their own.
+#[derive(Debug)]
+pub struct PendingReads {
+    thread: Option<JoinHandle<BatchReads>>,
+}
+
+impl PendingReads {
+    /// Whether all the reads finished, without blocking.
+    pub fn is_complete(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+
+    /// Blocks until the reads finished, and returns each slice with the number of bytes read
+    /// into it. If a read fails, the slices are dropped and its error returned.
+    pub fn wait(mut self) -> BatchReads {
+        let thread = self.thread.take().unwrap();
+        match thread.join() {
+            Ok(reads) => reads,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for PendingReads {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A [CudaSlice] registered with cuFile by [CuFileDriver::register_buffer()], deregistered
+/// when dropped. Derefs to the slice, so it can be passed to [CuFile::read_into()].
+#[derive(Debug)]
+pub struct RegisteredBuffer {
+    slice: Option<CudaSlice<u8>>,
+    driver: Arc<CuFileDriver>,
+}
+
+impl RegisteredBuffer {
+    /// Deregisters the buffer, and returns the slice.
+    pub fn into_inner(mut self) -> CudaSlice<u8> {
+        self.deregister();
+        self.slice.take().unwrap()
+    }
+
+    fn deregister(&mut self) {
+        if let Some(slice) = self.slice.as_ref().filter(|_| self.driver.gds) {
+            let _ctx = slice
+                .device
+                .bind_scoped_or_handle("RegisteredBuffer::deregister");
+            let res = unsafe { result::buf_deregister(slice.cu_device_ptr) };
+            or_log(res, "RegisteredBuffer::deregister");
+        }
+    }
+}
+
+impl Deref for RegisteredBuffer {
+    type Target = CudaSlice<u8>;
+    fn deref(&self) -> &Self::Target {
+        self.slice.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for RegisteredBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.slice.as_mut().unwrap()
+    }
+}
+
+impl Drop for RegisteredBuffer {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{CudaDevice, DeviceSlice};
+
+    /// Writes `len` bytes counting up from 0 to a new file, and returns its path.
+    fn counting_file(name: &str, len: usize) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(std::format!("cudarc-cufile-{name}-{}", std::process::id()));
+        let bytes: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn counting(range: std::ops::Range<usize>) -> Vec<u8> {
+        range.map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn test_fallback_driver() {
+        let driver = CuFileDriver::fallback();
+        assert!(!driver.is_gds_enabled());
+        assert_eq!(driver.gds_error(), None);
+        // fallback drivers aren't shared, unlike the opened one
+        assert!(!Arc::ptr_eq(&driver, &CuFileDriver::fallback()));
+        let opened = CuFileDriver::open();
+        assert!(Arc::ptr_eq(&opened, &CuFileDriver::open()));
+        assert_eq!(opened.is_gds_enabled(), opened.gds_error().is_none());
+
+        let path = counting_file("fallback", 16);
+        let file = CuFile::open_with(&driver, &path, 0).unwrap();
+        assert!(!file.is_gds_enabled());
+        assert!(Arc::ptr_eq(file.driver(), &driver));
+        let mut buf = [0; 20];
+        assert_eq!(read_at(&file.file, &mut buf, 10).unwrap(), 6);
+        assert_eq!(&buf[..6], &counting(10..16)[..]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            CuFile::open_with(&driver, &path, 0).unwrap_err(),
+            CuFileError::Io(std::io::ErrorKind::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_read_into() {
+        let dev = CudaDevice::new(0).unwrap();
+        // more than a staging buffer, so the fallback path reads chunks
+        let len = STAGING_BYTES * 2 + 1000;
+        let path = counting_file("read_into", len + 123);
+        for driver in [CuFileDriver::open(), CuFileDriver::fallback()] {
+            let file = CuFile::open_with(&driver, &path, 0).unwrap();
+            let mut slice = dev.alloc_zeros::<u8>(len + 10).unwrap();
+            assert_eq!(file.read_into(&mut slice, 123, len).unwrap(), len);
+            let host = dev.dtoh_sync_copy(&slice).unwrap();
+            assert_eq!(&host[..len], &counting(123..len + 123)[..]);
+            assert_eq!(&host[len..], &[0; 10]);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_past_end() {
+        let dev = CudaDevice::new(0).unwrap();
+        let path = counting_file("past_end", 100);
+        let file = CuFile::open(&path, 0).unwrap();
+        let mut slice = dev.alloc_zeros::<u8>(64).unwrap();
+        assert_eq!(file.read_into(&mut slice, 60, 64).unwrap(), 40);
+        let host = dev.dtoh_sync_copy(&slice).unwrap();
+        assert_eq!(&host[..40], &counting(60..100)[..]);
+        assert_eq!(file.read_into(&mut slice, 200, 64).unwrap(), 0);
+        assert_eq!(
+            file.read_into(&mut slice, 0, 65),
+            Err(CuFileError::OutOfBounds {
+                len: 65,
+                capacity: 64
+            })
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_registered_buffer_reads() {
+        let dev = CudaDevice::new(0).unwrap();
+        let path = counting_file("registered", 4096);
+        let file = CuFile::open(&path, 0).unwrap();
+        let mut buf = file
+            .driver()
+            .register_buffer(dev.alloc_zeros::<u8>(1024).unwrap())
+            .unwrap();
+        for offset in [0, 1024, 3000] {
+            let n = file.read_into(&mut buf, offset, 1024).unwrap();
+            assert_eq!(n, 1024.min(4096 - offset as usize));
+            let host = dev.dtoh_sync_copy(&*buf).unwrap();
+            assert_eq!(
+                &host[..n],
+                &counting(offset as usize..offset as usize + n)[..]
+            );
+        }
+        let slice = buf.into_inner();
+        assert_eq!(slice.len(), 1024);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_batch_async() {
+        let dev = CudaDevice::new(0).unwrap();
+        let path = counting_file("batch", 1000);
+        let file = Arc::new(CuFile::open(&path, 0).unwrap());
+        let reads = std::vec![
+            (dev.alloc_zeros::<u8>(100).unwrap(), 0, 100),
+            (dev.alloc_zeros::<u8>(100).unwrap(), 500, 50),
+            (dev.alloc_zeros::<u8>(100).unwrap(), 950, 100),
+        ];
+        let done = file.read_batch_async(reads).wait().unwrap();
+        assert_eq!(
+            done.iter().map(|(_, n)| *n).collect::<Vec<_>>(),
+            [100, 50, 50]
+        );
+        let host = dev.dtoh_sync_copy(&done[2].0).unwrap();
+        assert_eq!(&host[..50], &counting(950..1000)[..]);
+
+        let mut a = dev.alloc_zeros::<u8>(10).unwrap();
+        let mut b = dev.alloc_zeros::<u8>(10).unwrap();
+        let n = file
+            .read_batch(&mut [(&mut a, 10, 10), (&mut b, 995, 10)])
+            .unwrap();
+        assert_eq!(n, [10, 5]);
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), counting(10..20));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+//! Bindings to the parts of `cufile.h` used by [super::safe]. Unlike the other libraries,
+//! the cuFile API these use is the same for every supported cuda version, so there is a
+//! single set of bindings.
+
+pub type CUfileOpError = ::core::ffi::c_int;
+pub const CU_FILE_SUCCESS: CUfileOpError = 0;
+pub const CU_FILE_DRIVER_NOT_INITIALIZED: CUfileOpError = 5001;
+pub const CU_FILE_DRIVER_INVALID_PROPS: CUfileOpError = 5002;
+pub const CU_FILE_DRIVER_UNSUPPORTED_LIMIT: CUfileOpError = 5003;
+pub const CU_FILE_DRIVER_VERSION_MISMATCH: CUfileOpError = 5004;
+pub const CU_FILE_DRIVER_VERSION_READ_ERROR: CUfileOpError = 5005;
+pub const CU_FILE_DRIVER_CLOSING: CUfileOpError = 5006;
+pub const CU_FILE_PLATFORM_NOT_SUPPORTED: CUfileOpError = 5007;
+pub const CU_FILE_IO_NOT_SUPPORTED: CUfileOpError = 5008;
+pub const CU_FILE_DEVICE_NOT_SUPPORTED: CUfileOpError = 5009;
+pub const CU_FILE_NVFS_DRIVER_ERROR: CUfileOpError = 5010;
+pub const CU_FILE_CUDA_DRIVER_ERROR: CUfileOpError = 5011;
+pub const CU_FILE_CUDA_POINTER_INVALID: CUfileOpError = 5012;
+pub const CU_FILE_CUDA_MEMORY_TYPE_INVALID: CUfileOpError = 5013;
+pub const CU_FILE_CUDA_POINTER_RANGE_ERROR: CUfileOpError = 5014;
+pub const CU_FILE_CUDA_CONTEXT_MISMATCH: CUfileOpError = 5015;
+pub const CU_FILE_INVALID_MAPPING_SIZE: CUfileOpError = 5016;
+pub const CU_FILE_INVALID_MAPPING_RANGE: CUfileOpError = 5017;
+pub const CU_FILE_INVALID_FILE_TYPE: CUfileOpError = 5018;
+pub const CU_FILE_INVALID_FILE_OPEN_FLAG: CUfileOpError = 5019;
+pub const CU_FILE_DIO_NOT_SET: CUfileOpError = 5020;
+pub const CU_FILE_INVALID_VALUE: CUfileOpError = 5022;
+pub const CU_FILE_MEMORY_ALREADY_REGISTERED: CUfileOpError = 5023;
+pub const CU_FILE_MEMORY_NOT_REGISTERED: CUfileOpError = 5024;
+pub const CU_FILE_PERMISSION_DENIED: CUfileOpError = 5025;
+pub const CU_FILE_DRIVER_ALREADY_OPEN: CUfileOpError = 5026;
+pub const CU_FILE_HANDLE_NOT_REGISTERED: CUfileOpError = 5027;
+pub const CU_FILE_HANDLE_ALREADY_REGISTERED: CUfileOpError = 5028;
+pub const CU_FILE_DEVICE_NOT_FOUND: CUfileOpError = 5029;
+pub const CU_FILE_INTERNAL_ERROR: CUfileOpError = 5030;
+pub const CU_FILE_GETNEWFD_FAILED: CUfileOpError = 5031;
+pub const CU_FILE_NVFS_SETUP_ERROR: CUfileOpError = 5033;
+pub const CU_FILE_IO_DISABLED: CUfileOpError = 5034;
+
+/// The error of a cuFile call, with the cuda driver's error if `err` is
+/// [CU_FILE_CUDA_DRIVER_ERROR].
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct CUfileError_t {
+    pub err: CUfileOpError,
+    pub cu_err: ::core::ffi::c_int,
+}
+
+pub type CUfileFileHandleType = ::core::ffi::c_int;
+pub const CU_FILE_HANDLE_TYPE_OPAQUE_FD: CUfileFileHandleType = 1;
+pub const CU_FILE_HANDLE_TYPE_OPAQUE_WIN32: CUfileFileHandleType = 2;
+pub const CU_FILE_HANDLE_TYPE_USERSPACE_FS: CUfileFileHandleType = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union CUfileDescr_t__bindgen_ty_1 {
+    pub fd: ::core::ffi::c_int,
+    pub handle: *mut ::core::ffi::c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CUfileDescr_t {
+    pub type_: CUfileFileHandleType,
+    pub handle: CUfileDescr_t__bindgen_ty_1,
+    /// `const CUfileFSOps_t *`, only used for [CU_FILE_HANDLE_TYPE_USERSPACE_FS].
+    pub fs_ops: *const ::core::ffi::c_void,
+}
+
+pub type CUfileHandle_t = *mut ::core::ffi::c_void;
+
+pub struct Lib {
+    __library: ::libloading::Library,
+    pub cuFileDriverOpen: Result<unsafe extern "C" fn() -> CUfileError_t, ::libloading::Error>,
+    pub cuFileDriverClose: Result<unsafe extern "C" fn() -> CUfileError_t, ::libloading::Error>,
+    pub cuFileHandleRegister: Result<
+        unsafe extern "C" fn(fh: *mut CUfileHandle_t, descr: *mut CUfileDescr_t) -> CUfileError_t,
+        ::libloading::Error,
+    >,
+    pub cuFileHandleDeregister:
+        Result<unsafe extern "C" fn(fh: CUfileHandle_t), ::libloading::Error>,
+    pub cuFileBufRegister: Result<
+        unsafe extern "C" fn(
+            devPtr_base: *const ::core::ffi::c_void,
+            length: usize,
+            flags: ::core::ffi::c_int,
+        ) -> CUfileError_t,
+        ::libloading::Error,
+    >,
+    pub cuFileBufDeregister: Result<
+        unsafe extern "C" fn(devPtr_base: *const ::core::ffi::c_void) -> CUfileError_t,
+        ::libloading::Error,
+    >,
+    pub cuFileRead: Result<
+        unsafe extern "C" fn(
+            fh: CUfileHandle_t,
+            devPtr_base: *mut ::core::ffi::c_void,
+            size: usize,
+            file_offset: i64,
+            devPtr_offset: i64,
+        ) -> isize,
+        ::libloading::Error,
+    >,
+}
+
+impl Lib {
+    pub unsafe fn new<P>(path: P) -> Result<Self, ::libloading::Error>
+    where
+        P: AsRef<::std::ffi::OsStr>,
+    {
+        let library = ::libloading::Library::new(path)?;
+        Self::from_library(library)
+    }
+    pub unsafe fn from_library<L>(library: L) -> Result<Self, ::libloading::Error>
+    where
+        L: Into<::libloading::Library>,
+    {
+        let __library = library.into();
+        let cuFileDriverOpen = __library.get(b"cuFileDriverOpen\0").map(|sym| *sym);
+        let cuFileDriverClose = __library.get(b"cuFileDriverClose\0").map(|sym| *sym);
+        let cuFileHandleRegister = __library.get(b"cuFileHandleRegister\0").map(|sym| *sym);
+        let cuFileHandleDeregister = __library.get(b"cuFileHandleDeregister\0").map(|sym| *sym);
+        let cuFileBufRegister = __library.get(b"cuFileBufRegister\0").map(|sym| *sym);
+        let cuFileBufDeregister = __library.get(b"cuFileBufDeregister\0").map(|sym| *sym);
+        let cuFileRead = __library.get(b"cuFileRead\0").map(|sym| *sym);
+        Ok(Lib {
+            __library,
+            cuFileDriverOpen,
+            cuFileDriverClose,
+            cuFileHandleRegister,
+            cuFileHandleDeregister,
+            cuFileBufRegister,
+            cuFileBufDeregister,
+            cuFileRead,
+        })
+    }
+    pub unsafe fn cuFileDriverOpen(&self) -> CUfileError_t {
+        (self
+            .cuFileDriverOpen
+            .as_ref()
+            .expect("Expected function, got error."))()
+    }
+    pub unsafe fn cuFileDriverClose(&self) -> CUfileError_t {
+        (self
+            .cuFileDriverClose
+            .as_ref()
+            .expect("Expected function, got error."))()
+    }
+    pub unsafe fn cuFileHandleRegister(
+        &self,
+        fh: *mut CUfileHandle_t,
+        descr: *mut CUfileDescr_t,
+    ) -> CUfileError_t {
+        (self
+            .cuFileHandleRegister
+            .as_ref()
+            .expect("Expected function, got error."))(fh, descr)
+    }
+    pub unsafe fn cuFileHandleDeregister(&self, fh: CUfileHandle_t) {
+        (self
+            .cuFileHandleDeregister
+            .as_ref()
+            .expect("Expected function, got error."))(fh)
+    }
+    pub unsafe fn cuFileBufRegister(
+        &self,
+        devPtr_base: *const ::core::ffi::c_void,
+        length: usize,
+        flags: ::core::ffi::c_int,
+    ) -> CUfileError_t {
+        (self
+            .cuFileBufRegister
+            .as_ref()
+            .expect("Expected function, got error."))(devPtr_base, length, flags)
+    }
+    pub unsafe fn cuFileBufDeregister(
+        &self,
+        devPtr_base: *const ::core::ffi::c_void,
+    ) -> CUfileError_t {
+        (self
+            .cuFileBufDeregister
+            .as_ref()
+            .expect("Expected function, got error."))(devPtr_base)
+    }
+    pub unsafe fn cuFileRead(
+        &self,
+        fh: CUfileHandle_t,
+        devPtr_base: *mut ::core::ffi::c_void,
+        size: usize,
+        file_offset: i64,
+        devPtr_offset: i64,
+    ) -> isize {
+        (self
+            .cuFileRead
+            .as_ref()
+            .expect("Expected function, got error."))(
+            fh,
+            devPtr_base,
+            size,
+            file_offset,
+            devPtr_offset,
+        )
+    }
+}
+
+/// The names of libcufile, which has no version in its name on most installs.
+const LIB_NAMES: &[&str] = &["libcufile.so", "libcufile.so.0", "libcufile.so.1"];
+
+/// The cuFile library, or `None` if it isn't installed.
+pub unsafe fn try_lib() -> Option<&'static Lib> {
+    static LIB: std::sync::OnceLock<Option<Lib>> = std::sync::OnceLock::new();
+    LIB.get_or_init(|| {
+        let choices = crate::get_lib_name_candidates("cufile");
+        let extra = LIB_NAMES
+            .iter()
+            .map(|name| std::string::String::from(*name));
+        choices
+            .into_iter()
+            .chain(extra)
+            .find_map(|choice| Lib::new(choice).ok())
+    })
+    .as_ref()
+}
+
+pub unsafe fn lib() -> &'static Lib {
+    match try_lib() {
+        Some(lib) => lib,
+        None => crate::panic_no_lib_found("cufile", LIB_NAMES),
+    }
+}
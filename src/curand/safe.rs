@@ -1,10 +1,48 @@
 //! Safe abstractions around [crate::curand::result] with [CudaRng].
 
 use super::{result, sys};
-use crate::driver::{CudaDevice, CudaSlice, DeviceSlice};
+use crate::driver::{safe::error_handler::OrHandle, CudaDevice, CudaStream, DevicePtrMut};
+use core::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-/// Host side RNG that can fill [CudaSlice] with random values.
+#[cfg(feature = "no-std")]
+use spin::Mutex;
+#[cfg(not(feature = "no-std"))]
+use std::sync::Mutex;
+
+/// The pseudo random generators whose state can be fully reconstructed from a seed
+/// and an offset, see [RngState].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RngType {
+    /// `CURAND_RNG_PSEUDO_XORWOW`, the generator used by [CudaRng::new()].
+    #[default]
+    Xorwow,
+    /// `CURAND_RNG_PSEUDO_PHILOX4_32_10`
+    Philox4_32_10,
+}
+
+impl RngType {
+    fn to_sys(self) -> sys::curandRngType_t {
+        match self {
+            RngType::Xorwow => sys::curandRngType_t::CURAND_RNG_PSEUDO_XORWOW,
+            RngType::Philox4_32_10 => sys::curandRngType_t::CURAND_RNG_PSEUDO_PHILOX4_32_10,
+        }
+    }
+}
+
+/// A checkpoint of a [CudaRng], created with [CudaRng::state()] and restored
+/// with [CudaRng::from_state()].
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RngState {
+    pub generator_type: RngType,
+    pub seed: u64,
+    /// The number of values generated since the generator was seeded.
+    pub offset: u64,
+}
+
+/// Host side RNG that can fill [crate::driver::CudaSlice] with random values.
 ///
 /// 1. Create:
 /// ```rust
@@ -25,65 +63,177 @@ use std::sync::Arc;
 /// 1. Uniform - [CudaRng::fill_with_uniform()]
 /// 2. Normal - [CudaRng::fill_with_normal()]
 /// 3. LogNormal - [CudaRng::fill_with_log_normal()]
+///
+/// A device can have any number of independent generators, and each can generate on its
+/// own stream, see [CudaRng::set_stream()]. Fills take `&self` and only lock the curand
+/// generator itself, so a [CudaRng] can be shared between threads.
+///
+/// A generator's values only depend on its seed and the order of the fills made with it,
+/// not on the stream they run on. Fills from several threads on one generator are ordered
+/// by whichever thread gets there first, so for reproducible results use one generator
+/// per stream or thread, each with its own seed.
 pub struct CudaRng {
     pub(crate) gen: sys::curandGenerator_t,
     pub(crate) device: Arc<CudaDevice>,
+    generator_type: RngType,
+    seed: u64,
+    offset: AtomicU64,
+    lock: Mutex<()>,
 }
 
+unsafe impl Send for CudaRng {}
+unsafe impl Sync for CudaRng {}
+
 impl CudaRng {
     /// Constructs the RNG with the given `seed`. Requires the stream from [CudaDevice] to submit kernels.
     pub fn new(seed: u64, device: Arc<CudaDevice>) -> Result<Self, result::CurandError> {
-        device.bind_to_thread().unwrap();
-        let gen = result::create_generator()?;
-        let mut rng = Self { gen, device };
-        rng.set_seed(seed)?;
+        Self::from_state(
+            &device,
+            RngState {
+                generator_type: RngType::Xorwow,
+                seed,
+                offset: 0,
+            },
+        )
+    }
+
+    /// Recreates the RNG saved with [CudaRng::state()], so that it generates the same
+    /// values the saved RNG would have generated next.
+    ///
+    /// If the `device`'s context can't be bound to the thread, the [crate::driver::DriverError]
+    /// is passed to the [crate::driver::ErrorHandler] and `CURAND_STATUS_INITIALIZATION_FAILED`
+    /// is returned.
+    pub fn from_state(
+        device: &Arc<CudaDevice>,
+        state: RngState,
+    ) -> Result<Self, result::CurandError> {
+        device.bind_to_thread().or_report(
+            "CudaRng::from_state",
+            result::CurandError(sys::curandStatus_t::CURAND_STATUS_INITIALIZATION_FAILED),
+        )?;
+        let gen = result::create_generator_kind(state.generator_type.to_sys())?;
+        let mut rng = Self {
+            gen,
+            device: device.clone(),
+            generator_type: state.generator_type,
+            seed: state.seed,
+            offset: AtomicU64::new(0),
+            lock: Mutex::new(()),
+        };
+        rng.set_seed(state.seed)?;
+        rng.set_offset(state.offset)?;
         unsafe { result::set_stream(rng.gen, rng.device.stream as *mut _) }?;
         Ok(rng)
     }
 
-    /// Re-seed the RNG.
+    /// Saves the seed and the number of values generated so far. The offset is counted
+    /// in elements of the filled [crate::driver::CudaSlice]s, so it only matches what curand consumed
+    /// when every fill since seeding generated 32 bit values, or when the restored RNG
+    /// repeats the same sequence of fills.
+    pub fn state(&self) -> RngState {
+        RngState {
+            generator_type: self.generator_type,
+            seed: self.seed,
+            offset: self.offset.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Re-seed the RNG, restarting its sequence at offset 0 so that it generates the same
+    /// values as a new RNG with `seed`.
     pub fn set_seed(&mut self, seed: u64) -> Result<(), result::CurandError> {
-        unsafe { result::set_seed(self.gen, seed) }
+        unsafe { result::set_seed(self.gen, seed) }?;
+        self.seed = seed;
+        self.set_offset(0)
     }
 
     pub fn set_offset(&mut self, offset: u64) -> Result<(), result::CurandError> {
-        unsafe { result::set_offset(self.gen, offset) }
+        unsafe { result::set_offset(self.gen, offset) }?;
+        *self.offset.get_mut() = offset;
+        Ok(())
     }
 
-    /// Fill the [CudaSlice] with data from a `Uniform` distribution
-    pub fn fill_with_uniform<T>(&self, t: &mut CudaSlice<T>) -> Result<(), result::CurandError>
+    /// Sets the stream the generator's kernels are launched on, either the stream specified,
+    /// or the device's default work stream.
+    ///
+    /// # Safety
+    /// This is unsafe because you can end up scheduling multiple concurrent kernels that all
+    /// write to the same memory address. `stream` must also outlive any use of this generator
+    /// on it.
+    pub unsafe fn set_stream(
+        &self,
+        opt_stream: Option<&CudaStream>,
+    ) -> Result<(), result::CurandError> {
+        let stream = match opt_stream {
+            Some(s) => s.stream,
+            None => self.device.stream,
+        };
+        let _guard = self.lock.lock();
+        #[cfg(not(feature = "no-std"))]
+        let _guard = _guard.unwrap_or_else(|e| e.into_inner());
+        result::set_stream(self.gen, stream as *mut _)
+    }
+
+    fn advance(&self, num: usize) {
+        self.offset.fetch_add(num as u64, Ordering::Relaxed);
+    }
+
+    /// Fill `t`, e.g. a [crate::driver::CudaSlice] or a view, with data from a `Uniform` distribution
+    pub fn fill_with_uniform<T, Dst: DevicePtrMut<T>>(
+        &self,
+        t: &mut Dst,
+    ) -> Result<(), result::CurandError>
     where
         sys::curandGenerator_t: result::UniformFill<T>,
     {
-        unsafe { result::UniformFill::fill(self.gen, t.cu_device_ptr as *mut T, t.len()) }
+        let _guard = self.lock.lock();
+        #[cfg(not(feature = "no-std"))]
+        let _guard = _guard.unwrap_or_else(|e| e.into_inner());
+        let len = t.len();
+        unsafe { result::UniformFill::fill(self.gen, *t.device_ptr_mut() as *mut T, len) }?;
+        self.advance(t.len());
+        Ok(())
     }
 
-    /// Fill the [CudaSlice] with data from a `Normal(mean, std)` distribution.
-    pub fn fill_with_normal<T>(
+    /// Fill `t`, e.g. a [crate::driver::CudaSlice] or a view, with data from a `Normal(mean, std)` distribution.
+    pub fn fill_with_normal<T, Dst: DevicePtrMut<T>>(
         &self,
-        t: &mut CudaSlice<T>,
+        t: &mut Dst,
         mean: T,
         std: T,
     ) -> Result<(), result::CurandError>
     where
         sys::curandGenerator_t: result::NormalFill<T>,
     {
-        unsafe { result::NormalFill::fill(self.gen, t.cu_device_ptr as *mut T, t.len(), mean, std) }
+        let _guard = self.lock.lock();
+        #[cfg(not(feature = "no-std"))]
+        let _guard = _guard.unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            let len = t.len();
+            result::NormalFill::fill(self.gen, *t.device_ptr_mut() as *mut T, len, mean, std)
+        }?;
+        self.advance(t.len());
+        Ok(())
     }
 
-    /// Fill the `CudaRc` with data from a `LogNormal(mean, std)` distribution.
-    pub fn fill_with_log_normal<T>(
+    /// Fill `t`, e.g. a [crate::driver::CudaSlice] or a view, with data from a `LogNormal(mean, std)` distribution.
+    pub fn fill_with_log_normal<T, Dst: DevicePtrMut<T>>(
         &self,
-        t: &mut CudaSlice<T>,
+        t: &mut Dst,
         mean: T,
         std: T,
     ) -> Result<(), result::CurandError>
     where
         sys::curandGenerator_t: result::LogNormalFill<T>,
     {
+        let _guard = self.lock.lock();
+        #[cfg(not(feature = "no-std"))]
+        let _guard = _guard.unwrap_or_else(|e| e.into_inner());
         unsafe {
-            result::LogNormalFill::fill(self.gen, t.cu_device_ptr as *mut T, t.len(), mean, std)
-        }
+            let len = t.len();
+            result::LogNormalFill::fill(self.gen, *t.device_ptr_mut() as *mut T, len, mean, std)
+        }?;
+        self.advance(t.len());
+        Ok(())
     }
 }
 
@@ -203,6 +353,34 @@ mod tests {
         assert_ne!(a_host, b_host);
     }
 
+    #[test]
+    fn test_fill_view() {
+        let dev = CudaDevice::new(0).unwrap();
+        let rng = CudaRng::new(0, dev.clone()).unwrap();
+        let mut a_dev = dev.alloc_zeros::<f32>(10).unwrap();
+        rng.fill_with_uniform(&mut a_dev.slice_mut(4..8)).unwrap();
+        let a_host = dev.sync_reclaim(a_dev).unwrap();
+        assert!(a_host[..4].iter().chain(&a_host[8..]).all(|&x| x == 0.0));
+        assert!(a_host[4..8].iter().all(|&x| x > 0.0 && x <= 1.0));
+        assert_eq!(rng.state().offset, 4);
+    }
+
+    #[test]
+    fn test_set_seed_restarts_sequence() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut rng = CudaRng::new(42, dev.clone()).unwrap();
+        let mut a_dev = dev.alloc_zeros::<f32>(10).unwrap();
+        rng.fill_with_uniform(&mut a_dev).unwrap();
+        let first = dev.dtoh_sync_copy(&a_dev).unwrap();
+        rng.fill_with_uniform(&mut a_dev).unwrap();
+        assert_eq!(rng.state().offset, 20);
+
+        rng.set_seed(42).unwrap();
+        assert_eq!(rng.state().offset, 0);
+        rng.fill_with_uniform(&mut a_dev).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&a_dev).unwrap(), first);
+    }
+
     #[test]
     fn test_set_offset() {
         let dev = CudaDevice::new(0).unwrap();
@@ -223,6 +401,74 @@ mod tests {
         assert_eq!(a_host, b_host);
     }
 
+    #[test]
+    fn test_generate_on_two_streams() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut outs = [
+            dev.alloc_zeros::<f32>(1 << 20).unwrap(),
+            dev.alloc_zeros::<f32>(1 << 20).unwrap(),
+        ];
+        // forked after the allocations, so the streams are ordered after the memsets
+        let streams = [
+            dev.fork_default_stream().unwrap(),
+            dev.fork_default_stream().unwrap(),
+        ];
+        let rngs = [
+            CudaRng::new(1, dev.clone()).unwrap(),
+            CudaRng::new(2, dev.clone()).unwrap(),
+        ];
+        for (rng, stream) in rngs.iter().zip(&streams) {
+            unsafe { rng.set_stream(Some(stream)) }.unwrap();
+        }
+
+        std::thread::scope(|s| {
+            for (rng, out) in rngs.iter().zip(outs.iter_mut()) {
+                let dev = dev.clone();
+                s.spawn(move || {
+                    dev.bind_to_thread().unwrap();
+                    for _ in 0..4 {
+                        rng.fill_with_uniform(out).unwrap();
+                    }
+                });
+            }
+        });
+        for stream in &streams {
+            dev.wait_for(stream).unwrap();
+        }
+        let a = dev.dtoh_sync_copy(&outs[0]).unwrap();
+        let b = dev.dtoh_sync_copy(&outs[1]).unwrap();
+        assert!(a.iter().chain(&b).all(|&x| x > 0.0 && x <= 1.0));
+        assert_ne!(a, b);
+        assert_eq!(rngs[0].state().offset, 4 << 20);
+    }
+
+    #[test]
+    fn test_state_restore() {
+        let dev = CudaDevice::new(0).unwrap();
+        for generator_type in [RngType::Xorwow, RngType::Philox4_32_10] {
+            let state = RngState {
+                generator_type,
+                seed: 7,
+                offset: 0,
+            };
+            let rng = CudaRng::from_state(&dev, state).unwrap();
+            let mut a_dev = dev.alloc_zeros::<u32>(100).unwrap();
+            rng.fill_with_uniform(&mut a_dev).unwrap();
+
+            let checkpoint = rng.state();
+            assert_eq!(checkpoint.offset, 100);
+            let mut b_dev = dev.alloc_zeros::<u32>(64).unwrap();
+            rng.fill_with_uniform(&mut b_dev).unwrap();
+            let expected = dev.sync_reclaim(b_dev).unwrap();
+
+            let restored = CudaRng::from_state(&dev, checkpoint).unwrap();
+            assert_eq!(restored.state(), checkpoint);
+            let mut c_dev = dev.alloc_zeros::<u32>(64).unwrap();
+            restored.fill_with_uniform(&mut c_dev).unwrap();
+            assert_eq!(dev.sync_reclaim(c_dev).unwrap(), expected);
+        }
+    }
+
     const N: usize = 1000;
 
     #[test]
@@ -12,65 +12,831 @@
 //! turns into [stream::create()], where [stream] is a module.
 
 use super::sys::{self, lib};
+use crate::nvrtc::PtxError;
 use core::ffi::{c_uchar, c_uint, c_void, CStr};
 use std::mem::MaybeUninit;
+use std::string::String;
+use std::sync::Arc;
 
-/// Wrapper around [sys::CUresult]. See
+/// Errors returned by the driver API, or by checks cudarc performs before calling into it.
+///
+/// [DriverError::Cuda] wraps a [sys::CUresult]. See
 /// nvidia's [CUresult docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__TYPES.html#group__CUDA__TYPES_1gc6c391505e117393cc2558fff6bfc2e9)
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub struct DriverError(pub sys::CUresult);
+///
+/// New variants may be added in minor releases, match on [DriverError::kind()] to handle
+/// errors the same way across versions.
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DriverError {
+    /// An error code returned by the driver.
+    Cuda(sys::CUresult),
+    /// A kernel launch was rejected before reaching the driver. Apart from
+    /// [InvalidLaunch::ClustersUnsupported] and the shared memory checks, only produced
+    /// when the `launch-validation` feature is enabled.
+    InvalidLaunch(InvalidLaunch),
+    /// A batch of stream memory operations was rejected before reaching the driver.
+    InvalidMemOp(InvalidMemOp),
+    /// The launch configuration exceeds a [LaunchBound] of the function, e.g. a block of 512
+    /// threads for a kernel compiled with `__launch_bounds__(256)`. `function` is the name the
+    /// function was loaded with by `CudaDevice::get_func()` or from a `CudaKernel`.
+    ///
+    /// Checked for every launch except with `LaunchAsync::launch_unchecked()`.
+    LaunchConfigExceedsBounds {
+        bound: LaunchBound,
+        max: u32,
+        requested: u32,
+        function: Option<Arc<str>>,
+    },
+    /// Creating a context on device `ordinal` failed because of its compute mode, e.g.
+    /// another process holds a device in `CU_COMPUTEMODE_EXCLUSIVE_PROCESS` mode.
+    DeviceBusy {
+        ordinal: usize,
+        compute_mode: sys::CUcomputemode,
+    },
+    /// A sticky error like [sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS] from a kernel, along with
+    /// the most recent launches on the device, oldest first. Only produced when launch tracking
+    /// is enabled with `CudaDevice::set_launch_tracking()`.
+    KernelFault {
+        code: sys::CUresult,
+        recent_launches: Arc<[LaunchRecord]>,
+    },
+    /// External memory was imported with a size of 0, or its size could not be determined
+    /// from its file descriptor.
+    InvalidExternalMemorySize,
+    /// The device's context was left unusable by an earlier [DriverError::is_sticky()] error
+    /// `original`, so the operation was not attempted. See `CudaDevice::is_poisoned()`.
+    ContextPoisoned { original: sys::CUresult },
+    /// `cuInit` failed with `code` while creating a `CudaDevice`, e.g. because the kernel
+    /// module isn't loaded or the process can't open `/dev/nvidia*`. Call
+    /// `cudarc::driver::diagnose()` to find out why.
+    InitFailed { code: sys::CUresult },
+    /// The file of a `Ptx::from_file()` couldn't be read, or a `Ptx::from_compressed()`
+    /// couldn't be decompressed, so nothing was passed to the driver.
+    InvalidPtx(PtxError),
+    /// The loaded driver is too old for the operation, it needs the functions of an
+    /// [ApiGroup] introduced in cuda `required`. `found` is the version of the driver as
+    /// `1000 * major + 10 * minor`, or 0 if that is unknown.
+    UnsupportedDriver {
+        required: &'static str,
+        found: core::ffi::c_int,
+    },
+    /// A slice can't be exported as a dma-buf.
+    NotExportable(NotExportable),
+    /// Device `ordinal` was requested, but only `allowed` devices are visible with the
+    /// allowlist set by `cudarc::driver::set_device_allowlist()`.
+    DeviceNotAllowed { ordinal: usize, allowed: usize },
+    /// An external semaphore was signaled or waited on with the wrong kind of value.
+    SemaphoreValue(SemaphoreValue),
+    /// A texture, array or surface doesn't fit the limits of the device, see
+    /// `cudarc::driver::DeviceLimits`.
+    DeviceLimit(DeviceLimitError),
+    /// None of the variants of an `ArchDispatch` covers the compute capability of the
+    /// device. `available` are the ranges of the variants, in the order they were added.
+    NoArchVariant {
+        compute_capability: (i32, i32),
+        available: Arc<[ArchRange]>,
+    },
+    /// A code returned by the driver that isn't one of the [sys::CUresult] constants of the
+    /// cuda version cudarc was built for, e.g. one added by a newer driver. Holds the raw
+    /// value. [sys::CUresult] is a newtype over the `u32` the driver returns, so that such
+    /// codes reach [sys::CUresult::result()] as they are.
+    Unknown(u32),
+    /// One of the kernels cudarc compiles with nvrtc on first use, e.g. for
+    /// `CudaDevice::sort()`, failed to compile. Usually the nvrtc library is older than the
+    /// driver or doesn't support the device.
+    BuiltinCompile(crate::nvrtc::result::NvrtcError),
+}
+
+/// A classification of [DriverError]s that stays the same across cuda versions, returned by
+/// [DriverError::kind()].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Device or host memory ran out.
+    OutOfMemory,
+    /// An argument, handle or launch configuration was rejected, by the driver or by cudarc.
+    InvalidValue,
+    /// The device, driver or binary doesn't support the operation.
+    NotSupported,
+    /// The driver, device or operating system isn't usable, e.g. no device is visible or
+    /// the driver failed to initialize.
+    System,
+    /// The context is unusable after this error, see [DriverError::is_sticky()].
+    Sticky,
+    /// A code cudarc doesn't know, see [DriverError::Unknown], or `CUDA_ERROR_UNKNOWN`.
+    Unknown,
+    /// Any other error, e.g. [sys::CUresult::CUDA_ERROR_NOT_READY].
+    Other,
+}
+
+/// A range of compute capabilities, written as `10 * major + minor`, e.g. `80` for 8.0.
+/// `end` is exclusive, and `None` if the range is open ended.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArchRange {
+    pub start: u32,
+    pub end: Option<u32>,
+}
+
+impl ArchRange {
+    /// Whether `compute_capability` (as `10 * major + minor`) is in the range.
+    pub fn contains(&self, compute_capability: u32) -> bool {
+        self.start <= compute_capability && self.end.is_none_or(|end| compute_capability < end)
+    }
+}
+
+impl std::fmt::Debug for ArchRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sm_{}..", self.start)?;
+        match self.end {
+            Some(end) => write!(f, "sm_{end}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A kernel launch recorded by launch tracking, see [DriverError::KernelFault].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LaunchRecord {
+    /// The number of launches recorded before this one since tracking was enabled.
+    pub seq: u64,
+    /// The name the function was loaded with, if it was loaded into a module of the device.
+    pub function: Option<String>,
+    pub grid_dim: (u32, u32, u32),
+    pub block_dim: (u32, u32, u32),
+    pub shared_mem_bytes: u32,
+    /// The `CudaDevice::scope()`s active during the launch, joined by `/`.
+    pub scope: Option<String>,
+}
+
+/// The reason a kernel launch was rejected by the `launch-validation` checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidLaunch {
+    /// The number of marshalled params does not match the number of params the kernel declares.
+    ParamCount { expected: usize, found: usize },
+    /// Param number `param` is declared by the kernel with `expected` bytes, but the
+    /// signature of a `TypedFunction` gives it `found` bytes. Checked by
+    /// `CudaFunction::typed()` whether or not `launch-validation` is enabled.
+    ParamSize {
+        param: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// Param number `param` points to memory on device `found` instead of the launching device.
+    /// Both are ordinals as in `CudaDevice::ordinal()`, `found` is -1 for a device outside
+    /// the allowlist of `cudarc::driver::set_device_allowlist()`.
+    ForeignPointer {
+        param: usize,
+        expected: i32,
+        found: i32,
+    },
+    /// One of the grid or block dimensions is zero.
+    ZeroDim {
+        grid_dim: (u32, u32, u32),
+        block_dim: (u32, u32, u32),
+    },
+    /// The grid is larger than the device supports.
+    GridDim {
+        requested: (u32, u32, u32),
+        max: (u32, u32, u32),
+    },
+    /// The block is larger than the device supports in one of its dimensions.
+    BlockDim {
+        requested: (u32, u32, u32),
+        max: (u32, u32, u32),
+    },
+    /// More dynamic shared memory was requested than the device supports for the function,
+    /// even after opting in. Checked whether or not `launch-validation` is enabled.
+    SharedMem { requested: u32, max: u32 },
+    /// More dynamic shared memory was requested than the function's current
+    /// `CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES` (48 KB by default), but no more than
+    /// the device's opt-in maximum `opt_in_max`. Raise the attribute with
+    /// `CudaFunction::set_max_dynamic_shared_mem()`, or launch with
+    /// `LaunchConfig::auto_raise_smem(true)` to have it raised automatically. Checked
+    /// whether or not `launch-validation` is enabled.
+    SharedMemNotRaised {
+        requested: u32,
+        max: u32,
+        opt_in_max: u32,
+    },
+    /// Thread block clusters were requested on a device older than compute capability 9.0.
+    /// Checked whether or not `launch-validation` is enabled.
+    ClustersUnsupported { compute_capability: (i32, i32) },
+}
+
+/// The limit of a function exceeded by a [DriverError::LaunchConfigExceedsBounds].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum LaunchBound {
+    /// `x * y * z` of the block is more than `CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK`, which
+    /// is lowered by `__launch_bounds__` and by the registers the kernel uses.
+    ThreadsPerBlock,
+    /// The function was compiled with `__cluster_dims__`, and the `x` dimension of the
+    /// cluster isn't the one it requires. `max` is the required width.
+    ClusterWidth,
+}
+
+/// The reason a batch of stream memory operations was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvalidMemOp {
+    /// Operation number `op` references an address that isn't aligned to `align` bytes.
+    Misaligned {
+        op: usize,
+        addr: sys::CUdeviceptr,
+        align: usize,
+    },
+    /// Operation number `op` references an address the device can't access.
+    Inaccessible { op: usize, addr: sys::CUdeviceptr },
+}
+
+/// Why a slice can't be exported as a dma-buf by `CudaSlice::export_dmabuf()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotExportable {
+    /// The device doesn't report `CU_DEVICE_ATTRIBUTE_DMA_BUF_SUPPORTED`. dma-bufs need a
+    /// Turing or newer GPU and, on most drivers, the open kernel modules.
+    DeviceUnsupported,
+    /// The slice comes from a memory pool, which every allocation of a `CudaDevice` that
+    /// supports pools does. Allocate it with `CudaDevice::alloc_exportable()` instead.
+    PoolAllocation,
+    /// The slice doesn't start on a host page boundary of `page_size` bytes, e.g. because it
+    /// is a sub-allocation of the driver. `CudaDevice::alloc_exportable()` always is aligned.
+    Misaligned { page_size: usize },
+}
+
+/// A dimension checked by `cudarc::driver::DeviceLimits` that is out of the device's limits.
+///
+/// `what` names the dimension, e.g. `"1D linear texture width"`, so that the error reads
+/// like "1D linear texture width 1048576 exceeds max 268435456".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceLimitError {
+    /// `value` is larger than `max`.
+    TooLarge {
+        what: &'static str,
+        value: usize,
+        max: usize,
+    },
+    /// `value` isn't a multiple of `align`.
+    Misaligned {
+        what: &'static str,
+        value: usize,
+        align: usize,
+    },
+}
+
+impl std::fmt::Display for DeviceLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { what, value, max } => {
+                write!(f, "{what} {value} exceeds max {max}")
+            }
+            Self::Misaligned { what, value, align } => {
+                write!(f, "{what} {value} is not a multiple of {align}")
+            }
+        }
+    }
+}
+
+/// Why the value passed to `ExternalSemaphore::signal_async()` or
+/// `ExternalSemaphore::wait_async()` doesn't fit the semaphore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SemaphoreValue {
+    /// The semaphore is a fence or timeline semaphore, which is signaled and waited on with
+    /// a 64-bit value.
+    Missing,
+    /// The semaphore is a binary semaphore, which has no value.
+    Unexpected,
+}
 
 impl sys::CUresult {
+    /// Codes the bindings don't know become [DriverError::Unknown], see
+    /// [DriverError::from_raw()].
     #[inline]
     pub fn result(self) -> Result<(), DriverError> {
-        match self {
-            sys::CUresult::CUDA_SUCCESS => Ok(()),
-            _ => Err(DriverError(self)),
+        match self.0 {
+            0 => Ok(()),
+            raw => Err(DriverError::from_raw(raw)),
         }
     }
 }
 
+/// Prints the name of the code, e.g. `CUDA_ERROR_OUT_OF_MEMORY`, or its value if the
+/// bindings don't know it.
+impl core::fmt::Debug for sys::CUresult {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if *self == sys::CUresult::CUDA_SUCCESS {
+            return f.write_str("CUDA_SUCCESS");
+        }
+        match KNOWN_CODES.iter().find(|(code, _)| code == self) {
+            Some((_, name)) => f.write_str(name),
+            None => write!(f, "CUresult({})", self.0),
+        }
+    }
+}
+
+macro_rules! known_codes {
+    ($($(#[$attr:meta])* $code:ident,)*) => {
+        &[$($(#[$attr])* (sys::CUresult::$code, stringify!($code)),)*]
+    };
+}
+
+/// Every [sys::CUresult] except `CUDA_SUCCESS` and its name, for [DriverError::from_raw()].
+const KNOWN_CODES: &[(sys::CUresult, &str)] = known_codes![
+    CUDA_ERROR_INVALID_VALUE,
+    CUDA_ERROR_OUT_OF_MEMORY,
+    CUDA_ERROR_NOT_INITIALIZED,
+    CUDA_ERROR_DEINITIALIZED,
+    CUDA_ERROR_PROFILER_DISABLED,
+    CUDA_ERROR_PROFILER_NOT_INITIALIZED,
+    CUDA_ERROR_PROFILER_ALREADY_STARTED,
+    CUDA_ERROR_PROFILER_ALREADY_STOPPED,
+    CUDA_ERROR_STUB_LIBRARY,
+    CUDA_ERROR_NO_DEVICE,
+    CUDA_ERROR_INVALID_DEVICE,
+    CUDA_ERROR_DEVICE_NOT_LICENSED,
+    CUDA_ERROR_INVALID_IMAGE,
+    CUDA_ERROR_INVALID_CONTEXT,
+    CUDA_ERROR_CONTEXT_ALREADY_CURRENT,
+    CUDA_ERROR_MAP_FAILED,
+    CUDA_ERROR_UNMAP_FAILED,
+    CUDA_ERROR_ARRAY_IS_MAPPED,
+    CUDA_ERROR_ALREADY_MAPPED,
+    CUDA_ERROR_NO_BINARY_FOR_GPU,
+    CUDA_ERROR_ALREADY_ACQUIRED,
+    CUDA_ERROR_NOT_MAPPED,
+    CUDA_ERROR_NOT_MAPPED_AS_ARRAY,
+    CUDA_ERROR_NOT_MAPPED_AS_POINTER,
+    CUDA_ERROR_ECC_UNCORRECTABLE,
+    CUDA_ERROR_UNSUPPORTED_LIMIT,
+    CUDA_ERROR_CONTEXT_ALREADY_IN_USE,
+    CUDA_ERROR_PEER_ACCESS_UNSUPPORTED,
+    CUDA_ERROR_INVALID_PTX,
+    CUDA_ERROR_INVALID_GRAPHICS_CONTEXT,
+    CUDA_ERROR_NVLINK_UNCORRECTABLE,
+    CUDA_ERROR_JIT_COMPILER_NOT_FOUND,
+    CUDA_ERROR_UNSUPPORTED_PTX_VERSION,
+    CUDA_ERROR_JIT_COMPILATION_DISABLED,
+    CUDA_ERROR_UNSUPPORTED_EXEC_AFFINITY,
+    CUDA_ERROR_INVALID_SOURCE,
+    CUDA_ERROR_FILE_NOT_FOUND,
+    CUDA_ERROR_SHARED_OBJECT_SYMBOL_NOT_FOUND,
+    CUDA_ERROR_SHARED_OBJECT_INIT_FAILED,
+    CUDA_ERROR_OPERATING_SYSTEM,
+    CUDA_ERROR_INVALID_HANDLE,
+    CUDA_ERROR_ILLEGAL_STATE,
+    CUDA_ERROR_NOT_FOUND,
+    CUDA_ERROR_NOT_READY,
+    CUDA_ERROR_ILLEGAL_ADDRESS,
+    CUDA_ERROR_LAUNCH_OUT_OF_RESOURCES,
+    CUDA_ERROR_LAUNCH_TIMEOUT,
+    CUDA_ERROR_LAUNCH_INCOMPATIBLE_TEXTURING,
+    CUDA_ERROR_PEER_ACCESS_ALREADY_ENABLED,
+    CUDA_ERROR_PEER_ACCESS_NOT_ENABLED,
+    CUDA_ERROR_PRIMARY_CONTEXT_ACTIVE,
+    CUDA_ERROR_CONTEXT_IS_DESTROYED,
+    CUDA_ERROR_ASSERT,
+    CUDA_ERROR_TOO_MANY_PEERS,
+    CUDA_ERROR_HOST_MEMORY_ALREADY_REGISTERED,
+    CUDA_ERROR_HOST_MEMORY_NOT_REGISTERED,
+    CUDA_ERROR_HARDWARE_STACK_ERROR,
+    CUDA_ERROR_ILLEGAL_INSTRUCTION,
+    CUDA_ERROR_MISALIGNED_ADDRESS,
+    CUDA_ERROR_INVALID_ADDRESS_SPACE,
+    CUDA_ERROR_INVALID_PC,
+    CUDA_ERROR_LAUNCH_FAILED,
+    CUDA_ERROR_COOPERATIVE_LAUNCH_TOO_LARGE,
+    CUDA_ERROR_NOT_PERMITTED,
+    CUDA_ERROR_NOT_SUPPORTED,
+    CUDA_ERROR_SYSTEM_NOT_READY,
+    CUDA_ERROR_SYSTEM_DRIVER_MISMATCH,
+    CUDA_ERROR_COMPAT_NOT_SUPPORTED_ON_DEVICE,
+    CUDA_ERROR_MPS_CONNECTION_FAILED,
+    CUDA_ERROR_MPS_RPC_FAILURE,
+    CUDA_ERROR_MPS_SERVER_NOT_READY,
+    CUDA_ERROR_MPS_MAX_CLIENTS_REACHED,
+    CUDA_ERROR_MPS_MAX_CONNECTIONS_REACHED,
+    CUDA_ERROR_STREAM_CAPTURE_UNSUPPORTED,
+    CUDA_ERROR_STREAM_CAPTURE_INVALIDATED,
+    CUDA_ERROR_STREAM_CAPTURE_MERGE,
+    CUDA_ERROR_STREAM_CAPTURE_UNMATCHED,
+    CUDA_ERROR_STREAM_CAPTURE_UNJOINED,
+    CUDA_ERROR_STREAM_CAPTURE_ISOLATION,
+    CUDA_ERROR_STREAM_CAPTURE_IMPLICIT,
+    CUDA_ERROR_CAPTURED_EVENT,
+    CUDA_ERROR_STREAM_CAPTURE_WRONG_THREAD,
+    CUDA_ERROR_TIMEOUT,
+    CUDA_ERROR_GRAPH_EXEC_UPDATE_FAILURE,
+    CUDA_ERROR_EXTERNAL_DEVICE,
+    CUDA_ERROR_UNKNOWN,
+    #[cfg(not(any(feature = "cuda-11040", feature = "cuda-11050", feature = "cuda-11060")))]
+    CUDA_ERROR_DEVICE_UNAVAILABLE,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070"
+    )))]
+    CUDA_ERROR_MPS_CLIENT_TERMINATED,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070"
+    )))]
+    CUDA_ERROR_INVALID_CLUSTER_SIZE,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080"
+    )))]
+    CUDA_ERROR_CDP_NOT_SUPPORTED,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080"
+    )))]
+    CUDA_ERROR_CDP_VERSION_MISMATCH,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080",
+        feature = "cuda-12000"
+    )))]
+    CUDA_ERROR_UNSUPPORTED_DEVSIDE_SYNC,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080",
+        feature = "cuda-12000",
+        feature = "cuda-12010",
+        feature = "cuda-12020"
+    )))]
+    CUDA_ERROR_LOSSY_QUERY,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080",
+        feature = "cuda-12000",
+        feature = "cuda-12010",
+        feature = "cuda-12020",
+        feature = "cuda-12030"
+    )))]
+    CUDA_ERROR_FUNCTION_NOT_LOADED,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080",
+        feature = "cuda-12000",
+        feature = "cuda-12010",
+        feature = "cuda-12020",
+        feature = "cuda-12030"
+    )))]
+    CUDA_ERROR_INVALID_RESOURCE_TYPE,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080",
+        feature = "cuda-12000",
+        feature = "cuda-12010",
+        feature = "cuda-12020",
+        feature = "cuda-12030"
+    )))]
+    CUDA_ERROR_INVALID_RESOURCE_CONFIGURATION,
+];
+
 impl DriverError {
+    /// The error for a raw code returned by the driver: [DriverError::Cuda] if it is a
+    /// [sys::CUresult] of the cuda version cudarc was built for, [DriverError::Unknown]
+    /// otherwise. `0` (`CUDA_SUCCESS`) is not an error, and is returned as
+    /// [DriverError::Unknown].
+    pub fn from_raw(raw: u32) -> Self {
+        match KNOWN_CODES.iter().find(|(code, _)| code.0 == raw) {
+            Some((code, _)) => Self::Cuda(*code),
+            None => Self::Unknown(raw),
+        }
+    }
+
+    /// The raw value of [DriverError::code()], or of the code of [DriverError::Unknown].
+    pub fn raw_code(&self) -> u32 {
+        match self {
+            Self::Unknown(raw) => *raw,
+            _ => self.code().0,
+        }
+    }
+
+    /// The [sys::CUresult] closest to this error. Errors that cudarc raises itself
+    /// map to [sys::CUresult::CUDA_ERROR_INVALID_VALUE], which is what the driver
+    /// would most likely have returned.
+    pub fn code(&self) -> sys::CUresult {
+        match self {
+            Self::Cuda(code) => *code,
+            Self::InvalidLaunch(_)
+            | Self::InvalidMemOp(_)
+            | Self::LaunchConfigExceedsBounds { .. }
+            | Self::InvalidExternalMemorySize
+            | Self::NotExportable(_)
+            | Self::SemaphoreValue(_)
+            | Self::DeviceLimit(_) => sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+            #[cfg(any(feature = "cuda-11040", feature = "cuda-11050", feature = "cuda-11060"))]
+            Self::DeviceBusy { .. } => sys::CUresult::CUDA_ERROR_INVALID_DEVICE,
+            #[cfg(not(any(
+                feature = "cuda-11040",
+                feature = "cuda-11050",
+                feature = "cuda-11060"
+            )))]
+            Self::DeviceBusy { .. } => sys::CUresult::CUDA_ERROR_DEVICE_UNAVAILABLE,
+            Self::KernelFault { code, .. } => *code,
+            Self::ContextPoisoned { original } => *original,
+            Self::InitFailed { code } => *code,
+            Self::InvalidPtx(PtxError::Io { .. }) => sys::CUresult::CUDA_ERROR_FILE_NOT_FOUND,
+            Self::InvalidPtx(_) => sys::CUresult::CUDA_ERROR_INVALID_IMAGE,
+            Self::UnsupportedDriver { .. } => sys::CUresult::CUDA_ERROR_NOT_SUPPORTED,
+            Self::DeviceNotAllowed { .. } => sys::CUresult::CUDA_ERROR_INVALID_DEVICE,
+            Self::NoArchVariant { .. } => sys::CUresult::CUDA_ERROR_NO_BINARY_FOR_GPU,
+            Self::BuiltinCompile(_) => sys::CUresult::CUDA_ERROR_INVALID_SOURCE,
+            Self::Unknown(_) => sys::CUresult::CUDA_ERROR_UNKNOWN,
+        }
+    }
+
+    /// What kind of error this is, to match on without listing [sys::CUresult]s that
+    /// differ between cuda versions. Sticky errors are [ErrorKind::Sticky] whatever their
+    /// code, e.g. a [DriverError::KernelFault].
+    pub fn kind(&self) -> ErrorKind {
+        if self.is_sticky() {
+            return ErrorKind::Sticky;
+        }
+        match self {
+            Self::Unknown(_) => return ErrorKind::Unknown,
+            Self::InitFailed { .. } | Self::DeviceBusy { .. } | Self::DeviceNotAllowed { .. } => {
+                return ErrorKind::System
+            }
+            _ => {}
+        }
+        match self.code() {
+            sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY => ErrorKind::OutOfMemory,
+            sys::CUresult::CUDA_ERROR_INVALID_VALUE
+            | sys::CUresult::CUDA_ERROR_INVALID_DEVICE
+            | sys::CUresult::CUDA_ERROR_INVALID_CONTEXT
+            | sys::CUresult::CUDA_ERROR_INVALID_HANDLE
+            | sys::CUresult::CUDA_ERROR_INVALID_IMAGE
+            | sys::CUresult::CUDA_ERROR_INVALID_PTX
+            | sys::CUresult::CUDA_ERROR_INVALID_SOURCE
+            | sys::CUresult::CUDA_ERROR_INVALID_GRAPHICS_CONTEXT
+            | sys::CUresult::CUDA_ERROR_NOT_FOUND
+            | sys::CUresult::CUDA_ERROR_NOT_MAPPED
+            | sys::CUresult::CUDA_ERROR_NOT_MAPPED_AS_ARRAY
+            | sys::CUresult::CUDA_ERROR_NOT_MAPPED_AS_POINTER
+            | sys::CUresult::CUDA_ERROR_ALREADY_MAPPED
+            | sys::CUresult::CUDA_ERROR_ALREADY_ACQUIRED
+            | sys::CUresult::CUDA_ERROR_ILLEGAL_STATE
+            | sys::CUresult::CUDA_ERROR_LAUNCH_OUT_OF_RESOURCES
+            | sys::CUresult::CUDA_ERROR_FILE_NOT_FOUND => ErrorKind::InvalidValue,
+            sys::CUresult::CUDA_ERROR_NOT_SUPPORTED
+            | sys::CUresult::CUDA_ERROR_NO_BINARY_FOR_GPU
+            | sys::CUresult::CUDA_ERROR_UNSUPPORTED_PTX_VERSION
+            | sys::CUresult::CUDA_ERROR_UNSUPPORTED_LIMIT
+            | sys::CUresult::CUDA_ERROR_UNSUPPORTED_EXEC_AFFINITY
+            | sys::CUresult::CUDA_ERROR_JIT_COMPILER_NOT_FOUND
+            | sys::CUresult::CUDA_ERROR_COMPAT_NOT_SUPPORTED_ON_DEVICE
+            | sys::CUresult::CUDA_ERROR_NOT_PERMITTED => ErrorKind::NotSupported,
+            sys::CUresult::CUDA_ERROR_NOT_INITIALIZED
+            | sys::CUresult::CUDA_ERROR_DEINITIALIZED
+            | sys::CUresult::CUDA_ERROR_NO_DEVICE
+            | sys::CUresult::CUDA_ERROR_STUB_LIBRARY
+            | sys::CUresult::CUDA_ERROR_SYSTEM_NOT_READY
+            | sys::CUresult::CUDA_ERROR_SYSTEM_DRIVER_MISMATCH
+            | sys::CUresult::CUDA_ERROR_OPERATING_SYSTEM
+            | sys::CUresult::CUDA_ERROR_SHARED_OBJECT_INIT_FAILED
+            | sys::CUresult::CUDA_ERROR_SHARED_OBJECT_SYMBOL_NOT_FOUND
+            | sys::CUresult::CUDA_ERROR_DEVICE_NOT_LICENSED
+            | sys::CUresult::CUDA_ERROR_MPS_CONNECTION_FAILED
+            | sys::CUresult::CUDA_ERROR_MPS_SERVER_NOT_READY => ErrorKind::System,
+            sys::CUresult::CUDA_ERROR_UNKNOWN => ErrorKind::Unknown,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Whether this error leaves the context unusable, so that every later call on it
+    /// fails too, e.g. [sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS] from a faulting kernel
+    /// or an uncorrectable ECC error. Retrying after one of these is pointless.
+    pub fn is_sticky(&self) -> bool {
+        match self {
+            Self::Cuda(code) => matches!(
+                *code,
+                sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS
+                    | sys::CUresult::CUDA_ERROR_LAUNCH_FAILED
+                    | sys::CUresult::CUDA_ERROR_MISALIGNED_ADDRESS
+                    | sys::CUresult::CUDA_ERROR_ILLEGAL_INSTRUCTION
+                    | sys::CUresult::CUDA_ERROR_INVALID_ADDRESS_SPACE
+                    | sys::CUresult::CUDA_ERROR_INVALID_PC
+                    | sys::CUresult::CUDA_ERROR_HARDWARE_STACK_ERROR
+                    | sys::CUresult::CUDA_ERROR_ASSERT
+                    | sys::CUresult::CUDA_ERROR_LAUNCH_TIMEOUT
+                    | sys::CUresult::CUDA_ERROR_ECC_UNCORRECTABLE
+            ),
+            Self::KernelFault { .. } | Self::ContextPoisoned { .. } => true,
+            _ => false,
+        }
+    }
+
     /// Gets the name for this error.
     ///
     /// See [cuGetErrorName() docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__ERROR.html#group__CUDA__ERROR_1g2c4ac087113652bb3d1f95bf2513c468)
     pub fn error_name(&self) -> Result<&CStr, DriverError> {
-        let mut err_str = MaybeUninit::uninit();
-        unsafe {
-            lib()
-                .cuGetErrorName(self.0, err_str.as_mut_ptr())
-                .result()?;
-            Ok(CStr::from_ptr(err_str.assume_init()))
-        }
+        self.error_text(unsafe { lib() }.cuGetErrorName.as_ref().ok())
     }
 
     /// Gets the error string for this error.
     ///
     /// See [cuGetErrorString() docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__ERROR.html#group__CUDA__ERROR_1g72758fcaf05b5c7fac5c25ead9445ada)
     pub fn error_string(&self) -> Result<&CStr, DriverError> {
+        self.error_text(unsafe { lib() }.cuGetErrorString.as_ref().ok())
+    }
+
+    /// Calls `cuGetErrorName` or `cuGetErrorString` with [DriverError::raw_code()], so that
+    /// a newer driver can still describe a [DriverError::Unknown] code.
+    fn error_text(
+        &self,
+        f: Option<
+            &unsafe extern "C" fn(sys::CUresult, *mut *const core::ffi::c_char) -> sys::CUresult,
+        >,
+    ) -> Result<&'static CStr, DriverError> {
+        let f = f.ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED))?;
         let mut err_str = MaybeUninit::uninit();
         unsafe {
-            lib()
-                .cuGetErrorString(self.0, err_str.as_mut_ptr())
-                .result()?;
+            f(sys::CUresult(self.raw_code()), err_str.as_mut_ptr()).result()?;
             Ok(CStr::from_ptr(err_str.assume_init()))
         }
     }
 }
 
+impl From<InvalidLaunch> for DriverError {
+    fn from(value: InvalidLaunch) -> Self {
+        Self::InvalidLaunch(value)
+    }
+}
+
+impl From<InvalidMemOp> for DriverError {
+    fn from(value: InvalidMemOp) -> Self {
+        Self::InvalidMemOp(value)
+    }
+}
+
+impl From<NotExportable> for DriverError {
+    fn from(value: NotExportable) -> Self {
+        Self::NotExportable(value)
+    }
+}
+
+impl From<SemaphoreValue> for DriverError {
+    fn from(value: SemaphoreValue) -> Self {
+        Self::SemaphoreValue(value)
+    }
+}
+
+impl From<DeviceLimitError> for DriverError {
+    fn from(value: DeviceLimitError) -> Self {
+        Self::DeviceLimit(value)
+    }
+}
+
+impl From<PtxError> for DriverError {
+    fn from(value: PtxError) -> Self {
+        Self::InvalidPtx(value)
+    }
+}
+
 impl std::fmt::Debug for DriverError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.error_string() {
-            Ok(err_str) => f
-                .debug_tuple("DriverError")
-                .field(&self.0)
-                .field(&err_str)
+        match self {
+            Self::Cuda(code) => match self.error_string() {
+                Ok(err_str) => f
+                    .debug_tuple("DriverError")
+                    .field(code)
+                    .field(&err_str)
+                    .finish(),
+                Err(_) => f
+                    .debug_tuple("DriverError")
+                    .field(code)
+                    .field(&"<Failure when calling cuGetErrorString()>")
+                    .finish(),
+            },
+            Self::Unknown(raw) => {
+                let mut s = f.debug_tuple("Unknown");
+                s.field(raw);
+                if let Ok(err_name) = self.error_name() {
+                    s.field(&err_name);
+                }
+                if let Ok(err_str) = self.error_string() {
+                    s.field(&err_str);
+                }
+                s.finish()
+            }
+            Self::InvalidLaunch(reason) => f.debug_tuple("InvalidLaunch").field(reason).finish(),
+            Self::InvalidMemOp(reason) => f.debug_tuple("InvalidMemOp").field(reason).finish(),
+            Self::LaunchConfigExceedsBounds {
+                bound,
+                max,
+                requested,
+                function,
+            } => f
+                .debug_struct("LaunchConfigExceedsBounds")
+                .field("bound", bound)
+                .field("max", max)
+                .field("requested", requested)
+                .field("function", &function.as_deref().unwrap_or("<unknown>"))
+                .finish(),
+            Self::InvalidExternalMemorySize => f.write_str("InvalidExternalMemorySize"),
+            Self::InvalidPtx(reason) => f.debug_tuple("InvalidPtx").field(reason).finish(),
+            Self::BuiltinCompile(nvrtc) => f.debug_tuple("BuiltinCompile").field(nvrtc).finish(),
+            Self::NotExportable(reason) => f.debug_tuple("NotExportable").field(reason).finish(),
+            Self::SemaphoreValue(reason) => f.debug_tuple("SemaphoreValue").field(reason).finish(),
+            Self::DeviceLimit(reason) => write!(f, "DeviceLimit({reason})"),
+            Self::NoArchVariant {
+                compute_capability: (major, minor),
+                available,
+            } => f
+                .debug_struct("NoArchVariant")
+                .field("compute_capability", &format_args!("{major}.{minor}"))
+                .field("available", available)
+                .finish(),
+            Self::DeviceNotAllowed { ordinal, allowed } => f
+                .debug_struct("DeviceNotAllowed")
+                .field("ordinal", ordinal)
+                .field("allowed", allowed)
                 .finish(),
-            Err(_) => f
-                .debug_tuple("DriverError")
-                .field(&self.0)
-                .field(&"<Failure when calling cuGetErrorString()>")
+            Self::UnsupportedDriver { required, found } => f
+                .debug_struct("UnsupportedDriver")
+                .field("required", required)
+                .field(
+                    "found",
+                    &format_args!("{}.{}", found / 1000, found % 1000 / 10),
+                )
                 .finish(),
+            Self::DeviceBusy {
+                ordinal,
+                compute_mode,
+            } => {
+                let help = match compute_mode {
+                    sys::CUcomputemode::CU_COMPUTEMODE_PROHIBITED => {
+                        "the device does not allow contexts to be created"
+                    }
+                    _ => {
+                        "another process is using the device, pick a different ordinal \
+                          (e.g. with CudaDevice::new_first_available()) or ask the admin \
+                          to change the compute mode with `nvidia-smi -c`"
+                    }
+                };
+                f.debug_struct("DeviceBusy")
+                    .field("ordinal", ordinal)
+                    .field("compute_mode", compute_mode)
+                    .field("help", &help)
+                    .finish()
+            }
+            Self::KernelFault {
+                code,
+                recent_launches,
+            } => {
+                let mut s = f.debug_struct("KernelFault");
+                s.field("code", code);
+                if let Ok(err_str) = self.error_string() {
+                    s.field("error", &err_str);
+                }
+                s.field("recent_launches", recent_launches).finish()
+            }
+            Self::ContextPoisoned { original } => {
+                let mut s = f.debug_struct("ContextPoisoned");
+                s.field("original", original);
+                if let Ok(err_str) = self.error_string() {
+                    s.field("error", &err_str);
+                }
+                s.finish()
+            }
+            Self::InitFailed { code } => {
+                let mut s = f.debug_struct("InitFailed");
+                s.field("code", code);
+                if let Ok(err_str) = self.error_string() {
+                    s.field("error", &err_str);
+                }
+                s.field(
+                    "help",
+                    &"call cudarc::driver::diagnose() to find out why the driver could not be initialized",
+                )
+                .finish()
+            }
         }
     }
 }
@@ -93,6 +859,176 @@ pub fn init() -> Result<(), DriverError> {
     unsafe { lib().cuInit(0).result() }
 }
 
+/// The latest CUDA version supported by the driver, as `1000 * major + 10 * minor`.
+///
+/// See [cuDriverGetVersion() docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VERSION.html)
+pub fn driver_version() -> Result<std::ffi::c_int, DriverError> {
+    let mut version = MaybeUninit::uninit();
+    unsafe {
+        lib().cuDriverGetVersion(version.as_mut_ptr()).result()?;
+        Ok(version.assume_init())
+    }
+}
+
+/// Whether the loaded driver library exports the function `name`, e.g. `"cuGreenCtxCreate"`.
+///
+/// Every function is looked up separately when the library is loaded, so a missing one
+/// only fails when it is called. Check this, or [ApiGroup::is_available()], first.
+pub fn is_symbol_available(name: &str) -> bool {
+    let mut symbol = std::vec::Vec::with_capacity(name.len() + 1);
+    symbol.extend_from_slice(name.as_bytes());
+    symbol.push(0);
+    unsafe { sys::library().get::<*const c_void>(&symbol) }.is_ok()
+}
+
+/// A group of driver functions that were introduced together, so that an older driver
+/// either has all of them or none. See [require()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiGroup {
+    /// Devices, contexts, memory, modules and launches.
+    Core,
+    /// `cuMemPool*` and stream ordered allocation.
+    MemoryPools,
+    /// Stream capture and graph instantiation.
+    Graphs,
+    /// Importing external memory and semaphores.
+    ExternalResources,
+    /// `cuLibrary*`, context independent loading.
+    Library,
+    /// Green contexts and device resources.
+    GreenContexts,
+}
+
+/// For every [ApiGroup], in the order they are declared: the driver version that introduced
+/// the group and the functions checked by [ApiGroup::is_available()].
+const API_GROUPS: [(ApiGroup, &str, &[&str]); 6] = [
+    (
+        ApiGroup::Core,
+        // cuDevicePrimaryCtxRetain is the newest of them
+        "7.0",
+        &[
+            "cuInit",
+            "cuDeviceGet",
+            "cuDevicePrimaryCtxRetain",
+            "cuCtxSetCurrent",
+            "cuStreamCreate",
+            "cuMemAlloc_v2",
+            "cuMemcpyHtoD_v2",
+            "cuModuleLoadData",
+            "cuModuleGetFunction",
+            "cuLaunchKernel",
+        ],
+    ),
+    (
+        ApiGroup::MemoryPools,
+        "11.2",
+        &[
+            "cuDeviceGetDefaultMemPool",
+            "cuMemPoolCreate",
+            "cuMemPoolDestroy",
+            "cuMemAllocAsync",
+            "cuMemAllocFromPoolAsync",
+            "cuMemFreeAsync",
+        ],
+    ),
+    (
+        ApiGroup::Graphs,
+        // cuGraphInstantiateWithFlags is the newest of them
+        "11.4",
+        &[
+            "cuStreamBeginCapture_v2",
+            "cuStreamEndCapture",
+            "cuGraphInstantiateWithFlags",
+            "cuGraphLaunch",
+            "cuGraphExecDestroy",
+        ],
+    ),
+    (
+        ApiGroup::ExternalResources,
+        "10.0",
+        &[
+            "cuImportExternalMemory",
+            "cuExternalMemoryGetMappedBuffer",
+            "cuDestroyExternalMemory",
+            "cuImportExternalSemaphore",
+            "cuSignalExternalSemaphoresAsync",
+            "cuWaitExternalSemaphoresAsync",
+        ],
+    ),
+    (
+        ApiGroup::Library,
+        "12.0",
+        &[
+            "cuLibraryLoadData",
+            "cuLibraryGetKernel",
+            "cuKernelGetFunction",
+        ],
+    ),
+    (
+        ApiGroup::GreenContexts,
+        "12.4",
+        &[
+            "cuDeviceGetDevResource",
+            "cuDevSmResourceSplitByCount",
+            "cuDevResourceGenerateDesc",
+            "cuGreenCtxCreate",
+            "cuCtxFromGreenCtx",
+        ],
+    ),
+];
+
+/// [ApiGroup::is_available()] of every group once it has been looked up, indexed like
+/// [API_GROUPS]. The library is only loaded once, so the answer can't change.
+static API_GROUPS_AVAILABLE: [core::sync::atomic::AtomicU8; API_GROUPS.len()] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const UNKNOWN: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0);
+    [UNKNOWN; API_GROUPS.len()]
+};
+
+impl ApiGroup {
+    fn entry(&self) -> &'static (ApiGroup, &'static str, &'static [&'static str]) {
+        &API_GROUPS[*self as usize]
+    }
+
+    /// The oldest driver version exporting every function of the group, as `"major.minor"`.
+    pub fn required_version(&self) -> &'static str {
+        self.entry().1
+    }
+
+    /// The functions checked by [ApiGroup::is_available()].
+    pub fn symbols(&self) -> &'static [&'static str] {
+        self.entry().2
+    }
+
+    /// Whether the loaded driver exports every function of the group. The functions are
+    /// only looked up the first time.
+    pub fn is_available(&self) -> bool {
+        use core::sync::atomic::Ordering;
+        let cached = &API_GROUPS_AVAILABLE[*self as usize];
+        match cached.load(Ordering::Relaxed) {
+            1 => true,
+            2 => false,
+            _ => {
+                let available = self.symbols().iter().all(|name| is_symbol_available(name));
+                cached.store(if available { 1 } else { 2 }, Ordering::Relaxed);
+                available
+            }
+        }
+    }
+}
+
+/// Fails with [DriverError::UnsupportedDriver] if the loaded driver doesn't export
+/// every function of `group`, instead of panicking when one of them is called.
+pub fn require(group: ApiGroup) -> Result<(), DriverError> {
+    if group.is_available() {
+        return Ok(());
+    }
+    Err(DriverError::UnsupportedDriver {
+        required: group.required_version(),
+        found: driver_version().unwrap_or(0),
+    })
+}
+
 pub mod device {
     //! Device management functions (`cuDevice*`).
     //!
@@ -173,6 +1109,35 @@ pub mod device {
         let name = CStr::from_bytes_until_nul(&buf).expect("No null byte was present");
         Ok(String::from_utf8_lossy(name.to_bytes()).into())
     }
+
+    /// Get the UUID of the device.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__DEVICE.html)
+    pub fn get_uuid(dev: sys::CUdevice) -> Result<[u8; 16], DriverError> {
+        let mut uuid = MaybeUninit::<sys::CUuuid>::uninit();
+        unsafe {
+            lib().cuDeviceGetUuid_v2(uuid.as_mut_ptr(), dev).result()?;
+            Ok(uuid.assume_init().bytes.map(|b| b as u8))
+        }
+    }
+
+    /// The widest 1D texture over linear memory of `num_channels` elements of `format`, in
+    /// elements.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__DEVICE.html)
+    pub fn get_texture_1d_linear_max_width(
+        dev: sys::CUdevice,
+        format: sys::CUarray_format,
+        num_channels: u32,
+    ) -> Result<usize, DriverError> {
+        let mut width = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuDeviceGetTexture1DLinearMaxWidth(width.as_mut_ptr(), format, num_channels, dev)
+                .result()?;
+            Ok(width.assume_init())
+        }
+    }
 }
 
 pub mod function {
@@ -195,6 +1160,151 @@ pub mod function {
 
         Ok(())
     }
+
+    /// Gets the specific attribute of a cuda function.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html)
+    ///
+    /// # Safety
+    /// Function must exist.
+    pub unsafe fn get_function_attribute(
+        f: sys::CUfunction,
+        attribute: CUfunction_attribute_enum,
+    ) -> Result<i32, super::DriverError> {
+        let mut value = core::mem::MaybeUninit::uninit();
+        lib()
+            .cuFuncGetAttribute(value.as_mut_ptr(), attribute, f)
+            .result()?;
+        Ok(value.assume_init())
+    }
+
+    /// Sets the preferred split between L1 cache and shared memory for launches of `f`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html)
+    ///
+    /// # Safety
+    /// Function must exist.
+    pub unsafe fn set_cache_config(
+        f: sys::CUfunction,
+        config: sys::CUfunc_cache,
+    ) -> Result<(), super::DriverError> {
+        lib().cuFuncSetCacheConfig(f, config).result()
+    }
+
+    /// Returns the `(offset, size)` in bytes of the kernel parameter at `index`.
+    /// Errors with [sys::CUresult::CUDA_ERROR_INVALID_VALUE] once `index` is past
+    /// the last parameter.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html)
+    /// **Only available in 12.4+.
+    ///
+    /// # Safety
+    /// Function must exist and be a kernel.
+    #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+    pub unsafe fn get_param_info(
+        f: sys::CUfunction,
+        index: usize,
+    ) -> Result<(usize, usize), super::DriverError> {
+        let mut offset = 0;
+        let mut size = 0;
+        lib()
+            .cuFuncGetParamInfo(f, index, &mut offset, &mut size)
+            .result()?;
+        Ok((offset, size))
+    }
+
+    /// Returns the mangled name of the function.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html)
+    /// **Only available in 12.3+.
+    ///
+    /// # Safety
+    /// Function must exist.
+    #[cfg(any(
+        feature = "cuda-12030",
+        feature = "cuda-12040",
+        feature = "cuda-12050",
+        feature = "cuda-12060"
+    ))]
+    pub unsafe fn get_name(f: sys::CUfunction) -> Result<std::string::String, super::DriverError> {
+        let mut name = core::ptr::null();
+        lib().cuFuncGetName(&mut name, f).result()?;
+        Ok(core::ffi::CStr::from_ptr(name)
+            .to_string_lossy()
+            .into_owned())
+    }
+}
+
+pub mod pointer {
+    //! Pointer attribute queries (`cuPointer*`).
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__UNIFIED.html)
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::mem::MaybeUninit;
+
+    /// Returns information about a pointer.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__UNIFIED.html)
+    ///
+    /// # Safety
+    /// `T` must be the type the driver writes for `attribute`, e.g. `c_int` for
+    /// [sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_DEVICE_ORDINAL].
+    pub unsafe fn get_attribute<T>(
+        ptr: sys::CUdeviceptr,
+        attribute: sys::CUpointer_attribute,
+    ) -> Result<T, DriverError> {
+        let mut value = MaybeUninit::<T>::uninit();
+        lib()
+            .cuPointerGetAttribute(value.as_mut_ptr() as *mut _, attribute, ptr)
+            .result()?;
+        Ok(value.assume_init())
+    }
+
+    /// Returns several attributes of a pointer at once, writing attribute `i` to `data[i]`.
+    /// Unlike [get_attribute()], this succeeds for pointers the driver doesn't know about,
+    /// setting their memory type to 0.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__UNIFIED.html)
+    ///
+    /// # Safety
+    /// 1. `attributes` and `data` must have the same length
+    /// 2. Each `data[i]` must point to the type the driver writes for `attributes[i]`
+    pub unsafe fn get_attributes(
+        ptr: sys::CUdeviceptr,
+        attributes: &mut [sys::CUpointer_attribute],
+        data: &mut [*mut core::ffi::c_void],
+    ) -> Result<(), DriverError> {
+        debug_assert_eq!(attributes.len(), data.len());
+        lib()
+            .cuPointerGetAttributes(
+                attributes.len() as core::ffi::c_uint,
+                attributes.as_mut_ptr(),
+                data.as_mut_ptr(),
+                ptr,
+            )
+            .result()
+    }
+
+    /// Returns the base address and size of the allocation containing `ptr`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+    ///
+    /// # Safety
+    /// The current context must be the one `ptr` was allocated in.
+    pub unsafe fn get_address_range(
+        ptr: sys::CUdeviceptr,
+    ) -> Result<(sys::CUdeviceptr, usize), DriverError> {
+        let mut base = 0;
+        let mut size = 0;
+        lib()
+            .cuMemGetAddressRange_v2(&mut base, &mut size, ptr)
+            .result()?;
+        Ok((base, size))
+    }
 }
 
 pub mod occupancy {
@@ -388,6 +1498,18 @@ pub mod primary_ctx {
     pub unsafe fn release(dev: sys::CUdevice) -> Result<(), DriverError> {
         lib().cuDevicePrimaryCtxRelease_v2(dev).result()
     }
+
+    /// Destroys all allocations and resets all state of the primary context of the device.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__PRIMARY__CTX.html)
+    ///
+    /// # Safety
+    ///
+    /// Every resource created in the primary context is invalidated, nothing created
+    /// before the reset may be used after it.
+    pub unsafe fn reset(dev: sys::CUdevice) -> Result<(), DriverError> {
+        lib().cuDevicePrimaryCtxReset_v2(dev).result()
+    }
 }
 
 pub mod ctx {
@@ -429,45 +1551,310 @@ pub mod ctx {
             }
         }
     }
-}
-
-pub mod stream {
-    //! Stream management functions (`cuStream*`).
-    //!
-    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html#group__CUDA__STREAM).
-
-    use super::{
-        sys::{self, lib},
-        DriverError,
-    };
-    use std::mem::MaybeUninit;
 
-    /// The kind of stream to initialize.
+    /// Blocks until all work in the current context has completed.
     ///
-    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html#group__CUDA__STREAM_1ga581f0c5833e21ded8b5a56594e243f4)
-    pub enum StreamKind {
-        /// From cuda docs:
-        /// > Default stream creation flag.
-        Default,
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html)
+    pub fn synchronize() -> Result<(), DriverError> {
+        unsafe { lib().cuCtxSynchronize().result() }
+    }
 
-        /// From cuda docs:
-        /// > Specifies that work running in the created stream
-        /// > may run concurrently with work in stream 0 (the NULL stream),
-        /// > and that the created stream should perform no implicit
-        /// > synchronization with stream 0.
-        NonBlocking,
+    /// Sets a resource limit of the current context.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html)
+    pub fn set_limit(limit: sys::CUlimit, value: usize) -> Result<(), DriverError> {
+        unsafe { lib().cuCtxSetLimit(limit, value).result() }
     }
 
-    impl StreamKind {
-        fn flags(self) -> sys::CUstream_flags {
-            match self {
-                Self::Default => sys::CUstream_flags::CU_STREAM_DEFAULT,
-                Self::NonBlocking => sys::CUstream_flags::CU_STREAM_NON_BLOCKING,
-            }
+    /// Returns a resource limit of the current context.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html)
+    pub fn get_limit(limit: sys::CUlimit) -> Result<usize, DriverError> {
+        let mut value = MaybeUninit::uninit();
+        unsafe {
+            lib().cuCtxGetLimit(value.as_mut_ptr(), limit).result()?;
+            Ok(value.assume_init())
         }
     }
 
-    /// The null stream, which is just a null pointer. **Recommend not using this.**
+    /// Sets the preferred split between L1 cache and shared memory of the current context.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html)
+    pub fn set_cache_config(config: sys::CUfunc_cache) -> Result<(), DriverError> {
+        unsafe { lib().cuCtxSetCacheConfig(config).result() }
+    }
+
+    /// Returns the preferred split between L1 cache and shared memory of the current context.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html)
+    pub fn get_cache_config() -> Result<sys::CUfunc_cache, DriverError> {
+        let mut config = MaybeUninit::uninit();
+        unsafe {
+            lib().cuCtxGetCacheConfig(config.as_mut_ptr()).result()?;
+            Ok(config.assume_init())
+        }
+    }
+
+    /// Creates a new context on `dev`, which is pushed onto the calling thread's context stack.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1g65dc0012348bc84810e2103a40d8e2cf)
+    pub fn create(flags: u32, dev: sys::CUdevice) -> Result<sys::CUcontext, DriverError> {
+        let mut ctx = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuCtxCreate_v2(ctx.as_mut_ptr(), flags, dev)
+                .result()?;
+            Ok(ctx.assume_init())
+        }
+    }
+
+    /// Destroys a context created by [create].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1g27a365aebb0eb548166309f58a1e8b8e)
+    ///
+    /// # Safety
+    /// `ctx` must not be used afterwards, by this or any other thread.
+    pub unsafe fn destroy(ctx: sys::CUcontext) -> Result<(), DriverError> {
+        lib().cuCtxDestroy_v2(ctx).result()
+    }
+
+    /// Pushes `ctx` onto the calling thread's context stack, making it current.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1gb02d4c850eb16f861fe5a29682cc90ba)
+    ///
+    /// # Safety
+    /// `ctx` must be a valid context.
+    pub unsafe fn push_current(ctx: sys::CUcontext) -> Result<(), DriverError> {
+        lib().cuCtxPushCurrent_v2(ctx).result()
+    }
+
+    /// Pops the current context off the calling thread's context stack, making the one
+    /// below it current, and returns it.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1g2fac188026a062d92e91a8687d0a7902)
+    pub fn pop_current() -> Result<sys::CUcontext, DriverError> {
+        let mut ctx = MaybeUninit::uninit();
+        unsafe {
+            lib().cuCtxPopCurrent_v2(ctx.as_mut_ptr()).result()?;
+            Ok(ctx.assume_init())
+        }
+    }
+
+    /// Returns the device of the current context.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1g4e84b109eba36cdaaade167f34ae881e)
+    pub fn get_device() -> Result<sys::CUdevice, DriverError> {
+        let mut dev = MaybeUninit::uninit();
+        unsafe {
+            lib().cuCtxGetDevice(dev.as_mut_ptr()).result()?;
+            Ok(dev.assume_init())
+        }
+    }
+}
+
+#[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+pub mod green_ctx {
+    //! Green contexts, which own a partition of a device's SMs (`cuGreenCtx*` and `cuDevResource*`).
+    //! Added in cuda 12.4.
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::ffi::c_uint;
+    use std::mem::MaybeUninit;
+    use std::vec::Vec;
+
+    /// Whether the loaded driver exports the green context api, i.e. is 12.4 or newer.
+    pub fn is_supported() -> bool {
+        let lib = unsafe { lib() };
+        lib.cuDeviceGetDevResource.is_ok()
+            && lib.cuDevSmResourceSplitByCount.is_ok()
+            && lib.cuDevResourceGenerateDesc.is_ok()
+            && lib.cuGreenCtxCreate.is_ok()
+            && lib.cuCtxFromGreenCtx.is_ok()
+    }
+
+    /// Returns all the SMs of `dev`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    pub fn device_sm_resource(dev: sys::CUdevice) -> Result<sys::CUdevResource, DriverError> {
+        let mut resource = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuDeviceGetDevResource(
+                    dev,
+                    resource.as_mut_ptr(),
+                    sys::CUdevResourceType::CU_DEV_RESOURCE_TYPE_SM,
+                )
+                .result()?;
+            Ok(resource.assume_init())
+        }
+    }
+
+    /// Splits `input` into up to `nb_groups` groups of at least `min_count` SMs each.
+    /// Returns the groups, and the SMs that were left over.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    pub fn split_sm_by_count(
+        input: &sys::CUdevResource,
+        nb_groups: u32,
+        min_count: u32,
+    ) -> Result<(Vec<sys::CUdevResource>, sys::CUdevResource), DriverError> {
+        let mut groups = Vec::with_capacity(nb_groups as usize);
+        let mut nb_groups = nb_groups as c_uint;
+        let mut remaining = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuDevSmResourceSplitByCount(
+                    groups.as_mut_ptr(),
+                    &mut nb_groups,
+                    input,
+                    remaining.as_mut_ptr(),
+                    0,
+                    min_count,
+                )
+                .result()?;
+            groups.set_len(nb_groups as usize);
+            Ok((groups, remaining.assume_init()))
+        }
+    }
+
+    /// Creates a descriptor for a green context owning `resources`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    pub fn generate_desc(
+        resources: &mut [sys::CUdevResource],
+    ) -> Result<sys::CUdevResourceDesc, DriverError> {
+        let mut desc = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuDevResourceGenerateDesc(
+                    desc.as_mut_ptr(),
+                    resources.as_mut_ptr(),
+                    resources.len() as c_uint,
+                )
+                .result()?;
+            Ok(desc.assume_init())
+        }
+    }
+
+    /// Creates a green context on `dev`. The context has its own default stream.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    ///
+    /// # Safety
+    /// `desc` must have been created by [generate_desc()] with resources of `dev`.
+    pub unsafe fn create(
+        desc: sys::CUdevResourceDesc,
+        dev: sys::CUdevice,
+    ) -> Result<sys::CUgreenCtx, DriverError> {
+        let mut green_ctx = MaybeUninit::uninit();
+        lib()
+            .cuGreenCtxCreate(
+                green_ctx.as_mut_ptr(),
+                desc,
+                dev,
+                sys::CUgreenCtxCreate_flags::CU_GREEN_CTX_DEFAULT_STREAM as c_uint,
+            )
+            .result()?;
+        Ok(green_ctx.assume_init())
+    }
+
+    /// Returns the [sys::CUcontext] for a green context, which can be used with the rest
+    /// of the driver api.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    ///
+    /// # Safety
+    /// `green_ctx` must not have been destroyed.
+    pub unsafe fn to_ctx(green_ctx: sys::CUgreenCtx) -> Result<sys::CUcontext, DriverError> {
+        let mut ctx = MaybeUninit::uninit();
+        lib()
+            .cuCtxFromGreenCtx(ctx.as_mut_ptr(), green_ctx)
+            .result()?;
+        Ok(ctx.assume_init())
+    }
+
+    /// Creates a non blocking stream whose work only runs on the SMs of `green_ctx`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    ///
+    /// # Safety
+    /// `green_ctx` must not have been destroyed. With cuda 12.4, `green_ctx` must be
+    /// bound to the calling thread.
+    pub unsafe fn stream_create(green_ctx: sys::CUgreenCtx) -> Result<sys::CUstream, DriverError> {
+        #[cfg(feature = "cuda-12040")]
+        {
+            let _ = green_ctx;
+            super::stream::create(super::stream::StreamKind::NonBlocking)
+        }
+        #[cfg(not(feature = "cuda-12040"))]
+        {
+            let mut stream = MaybeUninit::uninit();
+            lib()
+                .cuGreenCtxStreamCreate(
+                    stream.as_mut_ptr(),
+                    green_ctx,
+                    sys::CUstream_flags::CU_STREAM_NON_BLOCKING as c_uint,
+                    0,
+                )
+                .result()?;
+            Ok(stream.assume_init())
+        }
+    }
+
+    /// Destroys a green context.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+    ///
+    /// # Safety
+    /// `green_ctx` must not have been destroyed already.
+    pub unsafe fn destroy(green_ctx: sys::CUgreenCtx) -> Result<(), DriverError> {
+        lib().cuGreenCtxDestroy(green_ctx).result()
+    }
+}
+
+pub mod stream {
+    //! Stream management functions (`cuStream*`).
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html#group__CUDA__STREAM).
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::ffi::c_uint;
+    use std::mem::MaybeUninit;
+
+    /// The kind of stream to initialize.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html#group__CUDA__STREAM_1ga581f0c5833e21ded8b5a56594e243f4)
+    pub enum StreamKind {
+        /// From cuda docs:
+        /// > Default stream creation flag.
+        Default,
+
+        /// From cuda docs:
+        /// > Specifies that work running in the created stream
+        /// > may run concurrently with work in stream 0 (the NULL stream),
+        /// > and that the created stream should perform no implicit
+        /// > synchronization with stream 0.
+        NonBlocking,
+    }
+
+    impl StreamKind {
+        fn flags(self) -> sys::CUstream_flags {
+            match self {
+                Self::Default => sys::CUstream_flags::CU_STREAM_DEFAULT,
+                Self::NonBlocking => sys::CUstream_flags::CU_STREAM_NON_BLOCKING,
+            }
+        }
+    }
+
+    /// The null stream, which is just a null pointer. **Recommend not using this.**
     ///
     /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/stream-sync-behavior.html#stream-sync-behavior__default-stream)
     pub fn null() -> sys::CUstream {
@@ -527,6 +1914,50 @@ pub mod stream {
             .result()
     }
 
+    /// Begins capturing the work submitted to `stream` into a graph, instead of executing it.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already, and must not be the legacy default stream
+    pub unsafe fn begin_capture(
+        stream: sys::CUstream,
+        mode: sys::CUstreamCaptureMode,
+    ) -> Result<(), DriverError> {
+        lib().cuStreamBeginCapture_v2(stream, mode).result()
+    }
+
+    /// Ends capturing `stream`, returning the captured graph.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already
+    /// 2. The graph must be destroyed with [super::graph::destroy()]
+    pub unsafe fn end_capture(stream: sys::CUstream) -> Result<sys::CUgraph, DriverError> {
+        let mut graph = MaybeUninit::uninit();
+        lib()
+            .cuStreamEndCapture(stream, graph.as_mut_ptr())
+            .result()?;
+        Ok(graph.assume_init())
+    }
+
+    /// Returns whether `stream` is being captured.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already
+    pub unsafe fn is_capturing(
+        stream: sys::CUstream,
+    ) -> Result<sys::CUstreamCaptureStatus, DriverError> {
+        let mut status = MaybeUninit::uninit();
+        lib()
+            .cuStreamIsCapturing(stream, status.as_mut_ptr())
+            .result()?;
+        Ok(status.assume_init())
+    }
+
     /// Attach managed memory to a stream.
     ///
     /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html#group__CUDA__STREAM_1g6e468d680e263e7eba02a56643c50533)
@@ -544,6 +1975,165 @@ pub mod stream {
             .cuStreamAttachMemAsync(stream, dptr, num_bytes, flags as u32)
             .result()
     }
+
+    /// Makes `stream` wait until the 32 bit value at `addr` satisfies `flags`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEMOP.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already
+    /// 2. `addr` must be device accessible memory
+    pub unsafe fn wait_value_32(
+        stream: sys::CUstream,
+        addr: sys::CUdeviceptr,
+        value: u32,
+        flags: sys::CUstreamWaitValue_flags,
+    ) -> Result<(), DriverError> {
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        ))]
+        let res = lib().cuStreamWaitValue32(stream, addr, value, flags as u32);
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let res = lib().cuStreamWaitValue32_v2(stream, addr, value, flags as u32);
+        res.result()
+    }
+
+    /// Makes `stream` wait until the 64 bit value at `addr` satisfies `flags`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEMOP.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already
+    /// 2. `addr` must be device accessible memory and 8 byte aligned
+    pub unsafe fn wait_value_64(
+        stream: sys::CUstream,
+        addr: sys::CUdeviceptr,
+        value: u64,
+        flags: sys::CUstreamWaitValue_flags,
+    ) -> Result<(), DriverError> {
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        ))]
+        let res = lib().cuStreamWaitValue64(stream, addr, value, flags as u32);
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let res = lib().cuStreamWaitValue64_v2(stream, addr, value, flags as u32);
+        res.result()
+    }
+
+    /// Writes a 32 bit `value` to `addr` once all prior work on `stream` is done.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEMOP.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already
+    /// 2. `addr` must be device accessible memory
+    pub unsafe fn write_value_32(
+        stream: sys::CUstream,
+        addr: sys::CUdeviceptr,
+        value: u32,
+        flags: sys::CUstreamWriteValue_flags,
+    ) -> Result<(), DriverError> {
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        ))]
+        let res = lib().cuStreamWriteValue32(stream, addr, value, flags as u32);
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let res = lib().cuStreamWriteValue32_v2(stream, addr, value, flags as u32);
+        res.result()
+    }
+
+    /// Writes a 64 bit `value` to `addr` once all prior work on `stream` is done.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEMOP.html)
+    ///
+    /// # Safety
+    /// 1. The stream must not have been freed already
+    /// 2. `addr` must be device accessible memory and 8 byte aligned
+    pub unsafe fn write_value_64(
+        stream: sys::CUstream,
+        addr: sys::CUdeviceptr,
+        value: u64,
+        flags: sys::CUstreamWriteValue_flags,
+    ) -> Result<(), DriverError> {
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        ))]
+        let res = lib().cuStreamWriteValue64(stream, addr, value, flags as u32);
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let res = lib().cuStreamWriteValue64_v2(stream, addr, value, flags as u32);
+        res.result()
+    }
+
+    /// Enqueues all of `params` as a single batch of memory operations.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEMOP.html)
+    ///
+    /// # Safety
+    /// Every address referenced by `params` must be valid and suitably aligned for
+    /// the lifetime of the operations.
+    pub unsafe fn batch_mem_op(
+        stream: sys::CUstream,
+        params: &mut [sys::CUstreamBatchMemOpParams],
+    ) -> Result<(), DriverError> {
+        let count = params.len() as c_uint;
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        ))]
+        let res = lib().cuStreamBatchMemOp(stream, count, params.as_mut_ptr(), 0);
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let res = lib().cuStreamBatchMemOp_v2(stream, count, params.as_mut_ptr(), 0);
+        res.result()
+    }
 }
 
 /// Allocates memory with stream ordered semantics.
@@ -596,6 +2186,75 @@ pub unsafe fn malloc_managed(
     Ok(dev_ptr.assume_init())
 }
 
+/// Allocates page-locked host memory that the device can access directly.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. The memory return by this is unset, which may be invalid for `T`.
+/// 2. The memory must be freed with [free_host()].
+pub unsafe fn malloc_host(num_bytes: usize, flags: c_uint) -> Result<*mut c_void, DriverError> {
+    let mut host_ptr = MaybeUninit::uninit();
+    lib()
+        .cuMemHostAlloc(host_ptr.as_mut_ptr(), num_bytes, flags)
+        .result()?;
+    Ok(host_ptr.assume_init())
+}
+
+/// Frees host memory allocated with [malloc_host()].
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. Memory must only be freed once, and all copies from it must have completed.
+pub unsafe fn free_host(host_ptr: *mut c_void) -> Result<(), DriverError> {
+    lib().cuMemFreeHost(host_ptr).result()
+}
+
+/// Page-locks `num_bytes` of existing host memory at `host_ptr`, so copies from it
+/// can be asynchronous.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. The memory must stay allocated until it is unregistered with [host_unregister()].
+pub unsafe fn host_register(
+    host_ptr: *mut c_void,
+    num_bytes: usize,
+    flags: c_uint,
+) -> Result<(), DriverError> {
+    lib()
+        .cuMemHostRegister_v2(host_ptr, num_bytes, flags)
+        .result()
+}
+
+/// Unregisters memory registered with [host_register()].
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. All copies from the memory must have completed.
+pub unsafe fn host_unregister(host_ptr: *mut c_void) -> Result<(), DriverError> {
+    lib().cuMemHostUnregister(host_ptr).result()
+}
+
+/// Returns the device pointer of host memory allocated by [malloc_host()] with
+/// [sys::CU_MEMHOSTALLOC_DEVICEMAP].
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. `host_ptr` must have been allocated by [malloc_host()] and not freed.
+pub unsafe fn host_get_device_pointer(
+    host_ptr: *mut c_void,
+) -> Result<sys::CUdeviceptr, DriverError> {
+    let mut dptr = MaybeUninit::uninit();
+    lib()
+        .cuMemHostGetDevicePointer_v2(dptr.as_mut_ptr(), host_ptr, 0)
+        .result()?;
+    Ok(dptr.assume_init())
+}
+
 /// Advise about the usage of a given memory range.
 ///
 /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__UNIFIED.html#group__CUDA__UNIFIED_1g27608c857a9254789c13f3e3b72029e2)
@@ -665,6 +2324,31 @@ pub unsafe fn free_sync(dptr: sys::CUdeviceptr) -> Result<(), DriverError> {
     lib().cuMemFree_v2(dptr).result()
 }
 
+/// Exports `size` bytes of device memory starting at `dptr` as a dma-buf file descriptor.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// `dptr..dptr + size` must be within one allocation from [malloc_sync()] or
+/// [virtual_memory::create()], and both must be aligned to the host page size.
+#[cfg(not(any(feature = "cuda-11040", feature = "cuda-11050", feature = "cuda-11060")))]
+pub unsafe fn get_dmabuf_fd(
+    dptr: sys::CUdeviceptr,
+    size: usize,
+) -> Result<core::ffi::c_int, DriverError> {
+    let mut fd: core::ffi::c_int = -1;
+    lib()
+        .cuMemGetHandleForAddressRange(
+            &mut fd as *mut core::ffi::c_int as *mut c_void,
+            dptr,
+            size,
+            sys::CUmemRangeHandleType::CU_MEM_RANGE_HANDLE_TYPE_DMA_BUF_FD,
+            0,
+        )
+        .result()?;
+    Ok(fd)
+}
+
 /// Frees device memory.
 ///
 /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html#group__CUDA__MEM_1g89b3f154e17cc89b6eea277dbdf5c93a)
@@ -708,17 +2392,123 @@ pub unsafe fn memset_d8_sync(
     lib().cuMemsetD8_v2(dptr, uc, num_bytes).result()
 }
 
-/// Copies memory from Host to Device with stream ordered semantics.
+/// Allocates pitched memory for `height` rows of `width_bytes` bytes each, returning
+/// the pointer and the pitch (in bytes) between rows.
 ///
-/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html#group__CUDA__MEM_1g4d32266788c440b0220b1a9ba5795169)
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html#group__CUDA__MEM_1gcbe9b033f6c5ea8f42e4980d4ecf5504)
 ///
 /// # Safety
-/// **This function is asynchronous** in most cases, so the data from `src`
-/// will be copied at a later point after this function returns.
-///
-/// 1. `T` must be the type that device pointer was allocated with.
-/// 2. The device pointer should not have been freed already (double free)
-/// 3. The stream should be the stream the memory was allocated on.
+/// 1. The memory return by this is unset, which may be invalid for `T`.
+/// 2. `element_size_bytes` must be 4, 8 or 16.
+pub unsafe fn malloc_pitch(
+    width_bytes: usize,
+    height: usize,
+    element_size_bytes: u32,
+) -> Result<(sys::CUdeviceptr, usize), DriverError> {
+    let mut dev_ptr = MaybeUninit::uninit();
+    let mut pitch = MaybeUninit::uninit();
+    lib()
+        .cuMemAllocPitch_v2(
+            dev_ptr.as_mut_ptr(),
+            pitch.as_mut_ptr(),
+            width_bytes,
+            height,
+            element_size_bytes,
+        )
+        .result()?;
+    Ok((dev_ptr.assume_init(), pitch.assume_init()))
+}
+
+/// Sets `height` rows of `width` bytes of pitched device memory with stream ordered semantics.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. The resulting memory pattern may not be valid for `T`.
+/// 2. The device pointer should not have been freed already (double free)
+/// 3. `pitch`, `width` and `height` must be within the allocation.
+pub unsafe fn memset_d2d8_async(
+    dptr: sys::CUdeviceptr,
+    pitch: usize,
+    uc: c_uchar,
+    width: usize,
+    height: usize,
+    stream: sys::CUstream,
+) -> Result<(), DriverError> {
+    lib()
+        .cuMemsetD2D8Async(dptr, pitch, uc, width, height, stream)
+        .result()
+}
+
+/// Sets `height` rows of `width` 16 bit values of pitched device memory with stream
+/// ordered semantics.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. The resulting memory pattern may not be valid for `T`.
+/// 2. The device pointer should not have been freed already (double free)
+/// 3. `pitch`, `width` and `height` must be within the allocation.
+pub unsafe fn memset_d2d16_async(
+    dptr: sys::CUdeviceptr,
+    pitch: usize,
+    us: u16,
+    width: usize,
+    height: usize,
+    stream: sys::CUstream,
+) -> Result<(), DriverError> {
+    lib()
+        .cuMemsetD2D16Async(dptr, pitch, us, width, height, stream)
+        .result()
+}
+
+/// Sets `height` rows of `width` 32 bit values of pitched device memory with stream
+/// ordered semantics.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. The resulting memory pattern may not be valid for `T`.
+/// 2. The device pointer should not have been freed already (double free)
+/// 3. `pitch`, `width` and `height` must be within the allocation.
+pub unsafe fn memset_d2d32_async(
+    dptr: sys::CUdeviceptr,
+    pitch: usize,
+    ui: u32,
+    width: usize,
+    height: usize,
+    stream: sys::CUstream,
+) -> Result<(), DriverError> {
+    lib()
+        .cuMemsetD2D32Async(dptr, pitch, ui, width, height, stream)
+        .result()
+}
+
+/// Copies a 2D region of memory with stream ordered semantics.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html#group__CUDA__MEM_1g4acf155faeb969d9d21f5433d3d0f274)
+///
+/// # Safety
+/// 1. The source and destination described by `copy` must be valid for the whole copy.
+/// 2. Host memory in `copy` must not be moved or freed until the copy has completed.
+pub unsafe fn memcpy_2d_async(
+    copy: &sys::CUDA_MEMCPY2D,
+    stream: sys::CUstream,
+) -> Result<(), DriverError> {
+    lib().cuMemcpy2DAsync_v2(copy, stream).result()
+}
+
+/// Copies memory from Host to Device with stream ordered semantics.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html#group__CUDA__MEM_1g4d32266788c440b0220b1a9ba5795169)
+///
+/// # Safety
+/// **This function is asynchronous** in most cases, so the data from `src`
+/// will be copied at a later point after this function returns.
+///
+/// 1. `T` must be the type that device pointer was allocated with.
+/// 2. The device pointer should not have been freed already (double free)
+/// 3. The stream should be the stream the memory was allocated on.
 /// 4. `src` must not be moved
 pub unsafe fn memcpy_htod_async<T>(
     dst: sys::CUdeviceptr,
@@ -810,6 +2600,80 @@ pub unsafe fn memcpy_dtod_async(
         .result()
 }
 
+/// `CUmemcpyAttributes` of CUDA 12.8, which the bindings don't include yet.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct MemcpyAttributes {
+    src_access_order: c_uint,
+    src_loc_hint: sys::CUmemLocation,
+    dst_loc_hint: sys::CUmemLocation,
+    flags: c_uint,
+}
+
+/// `CU_MEMCPY_SRC_ACCESS_ORDER_STREAM`, the sources are read in stream order.
+const MEMCPY_SRC_ACCESS_ORDER_STREAM: c_uint = 1;
+
+type MemcpyBatchAsyncFn = unsafe extern "C" fn(
+    *mut sys::CUdeviceptr,
+    *mut sys::CUdeviceptr,
+    *mut usize,
+    usize,
+    *mut MemcpyAttributes,
+    *mut usize,
+    usize,
+    *mut usize,
+    sys::CUstream,
+) -> sys::CUresult;
+
+/// Copies `sizes[i]` bytes from `srcs[i]` to `dsts[i]` for every `i` with stream ordered
+/// semantics, in a single `cuMemcpyBatchAsync` call. It was added in CUDA 12.8 and is
+/// looked up by name the first time, since the bindings don't include it yet: returns
+/// [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the driver doesn't export it.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+///
+/// # Safety
+/// 1. Each source and destination must be valid for its size until the copy has completed.
+/// 2. `stream` must not be the legacy default stream, which `cuMemcpyBatchAsync` rejects.
+pub unsafe fn memcpy_dtod_batch_async(
+    dsts: &[sys::CUdeviceptr],
+    srcs: &[sys::CUdeviceptr],
+    sizes: &[usize],
+    stream: sys::CUstream,
+) -> Result<(), DriverError> {
+    static BATCH_ASYNC: std::sync::OnceLock<Option<MemcpyBatchAsyncFn>> =
+        std::sync::OnceLock::new();
+    if dsts.len() != srcs.len() || dsts.len() != sizes.len() {
+        return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+    }
+    let f = BATCH_ASYNC.get_or_init(|| {
+        sys::library()
+            .get::<MemcpyBatchAsyncFn>(b"cuMemcpyBatchAsync\0")
+            .ok()
+            .map(|f| *f)
+    });
+    let f = f.ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED))?;
+    let mut attrs = MemcpyAttributes {
+        src_access_order: MEMCPY_SRC_ACCESS_ORDER_STREAM,
+        ..Default::default()
+    };
+    // every copy uses `attrs`
+    let mut attrs_idx = 0usize;
+    let mut fail_idx = 0usize;
+    f(
+        dsts.as_ptr() as *mut _,
+        srcs.as_ptr() as *mut _,
+        sizes.as_ptr() as *mut _,
+        dsts.len(),
+        &mut attrs,
+        &mut attrs_idx,
+        1,
+        &mut fail_idx,
+        stream,
+    )
+    .result()
+}
+
 /// Copies memory from Device to Device
 ///
 /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html#group__CUDA__MEM_1g1725774abf8b51b91945f3336b778c8b)
@@ -835,78 +2699,729 @@ pub fn mem_get_info() -> Result<(usize, usize), DriverError> {
     Ok((free, total))
 }
 
-pub mod module {
-    //! Module management functions (`cuModule*`).
+pub mod mem_pool {
+    //! Stream-ordered memory pools (`cuMemPool*`).
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::{ffi::c_void, mem::MaybeUninit};
+
+    /// The pool `cuMemAllocAsync` allocates from on `device`, unless changed with `cuDeviceSetMemPool`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__DEVICE.html)
+    pub fn get_default(device: sys::CUdevice) -> Result<sys::CUmemoryPool, DriverError> {
+        let mut pool = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuDeviceGetDefaultMemPool(pool.as_mut_ptr(), device)
+                .result()?;
+            Ok(pool.assume_init())
+        }
+    }
+
+    /// Creates a pool with `props`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// The pool must be destroyed with [destroy()].
+    pub unsafe fn create(props: &sys::CUmemPoolProps) -> Result<sys::CUmemoryPool, DriverError> {
+        let mut pool = MaybeUninit::uninit();
+        lib().cuMemPoolCreate(pool.as_mut_ptr(), props).result()?;
+        Ok(pool.assume_init())
+    }
+
+    /// Destroys a pool created with [create()]. Memory still allocated from it is freed
+    /// once it is released.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// `pool` must not have been destroyed, and must not be the default pool.
+    pub unsafe fn destroy(pool: sys::CUmemoryPool) -> Result<(), DriverError> {
+        lib().cuMemPoolDestroy(pool).result()
+    }
+
+    /// Releases reserved memory until the pool holds at most `min_bytes_to_keep` bytes
+    /// that aren't in use.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// `pool` must not have been destroyed.
+    pub unsafe fn trim_to(
+        pool: sys::CUmemoryPool,
+        min_bytes_to_keep: usize,
+    ) -> Result<(), DriverError> {
+        lib().cuMemPoolTrimTo(pool, min_bytes_to_keep).result()
+    }
+
+    /// Reads a `cuuint64_t` attribute, i.e. the release threshold or the reserved/used counters.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// `pool` must not have been destroyed, and `attr` must be a 64 bit attribute.
+    pub unsafe fn get_attribute_u64(
+        pool: sys::CUmemoryPool,
+        attr: sys::CUmemPool_attribute,
+    ) -> Result<u64, DriverError> {
+        let mut value: u64 = 0;
+        lib()
+            .cuMemPoolGetAttribute(pool, attr, &mut value as *mut u64 as *mut c_void)
+            .result()?;
+        Ok(value)
+    }
+
+    /// Sets a `cuuint64_t` attribute. The `*_HIGH` counters can only be reset to 0.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// `pool` must not have been destroyed, and `attr` must be a 64 bit attribute.
+    pub unsafe fn set_attribute_u64(
+        pool: sys::CUmemoryPool,
+        attr: sys::CUmemPool_attribute,
+        mut value: u64,
+    ) -> Result<(), DriverError> {
+        lib()
+            .cuMemPoolSetAttribute(pool, attr, &mut value as *mut u64 as *mut c_void)
+            .result()
+    }
+
+    /// Allocates `num_bytes` from `pool`, ordered on `stream`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// 1. `pool` must not have been destroyed
+    /// 2. The memory must be freed with [super::free_async()] or [super::free_sync()]
+    pub unsafe fn alloc_async(
+        pool: sys::CUmemoryPool,
+        num_bytes: usize,
+        stream: sys::CUstream,
+    ) -> Result<sys::CUdeviceptr, DriverError> {
+        let mut dev_ptr = MaybeUninit::uninit();
+        lib()
+            .cuMemAllocFromPoolAsync(dev_ptr.as_mut_ptr(), num_bytes, pool, stream)
+            .result()?;
+        Ok(dev_ptr.assume_init())
+    }
+
+    /// Exports `pool` as an OS handle another process can import with
+    /// [import_from_shareable_handle()]. The handle is written to `handle_out`, which must
+    /// point to an `int` for file descriptors and a `HANDLE` for win32 handles.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// 1. `pool` must not have been destroyed, and have been created with `handle_type`
+    /// 2. `handle_out` must be valid for the handle type
+    pub unsafe fn export_to_shareable_handle(
+        pool: sys::CUmemoryPool,
+        handle_type: sys::CUmemAllocationHandleType,
+        handle_out: *mut c_void,
+    ) -> Result<(), DriverError> {
+        lib()
+            .cuMemPoolExportToShareableHandle(handle_out, pool, handle_type, 0)
+            .result()
+    }
+
+    /// Imports a pool exported with [export_to_shareable_handle()]. `handle` is the file
+    /// descriptor cast to a pointer, or the win32 `HANDLE`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// The pool must be destroyed with [destroy()].
+    pub unsafe fn import_from_shareable_handle(
+        handle: *mut c_void,
+        handle_type: sys::CUmemAllocationHandleType,
+    ) -> Result<sys::CUmemoryPool, DriverError> {
+        let mut pool = MaybeUninit::uninit();
+        lib()
+            .cuMemPoolImportFromShareableHandle(pool.as_mut_ptr(), handle, handle_type, 0)
+            .result()?;
+        Ok(pool.assume_init())
+    }
+
+    /// Exports an allocation of an exported pool, to be imported with [import_pointer()].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated from a pool that can be exported.
+    pub unsafe fn export_pointer(
+        ptr: sys::CUdeviceptr,
+    ) -> Result<sys::CUmemPoolPtrExportData, DriverError> {
+        let mut data = MaybeUninit::uninit();
+        lib()
+            .cuMemPoolExportPointer(data.as_mut_ptr(), ptr)
+            .result()?;
+        Ok(data.assume_init())
+    }
+
+    /// Imports an allocation exported with [export_pointer()] from the imported `pool`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html)
+    ///
+    /// # Safety
+    /// 1. `pool` must have been imported from the pool the allocation was exported from
+    /// 2. The memory must be freed with [super::free_async()] or [super::free_sync()]
+    ///    before the exporting process frees it
+    pub unsafe fn import_pointer(
+        pool: sys::CUmemoryPool,
+        mut data: sys::CUmemPoolPtrExportData,
+    ) -> Result<sys::CUdeviceptr, DriverError> {
+        let mut ptr = MaybeUninit::uninit();
+        lib()
+            .cuMemPoolImportPointer(ptr.as_mut_ptr(), pool, &mut data)
+            .result()?;
+        Ok(ptr.assume_init())
+    }
+}
+
+pub mod virtual_memory {
+    //! Virtual memory management (`cuMemCreate`, `cuMemMap`, ...), where physical memory is
+    //! allocated and mapped into a reserved address range separately.
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::mem::MaybeUninit;
+
+    /// The minimum granularity of sizes and addresses of allocations with `prop`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    pub fn granularity(prop: &sys::CUmemAllocationProp) -> Result<usize, DriverError> {
+        let mut granularity = 0;
+        unsafe {
+            lib()
+                .cuMemGetAllocationGranularity(
+                    &mut granularity,
+                    prop,
+                    sys::CUmemAllocationGranularity_flags::CU_MEM_ALLOC_GRANULARITY_MINIMUM,
+                )
+                .result()?;
+        }
+        Ok(granularity)
+    }
+
+    /// Allocates `size` bytes of physical memory with `prop`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// 1. `size` must be a multiple of [granularity()].
+    /// 2. The handle must be released with [release()].
+    pub unsafe fn create(
+        size: usize,
+        prop: &sys::CUmemAllocationProp,
+    ) -> Result<sys::CUmemGenericAllocationHandle, DriverError> {
+        let mut handle = MaybeUninit::uninit();
+        lib()
+            .cuMemCreate(handle.as_mut_ptr(), size, prop, 0)
+            .result()?;
+        Ok(handle.assume_init())
+    }
+
+    /// Releases a handle from [create()]. The memory is freed once it is no longer mapped.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// `handle` must not have been released already.
+    pub unsafe fn release(handle: sys::CUmemGenericAllocationHandle) -> Result<(), DriverError> {
+        lib().cuMemRelease(handle).result()
+    }
+
+    /// Reserves `size` bytes of virtual addresses aligned to `alignment`, or to
+    /// [granularity()] if it is 0.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// The range must be freed with [address_free()].
+    pub unsafe fn address_reserve(
+        size: usize,
+        alignment: usize,
+    ) -> Result<sys::CUdeviceptr, DriverError> {
+        let mut ptr = MaybeUninit::uninit();
+        lib()
+            .cuMemAddressReserve(ptr.as_mut_ptr(), size, alignment, 0, 0)
+            .result()?;
+        Ok(ptr.assume_init())
+    }
+
+    /// Frees a range from [address_reserve()].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// `ptr` and `size` must be those of a reserved range, that is no longer mapped.
+    pub unsafe fn address_free(ptr: sys::CUdeviceptr, size: usize) -> Result<(), DriverError> {
+        lib().cuMemAddressFree(ptr, size).result()
+    }
+
+    /// Maps all `size` bytes of `handle` at `ptr`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// `ptr..ptr + size` must be within a reserved range that isn't mapped yet.
+    pub unsafe fn map(
+        ptr: sys::CUdeviceptr,
+        size: usize,
+        handle: sys::CUmemGenericAllocationHandle,
+    ) -> Result<(), DriverError> {
+        lib().cuMemMap(ptr, size, 0, handle, 0).result()
+    }
+
+    /// Unmaps `ptr..ptr + size`. This doesn't wait for work using the memory.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// The range must have been mapped with [map()], and no work may still use it.
+    pub unsafe fn unmap(ptr: sys::CUdeviceptr, size: usize) -> Result<(), DriverError> {
+        lib().cuMemUnmap(ptr, size).result()
+    }
+
+    /// Makes `ptr..ptr + size` readable and writable from `device`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__VA.html)
+    ///
+    /// # Safety
+    /// The range must have been mapped with [map()].
+    pub unsafe fn set_read_write(
+        ptr: sys::CUdeviceptr,
+        size: usize,
+        device: sys::CUdevice,
+    ) -> Result<(), DriverError> {
+        let desc = sys::CUmemAccessDesc {
+            location: sys::CUmemLocation {
+                type_: sys::CUmemLocationType::CU_MEM_LOCATION_TYPE_DEVICE,
+                id: device,
+            },
+            flags: sys::CUmemAccess_flags::CU_MEM_ACCESS_FLAGS_PROT_READWRITE,
+        };
+        lib().cuMemSetAccess(ptr, size, &desc, 1).result()
+    }
+}
+
+pub mod module {
+    //! Module management functions (`cuModule*`).
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE)
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::ffi::c_void;
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+
+    /// Loads a compute module from a given file.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1g366093bd269dafd0af21f1c7d18115d3)
+    pub fn load(fname: CString) -> Result<sys::CUmodule, DriverError> {
+        let fname_ptr = fname.as_c_str().as_ptr();
+        let mut module = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .cuModuleLoad(module.as_mut_ptr(), fname_ptr)
+                .result()?;
+            Ok(module.assume_init())
+        }
+    }
+
+    /// Load a module's data:
+    ///
+    /// > The pointer may be obtained by mapping a cubin or PTX or fatbin file,
+    /// > passing a cubin or PTX or fatbin file as a NULL-terminated text string,
+    /// > or incorporating a cubin or fatbin object into the executable resources
+    /// > and using operating system calls such as Windows FindResource() to obtain the pointer.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1g04ce266ce03720f479eab76136b90c0b)
+    ///
+    /// # Safety
+    /// The image must be properly formed pointer
+    pub unsafe fn load_data(image: *const c_void) -> Result<sys::CUmodule, DriverError> {
+        let mut module = MaybeUninit::uninit();
+        lib()
+            .cuModuleLoadData(module.as_mut_ptr(), image)
+            .result()?;
+        Ok(module.assume_init())
+    }
+
+    /// Load a module's data with jit options. `options` and `values` must be the
+    /// same length.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html)
+    ///
+    /// # Safety
+    /// The image must be properly formed pointer, and `values` must be valid for `options`.
+    pub unsafe fn load_data_ex(
+        image: *const c_void,
+        options: &mut [sys::CUjit_option],
+        values: &mut [*mut c_void],
+    ) -> Result<sys::CUmodule, DriverError> {
+        assert_eq!(options.len(), values.len());
+        let mut module = MaybeUninit::uninit();
+        lib()
+            .cuModuleLoadDataEx(
+                module.as_mut_ptr(),
+                image,
+                options.len() as core::ffi::c_uint,
+                options.as_mut_ptr(),
+                values.as_mut_ptr(),
+            )
+            .result()?;
+        Ok(module.assume_init())
+    }
+
+    /// Returns a function handle from the given module.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1ga52be009b0d4045811b30c965e1cb2cf)
+    ///
+    /// # Safety
+    /// `module` must be a properly allocated and not freed module.
+    pub unsafe fn get_function(
+        module: sys::CUmodule,
+        name: CString,
+    ) -> Result<sys::CUfunction, DriverError> {
+        let name_ptr = name.as_c_str().as_ptr();
+        let mut func = MaybeUninit::uninit();
+        lib()
+            .cuModuleGetFunction(func.as_mut_ptr(), module, name_ptr)
+            .result()?;
+        Ok(func.assume_init())
+    }
+
+    /// Whether the driver can enumerate the functions of a module with
+    /// [enumerate_functions()] and name them with [super::function::get_name()].
+    #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+    pub fn can_enumerate_functions() -> bool {
+        let lib = unsafe { lib() };
+        lib.cuModuleGetFunctionCount.is_ok()
+            && lib.cuModuleEnumerateFunctions.is_ok()
+            && lib.cuFuncGetName.is_ok()
+    }
+
+    /// Returns handles to every function in the module.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html)
+    /// **Only available in 12.4+.
+    ///
+    /// # Safety
+    /// `module` must be a properly allocated and not freed module.
+    #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+    pub unsafe fn enumerate_functions(
+        module: sys::CUmodule,
+    ) -> Result<std::vec::Vec<sys::CUfunction>, DriverError> {
+        let mut count = 0;
+        lib()
+            .cuModuleGetFunctionCount(&mut count, module)
+            .result()?;
+        let mut functions = std::vec![core::ptr::null_mut(); count as usize];
+        lib()
+            .cuModuleEnumerateFunctions(functions.as_mut_ptr(), count, module)
+            .result()?;
+        Ok(functions)
+    }
+
+    /// Returns the device pointer and size in bytes of the global variable `name` of the
+    /// module, e.g. a `__device__` or `__constant__` variable.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html)
+    ///
+    /// # Safety
+    /// `module` must be a properly allocated and not freed module.
+    pub unsafe fn get_global(
+        module: sys::CUmodule,
+        name: CString,
+    ) -> Result<(sys::CUdeviceptr, usize), DriverError> {
+        let name_ptr = name.as_c_str().as_ptr();
+        let mut dptr = MaybeUninit::uninit();
+        let mut bytes = MaybeUninit::uninit();
+        lib()
+            .cuModuleGetGlobal_v2(dptr.as_mut_ptr(), bytes.as_mut_ptr(), module, name_ptr)
+            .result()?;
+        Ok((dptr.assume_init(), bytes.assume_init()))
+    }
+
+    /// Unloads a module.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1g8ea3d716524369de3763104ced4ea57b)
+    ///
+    /// # Safety
+    /// `module` must not have be unloaded already.
+    pub unsafe fn unload(module: sys::CUmodule) -> Result<(), DriverError> {
+        lib().cuModuleUnload(module).result()
+    }
+}
+
+#[cfg(not(any(
+    feature = "cuda-11040",
+    feature = "cuda-11050",
+    feature = "cuda-11060",
+    feature = "cuda-11070",
+    feature = "cuda-11080"
+)))]
+pub mod library {
+    //! Context independent module management (`cuLibrary*` and `cuKernel*`). Added in cuda 12.0.
+    //!
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__LIBRARY.html)
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+    use core::ffi::{c_uint, c_void};
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+
+    /// Whether the loaded driver exports the library api, i.e. is 12.0 or newer.
+    pub fn is_supported() -> bool {
+        let lib = unsafe { lib() };
+        lib.cuLibraryLoadData.is_ok()
+            && lib.cuLibraryGetKernel.is_ok()
+            && lib.cuKernelGetFunction.is_ok()
+    }
+
+    /// Loads a library from a cubin, fatbin or null terminated ptx. `options` and
+    /// `values` are jit options and must be the same length.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__LIBRARY.html)
+    ///
+    /// # Safety
+    /// `code` must be a properly formed image, and `values` must be valid for `options`.
+    pub unsafe fn load_data(
+        code: *const c_void,
+        options: &mut [sys::CUjit_option],
+        values: &mut [*mut c_void],
+    ) -> Result<sys::CUlibrary, DriverError> {
+        assert_eq!(options.len(), values.len());
+        let mut library = MaybeUninit::uninit();
+        lib()
+            .cuLibraryLoadData(
+                library.as_mut_ptr(),
+                code,
+                options.as_mut_ptr(),
+                values.as_mut_ptr(),
+                options.len() as c_uint,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+            )
+            .result()?;
+        Ok(library.assume_init())
+    }
+
+    /// Returns the kernel named `name` in `library`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__LIBRARY.html)
+    ///
+    /// # Safety
+    /// `library` must be loaded and not unloaded.
+    pub unsafe fn get_kernel(
+        library: sys::CUlibrary,
+        name: CString,
+    ) -> Result<sys::CUkernel, DriverError> {
+        let mut kernel = MaybeUninit::uninit();
+        lib()
+            .cuLibraryGetKernel(kernel.as_mut_ptr(), library, name.as_ptr())
+            .result()?;
+        Ok(kernel.assume_init())
+    }
+
+    /// Returns the function for `kernel` in the current context, loading it
+    /// into the context if needed.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__LIBRARY.html)
+    ///
+    /// # Safety
+    /// The library of `kernel` must not be unloaded.
+    pub unsafe fn kernel_get_function(
+        kernel: sys::CUkernel,
+    ) -> Result<sys::CUfunction, DriverError> {
+        let mut func = MaybeUninit::uninit();
+        lib()
+            .cuKernelGetFunction(func.as_mut_ptr(), kernel)
+            .result()?;
+        Ok(func.assume_init())
+    }
+
+    /// Unloads a library.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__LIBRARY.html)
+    ///
+    /// # Safety
+    /// `library` must not have been unloaded already.
+    pub unsafe fn unload(library: sys::CUlibrary) -> Result<(), DriverError> {
+        lib().cuLibraryUnload(library).result()
+    }
+}
+
+pub mod graph {
+    //! Executing graphs, e.g. captured with [super::stream::begin_capture()].
     //!
-    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE)
+    //! See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
 
     use super::{
         sys::{self, lib},
         DriverError,
     };
-    use core::ffi::c_void;
-    use std::ffi::CString;
-    use std::mem::MaybeUninit;
+    use core::mem::MaybeUninit;
 
-    /// Loads a compute module from a given file.
+    /// Creates an executable graph from `graph`.
     ///
-    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1g366093bd269dafd0af21f1c7d18115d3)
-    pub fn load(fname: CString) -> Result<sys::CUmodule, DriverError> {
-        let fname_ptr = fname.as_c_str().as_ptr();
-        let mut module = MaybeUninit::uninit();
-        unsafe {
-            lib()
-                .cuModuleLoad(module.as_mut_ptr(), fname_ptr)
-                .result()?;
-            Ok(module.assume_init())
-        }
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
+    ///
+    /// # Safety
+    /// 1. `graph` must not have been destroyed
+    /// 2. The executable graph must be destroyed with [exec_destroy()]
+    pub unsafe fn instantiate(
+        graph: sys::CUgraph,
+        flags: u64,
+    ) -> Result<sys::CUgraphExec, DriverError> {
+        let mut exec = MaybeUninit::uninit();
+        lib()
+            .cuGraphInstantiateWithFlags(exec.as_mut_ptr(), graph, flags)
+            .result()?;
+        Ok(exec.assume_init())
     }
 
-    /// Load a module's data:
+    /// Launches an executable graph on `stream`.
     ///
-    /// > The pointer may be obtained by mapping a cubin or PTX or fatbin file,
-    /// > passing a cubin or PTX or fatbin file as a NULL-terminated text string,
-    /// > or incorporating a cubin or fatbin object into the executable resources
-    /// > and using operating system calls such as Windows FindResource() to obtain the pointer.
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
     ///
-    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1g04ce266ce03720f479eab76136b90c0b)
+    /// # Safety
+    /// 1. `exec` and `stream` must not have been destroyed
+    pub unsafe fn launch(exec: sys::CUgraphExec, stream: sys::CUstream) -> Result<(), DriverError> {
+        lib().cuGraphLaunch(exec, stream).result()
+    }
+
+    /// Returns the number of nodes in `graph`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
     ///
     /// # Safety
-    /// The image must be properly formed pointer
-    pub unsafe fn load_data(image: *const c_void) -> Result<sys::CUmodule, DriverError> {
-        let mut module = MaybeUninit::uninit();
+    /// 1. `graph` must not have been destroyed
+    pub unsafe fn num_nodes(graph: sys::CUgraph) -> Result<usize, DriverError> {
+        let mut num_nodes = 0;
         lib()
-            .cuModuleLoadData(module.as_mut_ptr(), image)
+            .cuGraphGetNodes(graph, std::ptr::null_mut(), &mut num_nodes)
             .result()?;
-        Ok(module.assume_init())
+        Ok(num_nodes)
     }
 
-    /// Returns a function handle from the given module.
+    /// Creates an empty graph.
     ///
-    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1ga52be009b0d4045811b30c965e1cb2cf)
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
     ///
     /// # Safety
-    /// `module` must be a properly allocated and not freed module.
-    pub unsafe fn get_function(
-        module: sys::CUmodule,
-        name: CString,
-    ) -> Result<sys::CUfunction, DriverError> {
-        let name_ptr = name.as_c_str().as_ptr();
-        let mut func = MaybeUninit::uninit();
+    /// The graph must be destroyed with [destroy()]
+    pub unsafe fn create() -> Result<sys::CUgraph, DriverError> {
+        let mut graph = MaybeUninit::uninit();
+        lib().cuGraphCreate(graph.as_mut_ptr(), 0).result()?;
+        Ok(graph.assume_init())
+    }
+
+    /// Adds a node to `graph` that waits on `external_semaphore` until it reaches `value`,
+    /// after `dependencies`. `value` is ignored by binary semaphores.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
+    ///
+    /// # Safety
+    /// 1. `graph` and the nodes in `dependencies` must not have been destroyed
+    /// 2. `external_semaphore` must outlive every launch of the graph
+    pub unsafe fn add_external_semaphore_wait_node(
+        graph: sys::CUgraph,
+        dependencies: &[sys::CUgraphNode],
+        mut external_semaphore: sys::CUexternalSemaphore,
+        value: u64,
+    ) -> Result<sys::CUgraphNode, DriverError> {
+        let mut wait_params = sys::CUDA_EXTERNAL_SEMAPHORE_WAIT_PARAMS::default();
+        wait_params.params.fence.value = value;
+        let params = sys::CUDA_EXT_SEM_WAIT_NODE_PARAMS {
+            extSemArray: &mut external_semaphore,
+            paramsArray: &wait_params,
+            numExtSems: 1,
+        };
+        let mut node = MaybeUninit::uninit();
         lib()
-            .cuModuleGetFunction(func.as_mut_ptr(), module, name_ptr)
+            .cuGraphAddExternalSemaphoresWaitNode(
+                node.as_mut_ptr(),
+                graph,
+                dependencies.as_ptr(),
+                dependencies.len(),
+                &params,
+            )
             .result()?;
-        Ok(func.assume_init())
+        Ok(node.assume_init())
     }
 
-    /// Unloads a module.
+    /// Adds a node to `graph` that signals `external_semaphore`, setting it to `value`,
+    /// after `dependencies`. `value` is ignored by binary semaphores.
     ///
-    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MODULE.html#group__CUDA__MODULE_1g8ea3d716524369de3763104ced4ea57b)
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
     ///
     /// # Safety
-    /// `module` must not have be unloaded already.
-    pub unsafe fn unload(module: sys::CUmodule) -> Result<(), DriverError> {
-        lib().cuModuleUnload(module).result()
+    /// 1. `graph` and the nodes in `dependencies` must not have been destroyed
+    /// 2. `external_semaphore` must outlive every launch of the graph
+    pub unsafe fn add_external_semaphore_signal_node(
+        graph: sys::CUgraph,
+        dependencies: &[sys::CUgraphNode],
+        mut external_semaphore: sys::CUexternalSemaphore,
+        value: u64,
+    ) -> Result<sys::CUgraphNode, DriverError> {
+        let mut signal_params = sys::CUDA_EXTERNAL_SEMAPHORE_SIGNAL_PARAMS::default();
+        signal_params.params.fence.value = value;
+        let params = sys::CUDA_EXT_SEM_SIGNAL_NODE_PARAMS {
+            extSemArray: &mut external_semaphore,
+            paramsArray: &signal_params,
+            numExtSems: 1,
+        };
+        let mut node = MaybeUninit::uninit();
+        lib()
+            .cuGraphAddExternalSemaphoresSignalNode(
+                node.as_mut_ptr(),
+                graph,
+                dependencies.as_ptr(),
+                dependencies.len(),
+                &params,
+            )
+            .result()?;
+        Ok(node.assume_init())
+    }
+
+    /// Destroys an executable graph.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
+    ///
+    /// # Safety
+    /// 1. `exec` must not have been destroyed already
+    pub unsafe fn exec_destroy(exec: sys::CUgraphExec) -> Result<(), DriverError> {
+        lib().cuGraphExecDestroy(exec).result()
+    }
+
+    /// Destroys a graph.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html)
+    ///
+    /// # Safety
+    /// 1. `graph` must not have been destroyed already
+    pub unsafe fn destroy(graph: sys::CUgraph) -> Result<(), DriverError> {
+        lib().cuGraphDestroy(graph).result()
     }
 }
 
@@ -957,6 +3472,31 @@ pub mod event {
         Ok(ms)
     }
 
+    /// Waits until the work captured by the last [record] of the event has completed.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EVENT.html)
+    ///
+    /// # Safety
+    /// 1. Event must have been created by [create] and not destroyed.
+    pub unsafe fn synchronize(event: sys::CUevent) -> Result<(), DriverError> {
+        lib().cuEventSynchronize(event).result()
+    }
+
+    /// Returns `true` if the work captured by the last [record] of the event has completed,
+    /// and `false` if it is still pending.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EVENT.html)
+    ///
+    /// # Safety
+    /// 1. Event must have been created by [create] and not destroyed.
+    pub unsafe fn query(event: sys::CUevent) -> Result<bool, DriverError> {
+        match lib().cuEventQuery(event) {
+            sys::CUresult::CUDA_SUCCESS => Ok(true),
+            sys::CUresult::CUDA_ERROR_NOT_READY => Ok(false),
+            e => Err(DriverError::Cuda(e)),
+        }
+    }
+
     /// Destroys an event.
     ///
     /// > An event may be destroyed before it is complete (i.e., while cuEventQuery() would return CUDA_ERROR_NOT_READY).
@@ -1049,7 +3589,33 @@ pub unsafe fn launch_cooperative_kernel(
         .result()
 }
 
+/// Launches a cuda function with the extended launch config, which carries
+/// launch attributes such as cluster dimensions.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html)
+///
+/// # Safety
+/// This method is **very unsafe**, see [launch_kernel()]. Additionally `config.attrs`
+/// must point to `config.numAttrs` valid attributes.
+#[cfg(not(any(
+    feature = "cuda-11040",
+    feature = "cuda-11050",
+    feature = "cuda-11060",
+    feature = "cuda-11070"
+)))]
+#[inline]
+pub unsafe fn launch_kernel_ex(
+    f: sys::CUfunction,
+    config: &sys::CUlaunchConfig,
+    kernel_params: &mut [*mut c_void],
+) -> Result<(), DriverError> {
+    lib()
+        .cuLaunchKernelEx(config, f, kernel_params.as_mut_ptr(), std::ptr::null_mut())
+        .result()
+}
+
 pub mod external_memory {
+    use core::ffi::c_uint;
     use std::mem::MaybeUninit;
 
     use super::{
@@ -1070,7 +3636,7 @@ pub mod external_memory {
         fd: std::os::fd::RawFd,
         size: u64,
     ) -> Result<sys::CUexternalMemory, DriverError> {
-        import_external_memory(fd, size, sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD)
+        import_external_memory_with_dedicated(fd, size, sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD, false)
     }
 
     /// Imports an external memory object, in this case an OpaqueWin32 handle.
@@ -1086,35 +3652,104 @@ pub mod external_memory {
         handle: std::os::windows::io::RawHandle,
         size: u64,
     ) -> Result<sys::CUexternalMemory, DriverError> {
-        import_external_memory(handle, size, sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32)
+        import_external_memory_with_dedicated(handle, size, sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32, false)
     }
 
+    /// Imports an external memory object from a file descriptor.
+    ///
+    /// The memory should be destroyed using [`destroy_external_memory`].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1g52aba3a7f780157d8ba12972b2481735)
+    ///
+    /// # Safety
+    /// 1. `fd` must be a valid file descriptor of the given `type_`.
+    /// 2. `size` must be the size of the memory object in bytes.
     #[cfg(unix)]
+    #[deprecated(note = "use import_external_memory_with_dedicated instead")]
+    pub unsafe fn import_external_memory(
+        fd: std::os::fd::RawFd,
+        size: u64,
+        type_: sys::CUexternalMemoryHandleType_enum,
+    ) -> Result<sys::CUexternalMemory, DriverError> {
+        import_external_memory_with_dedicated(fd, size, type_, false)
+    }
+
+    /// Imports an external memory object from a file descriptor.
+    ///
+    /// The memory should be destroyed using [`destroy_external_memory`].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1g52aba3a7f780157d8ba12972b2481735)
+    ///
+    /// Set `dedicated` when the memory is a dedicated allocation, e.g. one backing a
+    /// single Vulkan image. This sets [sys::CUDA_EXTERNAL_MEMORY_DEDICATED].
+    ///
+    /// # Safety
+    /// 1. `fd` must be a valid file descriptor of the given `type_`.
+    /// 2. `size` must be the size of the memory object in bytes.
     // FIXME: This currently only works when memory type is CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD
     // but should work for CU_EXTERNAL_MEMORY_HANDLE_TYPE_NVSCIBUF too
-    pub unsafe fn import_external_memory(
+    #[cfg(unix)]
+    pub unsafe fn import_external_memory_with_dedicated(
         fd: std::os::fd::RawFd,
         size: u64,
         type_: sys::CUexternalMemoryHandleType_enum,
+        dedicated: bool,
     ) -> Result<sys::CUexternalMemory, DriverError> {
         // FIXME: Should be removed
-        assert_eq!(type_, sys::CUexternalSemaphoreHandleType_enum::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD, "This function currently only supports opaque file descriptor type");
+        assert_eq!(type_, sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD, "This function currently only supports opaque file descriptor type");
         let mut external_memory = MaybeUninit::uninit();
         let handle_description = sys::CUDA_EXTERNAL_MEMORY_HANDLE_DESC {
             type_,
             handle: sys::CUDA_EXTERNAL_MEMORY_HANDLE_DESC_st__bindgen_ty_1 { fd },
             size,
+            flags: if dedicated {
+                sys::CUDA_EXTERNAL_MEMORY_DEDICATED
+            } else {
+                0
+            },
             ..Default::default()
         };
-        sys::cuImportExternalMemory(external_memory.as_mut_ptr(), &handle_description).result()?;
+        lib().cuImportExternalMemory(external_memory.as_mut_ptr(), &handle_description).result()?;
         Ok(external_memory.assume_init())
     }
 
+    /// Imports an external memory object from a win32 handle.
+    ///
+    /// The memory should be destroyed using [`destroy_external_memory`].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1g52aba3a7f780157d8ba12972b2481735)
+    ///
+    /// # Safety
+    /// 1. `handle` must be a valid handle of the given `type_`.
+    /// 2. `size` must be the size of the memory object in bytes.
     #[cfg(windows)]
+    #[deprecated(note = "use import_external_memory_with_dedicated instead")]
     pub unsafe fn import_external_memory(
         handle: std::os::windows::io::RawHandle,
         size: u64,
         type_: sys::CUexternalMemoryHandleType_enum,
+    ) -> Result<sys::CUexternalMemory, DriverError> {
+        import_external_memory_with_dedicated(handle, size, type_, false)
+    }
+
+    /// Imports an external memory object from a win32 handle.
+    ///
+    /// The memory should be destroyed using [`destroy_external_memory`].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1g52aba3a7f780157d8ba12972b2481735)
+    ///
+    /// `dedicated` sets [sys::CUDA_EXTERNAL_MEMORY_DEDICATED]. It is always set for
+    /// D3D11/D3D12 resources, which require it.
+    ///
+    /// # Safety
+    /// 1. `handle` must be a valid handle of the given `type_`.
+    /// 2. `size` must be the size of the memory object in bytes.
+    #[cfg(windows)]
+    pub unsafe fn import_external_memory_with_dedicated(
+        handle: std::os::windows::io::RawHandle,
+        size: u64,
+        type_: sys::CUexternalMemoryHandleType_enum,
+        dedicated: bool,
     ) -> Result<sys::CUexternalMemory, DriverError> {
         let mut external_memory = MaybeUninit::uninit();
 
@@ -1125,6 +3760,7 @@ pub mod external_memory {
             sys::CUexternalMemoryHandleType::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D12_RESOURCE |
             sys::CUexternalMemoryHandleType::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D11_RESOURCE |
             sys::CUexternalMemoryHandleType::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D11_RESOURCE_KMT => sys::CUDA_EXTERNAL_MEMORY_DEDICATED,
+            _ if dedicated => sys::CUDA_EXTERNAL_MEMORY_DEDICATED,
             _ => 0
         };
 
@@ -1167,15 +3803,36 @@ pub mod external_memory {
     ///
     /// # Safety
     /// Mapped buffers may overlap.
+    #[deprecated(note = "use get_mapped_buffer_with_flags instead")]
     pub unsafe fn get_mapped_buffer(
         external_memory: sys::CUexternalMemory,
         offset: u64,
         size: u64,
+    ) -> Result<sys::CUdeviceptr, DriverError> {
+        get_mapped_buffer_with_flags(external_memory, offset, size, 0)
+    }
+
+    /// Maps a buffer onto an imported memory object.
+    ///
+    /// The buffer must be freed using [`memory_free`](super::memory_free).
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1gb9fec33920400c70961b4e33d838da91)
+    ///
+    /// `flags` is passed through as `CUDA_EXTERNAL_MEMORY_BUFFER_DESC::flags`.
+    ///
+    /// # Safety
+    /// Mapped buffers may overlap.
+    pub unsafe fn get_mapped_buffer_with_flags(
+        external_memory: sys::CUexternalMemory,
+        offset: u64,
+        size: u64,
+        flags: c_uint,
     ) -> Result<sys::CUdeviceptr, DriverError> {
         let mut device_ptr = MaybeUninit::uninit();
         let buffer_description = sys::CUDA_EXTERNAL_MEMORY_BUFFER_DESC {
             offset,
             size,
+            flags,
             ..Default::default()
         };
         lib()
@@ -1188,6 +3845,15 @@ pub mod external_memory {
         Ok(device_ptr.assume_init())
     }
 
+    /// Maps a 2d mipmapped array onto an imported memory object.
+    ///
+    /// The array must be destroyed with `cuMipmappedArrayDestroy`.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+    ///
+    /// # Safety
+    /// `external_memory` must be a valid imported memory object that is large enough to back
+    /// the array.
     pub unsafe fn get_mapped_mipmapped_array(
         external_memory: sys::CUexternalMemory,
         width: usize,
@@ -1213,6 +3879,12 @@ pub mod external_memory {
         Ok(mipmapped_array.assume_init())
     }
 
+    /// Gets a mipmap level of a mipmapped array.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEM.html)
+    ///
+    /// # Safety
+    /// `mipmapped_array` must be a valid, not yet destroyed, mipmapped array.
     pub unsafe fn get_mipmapped_array_level(
         mipmapped_array: sys::CUmipmappedArray,
         level: u32
@@ -1223,3 +3895,225 @@ pub mod external_memory {
         Ok(array.assume_init())
     }
 }
+
+pub mod external_semaphore {
+    use std::mem::MaybeUninit;
+
+    use super::{
+        sys::{self, lib},
+        DriverError,
+    };
+
+    /// Imports an external semaphore object from a file descriptor.
+    ///
+    /// The semaphore should be destroyed using [`destroy_external_semaphore`].
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+    ///
+    /// # Safety
+    /// `fd` must be a valid file descriptor of the given `type_`.
+    #[cfg(unix)]
+    pub unsafe fn import_external_semaphore(
+        fd: std::os::fd::RawFd,
+        type_: sys::CUexternalSemaphoreHandleType,
+    ) -> Result<sys::CUexternalSemaphore, DriverError> {
+        let mut external_semaphore = MaybeUninit::uninit();
+        let handle_description = sys::CUDA_EXTERNAL_SEMAPHORE_HANDLE_DESC {
+            type_,
+            handle: sys::CUDA_EXTERNAL_SEMAPHORE_HANDLE_DESC_st__bindgen_ty_1 { fd },
+            ..Default::default()
+        };
+        lib()
+            .cuImportExternalSemaphore(external_semaphore.as_mut_ptr(), &handle_description)
+            .result()?;
+        Ok(external_semaphore.assume_init())
+    }
+
+    /// Imports an external semaphore object from a win32 handle, e.g. of a D3D12 fence.
+    ///
+    /// The semaphore should be destroyed using [`destroy_external_semaphore`]. The handle
+    /// isn't owned by the driver, and has to be closed by the caller.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+    ///
+    /// # Safety
+    /// `handle` must be a valid handle of the given `type_`.
+    #[cfg(windows)]
+    pub unsafe fn import_external_semaphore(
+        handle: std::os::windows::io::RawHandle,
+        type_: sys::CUexternalSemaphoreHandleType,
+    ) -> Result<sys::CUexternalSemaphore, DriverError> {
+        let mut external_semaphore = MaybeUninit::uninit();
+        let handle_description = sys::CUDA_EXTERNAL_SEMAPHORE_HANDLE_DESC {
+            type_,
+            handle: sys::CUDA_EXTERNAL_SEMAPHORE_HANDLE_DESC_st__bindgen_ty_1 {
+                win32: sys::CUDA_EXTERNAL_SEMAPHORE_HANDLE_DESC_st__bindgen_ty_1__bindgen_ty_1 {
+                    handle,
+                    name: std::ptr::null(),
+                },
+            },
+            ..Default::default()
+        };
+        lib()
+            .cuImportExternalSemaphore(external_semaphore.as_mut_ptr(), &handle_description)
+            .result()?;
+        Ok(external_semaphore.assume_init())
+    }
+
+    /// Destroys an external semaphore object.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+    ///
+    /// # Safety
+    /// 1. No signal or wait enqueued on the semaphore may be pending.
+    /// 2. The external semaphore must only be destroyed once.
+    pub unsafe fn destroy_external_semaphore(
+        external_semaphore: sys::CUexternalSemaphore,
+    ) -> Result<(), DriverError> {
+        lib()
+            .cuDestroyExternalSemaphore(external_semaphore)
+            .result()
+    }
+
+    /// Enqueues a signal of `external_semaphore` on `stream`. `value` is the value fences and
+    /// timeline semaphores are set to, and is ignored by binary semaphores.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+    ///
+    /// # Safety
+    /// `external_semaphore` must be a valid imported semaphore, and `stream` a valid stream.
+    pub unsafe fn signal_async(
+        external_semaphore: sys::CUexternalSemaphore,
+        value: u64,
+        stream: sys::CUstream,
+    ) -> Result<(), DriverError> {
+        let mut params = sys::CUDA_EXTERNAL_SEMAPHORE_SIGNAL_PARAMS::default();
+        params.params.fence.value = value;
+        lib()
+            .cuSignalExternalSemaphoresAsync(&external_semaphore, &params, 1, stream)
+            .result()
+    }
+
+    /// Enqueues a wait on `external_semaphore` on `stream`. Fences and timeline semaphores
+    /// are waited on until they reach `value`, which is ignored by binary semaphores.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+    ///
+    /// # Safety
+    /// `external_semaphore` must be a valid imported semaphore, and `stream` a valid stream.
+    pub unsafe fn wait_async(
+        external_semaphore: sys::CUexternalSemaphore,
+        value: u64,
+        stream: sys::CUstream,
+    ) -> Result<(), DriverError> {
+        let mut params = sys::CUDA_EXTERNAL_SEMAPHORE_WAIT_PARAMS::default();
+        params.params.fence.value = value;
+        lib()
+            .cuWaitExternalSemaphoresAsync(&external_semaphore, &params, 1, stream)
+            .result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Larger than any code of the driver API.
+    const OUT_OF_RANGE: u32 = 0xdead_beef;
+
+    #[test]
+    fn test_api_groups_are_in_order() {
+        for (i, (group, version, symbols)) in API_GROUPS.iter().enumerate() {
+            assert_eq!(*group as usize, i);
+            assert_eq!(group.required_version(), *version);
+            assert!(!symbols.is_empty());
+        }
+        assert_eq!(ApiGroup::Core.required_version(), "7.0");
+    }
+
+    #[test]
+    fn test_unknown_codes_are_preserved() {
+        assert_eq!(
+            DriverError::from_raw(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY.0),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY)
+        );
+        assert_eq!(
+            DriverError::from_raw(999),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_UNKNOWN)
+        );
+
+        let err = DriverError::from_raw(OUT_OF_RANGE);
+        assert_eq!(err, DriverError::Unknown(OUT_OF_RANGE));
+        assert_eq!(err.raw_code(), OUT_OF_RANGE);
+        assert_eq!(err.code(), sys::CUresult::CUDA_ERROR_UNKNOWN);
+        assert_eq!(err.kind(), ErrorKind::Unknown);
+        assert!(!err.is_sticky());
+
+        // the value a newer driver returns reaches `result()` as it is
+        assert_eq!(
+            sys::CUresult(OUT_OF_RANGE).result(),
+            Err(DriverError::Unknown(OUT_OF_RANGE))
+        );
+        assert_eq!(
+            std::format!("{:?}", sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY),
+            "CUDA_ERROR_OUT_OF_MEMORY"
+        );
+        assert_eq!(
+            std::format!("{:?}", sys::CUresult(OUT_OF_RANGE)),
+            std::format!("CUresult({OUT_OF_RANGE})")
+        );
+    }
+
+    #[test]
+    fn test_error_kind() {
+        let kind = |code| DriverError::Cuda(code).kind();
+        assert_eq!(
+            kind(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY),
+            ErrorKind::OutOfMemory
+        );
+        assert_eq!(
+            kind(sys::CUresult::CUDA_ERROR_INVALID_HANDLE),
+            ErrorKind::InvalidValue
+        );
+        assert_eq!(
+            kind(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED),
+            ErrorKind::NotSupported
+        );
+        assert_eq!(kind(sys::CUresult::CUDA_ERROR_NO_DEVICE), ErrorKind::System);
+        assert_eq!(
+            kind(sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS),
+            ErrorKind::Sticky
+        );
+        assert_eq!(kind(sys::CUresult::CUDA_ERROR_UNKNOWN), ErrorKind::Unknown);
+        assert_eq!(kind(sys::CUresult::CUDA_ERROR_NOT_READY), ErrorKind::Other);
+
+        let zero_dim = InvalidLaunch::ZeroDim {
+            grid_dim: (0, 1, 1),
+            block_dim: (1, 1, 1),
+        };
+        assert_eq!(DriverError::from(zero_dim).kind(), ErrorKind::InvalidValue);
+        let poisoned = DriverError::ContextPoisoned {
+            original: sys::CUresult::CUDA_ERROR_LAUNCH_FAILED,
+        };
+        assert_eq!(poisoned.kind(), ErrorKind::Sticky);
+        let init = DriverError::InitFailed {
+            code: sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+        };
+        assert_eq!(init.kind(), ErrorKind::System);
+    }
+
+    #[test]
+    fn test_unknown_code_text() {
+        init().unwrap();
+        let err = DriverError::Unknown(OUT_OF_RANGE);
+        // the driver rejects codes it doesn't know either
+        assert_eq!(
+            err.error_string(),
+            Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))
+        );
+        assert!(std::format!("{err:?}").starts_with("Unknown(3735928559"));
+        assert!(DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY)
+            .error_name()
+            .is_ok_and(|name| name.to_str() == Ok("CUDA_ERROR_OUT_OF_MEMORY")));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+use super::DriverError;
+
+use std::{ffi::CString, string::String, sync::Arc};
+
+/// CUDA source defining `CUDARC_ABI_BEGIN(T)`, `CUDARC_ABI_FIELD(T, field)` and
+/// `CUDARC_ABI_END`, to prepend to kernel source compiled with nvrtc. They compute the
+/// [AbiHash::ABI_HASH] of a struct from its size and the name, size and offset of each
+/// field, so that [CudaDevice::verify_abi()] can compare it with the rust struct:
+///
+/// ```cuda
+/// struct Params { float scale; unsigned int n; };
+/// __device__ __constant__ unsigned long long params_abi_hash =
+///     CUDARC_ABI_BEGIN(Params)
+///     CUDARC_ABI_FIELD(Params, scale)
+///     CUDARC_ABI_FIELD(Params, n)
+///     CUDARC_ABI_END;
+/// ```
+///
+/// The fields have to be listed in the same order as in [crate::impl_abi_hash!].
+pub const ABI_HASH_SRC: &str = r#"
+struct cudarc_abi_hasher {
+    unsigned long long h;
+    __host__ __device__ constexpr cudarc_abi_hasher mix(unsigned char b) const {
+        return cudarc_abi_hasher{(h ^ b) * 0x100000001b3ull};
+    }
+    __host__ __device__ constexpr cudarc_abi_hasher mix_u64(unsigned long long v, int i = 0) const {
+        return i == 8 ? *this : mix((unsigned char)(v >> (8 * i))).mix_u64(v, i + 1);
+    }
+    __host__ __device__ constexpr cudarc_abi_hasher mix_str(const char *s) const {
+        return *s ? mix((unsigned char)*s).mix_str(s + 1) : mix(0);
+    }
+    __host__ __device__ constexpr cudarc_abi_hasher field(const char *name, unsigned long long size, unsigned long long offset) const {
+        return mix_str(name).mix_u64(size).mix_u64(offset);
+    }
+};
+#define CUDARC_ABI_BEGIN(T) (cudarc_abi_hasher{0xcbf29ce484222325ull}.mix_u64(sizeof(T))
+#define CUDARC_ABI_FIELD(T, f) .field(#f, sizeof(((T *)0)->f), __builtin_offsetof(T, f))
+#define CUDARC_ABI_END .h)
+"#;
+
+/// The layout of a `#[repr(C)]` struct passed to kernels, compared by
+/// [CudaDevice::verify_abi()] with the hash the CUDA side computes with [ABI_HASH_SRC].
+///
+/// Implement it with [crate::impl_abi_hash!], or by hand with an [AbiHasher]:
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// #[repr(C)]
+/// struct Params {
+///     scale: f32,
+///     n: u32,
+/// }
+/// cudarc::impl_abi_hash!(Params { scale, n });
+/// ```
+pub trait AbiHash {
+    const ABI_HASH: u64;
+}
+
+/// A const FNV-1a hasher of struct layouts, the same as `cudarc_abi_hasher` of
+/// [ABI_HASH_SRC]. Start with the size of the struct, then add each field in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbiHasher(u64);
+
+impl AbiHasher {
+    pub const fn new(size: usize) -> Self {
+        Self(0xcbf29ce484222325).mix_u64(size as u64)
+    }
+
+    pub const fn field(self, name: &str, size: usize, offset: usize) -> Self {
+        self.mix_str(name)
+            .mix_u64(size as u64)
+            .mix_u64(offset as u64)
+    }
+
+    pub const fn finish(self) -> u64 {
+        self.0
+    }
+
+    /// The size of the field `f` returns, e.g. `AbiHasher::size_of_field(|p: &Params| &p.n)`.
+    pub const fn size_of_field<S, F>(_f: fn(&S) -> &F) -> usize {
+        core::mem::size_of::<F>()
+    }
+
+    const fn mix(self, b: u8) -> Self {
+        Self((self.0 ^ b as u64).wrapping_mul(0x100000001b3))
+    }
+
+    const fn mix_u64(self, v: u64) -> Self {
+        let mut h = self;
+        let mut i = 0;
+        while i < 8 {
+            h = h.mix((v >> (8 * i)) as u8);
+            i += 1;
+        }
+        h
+    }
+
+    const fn mix_str(self, s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let mut h = self;
+        let mut i = 0;
+        while i < bytes.len() {
+            h = h.mix(bytes[i]);
+            i += 1;
+        }
+        h.mix(0)
+    }
+}
+
+/// Implements [AbiHash](crate::driver::AbiHash) for a `#[repr(C)]` struct from its
+/// fields, listed in the same order as with `CUDARC_ABI_FIELD` of
+/// [ABI_HASH_SRC](crate::driver::ABI_HASH_SRC).
+#[macro_export]
+macro_rules! impl_abi_hash {
+    ($T:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::driver::AbiHash for $T {
+            const ABI_HASH: u64 = $crate::driver::AbiHasher::new(::core::mem::size_of::<$T>())
+                $(.field(
+                    stringify!($field),
+                    $crate::driver::AbiHasher::size_of_field(|s: &$T| &s.$field),
+                    ::core::mem::offset_of!($T, $field),
+                ))*
+                .finish();
+        }
+    };
+}
+
+/// Returned by [CudaDevice::verify_abi()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiError {
+    /// The module or hash symbol wasn't found, or the symbol isn't a 64 bit integer
+    /// ([sys::CUresult::CUDA_ERROR_INVALID_VALUE]).
+    Driver(DriverError),
+    /// The layout of `type_name` differs between rust and the CUDA source of `module`.
+    Mismatch {
+        type_name: &'static str,
+        module: String,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl From<DriverError> for AbiError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for AbiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Driver(e) => write!(f, "{e}"),
+            Self::Mismatch {
+                type_name,
+                module,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the layout of {type_name} doesn't match module {module:?}: abi hash {expected:#018x} in rust, {found:#018x} on the device"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AbiError {}
+
+impl CudaDevice {
+    /// Checks that `P` has the same layout in rust as in the module `module_name`, by
+    /// comparing [AbiHash::ABI_HASH] with the `__constant__ unsigned long long` named
+    /// `symbol` the module defines with [ABI_HASH_SRC]. Call it once after
+    /// [CudaDevice::load_ptx()], before launching kernels taking a `P`.
+    pub fn verify_abi<P: AbiHash>(
+        self: &Arc<Self>,
+        module_name: &str,
+        symbol: &str,
+    ) -> Result<(), AbiError> {
+        let module = self
+            .module(module_name)
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_FOUND))?;
+        let symbol = CString::new(symbol)
+            .map_err(|_| DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))?;
+        let _ctx = self.bind_scoped()?;
+        let (dptr, bytes) = unsafe { result::module::get_global(module.cu_module, symbol) }?;
+        if bytes != core::mem::size_of::<u64>() {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE).into());
+        }
+        let mut found = [0u64];
+        unsafe { result::memcpy_dtoh_sync(&mut found, dptr) }?;
+        if found[0] != P::ABI_HASH {
+            return Err(AbiError::Mismatch {
+                type_name: core::any::type_name::<P>(),
+                module: module_name.into(),
+                expected: P::ABI_HASH,
+                found: found[0],
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nvrtc::compile_ptx;
+
+    #[repr(C)]
+    struct Params {
+        scale: f32,
+        n: u32,
+        x: u64,
+    }
+    crate::impl_abi_hash!(Params { scale, n, x });
+
+    /// `n` and `x` swapped, which changes their offsets.
+    #[repr(C)]
+    struct Swapped {
+        scale: f32,
+        x: u64,
+        n: u32,
+    }
+    crate::impl_abi_hash!(Swapped { scale, x, n });
+
+    #[test]
+    fn test_abi_hash_layout() {
+        let manual = AbiHasher::new(16)
+            .field("scale", 4, 0)
+            .field("n", 4, 4)
+            .field("x", 8, 8)
+            .finish();
+        assert_eq!(Params::ABI_HASH, manual);
+        assert_ne!(Params::ABI_HASH, Swapped::ABI_HASH);
+        // field names are part of the hash
+        let renamed = AbiHasher::new(16)
+            .field("scale", 4, 0)
+            .field("m", 4, 4)
+            .field("x", 8, 8)
+            .finish();
+        assert_ne!(Params::ABI_HASH, renamed);
+    }
+
+    #[test]
+    fn test_verify_abi() {
+        let dev = CudaDevice::new(0).unwrap();
+        let src = std::format!(
+            "{ABI_HASH_SRC}
+struct Params {{ float scale; unsigned int n; unsigned long long x; }};
+__device__ __constant__ unsigned long long params_abi_hash =
+    CUDARC_ABI_BEGIN(Params)
+    CUDARC_ABI_FIELD(Params, scale)
+    CUDARC_ABI_FIELD(Params, n)
+    CUDARC_ABI_FIELD(Params, x)
+    CUDARC_ABI_END;
+extern \"C\" __global__ void use_params(Params p, float *out) {{ out[0] = p.scale; }}"
+        );
+        let ptx = compile_ptx(src).unwrap();
+        dev.load_ptx(ptx, "abi", &["use_params"]).unwrap();
+
+        dev.verify_abi::<Params>("abi", "params_abi_hash").unwrap();
+        let err = dev
+            .verify_abi::<Swapped>("abi", "params_abi_hash")
+            .unwrap_err();
+        assert_eq!(
+            err,
+            AbiError::Mismatch {
+                type_name: core::any::type_name::<Swapped>(),
+                module: "abi".into(),
+                expected: Swapped::ABI_HASH,
+                found: Params::ABI_HASH,
+            }
+        );
+        assert_eq!(
+            dev.verify_abi::<Params>("abi", "missing"),
+            Err(AbiError::Driver(DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_FOUND
+            )))
+        );
+        assert_eq!(
+            dev.verify_abi::<Params>("not_loaded", "params_abi_hash"),
+            Err(AbiError::Driver(DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_FOUND
+            )))
+        );
+    }
+}
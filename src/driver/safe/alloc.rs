@@ -1,9 +1,18 @@
 use crate::driver::{result, sys};
 
-use super::core::{CudaDevice, CudaSlice, CudaView, CudaViewMut};
+use super::core::{CudaDevice, CudaSlice, CudaStream, CudaView, CudaViewMut};
 use super::device_ptr::{DevicePtr, DevicePtrMut, DeviceSlice};
+use super::error_handler::OrHandle;
+use super::pinned::PinnedBuffer;
+use super::stream_tracking::LastWrite;
 
-use std::{marker::Unpin, pin::Pin, sync::Arc, vec::Vec};
+use std::{
+    marker::{PhantomData, Unpin},
+    panic::Location,
+    pin::Pin,
+    sync::Arc,
+    vec::Vec,
+};
 
 /// Something that can be copied to device memory and
 /// turned into a parameter for [result::launch_kernel].
@@ -18,6 +27,27 @@ pub unsafe trait DeviceRepr {
     fn as_kernel_param(&self) -> *mut std::ffi::c_void {
         self as *const Self as *mut _
     }
+
+    /// The device memory this param refers to, if it is a reference to device memory.
+    /// Only used by the `launch-validation` feature.
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        None
+    }
+
+    /// The last tracked write to the [CudaSlice] this param refers to, and whether the
+    /// kernel may write it. Only used when `CudaDevice::set_stream_tracking()` is enabled.
+    #[inline(always)]
+    fn tracked_access(&self) -> Option<(&LastWrite, bool)> {
+        None
+    }
+
+    /// The device buffer this param refers to, as its pointer and size in bytes. Only used
+    /// by `LaunchRecorder`, which snapshots the buffer instead of the pointer.
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        None
+    }
 }
 
 unsafe impl DeviceRepr for bool {}
@@ -45,6 +75,21 @@ unsafe impl<T: DeviceRepr> DeviceRepr for &mut CudaSlice<T> {
     fn as_kernel_param(&self) -> *mut std::ffi::c_void {
         (&self.cu_device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
     }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.cu_device_ptr)
+    }
+
+    #[inline(always)]
+    fn tracked_access(&self) -> Option<(&LastWrite, bool)> {
+        Some((&self.last_write, true))
+    }
+
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        Some((self.cu_device_ptr, self.num_bytes()))
+    }
 }
 
 unsafe impl<T: DeviceRepr> DeviceRepr for &CudaSlice<T> {
@@ -52,6 +97,21 @@ unsafe impl<T: DeviceRepr> DeviceRepr for &CudaSlice<T> {
     fn as_kernel_param(&self) -> *mut std::ffi::c_void {
         (&self.cu_device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
     }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.cu_device_ptr)
+    }
+
+    #[inline(always)]
+    fn tracked_access(&self) -> Option<(&LastWrite, bool)> {
+        Some((&self.last_write, false))
+    }
+
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        Some((self.cu_device_ptr, self.num_bytes()))
+    }
 }
 
 unsafe impl<'a, T: DeviceRepr> DeviceRepr for &CudaView<'a, T> {
@@ -59,6 +119,16 @@ unsafe impl<'a, T: DeviceRepr> DeviceRepr for &CudaView<'a, T> {
     fn as_kernel_param(&self) -> *mut std::ffi::c_void {
         (&self.ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
     }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.ptr)
+    }
+
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        Some((self.ptr, self.num_bytes()))
+    }
 }
 
 unsafe impl<'a, T: DeviceRepr> DeviceRepr for &mut CudaViewMut<'a, T> {
@@ -66,23 +136,63 @@ unsafe impl<'a, T: DeviceRepr> DeviceRepr for &mut CudaViewMut<'a, T> {
     fn as_kernel_param(&self) -> *mut std::ffi::c_void {
         (&self.ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
     }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.ptr)
+    }
+
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        Some((self.ptr, self.num_bytes()))
+    }
 }
 
 impl<T> CudaSlice<T> {
     /// Takes ownership of the underlying [sys::CUdeviceptr]. **It is up
     /// to the owner to free this value**.
     ///
-    /// Drops the underlying host_buf if there is one.
+    /// Drops the underlying host_buf if there is one. If the slice was allocated with the
+    /// [super::DeviceAllocator] of [CudaDevice::set_allocator()], it's the one to free it with.
+    ///
+    /// Returns the start of the allocation, so that it can be freed. For slices of
+    /// [CudaDevice::alloc_aligned()] that is before the slice's own pointer, and
+    /// [CudaDevice::upgrade_device_ptr()] makes a slice that isn't aligned of it.
     pub fn leak(mut self) -> sys::CUdeviceptr {
         if let Some(host_buf) = std::mem::take(&mut self.host_buf) {
             drop(host_buf);
         }
-        let ptr = self.cu_device_ptr;
+        drop(self.allocator.take());
+        if self.last_write_event().is_some() {
+            let _ctx = self.device.bind_scoped_or_handle("CudaSlice::leak");
+            self.last_write
+                .release(self.device.stream, "CudaSlice::leak");
+        }
+        self.device.untrack(&self);
+        let ptr = self.release_alloc_base();
         std::mem::forget(self);
         ptr
     }
 }
 
+/// The alignment of every allocation by the driver.
+const DRIVER_ALIGNMENT: usize = 256;
+
+/// The live [CudaSlice]s of a device, see [CudaDevice::alloc_stats()].
+#[cfg(feature = "allocation-tracking")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// The number of live slices.
+    pub slices: usize,
+    /// The bytes of the live slices, including their padding.
+    pub bytes: usize,
+    /// The most `bytes` there were at once since the device was created.
+    pub peak_bytes: usize,
+    /// The bytes of `bytes` that only align the slices of [CudaDevice::alloc_aligned()],
+    /// the same as [CudaDevice::aligned_padding_bytes()].
+    pub padding_bytes: usize,
+}
+
 impl CudaDevice {
     /// Creates a [CudaSlice] from a [sys::CUdeviceptr]. Useful in conjunction with
     /// [`CudaSlice::leak()`].
@@ -92,59 +202,78 @@ impl CudaDevice {
     /// - `cu_device_ptr` must space for `len * std::mem::size_of<T>()` bytes
     /// - The memory may not be valid for type `T`, so some sort of memset operation
     ///   should be called on the memory.
+    #[track_caller]
     pub unsafe fn upgrade_device_ptr<T>(
         self: &Arc<Self>,
         cu_device_ptr: sys::CUdeviceptr,
         len: usize,
     ) -> CudaSlice<T> {
-        CudaSlice {
+        self.tracked(CudaSlice {
             cu_device_ptr,
             len,
             device: self.clone(),
             host_buf: None,
-        }
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator: None,
+        })
     }
 }
 
 impl CudaDevice {
     /// Allocates an empty [CudaSlice] with 0 length.
+    #[track_caller]
     pub fn null<T>(self: &Arc<Self>) -> Result<CudaSlice<T>, result::DriverError> {
-        self.bind_to_thread()?;
-        let cu_device_ptr = unsafe {
+        let _ctx = self.bind_scoped()?;
+        let (cu_device_ptr, allocator) = self.alloc_with_allocator(0, self.stream, || unsafe {
             if self.is_async {
-                result::malloc_async(self.stream, 0)?
+                result::malloc_async(self.stream, 0)
             } else {
-                result::malloc_sync(0)?
+                self.malloc_sync_reaping(0)
             }
-        };
-        Ok(CudaSlice {
+        })?;
+        Ok(self.tracked(CudaSlice {
             cu_device_ptr,
             len: 0,
             device: self.clone(),
             host_buf: None,
-        })
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator,
+        }))
     }
 
     /// Allocates device memory and increments the reference counter of [CudaDevice].
     ///
     /// # Safety
     /// This is unsafe because the device memory is unset after this call.
+    #[track_caller]
     pub unsafe fn alloc<T: DeviceRepr>(
         self: &Arc<Self>,
         len: usize,
     ) -> Result<CudaSlice<T>, result::DriverError> {
-        self.bind_to_thread()?;
-        let cu_device_ptr = if self.is_async {
-            result::malloc_async(self.stream, len * std::mem::size_of::<T>())?
-        } else {
-            result::malloc_sync(len * std::mem::size_of::<T>())?
-        };
-        Ok(CudaSlice {
+        let _ctx = self.bind_scoped()?;
+        let num_bytes = len * std::mem::size_of::<T>();
+        let (cu_device_ptr, allocator) =
+            self.alloc_with_allocator(num_bytes, self.stream, || {
+                if self.is_async {
+                    result::malloc_async(self.stream, num_bytes)
+                } else {
+                    self.malloc_sync_reaping(num_bytes)
+                }
+            })?;
+        Ok(self.tracked(CudaSlice {
             cu_device_ptr,
             len,
             device: self.clone(),
             host_buf: None,
-        })
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator,
+        }))
     }
 
     /// Allocates device memory with no associated host memory, and memsets
@@ -153,6 +282,7 @@ impl CudaDevice {
     /// # Safety
     /// 1. `T` is marked as [ValidAsZeroBits], so the device memory is valid to use
     /// 2. Self is [`Arc<Self>`], and this method increments the rc for self
+    #[track_caller]
     pub fn alloc_zeros<T: ValidAsZeroBits + DeviceRepr>(
         self: &Arc<Self>,
         len: usize,
@@ -162,6 +292,123 @@ impl CudaDevice {
         Ok(dst)
     }
 
+    /// Allocates `len` elements starting at a multiple of `align` bytes, e.g. 4096 or 65536
+    /// for GPUDirect Storage or video encoders, which need stricter alignment than the 256
+    /// bytes the driver guarantees.
+    ///
+    /// Larger alignments are over-allocated by `align - 1` bytes, which are counted by
+    /// [CudaDevice::aligned_padding_bytes()] and, with the `allocation-tracking` feature,
+    /// `CudaDevice::alloc_stats()` while the slice is alive. The whole allocation is freed
+    /// when it is dropped, and [CudaSlice::leak()] returns its start.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `align` isn't a power of two.
+    ///
+    /// # Safety
+    /// This is unsafe because the device memory is unset after this call.
+    #[track_caller]
+    pub unsafe fn alloc_aligned<T: DeviceRepr>(
+        self: &Arc<Self>,
+        len: usize,
+        align: usize,
+    ) -> Result<CudaSlice<T>, result::DriverError> {
+        let invalid = result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE);
+        if !align.is_power_of_two() {
+            return Err(invalid);
+        }
+        if align <= DRIVER_ALIGNMENT {
+            return self.alloc(len);
+        }
+        let padding = align - 1;
+        let num_bytes = len
+            .checked_mul(std::mem::size_of::<T>())
+            .and_then(|n| n.checked_add(padding))
+            .ok_or(invalid)?;
+        let _ctx = self.bind_scoped()?;
+        let (base, allocator) = self.alloc_with_allocator(num_bytes, self.stream, || {
+            if self.is_async {
+                result::malloc_async(self.stream, num_bytes)
+            } else {
+                self.malloc_sync_reaping(num_bytes)
+            }
+        })?;
+        let mask = align as sys::CUdeviceptr - 1;
+        self.aligned_padding
+            .fetch_add(padding, std::sync::atomic::Ordering::Relaxed);
+        Ok(self.tracked(CudaSlice {
+            cu_device_ptr: (base + mask) & !mask,
+            len,
+            device: self.clone(),
+            host_buf: None,
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: Some((base, padding)),
+            allocator,
+        }))
+    }
+
+    /// [CudaDevice::alloc_aligned()], with the memory set to all 0s.
+    #[track_caller]
+    pub fn alloc_zeros_aligned<T: ValidAsZeroBits + DeviceRepr>(
+        self: &Arc<Self>,
+        len: usize,
+        align: usize,
+    ) -> Result<CudaSlice<T>, result::DriverError> {
+        let mut dst = unsafe { self.alloc_aligned(len, align) }?;
+        self.memset_zeros(&mut dst)?;
+        Ok(dst)
+    }
+
+    /// The bytes allocated beyond their length by the live slices of
+    /// [CudaDevice::alloc_aligned()], i.e. how much memory aligning them wastes.
+    pub fn aligned_padding_bytes(&self) -> usize {
+        self.aligned_padding
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The [CudaSlice]s of the device that are alive, with the `allocation-tracking`
+    /// feature. Slices of other devices, e.g. of [CudaDevice::upgrade_device_ptr()] with
+    /// another device's pointer, are counted for the device they were created with.
+    #[cfg(feature = "allocation-tracking")]
+    pub fn alloc_stats(&self) -> AllocStats {
+        let stats = self.alloc_stats.read();
+        #[cfg(not(feature = "no-std"))]
+        let stats = stats.unwrap_or_else(|e| e.into_inner());
+        AllocStats {
+            padding_bytes: self.aligned_padding_bytes(),
+            ..*stats
+        }
+    }
+
+    /// Counts `slice` in [CudaDevice::alloc_stats()] until it is dropped or leaked.
+    #[inline]
+    pub(crate) fn tracked<T>(&self, slice: CudaSlice<T>) -> CudaSlice<T> {
+        #[cfg(feature = "allocation-tracking")]
+        {
+            #[allow(unused_mut)]
+            let mut stats = self.alloc_stats.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut stats = stats.unwrap_or_else(|e| e.into_inner());
+            stats.slices += 1;
+            stats.bytes += slice.alloc_bytes();
+            stats.peak_bytes = stats.peak_bytes.max(stats.bytes);
+        }
+        slice
+    }
+
+    /// No longer counts `slice`, which is freed or leaked.
+    #[inline]
+    pub(crate) fn untrack<T>(&self, _slice: &CudaSlice<T>) {
+        #[cfg(feature = "allocation-tracking")]
+        {
+            #[allow(unused_mut)]
+            let mut stats = self.alloc_stats.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut stats = stats.unwrap_or_else(|e| e.into_inner());
+            stats.slices = stats.slices.saturating_sub(1);
+            stats.bytes = stats.bytes.saturating_sub(_slice.alloc_bytes());
+        }
+    }
+
     /// Sets all memory to 0 asynchronously.
     ///
     /// # Safety
@@ -171,14 +418,16 @@ impl CudaDevice {
         self: &Arc<Self>,
         dst: &mut Dst,
     ) -> Result<(), result::DriverError> {
-        self.bind_to_thread()?;
+        let _ctx = self.bind_scoped()?;
+        self.wait_for_write(self.stream, dst)?;
         if self.is_async {
             unsafe {
                 result::memset_d8_async(*dst.device_ptr_mut(), 0, dst.num_bytes(), self.stream)
-            }
+            }?
         } else {
-            unsafe { result::memset_d8_sync(*dst.device_ptr_mut(), 0, dst.num_bytes()) }
+            unsafe { result::memset_d8_sync(*dst.device_ptr_mut(), 0, dst.num_bytes()) }?
         }
+        self.record_write(self.stream, dst)
     }
 
     /// Device to device copy (safe version of [result::memcpy_dtod_async]).
@@ -189,7 +438,7 @@ impl CudaDevice {
     ///
     /// # Safety
     /// 1. We are guarunteed that `src` and `dst` are pointers to the same underlying
-    ///     type `T`
+    ///    type `T`
     /// 2. Since they are both references, they can't have been freed
     /// 3. Self is [`Arc<Self>`], and this method increments the rc for self
     pub fn dtod_copy<T: DeviceRepr, Src: DevicePtr<T>, Dst: DevicePtrMut<T>>(
@@ -198,7 +447,9 @@ impl CudaDevice {
         dst: &mut Dst,
     ) -> Result<(), result::DriverError> {
         assert_eq!(src.len(), dst.len());
-        self.bind_to_thread()?;
+        let _ctx = self.bind_scoped()?;
+        self.wait_for_write(self.stream, src)?;
+        self.wait_for_write(self.stream, dst)?;
         if self.is_async {
             unsafe {
                 result::memcpy_dtod_async(
@@ -207,7 +458,7 @@ impl CudaDevice {
                     src.len() * std::mem::size_of::<T>(),
                     self.stream,
                 )
-            }
+            }?
         } else {
             unsafe {
                 result::memcpy_dtod_sync(
@@ -215,8 +466,9 @@ impl CudaDevice {
                     *src.device_ptr(),
                     src.len() * std::mem::size_of::<T>(),
                 )
-            }
+            }?
         }
+        self.record_write(self.stream, dst)
     }
 
     /// Takes ownership of the host data and copies it to device data asynchronously.
@@ -226,6 +478,7 @@ impl CudaDevice {
     /// 1. Since `src` is owned by this funcion, it is safe to copy data. Any actions executed
     ///    after this will take place after the data has been successfully copied.
     /// 2. Self is [`Arc<Self>`], and this method increments the rc for self
+    #[track_caller]
     pub fn htod_copy<T: Unpin + DeviceRepr>(
         self: &Arc<Self>,
         src: Vec<T>,
@@ -242,6 +495,7 @@ impl CudaDevice {
     /// 1. Since `src` is owned by this funcion, it is safe to copy data. Any actions executed
     ///    after this will take place after the data has been successfully copied.
     /// 2. Self is [`Arc<Self>`], and this method increments the rc for self
+    #[track_caller]
     pub fn htod_copy_into<T: DeviceRepr + Unpin>(
         self: &Arc<Self>,
         src: Vec<T>,
@@ -249,7 +503,14 @@ impl CudaDevice {
     ) -> Result<(), result::DriverError> {
         assert_eq!(src.len(), dst.len());
         dst.host_buf = Some(Pin::new(src));
-        self.bind_to_thread()?;
+        let _ctx = self.bind_scoped()?;
+        self.wait_for_write(self.stream, dst)?;
+        let host = dst.host_buf.as_ref().unwrap().as_ptr();
+        let num_bytes = dst.len * std::mem::size_of::<T>();
+        if unsafe { self.staged_htod(dst.cu_device_ptr, host as *const u8, num_bytes) }? {
+            return self.record_write(self.stream, dst);
+        }
+        self.check_pageable("htod_copy_into", host as _, Location::caller());
         if self.is_async {
             unsafe {
                 result::memcpy_htod_async(
@@ -261,7 +522,72 @@ impl CudaDevice {
         } else {
             unsafe { result::memcpy_htod_sync(dst.cu_device_ptr, dst.host_buf.as_ref().unwrap()) }?
         }
-        Ok(())
+        // `src` is pageable, so the copy has completed by the time the driver returns
+        self.implicit_sync("htod_copy_into", Location::caller());
+        self.record_write(self.stream, dst)
+    }
+
+    /// Allocates `total_len` elements of device memory and fills it with the first `total_len`
+    /// items of `iter`, without collecting the items on the host first.
+    ///
+    /// Items are staged through two page-locked buffers, so that filling one buffer from `iter`
+    /// overlaps with the copy of the other to the device. Returns once the last copy has completed.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `iter` yields fewer than
+    /// `total_len` items.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::safe::CudaDevice;
+    /// # let dev = CudaDevice::new(0).unwrap();
+    /// let a = dev.htod_copy_from_iter((0..1000).map(|i| i as f32), 1000).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&a).unwrap()[999], 999.0);
+    /// ```
+    #[track_caller]
+    pub fn htod_copy_from_iter<T: DeviceRepr>(
+        self: &Arc<Self>,
+        iter: impl Iterator<Item = T>,
+        total_len: usize,
+    ) -> Result<CudaSlice<T>, result::DriverError> {
+        let chunk_len = (STAGING_BYTES / std::mem::size_of::<T>().max(1)).max(1);
+        self.htod_copy_from_iter_chunked(iter, total_len, chunk_len)
+    }
+
+    #[track_caller]
+    fn htod_copy_from_iter_chunked<T: DeviceRepr>(
+        self: &Arc<Self>,
+        mut iter: impl Iterator<Item = T>,
+        total_len: usize,
+        chunk_len: usize,
+    ) -> Result<CudaSlice<T>, result::DriverError> {
+        let mut dst = unsafe { self.alloc::<T>(total_len) }?;
+        let chunk_len = chunk_len.min(total_len);
+        if chunk_len == 0 {
+            return Ok(dst);
+        }
+        let mut staging = [
+            StagingBuffer::new(chunk_len * std::mem::size_of::<T>())?,
+            StagingBuffer::new(chunk_len * std::mem::size_of::<T>())?,
+        ];
+        for (i, chunk) in dst.chunks_mut(chunk_len).enumerate() {
+            let buf = &mut staging[i % 2];
+            // wait for the previous copy out of this buffer before overwriting it
+            unsafe { result::event::synchronize(buf.event) }?;
+            let host = buf.ptr as *mut T;
+            for j in 0..chunk.len {
+                // the staging buffers wait for the in-flight copies when dropped
+                let item = iter.next().ok_or(result::DriverError::Cuda(
+                    sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+                ))?;
+                unsafe { host.add(j).write(item) };
+            }
+            let host = unsafe { std::slice::from_raw_parts(host, chunk.len) };
+            unsafe { result::memcpy_htod_async(chunk.ptr, host, self.stream) }?;
+            unsafe { result::event::record(buf.event, self.stream) }?;
+        }
+        for buf in staging.iter() {
+            unsafe { result::event::synchronize(buf.event) }?;
+        }
+        Ok(dst)
     }
 
     /// Allocates new device memory and synchronously copies data from `src` into the new allocation.
@@ -272,6 +598,7 @@ impl CudaDevice {
     ///
     /// 1. Since this function doesn't own `src` it is executed synchronously.
     /// 2. Self is [`Arc<Self>`], and this method increments the rc for self
+    #[track_caller]
     pub fn htod_sync_copy<T: DeviceRepr>(
         self: &Arc<Self>,
         src: &[T],
@@ -298,12 +625,19 @@ impl CudaDevice {
         dst: &mut Dst,
     ) -> Result<(), result::DriverError> {
         assert_eq!(src.len(), dst.len());
-        self.bind_to_thread()?;
-        if self.is_async {
+        let _ctx = self.bind_scoped()?;
+        self.wait_for_write(self.stream, dst)?;
+        let num_bytes = std::mem::size_of_val(src);
+        let staged =
+            unsafe { self.staged_htod(*dst.device_ptr_mut(), src.as_ptr() as _, num_bytes) }?;
+        if staged {
+            // enqueued through page-locked buffers of the staging pool
+        } else if self.is_async {
             unsafe { result::memcpy_htod_async(*dst.device_ptr_mut(), src, self.stream) }?;
         } else {
             unsafe { result::memcpy_htod_sync(*dst.device_ptr_mut(), src) }?;
         }
+        self.record_write(self.stream, dst)?;
         self.synchronize()
     }
 
@@ -342,8 +676,14 @@ impl CudaDevice {
         dst: &mut [T],
     ) -> Result<(), result::DriverError> {
         assert_eq!(src.len(), dst.len());
-        self.bind_to_thread()?;
-        if self.is_async {
+        let _ctx = self.bind_scoped()?;
+        self.wait_for_write(self.stream, src)?;
+        let num_bytes = std::mem::size_of_val(dst);
+        let staged =
+            unsafe { self.staged_dtoh(dst.as_mut_ptr() as _, *src.device_ptr(), num_bytes) }?;
+        if staged {
+            // copied through page-locked buffers of the staging pool
+        } else if self.is_async {
             unsafe { result::memcpy_dtoh_async(dst, *src.device_ptr(), self.stream) }?;
         } else {
             unsafe { result::memcpy_dtoh_sync(dst, *src.device_ptr()) }?;
@@ -351,6 +691,114 @@ impl CudaDevice {
         self.synchronize()
     }
 
+    /// Copies `src[index]` to the host on `stream` without blocking, returning a
+    /// [PendingValue] that can be polled or waited on, e.g. to read back a loss
+    /// without synchronizing the stream.
+    ///
+    /// The value is copied into page-locked memory from a small pool kept by the device,
+    /// so this doesn't allocate host memory once the pool is warm.
+    ///
+    /// If `stream` isn't the device's stream, the read is recorded in
+    /// [CudaSlice::last_write_event()] so that dropping `src` waits for it. For a view, the
+    /// device's stream waits for the read instead.
+    ///
+    /// Returns `None` if `index >= src.len()`.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::safe::CudaDevice;
+    /// # let dev = CudaDevice::new(0).unwrap();
+    /// let stream = dev.fork_default_stream().unwrap();
+    /// let a = dev.htod_sync_copy(&[1.0f32, 2.0, 3.0]).unwrap();
+    /// let loss = dev.dtoh_scalar_async(&a, 2, &stream).unwrap().unwrap();
+    /// assert_eq!(loss.wait().unwrap(), 3.0);
+    /// ```
+    pub fn dtoh_scalar_async<T: DeviceRepr + Copy, Src: DevicePtr<T>>(
+        self: &Arc<Self>,
+        src: &Src,
+        index: usize,
+        stream: &CudaStream,
+    ) -> Result<Option<PendingValue<T>>, result::DriverError> {
+        let Some(src_ptr) = src.try_at(index) else {
+            return Ok(None);
+        };
+        let _ctx = self.bind_scoped()?;
+        self.wait_for_write(stream.stream, src)?;
+        let pending = unsafe { self.readback_scalar(src_ptr, stream.stream) }?;
+        if stream.stream != self.stream {
+            // `src` is freed on the device's stream when dropped, which must come after the read
+            match src.last_write() {
+                Some(last_write) => last_write.record(stream.stream)?,
+                None => unsafe {
+                    result::stream::wait_event(
+                        self.stream,
+                        pending.slot.as_ref().unwrap().event,
+                        sys::CUevent_wait_flags::CU_EVENT_WAIT_DEFAULT,
+                    )
+                }?,
+            }
+        }
+        Ok(Some(pending))
+    }
+
+    /// Copies the `T` at `src_ptr` into a pinned slot on `stream`, see
+    /// [CudaDevice::dtoh_scalar_async()]. The context must be bound.
+    ///
+    /// # Safety
+    /// `src_ptr` must point to a `T` on this device.
+    pub(crate) unsafe fn readback_scalar<T: DeviceRepr + Copy>(
+        self: &Arc<Self>,
+        src_ptr: sys::CUdeviceptr,
+        stream: sys::CUstream,
+    ) -> Result<PendingValue<T>, result::DriverError> {
+        let slot = self.take_pinned_slot(std::mem::size_of::<T>())?;
+        let dst =
+            unsafe { std::slice::from_raw_parts_mut(slot.ptr as *mut std::mem::MaybeUninit<T>, 1) };
+        let pending = PendingValue {
+            slot: Some(slot),
+            device: self.clone(),
+            marker: PhantomData,
+        };
+        let slot = pending.slot.as_ref().unwrap();
+        result::memcpy_dtoh_async(dst, src_ptr, stream)?;
+        result::event::record(slot.event, stream)?;
+        Ok(pending)
+    }
+
+    fn take_pinned_slot(&self, num_bytes: usize) -> Result<StagingBuffer, result::DriverError> {
+        let slot = {
+            #[allow(unused_mut)]
+            let mut slots = self.pinned_slots.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut slots = slots.unwrap();
+            slots
+                .iter()
+                .position(|slot| slot.num_bytes >= num_bytes)
+                .map(|i| slots.swap_remove(i))
+        };
+        match slot {
+            Some(slot) => {
+                // a dropped [PendingValue] may not have completed
+                unsafe { result::event::synchronize(slot.event) }?;
+                Ok(slot)
+            }
+            None => StagingBuffer::new(num_bytes.max(PINNED_SLOT_BYTES)),
+        }
+    }
+
+    fn return_pinned_slot(&self, slot: StagingBuffer) {
+        #[allow(unused_mut)]
+        let mut slots = self.pinned_slots.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut slots = slots.unwrap();
+        if slots.len() < PINNED_SLOTS_MAX {
+            slots.push(slot);
+            return;
+        }
+        drop(slots);
+        let _ctx = self.bind_scoped_or_handle("StagingBuffer::drop");
+        drop(slot);
+    }
+
     /// Synchronously de-allocates `src` and converts it into it's host value.
     /// You can just [drop] the slice if you don't need the host data.
     ///
@@ -370,10 +818,273 @@ impl CudaDevice {
         Ok(Pin::into_inner(buf))
     }
 
-    /// Synchronizes the stream.
+    /// Synchronizes the stream, and frees the slices dropped with
+    /// [CudaSlice::drop_on_stream()] whose frees were deferred.
     pub fn synchronize(self: &Arc<Self>) -> Result<(), result::DriverError> {
-        self.bind_to_thread()?;
-        unsafe { result::stream::synchronize(self.stream) }
+        let _ctx = self.bind_scoped()?;
+        unsafe { result::stream::synchronize(self.stream) }.map_err(|e| self.annotate_error(e))?;
+        self.drain_deferred_frees()
+    }
+}
+
+/// Variants of the allocation and copy methods that enqueue all their work on an explicit
+/// stream and never synchronize, so they can be used while the stream is being captured
+/// into a [super::CudaGraph].
+impl CudaDevice {
+    /// Allocates device memory in stream order on `stream`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device doesn't support
+    /// async allocations, unless there is an allocator set with [CudaDevice::set_allocator()].
+    ///
+    /// # Safety
+    /// This is unsafe because the device memory is unset after this call.
+    #[track_caller]
+    pub unsafe fn alloc_on_stream<T: DeviceRepr>(
+        self: &Arc<Self>,
+        len: usize,
+        stream: &CudaStream,
+    ) -> Result<CudaSlice<T>, result::DriverError> {
+        let _ctx = stream.bind_scoped()?;
+        let num_bytes = len * std::mem::size_of::<T>();
+        let (cu_device_ptr, allocator) =
+            self.alloc_with_allocator(num_bytes, stream.stream, || {
+                if !self.is_async {
+                    return Err(result::DriverError::Cuda(
+                        sys::CUresult::CUDA_ERROR_NOT_SUPPORTED,
+                    ));
+                }
+                result::malloc_async(stream.stream, num_bytes)
+            })?;
+        Ok(self.tracked(CudaSlice {
+            cu_device_ptr,
+            len,
+            device: self.clone(),
+            host_buf: None,
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator,
+        }))
+    }
+
+    /// Allocates device memory on `stream` and memsets it to all 0s,
+    /// see [CudaDevice::alloc_on_stream()].
+    #[track_caller]
+    pub fn alloc_zeros_on_stream<T: ValidAsZeroBits + DeviceRepr>(
+        self: &Arc<Self>,
+        len: usize,
+        stream: &CudaStream,
+    ) -> Result<CudaSlice<T>, result::DriverError> {
+        let mut dst = unsafe { self.alloc_on_stream(len, stream) }?;
+        self.memset_zeros_on_stream(&mut dst, stream)?;
+        Ok(dst)
+    }
+
+    /// Sets all memory to 0 on `stream`.
+    pub fn memset_zeros_on_stream<T: ValidAsZeroBits + DeviceRepr, Dst: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        dst: &mut Dst,
+        stream: &CudaStream,
+    ) -> Result<(), result::DriverError> {
+        let _ctx = stream.bind_scoped()?;
+        self.wait_for_write(stream.stream, dst)?;
+        unsafe {
+            result::memset_d8_async(*dst.device_ptr_mut(), 0, dst.num_bytes(), stream.stream)
+        }?;
+        self.record_write(stream.stream, dst)
+    }
+
+    /// Device to device copy on `stream`.
+    ///
+    /// # Panics
+    ///
+    /// If the length of the two values are different
+    pub fn dtod_copy_on_stream<T: DeviceRepr, Src: DevicePtr<T>, Dst: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        src: &Src,
+        dst: &mut Dst,
+        stream: &CudaStream,
+    ) -> Result<(), result::DriverError> {
+        assert_eq!(src.len(), dst.len());
+        let _ctx = stream.bind_scoped()?;
+        self.wait_for_write(stream.stream, src)?;
+        self.wait_for_write(stream.stream, dst)?;
+        unsafe {
+            result::memcpy_dtod_async(
+                *dst.device_ptr_mut(),
+                *src.device_ptr(),
+                src.len() * std::mem::size_of::<T>(),
+                stream.stream,
+            )
+        }?;
+        self.record_write(stream.stream, dst)
+    }
+
+    /// Copies `src` to `dst` on `stream`.
+    ///
+    /// # Panics
+    ///
+    /// If the length of the two values are different
+    ///
+    /// # Safety
+    /// The copy reads `src` when it executes, so `src` must not be modified or dropped until
+    /// it has completed. When captured into a graph, the copy reads `src` on every launch.
+    pub unsafe fn htod_copy_on_stream<T: DeviceRepr, Dst: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        src: &PinnedBuffer<T>,
+        dst: &mut Dst,
+        stream: &CudaStream,
+    ) -> Result<(), result::DriverError> {
+        assert_eq!(src.len(), dst.len());
+        let _ctx = stream.bind_scoped()?;
+        self.wait_for_write(stream.stream, dst)?;
+        result::memcpy_htod_async(*dst.device_ptr_mut(), src.as_slice(), stream.stream)?;
+        self.record_write(stream.stream, dst)
+    }
+
+    /// Copies `src` to `dst` on `stream`.
+    ///
+    /// # Panics
+    ///
+    /// If the length of the two values are different
+    ///
+    /// # Safety
+    /// The copy writes `dst` when it executes, so `dst` must not be accessed or dropped until
+    /// it has completed. When captured into a graph, the copy writes `dst` on every launch.
+    pub unsafe fn dtoh_copy_on_stream<T: DeviceRepr, Src: DevicePtr<T>>(
+        self: &Arc<Self>,
+        src: &Src,
+        dst: &mut PinnedBuffer<T>,
+        stream: &CudaStream,
+    ) -> Result<(), result::DriverError> {
+        assert_eq!(src.len(), dst.len());
+        let _ctx = stream.bind_scoped()?;
+        self.wait_for_write(stream.stream, src)?;
+        result::memcpy_dtoh_async(dst.as_mut_slice(), *src.device_ptr(), stream.stream)
+    }
+}
+
+impl<T> CudaSlice<T> {
+    /// Frees the slice in stream order on `stream`, instead of on the device's default stream.
+    ///
+    /// If the device doesn't support async allocations, the memory is freed once an event
+    /// recorded on `stream` has completed, without synchronizing: later allocations free
+    /// the slices whose events have completed, and [CudaDevice::synchronize()] waits for
+    /// and frees all of them.
+    pub fn drop_on_stream(self, stream: &CudaStream) -> Result<(), result::DriverError> {
+        let mut slice = std::mem::ManuallyDrop::new(self);
+        let device = unsafe { std::ptr::read(&slice.device) };
+        let host_buf = unsafe { std::ptr::read(&slice.host_buf) };
+        let allocator = unsafe { std::ptr::read(&slice.allocator) };
+        let _ctx = stream.bind_scoped()?;
+        slice
+            .last_write
+            .release(stream.stream, "CudaSlice::drop_on_stream");
+        device.untrack(&slice);
+        let num_bytes = slice.alloc_bytes();
+        let ptr = slice.release_alloc_base();
+        let res = unsafe {
+            if let Some(allocator) = allocator {
+                allocator.free(ptr, num_bytes, stream.stream)
+            } else if device.is_async {
+                result::free_async(ptr, stream.stream)
+            } else {
+                device.defer_free(ptr, stream.stream)
+            }
+        };
+        drop(host_buf);
+        res
+    }
+}
+
+/// Size of each of the page-locked buffers used by [CudaDevice::htod_copy_from_iter()].
+const STAGING_BYTES: usize = 1 << 22;
+
+/// Number of [StagingBuffer]s kept by [CudaDevice::dtoh_scalar_async()] for reuse.
+const PINNED_SLOTS_MAX: usize = 16;
+
+/// Minimum size of the [StagingBuffer]s used by [CudaDevice::dtoh_scalar_async()].
+const PINNED_SLOT_BYTES: usize = 64;
+
+/// Page-locked host memory, and an event recorded after the last copy into or out of it.
+#[derive(Debug)]
+pub(crate) struct StagingBuffer {
+    pub(crate) ptr: *mut std::ffi::c_void,
+    pub(crate) num_bytes: usize,
+    pub(crate) event: sys::CUevent,
+}
+
+impl StagingBuffer {
+    pub(crate) fn new(num_bytes: usize) -> Result<Self, result::DriverError> {
+        let ptr = unsafe { result::malloc_host(num_bytes, 0) }?;
+        let event = match result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING) {
+            Ok(event) => event,
+            Err(e) => {
+                unsafe { result::free_host(ptr) }.or_handle("StagingBuffer::new");
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            ptr,
+            num_bytes,
+            event,
+        })
+    }
+}
+
+impl Drop for StagingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            result::event::synchronize(self.event).or_handle("StagingBuffer::drop");
+            result::event::destroy(self.event).or_handle("StagingBuffer::drop");
+            result::free_host(self.ptr).or_handle("StagingBuffer::drop");
+        }
+    }
+}
+
+/// A value being copied to the host by [CudaDevice::dtoh_scalar_async()].
+///
+/// Dropping it before the copy has completed is fine, the pinned memory is
+/// only reused once it has.
+#[derive(Debug)]
+pub struct PendingValue<T> {
+    slot: Option<StagingBuffer>,
+    device: Arc<CudaDevice>,
+    marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for PendingValue<T> {}
+unsafe impl<T: Sync> Sync for PendingValue<T> {}
+
+impl<T: DeviceRepr + Copy> PendingValue<T> {
+    /// Returns the value if the copy has completed, and `None` if it hasn't yet.
+    pub fn try_get(&self) -> Result<Option<T>, result::DriverError> {
+        let slot = self.slot.as_ref().unwrap();
+        if unsafe { result::event::query(slot.event) }? {
+            Ok(Some(self.read()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Blocks until the copy has completed and returns the value.
+    pub fn wait(self) -> Result<T, result::DriverError> {
+        let slot = self.slot.as_ref().unwrap();
+        unsafe { result::event::synchronize(slot.event) }?;
+        Ok(self.read())
+    }
+
+    fn read(&self) -> T {
+        let slot = self.slot.as_ref().unwrap();
+        unsafe { std::ptr::read(slot.ptr as *const T) }
+    }
+}
+
+impl<T> Drop for PendingValue<T> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            self.device.return_pinned_slot(slot);
+        }
     }
 }
 
@@ -539,6 +1250,145 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_htod_copy_from_iter() {
+        let dev = CudaDevice::new(0).unwrap();
+        let a = dev
+            .htod_copy_from_iter_chunked((0..10u32).map(|i| i * i), 10, 3)
+            .unwrap();
+        let expected: Vec<u32> = (0..10u32).map(|i| i * i).collect();
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), expected);
+
+        let b = dev.htod_copy_from_iter(0..1u8, 0).unwrap();
+        assert_eq!(b.len(), 0);
+
+        let short = dev.htod_copy_from_iter_chunked(0..5u32, 10, 3);
+        assert_eq!(
+            short.err(),
+            Some(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_INVALID_VALUE
+            ))
+        );
+    }
+
+    #[test]
+    fn test_dtoh_scalar_async() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        let a = dev.htod_sync_copy(&[1.5f64, -2.0, 7.25]).unwrap();
+        assert!(dev.dtoh_scalar_async(&a, 3, &stream).unwrap().is_none());
+
+        for _ in 0..3 {
+            let pending = dev.dtoh_scalar_async(&a, 2, &stream).unwrap().unwrap();
+            let value = loop {
+                if let Some(value) = pending.try_get().unwrap() {
+                    break value;
+                }
+            };
+            assert_eq!(value, 7.25);
+            assert_eq!(pending.wait().unwrap(), 7.25);
+        }
+        let pending: Vec<_> = (0..3)
+            .map(|i| {
+                dev.dtoh_scalar_async(&a.slice(..), i, &stream)
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+        let values: Vec<_> = pending.into_iter().map(|p| p.wait().unwrap()).collect();
+        assert_eq!(values, [1.5, -2.0, 7.25]);
+        let slots = dev.pinned_slots.read();
+        #[cfg(not(feature = "no-std"))]
+        let slots = slots.unwrap();
+        assert_eq!(slots.len(), 3);
+    }
+
+    #[test]
+    fn test_dtoh_scalar_async_outlives_src() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        let a = dev.htod_sync_copy(&[0.5f32; 1 << 20]).unwrap();
+        let pending = dev
+            .dtoh_scalar_async(&a, 1 << 19, &stream)
+            .unwrap()
+            .unwrap();
+        assert!(a.last_write_event().is_some());
+        drop(a);
+        assert_eq!(pending.wait().unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_alloc_aligned() {
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(dev.aligned_padding_bytes(), 0);
+        for align in [1, 256, 4096, 65536, 1 << 21] {
+            let slice = dev.alloc_zeros_aligned::<f32>(1000, align).unwrap();
+            let ptr = *slice.device_ptr();
+            assert_eq!(ptr % align as u64, 0, "{align}");
+            if align <= DRIVER_ALIGNMENT {
+                assert!(slice.aligned_base.is_none());
+                continue;
+            }
+            assert_eq!(dev.aligned_padding_bytes(), align - 1);
+            // the slice owns the whole allocation, and stays inside of it
+            let (base, size) = unsafe { result::pointer::get_address_range(ptr) }.unwrap();
+            assert_eq!(slice.aligned_base, Some((base, align - 1)));
+            assert!(size >= 4000 + align - 1);
+            assert!(ptr + 4000 <= base + size as u64);
+            assert_eq!(dev.dtoh_sync_copy(&slice).unwrap(), [0.0; 1000]);
+            drop(slice);
+            assert_eq!(dev.aligned_padding_bytes(), 0);
+        }
+        assert!(unsafe { dev.alloc_aligned::<f32>(10, 3000) }.is_err());
+        assert!(unsafe { dev.alloc_aligned::<f32>(10, 0) }.is_err());
+    }
+
+    #[test]
+    fn test_leak_aligned_returns_base() {
+        let dev = CudaDevice::new(0).unwrap();
+        let slice = dev.alloc_zeros_aligned::<f32>(1000, 65536).unwrap();
+        let (base, _) = slice.aligned_base.unwrap();
+        assert_eq!(slice.leak(), base);
+        assert_eq!(dev.aligned_padding_bytes(), 0);
+        // upgraded from the base, the slice frees the whole allocation
+        let slice = unsafe { dev.upgrade_device_ptr::<f32>(base, 1000) };
+        assert_eq!(dev.dtoh_sync_copy(&slice).unwrap().len(), 1000);
+    }
+
+    #[cfg(feature = "allocation-tracking")]
+    #[test]
+    fn test_alloc_stats() {
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(dev.alloc_stats(), AllocStats::default());
+        let a = dev.alloc_zeros::<f32>(100).unwrap();
+        let b = dev.alloc_zeros_aligned::<u8>(1000, 4096).unwrap();
+        assert_eq!(
+            dev.alloc_stats(),
+            AllocStats {
+                slices: 2,
+                bytes: 400 + 1000 + 4095,
+                peak_bytes: 400 + 1000 + 4095,
+                padding_bytes: 4095,
+            }
+        );
+        drop(b);
+        let ptr = a.leak();
+        assert_eq!(
+            dev.alloc_stats(),
+            AllocStats {
+                slices: 0,
+                bytes: 0,
+                peak_bytes: 400 + 1000 + 4095,
+                padding_bytes: 0,
+            }
+        );
+        let c = unsafe { dev.upgrade_device_ptr::<f32>(ptr, 100) };
+        let stream = dev.fork_default_stream().unwrap();
+        assert_eq!(dev.alloc_stats().slices, 1);
+        c.drop_on_stream(&stream).unwrap();
+        assert_eq!(dev.alloc_stats().bytes, 0);
+    }
+
     #[test]
     fn test_leak_and_upgrade() {
         let dev = CudaDevice::new(0).unwrap();
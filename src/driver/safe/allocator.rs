@@ -0,0 +1,353 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+use super::DriverError;
+
+use std::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// Allocates the device memory of [super::CudaSlice]s in place of the driver, once set with
+/// [CudaDevice::set_allocator()], e.g. to cache freed blocks like [CachingAllocator].
+///
+/// Every allocation of a [CudaDevice] goes through it, including [CudaDevice::alloc_zeros()],
+/// copies to new slices and the workspaces the wrapper libraries allocate, except for
+/// [CudaDevice::alloc_from_pool()] and other allocations of a specific kind of memory. Each
+/// slice is freed by the allocator it was allocated with, even if it has been replaced
+/// since.
+///
+/// Both methods are called with the context of the device bound to the calling thread.
+pub trait DeviceAllocator: core::fmt::Debug + Send + Sync {
+    /// Allocates `bytes` of device memory that can be used by work enqueued on `stream`,
+    /// aligned to at least 256 bytes like the driver's allocations. `bytes` may be `0`.
+    fn alloc(&self, bytes: usize, stream: sys::CUstream) -> Result<sys::CUdeviceptr, DriverError>;
+
+    /// Frees `ptr` after the work enqueued on `stream` so far, which may still use it.
+    ///
+    /// # Safety
+    /// `ptr` must have been returned by [DeviceAllocator::alloc()] of this allocator for
+    /// `bytes` bytes, and not be freed already.
+    unsafe fn free(
+        &self,
+        ptr: sys::CUdeviceptr,
+        bytes: usize,
+        stream: sys::CUstream,
+    ) -> Result<(), DriverError>;
+}
+
+impl CudaDevice {
+    /// Allocates the slices allocated from now on with `allocator`, or with the driver again
+    /// with `None`. Slices allocated before are freed the way they were allocated.
+    pub fn set_allocator(&self, allocator: Option<Arc<dyn DeviceAllocator>>) {
+        #[allow(unused_mut)]
+        let mut current = self.allocator.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut current = current.unwrap();
+        *current = allocator;
+    }
+
+    /// The allocator set with [CudaDevice::set_allocator()].
+    pub fn allocator(&self) -> Option<Arc<dyn DeviceAllocator>> {
+        let current = self.allocator.read();
+        #[cfg(not(feature = "no-std"))]
+        let current = current.unwrap();
+        current.clone()
+    }
+
+    /// Allocates `num_bytes` for a slice used on `stream` with the allocator of
+    /// [CudaDevice::set_allocator()], returned to free the slice with, or with `driver` if
+    /// there is none. The context must be bound to the calling thread.
+    pub(crate) fn alloc_with_allocator(
+        &self,
+        num_bytes: usize,
+        stream: sys::CUstream,
+        driver: impl FnOnce() -> Result<sys::CUdeviceptr, DriverError>,
+    ) -> Result<(sys::CUdeviceptr, Option<Arc<dyn DeviceAllocator>>), DriverError> {
+        match self.allocator() {
+            Some(allocator) => Ok((allocator.alloc(num_bytes, stream)?, Some(allocator))),
+            None => Ok((driver()?, None)),
+        }
+    }
+}
+
+/// The smallest block of a [CachingAllocator].
+const MIN_BLOCK_BYTES: usize = 512;
+
+/// The counters of a [CachingAllocator].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocatorStats {
+    /// Allocations of a cached block.
+    pub hits: u64,
+    /// Allocations of a new block from the driver.
+    pub misses: u64,
+    /// Blocks returned to the cache.
+    pub frees: u64,
+    /// The bytes of the blocks in use.
+    pub bytes_in_use: usize,
+    /// The bytes of the cached blocks.
+    pub bytes_cached: usize,
+}
+
+/// The context a block was allocated in, the stream it was freed on and its size.
+type BlockKey = (usize, usize, usize);
+
+#[derive(Debug, Default)]
+struct CacheState {
+    free: BTreeMap<BlockKey, Vec<sys::CUdeviceptr>>,
+    stats: AllocatorStats,
+}
+
+/// A [DeviceAllocator] that keeps freed blocks for reuse instead of returning them to the
+/// driver.
+///
+/// Allocations are rounded up to a power of two of at least 512 bytes, and served from a
+/// block of that size allocated in the same context and freed on the same stream if there
+/// is one, so that the work of the previous owner is ordered before the new one. New blocks
+/// are allocated with `cuMemAlloc`. If that fails, the cached blocks of the context are
+/// freed and the allocation retried.
+///
+/// It can be shared by several devices. Cached blocks are only freed by
+/// [CachingAllocator::empty_cache()] of their device, always in the context they were
+/// allocated in, or with that context. Empty the cache of a device with a context that
+/// isn't primary before dropping it, since a new context could get the same handle.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// # use std::sync::Arc;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let cache = Arc::new(CachingAllocator::new());
+/// dev.set_allocator(Some(cache.clone()));
+/// drop(dev.alloc_zeros::<f32>(1000).unwrap());
+/// let _reused = dev.alloc_zeros::<f32>(900).unwrap();
+/// assert_eq!(cache.stats().misses, 1);
+/// assert_eq!(cache.stats().hits, 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct CachingAllocator {
+    state: RwLock<CacheState>,
+}
+
+impl CachingAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        let state = self.state.read();
+        #[cfg(not(feature = "no-std"))]
+        let state = state.unwrap();
+        state.stats
+    }
+
+    /// Frees the cached blocks allocated in the context of `device`.
+    pub fn empty_cache(&self, device: &CudaDevice) -> Result<(), DriverError> {
+        let _ctx = device.bind_scoped()?;
+        #[allow(unused_mut)]
+        let mut state = self.state.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        Self::free_cached(&mut state, device.cu_ctx as usize)
+    }
+
+    /// The context bound to the calling thread, which the blocks allocated now belong to.
+    fn current_ctx() -> Result<usize, DriverError> {
+        result::ctx::get_current()?
+            .map(|ctx| ctx as usize)
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_CONTEXT))
+    }
+
+    /// The size of the block allocated for `bytes`.
+    fn block_bytes(bytes: usize) -> Result<usize, DriverError> {
+        bytes
+            .max(MIN_BLOCK_BYTES)
+            .checked_next_power_of_two()
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY))
+    }
+
+    /// Frees the cached blocks of `ctx`, which must be bound.
+    fn free_cached(state: &mut CacheState, ctx: usize) -> Result<(), DriverError> {
+        let keys: Vec<BlockKey> = state
+            .free
+            .range((ctx, 0, 0)..=(ctx, usize::MAX, usize::MAX))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            let Some(mut ptrs) = state.free.remove(&key) else {
+                continue;
+            };
+            while let Some(ptr) = ptrs.pop() {
+                if let Err(e) = unsafe { result::free_sync(ptr) } {
+                    ptrs.push(ptr);
+                    state.free.insert(key, ptrs);
+                    return Err(e);
+                }
+                state.stats.bytes_cached -= key.2;
+            }
+        }
+        Ok(())
+    }
+
+    /// The bytes of the cached blocks of `ctx`.
+    fn bytes_cached_in(state: &CacheState, ctx: usize) -> usize {
+        state
+            .free
+            .range((ctx, 0, 0)..=(ctx, usize::MAX, usize::MAX))
+            .map(|((_, _, block), ptrs)| block * ptrs.len())
+            .sum()
+    }
+}
+
+impl DeviceAllocator for CachingAllocator {
+    fn alloc(&self, bytes: usize, stream: sys::CUstream) -> Result<sys::CUdeviceptr, DriverError> {
+        let block = Self::block_bytes(bytes)?;
+        let ctx = Self::current_ctx()?;
+        #[allow(unused_mut)]
+        let mut state = self.state.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        let cached = state
+            .free
+            .get_mut(&(ctx, stream as usize, block))
+            .and_then(|ptrs| ptrs.pop());
+        let ptr = match cached {
+            Some(ptr) => {
+                state.stats.hits += 1;
+                state.stats.bytes_cached -= block;
+                ptr
+            }
+            None => {
+                let ptr = match unsafe { result::malloc_sync(block) } {
+                    Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY))
+                        if Self::bytes_cached_in(&state, ctx) > 0 =>
+                    {
+                        Self::free_cached(&mut state, ctx)?;
+                        unsafe { result::malloc_sync(block) }?
+                    }
+                    res => res?,
+                };
+                state.stats.misses += 1;
+                ptr
+            }
+        };
+        state.stats.bytes_in_use += block;
+        Ok(ptr)
+    }
+
+    unsafe fn free(
+        &self,
+        ptr: sys::CUdeviceptr,
+        bytes: usize,
+        stream: sys::CUstream,
+    ) -> Result<(), DriverError> {
+        let block = Self::block_bytes(bytes)?;
+        let ctx = Self::current_ctx()?;
+        #[allow(unused_mut)]
+        let mut state = self.state.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        state
+            .free
+            .entry((ctx, stream as usize, block))
+            .or_default()
+            .push(ptr);
+        state.stats.frees += 1;
+        state.stats.bytes_in_use -= block;
+        state.stats.bytes_cached += block;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::DevicePtr;
+
+    #[test]
+    fn test_block_bytes() {
+        assert_eq!(CachingAllocator::block_bytes(0), Ok(512));
+        assert_eq!(CachingAllocator::block_bytes(512), Ok(512));
+        assert_eq!(CachingAllocator::block_bytes(513), Ok(1024));
+        assert_eq!(CachingAllocator::block_bytes(3 << 20), Ok(4 << 20));
+        assert_eq!(
+            CachingAllocator::block_bytes(usize::MAX),
+            Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY))
+        );
+    }
+
+    #[test]
+    fn test_caching_allocator_reuses_blocks() {
+        let dev = CudaDevice::new(0).unwrap();
+        let cache = Arc::new(CachingAllocator::new());
+        let before = dev.alloc_zeros::<f32>(10).unwrap();
+        dev.set_allocator(Some(cache.clone()));
+
+        let a = dev.alloc_zeros::<f32>(1000).unwrap();
+        let a_ptr = *a.device_ptr();
+        let b = dev.htod_sync_copy(&[1u8; 100]).unwrap();
+        assert_eq!(
+            cache.stats(),
+            AllocatorStats {
+                misses: 2,
+                bytes_in_use: 4096 + 512,
+                ..Default::default()
+            }
+        );
+        drop(a);
+        drop(b);
+        // slices allocated earlier are still freed by the driver
+        drop(before);
+        assert_eq!(cache.stats().frees, 2);
+        assert_eq!(cache.stats().bytes_cached, 4096 + 512);
+
+        let c = dev.alloc_zeros::<f32>(700).unwrap();
+        assert_eq!(*c.device_ptr(), a_ptr);
+        let d = dev.alloc_zeros::<u16>(3).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&d).unwrap(), [0; 3]);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!(stats.bytes_cached, 0);
+
+        // blocks freed on another stream aren't reused on the default stream
+        let stream = dev.fork_default_stream().unwrap();
+        let e = unsafe { dev.alloc_on_stream::<u8>(5000, &stream) }.unwrap();
+        e.drop_on_stream(&stream).unwrap();
+        drop(dev.alloc_zeros::<u8>(5000).unwrap());
+        assert_eq!(cache.stats().misses, 4);
+
+        dev.set_allocator(None);
+        drop(c);
+        drop(d);
+        assert_eq!(cache.stats().bytes_in_use, 0);
+        cache.empty_cache(&dev).unwrap();
+        assert_eq!(cache.stats().bytes_cached, 0);
+    }
+
+    #[test]
+    fn test_caching_allocator_keys_blocks_by_context() {
+        let dev = CudaDevice::new(0).unwrap();
+        let owned = CudaDevice::new_with_owned_context(0, 0).unwrap();
+        let cache = Arc::new(CachingAllocator::new());
+        dev.set_allocator(Some(cache.clone()));
+        owned.set_allocator(Some(cache.clone()));
+
+        drop(dev.alloc_zeros::<u8>(1000).unwrap());
+        drop(owned.alloc_zeros::<u8>(1000).unwrap());
+        // both contexts' blocks are cached, neither is reused by the other
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (0, 2));
+        assert_eq!(stats.bytes_cached, 2 * 1024);
+        let x = owned.alloc_zeros::<u8>(1000).unwrap();
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(owned.dtoh_sync_copy(&x).unwrap(), [0; 1000]);
+        drop(x);
+
+        // only the blocks of the device's own context are freed, in that context
+        cache.empty_cache(&owned).unwrap();
+        assert_eq!(cache.stats().bytes_cached, 1024);
+        cache.empty_cache(&dev).unwrap();
+        assert_eq!(cache.stats().bytes_cached, 0);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::driver::{result, sys};
+
+use super::DriverError;
+
+use std::vec::Vec;
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// The physical ordinals of the allowed devices, in the order they are visible.
+static ALLOWLIST: RwLock<Option<Vec<usize>>> = RwLock::new(None);
+
+/// Restricts the devices cudarc uses in this process to the physical ordinals in `ordinals`,
+/// like `CUDA_VISIBLE_DEVICES` does for a whole process, e.g. for a plugin that can't change
+/// the environment of its host.
+///
+/// Ordinals passed to [super::CudaDevice::new()] and returned by
+/// [super::CudaDevice::ordinal()] are then indices into `ordinals`, so the allowed devices
+/// appear as `0..n`. [super::CudaDevice::count()] returns `n`, and creating a device past
+/// it returns [DriverError::DeviceNotAllowed]. Physical ordinals are the driver's, so
+/// they are still subject to `CUDA_VISIBLE_DEVICES`. Duplicates are ignored, and ordinals
+/// without a device are skipped.
+///
+/// Set the allowlist before creating any [super::CudaDevice], devices that already exist
+/// keep the ordinal they were created with.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// set_device_allowlist(&[0]);
+/// let dev = CudaDevice::new(0).unwrap();
+/// assert_eq!(dev.physical_ordinal(), 0);
+/// assert!(CudaDevice::new(1).is_err());
+/// clear_device_allowlist();
+/// ```
+pub fn set_device_allowlist(ordinals: &[usize]) {
+    let mut allowed = Vec::with_capacity(ordinals.len());
+    for &ordinal in ordinals {
+        if !allowed.contains(&ordinal) {
+            allowed.push(ordinal);
+        }
+    }
+    #[allow(unused_mut)]
+    let mut list = ALLOWLIST.write();
+    #[cfg(not(feature = "no-std"))]
+    let mut list = list.unwrap();
+    *list = Some(allowed);
+}
+
+/// [set_device_allowlist()] with the devices' UUIDs, as reported by `nvidia-smi -L`,
+/// which unlike ordinals don't depend on `CUDA_VISIBLE_DEVICES` or the enumeration order.
+///
+/// Returns [sys::CUresult::CUDA_ERROR_INVALID_DEVICE] if a UUID doesn't belong to any
+/// device, leaving the allowlist unchanged.
+pub fn set_device_allowlist_by_uuid(uuids: &[[u8; 16]]) -> Result<(), DriverError> {
+    result::init()?;
+    let count = result::device::get_count()?;
+    let physical = (0..count)
+        .map(|ordinal| result::device::get_uuid(result::device::get(ordinal)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let ordinals = uuids
+        .iter()
+        .map(|uuid| {
+            physical
+                .iter()
+                .position(|p| p == uuid)
+                .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_DEVICE))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    set_device_allowlist(&ordinals);
+    Ok(())
+}
+
+/// Removes the allowlist set by [set_device_allowlist()], making all devices visible.
+pub fn clear_device_allowlist() {
+    #[allow(unused_mut)]
+    let mut list = ALLOWLIST.write();
+    #[cfg(not(feature = "no-std"))]
+    let mut list = list.unwrap();
+    *list = None;
+}
+
+/// The physical ordinals set by [set_device_allowlist()], in the order they are visible.
+pub fn device_allowlist() -> Option<Vec<usize>> {
+    let list = ALLOWLIST.read();
+    #[cfg(not(feature = "no-std"))]
+    let list = list.unwrap();
+    list.clone()
+}
+
+/// The allowed physical ordinals with a device, in visible order.
+fn visible_devices(list: &[usize], physical_count: usize) -> impl Iterator<Item = usize> + '_ {
+    list.iter()
+        .copied()
+        .filter(move |&ordinal| ordinal < physical_count)
+}
+
+/// Runs `f` with the allowlist and the driver's device count, or returns `None` without
+/// querying the driver if there is no allowlist. The driver must be initialized.
+fn with_allowlist<R>(f: impl FnOnce(&[usize], usize) -> R) -> Result<Option<R>, DriverError> {
+    let list = ALLOWLIST.read();
+    #[cfg(not(feature = "no-std"))]
+    let list = list.unwrap();
+    match list.as_deref() {
+        Some(list) => {
+            let physical_count = result::device::get_count()? as usize;
+            Ok(Some(f(list, physical_count)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The number of devices visible through the allowlist. The driver must be initialized.
+pub(crate) fn visible_count() -> Result<usize, DriverError> {
+    match with_allowlist(|list, physical_count| visible_devices(list, physical_count).count())? {
+        Some(count) => Ok(count),
+        None => Ok(result::device::get_count()? as usize),
+    }
+}
+
+/// Translates a visible ordinal into the driver's.
+pub(crate) fn to_physical(ordinal: usize) -> Result<usize, DriverError> {
+    let physical = with_allowlist(|list, physical_count| {
+        visible_devices(list, physical_count)
+            .nth(ordinal)
+            .ok_or_else(|| DriverError::DeviceNotAllowed {
+                ordinal,
+                allowed: visible_devices(list, physical_count).count(),
+            })
+    })?;
+    physical.unwrap_or(Ok(ordinal))
+}
+
+/// Translates one of the driver's ordinals into a visible one, `None` if it isn't allowed.
+pub(crate) fn to_visible(physical: usize) -> Result<Option<usize>, DriverError> {
+    with_allowlist(|list, physical_count| {
+        visible_devices(list, physical_count).position(|ordinal| ordinal == physical)
+    })
+    .map(|visible| visible.unwrap_or(Some(physical)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_devices_remaps_over_allowed_set() {
+        // 4 physical devices, with an ordinal that doesn't exist
+        let list = [3, 1, 7];
+        let visible: Vec<usize> = visible_devices(&list, 4).collect();
+        assert_eq!(visible, [3, 1]);
+        assert_eq!(visible_devices(&list, 4).nth(1), Some(1));
+        assert_eq!(visible_devices(&list, 4).nth(2), None);
+        assert_eq!(visible_devices(&list, 4).position(|o| o == 1), Some(1));
+        assert_eq!(visible_devices(&list, 4).position(|o| o == 0), None);
+        assert_eq!(visible_devices(&list, 8).count(), 3);
+        assert_eq!(visible_devices(&[], 8).count(), 0);
+    }
+
+    #[test]
+    fn test_device_not_allowed_code() {
+        let e = DriverError::DeviceNotAllowed {
+            ordinal: 2,
+            allowed: 1,
+        };
+        assert_eq!(e.code(), sys::CUresult::CUDA_ERROR_INVALID_DEVICE);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::driver::result;
+use crate::nvrtc::Ptx;
+
+use super::core::CudaDevice;
+use super::library::JitOptions;
+use super::ptx::{load_module, ptx_source};
+use super::{ArchRange, DriverError};
+
+use core::ops::{Bound, RangeBounds};
+use std::panic::Location;
+use std::{borrow::Cow, sync::Arc, vec::Vec};
+
+/// Variants of a module built for different compute capabilities, of which
+/// [ArchDispatch::load()] loads the one that fits the device best.
+///
+/// Compute capabilities are written as `10 * major + minor`, so `80..` covers 8.0 and
+/// newer. If the ranges of several variants cover the device, the one whose range starts
+/// at the highest compute capability is picked, so a generic fallback can cover everything:
+///
+/// ```rust
+/// # use cudarc::{driver::*, nvrtc::*};
+/// # let src = "extern \"C\" __global__ void kernel(float *out) { }";
+/// # let (ptx_sm70, ptx_sm80) = (compile_ptx(src).unwrap(), compile_ptx(src).unwrap());
+/// let dev = CudaDevice::new(0).unwrap();
+/// let variant = ArchDispatch::new()
+///     .add(80.., ptx_sm80)
+///     .add(..80, ptx_sm70)
+///     .load(&dev, "module", &["kernel"])
+///     .unwrap();
+/// println!("loaded the variant for {:?}", variant.range);
+/// let f = dev.get_func("module", "kernel").unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ArchDispatch {
+    variants: Vec<(ArchRange, ArchCode)>,
+    options: JitOptions,
+}
+
+#[derive(Debug, Clone)]
+enum ArchCode {
+    Ptx(Ptx),
+    /// A cubin or fatbin.
+    Image(Cow<'static, [u8]>),
+}
+
+/// The variant loaded by [ArchDispatch::load()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchVariant {
+    /// The position of the variant in the order it was added.
+    pub index: usize,
+    pub range: ArchRange,
+    /// The `(major, minor)` compute capability of the device.
+    pub compute_capability: (i32, i32),
+}
+
+impl ArchDispatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `ptx` for the compute capabilities of `range`.
+    pub fn add(mut self, range: impl RangeBounds<u32>, ptx: Ptx) -> Self {
+        self.variants.push((arch_range(range), ArchCode::Ptx(ptx)));
+        self
+    }
+
+    /// Adds a cubin or fatbin `image`, e.g. embedded with `include_bytes!`, for the compute
+    /// capabilities of `range`. Loading it fails if it has no code the device can run.
+    pub fn add_image(
+        mut self,
+        range: impl RangeBounds<u32>,
+        image: impl Into<Cow<'static, [u8]>>,
+    ) -> Self {
+        self.variants
+            .push((arch_range(range), ArchCode::Image(image.into())));
+        self
+    }
+
+    /// Jit compiles ptx variants, and the ptx embedded in fatbins, with `options`.
+    pub fn with_jit_options(mut self, options: JitOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The ranges of the variants, in the order they were added.
+    pub fn ranges(&self) -> Vec<ArchRange> {
+        self.variants.iter().map(|(range, _)| *range).collect()
+    }
+
+    /// The index of the variant that would be loaded on a device of `compute_capability`.
+    pub fn select(&self, (major, minor): (i32, i32)) -> Option<usize> {
+        let cc = (10 * major + minor) as u32;
+        self.variants
+            .iter()
+            .enumerate()
+            .filter(|(_, (range, _))| range.contains(cc))
+            // the first added wins a tie
+            .min_by_key(|(_, (range, _))| core::cmp::Reverse(range.start))
+            .map(|(i, _)| i)
+    }
+
+    /// Loads the variant picked by [ArchDispatch::select()] for `device` like
+    /// [CudaDevice::load_ptx()], and returns which one it was.
+    ///
+    /// Fails with [DriverError::NoArchVariant] if no variant covers the device.
+    #[track_caller]
+    pub fn load(
+        &self,
+        device: &Arc<CudaDevice>,
+        module_name: &str,
+        func_names: &[&'static str],
+    ) -> Result<ArchVariant, DriverError> {
+        let compute_capability = device.compute_capability()?;
+        let Some(index) = self.select(compute_capability) else {
+            return Err(DriverError::NoArchVariant {
+                compute_capability,
+                available: self.ranges().into(),
+            });
+        };
+        let (range, code) = &self.variants[index];
+
+        let _ctx = device.bind_scoped()?;
+        let (mut opts, mut values) = self.options.to_raw();
+        let (cu_module, src) = match code {
+            ArchCode::Ptx(ptx) => {
+                let ptx = ptx.clone().read()?;
+                let src = ptx_source(&ptx);
+                let cu_module = if opts.is_empty() {
+                    load_module(ptx)?
+                } else {
+                    let image = ptx.into_image()?;
+                    unsafe {
+                        result::module::load_data_ex(
+                            image.as_ptr() as *const _,
+                            &mut opts,
+                            &mut values,
+                        )
+                    }?
+                };
+                (cu_module, src)
+            }
+            ArchCode::Image(image) => {
+                let cu_module = unsafe {
+                    result::module::load_data_ex(image.as_ptr() as *const _, &mut opts, &mut values)
+                }?;
+                (cu_module, None)
+            }
+        };
+        device.implicit_sync("ArchDispatch::load", Location::caller());
+        device.register_module(module_name, cu_module, func_names, src.as_deref())?;
+        Ok(ArchVariant {
+            index,
+            range: *range,
+            compute_capability,
+        })
+    }
+}
+
+fn arch_range(range: impl RangeBounds<u32>) -> ArchRange {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => match start.checked_add(1) {
+            Some(start) => start,
+            // nothing is above u32::MAX
+            None => {
+                return ArchRange {
+                    start,
+                    end: Some(start),
+                }
+            }
+        },
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        // everything is up to u32::MAX
+        Bound::Included(&end) => end.checked_add(1),
+        Bound::Excluded(&end) => Some(end),
+        Bound::Unbounded => None,
+    };
+    ArchRange { start, end }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{sys, LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+    use std::string::ToString;
+
+    const SRC: &str = "extern \"C\" __global__ void arch_kernel(int *out) { out[0] = VARIANT; }";
+
+    fn variant(n: i32) -> Ptx {
+        compile_ptx(SRC.replace("VARIANT", &n.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_select_most_specific() {
+        let ptx = Ptx::from_src("");
+        let dispatch = ArchDispatch::new()
+            .add(70..80, ptx.clone())
+            .add(80.., ptx.clone())
+            .add(90..=90, ptx.clone())
+            .add(80.., ptx);
+        assert_eq!(dispatch.select((6, 1)), None);
+        assert_eq!(dispatch.select((7, 5)), Some(0));
+        assert_eq!(dispatch.select((8, 6)), Some(1));
+        assert_eq!(dispatch.select((9, 0)), Some(2));
+        assert_eq!(dispatch.select((9, 1)), Some(1));
+        assert_eq!(
+            std::format!("{:?}", dispatch.ranges()),
+            "[sm_70..sm_80, sm_80.., sm_90..sm_91, sm_80..]"
+        );
+    }
+
+    #[test]
+    fn test_arch_range_bounds() {
+        let full = arch_range(..=u32::MAX);
+        assert_eq!(full, arch_range(..));
+        assert!(full.contains(u32::MAX));
+        let empty = arch_range((Bound::Excluded(u32::MAX), Bound::Unbounded));
+        assert!(!empty.contains(u32::MAX) && !empty.contains(0));
+        assert_eq!(
+            arch_range((Bound::Excluded(79), Bound::Included(89))),
+            arch_range(80..90)
+        );
+    }
+
+    #[test]
+    fn test_load_picks_variant() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (major, minor) = dev.compute_capability().unwrap();
+        let cc = (10 * major + minor) as u32;
+        let variant = ArchDispatch::new()
+            .add(..cc, variant(1))
+            .add(cc.., variant(2))
+            .add(..=cc, variant(3))
+            .with_jit_options(JitOptions {
+                optimization_level: Some(3),
+                ..Default::default()
+            })
+            .load(&dev, "arch_dispatch", &["arch_kernel"])
+            .unwrap();
+        assert_eq!(variant.index, 1);
+        assert_eq!(variant.compute_capability, (major, minor));
+
+        let f = dev.get_func("arch_dispatch", "arch_kernel").unwrap();
+        let mut out = dev.alloc_zeros::<i32>(1).unwrap();
+        unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(1),
+                (&mut out,),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [2]);
+    }
+
+    #[test]
+    fn test_no_matching_variant() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (major, minor) = dev.compute_capability().unwrap();
+        let cc = (10 * major + minor) as u32;
+        let res = ArchDispatch::new().add(cc + 1.., variant(1)).load(
+            &dev,
+            "arch_dispatch_none",
+            &["arch_kernel"],
+        );
+        let Err(
+            ref err @ DriverError::NoArchVariant {
+                compute_capability,
+                ref available,
+            },
+        ) = res
+        else {
+            panic!("{res:?}");
+        };
+        assert_eq!(compute_capability, (major, minor));
+        assert_eq!(&available[..], [arch_range(cc + 1..)]);
+        assert_eq!(err.code(), sys::CUresult::CUDA_ERROR_NO_BINARY_FOR_GPU);
+        #[cfg(feature = "std")]
+        {
+            let msg = std::format!("{err}");
+            assert!(msg.contains(&std::format!("{major}.{minor}")), "{msg}");
+            assert!(msg.contains(&std::format!("sm_{}..", cc + 1)), "{msg}");
+        }
+        assert!(!dev.has_func("arch_dispatch_none", "arch_kernel"));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction, StreamRef};
+use super::device_ptr::{checked_offset, DevicePtr, DevicePtrMut};
+use super::{LaunchAsync, LaunchConfig};
+
+use core::marker::PhantomData;
+use std::{sync::Arc, vec::Vec};
+
+/// One copy of [CudaDevice::batched_dtod_copy()], of `bytes` bytes from `src_ptr` to
+/// `dst_ptr`.
+///
+/// Borrows the slices it points into for `'a`, like [super::PtrArray], the destination
+/// mutably. For many jobs to write into disjoint parts of one buffer, split it into views
+/// first, e.g. with [super::CudaSlice::split_at_mut()] or [super::CudaSlice::chunks_mut()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyJob<'a> {
+    src_ptr: sys::CUdeviceptr,
+    dst_ptr: sys::CUdeviceptr,
+    bytes: usize,
+    marker: PhantomData<&'a ()>,
+}
+
+impl<'a> CopyJob<'a> {
+    /// A copy of the `len` elements of `src` starting at `src_offset` to the ones of `dst`
+    /// starting at `dst_offset`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if either range isn't within its
+    /// slice.
+    pub fn new<T, Src: DevicePtr<T>, Dst: DevicePtrMut<T>>(
+        src: &'a Src,
+        src_offset: usize,
+        dst: &'a mut Dst,
+        dst_offset: usize,
+        len: usize,
+    ) -> Result<Self, result::DriverError> {
+        let invalid = result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE);
+        let src_ptr = checked_offset::<T>(*src.device_ptr(), src.len(), src_offset, len);
+        let dst_len = dst.len();
+        let dst_ptr = checked_offset::<T>(*dst.device_ptr_mut(), dst_len, dst_offset, len);
+        let bytes = len.checked_mul(core::mem::size_of::<T>());
+        match (src_ptr, dst_ptr, bytes) {
+            (Some(src_ptr), Some(dst_ptr), Some(bytes)) => Ok(Self {
+                src_ptr,
+                dst_ptr,
+                bytes,
+                marker: PhantomData,
+            }),
+            _ => Err(invalid),
+        }
+    }
+
+    /// A copy between raw device pointers.
+    ///
+    /// # Safety
+    /// `src_ptr` and `dst_ptr` must be valid for `bytes` bytes until the copy has completed.
+    pub unsafe fn from_raw(
+        src_ptr: sys::CUdeviceptr,
+        dst_ptr: sys::CUdeviceptr,
+        bytes: usize,
+    ) -> Self {
+        Self {
+            src_ptr,
+            dst_ptr,
+            bytes,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn src_ptr(&self) -> sys::CUdeviceptr {
+        self.src_ptr
+    }
+
+    pub fn dst_ptr(&self) -> sys::CUdeviceptr {
+        self.dst_ptr
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// Each block copies one job at a time, in 16, 4 or 1 byte words depending on the
+/// alignment of the job.
+const BATCH_COPY_SRC: &str = r#"
+struct cudarc_copy_job { unsigned long long src, dst, bytes; };
+extern "C" __global__ void batched_copy(const cudarc_copy_job *jobs, const size_t n) {
+    for (size_t j = blockIdx.x; j < n; j += gridDim.x) {
+        const cudarc_copy_job job = jobs[j];
+        const unsigned long long align = job.src | job.dst | job.bytes;
+        if ((align & 15) == 0) {
+            const uint4 *src = (const uint4 *)job.src;
+            uint4 *dst = (uint4 *)job.dst;
+            for (size_t i = threadIdx.x; i < job.bytes / 16; i += blockDim.x) dst[i] = src[i];
+        } else if ((align & 3) == 0) {
+            const unsigned int *src = (const unsigned int *)job.src;
+            unsigned int *dst = (unsigned int *)job.dst;
+            for (size_t i = threadIdx.x; i < job.bytes / 4; i += blockDim.x) dst[i] = src[i];
+        } else {
+            const unsigned char *src = (const unsigned char *)job.src;
+            unsigned char *dst = (unsigned char *)job.dst;
+            for (size_t i = threadIdx.x; i < job.bytes; i += blockDim.x) dst[i] = src[i];
+        }
+    }
+}
+"#;
+
+const BATCH_COPY_MODULE: &str = "cudarc_batch_copy";
+
+/// Blocks of the copy kernel. Jobs are small, so one block per job up to this many.
+const MAX_BLOCKS: usize = 1 << 16;
+
+impl CudaDevice {
+    /// Enqueues all the copies of `jobs` on `stream` at once, which is much cheaper than a
+    /// `cuMemcpyDtoDAsync` per job when there are many small ones. `examples/batched-copy.rs`
+    /// compares the two.
+    ///
+    /// On a driver with `cuMemcpyBatchAsync` (CUDA 12.8), the copies are enqueued with it,
+    /// see [result::memcpy_dtod_batch_async()]. It doesn't take the legacy default stream,
+    /// so on that stream, or with an older driver, the jobs are uploaded to the device as an
+    /// array with a pageable copy on `stream` instead, without synchronizing, and copied by a
+    /// kernel compiled with nvrtc on first use into a module named `cudarc_batch_copy`.
+    /// Either way the jobs run in any order and concurrently, so the result of jobs whose
+    /// destinations overlap each other or another job's source is unspecified.
+    ///
+    /// The copies are not recorded by [CudaDevice::set_stream_tracking()], like writes
+    /// through [super::CudaView]s.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let src = dev.htod_sync_copy(&[0u32, 1, 2, 3, 4, 5]).unwrap();
+    /// let mut dst = dev.alloc_zeros::<u32>(6).unwrap();
+    /// let (mut lo, mut hi) = dst.split_at_mut(3);
+    /// let jobs = [
+    ///     CopyJob::new(&src, 3, &mut lo, 0, 3).unwrap(),
+    ///     CopyJob::new(&src, 0, &mut hi, 1, 2).unwrap(),
+    /// ];
+    /// dev.batched_dtod_copy(&jobs, dev.default_stream()).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&dst).unwrap(), [3, 4, 5, 0, 0, 1]);
+    /// ```
+    pub fn batched_dtod_copy<'s>(
+        self: &Arc<Self>,
+        jobs: &[CopyJob],
+        stream: impl Into<StreamRef<'s>>,
+    ) -> Result<(), result::DriverError> {
+        let stream = stream.into();
+        if jobs.is_empty() {
+            return Ok(());
+        }
+        if !stream.cu_stream().is_null() {
+            let _ctx = stream.bind_scoped()?;
+            // empty copies are skipped, like the kernel does
+            let jobs = jobs.iter().filter(|job| job.bytes > 0);
+            let dsts: Vec<_> = jobs.clone().map(|job| job.dst_ptr).collect();
+            let srcs: Vec<_> = jobs.clone().map(|job| job.src_ptr).collect();
+            let sizes: Vec<_> = jobs.map(|job| job.bytes).collect();
+            if dsts.is_empty() {
+                return Ok(());
+            }
+            match unsafe {
+                result::memcpy_dtod_batch_async(&dsts, &srcs, &sizes, stream.cu_stream())
+            } {
+                Err(result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED)) => {}
+                res => return res,
+            }
+        }
+        let f = self.batch_copy_function()?;
+        let raw: Vec<u64> = jobs
+            .iter()
+            .flat_map(|job| [job.src_ptr, job.dst_ptr, job.bytes as u64])
+            .collect();
+        let cfg = LaunchConfig {
+            grid_dim: (jobs.len().min(MAX_BLOCKS) as u32, 1, 1),
+            block_dim: (128, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let mut on_device = match stream {
+            StreamRef::Stream(s) if self.is_async => unsafe { self.alloc_on_stream(raw.len(), s) }?,
+            _ => unsafe { self.alloc::<u64>(raw.len()) }?,
+        };
+        let _ctx = stream.bind_scoped()?;
+        // pageable copies return once `raw` has been staged, so it can be dropped
+        unsafe {
+            result::memcpy_htod_async(*on_device.device_ptr_mut(), &raw, stream.cu_stream())
+        }?;
+        unsafe { f.launch(stream, cfg, (&on_device, jobs.len())) }?;
+        match stream {
+            StreamRef::Default(_) => Ok(()),
+            StreamRef::Stream(s) => on_device.drop_on_stream(s),
+        }
+    }
+
+    fn batch_copy_function(self: &Arc<Self>) -> Result<CudaFunction, result::DriverError> {
+        self.load_builtin(
+            BATCH_COPY_MODULE,
+            || BATCH_COPY_SRC,
+            &["batched_copy"],
+            "batched_copy",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_job_bounds() {
+        let dev = CudaDevice::new(0).unwrap();
+        let src = dev.alloc_zeros::<u32>(8).unwrap();
+        let mut dst = dev.alloc_zeros::<u32>(4).unwrap();
+        let dst_ptr = *dst.device_ptr();
+        let job = CopyJob::new(&src, 6, &mut dst, 1, 2).unwrap();
+        assert_eq!(job.src_ptr(), *src.device_ptr() + 24);
+        assert_eq!(job.dst_ptr(), dst_ptr + 4);
+        assert_eq!(job.bytes(), 8);
+        assert!(CopyJob::new(&src, 0, &mut dst, 0, 4).is_ok());
+        let invalid = Err(result::DriverError::Cuda(
+            sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+        ));
+        assert_eq!(CopyJob::new(&src, 7, &mut dst, 0, 2), invalid);
+        assert_eq!(CopyJob::new(&src, 0, &mut dst, 3, 2), invalid);
+        assert_eq!(CopyJob::new(&src, 0, &mut dst, 0, 5), invalid);
+        assert_eq!(CopyJob::new(&src, usize::MAX, &mut dst, 0, 2), invalid);
+    }
+
+    #[test]
+    fn test_batched_dtod_copy() {
+        let dev = CudaDevice::new(0).unwrap();
+        let host: Vec<u8> = (0..4096).map(|i| (i * 7 % 251) as u8).collect();
+        let src = dev.htod_sync_copy(&host).unwrap();
+        // 16 byte, 4 byte and unaligned jobs, each into its own 640 byte row of `dst`, in
+        // reverse order
+        const ROW: usize = 640;
+        let lens: Vec<usize> = [512, 64, 100, 3, 17, 256, 0, 1]
+            .into_iter()
+            .cycle()
+            .take(24)
+            .collect();
+        let mut expected = std::vec![0u8; lens.len() * ROW];
+        let mut dst = dev.alloc_zeros::<u8>(expected.len()).unwrap();
+
+        // the legacy default stream takes the kernel, a forked stream
+        // `cuMemcpyBatchAsync` if the driver has it
+        let stream = dev.fork_default_stream().unwrap();
+        for stream in [dev.default_stream(), StreamRef::from(&stream)] {
+            let mut rows: Vec<_> = dst.chunks_mut(ROW).collect();
+            let mut jobs = Vec::new();
+            let mut offset = 0;
+            for (i, (len, row)) in lens.iter().zip(rows.iter_mut().rev()).enumerate() {
+                let dst_offset = ROW - len - i % 5;
+                jobs.push(CopyJob::new(&src, offset, row, dst_offset, *len).unwrap());
+                let row_start = (lens.len() - 1 - i) * ROW + dst_offset;
+                expected[row_start..row_start + len].copy_from_slice(&host[offset..offset + len]);
+                offset += len + i % 3;
+            }
+            dev.batched_dtod_copy(&jobs, stream).unwrap();
+            unsafe { result::stream::synchronize(stream.cu_stream()) }.unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&dst).unwrap(), expected);
+            dev.memset_zeros(&mut dst).unwrap();
+            dev.synchronize().unwrap();
+        }
+
+        let mut dst2 = dev.alloc_zeros::<u8>(4096).unwrap();
+        let mut words: Vec<_> = dst2.chunks_mut(4).collect();
+        let jobs: Vec<_> = words
+            .iter_mut()
+            .rev()
+            .take(1000)
+            .enumerate()
+            .map(|(i, word)| CopyJob::new(&src, i * 4, word, 0, 4).unwrap())
+            .collect();
+        dev.batched_dtod_copy(&jobs, &stream).unwrap();
+        dev.wait_for(&stream).unwrap();
+        let out = dev.dtoh_sync_copy(&dst2).unwrap();
+        for i in 0..1000 {
+            assert_eq!(out[4092 - i * 4..4096 - i * 4], host[i * 4..i * 4 + 4]);
+        }
+
+        dev.batched_dtod_copy(&[], dev.default_stream()).unwrap();
+    }
+}
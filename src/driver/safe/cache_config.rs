@@ -0,0 +1,183 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction};
+use super::DriverError;
+
+/// The preferred split of on-chip memory between L1 cache and shared memory,
+/// see [CudaDevice::set_cache_config()] and [CudaFunction::set_cache_config()].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheConfig {
+    /// No preference, the default.
+    #[default]
+    PreferNone,
+    /// Larger shared memory and smaller L1 cache.
+    PreferShared,
+    /// Larger L1 cache and smaller shared memory.
+    PreferL1,
+    /// Equal sized L1 cache and shared memory.
+    PreferEqual,
+}
+
+impl CacheConfig {
+    fn to_sys(self) -> sys::CUfunc_cache {
+        use sys::CUfunc_cache::*;
+        match self {
+            Self::PreferNone => CU_FUNC_CACHE_PREFER_NONE,
+            Self::PreferShared => CU_FUNC_CACHE_PREFER_SHARED,
+            Self::PreferL1 => CU_FUNC_CACHE_PREFER_L1,
+            Self::PreferEqual => CU_FUNC_CACHE_PREFER_EQUAL,
+        }
+    }
+
+    fn from_sys(config: sys::CUfunc_cache) -> Self {
+        use sys::CUfunc_cache::*;
+        match config {
+            CU_FUNC_CACHE_PREFER_NONE => Self::PreferNone,
+            CU_FUNC_CACHE_PREFER_SHARED => Self::PreferShared,
+            CU_FUNC_CACHE_PREFER_L1 => Self::PreferL1,
+            CU_FUNC_CACHE_PREFER_EQUAL => Self::PreferEqual,
+        }
+    }
+}
+
+/// The preferred shared memory carveout of a [CudaFunction], i.e. how much of the
+/// combined L1 cache and shared memory is used as shared memory.
+/// See [CudaFunction::set_shared_mem_carveout()].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SharedMemCarveout {
+    /// No preference, the default.
+    #[default]
+    Default,
+    /// As much shared memory as the device supports.
+    MaxShared,
+    /// As much L1 cache as the device supports.
+    MaxL1,
+    /// A percentage of the maximum shared memory, from 0 to 100. The driver rounds it
+    /// to the nearest split the device supports.
+    Percent(u8),
+}
+
+impl SharedMemCarveout {
+    fn to_attribute(self) -> i32 {
+        use sys::CUshared_carveout::*;
+        match self {
+            Self::Default => CU_SHAREDMEM_CARVEOUT_DEFAULT as i32,
+            Self::MaxShared => CU_SHAREDMEM_CARVEOUT_MAX_SHARED as i32,
+            Self::MaxL1 => CU_SHAREDMEM_CARVEOUT_MAX_L1 as i32,
+            Self::Percent(percent) => percent as i32,
+        }
+    }
+}
+
+impl CudaDevice {
+    /// Sets the preferred split between L1 cache and shared memory for every kernel
+    /// launched on this device, unless overridden with [CudaFunction::set_cache_config()].
+    ///
+    /// This is only a preference, and does nothing on devices with a fixed split.
+    pub fn set_cache_config(&self, config: CacheConfig) -> Result<(), DriverError> {
+        let _ctx = self.bind_scoped()?;
+        result::ctx::set_cache_config(config.to_sys())
+    }
+
+    /// The preference set with [CudaDevice::set_cache_config()].
+    pub fn cache_config(&self) -> Result<CacheConfig, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        result::ctx::get_cache_config().map(CacheConfig::from_sys)
+    }
+}
+
+impl CudaFunction {
+    /// Sets the preferred split between L1 cache and shared memory for launches of this
+    /// function, overriding [CudaDevice::set_cache_config()]. The setting is kept by the
+    /// function, and applies to every launch after this call.
+    ///
+    /// On devices where the split is configurable per function (Volta and later), a
+    /// carveout set with [CudaFunction::set_shared_mem_carveout()] takes precedence over
+    /// this preference.
+    pub fn set_cache_config(&self, config: CacheConfig) -> Result<(), DriverError> {
+        let _ctx = self.device.bind_scoped()?;
+        unsafe { result::function::set_cache_config(self.cu_function, config.to_sys()) }
+    }
+
+    /// Sets the preferred shared memory carveout for launches of this function, through
+    /// `CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT`. This is a finer grained
+    /// version of [CudaFunction::set_cache_config()], and takes precedence over it.
+    ///
+    /// It is only a hint, the driver may use a different split if a launch needs more
+    /// shared memory than the carveout leaves.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if a percentage is over 100.
+    pub fn set_shared_mem_carveout(&self, carveout: SharedMemCarveout) -> Result<(), DriverError> {
+        if matches!(carveout, SharedMemCarveout::Percent(p) if p > 100) {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        }
+        let _ctx = self.device.bind_scoped()?;
+        self.set_attribute(
+            sys::CUfunction_attribute::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT,
+            carveout.to_attribute(),
+        )
+    }
+
+    /// The carveout set with [CudaFunction::set_shared_mem_carveout()], as a percentage,
+    /// or -1 if none was set.
+    pub fn shared_mem_carveout(&self) -> Result<i32, DriverError> {
+        let _ctx = self.device.bind_scoped()?;
+        unsafe {
+            result::function::get_function_attribute(
+                self.cu_function,
+                sys::CUfunction_attribute::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_cache_config_persists_across_launches() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.set_cache_config(CacheConfig::PreferL1).unwrap();
+        assert_eq!(dev.cache_config().unwrap(), CacheConfig::PreferL1);
+        dev.set_cache_config(CacheConfig::PreferNone).unwrap();
+
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void stage(float *x) {
+                __shared__ float tile[256];
+                tile[threadIdx.x] = x[threadIdx.x];
+                __syncthreads();
+                x[threadIdx.x] = tile[255 - threadIdx.x];
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "cache_config", &["stage"]).unwrap();
+        let f = dev.get_func("cache_config", "stage").unwrap();
+        f.set_cache_config(CacheConfig::PreferShared).unwrap();
+        f.set_shared_mem_carveout(SharedMemCarveout::Percent(50))
+            .unwrap();
+        assert_eq!(
+            f.set_shared_mem_carveout(SharedMemCarveout::Percent(101)),
+            Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))
+        );
+
+        let host: std::vec::Vec<f32> = (0..256).map(|i| i as f32).collect();
+        let mut x = dev.htod_sync_copy(&host).unwrap();
+        for _ in 0..2 {
+            let cfg = LaunchConfig {
+                grid_dim: (1, 1, 1),
+                block_dim: (256, 1, 1),
+                shared_mem_bytes: 0,
+            };
+            unsafe { f.clone().launch(dev.default_stream(), cfg, (&mut x,)) }.unwrap();
+            assert_eq!(f.shared_mem_carveout().unwrap(), 50);
+        }
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), host);
+
+        // the carveout is kept by the loaded function, not the handle
+        let g = dev.get_func("cache_config", "stage").unwrap();
+        assert_eq!(g.shared_mem_carveout().unwrap(), 50);
+    }
+}
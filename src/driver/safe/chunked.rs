@@ -0,0 +1,237 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use core::time::Duration;
+use std::sync::Arc;
+
+/// How [CudaDevice::launch_chunked()] splits a problem into launches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkConfig {
+    /// The number of elements in the first launch, and in every launch if there is no
+    /// `budget`.
+    pub chunk_elems: usize,
+    /// Synchronize after every launch, so that the host gets a chance to run between them,
+    /// e.g. to service a display.
+    pub synchronize: bool,
+    /// The longest a single launch should run. After every launch the chunk size is adapted
+    /// to how long it took, aiming for half of the budget. Implies `synchronize`.
+    pub budget: Option<Duration>,
+    /// Only apply `budget` if the device kills long running kernels, see
+    /// [CudaDevice::has_kernel_timeout()].
+    pub budget_only_with_timeout: bool,
+}
+
+impl ChunkConfig {
+    /// A budget well under the watchdogs of windows (2s) and X (around 5s).
+    pub const WATCHDOG_BUDGET: Duration = Duration::from_millis(500);
+
+    /// Chunks of `chunk_elems`, resized to stay under [ChunkConfig::WATCHDOG_BUDGET] on
+    /// devices with a kernel timeout.
+    pub fn new(chunk_elems: usize) -> Self {
+        Self {
+            chunk_elems,
+            synchronize: false,
+            budget: Some(Self::WATCHDOG_BUDGET),
+            budget_only_with_timeout: true,
+        }
+    }
+}
+
+/// The fraction of [ChunkConfig::budget] a launch aims for, to leave room for variance
+/// between chunks.
+const BUDGET_MARGIN: f64 = 0.5;
+
+/// The start and end events timing a chunk.
+struct Timer {
+    start: sys::CUevent,
+    end: sys::CUevent,
+}
+
+impl Timer {
+    fn new() -> Result<Self, DriverError> {
+        let start = result::event::create(sys::CUevent_flags::CU_EVENT_DEFAULT)?;
+        let end =
+            result::event::create(sys::CUevent_flags::CU_EVENT_DEFAULT).inspect_err(|_| {
+                unsafe { result::event::destroy(start) }.or_handle("Timer::new");
+            })?;
+        Ok(Self { start, end })
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe { result::event::destroy(self.start) }.or_handle("Timer::drop");
+        unsafe { result::event::destroy(self.end) }.or_handle("Timer::drop");
+    }
+}
+
+impl CudaDevice {
+    /// Whether kernels running for too long on this device are killed with
+    /// [sys::CUresult::CUDA_ERROR_LAUNCH_TIMEOUT], usually because it drives a display.
+    /// See `CU_DEVICE_ATTRIBUTE_KERNEL_EXEC_TIMEOUT`.
+    pub fn has_kernel_timeout(&self) -> Result<bool, DriverError> {
+        self.attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_KERNEL_EXEC_TIMEOUT)
+            .map(|timeout| timeout != 0)
+    }
+
+    /// Launches a kernel over a 1D problem of `total_elems` elements as a sequence of smaller
+    /// launches, so that no single launch runs into the watchdog of a display GPU.
+    ///
+    /// `launch_fn(offset, len)` enqueues the chunk of `len` elements starting at element
+    /// `offset` on the device's stream, e.g. with [super::LaunchAsync::launch()]. The kernel must
+    /// add `offset` to the indices it computes. Returns the number of launches.
+    ///
+    /// As the chunk is launched inside `launch_fn`, the slices the kernel writes can be
+    /// borrowed as `&mut`, so that [CudaDevice::set_stream_tracking()] records the writes.
+    ///
+    /// With a [ChunkConfig::budget] each launch is timed with events, and the next chunk is
+    /// sized to take half of the budget, growing at most 2x per launch.
+    ///
+    /// ```rust
+    /// # use cudarc::{driver::*, nvrtc::compile_ptx};
+    /// # let dev = CudaDevice::new(0).unwrap();
+    /// # let ptx = compile_ptx("extern \"C\" __global__ void iota(float *x, int offset, int n) {
+    /// #     int i = blockIdx.x * blockDim.x + threadIdx.x;
+    /// #     if (i < n) { x[offset + i] = offset + i; }
+    /// # }").unwrap();
+    /// # dev.load_ptx(ptx, "m", &["iota"]).unwrap();
+    /// let f = dev.get_func("m", "iota").unwrap();
+    /// let mut x = dev.alloc_zeros::<f32>(1_000_000).unwrap();
+    /// dev.launch_chunked(x.len(), ChunkConfig::new(1 << 16), |offset, len| {
+    ///     let cfg = LaunchConfig::for_num_elems(len as u32);
+    ///     let params = (&mut x, offset as i32, len as i32);
+    ///     unsafe { f.clone().launch(dev.default_stream(), cfg, params) }
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn launch_chunked(
+        self: &Arc<Self>,
+        total_elems: usize,
+        config: ChunkConfig,
+        mut launch_fn: impl FnMut(usize, usize) -> Result<(), DriverError>,
+    ) -> Result<usize, DriverError> {
+        if config.chunk_elems == 0 {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        }
+        let budget = match config.budget {
+            Some(budget) if !config.budget_only_with_timeout || self.has_kernel_timeout()? => {
+                Some(budget)
+            }
+            _ => None,
+        };
+        let _ctx = self.bind_scoped()?;
+        let timer = budget.map(|_| Timer::new()).transpose()?;
+
+        let mut offset = 0;
+        let mut chunk_elems = config.chunk_elems;
+        let mut launches = 0;
+        while offset < total_elems {
+            let len = chunk_elems.min(total_elems - offset);
+            if let Some(timer) = &timer {
+                unsafe { result::event::record(timer.start, self.stream) }?;
+            }
+            launch_fn(offset, len)?;
+            offset += len;
+            launches += 1;
+
+            match (&timer, budget) {
+                (Some(timer), Some(budget)) => {
+                    let elapsed_ms = unsafe {
+                        result::event::record(timer.end, self.stream)?;
+                        result::event::synchronize(timer.end)?;
+                        result::event::elapsed(timer.start, timer.end)? as f64
+                    };
+                    let target_ms = budget.as_secs_f64() * 1e3 * BUDGET_MARGIN;
+                    // tiny chunks can finish within the resolution of the events
+                    let scale = if elapsed_ms > 0.0 {
+                        (target_ms / elapsed_ms).min(2.0)
+                    } else {
+                        2.0
+                    };
+                    chunk_elems = ((len as f64 * scale) as usize).max(1);
+                }
+                _ if config.synchronize => self.synchronize()?,
+                _ => (),
+            }
+        }
+        Ok(launches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_launch_chunked_matches_single_launch() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void slow_square(float *x, int offset, int n) {
+                long long start = clock64();
+                while (clock64() - start < 100000) {}
+                int i = blockIdx.x * blockDim.x + threadIdx.x;
+                if (i < n) { x[offset + i] = (float)(offset + i) * (offset + i); }
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "chunked", &["slow_square"]).unwrap();
+        let f = dev.get_func("chunked", "slow_square").unwrap();
+        let n = 1 << 16;
+        let cfg = |len: usize| LaunchConfig::for_num_elems(len as u32);
+
+        let mut expected = dev.alloc_zeros::<f32>(n).unwrap();
+        unsafe {
+            f.clone().launch(
+                dev.default_stream(),
+                cfg(n),
+                (&mut expected, 0i32, n as i32),
+            )
+        }
+        .unwrap();
+
+        // a budget far below the duration of a single launch over all of `n`
+        let config = ChunkConfig {
+            chunk_elems: 256,
+            synchronize: false,
+            budget: Some(Duration::from_micros(200)),
+            budget_only_with_timeout: false,
+        };
+        let mut x = dev.alloc_zeros::<f32>(n).unwrap();
+        let launches = dev
+            .launch_chunked(n, config, |offset, len| unsafe {
+                let params = (&mut x, offset as i32, len as i32);
+                f.clone().launch(dev.default_stream(), cfg(len), params)
+            })
+            .unwrap();
+        assert!(launches > 1);
+        assert_eq!(
+            dev.dtoh_sync_copy(&x).unwrap(),
+            dev.dtoh_sync_copy(&expected).unwrap()
+        );
+
+        // fixed chunks without a budget
+        let config = ChunkConfig {
+            chunk_elems: 10_000,
+            synchronize: true,
+            budget: None,
+            budget_only_with_timeout: false,
+        };
+        let mut x = dev.alloc_zeros::<f32>(n).unwrap();
+        let launches = dev
+            .launch_chunked(n, config, |offset, len| unsafe {
+                let params = (&mut x, offset as i32, len as i32);
+                f.clone().launch(dev.default_stream(), cfg(len), params)
+            })
+            .unwrap();
+        assert_eq!(launches, 7);
+        assert_eq!(
+            dev.dtoh_sync_copy(&x).unwrap(),
+            dev.dtoh_sync_copy(&expected).unwrap()
+        );
+    }
+}
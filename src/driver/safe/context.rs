@@ -0,0 +1,261 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+use super::error_handler::OrHandle;
+use super::{allowlist, init_hooks, DriverError};
+
+use core::marker::PhantomData;
+use std::sync::Arc;
+
+/// Which context a [CudaDevice] runs in, see [CudaDevice::context_kind()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextKind {
+    /// The device's primary context, shared with everything else in the process using it,
+    /// e.g. the cuda runtime. Retained by [CudaDevice::new()] and released on drop.
+    Primary,
+    /// A context created by [CudaDevice::new_with_owned_context()], destroyed on drop.
+    Owned,
+    /// A context created by someone else, see [CudaDevice::from_existing_context()]. It is
+    /// left alone on drop.
+    Borrowed,
+}
+
+/// Keeps the context of a [CudaDevice] pushed onto the calling thread's context stack,
+/// and pops it on drop, making the context that was current before current again. See
+/// [CudaDevice::push_context()].
+#[derive(Debug)]
+#[must_use]
+pub struct ContextGuard<'a> {
+    device: &'a CudaDevice,
+    /// Contexts stacks are per thread.
+    not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ContextGuard<'_> {
+    fn drop(&mut self) {
+        let popped = result::ctx::pop_current();
+        debug_assert!(
+            popped
+                .as_ref()
+                .map_or(true, |&ctx| ctx == self.device.cu_ctx),
+            "the context pushed by ContextGuard was not on top when popping it"
+        );
+        popped.map(|_| ()).or_handle("ContextGuard::drop");
+    }
+}
+
+impl CudaDevice {
+    /// Creates a [CudaDevice] in a new context on device index `ordinal`, created with
+    /// `cuCtxCreate` and the `CUctx_flags` in `flags`, instead of the primary context.
+    ///
+    /// Calls leave the thread's current context alone, as for
+    /// [CudaDevice::from_existing_context()]. The context is destroyed when the device is
+    /// dropped.
+    pub fn new_with_owned_context(ordinal: usize, flags: u32) -> Result<Arc<Self>, DriverError> {
+        result::require(result::ApiGroup::Core)?;
+        result::init().map_err(|e| DriverError::InitFailed { code: e.code() })?;
+
+        let physical_ordinal = allowlist::to_physical(ordinal)?;
+        let cu_device = result::device::get(physical_ordinal as i32)?;
+        // pushed by cuCtxCreate
+        let ctx = result::ctx::create(flags, cu_device)?;
+        let device = Self::with_current_ctx(
+            ordinal,
+            physical_ordinal,
+            cu_device,
+            ctx,
+            ContextKind::Owned,
+            false,
+        );
+        result::ctx::pop_current()?;
+        let device = match device {
+            Ok(device) => Arc::new(device),
+            Err(e) => {
+                unsafe { result::ctx::destroy(ctx) }?;
+                return Err(e);
+            }
+        };
+        init_hooks::run_device_init_hooks(&device)?;
+        Ok(device)
+    }
+
+    /// Creates a [CudaDevice] that runs in `ctx`, a context created by something else, e.g.
+    /// a host application or a library like OptiX, on device index `ordinal`.
+    ///
+    /// The thread's current context isn't replaced by cudarc: if `ctx` is already current,
+    /// calls use it as it is, if another context is current they push `ctx` and pop it
+    /// again before returning, and if none is they make it current. Only
+    /// [CudaDevice::bind_to_thread()] replaces the current context, as for the primary
+    /// context. To save the push and pop of each call, wrap calls into
+    /// [CudaDevice::push_context()]:
+    ///
+    /// ```rust,no_run
+    /// # use cudarc::driver::*;
+    /// # let ctx: sys::CUcontext = std::ptr::null_mut();
+    /// let dev = unsafe { CudaDevice::from_existing_context(ctx, 0) }.unwrap();
+    /// let x = {
+    ///     let _guard = dev.push_context().unwrap();
+    ///     dev.alloc_zeros::<f32>(100).unwrap()
+    /// };
+    /// // the application's context is current again
+    /// ```
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_DEVICE] if `ctx` isn't on the device
+    /// `ordinal`.
+    ///
+    /// # Safety
+    /// `ctx` must be a valid context, and outlive the device and everything created from it.
+    /// It is not destroyed when the device is dropped.
+    pub unsafe fn from_existing_context(
+        ctx: sys::CUcontext,
+        ordinal: usize,
+    ) -> Result<Arc<Self>, DriverError> {
+        result::require(result::ApiGroup::Core)?;
+        result::init().map_err(|e| DriverError::InitFailed { code: e.code() })?;
+
+        let physical_ordinal = allowlist::to_physical(ordinal)?;
+        let cu_device = result::device::get(physical_ordinal as i32)?;
+        result::ctx::push_current(ctx)?;
+        let device = match result::ctx::get_device() {
+            Ok(ctx_device) if ctx_device != cu_device => {
+                Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_DEVICE))
+            }
+            Ok(_) => Self::with_current_ctx(
+                ordinal,
+                physical_ordinal,
+                cu_device,
+                ctx,
+                ContextKind::Borrowed,
+                false,
+            ),
+            Err(e) => Err(e),
+        };
+        result::ctx::pop_current()?;
+        let device = Arc::new(device?);
+        init_hooks::run_device_init_hooks(&device)?;
+        Ok(device)
+    }
+
+    /// Which context the device runs in.
+    pub fn context_kind(&self) -> ContextKind {
+        self.ctx_kind
+    }
+
+    /// Pushes the device's context onto the calling thread's context stack until the
+    /// returned guard is dropped, which makes the previously current context current again.
+    ///
+    /// cudarc calls made while the guard lives use the pushed context as it is. This is how
+    /// to share a thread with code managing its own contexts for more than a call, see
+    /// [CudaDevice::from_existing_context()].
+    pub fn push_context(&self) -> Result<ContextGuard<'_>, DriverError> {
+        self.check_poisoned()?;
+        unsafe { result::ctx::push_current(self.cu_ctx) }?;
+        Ok(ContextGuard {
+            device: self,
+            not_send: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    /// A context of the "host application", current on the calling thread.
+    struct AppContext(sys::CUcontext);
+
+    impl AppContext {
+        fn new() -> Self {
+            result::init().unwrap();
+            let ctx = result::ctx::create(0, result::device::get(0).unwrap()).unwrap();
+            Self(ctx)
+        }
+    }
+
+    impl Drop for AppContext {
+        fn drop(&mut self) {
+            unsafe { result::ctx::destroy(self.0) }.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_borrowed_context_keeps_current() {
+        let app = AppContext::new();
+        let borrowed = AppContext::new();
+        // `borrowed` was pushed on top of `app`
+        result::ctx::pop_current().unwrap();
+        assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+
+        let dev = unsafe { CudaDevice::from_existing_context(borrowed.0, 0) }.unwrap();
+        assert_eq!(dev.context_kind(), ContextKind::Borrowed);
+        assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+        {
+            let _guard = dev.push_context().unwrap();
+            let x = dev.htod_copy(std::vec![1.0f32, 2.0, 3.0]).unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), [1.0, 2.0, 3.0]);
+            assert_eq!(result::ctx::get_current().unwrap(), Some(borrowed.0));
+        }
+        assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+
+        drop(dev);
+        assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+        // still usable by its owner
+        unsafe { result::ctx::set_current(borrowed.0) }.unwrap();
+        result::ctx::synchronize().unwrap();
+    }
+
+    #[test]
+    fn test_borrowed_context_already_current() {
+        let app = AppContext::new();
+        let dev = unsafe { CudaDevice::from_existing_context(app.0, 0) }.unwrap();
+        let x = dev.alloc_zeros::<u32>(16).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), [0; 16]);
+        assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+        drop(x);
+        drop(dev);
+        assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+    }
+
+    #[test]
+    fn test_app_context_survives_launch() {
+        const SRC: &str =
+            "extern \"C\" __global__ void fill(unsigned int *out, unsigned int v) { out[threadIdx.x] = v; }";
+        let app = AppContext::new();
+        let borrowed = AppContext::new();
+        result::ctx::pop_current().unwrap();
+        let devices = [
+            CudaDevice::new_with_owned_context(0, 0).unwrap(),
+            unsafe { CudaDevice::from_existing_context(borrowed.0, 0) }.unwrap(),
+        ];
+        for dev in devices {
+            dev.load_ptx(compile_ptx(SRC).unwrap(), "survives", &["fill"])
+                .unwrap();
+            let f = dev.get_func("survives", "fill").unwrap();
+            let mut out = dev.alloc_zeros::<u32>(64).unwrap();
+            let cfg = LaunchConfig::for_num_elems(64);
+            unsafe { f.launch(dev.default_stream(), cfg, (&mut out, 7u32)) }.unwrap();
+            assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+            assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [7; 64]);
+            drop(out);
+            drop(dev);
+            assert_eq!(result::ctx::get_current().unwrap(), Some(app.0));
+        }
+    }
+
+    #[test]
+    fn test_owned_context() {
+        let dev = CudaDevice::new_with_owned_context(0, 0).unwrap();
+        assert_eq!(dev.context_kind(), ContextKind::Owned);
+        let primary = CudaDevice::new(0).unwrap();
+        assert_ne!(dev.cu_ctx(), primary.cu_ctx());
+
+        let x = dev.htod_copy(std::vec![7u8; 10]).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), [7; 10]);
+        drop(x);
+        primary.bind_to_thread().unwrap();
+        drop(dev);
+        assert_eq!(result::ctx::get_current().unwrap(), Some(*primary.cu_ctx()));
+    }
+}
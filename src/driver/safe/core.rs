@@ -3,12 +3,32 @@ use crate::driver::{
     sys::{self, lib, CUfunction_attribute_enum},
 };
 
-use super::{alloc::DeviceRepr, device_ptr::DeviceSlice};
+use super::{
+    alloc::{DeviceRepr, StagingBuffer},
+    allocator::DeviceAllocator,
+    allowlist,
+    context::ContextKind,
+    deferred_free::DeferredFrees,
+    device_ptr::{checked_offset, DevicePtr, DevicePtrMut, DeviceSlice},
+    error_handler::OrHandle,
+    init_hooks,
+    launch_log::LaunchLog,
+    perf_hints::PerfHintState,
+    scratch::ScratchState,
+    staging::StagingPool,
+    stream_tracking::LastWrite,
+    threading::CtxScope,
+};
+
+#[cfg(feature = "std")]
+use super::recorder::LaunchRecorder;
 
 use std::{
     marker::PhantomData,
     ops::{Bound, RangeBounds},
+    panic::Location,
     string::String,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize},
 };
 
 #[cfg(feature = "no-std")]
@@ -32,17 +52,79 @@ use std::{collections::BTreeMap, marker::Unpin, pin::Pin, sync::Arc, vec::Vec};
 ///    hanging around.
 /// 3. Any allocations enforce that self is an [Arc], meaning no allocation
 ///    can outlive the [CudaDevice]
+///
+/// # Thread safety
+/// A [CudaDevice] is [Send] and [Sync], and every method can be called from several threads
+/// at once on clones of the same [Arc]; each binds the device's context to the calling
+/// thread first, whichever [ContextKind] it is: owned, borrowed or primary.
+///
+/// Modules are compiled and loaded by [CudaDevice::load_ptx()] without holding any lock,
+/// and the module registry is only locked to insert or look up a module, never across a
+/// driver call. So [CudaDevice::get_func()] and launches don't wait for a `load_ptx` of
+/// another module in flight, and loads of different modules run concurrently. Loading a
+/// module under a name that is already registered replaces it for later lookups, while
+/// [CudaFunction]s from the replaced module stay valid until the device is dropped.
 #[derive(Debug)]
 pub struct CudaDevice {
     pub(crate) cu_device: sys::CUdevice,
-    pub(crate) cu_primary_ctx: sys::CUcontext,
+    /// The context of the device, which is only the primary context for
+    /// [ContextKind::Primary].
+    pub(crate) cu_ctx: sys::CUcontext,
+    pub(crate) ctx_kind: ContextKind,
     /// The stream that all work is executed on.
     pub(crate) stream: sys::CUstream,
     /// Used to synchronize with stream
     pub(crate) event: sys::CUevent,
-    pub(crate) modules: RwLock<BTreeMap<String, CudaModule>>,
-    pub(crate) ordinal: usize,
+    /// Cloned out of the lock by lookups, so that it is never held across a driver call.
+    pub(crate) modules: RwLock<BTreeMap<String, Arc<CudaModule>>>,
+    /// Modules replaced by loading another one under the same name, see
+    /// [CudaDevice::register_module()]. Unloaded on drop.
+    pub(crate) replaced_modules: RwLock<Vec<Arc<CudaModule>>>,
+    /// The ordinal the driver knows the device by, see [CudaDevice::physical_ordinal()].
+    pub(crate) physical_ordinal: usize,
+    /// The ordinal the device was created with, see [super::set_device_allowlist()].
+    pub(crate) visible_ordinal: usize,
     pub(crate) is_async: bool,
+    /// A [super::SyncDebug], see [CudaDevice::set_sync_debug()].
+    pub(crate) sync_debug: AtomicU8,
+    /// A [super::Determinism], see [CudaDevice::set_determinism()].
+    pub(crate) determinism: AtomicU8,
+    /// See [CudaDevice::set_perf_hints()].
+    pub(crate) perf_hints: PerfHintState,
+    /// See [CudaDevice::aligned_padding_bytes()].
+    pub(crate) aligned_padding: AtomicUsize,
+    /// Pinned memory reused by [CudaDevice::dtoh_scalar_async()].
+    pub(crate) pinned_slots: RwLock<Vec<StagingBuffer>>,
+    /// See [CudaDevice::set_staging_pool()].
+    pub(crate) staging_pool: StagingPool,
+    /// Frees deferred by [CudaSlice::drop_on_stream()].
+    pub(crate) deferred_frees: DeferredFrees,
+    /// Events reused by [super::WorkToken]s.
+    pub(crate) event_pool: RwLock<Vec<sys::CUevent>>,
+    /// Whether [CudaDevice::set_launch_tracking()] is enabled.
+    pub(crate) launch_tracking: AtomicBool,
+    pub(crate) launch_log: RwLock<LaunchLog>,
+    /// Whether a sticky error was observed, see [CudaDevice::is_poisoned()].
+    pub(crate) poisoned: AtomicBool,
+    /// The first sticky error observed, reported by [result::DriverError::ContextPoisoned].
+    pub(crate) sticky_error: RwLock<Option<sys::CUresult>>,
+    /// Whether [CudaDevice::set_stream_tracking()] is enabled.
+    pub(crate) stream_tracking: AtomicBool,
+    /// See [CudaDevice::set_allocator()].
+    pub(crate) allocator: RwLock<Option<Arc<dyn DeviceAllocator>>>,
+    /// See [CudaDevice::scratch_arena()].
+    pub(crate) scratch: ScratchState,
+    /// The grid and block dimension limits, queried by the first validated launch.
+    #[cfg(feature = "launch-validation")]
+    pub(crate) launch_limits: RwLock<Option<super::launch::LaunchLimits>>,
+    /// Whether a [LaunchRecorder] is attached with [CudaDevice::set_launch_recorder()].
+    #[cfg(feature = "std")]
+    pub(crate) launch_recording: AtomicBool,
+    #[cfg(feature = "std")]
+    pub(crate) launch_recorder: RwLock<Option<Arc<LaunchRecorder>>>,
+    /// See [CudaDevice::alloc_stats()].
+    #[cfg(feature = "allocation-tracking")]
+    pub(crate) alloc_stats: RwLock<super::AllocStats>,
 }
 
 unsafe impl Send for CudaDevice {}
@@ -51,49 +133,65 @@ unsafe impl Sync for CudaDevice {}
 impl CudaDevice {
     /// Creates a new [CudaDevice] on device index `ordinal`.
     pub fn new(ordinal: usize) -> Result<Arc<Self>, result::DriverError> {
-        result::init()?;
-
-        let cu_device = result::device::get(ordinal as i32)?;
-
-        // primary context initialization, can fail with OOM
-        let cu_primary_ctx = unsafe { result::primary_ctx::retain(cu_device) }?;
-
-        unsafe { result::ctx::set_current(cu_primary_ctx) }.unwrap();
+        Self::new_on_primary_ctx(ordinal, false)
+    }
 
-        // can fail with OOM
-        let event = result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?;
+    /// Creates a new [CudaDevice] on device index `ordinal` on a **non-default stream**.
+    pub fn new_with_stream(ordinal: usize) -> Result<Arc<Self>, result::DriverError> {
+        Self::new_on_primary_ctx(ordinal, true)
+    }
 
-        let value = unsafe {
-            result::device::get_attribute(
-                cu_device,
-                sys::CUdevice_attribute_enum::CU_DEVICE_ATTRIBUTE_MEMORY_POOLS_SUPPORTED,
-            )?
-        };
-        let is_async = value > 0;
+    fn new_on_primary_ctx(
+        ordinal: usize,
+        own_stream: bool,
+    ) -> Result<Arc<Self>, result::DriverError> {
+        let device = Arc::new(Self::primary_device(ordinal, own_stream)?);
+        init_hooks::run_device_init_hooks(&device)?;
+        Ok(device)
+    }
 
-        let device = CudaDevice {
-            cu_device,
-            cu_primary_ctx,
-            stream: std::ptr::null_mut(),
-            event,
-            modules: RwLock::new(BTreeMap::new()),
-            ordinal,
-            is_async,
-        };
+    /// A device on the primary context of `ordinal` that allocates with [result::malloc_sync()]
+    /// even if it supports memory pools, to test that path on any device.
+    #[cfg(test)]
+    pub(crate) fn new_without_memory_pools(
+        ordinal: usize,
+    ) -> Result<Arc<Self>, result::DriverError> {
+        let mut device = Self::primary_device(ordinal, false)?;
+        device.is_async = false;
         Ok(Arc::new(device))
     }
 
-    /// Creates a new [CudaDevice] on device index `ordinal` on a **non-default stream**.
-    pub fn new_with_stream(ordinal: usize) -> Result<Arc<Self>, result::DriverError> {
-        result::init()?;
+    fn primary_device(ordinal: usize, own_stream: bool) -> Result<Self, result::DriverError> {
+        result::require(result::ApiGroup::Core)?;
+        result::init().map_err(|e| result::DriverError::InitFailed { code: e.code() })?;
 
-        let cu_device = result::device::get(ordinal as i32)?;
+        let physical_ordinal = allowlist::to_physical(ordinal)?;
+        let cu_device = result::device::get(physical_ordinal as i32)?;
 
         // primary context initialization, can fail with OOM
-        let cu_primary_ctx = unsafe { result::primary_ctx::retain(cu_device) }?;
+        let primary_ctx = Self::retain_primary_ctx(ordinal, cu_device)?;
+
+        unsafe { result::ctx::set_current(primary_ctx) }?;
 
-        unsafe { result::ctx::set_current(cu_primary_ctx) }.unwrap();
+        Self::with_current_ctx(
+            ordinal,
+            physical_ordinal,
+            cu_device,
+            primary_ctx,
+            ContextKind::Primary,
+            own_stream,
+        )
+    }
 
+    /// Creates the [CudaDevice] for `ctx`, which must be current.
+    pub(crate) fn with_current_ctx(
+        ordinal: usize,
+        physical_ordinal: usize,
+        cu_device: sys::CUdevice,
+        ctx: sys::CUcontext,
+        ctx_kind: ContextKind,
+        own_stream: bool,
+    ) -> Result<Self, result::DriverError> {
         // can fail with OOM
         let event = result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?;
 
@@ -103,30 +201,103 @@ impl CudaDevice {
                 sys::CUdevice_attribute_enum::CU_DEVICE_ATTRIBUTE_MEMORY_POOLS_SUPPORTED,
             )?
         };
-        let is_async = value > 0;
+        // checked too, so that a build against newer bindings runs on older drivers
+        let is_async = value > 0 && result::ApiGroup::MemoryPools.is_available();
 
-        let stream = result::stream::create(result::stream::StreamKind::NonBlocking)?;
+        let stream = if own_stream {
+            result::stream::create(result::stream::StreamKind::NonBlocking)?
+        } else {
+            std::ptr::null_mut()
+        };
 
-        let device = CudaDevice {
+        Ok(CudaDevice {
             cu_device,
-            cu_primary_ctx,
+            cu_ctx: ctx,
+            ctx_kind,
             stream,
             event,
             modules: RwLock::new(BTreeMap::new()),
-            ordinal,
+            replaced_modules: RwLock::new(Vec::new()),
+            physical_ordinal,
+            visible_ordinal: ordinal,
             is_async,
-        };
-        Ok(Arc::new(device))
+            sync_debug: AtomicU8::new(0),
+            determinism: AtomicU8::new(0),
+            perf_hints: PerfHintState::default(),
+            aligned_padding: AtomicUsize::new(0),
+            pinned_slots: RwLock::new(Vec::new()),
+            staging_pool: StagingPool::default(),
+            deferred_frees: DeferredFrees::default(),
+            event_pool: RwLock::new(Vec::new()),
+            launch_tracking: AtomicBool::new(false),
+            launch_log: RwLock::new(LaunchLog::default()),
+            poisoned: AtomicBool::new(false),
+            sticky_error: RwLock::new(None),
+            stream_tracking: AtomicBool::new(false),
+            allocator: RwLock::new(None),
+            scratch: ScratchState::default(),
+            #[cfg(feature = "launch-validation")]
+            launch_limits: RwLock::new(None),
+            #[cfg(feature = "std")]
+            launch_recording: AtomicBool::new(false),
+            #[cfg(feature = "std")]
+            launch_recorder: RwLock::new(None),
+            #[cfg(feature = "allocation-tracking")]
+            alloc_stats: RwLock::new(Default::default()),
+        })
+    }
+
+    /// Creates a [CudaDevice] on the first device that a context can be created on,
+    /// skipping devices that are busy or prohibited because of their compute mode.
+    ///
+    /// Useful on clusters where devices are in `CU_COMPUTEMODE_EXCLUSIVE_PROCESS` mode.
+    /// If no device is available, returns the error from the last device tried.
+    pub fn new_first_available() -> Result<Arc<Self>, result::DriverError> {
+        let mut err = result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_NO_DEVICE);
+        for ordinal in 0..Self::count()? as usize {
+            match Self::new(ordinal) {
+                Ok(dev) => return Ok(dev),
+                Err(e) => err = e,
+            }
+        }
+        Err(err)
+    }
+
+    /// Retains the primary context, turning failures caused by the compute mode
+    /// into [result::DriverError::DeviceBusy].
+    fn retain_primary_ctx(
+        ordinal: usize,
+        cu_device: sys::CUdevice,
+    ) -> Result<sys::CUcontext, result::DriverError> {
+        unsafe { result::primary_ctx::retain(cu_device) }.map_err(|err| {
+            if err == result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY) {
+                return err;
+            }
+            match Self::compute_mode_of(cu_device) {
+                Ok(sys::CUcomputemode::CU_COMPUTEMODE_DEFAULT) | Err(_) => err,
+                Ok(compute_mode) => result::DriverError::DeviceBusy {
+                    ordinal,
+                    compute_mode,
+                },
+            }
+        })
     }
 
+    /// The number of devices, or of the devices allowed by [super::set_device_allowlist()].
     pub fn count() -> Result<i32, result::DriverError> {
-        result::init().unwrap();
-        result::device::get_count()
+        result::init()?;
+        allowlist::visible_count().map(|count| count as i32)
     }
 
-    /// Get the `ordinal` index of this [CudaDevice].
+    /// Get the `ordinal` index of this [CudaDevice], the one passed to [CudaDevice::new()].
     pub fn ordinal(&self) -> usize {
-        self.ordinal
+        self.visible_ordinal
+    }
+
+    /// The ordinal the driver knows this device by. Differs from [CudaDevice::ordinal()] if
+    /// the device was created with [super::set_device_allowlist()] set.
+    pub fn physical_ordinal(&self) -> usize {
+        self.physical_ordinal
     }
 
     /// Get the name of this device.
@@ -154,8 +325,15 @@ impl CudaDevice {
     ///
     /// **You must not free/release the context pointer**, as it is still
     /// owned by the [CudaDevice].
+    pub fn cu_ctx(&self) -> &sys::CUcontext {
+        &self.cu_ctx
+    }
+
+    #[deprecated(
+        note = "use cu_ctx() instead, the context is only the primary context for ContextKind::Primary"
+    )]
     pub fn cu_primary_ctx(&self) -> &sys::CUcontext {
-        &self.cu_primary_ctx
+        &self.cu_ctx
     }
 
     /// Get the underlying [sys::CUstream] that this [CudaDevice] executes
@@ -175,34 +353,121 @@ impl CudaDevice {
     pub fn attribute(&self, attrib: sys::CUdevice_attribute) -> Result<i32, result::DriverError> {
         unsafe { result::device::get_attribute(self.cu_device, attrib) }
     }
+
+    /// The `(major, minor)` compute capability of the device, e.g. `(8, 6)`.
+    pub fn compute_capability(&self) -> Result<(i32, i32), result::DriverError> {
+        use sys::CUdevice_attribute::*;
+        Ok((
+            self.attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?,
+            self.attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?,
+        ))
+    }
+
+    /// Returns the compute mode of the device, which controls how many contexts can
+    /// be created on it across processes.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__TYPES.html)
+    pub fn compute_mode(&self) -> Result<sys::CUcomputemode, result::DriverError> {
+        Self::compute_mode_of(self.cu_device)
+    }
+
+    fn compute_mode_of(
+        cu_device: sys::CUdevice,
+    ) -> Result<sys::CUcomputemode, result::DriverError> {
+        use sys::CUcomputemode::*;
+        let mode = unsafe {
+            result::device::get_attribute(
+                cu_device,
+                sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_COMPUTE_MODE,
+            )
+        }?;
+        match mode {
+            0 => Ok(CU_COMPUTEMODE_DEFAULT),
+            2 => Ok(CU_COMPUTEMODE_PROHIBITED),
+            3 => Ok(CU_COMPUTEMODE_EXCLUSIVE_PROCESS),
+            _ => Err(result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_UNKNOWN)),
+        }
+    }
 }
 
 impl Drop for CudaDevice {
     fn drop(&mut self) {
-        self.bind_to_thread().unwrap();
+        // other contexts are pushed, so that the thread's current context is kept
+        let pushed = match self.ctx_kind {
+            ContextKind::Primary => {
+                self.bind_to_thread().or_handle("CudaDevice::drop");
+                false
+            }
+            ContextKind::Owned | ContextKind::Borrowed => {
+                let res = unsafe { result::ctx::push_current(self.cu_ctx) };
+                let pushed = res.is_ok();
+                res.or_handle("CudaDevice::drop");
+                pushed
+            }
+        };
 
         let modules = RwLock::get_mut(&mut self.modules);
         #[cfg(not(feature = "no-std"))]
         let modules = modules.unwrap();
 
         for (_, module) in modules.iter() {
-            unsafe { result::module::unload(module.cu_module) }.unwrap();
+            unsafe { result::module::unload(module.cu_module) }.or_handle("CudaDevice::drop");
         }
         modules.clear();
 
+        let replaced_modules = RwLock::get_mut(&mut self.replaced_modules);
+        #[cfg(not(feature = "no-std"))]
+        let replaced_modules = replaced_modules.unwrap();
+        for module in replaced_modules.drain(..) {
+            unsafe { result::module::unload(module.cu_module) }.or_handle("CudaDevice::drop");
+        }
+
+        let pinned_slots = RwLock::get_mut(&mut self.pinned_slots);
+        #[cfg(not(feature = "no-std"))]
+        let pinned_slots = pinned_slots.unwrap();
+        pinned_slots.clear();
+        self.staging_pool.clear();
+        self.deferred_frees.clear();
+        self.scratch.clear(self.stream, "CudaDevice::drop");
+        // while the context is bound, in case it's the last reference to the allocator
+        let allocator = RwLock::get_mut(&mut self.allocator);
+        #[cfg(not(feature = "no-std"))]
+        let allocator = allocator.unwrap();
+        allocator.take();
+
+        let event_pool = RwLock::get_mut(&mut self.event_pool);
+        #[cfg(not(feature = "no-std"))]
+        let event_pool = event_pool.unwrap();
+        for event in event_pool.drain(..) {
+            unsafe { result::event::destroy(event) }.or_handle("CudaDevice::drop");
+        }
+
         let stream = std::mem::replace(&mut self.stream, std::ptr::null_mut());
         if !stream.is_null() {
-            unsafe { result::stream::destroy(stream) }.unwrap();
+            unsafe { result::stream::destroy(stream) }.or_handle("CudaDevice::drop");
         }
 
         let event = std::mem::replace(&mut self.event, std::ptr::null_mut());
         if !event.is_null() {
-            unsafe { result::event::destroy(event) }.unwrap();
+            unsafe { result::event::destroy(event) }.or_handle("CudaDevice::drop");
         }
 
-        let ctx = std::mem::replace(&mut self.cu_primary_ctx, std::ptr::null_mut());
+        if pushed {
+            result::ctx::pop_current()
+                .map(|_| ())
+                .or_handle("CudaDevice::drop");
+        }
+        let ctx = std::mem::replace(&mut self.cu_ctx, std::ptr::null_mut());
         if !ctx.is_null() {
-            unsafe { result::primary_ctx::release(self.cu_device) }.unwrap();
+            match self.ctx_kind {
+                ContextKind::Primary => unsafe { result::primary_ctx::release(self.cu_device) }
+                    .or_handle("CudaDevice::drop"),
+                ContextKind::Owned => {
+                    unsafe { result::ctx::destroy(ctx) }.or_handle("CudaDevice::drop")
+                }
+                // owned by whoever created it
+                ContextKind::Borrowed => {}
+            }
         }
     }
 }
@@ -239,6 +504,16 @@ pub struct CudaSlice<T> {
     pub(crate) len: usize,
     pub(crate) device: Arc<CudaDevice>,
     pub(crate) host_buf: Option<Pin<Vec<T>>>,
+    /// Where the slice was allocated, reported by [CudaDevice::set_sync_debug()].
+    pub(crate) location: &'static Location<'static>,
+    /// See [CudaDevice::set_stream_tracking()].
+    pub(crate) last_write: LastWrite,
+    /// The start of the allocation and the bytes allocated beyond `len` elements, if
+    /// `cu_device_ptr` was moved into it by [CudaDevice::alloc_aligned()].
+    pub(crate) aligned_base: Option<(sys::CUdeviceptr, usize)>,
+    /// The allocator of [CudaDevice::set_allocator()] the slice was allocated with, which
+    /// frees it. `None` for the driver.
+    pub(crate) allocator: Option<Arc<dyn DeviceAllocator>>,
 }
 
 unsafe impl<T: Send> Send for CudaSlice<T> {}
@@ -246,18 +521,49 @@ unsafe impl<T: Sync> Sync for CudaSlice<T> {}
 
 impl<T> Drop for CudaSlice<T> {
     fn drop(&mut self) {
-        self.device.bind_to_thread().unwrap();
+        let _ctx = self.device.bind_scoped_or_handle("CudaSlice::drop");
+        self.last_write
+            .release(self.device.stream, "CudaSlice::drop");
+        self.device.untrack(self);
+        let num_bytes = self.alloc_bytes();
+        let ptr = self.release_alloc_base();
+        if let Some(allocator) = self.allocator.take() {
+            unsafe { allocator.free(ptr, num_bytes, self.device.stream) }
+                .or_handle("CudaSlice::drop");
+            return;
+        }
         unsafe {
             if self.device.is_async {
-                result::free_async(self.cu_device_ptr, self.device.stream).unwrap();
+                result::free_async(ptr, self.device.stream).or_handle("CudaSlice::drop");
             } else {
-                result::free_sync(self.cu_device_ptr).unwrap();
+                result::free_sync(ptr).or_handle("CudaSlice::drop");
+                self.device.implicit_sync("CudaSlice::drop", self.location);
             }
         }
     }
 }
 
 impl<T> CudaSlice<T> {
+    /// The bytes of the allocation, including the padding of [CudaDevice::alloc_aligned()].
+    pub(crate) fn alloc_bytes(&self) -> usize {
+        let padding = self.aligned_base.map_or(0, |(_, padding)| padding);
+        self.len * std::mem::size_of::<T>() + padding
+    }
+
+    /// The start of the allocation, to be freed, no longer counting its padding in
+    /// [CudaDevice::aligned_padding_bytes()].
+    pub(crate) fn release_alloc_base(&self) -> sys::CUdeviceptr {
+        match self.aligned_base {
+            Some((base, padding)) => {
+                self.device
+                    .aligned_padding
+                    .fetch_sub(padding, core::sync::atomic::Ordering::Relaxed);
+                base
+            }
+            None => self.cu_device_ptr,
+        }
+    }
+
     /// Get a clone of the underlying [CudaDevice].
     pub fn device(&self) -> Arc<CudaDevice> {
         self.device.clone()
@@ -266,6 +572,7 @@ impl<T> CudaSlice<T> {
 
 impl<T: DeviceRepr> CudaSlice<T> {
     /// Allocates copy of self and schedules a device to device copy of memory.
+    #[track_caller]
     pub fn try_clone(&self) -> Result<Self, result::DriverError> {
         let mut dst = unsafe { self.device.alloc(self.len) }?;
         self.device.dtod_copy(self, &mut dst)?;
@@ -274,6 +581,7 @@ impl<T: DeviceRepr> CudaSlice<T> {
 }
 
 impl<T: DeviceRepr> Clone for CudaSlice<T> {
+    #[track_caller]
     fn clone(&self) -> Self {
         self.try_clone().unwrap()
     }
@@ -293,7 +601,12 @@ impl<T: Clone + Default + DeviceRepr + Unpin> TryFrom<CudaSlice<T>> for Vec<T> {
 #[derive(Debug)]
 pub(crate) struct CudaModule {
     pub(crate) cu_module: sys::CUmodule,
-    pub(crate) functions: BTreeMap<&'static str, sys::CUfunction>,
+    pub(crate) functions: BTreeMap<String, sys::CUfunction>,
+    /// Number of params of each function, as declared in the ptx source.
+    #[cfg(feature = "launch-validation")]
+    pub(crate) num_params: BTreeMap<String, usize>,
+    /// Size in bytes of each param of each function, as declared in the ptx source.
+    pub(crate) param_sizes: BTreeMap<String, Arc<[usize]>>,
 }
 
 unsafe impl Send for CudaModule {}
@@ -304,6 +617,30 @@ unsafe impl Sync for CudaModule {}
 pub struct CudaFunction {
     pub(crate) cu_function: sys::CUfunction,
     pub(crate) device: Arc<CudaDevice>,
+    /// The last known `CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES`, shared by clones.
+    /// `0` until the first launch with dynamic shared memory.
+    pub(crate) max_dynamic_smem: Arc<AtomicU32>,
+    /// `CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK`, which doesn't change once the function is
+    /// loaded, shared by clones. `0` until the first launch.
+    pub(crate) max_threads_per_block: Arc<AtomicU32>,
+    /// `CU_FUNC_ATTRIBUTE_REQUIRED_CLUSTER_WIDTH`, shared by clones. `u32::MAX` until the
+    /// first launch with a cluster.
+    #[allow(unused)]
+    pub(crate) required_cluster_width: Arc<AtomicU32>,
+    /// The name the function was loaded with, for [result::DriverError::LaunchConfigExceedsBounds].
+    pub(crate) name: Option<Arc<str>>,
+    #[cfg(feature = "launch-validation")]
+    pub(crate) num_params: Option<usize>,
+    /// The param count reported by the driver plus one, shared by clones. `0` until the first
+    /// validated launch. Only used with cuda 12.4+.
+    #[cfg(feature = "launch-validation")]
+    #[allow(unused)]
+    pub(crate) queried_num_params: Arc<AtomicUsize>,
+    /// Size in bytes of each param, if the ptx source was available when loading.
+    pub(crate) param_sizes: Option<Arc<[usize]>>,
+    /// Keeps the library of a function from a [super::CudaKernel] loaded.
+    #[allow(unused)]
+    pub(crate) library: Option<Arc<super::library::CudaLibrary>>,
 }
 
 impl CudaFunction {
@@ -459,9 +796,24 @@ impl CudaFunction {
         unsafe {
             result::function::set_function_attribute(self.cu_function, attribute, value)?;
         }
+        if attribute == CUfunction_attribute_enum::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES {
+            self.max_dynamic_smem
+                .store(value as u32, core::sync::atomic::Ordering::Relaxed);
+        }
 
         Ok(())
     }
+
+    /// Allows launches of this function with up to `bytes` of dynamic shared memory, by
+    /// setting `CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES`. Needed for more than 48 KB,
+    /// up to the device's `CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK_OPTIN` minus the
+    /// function's static shared memory.
+    pub fn set_max_dynamic_shared_mem(&self, bytes: u32) -> Result<(), result::DriverError> {
+        self.set_attribute(
+            CUfunction_attribute_enum::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES,
+            bytes as i32,
+        )
+    }
 }
 
 unsafe impl Send for CudaFunction {}
@@ -475,11 +827,11 @@ unsafe impl Sync for CudaFunction {}
 /// The synchronization happens in **code order**. E.g.
 /// ```ignore
 /// let stream = dev.fork_default_stream()?; // 0
-/// dev.launch(...)?; // 1
-/// function_1.launch_on_stream(&stream, ...)?; // 2
-/// function_2.launch(...)?; // 3
+/// function_0.launch(dev.default_stream(), ...)?; // 1
+/// function_1.launch(&stream, ...)?; // 2
+/// function_2.launch(dev.default_stream(), ...)?; // 3
 /// drop(stream); // 4
-/// dev.launch(...) // 5
+/// function_3.launch(dev.default_stream(), ...) // 5
 /// ```
 ///
 /// - 0 will place a streamWaitEvent(default work stream) on the new stream
@@ -513,17 +865,17 @@ unsafe impl Sync for CudaFunction {}
 /// let stream_3 = dev.fork_default_stream()?; // 2
 /// let stream_4 = dev.fork_default_stream()?; // 3
 ///
-/// function_1.launch_on_stream(&stream_1, ...)?; // 4
-/// function_2.launch_on_stream(&stream_2, ...)?; // 5
+/// function_1.launch(&stream_1, ...)?; // 4
+/// function_2.launch(&stream_2, ...)?; // 5
 /// dev.wait_for(&stream_1); // 6
 /// dev.wait_for(&stream_2); // 7
 ///
 /// stream_3.wait_for_default(); // 8
-/// function_3.launch_on_stream(&stream_3, ...)?; // 10
+/// function_3.launch(&stream_3, ...)?; // 10
 /// dev.wait_for(&stream_3); // 11
 ///
 /// stream_4.wait_for_default(); // 12
-/// function_4.launch_on_stream(&stream_4, ...)?; // 13
+/// function_4.launch(&stream_4, ...)?; // 13
 /// dev.wait_for(&stream_4); // 14
 /// ```
 ///
@@ -545,7 +897,12 @@ unsafe impl Sync for CudaFunction {}
 #[derive(Debug)]
 pub struct CudaStream {
     pub stream: sys::CUstream,
-    device: Arc<CudaDevice>,
+    pub(crate) device: Arc<CudaDevice>,
+    /// Set for streams created by [super::GreenContext::new_stream()].
+    #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+    pub(crate) green_ctx: Option<Arc<super::GreenContext>>,
+    /// See [CudaStream::scratch_arena()].
+    pub(crate) scratch: ScratchState,
 }
 
 impl CudaDevice {
@@ -557,10 +914,13 @@ impl CudaDevice {
     /// 1. On creation it adds a wait for any existing work on the default work stream to complete
     /// 2. On drop it adds a wait for any existign work on Self to complete *to the default stream*.
     pub fn fork_default_stream(self: &Arc<Self>) -> Result<CudaStream, result::DriverError> {
-        self.bind_to_thread()?;
+        let _ctx = self.bind_scoped()?;
         let stream = CudaStream {
             stream: result::stream::create(result::stream::StreamKind::NonBlocking)?,
             device: self.clone(),
+            #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+            green_ctx: None,
+            scratch: Default::default(),
         };
         stream.wait_for_default()?;
         Ok(stream)
@@ -570,12 +930,17 @@ impl CudaDevice {
     /// **This is asynchronous with respect to the host.**
     #[allow(unused_variables)]
     pub fn wait_for(self: &Arc<Self>, stream: &CudaStream) -> Result<(), result::DriverError> {
-        self.bind_to_thread()?;
+        let _ctx = stream.bind_scoped()?;
+        // events have to be recorded on a stream of the same context
+        #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+        let event = stream.green_ctx.as_ref().map_or(self.event, |g| g.event);
+        #[cfg(not(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060")))]
+        let event = self.event;
         unsafe {
-            result::event::record(self.event, stream.stream)?;
+            result::event::record(event, stream.stream)?;
             result::stream::wait_event(
                 self.stream,
-                self.event,
+                event,
                 sys::CUevent_wait_flags::CU_EVENT_WAIT_DEFAULT,
             )
         }
@@ -583,10 +948,29 @@ impl CudaDevice {
 }
 
 impl CudaStream {
+    /// Binds the context the stream was created in to the calling thread, like
+    /// [CudaDevice::bind_scoped()] does.
+    pub(crate) fn bind_scoped(&self) -> Result<CtxScope, result::DriverError> {
+        #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+        if let Some(green_ctx) = &self.green_ctx {
+            green_ctx.bind_to_thread()?;
+            return Ok(CtxScope::unchanged());
+        }
+        self.device.bind_scoped()
+    }
+
+    /// [CudaStream::bind_scoped()] for `Drop` impls.
+    pub(crate) fn bind_scoped_or_handle(&self, op: &'static str) -> CtxScope {
+        self.bind_scoped().unwrap_or_else(|err| {
+            super::error_handler::handle_error(err, op);
+            CtxScope::unchanged()
+        })
+    }
+
     /// Records the current default stream's workload, and then causes `self`
     /// to wait for the default stream to finish that recorded workload.
     pub fn wait_for_default(&self) -> Result<(), result::DriverError> {
-        self.device.bind_to_thread()?;
+        let _ctx = self.device.bind_scoped()?;
         unsafe {
             result::event::record(self.device.event, self.device.stream)?;
             result::stream::wait_event(
@@ -600,13 +984,79 @@ impl CudaStream {
 
 impl Drop for CudaStream {
     fn drop(&mut self) {
-        self.device.wait_for(self).unwrap();
+        let _ctx = self.bind_scoped_or_handle("CudaStream::drop");
+        self.scratch.clear(self.stream, "CudaStream::drop");
+        self.device.wait_for(self).or_handle("CudaStream::drop");
         unsafe {
-            result::stream::destroy(self.stream).unwrap();
+            result::stream::destroy(self.stream).or_handle("CudaStream::drop");
+        }
+    }
+}
+
+/// The stream a kernel is launched on with [super::LaunchAsync::launch()]: either the
+/// device's default work stream, from [CudaDevice::default_stream()], or a [CudaStream].
+///
+/// ```ignore
+/// unsafe { f.clone().launch(dev.default_stream(), cfg, params) }?;
+/// unsafe { f.launch(&stream, cfg, params) }?;
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum StreamRef<'a> {
+    /// The work stream of the device, which all the non `_on_stream` methods of
+    /// [CudaDevice] use.
+    Default(&'a CudaDevice),
+    Stream(&'a CudaStream),
+}
+
+impl StreamRef<'_> {
+    /// The underlying [sys::CUstream].
+    pub fn cu_stream(&self) -> sys::CUstream {
+        match self {
+            StreamRef::Default(device) => device.stream,
+            StreamRef::Stream(stream) => stream.stream,
+        }
+    }
+
+    /// The device the stream belongs to.
+    pub fn device(&self) -> &CudaDevice {
+        match self {
+            StreamRef::Default(device) => device,
+            StreamRef::Stream(stream) => &stream.device,
+        }
+    }
+
+    /// Binds the context the stream was created in to the calling thread, like
+    /// [CudaDevice::bind_scoped()] does.
+    pub(crate) fn bind_scoped(&self) -> Result<CtxScope, result::DriverError> {
+        match self {
+            StreamRef::Default(device) => device.bind_scoped(),
+            StreamRef::Stream(stream) => stream.bind_scoped(),
+        }
+    }
+
+    /// [StreamRef::bind_scoped()] for `Drop` impls.
+    pub(crate) fn bind_scoped_or_handle(&self, op: &'static str) -> CtxScope {
+        match self {
+            StreamRef::Default(device) => device.bind_scoped_or_handle(op),
+            StreamRef::Stream(stream) => stream.bind_scoped_or_handle(op),
         }
     }
 }
 
+impl<'a> From<&'a CudaStream> for StreamRef<'a> {
+    fn from(stream: &'a CudaStream) -> Self {
+        StreamRef::Stream(stream)
+    }
+}
+
+impl CudaDevice {
+    /// The device's default work stream, to launch kernels on with
+    /// [super::LaunchAsync::launch()].
+    pub fn default_stream(&self) -> StreamRef<'_> {
+        StreamRef::Default(self)
+    }
+}
+
 /// A immutable sub-view into a [CudaSlice] created by [CudaSlice::try_slice()] or [CudaSlice::slice()].
 ///
 /// This type is to [CudaSlice] as `&[T]` is to `Vec<T>`.
@@ -652,11 +1102,8 @@ impl<T> CudaSlice<T> {
 
     /// Fallible version of [CudaSlice::slice()].
     pub fn try_slice(&self, range: impl RangeBounds<usize>) -> Option<CudaView<'_, T>> {
-        range.bounds(..self.len()).map(|(start, end)| CudaView {
-            ptr: self.cu_device_ptr + (start * std::mem::size_of::<T>()) as u64,
-            len: end - start,
-            marker: PhantomData,
-        })
+        let (start, end) = range.bounds(..self.len())?;
+        self.try_view(start, end - start)
     }
 
     /// Reinterprets the slice of memory into a different type. `len` is the number
@@ -667,15 +1114,45 @@ impl<T> CudaSlice<T> {
     /// This is unsafe because not the memory for the view may not be a valid interpretation
     /// for the type `S`.
     pub unsafe fn transmute<S>(&self, len: usize) -> Option<CudaView<'_, S>> {
-        (len * std::mem::size_of::<S>() <= self.num_bytes()).then_some(CudaView {
+        (len.checked_mul(std::mem::size_of::<S>())? <= self.num_bytes()).then_some(CudaView {
             ptr: self.cu_device_ptr,
             len,
             marker: PhantomData,
         })
     }
+
+    /// Iterates over [CudaView]s of `chunk_len` elements, like [slice::chunks()].
+    /// The last view is shorter if `chunk_len` doesn't divide `self.len()`.
+    ///
+    /// Panics if `chunk_len` is 0.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::safe::{CudaDevice, DeviceSlice};
+    /// # let dev = CudaDevice::new(0).unwrap();
+    /// let slice = dev.alloc_zeros::<f32>(10).unwrap();
+    /// let lens: Vec<usize> = slice.chunks(4).map(|c| c.len()).collect();
+    /// assert_eq!(lens, [4, 4, 2]);
+    /// ```
+    pub fn chunks(&self, chunk_len: usize) -> impl Iterator<Item = CudaView<'_, T>> {
+        assert!(chunk_len != 0, "chunk_len must be non-zero");
+        let len = self.len();
+        (0..len)
+            .step_by(chunk_len)
+            .map(move |start| self.slice(start..start + chunk_len.min(len - start)))
+    }
 }
 
 impl<'a, T> CudaView<'a, T> {
+    /// # Safety
+    /// `ptr` must point to `len` elements of `T` that outlive `'a`.
+    pub(crate) unsafe fn from_raw_parts(ptr: sys::CUdeviceptr, len: usize) -> Self {
+        Self {
+            ptr,
+            len,
+            marker: PhantomData,
+        }
+    }
+
     /// Creates a [CudaView] at the specified offset from the start of `self`.
     ///
     /// Panics if `range.start >= self.len`.
@@ -697,11 +1174,9 @@ impl<'a, T> CudaView<'a, T> {
 
     /// Fallible version of [CudaView::slice]
     pub fn try_slice(&self, range: impl RangeBounds<usize>) -> Option<CudaView<'a, T>> {
-        range.bounds(..self.len()).map(|(start, end)| CudaView {
-            ptr: self.ptr + (start * std::mem::size_of::<T>()) as u64,
-            len: end - start,
-            marker: PhantomData,
-        })
+        let (start, end) = range.bounds(..self.len())?;
+        checked_offset::<T>(self.ptr, self.len, start, end - start)
+            .map(|ptr| unsafe { CudaView::from_raw_parts(ptr, end - start) })
     }
 
     /// Reinterprets the slice of memory into a different type. `len` is the number
@@ -712,7 +1187,7 @@ impl<'a, T> CudaView<'a, T> {
     /// This is unsafe because not the memory for the view may not be a valid interpretation
     /// for the type `S`.
     pub unsafe fn transmute<S>(&self, len: usize) -> Option<CudaView<'_, S>> {
-        (len * std::mem::size_of::<S>() <= self.num_bytes()).then_some(CudaView {
+        (len.checked_mul(std::mem::size_of::<S>())? <= self.num_bytes()).then_some(CudaView {
             ptr: self.ptr,
             len,
             marker: PhantomData,
@@ -778,11 +1253,8 @@ impl<T> CudaSlice<T> {
 
     /// Fallible version of [CudaSlice::slice_mut]
     pub fn try_slice_mut(&mut self, range: impl RangeBounds<usize>) -> Option<CudaViewMut<'_, T>> {
-        range.bounds(..self.len()).map(|(start, end)| CudaViewMut {
-            ptr: self.cu_device_ptr + (start * std::mem::size_of::<T>()) as u64,
-            len: end - start,
-            marker: PhantomData,
-        })
+        let (start, end) = range.bounds(..self.len())?;
+        self.try_view_mut(start, end - start)
     }
 
     /// Reinterprets the slice of memory into a different type. `len` is the number
@@ -793,7 +1265,7 @@ impl<T> CudaSlice<T> {
     /// This is unsafe because not the memory for the view may not be a valid interpretation
     /// for the type `S`.
     pub unsafe fn transmute_mut<S>(&mut self, len: usize) -> Option<CudaViewMut<'_, S>> {
-        (len * std::mem::size_of::<S>() <= self.num_bytes()).then_some(CudaViewMut {
+        (len.checked_mul(std::mem::size_of::<S>())? <= self.num_bytes()).then_some(CudaViewMut {
             ptr: self.cu_device_ptr,
             len,
             marker: PhantomData,
@@ -825,25 +1297,42 @@ impl<T> CudaSlice<T> {
         &mut self,
         mid: usize,
     ) -> Option<(CudaViewMut<'_, T>, CudaViewMut<'_, T>)> {
-        if mid > self.len() {
-            return None;
+        let (ptr, len) = (self.cu_device_ptr, self.len);
+        let rest = checked_offset::<T>(ptr, len, mid, len.checked_sub(mid)?)?;
+        unsafe {
+            Some((
+                CudaViewMut::from_raw_parts(ptr, mid),
+                CudaViewMut::from_raw_parts(rest, len - mid),
+            ))
         }
-        Some((
-            CudaViewMut {
-                ptr: self.cu_device_ptr,
-                len: mid,
-                marker: PhantomData,
-            },
-            CudaViewMut {
-                ptr: self.cu_device_ptr + (mid * std::mem::size_of::<T>()) as u64,
-                len: self.len - mid,
-                marker: PhantomData,
-            },
-        ))
+    }
+
+    /// Iterates over non-overlapping [CudaViewMut]s of `chunk_len` elements, like
+    /// [slice::chunks_mut()]. The last view is shorter if `chunk_len` doesn't divide `self.len()`.
+    ///
+    /// Panics if `chunk_len` is 0.
+    pub fn chunks_mut(&mut self, chunk_len: usize) -> impl Iterator<Item = CudaViewMut<'_, T>> {
+        assert!(chunk_len != 0, "chunk_len must be non-zero");
+        let (ptr, len) = (self.cu_device_ptr, self.len);
+        (0..len).step_by(chunk_len).map(move |start| CudaViewMut {
+            ptr: ptr + (start * std::mem::size_of::<T>()) as u64,
+            len: chunk_len.min(len - start),
+            marker: PhantomData,
+        })
     }
 }
 
 impl<'a, T> CudaViewMut<'a, T> {
+    /// # Safety
+    /// `ptr` must point to `len` elements of `T` that outlive `'a`, and are not aliased.
+    pub(crate) unsafe fn from_raw_parts(ptr: sys::CUdeviceptr, len: usize) -> Self {
+        Self {
+            ptr,
+            len,
+            marker: PhantomData,
+        }
+    }
+
     /// Creates a [CudaView] at the specified offset from the start of `self`.
     ///
     /// Panics if `range` and `0...self.len()` are not overlapping.
@@ -879,11 +1368,8 @@ impl<'a, T> CudaViewMut<'a, T> {
 
     /// Fallible version of [CudaViewMut::slice]
     pub fn try_slice<'b: 'a>(&'b self, range: impl RangeBounds<usize>) -> Option<CudaView<'a, T>> {
-        range.bounds(..self.len()).map(|(start, end)| CudaView {
-            ptr: self.ptr + (start * std::mem::size_of::<T>()) as u64,
-            len: end - start,
-            marker: PhantomData,
-        })
+        let (start, end) = range.bounds(..self.len())?;
+        self.try_view(start, end - start)
     }
 
     /// Reinterprets the slice of memory into a different type. `len` is the number
@@ -894,7 +1380,7 @@ impl<'a, T> CudaViewMut<'a, T> {
     /// This is unsafe because not the memory for the view may not be a valid interpretation
     /// for the type `S`.
     pub unsafe fn transmute<S>(&self, len: usize) -> Option<CudaView<'_, S>> {
-        (len * std::mem::size_of::<S>() <= self.num_bytes()).then_some(CudaView {
+        (len.checked_mul(std::mem::size_of::<S>())? <= self.num_bytes()).then_some(CudaView {
             ptr: self.ptr,
             len,
             marker: PhantomData,
@@ -913,11 +1399,8 @@ impl<'a, T> CudaViewMut<'a, T> {
         &'b mut self,
         range: impl RangeBounds<usize>,
     ) -> Option<CudaViewMut<'a, T>> {
-        range.bounds(..self.len()).map(|(start, end)| CudaViewMut {
-            ptr: self.ptr + (start * std::mem::size_of::<T>()) as u64,
-            len: end - start,
-            marker: PhantomData,
-        })
+        let (start, end) = range.bounds(..self.len())?;
+        self.try_view_mut(start, end - start)
     }
 
     /// Splits the [CudaViewMut] into two at the given index.
@@ -948,21 +1431,14 @@ impl<'a, T> CudaViewMut<'a, T> {
         &'b mut self,
         mid: usize,
     ) -> Option<(CudaViewMut<'a, T>, CudaViewMut<'a, T>)> {
-        if mid > self.len() {
-            return None;
+        let (ptr, len) = (self.ptr, self.len);
+        let rest = checked_offset::<T>(ptr, len, mid, len.checked_sub(mid)?)?;
+        unsafe {
+            Some((
+                CudaViewMut::from_raw_parts(ptr, mid),
+                CudaViewMut::from_raw_parts(rest, len - mid),
+            ))
         }
-        Some((
-            CudaViewMut {
-                ptr: self.ptr,
-                len: mid,
-                marker: PhantomData,
-            },
-            CudaViewMut {
-                ptr: self.ptr + (mid * std::mem::size_of::<T>()) as u64,
-                len: self.len - mid,
-                marker: PhantomData,
-            },
-        ))
     }
 
     /// Reinterprets the slice of memory into a different type. `len` is the number
@@ -973,7 +1449,7 @@ impl<'a, T> CudaViewMut<'a, T> {
     /// This is unsafe because not the memory for the view may not be a valid interpretation
     /// for the type `S`.
     pub unsafe fn transmute_mut<S>(&mut self, len: usize) -> Option<CudaViewMut<'_, S>> {
-        (len * std::mem::size_of::<S>() <= self.num_bytes()).then_some(CudaViewMut {
+        (len.checked_mul(std::mem::size_of::<S>())? <= self.num_bytes()).then_some(CudaViewMut {
             ptr: self.ptr,
             len,
             marker: PhantomData,
@@ -1000,13 +1476,13 @@ impl<R: RangeBounds<usize>> RangeHelper for R {
     fn inclusive_start(&self, valid_start: usize) -> usize {
         match self.start_bound() {
             Bound::Included(n) => *n,
-            Bound::Excluded(n) => *n + 1,
+            Bound::Excluded(n) => n.saturating_add(1),
             Bound::Unbounded => valid_start,
         }
     }
     fn exclusive_end(&self, valid_end: usize) -> usize {
         match self.end_bound() {
-            Bound::Included(n) => *n + 1,
+            Bound::Included(n) => n.saturating_add(1),
             Bound::Excluded(n) => *n,
             Bound::Unbounded => valid_end,
         }
@@ -1030,6 +1506,29 @@ mod tests {
         assert_eq!((1..=0usize).bounds(0..usize::MAX), None);
     }
 
+    #[test]
+    fn test_api_groups() {
+        assert!(result::is_symbol_available("cuInit"));
+        assert!(!result::is_symbol_available("cuNotAFunction"));
+        assert!(!result::is_symbol_available("cuInit\0"));
+        result::require(result::ApiGroup::Core).unwrap();
+
+        let version = result::driver_version().unwrap();
+        assert_eq!(
+            result::ApiGroup::GreenContexts.is_available(),
+            version >= 12040
+        );
+        if !result::ApiGroup::GreenContexts.is_available() {
+            assert_eq!(
+                result::require(result::ApiGroup::GreenContexts),
+                Err(result::DriverError::UnsupportedDriver {
+                    required: "12.4",
+                    found: version,
+                })
+            );
+        }
+    }
+
     #[test]
     fn test_transmutes() {
         let dev = CudaDevice::new(0).unwrap();
@@ -1053,4 +1552,74 @@ mod tests {
             assert!(unsafe { view_mut.transmute_mut::<f32>(26) }.is_none());
         }
     }
+
+    #[test]
+    fn test_chunks() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut slice = dev.htod_copy((0..10u32).collect()).unwrap();
+        let ptrs: Vec<_> = slice.chunks(4).map(|c| (c.ptr, c.len)).collect();
+        let base = slice.cu_device_ptr;
+        assert_eq!(ptrs, [(base, 4), (base + 16, 4), (base + 32, 2)]);
+
+        for (i, mut chunk) in slice.chunks_mut(3).enumerate() {
+            let host = std::vec![i as u32; chunk.len];
+            dev.htod_sync_copy_into(&host, &mut chunk).unwrap();
+        }
+        assert_eq!(
+            dev.dtoh_sync_copy(&slice).unwrap(),
+            [0, 0, 0, 1, 1, 1, 2, 2, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_checked_offset() {
+        assert_eq!(checked_offset::<u32>(1024, 10, 0, 10), Some(1024));
+        assert_eq!(checked_offset::<u32>(1024, 10, 9, 1), Some(1060));
+        assert_eq!(checked_offset::<u32>(1024, 10, 10, 0), Some(1064));
+        assert_eq!(checked_offset::<u32>(1024, 10, 10, 1), None);
+        assert_eq!(checked_offset::<u32>(1024, 10, 5, usize::MAX), None);
+        assert_eq!(
+            checked_offset::<u32>(1024, usize::MAX, usize::MAX / 2, 1),
+            None
+        );
+        assert_eq!(checked_offset::<u8>(u64::MAX, usize::MAX, 1, 1), None);
+    }
+
+    #[test]
+    fn test_try_at_and_try_view() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut slice = dev.alloc_zeros::<f32>(10).unwrap();
+        let base = slice.cu_device_ptr;
+        assert_eq!(slice.try_at(3), Some(base + 12));
+        assert_eq!(slice.try_at(10), None);
+        assert_eq!(slice.try_at(usize::MAX), None);
+
+        let view = slice.try_view(2, 6).unwrap();
+        assert_eq!((view.ptr, view.len), (base + 8, 6));
+        assert_eq!(view.try_at(5), Some(base + 28));
+        assert_eq!(view.try_at(6), None);
+        assert!(view.try_view(1, 6).is_none());
+        assert!(view.try_view(usize::MAX, 2).is_none());
+        assert!(slice.try_view(11, 0).is_none());
+        assert!(slice.try_slice(usize::MAX..).is_none());
+        assert!(slice.try_slice(..=usize::MAX).is_none());
+
+        let view = slice.try_view_mut(4, 2).unwrap();
+        assert_eq!((view.ptr, view.len), (base + 16, 2));
+        assert!(slice.try_split_at_mut(11).is_none());
+    }
+
+    #[test]
+    fn test_new_first_available() {
+        let dev = CudaDevice::new_first_available().unwrap();
+        if dev.compute_mode().unwrap() == sys::CUcomputemode::CU_COMPUTEMODE_DEFAULT {
+            assert_eq!(dev.ordinal(), 0);
+        }
+
+        let busy = result::DriverError::DeviceBusy {
+            ordinal: 1,
+            compute_mode: sys::CUcomputemode::CU_COMPUTEMODE_EXCLUSIVE_PROCESS,
+        };
+        assert!(std::format!("{busy:?}").contains("new_first_available"));
+    }
 }
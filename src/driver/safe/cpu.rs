@@ -0,0 +1,809 @@
+use super::alloc::{DeviceRepr, ValidAsZeroBits};
+use super::core::{CudaDevice, CudaSlice};
+use super::ops::{invalid_value, ApplyDtype, BinaryOp, UnaryOp};
+use super::reduce::ReduceDtype;
+use super::scan::ScanDtype;
+use super::sort::SortKey;
+use super::DriverError;
+
+use core::cmp::Ordering;
+use std::{sync::Arc, vec, vec::Vec};
+
+/// The builtin ops and copies of [CudaDevice], as a trait that [CpuDevice] implements too,
+/// so that code using them can run without a GPU, e.g. in unit tests on CI machines. Hold
+/// a [Device] to pick either at runtime.
+///
+/// The methods behave like the [CudaDevice] methods of the same name, and fail in the
+/// same cases. Results of floating point ops can differ by rounding between the two.
+///
+/// This is not an emulation of CUDA: kernels, streams, graphs and everything else that
+/// isn't part of this trait needs a [CudaDevice].
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// fn softplus<D: DeviceOps>(dev: &D, x: &[f32]) -> Result<Vec<f32>, DriverError> {
+///     let n = x.len();
+///     let x = dev.htod_copy(x)?;
+///     let mut y = dev.alloc_zeros::<f32>(n)?;
+///     dev.apply_unary(UnaryOp::Exp, &x, &mut y)?;
+///     let mut out = dev.alloc_zeros::<f32>(n)?;
+///     dev.apply_unary(UnaryOp::AddScalar(1.0), &y, &mut out)?;
+///     dev.apply_unary(UnaryOp::Log, &out, &mut y)?;
+///     dev.dtoh_copy(&y)
+/// }
+/// let y = softplus(&CpuDevice, &[0.0, 1.0]).unwrap();
+/// assert!((y[0] - 2f32.ln()).abs() < 1e-6);
+/// ```
+pub trait DeviceOps {
+    /// The buffers of the device: [CudaSlice] on a [CudaDevice], [Vec] on a [CpuDevice].
+    type Slice<T>;
+
+    /// See [CudaDevice::alloc_zeros()].
+    fn alloc_zeros<T: ValidAsZeroBits + DeviceRepr + HostDtype>(
+        &self,
+        len: usize,
+    ) -> Result<Self::Slice<T>, DriverError>;
+
+    /// See [CudaDevice::htod_sync_copy()].
+    fn htod_copy<T: DeviceRepr + HostDtype>(
+        &self,
+        src: &[T],
+    ) -> Result<Self::Slice<T>, DriverError>;
+
+    /// See [CudaDevice::dtoh_sync_copy()].
+    fn dtoh_copy<T: DeviceRepr + HostDtype>(
+        &self,
+        src: &Self::Slice<T>,
+    ) -> Result<Vec<T>, DriverError>;
+
+    /// See [CudaDevice::apply_unary()].
+    fn apply_unary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: UnaryOp,
+        x: &Self::Slice<T>,
+        out: &mut Self::Slice<T>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::apply_binary()].
+    fn apply_binary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: BinaryOp,
+        x: &Self::Slice<T>,
+        y: &Self::Slice<T>,
+        out: &mut Self::Slice<T>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::argmax()].
+    fn argmax<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &Self::Slice<T>,
+        out_idx: &mut Self::Slice<u32>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::argmin()].
+    fn argmin<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &Self::Slice<T>,
+        out_idx: &mut Self::Slice<u32>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::sum()].
+    fn sum<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &Self::Slice<T>,
+        out: &mut Self::Slice<f32>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::scan_exclusive()].
+    fn scan_exclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &Self::Slice<T>,
+        out: &mut Self::Slice<T>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::scan_inclusive()].
+    fn scan_inclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &Self::Slice<T>,
+        out: &mut Self::Slice<T>,
+    ) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::sort()].
+    fn sort<K: SortKey + HostDtype>(&self, keys: &mut Self::Slice<K>) -> Result<(), DriverError>;
+
+    /// See [CudaDevice::argsort()].
+    fn argsort<K: SortKey + HostDtype>(
+        &self,
+        keys: &Self::Slice<K>,
+    ) -> Result<Self::Slice<u32>, DriverError>;
+
+    /// See [CudaDevice::synchronize()]. [CpuDevice] runs everything synchronously.
+    fn synchronize(&self) -> Result<(), DriverError>;
+}
+
+/// Element types a [CpuDevice] computes with on the host.
+pub trait HostDtype: Copy + Default + 'static {
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+    /// `self + other` of the scans, wrapping around for integers.
+    fn scan_add(self, other: Self) -> Self;
+    /// The order of [CudaDevice::sort()], which is [f32::total_cmp()] for floats.
+    fn sort_cmp(&self, other: &Self) -> Ordering;
+}
+
+macro_rules! host_int {
+    ($($T:ty),*) => {
+        $(impl HostDtype for $T {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(value: f64) -> Self {
+                value as $T
+            }
+            fn scan_add(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+            fn sort_cmp(&self, other: &Self) -> Ordering {
+                self.cmp(other)
+            }
+        })*
+    };
+}
+
+host_int!(u8, u32, i32, u64, i64);
+
+macro_rules! host_float {
+    ($($T:ty),*) => {
+        $(impl HostDtype for $T {
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn from_f64(value: f64) -> Self {
+                value as $T
+            }
+            fn scan_add(self, other: Self) -> Self {
+                self + other
+            }
+            fn sort_cmp(&self, other: &Self) -> Ordering {
+                self.total_cmp(other)
+            }
+        })*
+    };
+}
+
+host_float!(f32, f64);
+
+#[cfg(feature = "f16")]
+macro_rules! host_half {
+    ($($T:ty),*) => {
+        $(impl HostDtype for $T {
+            fn to_f64(self) -> f64 {
+                self.to_f64()
+            }
+            fn from_f64(value: f64) -> Self {
+                <$T>::from_f64(value)
+            }
+            fn scan_add(self, other: Self) -> Self {
+                self + other
+            }
+            fn sort_cmp(&self, other: &Self) -> Ordering {
+                self.total_cmp(other)
+            }
+        })*
+    };
+}
+
+#[cfg(feature = "f16")]
+host_half!(half::f16, half::bf16);
+
+impl UnaryOp {
+    fn eval_host(&self, v: f64) -> f64 {
+        match *self {
+            Self::Neg => -v,
+            Self::Abs => v.abs(),
+            Self::Exp => v.exp(),
+            Self::Log => v.ln(),
+            Self::Sqrt => v.sqrt(),
+            Self::Recip => 1.0 / v,
+            Self::Square => v * v,
+            Self::Sin => v.sin(),
+            Self::Cos => v.cos(),
+            Self::Tanh => v.tanh(),
+            Self::Sigmoid => 1.0 / (1.0 + (-v).exp()),
+            Self::Relu => {
+                if v > 0.0 {
+                    v
+                } else {
+                    0.0
+                }
+            }
+            Self::Gelu => {
+                0.5 * v * (1.0 + (0.7978845608028654 * (v + 0.044715 * v * v * v)).tanh())
+            }
+            Self::Floor => v.floor(),
+            Self::Ceil => v.ceil(),
+            Self::AddScalar(s) => v + s,
+            Self::MulScalar(s) => v * s,
+            Self::PowScalar(s) => v.powf(s),
+            Self::MaxScalar(s) => v.max(s),
+            Self::MinScalar(s) => v.min(s),
+        }
+    }
+}
+
+impl BinaryOp {
+    fn eval_host(&self, a: f64, b: f64) -> f64 {
+        match self {
+            Self::Add => a + b,
+            Self::Sub => a - b,
+            Self::Mul => a * b,
+            Self::Div => a / b,
+            Self::Max => a.max(b),
+            Self::Min => a.min(b),
+            Self::Pow => a.powf(b),
+        }
+    }
+}
+
+/// Runs the [DeviceOps] on the host, over [Vec]s, so that code using them can be tested
+/// without a GPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuDevice;
+
+impl CpuDevice {
+    /// The index of the best element of `x` by the order of [CudaDevice::argmax()]: NaN
+    /// first, then `better` values, then lower indices.
+    fn arg_best<T: HostDtype>(
+        x: &[T],
+        out_idx: &mut [u32],
+        better: fn(f64, f64) -> bool,
+    ) -> Result<(), DriverError> {
+        if x.is_empty() || u32::try_from(x.len()).is_err() || out_idx.len() != 1 {
+            return Err(invalid_value());
+        }
+        let mut best = 0;
+        for (i, v) in x.iter().enumerate().skip(1) {
+            let (v, b) = (v.to_f64(), x[best].to_f64());
+            if b.is_nan() {
+                break;
+            }
+            if v.is_nan() || better(v, b) {
+                best = i;
+            }
+        }
+        out_idx[0] = best as u32;
+        Ok(())
+    }
+
+    fn scan<T: HostDtype>(x: &[T], out: &mut [T], inclusive: bool) -> Result<(), DriverError> {
+        if x.len() != out.len() {
+            return Err(invalid_value());
+        }
+        let mut acc = T::default();
+        for (x, out) in x.iter().zip(out.iter_mut()) {
+            let next = acc.scan_add(*x);
+            *out = if inclusive { next } else { acc };
+            acc = next;
+        }
+        Ok(())
+    }
+}
+
+impl DeviceOps for CpuDevice {
+    type Slice<T> = Vec<T>;
+
+    fn alloc_zeros<T: ValidAsZeroBits + DeviceRepr + HostDtype>(
+        &self,
+        len: usize,
+    ) -> Result<Vec<T>, DriverError> {
+        Ok(vec![T::default(); len])
+    }
+
+    fn htod_copy<T: DeviceRepr + HostDtype>(&self, src: &[T]) -> Result<Vec<T>, DriverError> {
+        Ok(src.to_vec())
+    }
+
+    fn dtoh_copy<T: DeviceRepr + HostDtype>(&self, src: &Vec<T>) -> Result<Vec<T>, DriverError> {
+        Ok(src.clone())
+    }
+
+    fn apply_unary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: UnaryOp,
+        x: &Vec<T>,
+        out: &mut Vec<T>,
+    ) -> Result<(), DriverError> {
+        if x.len() != out.len() {
+            return Err(invalid_value());
+        }
+        for (x, out) in x.iter().zip(out.iter_mut()) {
+            *out = HostDtype::from_f64(op.eval_host(x.to_f64()));
+        }
+        Ok(())
+    }
+
+    fn apply_binary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: BinaryOp,
+        x: &Vec<T>,
+        y: &Vec<T>,
+        out: &mut Vec<T>,
+    ) -> Result<(), DriverError> {
+        if x.len() != y.len() || x.len() != out.len() {
+            return Err(invalid_value());
+        }
+        for ((x, y), out) in x.iter().zip(y.iter()).zip(out.iter_mut()) {
+            *out = HostDtype::from_f64(op.eval_host(x.to_f64(), y.to_f64()));
+        }
+        Ok(())
+    }
+
+    fn argmax<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &Vec<T>,
+        out_idx: &mut Vec<u32>,
+    ) -> Result<(), DriverError> {
+        Self::arg_best(x, out_idx, |v, best| v > best)
+    }
+
+    fn argmin<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &Vec<T>,
+        out_idx: &mut Vec<u32>,
+    ) -> Result<(), DriverError> {
+        Self::arg_best(x, out_idx, |v, best| v < best)
+    }
+
+    fn sum<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &Vec<T>,
+        out: &mut Vec<f32>,
+    ) -> Result<(), DriverError> {
+        if x.is_empty() || u32::try_from(x.len()).is_err() || out.len() != 1 {
+            return Err(invalid_value());
+        }
+        out[0] = x.iter().map(|v| v.to_f64() as f32).sum();
+        Ok(())
+    }
+
+    fn scan_exclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &Vec<T>,
+        out: &mut Vec<T>,
+    ) -> Result<(), DriverError> {
+        Self::scan(x, out, false)
+    }
+
+    fn scan_inclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &Vec<T>,
+        out: &mut Vec<T>,
+    ) -> Result<(), DriverError> {
+        Self::scan(x, out, true)
+    }
+
+    fn sort<K: SortKey + HostDtype>(&self, keys: &mut Vec<K>) -> Result<(), DriverError> {
+        if u32::try_from(keys.len()).is_err() {
+            return Err(invalid_value());
+        }
+        keys.sort_by(K::sort_cmp);
+        Ok(())
+    }
+
+    fn argsort<K: SortKey + HostDtype>(&self, keys: &Vec<K>) -> Result<Vec<u32>, DriverError> {
+        if u32::try_from(keys.len()).is_err() {
+            return Err(invalid_value());
+        }
+        let mut indices: Vec<u32> = (0..keys.len() as u32).collect();
+        // stable, so equal keys stay in the order of their indices
+        indices.sort_by(|&a, &b| keys[a as usize].sort_cmp(&keys[b as usize]));
+        Ok(indices)
+    }
+
+    fn synchronize(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl DeviceOps for Arc<CudaDevice> {
+    type Slice<T> = CudaSlice<T>;
+
+    fn alloc_zeros<T: ValidAsZeroBits + DeviceRepr + HostDtype>(
+        &self,
+        len: usize,
+    ) -> Result<CudaSlice<T>, DriverError> {
+        CudaDevice::alloc_zeros(self, len)
+    }
+
+    fn htod_copy<T: DeviceRepr + HostDtype>(&self, src: &[T]) -> Result<CudaSlice<T>, DriverError> {
+        self.htod_sync_copy(src)
+    }
+
+    fn dtoh_copy<T: DeviceRepr + HostDtype>(
+        &self,
+        src: &CudaSlice<T>,
+    ) -> Result<Vec<T>, DriverError> {
+        self.dtoh_sync_copy(src)
+    }
+
+    fn apply_unary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: UnaryOp,
+        x: &CudaSlice<T>,
+        out: &mut CudaSlice<T>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::apply_unary(self, op, x, out)
+    }
+
+    fn apply_binary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: BinaryOp,
+        x: &CudaSlice<T>,
+        y: &CudaSlice<T>,
+        out: &mut CudaSlice<T>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::apply_binary(self, op, x, y, out)
+    }
+
+    fn argmax<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &CudaSlice<T>,
+        out_idx: &mut CudaSlice<u32>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::argmax(self, x, out_idx)
+    }
+
+    fn argmin<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &CudaSlice<T>,
+        out_idx: &mut CudaSlice<u32>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::argmin(self, x, out_idx)
+    }
+
+    fn sum<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &CudaSlice<T>,
+        out: &mut CudaSlice<f32>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::sum(self, x, out)
+    }
+
+    fn scan_exclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &CudaSlice<T>,
+        out: &mut CudaSlice<T>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::scan_exclusive(self, x, out)
+    }
+
+    fn scan_inclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &CudaSlice<T>,
+        out: &mut CudaSlice<T>,
+    ) -> Result<(), DriverError> {
+        CudaDevice::scan_inclusive(self, x, out)
+    }
+
+    fn sort<K: SortKey + HostDtype>(&self, keys: &mut CudaSlice<K>) -> Result<(), DriverError> {
+        CudaDevice::sort(self, keys)
+    }
+
+    fn argsort<K: SortKey + HostDtype>(
+        &self,
+        keys: &CudaSlice<K>,
+    ) -> Result<CudaSlice<u32>, DriverError> {
+        CudaDevice::argsort(self, keys)
+    }
+
+    fn synchronize(&self) -> Result<(), DriverError> {
+        CudaDevice::synchronize(self)
+    }
+}
+
+/// Either a [CudaDevice] or a [CpuDevice], for code that should run without a GPU too.
+/// Its [DeviceOps] take [DeviceBuffer]s, and fail with
+/// [crate::driver::sys::CUresult::CUDA_ERROR_INVALID_VALUE] when given a buffer of the other
+/// kind.
+#[derive(Debug, Clone)]
+pub enum Device {
+    Cuda(Arc<CudaDevice>),
+    Cpu(CpuDevice),
+}
+
+/// A buffer of a [Device].
+#[derive(Debug)]
+pub enum DeviceBuffer<T> {
+    Cuda(CudaSlice<T>),
+    Cpu(Vec<T>),
+}
+
+impl<T> DeviceBuffer<T> {
+    /// The number of elements of the buffer.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Cuda(slice) => slice.len,
+            Self::Cpu(vec) => vec.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl From<Arc<CudaDevice>> for Device {
+    fn from(device: Arc<CudaDevice>) -> Self {
+        Self::Cuda(device)
+    }
+}
+
+impl From<CpuDevice> for Device {
+    fn from(device: CpuDevice) -> Self {
+        Self::Cpu(device)
+    }
+}
+
+/// Calls `$method` of the [DeviceOps] of the device in `$device`, with the buffers
+/// `$buf` of the same kind unwrapped.
+macro_rules! dispatch {
+    ($device:expr, $method:ident, ($($arg:expr),*), [$($buf:ident),*]) => {
+        match $device {
+            Device::Cuda(dev) => match ($($buf,)*) {
+                ($(DeviceBuffer::Cuda($buf),)*) => DeviceOps::$method(dev, $($arg,)* $($buf),*),
+                #[allow(unreachable_patterns)]
+                _ => Err(invalid_value()),
+            },
+            Device::Cpu(dev) => match ($($buf,)*) {
+                ($(DeviceBuffer::Cpu($buf),)*) => DeviceOps::$method(dev, $($arg,)* $($buf),*),
+                #[allow(unreachable_patterns)]
+                _ => Err(invalid_value()),
+            },
+        }
+    };
+}
+
+impl DeviceOps for Device {
+    type Slice<T> = DeviceBuffer<T>;
+
+    fn alloc_zeros<T: ValidAsZeroBits + DeviceRepr + HostDtype>(
+        &self,
+        len: usize,
+    ) -> Result<DeviceBuffer<T>, DriverError> {
+        match self {
+            Self::Cuda(dev) => DeviceOps::alloc_zeros(dev, len).map(DeviceBuffer::Cuda),
+            Self::Cpu(dev) => DeviceOps::alloc_zeros(dev, len).map(DeviceBuffer::Cpu),
+        }
+    }
+
+    fn htod_copy<T: DeviceRepr + HostDtype>(
+        &self,
+        src: &[T],
+    ) -> Result<DeviceBuffer<T>, DriverError> {
+        match self {
+            Self::Cuda(dev) => DeviceOps::htod_copy(dev, src).map(DeviceBuffer::Cuda),
+            Self::Cpu(dev) => DeviceOps::htod_copy(dev, src).map(DeviceBuffer::Cpu),
+        }
+    }
+
+    fn dtoh_copy<T: DeviceRepr + HostDtype>(
+        &self,
+        src: &DeviceBuffer<T>,
+    ) -> Result<Vec<T>, DriverError> {
+        dispatch!(self, dtoh_copy, (), [src])
+    }
+
+    fn apply_unary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: UnaryOp,
+        x: &DeviceBuffer<T>,
+        out: &mut DeviceBuffer<T>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, apply_unary, (op), [x, out])
+    }
+
+    fn apply_binary<T: ApplyDtype + HostDtype>(
+        &self,
+        op: BinaryOp,
+        x: &DeviceBuffer<T>,
+        y: &DeviceBuffer<T>,
+        out: &mut DeviceBuffer<T>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, apply_binary, (op), [x, y, out])
+    }
+
+    fn argmax<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &DeviceBuffer<T>,
+        out_idx: &mut DeviceBuffer<u32>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, argmax, (), [x, out_idx])
+    }
+
+    fn argmin<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &DeviceBuffer<T>,
+        out_idx: &mut DeviceBuffer<u32>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, argmin, (), [x, out_idx])
+    }
+
+    fn sum<T: ReduceDtype + HostDtype>(
+        &self,
+        x: &DeviceBuffer<T>,
+        out: &mut DeviceBuffer<f32>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, sum, (), [x, out])
+    }
+
+    fn scan_exclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &DeviceBuffer<T>,
+        out: &mut DeviceBuffer<T>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, scan_exclusive, (), [x, out])
+    }
+
+    fn scan_inclusive<T: ScanDtype + HostDtype>(
+        &self,
+        x: &DeviceBuffer<T>,
+        out: &mut DeviceBuffer<T>,
+    ) -> Result<(), DriverError> {
+        dispatch!(self, scan_inclusive, (), [x, out])
+    }
+
+    fn sort<K: SortKey + HostDtype>(&self, keys: &mut DeviceBuffer<K>) -> Result<(), DriverError> {
+        dispatch!(self, sort, (), [keys])
+    }
+
+    fn argsort<K: SortKey + HostDtype>(
+        &self,
+        keys: &DeviceBuffer<K>,
+    ) -> Result<DeviceBuffer<u32>, DriverError> {
+        match self {
+            Self::Cuda(dev) => match keys {
+                DeviceBuffer::Cuda(keys) => DeviceOps::argsort(dev, keys).map(DeviceBuffer::Cuda),
+                DeviceBuffer::Cpu(_) => Err(invalid_value()),
+            },
+            Self::Cpu(dev) => match keys {
+                DeviceBuffer::Cpu(keys) => DeviceOps::argsort(dev, keys).map(DeviceBuffer::Cpu),
+                DeviceBuffer::Cuda(_) => Err(invalid_value()),
+            },
+        }
+    }
+
+    fn synchronize(&self) -> Result<(), DriverError> {
+        match self {
+            Self::Cuda(dev) => DeviceOps::synchronize(dev),
+            Self::Cpu(dev) => DeviceOps::synchronize(dev),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu() -> Device {
+        Device::Cpu(CpuDevice)
+    }
+
+    /// The cuda device, when a driver and GPU are available.
+    fn gpu() -> Option<Device> {
+        let dev = std::panic::catch_unwind(|| CudaDevice::new(0)).ok()?.ok()?;
+        Some(Device::Cuda(dev))
+    }
+
+    /// Runs `f` on the cpu, and on the gpu if there is one, checking that both agree.
+    fn parity<R: PartialEq + std::fmt::Debug>(f: impl Fn(&Device) -> R) -> R {
+        let on_cpu = f(&cpu());
+        if let Some(dev) = gpu() {
+            assert_eq!(f(&dev), on_cpu);
+        }
+        on_cpu
+    }
+
+    #[test]
+    fn test_cpu_ops_match_cuda() {
+        let x = [3.0f32, -1.5, f32::NAN, 0.25, 7.0, -8.0, 7.0];
+
+        let unary = |dev: &Device, op: UnaryOp| {
+            let x = dev.htod_copy(&[0.5f32, -2.0, 3.0]).unwrap();
+            let mut out = dev.alloc_zeros::<f32>(3).unwrap();
+            dev.apply_unary(op, &x, &mut out).unwrap();
+            // round away the differences of f32 and f64 math
+            let out = dev.dtoh_copy(&out).unwrap();
+            out.iter()
+                .map(|v| (v * 1e4).round() as i64)
+                .collect::<Vec<_>>()
+        };
+        for op in [
+            UnaryOp::Exp,
+            UnaryOp::Relu,
+            UnaryOp::Gelu,
+            UnaryOp::MulScalar(2.5),
+        ] {
+            parity(|dev| unary(dev, op));
+        }
+        let binary = parity(|dev| {
+            let a = dev.htod_copy(&[1.0f64, 2.0, -3.0]).unwrap();
+            let b = dev.htod_copy(&[4.0f64, -1.0, 0.5]).unwrap();
+            let mut out = dev.alloc_zeros::<f64>(3).unwrap();
+            dev.apply_binary(BinaryOp::Max, &a, &b, &mut out).unwrap();
+            dev.dtoh_copy(&out).unwrap()
+        });
+        assert_eq!(binary, [4.0, 2.0, 0.5]);
+
+        let (argmax, argmin) = parity(|dev| {
+            let x = dev.htod_copy(&x).unwrap();
+            let mut idx = dev.alloc_zeros::<u32>(1).unwrap();
+            dev.argmax(&x, &mut idx).unwrap();
+            let argmax = dev.dtoh_copy(&idx).unwrap()[0];
+            dev.argmin(&x, &mut idx).unwrap();
+            (argmax, dev.dtoh_copy(&idx).unwrap()[0])
+        });
+        // NaN ranks first in both
+        assert_eq!((argmax, argmin), (2, 2));
+        let argmax = parity(|dev| {
+            let x = dev.htod_copy(&[1.0f32, 7.0, -2.0, 7.0]).unwrap();
+            let mut idx = dev.alloc_zeros::<u32>(1).unwrap();
+            dev.argmax(&x, &mut idx).unwrap();
+            dev.dtoh_copy(&idx).unwrap()[0]
+        });
+        assert_eq!(argmax, 1);
+
+        let sum = parity(|dev| {
+            let x = dev.htod_copy(&[0.5f32, 2.0, -1.0, 3.0]).unwrap();
+            let mut out = dev.alloc_zeros::<f32>(1).unwrap();
+            dev.sum(&x, &mut out).unwrap();
+            dev.dtoh_copy(&out).unwrap()
+        });
+        assert_eq!(sum, [4.5]);
+
+        let scans = parity(|dev| {
+            let x = dev.htod_copy(&[3u32, 1, 4, 1, u32::MAX]).unwrap();
+            let mut out = dev.alloc_zeros::<u32>(5).unwrap();
+            dev.scan_exclusive(&x, &mut out).unwrap();
+            let exclusive = dev.dtoh_copy(&out).unwrap();
+            dev.scan_inclusive(&x, &mut out).unwrap();
+            (exclusive, dev.dtoh_copy(&out).unwrap())
+        });
+        assert_eq!(scans, (vec![0, 3, 4, 8, 9], vec![3, 4, 8, 9, 8]));
+
+        let sorted = parity(|dev| {
+            let mut keys = dev
+                .htod_copy(&[3.0f32, -1.0, -0.0, 0.0, 2.0, -1.0])
+                .unwrap();
+            let order = dev.argsort(&keys).unwrap();
+            dev.sort(&mut keys).unwrap();
+            let keys = dev.dtoh_copy(&keys).unwrap();
+            (
+                keys.iter().map(|k| k.to_bits()).collect::<Vec<_>>(),
+                dev.dtoh_copy(&order).unwrap(),
+            )
+        });
+        let expected = [-1.0f32, -1.0, -0.0, 0.0, 2.0, 3.0];
+        assert_eq!(sorted.0, expected.map(f32::to_bits));
+        assert_eq!(sorted.1, [1, 5, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn test_cpu_ops_errors() {
+        let dev = cpu();
+        let x = dev.htod_copy(&[1.0f32, 2.0]).unwrap();
+        let mut short = dev.alloc_zeros::<f32>(1).unwrap();
+        let invalid = Err(invalid_value());
+        assert_eq!(dev.apply_unary(UnaryOp::Neg, &x, &mut short), invalid);
+        let mut idx = dev.alloc_zeros::<u32>(2).unwrap();
+        assert_eq!(dev.argmax(&x, &mut idx), invalid);
+        let empty = dev.alloc_zeros::<f32>(0).unwrap();
+        assert_eq!(dev.sum(&empty, &mut short), invalid);
+
+        // buffers of another device are rejected
+        if let Some(gpu) = gpu() {
+            let on_gpu = gpu.htod_copy(&[1.0f32, 2.0]).unwrap();
+            assert_eq!(dev.dtoh_copy(&on_gpu), Err(invalid_value()));
+            assert_eq!(gpu.dtoh_copy(&x), Err(invalid_value()));
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+use super::error_handler::OrHandle;
+
+use std::vec::Vec;
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// Memory freed by [super::CudaSlice::drop_on_stream()] on a device without async
+/// allocations, and events recorded on the streams it was dropped on. Each allocation is
+/// freed once its event has completed, so that dropping it doesn't synchronize.
+#[derive(Debug, Default)]
+pub(crate) struct DeferredFrees {
+    pending: RwLock<Vec<(sys::CUdeviceptr, sys::CUevent)>>,
+}
+
+impl DeferredFrees {
+    /// Waits for and frees all the pending allocations, which needs the context of the
+    /// device to be bound.
+    pub(crate) fn clear(&mut self) {
+        let pending = RwLock::get_mut(&mut self.pending);
+        #[cfg(not(feature = "no-std"))]
+        let pending = pending.unwrap();
+        for (ptr, event) in pending.drain(..) {
+            unsafe {
+                result::event::synchronize(event).or_handle("CudaDevice::drop");
+                result::free_sync(ptr).or_handle("CudaDevice::drop");
+                result::event::destroy(event).or_handle("CudaDevice::drop");
+            }
+        }
+    }
+
+    /// Forgets the pending allocations and their events without freeing them, after the
+    /// reset of their context freed them.
+    pub(crate) fn forget(&mut self) {
+        let pending = RwLock::get_mut(&mut self.pending);
+        #[cfg(not(feature = "no-std"))]
+        let pending = pending.unwrap();
+        pending.clear();
+    }
+}
+
+impl CudaDevice {
+    /// Frees `ptr` once the work enqueued on `stream` so far has completed.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated with [result::malloc_sync()] and not be used by work
+    /// enqueued after this. The context must be bound.
+    pub(crate) unsafe fn defer_free(
+        &self,
+        ptr: sys::CUdeviceptr,
+        stream: sys::CUstream,
+    ) -> Result<(), result::DriverError> {
+        #[allow(unused_mut)]
+        let mut pending = self.deferred_frees.pending.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut pending = pending.unwrap();
+        self.reap(&mut pending);
+        // before the event is recorded, so that pushing it can't fail after
+        pending.reserve(1);
+        let event = match self.take_event() {
+            Ok(event) => event,
+            Err(e) => {
+                result::free_sync(ptr).or_handle("CudaSlice::drop_on_stream");
+                return Err(e);
+            }
+        };
+        if let Err(e) = result::event::record(event, stream) {
+            self.return_event(event);
+            result::free_sync(ptr).or_handle("CudaSlice::drop_on_stream");
+            return Err(e);
+        }
+        pending.push((ptr, event));
+        Ok(())
+    }
+
+    /// Frees the deferred allocations whose streams have reached their events, without
+    /// waiting for the others. The context must be bound.
+    pub(crate) fn reap_deferred_frees(&self) {
+        #[allow(unused_mut)]
+        let mut pending = self.deferred_frees.pending.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut pending = pending.unwrap();
+        self.reap(&mut pending);
+    }
+
+    fn reap(&self, pending: &mut Vec<(sys::CUdeviceptr, sys::CUevent)>) {
+        let mut i = 0;
+        while i < pending.len() {
+            let (ptr, event) = pending[i];
+            // errors are reported when waiting for it
+            if !unsafe { result::event::query(event) }.unwrap_or(false) {
+                i += 1;
+                continue;
+            }
+            pending.swap_remove(i);
+            unsafe { result::free_sync(ptr) }.or_handle("CudaDevice::reap_deferred_frees");
+            self.return_event(event);
+        }
+    }
+
+    /// [result::malloc_sync()] after freeing the deferred allocations that completed. If it
+    /// runs out of memory, it waits for the others and tries again. The context must be bound.
+    ///
+    /// # Safety
+    /// As for [result::malloc_sync()].
+    pub(crate) unsafe fn malloc_sync_reaping(
+        &self,
+        num_bytes: usize,
+    ) -> Result<sys::CUdeviceptr, result::DriverError> {
+        self.reap_deferred_frees();
+        match result::malloc_sync(num_bytes) {
+            Err(result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY))
+                if self.num_deferred_frees() > 0 =>
+            {
+                self.drain_deferred_frees()?;
+                result::malloc_sync(num_bytes)
+            }
+            res => res,
+        }
+    }
+
+    /// Waits for and frees all the deferred allocations. The context must be bound.
+    pub(crate) fn drain_deferred_frees(&self) -> Result<(), result::DriverError> {
+        let drained = {
+            #[allow(unused_mut)]
+            let mut pending = self.deferred_frees.pending.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut pending = pending.unwrap();
+            std::mem::take(&mut *pending)
+        };
+        let mut res = Ok(());
+        for (ptr, event) in drained {
+            unsafe {
+                if let Err(e) = result::event::synchronize(event) {
+                    res = res.and(Err(e));
+                }
+                if let Err(e) = result::free_sync(ptr) {
+                    res = res.and(Err(e));
+                }
+            }
+            self.return_event(event);
+        }
+        res
+    }
+
+    /// Number of allocations waiting to be freed.
+    pub(crate) fn num_deferred_frees(&self) -> usize {
+        #[allow(unused_mut)]
+        let pending = self.deferred_frees.pending.read();
+        #[cfg(not(feature = "no-std"))]
+        let pending = pending.unwrap();
+        pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig, SyncDebug};
+
+    const SPIN_SRC: &str = "
+extern \"C\" __global__ void spin(unsigned long long cycles) {
+    unsigned long long start = clock64();
+    while (clock64() - start < cycles) {}
+}";
+
+    #[test]
+    fn test_drop_on_stream_defers_free_without_sync() {
+        let dev = CudaDevice::new_without_memory_pools(0).unwrap();
+        let ptx = crate::nvrtc::compile_ptx(SPIN_SRC).unwrap();
+        dev.load_ptx(ptx, "spin", &["spin"]).unwrap();
+        let spin = dev.get_func("spin", "spin").unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+
+        dev.set_sync_debug(SyncDebug::Panic);
+        let x = dev.alloc_zeros::<f32>(1 << 20).unwrap();
+        unsafe { spin.launch(&stream, LaunchConfig::for_num_elems(1), (1u64 << 30,)) }.unwrap();
+        // would panic if it synchronized
+        x.drop_on_stream(&stream).unwrap();
+        assert_eq!(dev.num_deferred_frees(), 1);
+
+        dev.set_sync_debug(SyncDebug::Off);
+        // waits for the event on `stream`
+        dev.synchronize().unwrap();
+        assert_eq!(dev.num_deferred_frees(), 0);
+    }
+
+    #[test]
+    fn test_deferred_frees_reaped_on_alloc() {
+        let dev = CudaDevice::new_without_memory_pools(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        for _ in 0..8 {
+            let x = dev.alloc_zeros::<u8>(1024).unwrap();
+            x.drop_on_stream(&stream).unwrap();
+        }
+        unsafe { result::stream::synchronize(stream.stream) }.unwrap();
+        let _y = dev.alloc_zeros::<u8>(1024).unwrap();
+        assert_eq!(dev.num_deferred_frees(), 0);
+    }
+
+    #[test]
+    fn test_deferred_frees_drained_on_oom() {
+        let dev = CudaDevice::new_without_memory_pools(0).unwrap();
+        let ptx = crate::nvrtc::compile_ptx(SPIN_SRC).unwrap();
+        dev.load_ptx(ptx, "spin", &["spin"]).unwrap();
+        let spin = dev.get_func("spin", "spin").unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+
+        let (free, _) = result::mem_get_info().unwrap();
+        let big = dev.alloc_zeros::<u8>(free / 4 * 3).unwrap();
+        unsafe { spin.launch(&stream, LaunchConfig::for_num_elems(1), (1u64 << 28,)) }.unwrap();
+        big.drop_on_stream(&stream).unwrap();
+        assert_eq!(dev.num_deferred_frees(), 1);
+        // only fits once `big` is freed, which waits for `spin`
+        let _again = dev.alloc_zeros::<u8>(free / 4 * 3).unwrap();
+        assert_eq!(dev.num_deferred_frees(), 0);
+    }
+}
@@ -0,0 +1,55 @@
+use super::core::CudaDevice;
+
+use std::sync::atomic::Ordering;
+
+/// How the builtin ops of a [CudaDevice] order floating point sums, see
+/// [CudaDevice::set_determinism()].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Determinism {
+    /// Partial sums are combined with atomics in whatever order the blocks finish, so
+    /// results can differ from run to run by rounding.
+    #[default]
+    Fast,
+    /// Partial sums are combined in a fixed tree, which only depends on the length of the
+    /// input, so every run gives bitwise the same result. Takes an extra launch for each
+    /// level of the tree.
+    Bitwise,
+}
+
+impl Determinism {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            Determinism::Fast => 0,
+            Determinism::Bitwise => 1,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Determinism::Bitwise,
+            _ => Determinism::Fast,
+        }
+    }
+}
+
+impl CudaDevice {
+    /// Sets how the builtin reductions order floating point sums. Only [CudaDevice::sum()]
+    /// and [CudaDevice::sum_rows()] depend on it, the other builtin ops give the same
+    /// results in both modes:
+    /// 1. [CudaDevice::argmax()], [CudaDevice::top_k()] and the other selections compare
+    ///    elements exactly.
+    /// 2. [CudaDevice::scan_inclusive()] and [CudaDevice::scan_exclusive()] add in an order
+    ///    fixed by the length of the input.
+    /// 3. [CudaDevice::histogram()] counts with integer atomics, which are exact.
+    ///
+    /// See `CudaBlas::set_deterministic()` for cublas.
+    pub fn set_determinism(&self, mode: Determinism) {
+        self.determinism.store(mode.to_u8(), Ordering::Relaxed);
+    }
+
+    /// The current [Determinism] mode, [Determinism::Fast] by default.
+    pub fn determinism(&self) -> Determinism {
+        Determinism::from_u8(self.determinism.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,348 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::CudaDevice;
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use core::sync::atomic::{fence, Ordering};
+use std::{sync::Arc, vec::Vec};
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// CUDA source defining `CUDARC_LOG(log, code, value)`, to prepend to kernel source
+/// compiled with nvrtc. The kernel takes the log as a `cudarc_device_log *` parameter,
+/// passed as `&DeviceLog` when launching. If the parameter is named `cudarc_log`, the
+/// shorter `LOG(code, value)` can be used instead.
+///
+/// Each call appends a 16 byte record holding the `unsigned int` code and the
+/// `unsigned long long` value, which [DeviceLog::drain()] returns as a [LogRecord] in the
+/// order the records were appended. When the log is full, the record is dropped instead.
+///
+/// `CUDARC_ASSERT_LOG(flag, log, cond, code, value)` is `CUDARC_ASSERT(flag, cond, code)`
+/// that also logs `value` when `cond` is false, so every failure is kept rather than only
+/// the first one. It needs [DEVICE_ASSERT_SRC](crate::driver::DEVICE_ASSERT_SRC) to be
+/// prepended first.
+///
+/// ```cuda
+/// extern "C" __global__ void kernel(const float *x, size_t n, cudarc_device_log *cudarc_log) {
+///     size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+///     if (i < n && x[i] < 0.0f) {
+///         LOG(1, i);
+///     }
+/// }
+/// ```
+pub const DEVICE_LOG_SRC: &str = r#"
+struct cudarc_log_record {
+    unsigned int seq;
+    unsigned int code;
+    unsigned long long value;
+};
+struct cudarc_device_log {
+    unsigned long long cursor;
+    unsigned long long capacity;
+    unsigned long long read;
+    unsigned long long dropped;
+    cudarc_log_record records[1];
+};
+__device__ __forceinline__ void cudarc_log(cudarc_device_log *log, unsigned int code, unsigned long long value) {
+    volatile cudarc_device_log *l = log;
+    unsigned long long slot = l->cursor;
+    for (;;) {
+        if ((long long)(slot - l->read) >= (long long)l->capacity) {
+            atomicAdd(&log->dropped, 1ull);
+            return;
+        }
+        unsigned long long prev = atomicCAS(&log->cursor, slot, slot + 1);
+        if (prev == slot) break;
+        slot = prev;
+    }
+    volatile cudarc_log_record *r = &l->records[slot % l->capacity];
+    r->code = code;
+    r->value = value;
+    __threadfence_system();
+    r->seq = (unsigned int)(slot + 1);
+}
+#define CUDARC_LOG(log, code, value) cudarc_log((log), (code), (unsigned long long)(value))
+#ifndef LOG
+#define LOG(code, value) CUDARC_LOG(cudarc_log, (code), (value))
+#endif
+#define CUDARC_ASSERT_LOG(flag, log, cond, code, value) \
+    do { if (!(cond)) { cudarc_set_error((flag), (code), __LINE__); CUDARC_LOG((log), (code), (value)); } } while (0)
+"#;
+
+/// Bytes before the records: `cursor`, `capacity`, `read` and `dropped` of `cudarc_device_log`.
+const HEADER_BYTES: usize = 32;
+const RECORD_BYTES: usize = 16;
+
+/// A record appended with `CUDARC_LOG` (see [DEVICE_LOG_SRC]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LogRecord {
+    /// The position of the record in the log, counting from 0 since the log was allocated
+    /// or [DeviceLog::reset()].
+    pub seq: u64,
+    /// The `code` passed to `CUDARC_LOG`.
+    pub code: u32,
+    /// The `value` passed to `CUDARC_LOG`.
+    pub value: u64,
+}
+
+/// A ring buffer of [LogRecord]s kernels append to with `CUDARC_LOG` (see
+/// [DEVICE_LOG_SRC]). Create with [CudaDevice::alloc_device_log()].
+///
+/// Unlike `printf`, records are kept in the order they were appended, and can be read
+/// while kernels are still running, or after a kernel failed. Like [super::DeviceErrorFlag],
+/// the log lives in mapped page-locked host memory.
+///
+/// The log wraps around, reusing the space of drained records. Records appended when
+/// [DeviceLog::capacity()] records are waiting to be drained are dropped, and counted by
+/// [DeviceLog::num_dropped()].
+#[derive(Debug)]
+pub struct DeviceLog {
+    host_ptr: *mut u8,
+    dptr: sys::CUdeviceptr,
+    capacity: usize,
+    /// The position of the next record to drain.
+    next: RwLock<u64>,
+    device: Arc<CudaDevice>,
+}
+
+unsafe impl Send for DeviceLog {}
+unsafe impl Sync for DeviceLog {}
+
+impl CudaDevice {
+    /// Allocates an empty [DeviceLog] holding up to `capacity` records.
+    pub fn alloc_device_log(self: &Arc<Self>, capacity: usize) -> Result<DeviceLog, DriverError> {
+        if capacity == 0 {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        }
+        let _ctx = self.bind_scoped()?;
+        let num_bytes = HEADER_BYTES + capacity * RECORD_BYTES;
+        let host_ptr = unsafe { result::malloc_host(num_bytes, sys::CU_MEMHOSTALLOC_DEVICEMAP) }?;
+        let dptr = match unsafe { result::host_get_device_pointer(host_ptr) } {
+            Ok(dptr) => dptr,
+            Err(e) => {
+                unsafe { result::free_host(host_ptr) }.or_handle("CudaDevice::alloc_device_log");
+                return Err(e);
+            }
+        };
+        let log = DeviceLog {
+            host_ptr: host_ptr as *mut u8,
+            dptr,
+            capacity,
+            next: RwLock::new(0),
+            device: self.clone(),
+        };
+        log.reset();
+        Ok(log)
+    }
+}
+
+impl DeviceLog {
+    /// Returns the records appended since the last drain, oldest first, making room for
+    /// new ones.
+    ///
+    /// The device cursor is read once, and records appended after that are left for the
+    /// next drain. So are records still being written by the device, along with everything
+    /// after them, to keep the order.
+    pub fn drain(&self) -> Vec<LogRecord> {
+        let end = unsafe { std::ptr::read_volatile(self.header(0)) };
+        fence(Ordering::Acquire);
+
+        #[allow(unused_mut)]
+        let mut next = self.next.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut next = next.unwrap();
+
+        let mut records = Vec::with_capacity((end - *next) as usize);
+        while *next < end {
+            let slot = *next;
+            let words = unsafe {
+                self.host_ptr
+                    .add(HEADER_BYTES + (slot % self.capacity as u64) as usize * RECORD_BYTES)
+                    as *const u32
+            };
+            let read = |i: usize| unsafe { std::ptr::read_volatile(words.add(i)) };
+            if read(0) != (slot + 1) as u32 {
+                // the device hasn't finished writing it
+                break;
+            }
+            fence(Ordering::Acquire);
+            records.push(LogRecord {
+                seq: slot,
+                code: read(1),
+                value: read(2) as u64 | ((read(3) as u64) << 32),
+            });
+            *next += 1;
+        }
+
+        // only now can the device reuse the space
+        fence(Ordering::Release);
+        unsafe { std::ptr::write_volatile(self.header(2), *next) };
+        records
+    }
+
+    /// Clears the log, and the count of dropped records. Must not be called while a kernel
+    /// using the log is running.
+    pub fn reset(&self) {
+        #[allow(unused_mut)]
+        let mut next = self.next.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut next = next.unwrap();
+        *next = 0;
+        unsafe {
+            std::ptr::write_bytes(
+                self.host_ptr,
+                0,
+                HEADER_BYTES + self.capacity * RECORD_BYTES,
+            );
+            std::ptr::write_volatile(self.header(1), self.capacity as u64);
+        }
+        fence(Ordering::Release);
+    }
+
+    /// The number of records kept until they are drained.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of records dropped because the log was full.
+    pub fn num_dropped(&self) -> u64 {
+        unsafe { std::ptr::read_volatile(self.header(3)) }
+    }
+
+    /// The device pointer passed to kernels.
+    pub fn device_ptr(&self) -> sys::CUdeviceptr {
+        self.dptr
+    }
+
+    /// The `i`th `unsigned long long` of `cudarc_device_log`.
+    fn header(&self, i: usize) -> *mut u64 {
+        unsafe { (self.host_ptr as *mut u64).add(i) }
+    }
+}
+
+unsafe impl DeviceRepr for &DeviceLog {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.dptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+}
+
+impl Drop for DeviceLog {
+    fn drop(&mut self) {
+        let _ctx = self.device.bind_scoped_or_handle("DeviceLog::drop");
+        unsafe { result::free_host(self.host_ptr as *mut _) }.or_handle("DeviceLog::drop");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{DeviceAssert, LaunchAsync, LaunchConfig, DEVICE_ASSERT_SRC};
+    use crate::nvrtc::compile_ptx;
+    use std::collections::BTreeSet;
+
+    const SRC: &str = r#"
+extern "C" __global__ void log_many(unsigned int per_thread, cudarc_device_log *cudarc_log) {
+    unsigned int tid = blockIdx.x * blockDim.x + threadIdx.x;
+    for (unsigned int k = 0; k < per_thread; k++) {
+        unsigned int code = tid * per_thread + k;
+        LOG(code, (unsigned long long)code * 0x9E3779B97F4A7C15ull);
+    }
+}
+extern "C" __global__ void check_positive(const float *x, size_t n, unsigned int *flag, cudarc_device_log *log) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) {
+        CUDARC_ASSERT_LOG(flag, log, x[i] > 0.0f, 3, i);
+    }
+}
+"#;
+
+    fn load(dev: &Arc<CudaDevice>) {
+        let src = std::format!("{DEVICE_ASSERT_SRC}{DEVICE_LOG_SRC}{SRC}");
+        dev.load_ptx(
+            compile_ptx(src).unwrap(),
+            "log",
+            &["log_many", "check_positive"],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_device_log_concurrent_records() {
+        let dev = CudaDevice::new(0).unwrap();
+        load(&dev);
+        let f = dev.get_func("log", "log_many").unwrap();
+        let log = dev.alloc_device_log(8192).unwrap();
+        assert_eq!(log.drain(), []);
+
+        let cfg = LaunchConfig {
+            grid_dim: (16, 1, 1),
+            block_dim: (256, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        unsafe { f.clone().launch(dev.default_stream(), cfg, (2u32, &log)) }.unwrap();
+        dev.synchronize().unwrap();
+
+        let records = log.drain();
+        assert_eq!(records.len(), 8192);
+        let mut codes = BTreeSet::new();
+        for (i, r) in records.iter().enumerate() {
+            assert_eq!(r.seq, i as u64);
+            assert_eq!(r.value, r.code as u64 * 0x9E3779B97F4A7C15, "torn {r:?}");
+            assert!(codes.insert(r.code));
+        }
+        assert_eq!(codes.len(), 8192);
+        assert_eq!(log.drain(), []);
+        assert_eq!(log.num_dropped(), 0);
+
+        // wraps around to the start, and drops what doesn't fit
+        unsafe { f.launch(dev.default_stream(), cfg, (3u32, &log)) }.unwrap();
+        dev.synchronize().unwrap();
+        let records = log.drain();
+        assert_eq!(records.len(), 8192);
+        assert_eq!(log.num_dropped(), 4096);
+        for (i, r) in records.iter().enumerate() {
+            assert_eq!(r.seq, 8192 + i as u64);
+            assert_eq!(r.value, r.code as u64 * 0x9E3779B97F4A7C15, "torn {r:?}");
+        }
+
+        log.reset();
+        assert_eq!(log.num_dropped(), 0);
+        assert_eq!(log.drain(), []);
+    }
+
+    #[test]
+    fn test_device_log_with_error_flag() {
+        let dev = CudaDevice::new(0).unwrap();
+        load(&dev);
+        let f = dev.get_func("log", "check_positive").unwrap();
+        let flag = dev.alloc_error_flag().unwrap();
+        let log = dev.alloc_device_log(16).unwrap();
+
+        let mut host: Vec<f32> = (1..=256).map(|i| i as f32).collect();
+        host[10] = -1.0;
+        host[200] = 0.0;
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let cfg = LaunchConfig::for_num_elems(host.len() as u32);
+        unsafe { f.launch(dev.default_stream(), cfg, (&x, host.len(), &flag, &log)) }.unwrap();
+        dev.synchronize().unwrap();
+
+        let err: DeviceAssert = flag.check().unwrap_err();
+        assert_eq!(err.code, 3);
+        let mut values: Vec<u64> = log
+            .drain()
+            .into_iter()
+            .map(|r| {
+                assert_eq!(r.code, 3);
+                r.value
+            })
+            .collect();
+        values.sort();
+        assert_eq!(values, [10, 200]);
+    }
+}
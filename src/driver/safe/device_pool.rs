@@ -0,0 +1,166 @@
+use super::{CudaDevice, CudaSlice, DeviceRepr, DriverError, ValidAsZeroBits};
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{sync::Arc, vec::Vec};
+
+/// A collection of [CudaDevice]s, one per visible device.
+///
+/// Create with [CudaDevicePool::all()] or [CudaDevicePool::from_devices()].
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let pool = CudaDevicePool::all().unwrap();
+/// let dev = pool.next();
+/// let slices = pool.broadcast(&[1.0f32, 2.0, 3.0]).unwrap();
+/// assert_eq!(slices.len(), pool.len());
+/// ```
+#[derive(Debug)]
+pub struct CudaDevicePool {
+    devices: Vec<Arc<CudaDevice>>,
+    next: AtomicUsize,
+}
+
+impl CudaDevicePool {
+    /// Creates a [CudaDevice] for every device returned by [CudaDevice::count()].
+    ///
+    /// The driver already applies `CUDA_VISIBLE_DEVICES`, so ordinals here are
+    /// indices into the visible devices, not the physical ones. Only the devices allowed
+    /// by [super::set_device_allowlist()] are included.
+    pub fn all() -> Result<Self, DriverError> {
+        let n = CudaDevice::count()? as usize;
+        let devices = (0..n).map(CudaDevice::new).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_devices(devices))
+    }
+
+    /// Creates a pool out of already constructed devices.
+    pub fn from_devices(devices: Vec<Arc<CudaDevice>>) -> Self {
+        Self {
+            devices,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of devices in the pool.
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Whether the pool has no devices.
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// All the devices in the pool, in ordinal order.
+    pub fn devices(&self) -> &[Arc<CudaDevice>] {
+        &self.devices
+    }
+
+    /// Returns the device with [CudaDevice::ordinal()] equal to `ordinal`.
+    pub fn get(&self, ordinal: usize) -> Option<&Arc<CudaDevice>> {
+        self.devices.iter().find(|dev| dev.ordinal() == ordinal)
+    }
+
+    /// Returns the next device in round robin order. Safe to call from multiple threads.
+    ///
+    /// # Panics
+    /// If the pool is empty.
+    pub fn next(&self) -> &Arc<CudaDevice> {
+        assert!(!self.devices.is_empty(), "CudaDevicePool is empty");
+        let i = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.devices[i % self.devices.len()]
+    }
+
+    /// Runs `f` once per device, each on its own thread with the device's context
+    /// bound, and returns the results in device order.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let pool = CudaDevicePool::all().unwrap();
+    /// let sums = pool
+    ///     .par_map(|dev| {
+    ///         let slice = dev.htod_sync_copy(&[1.0f32; 10])?;
+    ///         Ok(dev.dtoh_sync_copy(&slice)?.iter().sum::<f32>())
+    ///     })
+    ///     .unwrap();
+    /// assert!(sums.iter().all(|&s| s == 10.0));
+    /// ```
+    #[cfg(not(feature = "no-std"))]
+    pub fn par_map<R, F>(&self, f: F) -> Result<Vec<R>, DriverError>
+    where
+        R: Send,
+        F: Fn(&Arc<CudaDevice>) -> Result<R, DriverError> + Sync,
+    {
+        let f = &f;
+        std::thread::scope(|s| {
+            let handles: Vec<_> = self
+                .devices
+                .iter()
+                .map(|dev| {
+                    s.spawn(move || {
+                        dev.bind_to_thread()?;
+                        f(dev)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    /// Synchronously copies `src` to every device in the pool, returning one
+    /// [CudaSlice] per device in device order.
+    pub fn broadcast<T: DeviceRepr>(&self, src: &[T]) -> Result<Vec<CudaSlice<T>>, DriverError> {
+        self.devices
+            .iter()
+            .map(|dev| dev.htod_sync_copy(src))
+            .collect()
+    }
+
+    /// Allocates `len` zeroed elements on every device in the pool.
+    pub fn broadcast_zeros<T: DeviceRepr + ValidAsZeroBits>(
+        &self,
+        len: usize,
+    ) -> Result<Vec<CudaSlice<T>>, DriverError> {
+        self.devices
+            .iter()
+            .map(|dev| dev.alloc_zeros(len))
+            .collect()
+    }
+
+    /// Synchronizes every device in the pool.
+    pub fn synchronize(&self) -> Result<(), DriverError> {
+        self.devices.iter().try_for_each(|dev| dev.synchronize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_pool_round_robin() {
+        let pool = CudaDevicePool::all().unwrap();
+        let n = pool.len();
+        assert!(n > 0);
+        let first = pool.next().ordinal();
+        for _ in 1..n {
+            pool.next();
+        }
+        assert_eq!(pool.next().ordinal(), first);
+        assert_eq!(pool.get(0).unwrap().ordinal(), 0);
+        assert!(pool.get(n).is_none());
+    }
+
+    #[cfg(not(feature = "no-std"))]
+    #[test]
+    fn test_device_pool_broadcast_par_map() {
+        let pool = CudaDevicePool::all().unwrap();
+        let slices = pool.broadcast(&[1.0f32, 2.0, 3.0]).unwrap();
+        assert_eq!(slices.len(), pool.len());
+        let outs = pool
+            .par_map(|dev| dev.dtoh_sync_copy(&slices[dev.ordinal()]))
+            .unwrap();
+        for out in outs {
+            assert_eq!(out, [1.0, 2.0, 3.0]);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::driver::sys;
 
 use super::core::{CudaSlice, CudaView, CudaViewMut};
+use super::stream_tracking::LastWrite;
 
 pub trait DeviceSlice<T> {
     fn len(&self) -> usize;
@@ -10,12 +11,20 @@ pub trait DeviceSlice<T> {
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    /// The last tracked write to this memory, if it is a [CudaSlice].
+    /// See [super::CudaDevice::set_stream_tracking()].
+    fn last_write(&self) -> Option<&LastWrite> {
+        None
+    }
 }
 
 impl<T> DeviceSlice<T> for CudaSlice<T> {
     fn len(&self) -> usize {
         self.len
     }
+    fn last_write(&self) -> Option<&LastWrite> {
+        Some(&self.last_write)
+    }
 }
 
 impl<'a, T> DeviceSlice<T> for CudaView<'a, T> {
@@ -30,9 +39,36 @@ impl<'a, T> DeviceSlice<T> for CudaViewMut<'a, T> {
     }
 }
 
+/// Returns `ptr + offset * size_of::<T>()` if `offset..offset + len` is within `0..bound`.
+/// Returns `None` instead of wrapping if any of the arithmetic overflows.
+pub(crate) fn checked_offset<T>(
+    ptr: sys::CUdeviceptr,
+    bound: usize,
+    offset: usize,
+    len: usize,
+) -> Option<sys::CUdeviceptr> {
+    if offset.checked_add(len)? > bound {
+        return None;
+    }
+    let num_bytes = offset.checked_mul(std::mem::size_of::<T>())?;
+    ptr.checked_add(num_bytes.try_into().ok()?)
+}
+
 /// Abstraction over [CudaSlice]/[CudaView]
 pub trait DevicePtr<T>: DeviceSlice<T> {
     fn device_ptr(&self) -> &sys::CUdeviceptr;
+
+    /// The address of the element at `index`, or `None` if `index >= self.len()`.
+    fn try_at(&self, index: usize) -> Option<sys::CUdeviceptr> {
+        checked_offset::<T>(*self.device_ptr(), self.len(), index, 1)
+    }
+
+    /// A [CudaView] of the `len` elements starting at `offset`, or `None` if they
+    /// are not all within `self`.
+    fn try_view(&self, offset: usize, len: usize) -> Option<CudaView<'_, T>> {
+        checked_offset::<T>(*self.device_ptr(), self.len(), offset, len)
+            .map(|ptr| unsafe { CudaView::from_raw_parts(ptr, len) })
+    }
 }
 
 impl<T> DevicePtr<T> for CudaSlice<T> {
@@ -56,6 +92,14 @@ impl<'a, T> DevicePtr<T> for CudaViewMut<'a, T> {
 /// Abstraction over [CudaSlice]/[CudaViewMut]
 pub trait DevicePtrMut<T>: DeviceSlice<T> {
     fn device_ptr_mut(&mut self) -> &mut sys::CUdeviceptr;
+
+    /// A [CudaViewMut] of the `len` elements starting at `offset`, or `None` if they
+    /// are not all within `self`.
+    fn try_view_mut(&mut self, offset: usize, len: usize) -> Option<CudaViewMut<'_, T>> {
+        let bound = self.len();
+        checked_offset::<T>(*self.device_ptr_mut(), bound, offset, len)
+            .map(|ptr| unsafe { CudaViewMut::from_raw_parts(ptr, len) })
+    }
 }
 
 impl<T> DevicePtrMut<T> for CudaSlice<T> {
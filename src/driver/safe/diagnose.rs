@@ -0,0 +1,677 @@
+use crate::driver::{result, sys};
+
+use super::DriverError;
+
+use std::{string::String, vec::Vec};
+
+/// Collects why the driver can or can't be used on this machine, e.g. after
+/// [super::CudaDevice::new()] failed with [DriverError::InitFailed].
+///
+/// Loads the driver library, calls `cuInit`, queries the driver version and enumerates the
+/// devices. On Linux it also reads the version of the kernel module from
+/// `/proc/driver/nvidia/version`, and checks that `/dev/nvidia*` can be opened. Nothing here
+/// panics, even if the library can't be loaded.
+///
+/// ```rust
+/// let report = cudarc::driver::diagnose();
+/// if !report.is_ok() {
+///     eprintln!("{report}");
+/// }
+/// ```
+pub fn diagnose() -> DiagnosticsReport {
+    collect(&System)
+}
+
+/// The result of [diagnose()]. [DiagnosticsReport::problems()] lists what went wrong, and
+/// its [std::fmt::Display] prints everything along with hints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticsReport {
+    /// The name of the driver library that was loaded, or the names that were tried.
+    pub library: Result<String, Vec<String>>,
+    /// The path of the loaded driver library, which includes its version on Linux,
+    /// e.g. `/usr/lib/x86_64-linux-gnu/libcuda.so.550.54.15`.
+    pub library_path: Option<String>,
+    /// The result of `cuInit`, if the library was loaded.
+    pub init: Option<Result<(), sys::CUresult>>,
+    /// The latest CUDA version the driver supports, as `1000 * major + 10 * minor`.
+    pub driver_version: Option<i32>,
+    /// The CUDA version cudarc was built for, in the same format as `driver_version`.
+    pub required_version: i32,
+    /// The devices the driver reports.
+    pub devices: Vec<DeviceSummary>,
+    /// The version of the kernel module, e.g. `550.54.15`. `None` if it isn't loaded, or
+    /// when not on Linux.
+    pub kernel_module_version: Option<String>,
+    /// The `/dev/nvidia*` device nodes. `None` when not on Linux.
+    pub device_nodes: Option<Vec<DeviceNode>>,
+}
+
+/// A device enumerated by [diagnose()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSummary {
+    pub ordinal: usize,
+    pub name: String,
+    pub compute_capability: (i32, i32),
+    pub total_mem: usize,
+}
+
+/// A `/dev/nvidia*` node checked by [diagnose()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceNode {
+    pub path: String,
+    /// Why the node couldn't be opened for reading and writing, if it couldn't.
+    pub error: Option<std::io::ErrorKind>,
+}
+
+/// Something [diagnose()] found wrong, see [DiagnosticsReport::problems()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// None of the driver library names could be loaded.
+    LibraryNotFound,
+    /// `cuInit` failed.
+    InitFailed(sys::CUresult),
+    /// The driver supports an older CUDA version than cudarc was built for.
+    DriverTooOld { driver: i32, required: i32 },
+    /// `/proc/driver/nvidia/version` doesn't exist, so the kernel module isn't loaded.
+    KernelModuleNotLoaded,
+    /// There are no `/dev/nvidia*` nodes.
+    NoDeviceNodes,
+    /// A `/dev/nvidia*` node can't be opened.
+    DeviceNodeInaccessible {
+        path: String,
+        error: std::io::ErrorKind,
+    },
+    /// The driver library and the kernel module are from different driver installs.
+    VersionMismatch {
+        library: String,
+        kernel_module: String,
+    },
+    /// The driver initialized, but reports no devices.
+    NoDevices,
+}
+
+impl DiagnosticsReport {
+    /// The version of the driver library, parsed from [DiagnosticsReport::library_path].
+    pub fn library_version(&self) -> Option<&str> {
+        self.library_path.as_deref().and_then(parse_library_version)
+    }
+
+    /// Everything that went wrong, most likely cause first.
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        if self.library.is_err() {
+            problems.push(Problem::LibraryNotFound);
+        }
+        if let Some(Err(code)) = self.init {
+            problems.push(Problem::InitFailed(code));
+        }
+        if let Some(nodes) = &self.device_nodes {
+            if self.kernel_module_version.is_none() {
+                problems.push(Problem::KernelModuleNotLoaded);
+            }
+            if nodes.is_empty() {
+                problems.push(Problem::NoDeviceNodes);
+            }
+            for node in nodes {
+                if let Some(error) = node.error {
+                    problems.push(Problem::DeviceNodeInaccessible {
+                        path: node.path.clone(),
+                        error,
+                    });
+                }
+            }
+        }
+        if let (Some(library), Some(kernel_module)) =
+            (self.library_version(), &self.kernel_module_version)
+        {
+            if library != kernel_module {
+                problems.push(Problem::VersionMismatch {
+                    library: library.into(),
+                    kernel_module: kernel_module.clone(),
+                });
+            }
+        }
+        if let Some(driver) = self.driver_version {
+            if driver < self.required_version {
+                problems.push(Problem::DriverTooOld {
+                    driver,
+                    required: self.required_version,
+                });
+            }
+        }
+        if self.init == Some(Ok(())) && self.devices.is_empty() {
+            problems.push(Problem::NoDevices);
+        }
+        problems
+    }
+
+    /// Whether [DiagnosticsReport::problems()] is empty.
+    pub fn is_ok(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+impl Problem {
+    /// What to do about it.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::LibraryNotFound => {
+                "install the NVIDIA driver, or add the directory containing libcuda to LD_LIBRARY_PATH"
+            }
+            Self::InitFailed(sys::CUresult::CUDA_ERROR_NO_DEVICE) => {
+                "no CUDA capable device was found, check CUDA_VISIBLE_DEVICES and that the device shows up in nvidia-smi"
+            }
+            Self::InitFailed(sys::CUresult::CUDA_ERROR_COMPAT_NOT_SUPPORTED_ON_DEVICE) => {
+                "the forward compatibility libcuda in use doesn't support this device, remove cuda-compat from LD_LIBRARY_PATH"
+            }
+            Self::InitFailed(sys::CUresult::CUDA_ERROR_SYSTEM_DRIVER_MISMATCH) => {
+                "libcuda doesn't match the kernel module, reinstall the driver or reboot after updating it"
+            }
+            Self::InitFailed(_) => {
+                "check that the kernel module is loaded and that this user can open /dev/nvidia*"
+            }
+            Self::DriverTooOld { .. } => {
+                "update the driver, or build cudarc for an older CUDA version with a cuda-* feature"
+            }
+            Self::KernelModuleNotLoaded => {
+                "load the kernel module with `modprobe nvidia`, or reboot after installing the driver"
+            }
+            Self::NoDeviceNodes => {
+                "the device nodes are created when the kernel module is loaded, e.g. by running nvidia-smi as root; in a container, pass them through"
+            }
+            Self::DeviceNodeInaccessible { .. } => {
+                "add this user to the group owning the node (often `video`), or fix its permissions"
+            }
+            Self::VersionMismatch { .. } => {
+                "the driver was updated without reloading the kernel module, reboot or reload it"
+            }
+            Self::NoDevices => "check CUDA_VISIBLE_DEVICES",
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LibraryNotFound => write!(f, "the driver library could not be loaded"),
+            Self::InitFailed(code) => write!(f, "cuInit failed with {code:?}"),
+            Self::DriverTooOld { driver, required } => write!(
+                f,
+                "the driver supports CUDA {}, but cudarc was built for CUDA {}",
+                format_version(*driver),
+                format_version(*required)
+            ),
+            Self::KernelModuleNotLoaded => write!(f, "the nvidia kernel module is not loaded"),
+            Self::NoDeviceNodes => write!(f, "there are no /dev/nvidia* device nodes"),
+            Self::DeviceNodeInaccessible { path, error } => {
+                write!(f, "{path} can't be opened: {error}")
+            }
+            Self::VersionMismatch {
+                library,
+                kernel_module,
+            } => write!(
+                f,
+                "libcuda is version {library}, but the kernel module is version {kernel_module}"
+            ),
+            Self::NoDevices => write!(f, "the driver reports no devices"),
+        }
+    }
+}
+
+impl std::fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.library {
+            Ok(name) => write!(f, "driver library: {name}")?,
+            Err(tried) => write!(f, "driver library: not found, tried {tried:?}")?,
+        }
+        match &self.library_path {
+            Some(path) => writeln!(f, " ({path})")?,
+            None => writeln!(f)?,
+        }
+        match self.init {
+            Some(Ok(())) => writeln!(f, "cuInit: ok")?,
+            Some(Err(code)) => writeln!(f, "cuInit: {code:?}")?,
+            None => writeln!(f, "cuInit: not called")?,
+        }
+        if let Some(version) = self.driver_version {
+            writeln!(f, "driver supports: CUDA {}", format_version(version))?;
+        }
+        writeln!(
+            f,
+            "built for: CUDA {}",
+            format_version(self.required_version)
+        )?;
+        if let Some(nodes) = &self.device_nodes {
+            match &self.kernel_module_version {
+                Some(version) => writeln!(f, "kernel module: {version}")?,
+                None => writeln!(f, "kernel module: not loaded")?,
+            }
+            write!(f, "device nodes:")?;
+            if nodes.is_empty() {
+                write!(f, " none")?;
+            }
+            for node in nodes {
+                match node.error {
+                    None => write!(f, " {} (ok)", node.path)?,
+                    Some(error) => write!(f, " {} ({error})", node.path)?,
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "devices: {}", self.devices.len())?;
+        for dev in &self.devices {
+            let (major, minor) = dev.compute_capability;
+            writeln!(
+                f,
+                "  {}: {} (sm_{major}{minor}, {} MiB)",
+                dev.ordinal,
+                dev.name,
+                dev.total_mem >> 20
+            )?;
+        }
+        let problems = self.problems();
+        if problems.is_empty() {
+            write!(f, "no problems found")
+        } else {
+            write!(f, "problems:")?;
+            for problem in problems {
+                write!(f, "\n  - {problem}\n    hint: {}", problem.hint())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn format_version(version: i32) -> String {
+    std::format!("{}.{}", version / 1000, (version % 1000) / 10)
+}
+
+/// The version at the end of a path like `libcuda.so.550.54.15`.
+fn parse_library_version(path: &str) -> Option<&str> {
+    let (_, version) = path.rsplit_once(".so.")?;
+    (version.contains('.') && version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        .then_some(version)
+}
+
+/// The version in the `NVRM version:` line of `/proc/driver/nvidia/version`, e.g.
+/// `NVRM version: NVIDIA UNIX x86_64 Kernel Module  550.54.15  Tue Mar  5 22:23:56 UTC 2024`.
+fn parse_kernel_module_version(contents: &str) -> Option<String> {
+    let line = contents
+        .lines()
+        .find(|line| line.starts_with("NVRM version:"))?;
+    line.split_whitespace()
+        .find(|word| {
+            word.contains('.')
+                && word.chars().next().is_some_and(|c| c.is_ascii_digit())
+                && word.chars().all(|c| c.is_ascii_digit() || c == '.')
+        })
+        .map(String::from)
+}
+
+/// Where [collect()] gets its information from, so failures can be mocked.
+trait Probe {
+    fn load_library(&self) -> Result<String, Vec<String>>;
+    /// The path of the file behind `name`, the library returned by [Probe::load_library()].
+    fn library_path(&self, name: &str) -> Option<String>;
+    fn init(&self) -> Result<(), DriverError>;
+    fn driver_version(&self) -> Result<i32, DriverError>;
+    fn devices(&self) -> Result<Vec<DeviceSummary>, DriverError>;
+    /// The contents of `/proc/driver/nvidia/version`, `None` if it can't be read.
+    fn kernel_module_info(&self) -> Option<String>;
+    /// `None` when there are no device nodes to check on this platform.
+    fn device_nodes(&self) -> Option<Vec<DeviceNode>>;
+}
+
+fn collect(probe: &impl Probe) -> DiagnosticsReport {
+    let major: i32 = env!("CUDA_MAJOR_VERSION").parse().unwrap_or(0);
+    let minor: i32 = env!("CUDA_MINOR_VERSION").parse().unwrap_or(0);
+    let mut report = DiagnosticsReport {
+        library: probe.load_library(),
+        library_path: None,
+        init: None,
+        driver_version: None,
+        required_version: 1000 * major + 10 * minor,
+        devices: Vec::new(),
+        kernel_module_version: None,
+        device_nodes: probe.device_nodes(),
+    };
+    if report.device_nodes.is_some() {
+        report.kernel_module_version = probe
+            .kernel_module_info()
+            .as_deref()
+            .and_then(parse_kernel_module_version);
+    }
+    let Ok(name) = &report.library else {
+        return report;
+    };
+    report.library_path = probe.library_path(name);
+    // the version is available even if cuInit fails
+    report.driver_version = probe.driver_version().ok();
+    let init = probe.init();
+    report.init = Some(init.as_ref().map(|_| ()).map_err(|e| e.code()));
+    if init.is_ok() {
+        report.devices = probe.devices().unwrap_or_default();
+    }
+    report
+}
+
+struct System;
+
+impl Probe for System {
+    fn load_library(&self) -> Result<String, Vec<String>> {
+        // the names tried by sys::lib(), which panics if none of them load
+        let choices = ["cuda", "nvcuda"];
+        let names: Vec<String> = choices
+            .iter()
+            .map(|choice| {
+                libloading::library_filename(choice)
+                    .to_string_lossy()
+                    .into()
+            })
+            .collect();
+        for name in &names {
+            if unsafe { sys::Lib::new(name) }.is_ok() {
+                return Ok(name.clone());
+            }
+        }
+        Err(names)
+    }
+
+    fn library_path(&self, name: &str) -> Option<String> {
+        // mapped while the maps are read, in case nothing else has loaded it
+        let _lib = unsafe { libloading::Library::new(name) }.ok()?;
+        // the real file, not the libcuda.so.1 symlink that was loaded
+        let maps = std::fs::read_to_string("/proc/self/maps").ok()?;
+        maps.lines()
+            .filter_map(|line| line.split_whitespace().nth(5))
+            .find(|path| path.contains("libcuda.so"))
+            .map(String::from)
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        result::init()
+    }
+
+    fn driver_version(&self) -> Result<i32, DriverError> {
+        result::driver_version()
+    }
+
+    fn devices(&self) -> Result<Vec<DeviceSummary>, DriverError> {
+        use sys::CUdevice_attribute::*;
+        let count = result::device::get_count()?;
+        let mut devices = Vec::with_capacity(count as usize);
+        for ordinal in 0..count {
+            let dev = result::device::get(ordinal)?;
+            let (major, minor, total_mem) = unsafe {
+                (
+                    result::device::get_attribute(
+                        dev,
+                        CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR,
+                    )?,
+                    result::device::get_attribute(
+                        dev,
+                        CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR,
+                    )?,
+                    result::device::total_mem(dev)?,
+                )
+            };
+            devices.push(DeviceSummary {
+                ordinal: ordinal as usize,
+                name: result::device::get_name(dev)?,
+                compute_capability: (major, minor),
+                total_mem,
+            });
+        }
+        Ok(devices)
+    }
+
+    fn kernel_module_info(&self) -> Option<String> {
+        std::fs::read_to_string("/proc/driver/nvidia/version").ok()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn device_nodes(&self) -> Option<Vec<DeviceNode>> {
+        let mut nodes: Vec<DeviceNode> = std::fs::read_dir("/dev")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("nvidia"))
+            .filter(|entry| entry.file_type().is_ok_and(|t| !t.is_dir()))
+            .map(|entry| {
+                let path = entry.path();
+                let error = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(&path)
+                    .err()
+                    .map(|e| e.kind());
+                DeviceNode {
+                    path: path.to_string_lossy().into(),
+                    error,
+                }
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.path.cmp(&b.path));
+        Some(nodes)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn device_nodes(&self) -> Option<Vec<DeviceNode>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::ToString, vec};
+
+    const NVRM: &str = "NVRM version: NVIDIA UNIX x86_64 Kernel Module  550.54.15  Tue Mar  5 22:23:56 UTC 2024\nGCC version:  gcc version 12.2.0 (Debian 12.2.0-14)\n";
+
+    #[derive(Clone)]
+    struct Mock {
+        library: Result<String, Vec<String>>,
+        library_path: Option<String>,
+        init: Result<(), DriverError>,
+        driver_version: i32,
+        devices: Vec<DeviceSummary>,
+        kernel_module_info: Option<String>,
+        device_nodes: Option<Vec<DeviceNode>>,
+    }
+
+    impl Default for Mock {
+        fn default() -> Self {
+            Self {
+                library: Ok("libcuda.so".into()),
+                library_path: Some("/usr/lib/x86_64-linux-gnu/libcuda.so.550.54.15".into()),
+                init: Ok(()),
+                driver_version: 12040,
+                devices: vec![DeviceSummary {
+                    ordinal: 0,
+                    name: "NVIDIA A100".into(),
+                    compute_capability: (8, 0),
+                    total_mem: 40 << 30,
+                }],
+                kernel_module_info: Some(NVRM.into()),
+                device_nodes: Some(vec![
+                    DeviceNode {
+                        path: "/dev/nvidia0".into(),
+                        error: None,
+                    },
+                    DeviceNode {
+                        path: "/dev/nvidiactl".into(),
+                        error: None,
+                    },
+                ]),
+            }
+        }
+    }
+
+    impl Probe for Mock {
+        fn load_library(&self) -> Result<String, Vec<String>> {
+            self.library.clone()
+        }
+        fn library_path(&self, _name: &str) -> Option<String> {
+            self.library_path.clone()
+        }
+        fn init(&self) -> Result<(), DriverError> {
+            self.init.clone()
+        }
+        fn driver_version(&self) -> Result<i32, DriverError> {
+            Ok(self.driver_version)
+        }
+        fn devices(&self) -> Result<Vec<DeviceSummary>, DriverError> {
+            Ok(self.devices.clone())
+        }
+        fn kernel_module_info(&self) -> Option<String> {
+            self.kernel_module_info.clone()
+        }
+        fn device_nodes(&self) -> Option<Vec<DeviceNode>> {
+            self.device_nodes.clone()
+        }
+    }
+
+    /// A report without the build's CUDA version, which depends on the features.
+    fn collect_mock(mock: Mock) -> DiagnosticsReport {
+        let mut report = collect(&mock);
+        report.required_version = 11040;
+        report
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        assert_eq!(
+            parse_kernel_module_version(NVRM).as_deref(),
+            Some("550.54.15")
+        );
+        assert_eq!(
+            parse_kernel_module_version(
+                "NVRM version: NVIDIA UNIX Open Kernel Module for x86_64  535.129.03  Release Build"
+            )
+            .as_deref(),
+            Some("535.129.03")
+        );
+        assert_eq!(parse_kernel_module_version("garbage"), None);
+        assert_eq!(
+            parse_library_version("/usr/lib64/libcuda.so.550.54.15"),
+            Some("550.54.15")
+        );
+        assert_eq!(parse_library_version("/usr/lib64/libcuda.so.1"), None);
+        assert_eq!(format_version(12040), "12.4");
+    }
+
+    #[test]
+    fn test_diagnose_healthy() {
+        let report = collect_mock(Mock::default());
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.library_version(), Some("550.54.15"));
+        let s = report.to_string();
+        assert!(s.contains("cuInit: ok"), "{s}");
+        assert!(s.contains("0: NVIDIA A100 (sm_80, 40960 MiB)"), "{s}");
+        assert!(s.ends_with("no problems found"), "{s}");
+    }
+
+    #[test]
+    fn test_diagnose_library_not_found() {
+        let report = collect_mock(Mock {
+            library: Err(vec!["libcuda.so".into()]),
+            kernel_module_info: None,
+            device_nodes: Some(vec![]),
+            ..Default::default()
+        });
+        assert_eq!(report.init, None);
+        assert_eq!(report.driver_version, None);
+        assert_eq!(
+            report.problems(),
+            [
+                Problem::LibraryNotFound,
+                Problem::KernelModuleNotLoaded,
+                Problem::NoDeviceNodes
+            ]
+        );
+        let s = report.to_string();
+        assert!(s.contains("not found, tried [\"libcuda.so\"]"), "{s}");
+        assert!(s.contains("LD_LIBRARY_PATH"), "{s}");
+    }
+
+    #[test]
+    fn test_diagnose_permission_denied() {
+        let mut mock = Mock {
+            init: Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_UNKNOWN)),
+            ..Default::default()
+        };
+        mock.device_nodes.as_mut().unwrap()[1].error = Some(std::io::ErrorKind::PermissionDenied);
+        let report = collect_mock(mock);
+        assert!(report.devices.is_empty());
+        assert_eq!(
+            report.problems(),
+            [
+                Problem::InitFailed(sys::CUresult::CUDA_ERROR_UNKNOWN),
+                Problem::DeviceNodeInaccessible {
+                    path: "/dev/nvidiactl".into(),
+                    error: std::io::ErrorKind::PermissionDenied
+                }
+            ]
+        );
+        let s = report.to_string();
+        assert!(s.contains("/dev/nvidiactl (permission denied)"), "{s}");
+        assert!(s.contains("add this user to the group"), "{s}");
+    }
+
+    #[test]
+    fn test_diagnose_version_mismatch() {
+        let report = collect_mock(Mock {
+            init: Err(DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_SYSTEM_DRIVER_MISMATCH,
+            )),
+            library_path: Some("/usr/lib64/libcuda.so.550.67".into()),
+            driver_version: 11020,
+            ..Default::default()
+        });
+        assert_eq!(
+            report.problems(),
+            [
+                Problem::InitFailed(sys::CUresult::CUDA_ERROR_SYSTEM_DRIVER_MISMATCH),
+                Problem::VersionMismatch {
+                    library: "550.67".into(),
+                    kernel_module: "550.54.15".into()
+                },
+                Problem::DriverTooOld {
+                    driver: 11020,
+                    required: 11040
+                }
+            ]
+        );
+        let s = report.to_string();
+        assert!(
+            s.contains("the driver supports CUDA 11.2, but cudarc was built for CUDA 11.4"),
+            "{s}"
+        );
+    }
+
+    #[test]
+    fn test_diagnose_no_devices() {
+        let report = collect_mock(Mock {
+            devices: vec![],
+            device_nodes: None,
+            ..Default::default()
+        });
+        // not on linux, so the kernel module isn't checked
+        assert_eq!(report.kernel_module_version, None);
+        assert_eq!(report.problems(), [Problem::NoDevices]);
+        assert!(!report.to_string().contains("kernel module"));
+    }
+
+    #[test]
+    fn test_diagnose_system() {
+        // whatever the machine is like, collecting the report must not panic
+        let report = diagnose();
+        let _ = report.to_string();
+        if report.init == Some(Ok(())) {
+            assert_eq!(
+                report.devices.len() as i32,
+                result::device::get_count().unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::driver::{
+    result::{self, NotExportable},
+    sys,
+};
+
+use super::alloc::DeviceRepr;
+use super::allocator::DeviceAllocator;
+use super::core::{CudaDevice, CudaSlice};
+use super::device_ptr::DeviceSlice;
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::panic::Location;
+use std::sync::Arc;
+
+/// The size of a host page, which dma-buf exports must be aligned to.
+fn page_size() -> usize {
+    extern "C" {
+        fn sysconf(name: core::ffi::c_int) -> core::ffi::c_long;
+    }
+    const SC_PAGESIZE: core::ffi::c_int = 30;
+    match unsafe { sysconf(SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+/// What [CudaDevice::alloc_exportable()] rounds allocations with `cuMemAlloc` up to, the
+/// size of a large page of device memory. The driver aligns allocations of whole large
+/// pages to them, so they start on a host page boundary too.
+const EXPORTABLE_ALIGN: usize = 2 << 20;
+
+/// Allocates and frees the slices of [CudaDevice::alloc_exportable()], so that they are
+/// freed the way they were allocated rather than with `cuMemFreeAsync` like the slices of
+/// the device's memory pool.
+#[derive(Debug)]
+enum ExportableAllocator {
+    /// Mapped with the virtual memory management API, in multiples of `granularity` bytes.
+    Vmm {
+        prop: sys::CUmemAllocationProp,
+        granularity: usize,
+    },
+    /// Allocated with `cuMemAlloc`, in multiples of [EXPORTABLE_ALIGN].
+    Malloc,
+}
+
+unsafe impl Send for ExportableAllocator {}
+unsafe impl Sync for ExportableAllocator {}
+
+impl ExportableAllocator {
+    /// Maps memory with the virtual memory management API if `device` supports GPUDirect
+    /// RDMA on such memory, so that it can be registered with a NIC too.
+    fn new(device: &CudaDevice) -> Result<Self, DriverError> {
+        use sys::CUdevice_attribute::*;
+        let vmm = device.attribute(CU_DEVICE_ATTRIBUTE_VIRTUAL_ADDRESS_MANAGEMENT_SUPPORTED)?;
+        let rdma = device.attribute(CU_DEVICE_ATTRIBUTE_GPU_DIRECT_RDMA_WITH_CUDA_VMM_SUPPORTED)?;
+        if vmm == 0 || rdma == 0 {
+            return Ok(Self::Malloc);
+        }
+        let mut prop: sys::CUmemAllocationProp = unsafe { std::mem::zeroed() };
+        prop.type_ = sys::CUmemAllocationType::CU_MEM_ALLOCATION_TYPE_PINNED;
+        prop.location.type_ = sys::CUmemLocationType::CU_MEM_LOCATION_TYPE_DEVICE;
+        prop.location.id = device.cu_device;
+        prop.allocFlags.gpuDirectRDMACapable = 1;
+        let granularity = result::virtual_memory::granularity(&prop)?;
+        Ok(Self::Vmm { prop, granularity })
+    }
+
+    /// The number of bytes actually allocated for `bytes`.
+    fn rounded(&self, bytes: usize) -> usize {
+        let align = match self {
+            Self::Vmm { granularity, .. } => *granularity,
+            Self::Malloc => EXPORTABLE_ALIGN,
+        };
+        bytes.max(1).next_multiple_of(align)
+    }
+}
+
+impl DeviceAllocator for ExportableAllocator {
+    fn alloc(&self, bytes: usize, _stream: sys::CUstream) -> Result<sys::CUdeviceptr, DriverError> {
+        let size = self.rounded(bytes);
+        let prop = match self {
+            Self::Vmm { prop, .. } => prop,
+            Self::Malloc => return unsafe { result::malloc_sync(size) },
+        };
+        use result::virtual_memory as vmm;
+        unsafe {
+            let handle = vmm::create(size, prop)?;
+            let ptr = vmm::address_reserve(size, 0).and_then(|ptr| {
+                let mapped = vmm::map(ptr, size, handle).and_then(|()| {
+                    let res = vmm::set_read_write(ptr, size, prop.location.id);
+                    if res.is_err() {
+                        vmm::unmap(ptr, size).or_handle("CudaDevice::alloc_exportable");
+                    }
+                    res
+                });
+                if mapped.is_err() {
+                    vmm::address_free(ptr, size).or_handle("CudaDevice::alloc_exportable");
+                }
+                mapped.map(|()| ptr)
+            });
+            // the mapping keeps the memory alive until it is unmapped
+            vmm::release(handle).or_handle("CudaDevice::alloc_exportable");
+            ptr
+        }
+    }
+
+    unsafe fn free(
+        &self,
+        ptr: sys::CUdeviceptr,
+        bytes: usize,
+        stream: sys::CUstream,
+    ) -> Result<(), DriverError> {
+        match self {
+            Self::Vmm { .. } => {
+                // unmapping doesn't wait for the work using the memory
+                result::stream::synchronize(stream)?;
+                let size = self.rounded(bytes);
+                result::virtual_memory::unmap(ptr, size)?;
+                result::virtual_memory::address_free(ptr, size)
+            }
+            Self::Malloc => result::free_sync(ptr),
+        }
+    }
+}
+
+impl CudaDevice {
+    /// Allocates memory that can be exported with [CudaSlice::export_dmabuf()], instead of
+    /// from the device's memory pool.
+    ///
+    /// If the device supports GPUDirect RDMA on memory of the virtual memory management API
+    /// (`cuMemCreate`), the memory is mapped with it, so that a NIC can register it too.
+    /// Otherwise it is allocated with `cuMemAlloc`, rounded up to a multiple of 2 MiB. Either
+    /// way the slice is freed the way it was allocated, which waits for the device's stream.
+    ///
+    /// # Safety
+    /// This is unsafe because the device memory is unset after this call.
+    #[track_caller]
+    pub unsafe fn alloc_exportable<T: DeviceRepr>(
+        self: &Arc<Self>,
+        len: usize,
+    ) -> Result<CudaSlice<T>, DriverError> {
+        let num_bytes = len
+            .checked_mul(std::mem::size_of::<T>())
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))?;
+        let _ctx = self.bind_scoped()?;
+        let allocator = ExportableAllocator::new(self)?;
+        let cu_device_ptr = allocator.alloc(num_bytes, self.stream)?;
+        Ok(self.tracked(CudaSlice {
+            cu_device_ptr,
+            len,
+            device: self.clone(),
+            host_buf: None,
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator: Some(Arc::new(allocator)),
+        }))
+    }
+}
+
+impl<T> CudaSlice<T> {
+    /// Exports the slice as a dma-buf, e.g. for a NIC doing GPUDirect RDMA or a media
+    /// encoder to read. The exported range is rounded up to the host page size.
+    ///
+    /// The slice must have been allocated with [CudaDevice::alloc_exportable()], or
+    /// otherwise with `cuMemAlloc` or `cuMemCreate` at a page boundary. Returns
+    /// [DriverError::NotExportable] if it wasn't, or if the device doesn't support dma-bufs.
+    /// The memory is only kept alive by the slice, not by the returned fd.
+    pub fn export_dmabuf(&self) -> Result<OwnedFd, DriverError> {
+        let supported = self
+            .device
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_DMA_BUF_SUPPORTED)?;
+        if supported == 0 {
+            return Err(NotExportable::DeviceUnsupported.into());
+        }
+        let _ctx = self.device.bind_scoped()?;
+        let pool: sys::CUmemoryPool = unsafe {
+            result::pointer::get_attribute(
+                self.cu_device_ptr,
+                sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_MEMPOOL_HANDLE,
+            )
+        }?;
+        if !pool.is_null() {
+            return Err(NotExportable::PoolAllocation.into());
+        }
+        let page_size = page_size();
+        if !self.cu_device_ptr.is_multiple_of(page_size as u64) {
+            return Err(NotExportable::Misaligned { page_size }.into());
+        }
+        let size = self.num_bytes().max(1).next_multiple_of(page_size);
+        let fd = unsafe { result::get_dmabuf_fd(self.cu_device_ptr, size) }?;
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::safe::{DevicePtr, ExternalMemoryType};
+    use std::fs::File;
+    use std::io::{Seek, SeekFrom};
+
+    #[test]
+    fn test_export_dmabuf() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut a = unsafe { dev.alloc_exportable::<f32>(1000) }.unwrap();
+        dev.htod_sync_copy_into(&[1.0; 1000], &mut a).unwrap();
+        let supported = dev
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_DMA_BUF_SUPPORTED)
+            .unwrap();
+        if supported == 0 {
+            assert_eq!(
+                a.export_dmabuf().unwrap_err(),
+                DriverError::NotExportable(NotExportable::DeviceUnsupported)
+            );
+            return;
+        }
+
+        let mut file = File::from(a.export_dmabuf().unwrap());
+        // the size of a dma-buf is only available by seeking to its end
+        let size = file.seek(SeekFrom::End(0)).unwrap() as usize;
+        assert_eq!(size, 4000usize.next_multiple_of(page_size()));
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), [1.0; 1000]);
+
+        if dev.is_async {
+            let b = dev.alloc_zeros::<f32>(1000).unwrap();
+            assert_eq!(
+                b.export_dmabuf().unwrap_err(),
+                DriverError::NotExportable(NotExportable::PoolAllocation)
+            );
+        }
+    }
+
+    #[test]
+    fn test_export_dmabuf_round_trip() {
+        let dev = CudaDevice::new(0).unwrap();
+        let supported = dev
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_DMA_BUF_SUPPORTED)
+            .unwrap();
+        if supported == 0 {
+            return;
+        }
+        let data: std::vec::Vec<u32> = (0..1000).collect();
+        let mut a = unsafe { dev.alloc_exportable::<u32>(data.len()) }.unwrap();
+        dev.htod_sync_copy_into(&data, &mut a).unwrap();
+
+        let file = File::from(a.export_dmabuf().unwrap());
+        let ext =
+            unsafe { dev.import_external_memory_auto(file, ExternalMemoryType::FileDescriptor) }
+                .unwrap();
+        let buf = ext.map_range(0..4000).unwrap();
+        let b = dev.alloc_zeros::<u32>(data.len()).unwrap();
+        unsafe { result::memcpy_dtod_sync(*b.device_ptr(), *buf.device_ptr(), 4000) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&b).unwrap(), data);
+        drop(buf);
+        drop(a);
+    }
+
+    #[test]
+    fn test_alloc_exportable_overflow() {
+        let dev = CudaDevice::new(0).unwrap();
+        let res = unsafe { dev.alloc_exportable::<u64>(usize::MAX / 2) };
+        assert_eq!(
+            res.unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::CudaDevice;
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use std::sync::Arc;
+
+/// CUDA source defining `CUDARC_ASSERT(flag, cond, code)`, to prepend to kernel source
+/// compiled with nvrtc. The kernel takes the flag as an `unsigned int *` parameter, passed
+/// as `&DeviceErrorFlag` when launching.
+///
+/// When `cond` is false, the first failing thread stores the non-zero `code` along with
+/// `__LINE__`, `blockIdx` and `threadIdx`, which [DeviceErrorFlag::check()] returns as a
+/// [DeviceAssert]. Unlike `assert()`, the kernel keeps running.
+///
+/// ```cuda
+/// extern "C" __global__ void kernel(const float *x, size_t n, unsigned int *flag) {
+///     size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+///     if (i < n) {
+///         CUDARC_ASSERT(flag, x[i] >= 0.0f, 1);
+///     }
+/// }
+/// ```
+pub const DEVICE_ASSERT_SRC: &str = r#"
+__device__ __forceinline__ void cudarc_set_error(unsigned int *flag, unsigned int code, unsigned int line) {
+    if (atomicCAS(flag, 0u, code) == 0u) {
+        flag[1] = line;
+        flag[2] = blockIdx.x; flag[3] = blockIdx.y; flag[4] = blockIdx.z;
+        flag[5] = threadIdx.x; flag[6] = threadIdx.y; flag[7] = threadIdx.z;
+        __threadfence_system();
+    }
+}
+#define CUDARC_ASSERT(flag, cond, code) \
+    do { if (!(cond)) { cudarc_set_error((flag), (code), __LINE__); } } while (0)
+"#;
+
+const FLAG_WORDS: usize = 8;
+
+/// The first failed `CUDARC_ASSERT` recorded in a [DeviceErrorFlag].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceAssert {
+    /// The `code` passed to `CUDARC_ASSERT`.
+    pub code: u32,
+    /// The source line of the `CUDARC_ASSERT`.
+    pub line: u32,
+    /// `blockIdx` of the failing thread.
+    pub block: (u32, u32, u32),
+    /// `threadIdx` of the failing thread.
+    pub thread: (u32, u32, u32),
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for DeviceAssert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "device assertion failed with code {} at line {} in block {:?} thread {:?}",
+            self.code, self.line, self.block, self.thread
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DeviceAssert {}
+
+/// A flag kernels set with `CUDARC_ASSERT` (see [DEVICE_ASSERT_SRC]) to report failures
+/// back to the host. Create with [CudaDevice::alloc_error_flag()].
+///
+/// The flag lives in mapped page-locked host memory, so the device writes straight into
+/// memory the host can read, without a copy.
+#[derive(Debug)]
+pub struct DeviceErrorFlag {
+    host_ptr: *mut u32,
+    dptr: sys::CUdeviceptr,
+    device: Arc<CudaDevice>,
+}
+
+unsafe impl Send for DeviceErrorFlag {}
+unsafe impl Sync for DeviceErrorFlag {}
+
+impl CudaDevice {
+    /// Allocates a cleared [DeviceErrorFlag].
+    pub fn alloc_error_flag(self: &Arc<Self>) -> Result<DeviceErrorFlag, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let num_bytes = FLAG_WORDS * std::mem::size_of::<u32>();
+        let host_ptr = unsafe { result::malloc_host(num_bytes, sys::CU_MEMHOSTALLOC_DEVICEMAP) }?;
+        let dptr = match unsafe { result::host_get_device_pointer(host_ptr) } {
+            Ok(dptr) => dptr,
+            Err(e) => {
+                unsafe { result::free_host(host_ptr) }.or_handle("CudaDevice::alloc_error_flag");
+                return Err(e);
+            }
+        };
+        let flag = DeviceErrorFlag {
+            host_ptr: host_ptr as *mut u32,
+            dptr,
+            device: self.clone(),
+        };
+        flag.reset();
+        Ok(flag)
+    }
+}
+
+impl DeviceErrorFlag {
+    /// Returns the first failed assertion since the flag was allocated or [DeviceErrorFlag::reset()].
+    ///
+    /// Kernels run asynchronously, so this only sees assertions from kernels that have completed,
+    /// e.g. after [CudaDevice::synchronize()].
+    pub fn check(&self) -> Result<(), DeviceAssert> {
+        let read = |i: usize| unsafe { std::ptr::read_volatile(self.host_ptr.add(i)) };
+        let code = read(0);
+        if code == 0 {
+            return Ok(());
+        }
+        Err(DeviceAssert {
+            code,
+            line: read(1),
+            block: (read(2), read(3), read(4)),
+            thread: (read(5), read(6), read(7)),
+        })
+    }
+
+    /// Clears the flag. Must not be called while a kernel using the flag is running.
+    pub fn reset(&self) {
+        for i in 0..FLAG_WORDS {
+            unsafe { std::ptr::write_volatile(self.host_ptr.add(i), 0) };
+        }
+    }
+
+    /// The device pointer passed to kernels.
+    pub fn device_ptr(&self) -> sys::CUdeviceptr {
+        self.dptr
+    }
+}
+
+unsafe impl DeviceRepr for &DeviceErrorFlag {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.dptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+}
+
+impl Drop for DeviceErrorFlag {
+    fn drop(&mut self) {
+        let _ctx = self.device.bind_scoped_or_handle("DeviceErrorFlag::drop");
+        unsafe { result::free_host(self.host_ptr as *mut _) }.or_handle("DeviceErrorFlag::drop");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+    use std::vec::Vec;
+
+    const SRC: &str = r#"
+extern "C" __global__ void check_positive(const float *x, size_t n, unsigned int *flag) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) {
+        CUDARC_ASSERT(flag, x[i] > 0.0f, 7);
+    }
+}
+"#;
+
+    #[test]
+    fn test_device_assert_is_reported() {
+        let src = std::format!("{DEVICE_ASSERT_SRC}{SRC}");
+        let line = src
+            .lines()
+            .position(|l| l.contains("CUDARC_ASSERT(flag, x[i]"))
+            .unwrap() as u32
+            + 1;
+
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(compile_ptx(src).unwrap(), "assert", &["check_positive"])
+            .unwrap();
+        let f = dev.get_func("assert", "check_positive").unwrap();
+        let flag = dev.alloc_error_flag().unwrap();
+        assert_eq!(flag.check(), Ok(()));
+
+        let mut host: Vec<f32> = (1..=300).map(|i| i as f32).collect();
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let cfg = LaunchConfig {
+            grid_dim: (3, 1, 1),
+            block_dim: (128, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        unsafe {
+            f.clone()
+                .launch(dev.default_stream(), cfg, (&x, host.len(), &flag))
+        }
+        .unwrap();
+        dev.synchronize().unwrap();
+        assert_eq!(flag.check(), Ok(()));
+
+        host[130] = -1.0;
+        let x = dev.htod_sync_copy(&host).unwrap();
+        unsafe { f.launch(dev.default_stream(), cfg, (&x, host.len(), &flag)) }.unwrap();
+        dev.synchronize().unwrap();
+        assert_eq!(
+            flag.check(),
+            Err(DeviceAssert {
+                code: 7,
+                line,
+                block: (1, 0, 0),
+                thread: (2, 0, 0),
+            })
+        );
+
+        flag.reset();
+        assert_eq!(flag.check(), Ok(()));
+    }
+}
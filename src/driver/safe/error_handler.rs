@@ -0,0 +1,159 @@
+use super::DriverError;
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Called with errors that can't be returned, e.g. from `Drop` impls, along with
+/// the name of the operation that failed. See [set_error_handler()].
+pub type ErrorHandler = fn(DriverError, &'static str);
+
+/// The [ErrorHandler], null until [set_error_handler()] is called.
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the [ErrorHandler] for the whole process, [log_error()] by default.
+///
+/// Failures in places that can't return an error, like freeing memory when a [super::CudaSlice]
+/// is dropped, are passed to the handler and otherwise ignored. Use [panic_on_error()] to
+/// panic instead.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// cudarc::set_error_handler(|err, op| eprintln!("{op} failed: {err:?}"));
+/// ```
+pub fn set_error_handler(handler: ErrorHandler) {
+    HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// The default [ErrorHandler], which logs the error (through `tracing` with the `tracing`
+/// feature, to stderr otherwise) and continues.
+pub fn log_error(err: DriverError, op: &'static str) {
+    #[cfg(feature = "tracing")]
+    tracing::error!(target: "cudarc::error", op, error = ?err, "unhandled driver error");
+    #[cfg(all(not(feature = "tracing"), feature = "std"))]
+    std::eprintln!("cudarc: {op} failed with {err:?}");
+    #[cfg(all(not(feature = "tracing"), not(feature = "std")))]
+    let _ = (err, op);
+}
+
+/// Logs an error of another library that can't be returned, like [log_error()] does. Only
+/// [DriverError]s go to the [ErrorHandler].
+#[cfg_attr(
+    not(any(
+        feature = "cublas",
+        feature = "cublaslt",
+        feature = "cufile",
+        feature = "nvml",
+        feature = "safetensors"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn log_library_error(err: &dyn core::fmt::Debug, op: &'static str) {
+    #[cfg(feature = "tracing")]
+    tracing::error!(target: "cudarc::error", op, error = ?err, "unhandled library error");
+    #[cfg(all(not(feature = "tracing"), feature = "std"))]
+    std::eprintln!("cudarc: {op} failed with {err:?}");
+    #[cfg(all(not(feature = "tracing"), not(feature = "std")))]
+    let _ = (err, op);
+}
+
+/// An [ErrorHandler] that panics, unless the thread is already panicking.
+pub fn panic_on_error(err: DriverError, op: &'static str) {
+    // e.g. a slice dropped while unwinding, panicking again would abort
+    #[cfg(feature = "std")]
+    if std::thread::panicking() {
+        return;
+    }
+    panic!("{op} failed with {err:?}")
+}
+
+/// Passes `err` to the current [ErrorHandler].
+pub(crate) fn handle_error(err: DriverError, op: &'static str) {
+    let handler = HANDLER.load(Ordering::Acquire);
+    let handler: ErrorHandler = if handler.is_null() {
+        log_error
+    } else {
+        // only ever set from an `ErrorHandler` in `set_error_handler`
+        unsafe { core::mem::transmute::<*mut (), ErrorHandler>(handler) }
+    };
+    handler(err, op)
+}
+
+/// Reports results that can't be returned to the [ErrorHandler].
+pub(crate) trait OrHandle {
+    type Ok;
+
+    fn or_handle(self, op: &'static str);
+
+    /// Reports the error to the [ErrorHandler] and returns `err` instead, for functions
+    /// whose error type can't hold a [DriverError].
+    #[cfg_attr(
+        not(any(
+            feature = "cublas",
+            feature = "cublaslt",
+            feature = "cudnn",
+            feature = "curand"
+        )),
+        allow(dead_code)
+    )]
+    fn or_report<E>(self, op: &'static str, err: E) -> Result<Self::Ok, E>;
+}
+
+impl<T> OrHandle for Result<T, DriverError> {
+    type Ok = T;
+
+    #[inline]
+    fn or_handle(self, op: &'static str) {
+        if let Err(err) = self {
+            handle_error(err, op)
+        }
+    }
+
+    #[inline]
+    fn or_report<E>(self, op: &'static str, err: E) -> Result<T, E> {
+        self.map_err(|e| {
+            handle_error(e, op);
+            err
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{CudaDevice, CudaSlice};
+    use std::{cell::RefCell, vec::Vec};
+
+    std::thread_local! {
+        // per thread, so errors from tests running at the same time aren't recorded
+        static ERRORS: RefCell<Vec<(DriverError, &'static str)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record_error(err: DriverError, op: &'static str) {
+        ERRORS.with_borrow_mut(|errors| errors.push((err, op)));
+    }
+
+    #[test]
+    fn test_drop_failure_calls_error_handler() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut bogus = unsafe { dev.alloc::<f32>(4) }.unwrap();
+        let real = bogus.cu_device_ptr;
+
+        set_error_handler(record_error);
+        bogus.cu_device_ptr = 0x10;
+        drop(bogus);
+        set_error_handler(log_error);
+
+        let errors = ERRORS.take();
+        assert!(errors.iter().any(|&(_, op)| op == "CudaSlice::drop"));
+        // free the allocation the slice used to own
+        drop(CudaSlice::<f32> {
+            cu_device_ptr: real,
+            len: 4,
+            device: dev.clone(),
+            host_buf: None,
+            location: core::panic::Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator: None,
+        });
+    }
+}
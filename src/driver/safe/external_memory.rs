@@ -1,15 +1,23 @@
+use core::any::Any;
 use core::mem::ManuallyDrop;
+use std::boxed::Box;
 use std::fs::File;
 use std::ops::Range;
 use std::sync::Arc;
 
-use super::{CudaDevice, DevicePtr, DeviceSlice};
+use super::error_handler::OrHandle;
+use super::{CudaDevice, CudaView, CudaViewMut, DevicePtr, DevicePtrMut, DeviceRepr, DeviceSlice};
 use crate::driver::sys::CUarray;
 use crate::driver::{result, sys, DriverError};
 
 impl CudaDevice {
     /// Import external memory from a [`File`].
     ///
+    /// On unix, [CudaDevice::import_external_memory_auto()] determines `size` from the file
+    /// for handles that support it.
+    ///
+    /// Returns [DriverError::InvalidExternalMemorySize] if `size` is 0.
+    ///
     /// # Safety
     /// `size` must be the size of the external memory in bytes.
     #[cfg(any(unix, windows))]
@@ -19,29 +27,224 @@ impl CudaDevice {
         size: u64,
         type_: ExternalMemoryType,
     ) -> Result<ExternalMemory, DriverError> {
-        self.bind_to_thread()?;
+        self.import_external_memory_with_dedicated(file, size, type_, false)
+    }
 
+    /// Import external memory from a [`File`], marking it as a dedicated allocation
+    /// when `dedicated` is true.
+    ///
+    /// Vulkan images created with a dedicated allocation (`VkMemoryDedicatedAllocateInfo`)
+    /// must be imported with `dedicated` set, otherwise mapping them may fail.
+    ///
+    /// # Safety
+    /// `size` must be the size of the external memory in bytes.
+    #[cfg(any(unix, windows))]
+    pub unsafe fn import_external_memory_with_dedicated(
+        self: &Arc<Self>,
+        file: File,
+        size: u64,
+        type_: ExternalMemoryType,
+        dedicated: bool,
+    ) -> Result<ExternalMemory, DriverError> {
         #[cfg(unix)]
-        let external_memory = unsafe {
+        let handle = {
             use std::os::fd::AsRawFd;
-            result::external_memory::import_external_memory(file.as_raw_fd(), size, type_.into())
-        }?;
+            file.as_raw_fd()
+        };
         #[cfg(windows)]
-        let external_memory = unsafe {
+        let handle = {
             use std::os::windows::io::AsRawHandle;
-            result::external_memory::import_external_memory(
-                file.as_raw_handle(),
+            file.as_raw_handle()
+        };
+        self.import_external_memory_raw(handle, size, type_, dedicated, KeepAlive::File(file))
+    }
+
+    /// Import external memory from a raw file descriptor, keeping `keep_alive` until the
+    /// [ExternalMemory] is dropped.
+    ///
+    /// Ownership of `fd` is transferred to the driver if the import succeeds. If it is owned
+    /// by a [`File`], pass it as [KeepAlive::File] so that it isn't closed twice.
+    ///
+    /// Returns [DriverError::InvalidExternalMemorySize] if `size` is 0.
+    ///
+    /// # Safety
+    /// 1. `fd` must be a valid file descriptor of the given `type_`.
+    /// 2. `size` must be the size of the external memory in bytes.
+    #[cfg(unix)]
+    pub unsafe fn import_external_memory_raw(
+        self: &Arc<Self>,
+        fd: std::os::fd::RawFd,
+        size: u64,
+        type_: ExternalMemoryType,
+        dedicated: bool,
+        keep_alive: KeepAlive,
+    ) -> Result<ExternalMemory, DriverError> {
+        self.import_external_memory_with(size, keep_alive, || unsafe {
+            result::external_memory::import_external_memory_with_dedicated(
+                fd,
                 size,
                 type_.into(),
+                dedicated,
             )
-        }?;
+        })
+    }
+
+    /// Import external memory from a raw win32 handle, keeping `keep_alive` until the
+    /// [ExternalMemory] is dropped.
+    ///
+    /// The driver doesn't take ownership of `handle`. KMT handles, e.g.
+    /// [ExternalMemoryType::WindowsKMT], don't even hold a reference to the resource, so
+    /// whatever owns it, e.g. a D3D11 resource wrapper, has to be passed as
+    /// [KeepAlive::Guard].
+    ///
+    /// Returns [DriverError::InvalidExternalMemorySize] if `size` is 0.
+    ///
+    /// # Safety
+    /// 1. `handle` must be a valid handle of the given `type_`, that stays valid as long as
+    ///    `keep_alive` is alive.
+    /// 2. `size` must be the size of the external memory in bytes.
+    #[cfg(windows)]
+    pub unsafe fn import_external_memory_raw(
+        self: &Arc<Self>,
+        handle: std::os::windows::io::RawHandle,
+        size: u64,
+        type_: ExternalMemoryType,
+        dedicated: bool,
+        keep_alive: KeepAlive,
+    ) -> Result<ExternalMemory, DriverError> {
+        self.import_external_memory_with(size, keep_alive, || unsafe {
+            result::external_memory::import_external_memory_with_dedicated(
+                handle,
+                size,
+                type_.into(),
+                dedicated,
+            )
+        })
+    }
+
+    #[cfg(any(unix, windows))]
+    fn import_external_memory_with(
+        self: &Arc<Self>,
+        size: u64,
+        keep_alive: KeepAlive,
+        import: impl FnOnce() -> Result<sys::CUexternalMemory, DriverError>,
+    ) -> Result<ExternalMemory, DriverError> {
+        if size == 0 {
+            return Err(DriverError::InvalidExternalMemorySize);
+        }
+        result::require(result::ApiGroup::ExternalResources)?;
+        let _ctx = self.bind_scoped()?;
+        let external_memory = import()?;
         Ok(ExternalMemory {
             external_memory,
             size,
             device: self.clone(),
-            _file: ManuallyDrop::new(file),
+            keep_alive,
         })
     }
+
+    /// Import external memory from a [`File`], using the size of the file.
+    ///
+    /// The size is taken from `fstat`, or from seeking to the end of the file if that is 0,
+    /// which works for memfd and dma-buf file descriptors. Opaque handles, e.g. from Vulkan's
+    /// `VK_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD_BIT`, usually can't be sized, in which case
+    /// this returns [DriverError::InvalidExternalMemorySize] and the size has to be passed to
+    /// [CudaDevice::import_external_memory()] instead.
+    ///
+    /// # Safety
+    /// The size of `file` found this way must be the size of the external memory in bytes,
+    /// as for [CudaDevice::import_external_memory()]. This holds for memfd and dma-buf file
+    /// descriptors, but not e.g. for a regular file of the wrong `type_`.
+    #[cfg(unix)]
+    pub unsafe fn import_external_memory_auto(
+        self: &Arc<Self>,
+        file: File,
+        type_: ExternalMemoryType,
+    ) -> Result<ExternalMemory, DriverError> {
+        let size = external_memory_size(&file)?;
+        self.import_external_memory(file, size, type_)
+    }
+}
+
+/// The size of the memory behind `file`, see [CudaDevice::import_external_memory_auto()].
+#[cfg(unix)]
+fn external_memory_size(file: &File) -> Result<u64, DriverError> {
+    use std::io::{Seek, SeekFrom};
+
+    let size = match file.metadata() {
+        Ok(metadata) if metadata.len() > 0 => metadata.len(),
+        _ => {
+            let mut file = file;
+            let size = file.seek(SeekFrom::End(0));
+            // dma-bufs only allow seeking back to the start
+            let _ = file.seek(SeekFrom::Start(0));
+            size.unwrap_or(0)
+        }
+    };
+    match size {
+        0 => Err(DriverError::InvalidExternalMemorySize),
+        size => Ok(size),
+    }
+}
+
+/// Flags passed to the driver when mapping a buffer onto [ExternalMemory].
+///
+/// See `CUDA_EXTERNAL_MEMORY_BUFFER_DESC::flags` in the
+/// [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/structCUDA__EXTERNAL__MEMORY__BUFFER__DESC__v1.html).
+/// The default is no flags, which is what [ExternalMemory::map_range()] uses.
+#[derive(Debug, Default, Copy, Clone, Hash, PartialEq, Eq)]
+pub struct MapFlags(pub u32);
+
+impl MapFlags {
+    /// No flags.
+    pub const NONE: Self = Self(0);
+}
+
+/// What an [ExternalMemory] or [super::ExternalSemaphore] keeps alive until it is dropped,
+/// after the import is destroyed.
+pub enum KeepAlive {
+    /// Nothing, the caller keeps the resource alive.
+    None,
+    /// The file the handle was imported from. On unix the driver owns the file descriptor
+    /// after the import, so it isn't closed again. On windows the handle is closed.
+    File(File),
+    /// Anything owning the resource, e.g. a wrapper around a D3D resource.
+    Guard(Box<dyn Any + Send>),
+}
+
+impl core::fmt::Debug for KeepAlive {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::None => f.write_str("None"),
+            Self::File(file) => f.debug_tuple("File").field(file).finish(),
+            Self::Guard(_) => f.write_str("Guard(..)"),
+        }
+    }
+}
+
+impl KeepAlive {
+    /// Releases what is kept alive, once the import has been destroyed.
+    pub(crate) fn release(&mut self) {
+        match core::mem::replace(self, Self::None) {
+            // From [CUDA docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1g52aba3a7f780157d8ba12972b2481735),
+            // when successfully importing UNIX file descriptor:
+            //
+            // > Ownership of the file descriptor is transferred to the CUDA driver when the handle is imported successfully.
+            // > Performing any operations on the file descriptor after it is imported results in undefined behavior.
+            //
+            // On the other hand, on Windows:
+            //
+            // > Ownership of this handle is not transferred to CUDA after the import operation,
+            // > so the application must release the handle using the appropriate system call.
+            //
+            // Therefore, we only close the file on Windows.
+            #[cfg(unix)]
+            Self::File(file) => {
+                let _ = ManuallyDrop::new(file);
+            }
+            keep_alive => drop(keep_alive),
+        }
+    }
 }
 
 /// An abstraction for imported external memory.
@@ -53,39 +256,30 @@ pub struct ExternalMemory {
     external_memory: sys::CUexternalMemory,
     size: u64,
     device: Arc<CudaDevice>,
-    _file: ManuallyDrop<File>,
+    keep_alive: KeepAlive,
 }
 
 impl Drop for ExternalMemory {
     fn drop(&mut self) {
-        self.device.bind_to_thread().unwrap();
-
-        unsafe { result::external_memory::destroy_external_memory(self.external_memory) }.unwrap();
-
-        // From [CUDA docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html#group__CUDA__EXTRES__INTEROP_1g52aba3a7f780157d8ba12972b2481735),
-        // when successfully importing UNIX file descriptor:
-        //
-        // > Ownership of the file descriptor is transferred to the CUDA driver when the handle is imported successfully.
-        // > Performing any operations on the file descriptor after it is imported results in undefined behavior.
-        //
-        // On the other hand, on Windows:
-        //
-        // > Ownership of this handle is not transferred to CUDA after the import operation,
-        // > so the application must release the handle using the appropriate system call.
-        //
-        // Therefore, we manually drop the file when we are on Windows.
-        #[cfg(windows)]
-        unsafe {
-            ManuallyDrop::<File>::drop(&mut self._file)
-        };
+        let _ctx = self.device.bind_scoped_or_handle("ExternalMemory::drop");
+
+        unsafe { result::external_memory::destroy_external_memory(self.external_memory) }
+            .or_handle("ExternalMemory::drop");
+
+        self.keep_alive.release();
     }
 }
 
 impl ExternalMemory {
     /// Map the whole external memory to get mapped buffer.
     pub fn map_all(self) -> Result<MappedBuffer, DriverError> {
+        self.map_all_with_flags(MapFlags::NONE)
+    }
+
+    /// Same as [ExternalMemory::map_all()], passing `flags` to the driver.
+    pub fn map_all_with_flags(self, flags: MapFlags) -> Result<MappedBuffer, DriverError> {
         let size = self.size as usize;
-        self.map_range(0..size)
+        self.map_range_with_flags(0..size, flags)
     }
 
     /// Map a range of the external memory to a mapped buffer.
@@ -98,17 +292,32 @@ impl ExternalMemory {
     /// This function will panic if the range is invalid,
     /// such as when the start or end is larger than the size.
     pub fn map_range(self, range: Range<usize>) -> Result<MappedBuffer, DriverError> {
+        self.map_range_with_flags(range, MapFlags::NONE)
+    }
+
+    /// Same as [ExternalMemory::map_range()], passing `flags` to the driver.
+    ///
+    /// # Panics
+    /// This function will panic if the range is invalid,
+    /// such as when the start or end is larger than the size.
+    pub fn map_range_with_flags(
+        self,
+        range: Range<usize>,
+        flags: MapFlags,
+    ) -> Result<MappedBuffer, DriverError> {
         assert!(range.start as u64 <= self.size);
         assert!(range.end as u64 <= self.size);
         let device_ptr = unsafe {
-            result::external_memory::get_mapped_buffer(
+            result::external_memory::get_mapped_buffer_with_flags(
                 self.external_memory,
                 range.start as u64,
                 range.len() as u64,
+                flags.0,
             )
         }?;
         Ok(MappedBuffer {
             device_ptr,
+            offset: range.start,
             len: range.len(),
             external_memory: self,
         })
@@ -118,7 +327,7 @@ impl ExternalMemory {
         &self,
         width: usize,
         height: usize,
-    ) -> Result<MipMappedArray, DriverError> {
+    ) -> Result<MipMappedArray<'_>, DriverError> {
         let mipmapped_array = unsafe {
             result::external_memory::get_mapped_mipmapped_array(
                 self.external_memory,
@@ -140,17 +349,32 @@ impl ExternalMemory {
 ///
 /// This struct can be created via [`ExternalMemory::map_range`] or [`ExternalMemory::map_all`].
 /// The underlying mapped buffer will be freed when this struct is dropped.
-#[derive(Debug)]
+///
+/// `&MappedBuffer` and `&mut MappedBuffer` can be passed to kernels as byte pointers.
 pub struct MappedBuffer {
     device_ptr: sys::CUdeviceptr,
+    offset: usize,
     len: usize,
     external_memory: ExternalMemory,
 }
 
+impl core::fmt::Debug for MappedBuffer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MappedBuffer")
+            .field("device_ptr", &format_args!("{:#x}", self.device_ptr))
+            .field("offset", &format_args!("{:#x}", self.offset))
+            .field("len", &format_args!("{:#x}", self.len))
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for MappedBuffer {
     fn drop(&mut self) {
-        self.external_memory.device.bind_to_thread().unwrap();
-        unsafe { result::memory_free(self.device_ptr) }.unwrap()
+        let _ctx = self
+            .external_memory
+            .device
+            .bind_scoped_or_handle("MappedBuffer::drop");
+        unsafe { result::memory_free(self.device_ptr) }.or_handle("MappedBuffer::drop")
     }
 }
 
@@ -166,7 +390,104 @@ impl DevicePtr<u8> for MappedBuffer {
     }
 }
 
-///
+impl DevicePtrMut<u8> for MappedBuffer {
+    fn device_ptr_mut(&mut self) -> &mut sys::CUdeviceptr {
+        &mut self.device_ptr
+    }
+}
+
+impl DeviceSlice<u8> for &MappedBuffer {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl DevicePtr<u8> for &MappedBuffer {
+    fn device_ptr(&self) -> &sys::CUdeviceptr {
+        &self.device_ptr
+    }
+}
+
+unsafe impl DeviceRepr for &MappedBuffer {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.device_ptr)
+    }
+
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        Some((self.device_ptr, self.len))
+    }
+}
+
+unsafe impl DeviceRepr for &mut MappedBuffer {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.device_ptr)
+    }
+
+    #[inline(always)]
+    fn buffer_param(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        Some((self.device_ptr, self.len))
+    }
+}
+
+impl MappedBuffer {
+    /// The offset in bytes of the mapped range into the [ExternalMemory].
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The size in bytes of the whole [ExternalMemory], as passed when importing it.
+    pub fn external_size(&self) -> u64 {
+        self.external_memory.size
+    }
+
+    /// The [ExternalMemory] the range is mapped from.
+    pub fn external_memory(&self) -> &ExternalMemory {
+        &self.external_memory
+    }
+
+    /// Reinterprets the mapped bytes as `len` elements of `T`, e.g. to pass a matrix
+    /// imported from another API to cuBLAS. Returns `None` if the buffer is too small,
+    /// or isn't aligned for `T`.
+    ///
+    /// # Safety
+    /// The mapped memory may not be a valid interpretation for the type `T`.
+    pub unsafe fn transmute<T>(&self, len: usize) -> Option<CudaView<'_, T>> {
+        self.typed_ptr::<T>(len)
+            .map(|ptr| CudaView::from_raw_parts(ptr, len))
+    }
+
+    /// Mutable version of [MappedBuffer::transmute()].
+    ///
+    /// # Safety
+    /// The mapped memory may not be a valid interpretation for the type `T`.
+    pub unsafe fn transmute_mut<T>(&mut self, len: usize) -> Option<CudaViewMut<'_, T>> {
+        self.typed_ptr::<T>(len)
+            .map(|ptr| CudaViewMut::from_raw_parts(ptr, len))
+    }
+
+    fn typed_ptr<T>(&self, len: usize) -> Option<sys::CUdeviceptr> {
+        let fits = len.checked_mul(std::mem::size_of::<T>())? <= self.len;
+        let aligned = self
+            .device_ptr
+            .is_multiple_of(std::mem::align_of::<T>() as u64);
+        (fits && aligned).then_some(self.device_ptr)
+    }
+}
+
+/// A mipmapped array mapped onto an [`ExternalMemory`].
 pub struct MipMappedArray<'a> {
     array: sys::CUmipmappedArray,
     width: usize,
@@ -195,7 +516,9 @@ impl MipMappedArray<'_> {
     pub fn level(&self, level: u32) -> Result<CUarray, DriverError> {
         let mut level_array = std::mem::MaybeUninit::uninit();
         unsafe {
-            sys::lib().cuMipmappedArrayGetLevel(level_array.as_mut_ptr(), self.array, level).result()?;
+            sys::lib()
+                .cuMipmappedArrayGetLevel(level_array.as_mut_ptr(), self.array, level)
+                .result()?;
 
             Ok(level_array.assume_init())
         }
@@ -242,31 +565,257 @@ pub enum ExternalMemoryType {
     NvSciBuf = sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_NVSCIBUF as u32,
 }
 
-impl Into<sys::CUexternalMemoryHandleType_enum> for ExternalMemoryType {
-    fn into(self) -> sys::CUexternalMemoryHandleType_enum {
-        match self {
+impl From<ExternalMemoryType> for sys::CUexternalMemoryHandleType_enum {
+    fn from(value: ExternalMemoryType) -> Self {
+        match value {
             #[cfg(unix)]
-            Self::FileDescriptor =>
+            ExternalMemoryType::FileDescriptor =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_FD,
             #[cfg(windows)]
-            Self::Windows => sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32,
+            ExternalMemoryType::Windows => sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32,
             #[cfg(windows)]
-            Self::WindowsKMT =>
+            ExternalMemoryType::WindowsKMT =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_OPAQUE_WIN32_KMT,
             #[cfg(windows)]
-            Self::DirectX12Heap =>
+            ExternalMemoryType::DirectX12Heap =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D12_HEAP,
             #[cfg(windows)]
-            Self::DirectX12Resource =>
+            ExternalMemoryType::DirectX12Resource =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D12_RESOURCE,
             #[cfg(windows)]
-            Self::DirectX11Resource =>
+            ExternalMemoryType::DirectX11Resource =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D11_RESOURCE,
             #[cfg(windows)]
-            Self::DirectX11ResourceKMT =>
+            ExternalMemoryType::DirectX11ResourceKMT =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_D3D11_RESOURCE_KMT,
-            Self::NvSciBuf =>
+            ExternalMemoryType::NvSciBuf =>
                 sys::CUexternalMemoryHandleType_enum::CU_EXTERNAL_MEMORY_HANDLE_TYPE_NVSCIBUF,
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::driver::sys::lib;
+    use std::os::fd::FromRawFd;
+
+    #[test]
+    fn test_external_memory_size() {
+        let path = std::env::temp_dir().join(std::format!(
+            "cudarc-external-memory-size-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, [0u8; 4096]).unwrap();
+        let file = File::open(&path).unwrap();
+        assert_eq!(external_memory_size(&file), Ok(4096));
+
+        std::fs::write(&path, []).unwrap();
+        let file = File::open(&path).unwrap();
+        assert_eq!(
+            external_memory_size(&file),
+            Err(DriverError::InvalidExternalMemorySize)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_import_zero_size() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (file, _) = export_allocation(&dev, 1 << 20);
+        let res =
+            unsafe { dev.import_external_memory(file, 0, ExternalMemoryType::FileDescriptor) };
+        assert_eq!(res.unwrap_err(), DriverError::InvalidExternalMemorySize);
+    }
+
+    /// Allocates `size` bytes with the virtual memory management API and exports them
+    /// as a posix file descriptor that can be imported as an opaque fd.
+    fn export_allocation(dev: &CudaDevice, size: usize) -> (File, u64) {
+        let mut prop: sys::CUmemAllocationProp = unsafe { std::mem::zeroed() };
+        prop.type_ = sys::CUmemAllocationType::CU_MEM_ALLOCATION_TYPE_PINNED;
+        prop.requestedHandleTypes =
+            sys::CUmemAllocationHandleType::CU_MEM_HANDLE_TYPE_POSIX_FILE_DESCRIPTOR;
+        prop.location.type_ = sys::CUmemLocationType::CU_MEM_LOCATION_TYPE_DEVICE;
+        prop.location.id = dev.physical_ordinal as i32;
+
+        let mut granularity = 0;
+        let mut handle = 0;
+        let mut fd: std::ffi::c_int = -1;
+        unsafe {
+            lib()
+                .cuMemGetAllocationGranularity(
+                    &mut granularity,
+                    &prop,
+                    sys::CUmemAllocationGranularity_flags::CU_MEM_ALLOC_GRANULARITY_MINIMUM,
+                )
+                .result()
+                .unwrap();
+            let size = size.div_ceil(granularity) * granularity;
+            lib()
+                .cuMemCreate(&mut handle, size, &prop, 0)
+                .result()
+                .unwrap();
+            lib()
+                .cuMemExportToShareableHandle(
+                    &mut fd as *mut _ as *mut _,
+                    handle,
+                    sys::CUmemAllocationHandleType::CU_MEM_HANDLE_TYPE_POSIX_FILE_DESCRIPTOR,
+                    0,
+                )
+                .result()
+                .unwrap();
+            // the exported fd keeps the allocation alive
+            lib().cuMemRelease(handle).result().unwrap();
+            (File::from_raw_fd(fd), size as u64)
+        }
+    }
+
+    #[test]
+    fn test_import_dedicated_allocation() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (file, size) = export_allocation(&dev, 1024);
+        let ext = unsafe {
+            dev.import_external_memory_with_dedicated(
+                file,
+                size,
+                ExternalMemoryType::FileDescriptor,
+                true,
+            )
+        }
+        .unwrap();
+        let buf = ext.map_range_with_flags(0..256, MapFlags::NONE).unwrap();
+        assert_eq!(buf.len(), 256);
+
+        let src = dev.htod_sync_copy(&[7u8; 256]).unwrap();
+        let dst = dev.alloc_zeros::<u8>(256).unwrap();
+        unsafe {
+            result::memcpy_dtod_sync(*buf.device_ptr(), *src.device_ptr(), 256).unwrap();
+            result::memcpy_dtod_sync(*dst.device_ptr(), *buf.device_ptr(), 256).unwrap();
+        }
+        assert_eq!(dev.dtoh_sync_copy(&dst).unwrap(), [7u8; 256]);
+    }
+
+    #[test]
+    fn test_import_raw_keeps_guard_alive() {
+        use std::os::fd::IntoRawFd;
+
+        let dev = CudaDevice::new(0).unwrap();
+        let (file, size) = export_allocation(&dev, 1024);
+        let guard = Arc::new(());
+        let ext = unsafe {
+            dev.import_external_memory_raw(
+                file.into_raw_fd(),
+                size,
+                ExternalMemoryType::FileDescriptor,
+                false,
+                KeepAlive::Guard(Box::new(guard.clone())),
+            )
+        }
+        .unwrap();
+        assert_eq!(Arc::strong_count(&guard), 2);
+        let buf = ext.map_all().unwrap();
+        assert_eq!(buf.len(), size as usize);
+        drop(buf);
+        assert_eq!(Arc::strong_count(&guard), 1);
+    }
+
+    #[test]
+    fn test_transmute_mapped_buffer() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (file, size) = export_allocation(&dev, 64);
+        let ext =
+            unsafe { dev.import_external_memory(file, size, ExternalMemoryType::FileDescriptor) }
+                .unwrap();
+        let mut buf = ext.map_range(0..64).unwrap();
+        assert!(unsafe { buf.transmute::<f32>(17) }.is_none());
+        assert!(unsafe { buf.transmute::<f32>(usize::MAX) }.is_none());
+
+        let mut view = unsafe { buf.transmute_mut::<f32>(16) }.unwrap();
+        dev.htod_sync_copy_into(&[2.0f32; 16], &mut view).unwrap();
+        let view = unsafe { buf.transmute::<f32>(16) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&view).unwrap(), [2.0f32; 16]);
+    }
+
+    #[test]
+    fn test_mapped_range_metadata_and_launch() {
+        use crate::driver::{LaunchAsync, LaunchConfig};
+
+        let dev = CudaDevice::new(0).unwrap();
+        let (file, size) = export_allocation(&dev, 1024);
+        let ext =
+            unsafe { dev.import_external_memory(file, size, ExternalMemoryType::FileDescriptor) }
+                .unwrap();
+        let mut buf = ext.map_range(256..512).unwrap();
+        assert_eq!(buf.offset(), 256);
+        assert_eq!(buf.len(), 256);
+        assert_eq!(buf.external_size(), size);
+        assert_eq!(buf.external_memory().size, size);
+        assert_eq!(
+            std::format!("{buf:?}"),
+            std::format!(
+                "MappedBuffer {{ device_ptr: {:#x}, offset: 0x100, len: 0x100, .. }}",
+                buf.device_ptr
+            )
+        );
+
+        let src = "
+extern \"C\" __global__ void fill(unsigned char *out, size_t n) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) { out[i] = 5; }
+}
+extern \"C\" __global__ void copy(const unsigned char *x, unsigned char *out, size_t n) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) { out[i] = x[i]; }
+}";
+        let ptx = crate::nvrtc::compile_ptx(src).unwrap();
+        dev.load_ptx(ptx, "mapped", &["fill", "copy"]).unwrap();
+        let cfg = LaunchConfig::for_num_elems(256);
+        let fill = dev.get_func("mapped", "fill").unwrap();
+        unsafe { fill.launch(dev.default_stream(), cfg, (&mut buf, 256usize)) }.unwrap();
+        let mut out = dev.alloc_zeros::<u8>(256).unwrap();
+        let copy = dev.get_func("mapped", "copy").unwrap();
+        unsafe { copy.launch(dev.default_stream(), cfg, (&buf, &mut out, 256usize)) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [5u8; 256]);
+        // references can be passed on by value, e.g. to generic code
+        fn num_bytes(buf: impl DevicePtr<u8>) -> usize {
+            buf.num_bytes()
+        }
+        assert_eq!(num_bytes(&buf), 256);
+    }
+
+    #[cfg(feature = "cublas")]
+    #[test]
+    fn test_gemm_on_mapped_buffer() {
+        use crate::cublas::{sys::cublasOperation_t, CudaBlas, Gemm, GemmConfig};
+
+        let dev = CudaDevice::new(0).unwrap();
+        let blas = CudaBlas::new(dev.clone()).unwrap();
+        let (file, size) = export_allocation(&dev, 3 * 4 * std::mem::size_of::<f32>());
+        let ext =
+            unsafe { dev.import_external_memory(file, size, ExternalMemoryType::FileDescriptor) }
+                .unwrap();
+        let mut mapped = ext.map_all().unwrap();
+
+        // a is 2x2 and b is 2x1, both column major, stored back to back in the mapping
+        let mut ab = unsafe { mapped.transmute_mut::<f32>(6) }.unwrap();
+        dev.htod_sync_copy_into(&[1.0f32, 3.0, 2.0, 4.0, 1.0, 1.0], &mut ab)
+            .unwrap();
+        let ab = unsafe { mapped.transmute::<f32>(6) }.unwrap();
+        let (a, b) = (ab.slice(0..4), ab.slice(4..6));
+        let mut c = dev.alloc_zeros::<f32>(2).unwrap();
+        let cfg = GemmConfig {
+            transa: cublasOperation_t::CUBLAS_OP_N,
+            transb: cublasOperation_t::CUBLAS_OP_N,
+            m: 2,
+            n: 1,
+            k: 2,
+            alpha: 1.0,
+            lda: 2,
+            ldb: 2,
+            beta: 0.0,
+            ldc: 2,
+        };
+        unsafe { blas.gemm(cfg, &a, &b, &mut c) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&c).unwrap(), [3.0, 7.0]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::sync::Arc;
+
+use super::error_handler::OrHandle;
+use super::{CudaDevice, CudaStream, KeepAlive};
+use crate::driver::result::SemaphoreValue;
+use crate::driver::{result, sys, DriverError};
+
+impl CudaDevice {
+    /// Import an external semaphore from a [`File`], e.g. a Vulkan semaphore or a D3D12
+    /// fence shared with CUDA.
+    ///
+    /// # Safety
+    /// `file` must be a handle of a semaphore of type `type_`.
+    #[cfg(any(unix, windows))]
+    pub unsafe fn import_external_semaphore(
+        self: &Arc<Self>,
+        file: File,
+        type_: ExternalSemaphoreType,
+    ) -> Result<ExternalSemaphore, DriverError> {
+        result::require(result::ApiGroup::ExternalResources)?;
+        let _ctx = self.bind_scoped()?;
+
+        #[cfg(unix)]
+        let external_semaphore = unsafe {
+            use std::os::fd::AsRawFd;
+            result::external_semaphore::import_external_semaphore(file.as_raw_fd(), type_.into())
+        }?;
+        #[cfg(windows)]
+        let external_semaphore = unsafe {
+            use std::os::windows::io::AsRawHandle;
+            result::external_semaphore::import_external_semaphore(
+                file.as_raw_handle(),
+                type_.into(),
+            )
+        }?;
+        Ok(ExternalSemaphore {
+            external_semaphore,
+            type_,
+            device: self.clone(),
+            keep_alive: KeepAlive::File(file),
+        })
+    }
+}
+
+/// An abstraction for an imported external semaphore.
+///
+/// This struct can be created via [`CudaDevice::import_external_semaphore`].
+/// The imported semaphore will be destroyed when this struct is dropped.
+#[derive(Debug)]
+pub struct ExternalSemaphore {
+    pub(crate) external_semaphore: sys::CUexternalSemaphore,
+    pub(crate) type_: ExternalSemaphoreType,
+    device: Arc<CudaDevice>,
+    keep_alive: KeepAlive,
+}
+
+impl Drop for ExternalSemaphore {
+    fn drop(&mut self) {
+        let _ctx = self.device.bind_scoped_or_handle("ExternalSemaphore::drop");
+
+        unsafe { result::external_semaphore::destroy_external_semaphore(self.external_semaphore) }
+            .or_handle("ExternalSemaphore::drop");
+
+        // windows handles, including those of D3D12 fences, are closed by us
+        self.keep_alive.release();
+    }
+}
+
+impl ExternalSemaphore {
+    /// The type the semaphore was imported as.
+    pub fn semaphore_type(&self) -> ExternalSemaphoreType {
+        self.type_
+    }
+
+    /// Enqueues a signal of the semaphore on the device's stream.
+    ///
+    /// Fences and timeline semaphores, see [ExternalSemaphoreType::has_value()], are set to
+    /// `Some(value)`, binary semaphores take `None`. Returns [DriverError::SemaphoreValue]
+    /// otherwise.
+    pub fn signal_async(&self, value: Option<u64>) -> Result<(), DriverError> {
+        self.signal_on(value, self.device.stream)
+    }
+
+    /// [ExternalSemaphore::signal_async()] on `stream`.
+    ///
+    /// Nothing is synchronized with the host, so the signal can be captured into a
+    /// [super::CudaGraph] with [CudaStream::begin_capture()].
+    pub fn signal_on_stream(
+        &self,
+        value: Option<u64>,
+        stream: &CudaStream,
+    ) -> Result<(), DriverError> {
+        self.signal_on(value, stream.stream)
+    }
+
+    /// Enqueues a wait on the semaphore on the device's stream.
+    ///
+    /// Fences and timeline semaphores, see [ExternalSemaphoreType::has_value()], are waited
+    /// on until they reach `Some(value)`, binary semaphores take `None`. Returns
+    /// [DriverError::SemaphoreValue] otherwise.
+    pub fn wait_async(&self, value: Option<u64>) -> Result<(), DriverError> {
+        self.wait_on(value, self.device.stream)
+    }
+
+    /// [ExternalSemaphore::wait_async()] on `stream`.
+    ///
+    /// Like [ExternalSemaphore::signal_on_stream()], the wait can be captured into a
+    /// [super::CudaGraph].
+    pub fn wait_on_stream(
+        &self,
+        value: Option<u64>,
+        stream: &CudaStream,
+    ) -> Result<(), DriverError> {
+        self.wait_on(value, stream.stream)
+    }
+
+    fn signal_on(&self, value: Option<u64>, stream: sys::CUstream) -> Result<(), DriverError> {
+        let value = self.type_.check_value(value)?;
+        let _ctx = self.device.bind_scoped()?;
+        unsafe { result::external_semaphore::signal_async(self.external_semaphore, value, stream) }
+    }
+
+    fn wait_on(&self, value: Option<u64>, stream: sys::CUstream) -> Result<(), DriverError> {
+        let value = self.type_.check_value(value)?;
+        let _ctx = self.device.bind_scoped()?;
+        unsafe { result::external_semaphore::wait_async(self.external_semaphore, value, stream) }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+#[repr(u32)]
+/// External semaphore handle descriptor.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXTRES__INTEROP.html)
+pub enum ExternalSemaphoreType {
+    #[cfg(unix)]
+    /// A valid file descriptor referencing a binary semaphore, e.g. from Vulkan's `VK_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD_BIT`. Ownership of the file descriptor is transferred to the CUDA driver when the handle is imported successfully.
+    FileDescriptor =
+        sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD as u32,
+    #[cfg(unix)]
+    /// A valid file descriptor referencing a Vulkan timeline semaphore. Ownership of the file descriptor is transferred to the CUDA driver when the handle is imported successfully.
+    TimelineFileDescriptor =
+        sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_TIMELINE_SEMAPHORE_FD
+            as u32,
+    #[cfg(windows)]
+    /// A valid shared NT handle referencing a binary semaphore. Ownership of this handle is not transferred to CUDA after the import operation, so the application must release the handle using the appropriate system call.
+    Windows =
+        sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_WIN32 as u32,
+    #[cfg(windows)]
+    /// A globally shared KMT handle referencing a binary semaphore. This handle does not hold a reference to the underlying object, and thus will be invalid when all references to the semaphore are destroyed.
+    WindowsKMT =
+        sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_WIN32_KMT
+            as u32,
+    #[cfg(windows)]
+    /// A valid shared NT handle that is returned by ID3D12Device::CreateSharedHandle when referring to a ID3D12Fence object. This handle holds a reference to the underlying object.
+    D3D12Fence =
+        sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_D3D12_FENCE as u32,
+    #[cfg(windows)]
+    /// A valid shared NT handle referencing a Vulkan timeline semaphore. Ownership of this handle is not transferred to CUDA after the import operation.
+    TimelineWindows =
+        sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_TIMELINE_SEMAPHORE_WIN32
+            as u32,
+}
+
+impl ExternalSemaphoreType {
+    /// Whether the semaphore is a fence or timeline semaphore, which is signaled and waited
+    /// on with a 64-bit value, rather than a binary semaphore.
+    pub fn has_value(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Self::TimelineFileDescriptor => true,
+            #[cfg(windows)]
+            Self::D3D12Fence | Self::TimelineWindows => true,
+            _ => false,
+        }
+    }
+
+    /// The value to pass to the driver, which ignores it for binary semaphores.
+    pub(crate) fn check_value(&self, value: Option<u64>) -> Result<u64, SemaphoreValue> {
+        match (self.has_value(), value) {
+            (true, Some(value)) => Ok(value),
+            (false, None) => Ok(0),
+            (true, None) => Err(SemaphoreValue::Missing),
+            (false, Some(_)) => Err(SemaphoreValue::Unexpected),
+        }
+    }
+}
+
+impl From<ExternalSemaphoreType> for sys::CUexternalSemaphoreHandleType {
+    fn from(value: ExternalSemaphoreType) -> Self {
+        match value {
+            #[cfg(unix)]
+            ExternalSemaphoreType::FileDescriptor =>
+                sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_FD,
+            #[cfg(unix)]
+            ExternalSemaphoreType::TimelineFileDescriptor =>
+                sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_TIMELINE_SEMAPHORE_FD,
+            #[cfg(windows)]
+            ExternalSemaphoreType::Windows =>
+                sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_WIN32,
+            #[cfg(windows)]
+            ExternalSemaphoreType::WindowsKMT =>
+                sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_OPAQUE_WIN32_KMT,
+            #[cfg(windows)]
+            ExternalSemaphoreType::D3D12Fence =>
+                sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_D3D12_FENCE,
+            #[cfg(windows)]
+            ExternalSemaphoreType::TimelineWindows =>
+                sys::CUexternalSemaphoreHandleType::CU_EXTERNAL_SEMAPHORE_HANDLE_TYPE_TIMELINE_SEMAPHORE_WIN32,
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_semaphore_value_matches_type() {
+        let binary = ExternalSemaphoreType::FileDescriptor;
+        let timeline = ExternalSemaphoreType::TimelineFileDescriptor;
+        assert!(!binary.has_value());
+        assert!(timeline.has_value());
+        assert_eq!(binary.check_value(None), Ok(0));
+        assert_eq!(binary.check_value(Some(1)), Err(SemaphoreValue::Unexpected));
+        assert_eq!(timeline.check_value(Some(7)), Ok(7));
+        assert_eq!(timeline.check_value(None), Err(SemaphoreValue::Missing));
+        assert_eq!(
+            DriverError::from(SemaphoreValue::Missing).code(),
+            sys::CUresult::CUDA_ERROR_INVALID_VALUE
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaStream};
+use super::error_handler::OrHandle;
+use super::external_semaphore::ExternalSemaphore;
+use super::DriverError;
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+/// A graph of work captured from a [CudaStream] with [CudaStream::begin_capture()] and
+/// [CudaStream::end_capture()], which can be replayed with [CudaGraph::launch_on_stream()].
+/// Nodes can also be added explicitly, e.g. to a graph created with [CudaGraph::new()].
+///
+/// Only work that is enqueued on the stream without synchronizing can be captured, e.g.
+/// kernels launched with [super::LaunchAsync::launch()] and the `*_on_stream`
+/// copies such as [CudaDevice::htod_copy_on_stream()].
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// # let dev = CudaDevice::new(0).unwrap();
+/// let stream = dev.fork_default_stream().unwrap();
+/// let mut host = dev.alloc_pinned::<f32>(100).unwrap();
+/// stream
+///     .begin_capture(sys::CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_THREAD_LOCAL)
+///     .unwrap();
+/// let buf = dev.alloc_zeros_on_stream::<f32>(100, &stream).unwrap();
+/// unsafe { dev.dtoh_copy_on_stream(&buf, &mut host, &stream) }.unwrap();
+/// buf.drop_on_stream(&stream).unwrap();
+/// let graph = stream.end_capture().unwrap();
+/// unsafe { graph.launch_on_stream(&stream) }.unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CudaGraph {
+    cu_graph: sys::CUgraph,
+    /// Null until the graph is instantiated, which [CudaGraph::launch_on_stream()] does
+    /// after nodes were added.
+    cu_graph_exec: AtomicPtr<sys::CUgraphExec_st>,
+    device: Arc<CudaDevice>,
+}
+
+unsafe impl Send for CudaGraph {}
+unsafe impl Sync for CudaGraph {}
+
+/// A node added to a [CudaGraph], which later nodes of the same graph can depend on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct GraphNode(sys::CUgraphNode);
+
+unsafe impl Send for GraphNode {}
+unsafe impl Sync for GraphNode {}
+
+impl GraphNode {
+    /// The node of the driver, owned by its graph.
+    pub fn cu_graph_node(&self) -> sys::CUgraphNode {
+        self.0
+    }
+}
+
+impl CudaStream {
+    /// Starts capturing the work enqueued on this stream into a graph instead of executing it.
+    ///
+    /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__STREAM.html)
+    pub fn begin_capture(&self, mode: sys::CUstreamCaptureMode) -> Result<(), DriverError> {
+        result::require(result::ApiGroup::Graphs)?;
+        let _ctx = self.bind_scoped()?;
+        unsafe { result::stream::begin_capture(self.stream, mode) }
+    }
+
+    /// Stops capturing, and instantiates the captured work as a [CudaGraph].
+    pub fn end_capture(&self) -> Result<CudaGraph, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let cu_graph = unsafe { result::stream::end_capture(self.stream) }?;
+        let cu_graph_exec = match unsafe { result::graph::instantiate(cu_graph, 0) } {
+            Ok(exec) => exec,
+            Err(e) => {
+                unsafe { result::graph::destroy(cu_graph) }.or_handle("CudaStream::end_capture");
+                return Err(e);
+            }
+        };
+        Ok(CudaGraph {
+            cu_graph,
+            cu_graph_exec: AtomicPtr::new(cu_graph_exec),
+            device: self.device.clone(),
+        })
+    }
+
+    /// Whether this stream is being captured.
+    pub fn is_capturing(&self) -> Result<bool, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let status = unsafe { result::stream::is_capturing(self.stream) }?;
+        Ok(status == sys::CUstreamCaptureStatus::CU_STREAM_CAPTURE_STATUS_ACTIVE)
+    }
+}
+
+impl CudaGraph {
+    /// Creates an empty graph, to add nodes to explicitly.
+    pub fn new(device: &Arc<CudaDevice>) -> Result<Self, DriverError> {
+        result::require(result::ApiGroup::Graphs)?;
+        let _ctx = device.bind_scoped()?;
+        let cu_graph = unsafe { result::graph::create() }?;
+        Ok(CudaGraph {
+            cu_graph,
+            cu_graph_exec: AtomicPtr::new(std::ptr::null_mut()),
+            device: device.clone(),
+        })
+    }
+
+    /// Enqueues the whole graph on `stream`.
+    ///
+    /// # Safety
+    /// The graph only holds the addresses of the memory its work used when it was captured,
+    /// not the buffers themselves. Every [super::CudaSlice] and [super::PinnedBuffer] used
+    /// by the captured work must still be alive, and must not be used by other work while
+    /// the launch runs.
+    pub unsafe fn launch_on_stream(&self, stream: &CudaStream) -> Result<(), DriverError> {
+        let _ctx = stream.bind_scoped()?;
+        let exec = self.exec()?;
+        result::graph::launch(exec, stream.stream)
+    }
+
+    /// Number of nodes in the graph.
+    pub fn node_count(&self) -> Result<usize, DriverError> {
+        unsafe { result::graph::num_nodes(self.cu_graph) }
+    }
+
+    /// Adds a node waiting on `semaphore` after `dependencies`, e.g. for a Vulkan frame to
+    /// finish rendering before the kernels of the graph read it.
+    ///
+    /// `value` is the value to wait for, like for [ExternalSemaphore::wait_async()].
+    ///
+    /// # Safety
+    /// 1. `dependencies` must be nodes of this graph.
+    /// 2. `semaphore` must outlive every launch of the graph.
+    pub unsafe fn add_external_semaphore_wait_node(
+        &mut self,
+        dependencies: &[GraphNode],
+        semaphore: &ExternalSemaphore,
+        value: Option<u64>,
+    ) -> Result<GraphNode, DriverError> {
+        let value = semaphore.type_.check_value(value)?;
+        let _ctx = self.device.bind_scoped()?;
+        let node = result::graph::add_external_semaphore_wait_node(
+            self.cu_graph,
+            Self::cu_nodes(dependencies),
+            semaphore.external_semaphore,
+            value,
+        )?;
+        self.invalidate();
+        Ok(GraphNode(node))
+    }
+
+    /// Adds a node signaling `semaphore` after `dependencies`, e.g. to hand a frame the
+    /// graph wrote back to Vulkan.
+    ///
+    /// `value` is the value to signal, like for [ExternalSemaphore::signal_async()].
+    ///
+    /// # Safety
+    /// 1. `dependencies` must be nodes of this graph.
+    /// 2. `semaphore` must outlive every launch of the graph.
+    pub unsafe fn add_external_semaphore_signal_node(
+        &mut self,
+        dependencies: &[GraphNode],
+        semaphore: &ExternalSemaphore,
+        value: Option<u64>,
+    ) -> Result<GraphNode, DriverError> {
+        let value = semaphore.type_.check_value(value)?;
+        let _ctx = self.device.bind_scoped()?;
+        let node = result::graph::add_external_semaphore_signal_node(
+            self.cu_graph,
+            Self::cu_nodes(dependencies),
+            semaphore.external_semaphore,
+            value,
+        )?;
+        self.invalidate();
+        Ok(GraphNode(node))
+    }
+
+    fn cu_nodes(nodes: &[GraphNode]) -> &[sys::CUgraphNode] {
+        // GraphNode is repr(transparent)
+        unsafe {
+            core::slice::from_raw_parts(nodes.as_ptr() as *const sys::CUgraphNode, nodes.len())
+        }
+    }
+
+    /// The executable graph, instantiating it if nodes were added since the last launch.
+    fn exec(&self) -> Result<sys::CUgraphExec, DriverError> {
+        let exec = self.cu_graph_exec.load(Ordering::Acquire);
+        if !exec.is_null() {
+            return Ok(exec);
+        }
+        let exec = unsafe { result::graph::instantiate(self.cu_graph, 0) }?;
+        match self.cu_graph_exec.compare_exchange(
+            std::ptr::null_mut(),
+            exec,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Ok(exec),
+            // instantiated by another thread in the meantime
+            Err(other) => {
+                unsafe { result::graph::exec_destroy(exec) }.or_handle("CudaGraph::exec");
+                Ok(other)
+            }
+        }
+    }
+
+    /// Destroys the executable graph, which doesn't contain nodes added after it was
+    /// instantiated.
+    fn invalidate(&mut self) {
+        let exec = core::mem::replace(self.cu_graph_exec.get_mut(), std::ptr::null_mut());
+        if !exec.is_null() {
+            unsafe { result::graph::exec_destroy(exec) }.or_handle("CudaGraph::invalidate");
+        }
+    }
+}
+
+impl Drop for CudaGraph {
+    fn drop(&mut self) {
+        let _ctx = self.device.bind_scoped_or_handle("CudaGraph::drop");
+        self.invalidate();
+        unsafe { result::graph::destroy(self.cu_graph) }.or_handle("CudaGraph::drop");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_capture_copies_and_kernel_into_graph() {
+        let dev = CudaDevice::new(0).unwrap();
+        if !dev.is_async {
+            return;
+        }
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void double_it(float *y, const float *x, size_t n) {
+                size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+                if (i < n) { y[i] = 2.0f * x[i]; }
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "graph", &["double_it"]).unwrap();
+        let f = dev.get_func("graph", "double_it").unwrap();
+
+        const N: usize = 1000;
+        let stream = dev.fork_default_stream().unwrap();
+        let mut host_in = dev.alloc_pinned::<f32>(N).unwrap();
+        let mut host_out = dev.alloc_pinned::<f32>(N).unwrap();
+
+        stream
+            .begin_capture(sys::CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_THREAD_LOCAL)
+            .unwrap();
+        assert!(stream.is_capturing().unwrap());
+        let mut x = unsafe { dev.alloc_on_stream::<f32>(N, &stream) }.unwrap();
+        unsafe { dev.htod_copy_on_stream(&host_in, &mut x, &stream) }.unwrap();
+        let mut y = dev.alloc_zeros_on_stream::<f32>(N, &stream).unwrap();
+        let cfg = LaunchConfig::for_num_elems(N as u32);
+        unsafe { f.launch(&stream, cfg, (&mut y, &x, N)) }.unwrap();
+        unsafe { dev.dtoh_copy_on_stream(&y, &mut host_out, &stream) }.unwrap();
+        x.drop_on_stream(&stream).unwrap();
+        y.drop_on_stream(&stream).unwrap();
+        let graph = stream.end_capture().unwrap();
+        assert!(!stream.is_capturing().unwrap());
+        // 2 allocs, htod, memset, kernel, dtoh, 2 frees
+        assert_eq!(graph.node_count().unwrap(), 8);
+
+        for round in 0..2 {
+            for (i, v) in host_in.as_mut_slice().iter_mut().enumerate() {
+                *v = (i + round * N) as f32;
+            }
+            unsafe { graph.launch_on_stream(&stream) }.unwrap();
+            dev.wait_for(&stream).unwrap();
+            dev.synchronize().unwrap();
+            for (i, v) in host_out.as_slice().iter().enumerate() {
+                assert_eq!(*v, 2.0 * (i + round * N) as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn test_explicit_graph() {
+        let dev = CudaDevice::new(0).unwrap();
+        let graph = CudaGraph::new(&dev).unwrap();
+        assert_eq!(graph.node_count().unwrap(), 0);
+        let stream = dev.fork_default_stream().unwrap();
+        unsafe { graph.launch_on_stream(&stream) }.unwrap();
+        unsafe { graph.launch_on_stream(&stream) }.unwrap();
+        dev.wait_for(&stream).unwrap();
+        dev.synchronize().unwrap();
+    }
+
+    /// Needs an exported Vulkan timeline semaphore at 0, e.g. from
+    /// `vkGetSemaphoreFdKHR`, whose fd is passed in `CUDARC_TEST_TIMELINE_SEMAPHORE_FD`.
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "needs a timeline semaphore exported by Vulkan"]
+    fn test_external_semaphore_nodes() {
+        use super::super::ExternalSemaphoreType;
+        use std::os::fd::FromRawFd;
+
+        let fd = std::env::var("CUDARC_TEST_TIMELINE_SEMAPHORE_FD")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let dev = CudaDevice::new(0).unwrap();
+        let semaphore = unsafe {
+            dev.import_external_semaphore(
+                std::fs::File::from_raw_fd(fd),
+                ExternalSemaphoreType::TimelineFileDescriptor,
+            )
+        }
+        .unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+
+        // explicit nodes: wait for 1, then signal 2
+        let mut explicit = CudaGraph::new(&dev).unwrap();
+        let wait =
+            unsafe { explicit.add_external_semaphore_wait_node(&[], &semaphore, Some(1)) }.unwrap();
+        unsafe { explicit.add_external_semaphore_signal_node(&[wait], &semaphore, Some(2)) }
+            .unwrap();
+        assert_eq!(explicit.node_count().unwrap(), 2);
+
+        // captured nodes: wait for 2, then signal 3
+        stream
+            .begin_capture(sys::CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_THREAD_LOCAL)
+            .unwrap();
+        semaphore.wait_on_stream(Some(2), &stream).unwrap();
+        semaphore.signal_on_stream(Some(3), &stream).unwrap();
+        let captured = stream.end_capture().unwrap();
+        assert_eq!(captured.node_count().unwrap(), 2);
+
+        // each graph only completes if the one before signaled it, so this hangs if a
+        // node doesn't do its part
+        semaphore.signal_on_stream(Some(1), &stream).unwrap();
+        unsafe { explicit.launch_on_stream(&stream) }.unwrap();
+        unsafe { captured.launch_on_stream(&stream) }.unwrap();
+        semaphore.wait_on_stream(Some(3), &stream).unwrap();
+        dev.wait_for(&stream).unwrap();
+        dev.synchronize().unwrap();
+    }
+}
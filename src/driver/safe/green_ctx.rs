@@ -0,0 +1,254 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction, CudaStream};
+use super::error_handler::OrHandle;
+use super::{CudaKernel, DriverError};
+
+use std::{sync::Arc, vec::Vec};
+
+/// A context that only runs work on a partition of a device's SMs, created with
+/// [CudaDevice::split_sm_resources()]. Requires a cuda 12.4 driver.
+///
+/// Work is submitted to a green context through streams from [GreenContext::new_stream()].
+/// Kernels launched on those streams must come from [GreenContext::kernel_function()].
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GREEN__CONTEXTS.html)
+#[derive(Debug)]
+pub struct GreenContext {
+    pub(crate) green_ctx: sys::CUgreenCtx,
+    pub(crate) ctx: sys::CUcontext,
+    /// Used to synchronize the device's stream with streams of this context.
+    pub(crate) event: sys::CUevent,
+    pub(crate) device: Arc<CudaDevice>,
+    sm_count: u32,
+}
+
+unsafe impl Send for GreenContext {}
+unsafe impl Sync for GreenContext {}
+
+impl CudaDevice {
+    /// Splits the device's SMs into one [GreenContext] per entry of `counts`, where the
+    /// partition for `counts[i]` has at least `counts[i]` SMs. The driver rounds counts up
+    /// to the SM granularity of the architecture, see [GreenContext::sm_count()].
+    ///
+    /// Returns [DriverError::UnsupportedDriver] if the driver is older than 12.4, and
+    /// [sys::CUresult::CUDA_ERROR_INVALID_RESOURCE_CONFIGURATION] if the device doesn't have
+    /// enough SMs.
+    pub fn split_sm_resources(
+        self: &Arc<Self>,
+        counts: &[u32],
+    ) -> Result<Vec<Arc<GreenContext>>, DriverError> {
+        result::require(result::ApiGroup::GreenContexts)?;
+        let mut remaining = result::green_ctx::device_sm_resource(self.cu_device)?;
+        let mut partitions = Vec::with_capacity(counts.len());
+        for &count in counts {
+            let (groups, rest) = result::green_ctx::split_sm_by_count(&remaining, 1, count)?;
+            let Some(&group) = groups.first() else {
+                return Err(DriverError::Cuda(
+                    sys::CUresult::CUDA_ERROR_INVALID_RESOURCE_CONFIGURATION,
+                ));
+            };
+            partitions.push(group);
+            remaining = rest;
+        }
+        partitions
+            .into_iter()
+            .map(|mut group| GreenContext::new(self.clone(), &mut group).map(Arc::new))
+            .collect()
+    }
+}
+
+impl GreenContext {
+    /// Creates the context for `group`, leaving the context that was current bound to the
+    /// calling thread.
+    fn new(device: Arc<CudaDevice>, group: &mut sys::CUdevResource) -> Result<Self, DriverError> {
+        let prev = result::ctx::get_current()?;
+        let desc = result::green_ctx::generate_desc(std::slice::from_mut(group))?;
+        let green_ctx = unsafe { result::green_ctx::create(desc, device.cu_device) }?;
+        // the context is only bound to create its event in it
+        let created = unsafe { result::green_ctx::to_ctx(green_ctx) }.and_then(|ctx| {
+            unsafe { result::ctx::set_current(ctx) }?;
+            let event = result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?;
+            Ok((ctx, event))
+        });
+        let restored = unsafe { result::ctx::set_current(prev.unwrap_or(std::ptr::null_mut())) };
+        let (ctx, event) = match created {
+            Ok(created) => created,
+            Err(e) => {
+                unsafe { result::green_ctx::destroy(green_ctx) }.or_handle("GreenContext::new");
+                return Err(e);
+            }
+        };
+        let green_ctx = Self {
+            green_ctx,
+            ctx,
+            event,
+            device,
+            sm_count: unsafe { group.__bindgen_anon_1.sm.smCount },
+        };
+        restored?;
+        Ok(green_ctx)
+    }
+
+    /// The number of SMs in this partition.
+    pub fn sm_count(&self) -> u32 {
+        self.sm_count
+    }
+
+    /// The device this context partitions.
+    pub fn device(&self) -> &Arc<CudaDevice> {
+        &self.device
+    }
+
+    /// Get the underlying [sys::CUgreenCtx] of this [GreenContext].
+    ///
+    /// # Safety
+    /// While this function is marked as safe, actually using the
+    /// returned object is unsafe.
+    ///
+    /// **You must not free/release the context pointer, as it is still
+    /// owned by the [GreenContext].**
+    pub fn cu_green_ctx(&self) -> &sys::CUgreenCtx {
+        &self.green_ctx
+    }
+
+    /// Binds the context to the calling thread.
+    pub fn bind_to_thread(&self) -> Result<(), DriverError> {
+        unsafe { result::ctx::set_current(self.ctx) }
+    }
+
+    /// Creates a stream whose work only runs on this partition's SMs.
+    ///
+    /// Like [CudaDevice::fork_default_stream()], the stream waits for the device's work
+    /// stream on creation, and the device's work stream waits for it on drop.
+    pub fn new_stream(self: &Arc<Self>) -> Result<CudaStream, DriverError> {
+        self.bind_to_thread()?;
+        let stream = CudaStream {
+            stream: unsafe { result::green_ctx::stream_create(self.green_ctx) }?,
+            device: self.device.clone(),
+            green_ctx: Some(self.clone()),
+            scratch: Default::default(),
+        };
+        stream.wait_for_default()?;
+        Ok(stream)
+    }
+
+    /// Loads `kernel` into this context. The returned [CudaFunction] must only be
+    /// launched on streams from [GreenContext::new_stream()], e.g. with
+    /// [super::LaunchAsync::launch()].
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the kernel's library isn't
+    /// [super::CudaLibrary::is_context_independent()].
+    pub fn kernel_function(&self, kernel: &CudaKernel) -> Result<CudaFunction, DriverError> {
+        let cu_kernel = kernel
+            .cu_kernel()
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED))?;
+        self.bind_to_thread()?;
+        let cu_function = unsafe { result::library::kernel_get_function(cu_kernel) }?;
+        Ok(CudaFunction {
+            cu_function,
+            device: self.device.clone(),
+            max_dynamic_smem: Default::default(),
+            max_threads_per_block: Default::default(),
+            required_cluster_width: Arc::new(u32::MAX.into()),
+            name: kernel.function_name(),
+            #[cfg(feature = "launch-validation")]
+            num_params: kernel.num_params,
+            #[cfg(feature = "launch-validation")]
+            queried_num_params: Default::default(),
+            param_sizes: None,
+            library: Some(kernel.library().clone()),
+        })
+    }
+}
+
+impl Drop for GreenContext {
+    fn drop(&mut self) {
+        let prev = result::ctx::get_current().unwrap_or(None);
+        self.bind_to_thread().or_handle("GreenContext::drop");
+        unsafe {
+            result::event::destroy(self.event).or_handle("GreenContext::drop");
+            result::green_ctx::destroy(self.green_ctx).or_handle("GreenContext::drop");
+        }
+        // restores the context that was current, unless it is the one just destroyed
+        if prev == Some(self.ctx) {
+            self.device.bind_to_thread().or_handle("GreenContext::drop");
+        } else {
+            unsafe { result::ctx::set_current(prev.unwrap_or(std::ptr::null_mut())) }
+                .or_handle("GreenContext::drop");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{CudaLibrary, JitOptions, LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    const SPIN: &str = "
+extern \"C\" __global__ void spin(unsigned long long *times, const long long cycles) {
+    unsigned long long start;
+    asm volatile(\"mov.u64 %0, %%globaltimer;\" : \"=l\"(start));
+    long long t0 = clock64();
+    while (clock64() - t0 < cycles) {}
+    unsigned long long end;
+    asm volatile(\"mov.u64 %0, %%globaltimer;\" : \"=l\"(end));
+    if (blockIdx.x == 0 && threadIdx.x == 0) {
+        times[0] = start;
+        times[1] = end;
+    }
+}";
+
+    #[test]
+    fn test_green_ctx_keeps_current_ctx() {
+        if !result::green_ctx::is_supported() {
+            return;
+        }
+        let dev = CudaDevice::new(0).unwrap();
+        dev.bind_to_thread().unwrap();
+        let partitions = dev.split_sm_resources(&[1]).unwrap();
+        assert_eq!(result::ctx::get_current().unwrap(), Some(*dev.cu_ctx()));
+        drop(partitions);
+        assert_eq!(result::ctx::get_current().unwrap(), Some(*dev.cu_ctx()));
+    }
+
+    #[test]
+    fn test_green_ctx_partitions_overlap() {
+        if !result::green_ctx::is_supported() {
+            return;
+        }
+        let dev = CudaDevice::new(0).unwrap();
+        let sms = dev
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)
+            .unwrap() as u32;
+        let partitions = dev.split_sm_resources(&[sms / 4, sms / 4]).unwrap();
+        assert_eq!(partitions.len(), 2);
+        assert!(partitions.iter().all(|p| p.sm_count() >= sms / 4));
+
+        let library = CudaLibrary::load(compile_ptx(SPIN).unwrap(), JitOptions::default()).unwrap();
+        let kernel = library.kernel("spin").unwrap();
+
+        let mut times = [
+            dev.alloc_zeros::<u64>(2).unwrap(),
+            dev.alloc_zeros::<u64>(2).unwrap(),
+        ];
+        let streams: Vec<_> = partitions.iter().map(|p| p.new_stream().unwrap()).collect();
+        for ((partition, stream), times) in partitions.iter().zip(&streams).zip(&mut times) {
+            let f = partition.kernel_function(&kernel).unwrap();
+            let cfg = LaunchConfig {
+                grid_dim: (partition.sm_count(), 1, 1),
+                block_dim: (32, 1, 1),
+                shared_mem_bytes: 0,
+            };
+            unsafe { f.launch(stream, cfg, (times, 100_000_000i64)) }.unwrap();
+        }
+        drop(streams);
+        let a = dev.dtoh_sync_copy(&times[0]).unwrap();
+        let b = dev.dtoh_sync_copy(&times[1]).unwrap();
+        assert!(
+            a[0] < b[1] && b[0] < a[1],
+            "{a:?} and {b:?} did not overlap"
+        );
+    }
+}
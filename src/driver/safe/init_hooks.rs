@@ -0,0 +1,227 @@
+use crate::nvrtc::Ptx;
+
+use super::core::CudaDevice;
+use super::DriverError;
+
+use std::{sync::Arc, vec::Vec};
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// A hook run on every new [CudaDevice], see [register_device_init_hook()].
+pub type DeviceInitHook = fn(&Arc<CudaDevice>) -> Result<(), DriverError>;
+
+/// The hooks of the process, run by the constructors of [CudaDevice].
+static INIT_HOOKS: InitHooks = InitHooks::new();
+
+/// Registers `hook` to run on every [CudaDevice] created in this process from now on, e.g.
+/// to load a standard set of kernels with [CudaDevice::preload_ptx_set()] no matter which
+/// component creates the device.
+///
+/// Hooks run in the order they were registered, exactly once for each device, before
+/// [CudaDevice::new()] and the other constructors return it. Modules are loaded per
+/// [CudaDevice], so every device of an ordinal runs the hooks, not just the first one. If
+/// a hook fails, creating the device fails with its error.
+///
+/// A hook that creates a [CudaDevice] runs the hooks for that device too, so it must not
+/// do so unconditionally.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// # use std::sync::Arc;
+/// fn hook(dev: &Arc<CudaDevice>) -> Result<(), DriverError> {
+///     dev.set_perf_hints(true);
+///     Ok(())
+/// }
+/// register_device_init_hook(hook);
+/// let dev = CudaDevice::new(0).unwrap();
+/// ```
+pub fn register_device_init_hook(hook: DeviceInitHook) {
+    INIT_HOOKS.register(hook);
+}
+
+/// Runs the registered hooks for `device`, see [register_device_init_hook()].
+pub(crate) fn run_device_init_hooks(device: &Arc<CudaDevice>) -> Result<(), DriverError> {
+    INIT_HOOKS.run(device)
+}
+
+/// The registered hooks, in the order they were registered.
+#[derive(Debug)]
+struct InitHooks {
+    hooks: RwLock<Vec<DeviceInitHook>>,
+}
+
+impl InitHooks {
+    const fn new() -> Self {
+        Self {
+            hooks: RwLock::new(Vec::new()),
+        }
+    }
+
+    fn register(&self, hook: DeviceInitHook) {
+        #[allow(unused_mut)]
+        let mut hooks = self.hooks.write();
+        // a hook panicking doesn't leave the list half-written
+        #[cfg(not(feature = "no-std"))]
+        let mut hooks = hooks.unwrap_or_else(|e| e.into_inner());
+        hooks.push(hook);
+    }
+
+    fn run(&self, device: &Arc<CudaDevice>) -> Result<(), DriverError> {
+        // cloned, so that hooks can register other hooks or create devices
+        let hooks = {
+            let hooks = self.hooks.read();
+            #[cfg(not(feature = "no-std"))]
+            let hooks = hooks.unwrap_or_else(|e| e.into_inner());
+            hooks.clone()
+        };
+        for hook in hooks {
+            hook(device)?;
+        }
+        Ok(())
+    }
+}
+
+impl CudaDevice {
+    /// Loads each `(module_name, ptx, func_names)` of `set` with [CudaDevice::load_ptx()],
+    /// skipping modules whose functions are all loaded already. Meant for hooks of
+    /// [register_device_init_hook()], so that components that load the same modules
+    /// themselves later find them.
+    ///
+    /// Stops at the first module that fails to load, and returns its error.
+    pub fn preload_ptx_set(
+        self: &Arc<Self>,
+        set: &[(&str, Ptx, &[&'static str])],
+    ) -> Result<(), DriverError> {
+        for (module_name, ptx, func_names) in set {
+            if func_names.iter().all(|f| self.has_func(module_name, f)) {
+                continue;
+            }
+            self.load_ptx(ptx.clone(), module_name, func_names)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::sys;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const KERNEL: &str = "extern \"C\" __global__ void init_hook_kernel(float *x) { x[0] = 1.0f; }";
+
+    #[test]
+    fn test_hooks_run_for_each_concurrent_device() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn hook(dev: &Arc<CudaDevice>) -> Result<(), DriverError> {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            let ptx = crate::nvrtc::compile_ptx(KERNEL).unwrap();
+            dev.preload_ptx_set(&[("init_hooks", ptx, &["init_hook_kernel"])])
+        }
+        let hooks = InitHooks::new();
+        hooks.register(hook);
+
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        let dev = CudaDevice::new(0).unwrap();
+                        hooks.run(&dev).unwrap();
+                        dev
+                    })
+                })
+                .collect();
+            let devs: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+            assert_eq!(CALLS.load(Ordering::SeqCst), 8);
+            assert!(devs
+                .iter()
+                .all(|dev| dev.has_func("init_hooks", "init_hook_kernel")));
+        });
+    }
+
+    #[test]
+    fn test_failed_hook_fails_the_device() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn hook(_: &Arc<CudaDevice>) -> Result<(), DriverError> {
+            match CALLS.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_READY)),
+                _ => Ok(()),
+            }
+        }
+        let hooks = InitHooks::new();
+        hooks.register(hook);
+
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(
+            hooks.run(&dev),
+            Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_READY))
+        );
+        hooks.run(&dev).unwrap();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-std"))]
+    fn test_poisoned_hooks_still_run() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        fn hook(_: &Arc<CudaDevice>) -> Result<(), DriverError> {
+            CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+        let hooks = InitHooks::new();
+        hooks.register(hook);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _hooks = hooks.hooks.write().unwrap();
+                panic!("poisons the lock");
+            })
+            .join()
+            .unwrap_err();
+        });
+        hooks.register(hook);
+        let dev = CudaDevice::new(0).unwrap();
+        hooks.run(&dev).unwrap();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_registered_hook_runs_in_constructor() {
+        use core::cell::RefCell;
+        std::thread_local! {
+            // per thread, so that devices of tests running in parallel aren't recorded
+            static DEVICES: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+        }
+        fn hook(dev: &Arc<CudaDevice>) -> Result<(), DriverError> {
+            DEVICES.with_borrow_mut(|devices| devices.push(Arc::as_ptr(dev) as usize));
+            Ok(())
+        }
+        register_device_init_hook(hook);
+
+        let a = CudaDevice::new(0).unwrap();
+        let b = CudaDevice::new(0).unwrap();
+        assert_eq!(
+            DEVICES.take(),
+            [Arc::as_ptr(&a) as usize, Arc::as_ptr(&b) as usize]
+        );
+    }
+
+    #[test]
+    fn test_preload_ptx_set_skips_loaded_modules() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = crate::nvrtc::compile_ptx(KERNEL).unwrap();
+        let set = [("preloaded", ptx, &["init_hook_kernel"][..])];
+        dev.preload_ptx_set(&set).unwrap();
+        let f = dev.get_func("preloaded", "init_hook_kernel").unwrap();
+        dev.preload_ptx_set(&set).unwrap();
+        // not replaced, so the function is the same
+        assert_eq!(
+            f.cu_function,
+            dev.get_func("preloaded", "init_hook_kernel")
+                .unwrap()
+                .cu_function
+        );
+    }
+}
@@ -1,35 +1,50 @@
 use crate::driver::{result, sys};
 
 use super::alloc::DeviceRepr;
-use super::core::{CudaDevice, CudaFunction, CudaModule, CudaStream};
+use super::core::{CudaDevice, CudaFunction, CudaModule, CudaStream, StreamRef};
+use super::stream_tracking::TrackedParams;
+
+#[cfg(feature = "std")]
+use super::recorder::RecordParam;
 
 use std::{sync::Arc, vec::Vec};
 
 impl CudaDevice {
-    /// Whether a module and function are currently loaded into the device.
-    pub fn has_func(self: &Arc<Self>, module_name: &str, func_name: &str) -> bool {
+    /// The module registered under `module_name`, cloned out of the registry so that the
+    /// lock isn't held by the caller.
+    pub(crate) fn module(&self, module_name: &str) -> Option<Arc<CudaModule>> {
         let modules = self.modules.read();
         #[cfg(not(feature = "no-std"))]
         let modules = modules.unwrap();
+        modules.get(module_name).cloned()
+    }
 
-        modules
-            .get(module_name)
-            .map_or(false, |module| module.has_func(func_name))
+    /// Whether a module and function are currently loaded into the device.
+    pub fn has_func(self: &Arc<Self>, module_name: &str, func_name: &str) -> bool {
+        self.module(module_name)
+            .is_some_and(|module| module.has_func(func_name))
     }
 
     /// Retrieves a [CudaFunction] that was registered under `module_name` and `func_name`.
+    ///
+    /// Doesn't wait for a [CudaDevice::load_ptx()] in flight on another thread, see
+    /// [CudaDevice#thread-safety].
     pub fn get_func(self: &Arc<Self>, module_name: &str, func_name: &str) -> Option<CudaFunction> {
-        let modules = self.modules.read();
-        #[cfg(not(feature = "no-std"))]
-        let modules = modules.unwrap();
-
-        modules
-            .get(module_name)
-            .and_then(|m| m.get_func(func_name))
-            .map(|cu_function| CudaFunction {
-                cu_function,
-                device: self.clone(),
-            })
+        let module = self.module(module_name)?;
+        module.get_func(func_name).map(|cu_function| CudaFunction {
+            cu_function,
+            device: self.clone(),
+            max_dynamic_smem: Default::default(),
+            max_threads_per_block: Default::default(),
+            required_cluster_width: Arc::new(u32::MAX.into()),
+            name: Some(func_name.into()),
+            #[cfg(feature = "launch-validation")]
+            num_params: module.num_params.get(func_name).copied(),
+            #[cfg(feature = "launch-validation")]
+            queried_num_params: Default::default(),
+            param_sizes: module.param_sizes.get(func_name).cloned(),
+            library: None,
+        })
     }
 }
 
@@ -47,59 +62,455 @@ impl CudaModule {
 }
 
 impl CudaFunction {
+    /// Launches on `stream`, checking `cfg` against the function's limits first unless
+    /// `checked` is unset by [LaunchAsync::launch_unchecked()].
     #[inline(always)]
     unsafe fn launch_async_impl(
         self,
+        stream: StreamRef<'_>,
         cfg: LaunchConfig,
         params: &mut [*mut std::ffi::c_void],
+        tracked: TrackedParams,
+        checked: bool,
     ) -> Result<(), result::DriverError> {
-        self.device.bind_to_thread()?;
-        result::launch_kernel(
-            self.cu_function,
-            cfg.grid_dim,
-            cfg.block_dim,
-            cfg.shared_mem_bytes,
-            self.device.stream,
-            params,
-        )
+        let _ctx = stream.bind_scoped()?;
+        if checked {
+            self.check_launch_bounds(cfg.block_dim)?;
+            self.check_shared_mem(cfg.shared_mem_bytes, false)?;
+        }
+        let cu_stream = stream.cu_stream();
+        self.device.track_params(cu_stream, tracked, || {
+            let res = result::launch_kernel(
+                self.cu_function,
+                cfg.grid_dim,
+                cfg.block_dim,
+                cfg.shared_mem_bytes,
+                cu_stream,
+                params,
+            );
+            let dims = (cfg.grid_dim, cfg.block_dim, cfg.shared_mem_bytes);
+            self.device.track_launch(self.cu_function, dims, res)
+        })
     }
 
     #[inline(always)]
     unsafe fn launch_cooperative_async_impl(
         self,
+        stream: StreamRef<'_>,
         cfg: LaunchConfig,
         params: &mut [*mut std::ffi::c_void],
+        tracked: TrackedParams,
     ) -> Result<(), result::DriverError> {
-        self.device.bind_to_thread()?;
-        result::launch_cooperative_kernel(
-            self.cu_function,
-            cfg.grid_dim,
-            cfg.block_dim,
-            cfg.shared_mem_bytes,
-            self.device.stream,
-            params,
-        )
+        let _ctx = stream.bind_scoped()?;
+        self.check_launch_bounds(cfg.block_dim)?;
+        self.check_shared_mem(cfg.shared_mem_bytes, false)?;
+        let cu_stream = stream.cu_stream();
+        self.device.track_params(cu_stream, tracked, || {
+            let res = result::launch_cooperative_kernel(
+                self.cu_function,
+                cfg.grid_dim,
+                cfg.block_dim,
+                cfg.shared_mem_bytes,
+                cu_stream,
+                params,
+            );
+            let dims = (cfg.grid_dim, cfg.block_dim, cfg.shared_mem_bytes);
+            self.device.track_launch(self.cu_function, dims, res)
+        })
     }
 
     #[inline(always)]
-    unsafe fn par_launch_async_impl(
+    unsafe fn launch_ex_impl(
         self,
-        stream: &CudaStream,
-        cfg: LaunchConfig,
+        stream: StreamRef<'_>,
+        cfg: LaunchConfigEx,
         params: &mut [*mut std::ffi::c_void],
+        tracked: TrackedParams,
     ) -> Result<(), result::DriverError> {
-        self.device.bind_to_thread()?;
-        result::launch_kernel(
-            self.cu_function,
-            cfg.grid_dim,
-            cfg.block_dim,
-            cfg.shared_mem_bytes,
-            stream.stream,
-            params,
-        )
+        let _ctx = stream.bind_scoped()?;
+        let cu_stream = stream.cu_stream();
+        self.device.track_params(cu_stream, tracked, || {
+            self.launch_ex_untracked(cu_stream, cfg, params)
+        })
+    }
+
+    #[inline(always)]
+    unsafe fn launch_ex_untracked(
+        &self,
+        stream: sys::CUstream,
+        cfg: LaunchConfigEx,
+        params: &mut [*mut std::ffi::c_void],
+    ) -> Result<(), result::DriverError> {
+        if cfg.cluster_dim.is_some() {
+            use sys::CUdevice_attribute::*;
+            let major = self
+                .device
+                .attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?;
+            if major < 9 {
+                let minor = self
+                    .device
+                    .attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?;
+                return Err(result::InvalidLaunch::ClustersUnsupported {
+                    compute_capability: (major, minor),
+                }
+                .into());
+            }
+        }
+        let base = cfg.base;
+        self.check_launch_bounds(base.block_dim)?;
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070"
+        )))]
+        if let Some((width, _, _)) = cfg.cluster_dim {
+            self.check_cluster_width(width)?;
+        }
+        self.check_shared_mem(base.shared_mem_bytes, cfg.auto_raise_smem)?;
+        let dims = (base.grid_dim, base.block_dim, base.shared_mem_bytes);
+        if !cfg.is_extended() {
+            let res = result::launch_kernel(
+                self.cu_function,
+                base.grid_dim,
+                base.block_dim,
+                base.shared_mem_bytes,
+                stream,
+                params,
+            );
+            return self.device.track_launch(self.cu_function, dims, res);
+        }
+
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070"
+        )))]
+        {
+            let mut attrs = cfg.raw_attributes();
+            let config = sys::CUlaunchConfig {
+                gridDimX: base.grid_dim.0,
+                gridDimY: base.grid_dim.1,
+                gridDimZ: base.grid_dim.2,
+                blockDimX: base.block_dim.0,
+                blockDimY: base.block_dim.1,
+                blockDimZ: base.block_dim.2,
+                sharedMemBytes: base.shared_mem_bytes,
+                hStream: stream,
+                attrs: attrs.as_mut_ptr(),
+                numAttrs: attrs.len() as std::ffi::c_uint,
+            };
+            let res = result::launch_kernel_ex(self.cu_function, &config, params);
+            self.device.track_launch(self.cu_function, dims, res)
+        }
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070"
+        ))]
+        Err(result::DriverError::Cuda(
+            sys::CUresult::CUDA_ERROR_NOT_SUPPORTED,
+        ))
     }
+
+    /// Checks that `x * y * z` of `block_dim` is within the function's
+    /// `CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK`, which is only queried for the first launch.
+    fn check_launch_bounds(&self, (x, y, z): (u32, u32, u32)) -> Result<(), result::DriverError> {
+        use core::sync::atomic::Ordering;
+
+        let mut max = self.max_threads_per_block.load(Ordering::Relaxed);
+        if max == 0 {
+            max = unsafe {
+                result::function::get_function_attribute(
+                    self.cu_function,
+                    sys::CUfunction_attribute::CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK,
+                )
+            }? as u32;
+            self.max_threads_per_block.store(max, Ordering::Relaxed);
+        }
+        let requested = x.saturating_mul(y).saturating_mul(z);
+        if requested > max {
+            return Err(result::DriverError::LaunchConfigExceedsBounds {
+                bound: result::LaunchBound::ThreadsPerBlock,
+                max,
+                requested,
+                function: self.name.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the `x` dimension of a cluster against the width required by the function's
+    /// `__cluster_dims__`, if it has any, which is only queried for the first launch with a
+    /// cluster.
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070"
+    )))]
+    fn check_cluster_width(&self, width: u32) -> Result<(), result::DriverError> {
+        use core::sync::atomic::Ordering;
+
+        let mut required = self.required_cluster_width.load(Ordering::Relaxed);
+        if required == u32::MAX {
+            required = unsafe {
+                result::function::get_function_attribute(
+                    self.cu_function,
+                    sys::CUfunction_attribute::CU_FUNC_ATTRIBUTE_REQUIRED_CLUSTER_WIDTH,
+                )
+            }? as u32;
+            self.required_cluster_width
+                .store(required, Ordering::Relaxed);
+        }
+        if required != 0 && required != width {
+            return Err(result::DriverError::LaunchConfigExceedsBounds {
+                bound: result::LaunchBound::ClusterWidth,
+                max: required,
+                requested: width,
+                function: self.name.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks `shared_mem_bytes` against the function's
+    /// `CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES`, raising it up to the device's opt-in
+    /// maximum if `auto_raise` is set. Launches within the cached maximum don't query the driver.
+    fn check_shared_mem(
+        &self,
+        shared_mem_bytes: u32,
+        auto_raise: bool,
+    ) -> Result<(), result::DriverError> {
+        use core::sync::atomic::Ordering;
+        use result::InvalidLaunch;
+        use sys::{CUdevice_attribute_enum as DevAttr, CUfunction_attribute_enum as FuncAttr};
+
+        if shared_mem_bytes <= self.max_dynamic_smem.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        // the attribute may have been raised through another handle to the same function
+        let max = unsafe {
+            result::function::get_function_attribute(
+                self.cu_function,
+                FuncAttr::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES,
+            )
+        }? as u32;
+        self.max_dynamic_smem.store(max, Ordering::Relaxed);
+        if shared_mem_bytes <= max {
+            return Ok(());
+        }
+
+        let static_smem = unsafe {
+            result::function::get_function_attribute(
+                self.cu_function,
+                FuncAttr::CU_FUNC_ATTRIBUTE_SHARED_SIZE_BYTES,
+            )
+        }? as u32;
+        let opt_in_max = (self
+            .device
+            .attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK_OPTIN)?
+            as u32)
+            .saturating_sub(static_smem);
+        if shared_mem_bytes > opt_in_max {
+            Err(InvalidLaunch::SharedMem {
+                requested: shared_mem_bytes,
+                max: opt_in_max,
+            }
+            .into())
+        } else if !auto_raise {
+            Err(InvalidLaunch::SharedMemNotRaised {
+                requested: shared_mem_bytes,
+                max,
+                opt_in_max,
+            }
+            .into())
+        } else {
+            self.set_max_dynamic_shared_mem(shared_mem_bytes)
+        }
+    }
+}
+
+#[cfg(feature = "launch-validation")]
+impl CudaFunction {
+    /// Checks a launch against the function and device limits before it reaches the driver.
+    /// `ptrs` holds [DeviceRepr::device_ptr_param()] for each param, and is empty when the
+    /// params were passed already marshalled.
+    fn validate_launch(
+        &self,
+        cfg: &LaunchConfig,
+        num_params: usize,
+        ptrs: &[Option<sys::CUdeviceptr>],
+    ) -> Result<(), result::DriverError> {
+        use result::InvalidLaunch;
+
+        let (gx, gy, gz) = cfg.grid_dim;
+        let (bx, by, bz) = cfg.block_dim;
+        if gx == 0 || gy == 0 || gz == 0 || bx == 0 || by == 0 || bz == 0 {
+            return Err(InvalidLaunch::ZeroDim {
+                grid_dim: cfg.grid_dim,
+                block_dim: cfg.block_dim,
+            }
+            .into());
+        }
+
+        let dev = &self.device;
+        let LaunchLimits {
+            max_grid,
+            max_block,
+        } = dev.launch_limits()?;
+        if gx > max_grid.0 || gy > max_grid.1 || gz > max_grid.2 {
+            return Err(InvalidLaunch::GridDim {
+                requested: cfg.grid_dim,
+                max: max_grid,
+            }
+            .into());
+        }
+        if bx > max_block.0 || by > max_block.1 || bz > max_block.2 {
+            return Err(InvalidLaunch::BlockDim {
+                requested: cfg.block_dim,
+                max: max_block,
+            }
+            .into());
+        }
+
+        // threads per block and shared memory are checked for every launch, see
+        // `check_launch_bounds` and `check_shared_mem`
+
+        if let Some(expected) = self.declared_num_params()? {
+            if expected != num_params {
+                return Err(InvalidLaunch::ParamCount {
+                    expected,
+                    found: num_params,
+                }
+                .into());
+            }
+        }
+
+        let expected = dev.physical_ordinal as std::ffi::c_int;
+        for (param, ptr) in ptrs.iter().enumerate() {
+            let Some(ptr) = *ptr else {
+                continue;
+            };
+            if ptr == 0 {
+                continue;
+            }
+            let found: std::ffi::c_int = unsafe {
+                result::pointer::get_attribute(
+                    ptr,
+                    sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_DEVICE_ORDINAL,
+                )
+            }?;
+            if found != expected {
+                // reported in the same ordinals as `CudaDevice::ordinal()`
+                let found = match usize::try_from(found) {
+                    Ok(physical) => super::allowlist::to_visible(physical)?
+                        .map_or(-1, |ordinal| ordinal as std::ffi::c_int),
+                    Err(_) => found,
+                };
+                return Err(InvalidLaunch::ForeignPointer {
+                    param,
+                    expected: dev.ordinal() as std::ffi::c_int,
+                    found,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Asks the driver how many params the kernel takes when it can (12.4+), only for the
+    /// first launch, otherwise falls back to the count parsed from the ptx in
+    /// [CudaDevice::load_ptx()].
+    fn declared_num_params(&self) -> Result<Option<usize>, result::DriverError> {
+        #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+        {
+            use core::sync::atomic::Ordering;
+
+            let queried = self.queried_num_params.load(Ordering::Relaxed);
+            if queried != 0 {
+                return Ok(Some(queried - 1));
+            }
+            let mut count = 0;
+            loop {
+                match unsafe { result::function::get_param_info(self.cu_function, count) } {
+                    Ok(_) => count += 1,
+                    Err(result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)) => {
+                        self.queried_num_params.store(count + 1, Ordering::Relaxed);
+                        return Ok(Some(count));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        #[allow(unreachable_code)]
+        Ok(self.num_params)
+    }
+}
+
+/// The grid and block dimension limits of a device, see [CudaDevice::launch_limits()].
+#[cfg(feature = "launch-validation")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LaunchLimits {
+    max_grid: (u32, u32, u32),
+    max_block: (u32, u32, u32),
 }
 
+#[cfg(feature = "launch-validation")]
+impl CudaDevice {
+    /// The grid and block dimension limits, which are only queried for the first validated
+    /// launch.
+    fn launch_limits(&self) -> Result<LaunchLimits, result::DriverError> {
+        use sys::CUdevice_attribute_enum as DevAttr;
+
+        {
+            let limits = self.launch_limits.read();
+            #[cfg(not(feature = "no-std"))]
+            let limits = limits.unwrap();
+            if let Some(limits) = *limits {
+                return Ok(limits);
+            }
+        }
+        let limits = LaunchLimits {
+            max_grid: (
+                self.attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X)? as u32,
+                self.attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y)? as u32,
+                self.attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z)? as u32,
+            ),
+            max_block: (
+                self.attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X)? as u32,
+                self.attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y)? as u32,
+                self.attribute(DevAttr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z)? as u32,
+            ),
+        };
+        #[allow(unused_mut)]
+        let mut cached = self.launch_limits.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut cached = cached.unwrap();
+        *cached = Some(limits);
+        Ok(limits)
+    }
+}
+
+/// CUDA source defining `GRID_STRIDE_LOOP(i, n)`, to prepend to kernel source compiled
+/// with nvrtc and launched with [LaunchConfig::grid_stride()]. It loops a `size_t i` over
+/// the elements `0..n` of the calling thread, however many blocks the grid has:
+///
+/// ```cuda
+/// extern "C" __global__ void fill(unsigned char *out, size_t n, unsigned char value) {
+///     GRID_STRIDE_LOOP(i, n) {
+///         out[i] = value;
+///     }
+/// }
+/// ```
+pub const GRID_STRIDE_SRC: &str = r#"
+#define GRID_STRIDE_LOOP(i, n) \
+    for (size_t i = (size_t)blockIdx.x * blockDim.x + threadIdx.x; i < (size_t)(n); i += (size_t)blockDim.x * gridDim.x)
+"#;
+
 /// Configuration for [result::launch_kernel]
 ///
 /// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html#group__CUDA__EXEC_1gb8f3dc3031b40da29d5f9a7139e52e15)
@@ -123,13 +534,198 @@ impl LaunchConfig {
     /// - shared_mem_bytes == `0`
     pub fn for_num_elems(n: u32) -> Self {
         const NUM_THREADS: u32 = 1024;
-        let num_blocks = (n + NUM_THREADS - 1) / NUM_THREADS;
+        let num_blocks = n.div_ceil(NUM_THREADS);
         Self {
             grid_dim: (num_blocks, 1, 1),
             block_dim: (NUM_THREADS, 1, 1),
             shared_mem_bytes: 0,
         }
     }
+
+    /// Creates a [LaunchConfig] for a kernel looping over `n_elems` elements with
+    /// `GRID_STRIDE_LOOP` (see [GRID_STRIDE_SRC]), so that each thread handles every
+    /// `grid_dim.0 * block_size`th element. Unlike [LaunchConfig::for_num_elems()] it works
+    /// for any number of elements, and doesn't launch more blocks than `f` can keep resident:
+    /// - block_dim == `block_size`
+    /// - grid_dim == `(n_elems + block_size - 1) / block_size`, capped at the number of
+    ///   multiprocessors times [CudaFunction::occupancy_max_active_blocks_per_multiprocessor()],
+    ///   and at `max_blocks`
+    /// - shared_mem_bytes == `0`
+    ///
+    /// Fails with [sys::CUresult::CUDA_ERROR_INVALID_VALUE] when `n_elems` is `0`, or
+    /// `block_size` isn't a non zero multiple of the warp size.
+    pub fn grid_stride(
+        f: &CudaFunction,
+        n_elems: usize,
+        block_size: u32,
+        max_blocks: Option<u32>,
+    ) -> Result<Self, result::DriverError> {
+        let invalid = result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE);
+        let warp_size = f
+            .device
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_WARP_SIZE)?
+            as u32;
+        if n_elems == 0 || block_size == 0 || !block_size.is_multiple_of(warp_size) {
+            return Err(invalid);
+        }
+        let num_sms = f
+            .device
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)?
+            as u32;
+        let blocks_per_sm =
+            f.occupancy_max_active_blocks_per_multiprocessor(block_size, 0, None)?;
+        if blocks_per_sm == 0 {
+            // too many threads, or registers, per block for the function
+            return Err(invalid);
+        }
+        let num_blocks = n_elems
+            .div_ceil(block_size as usize)
+            .min((num_sms * blocks_per_sm) as usize)
+            .min(max_blocks.unwrap_or(u32::MAX).max(1) as usize);
+        Ok(Self {
+            grid_dim: (num_blocks as u32, 1, 1),
+            block_dim: (block_size, 1, 1),
+            shared_mem_bytes: 0,
+        })
+    }
+
+    /// Whether to raise the function's `CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES` when
+    /// `shared_mem_bytes` is more than it allows (48 KB by default) but within the device's
+    /// opt-in maximum. Launches without it fail with
+    /// [result::InvalidLaunch::SharedMemNotRaised] instead.
+    ///
+    /// The attribute stays raised for later launches of the function.
+    ///
+    /// Returns a [LaunchConfigEx], launch it with [LaunchAsync::launch_ex()].
+    pub fn auto_raise_smem(self, auto_raise: bool) -> LaunchConfigEx {
+        LaunchConfigEx::from(self).with_auto_raise_smem(auto_raise)
+    }
+}
+
+/// A launch attribute for [LaunchConfigEx].
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__TYPES.html)
+/// for description of each attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LaunchAttribute {
+    /// `CU_LAUNCH_ATTRIBUTE_COOPERATIVE`
+    Cooperative(bool),
+    /// `CU_LAUNCH_ATTRIBUTE_PROGRAMMATIC_STREAM_SERIALIZATION`: lets the kernel start
+    /// before the previous kernel in the stream has finished (programmatic dependent launch).
+    ProgrammaticStreamSerialization(bool),
+    /// `CU_LAUNCH_ATTRIBUTE_PRIORITY`
+    Priority(i32),
+    /// `CU_LAUNCH_ATTRIBUTE_CLUSTER_SCHEDULING_POLICY_PREFERENCE`
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070"
+    )))]
+    ClusterSchedulingPolicy(sys::CUclusterSchedulingPolicy),
+}
+
+/// A [LaunchConfig] plus extended attributes, launched with `cuLaunchKernelEx`
+/// by [LaunchAsync::launch_ex()].
+///
+/// If there is no cluster dimension and no attributes, the launch goes through
+/// the regular `cuLaunchKernel`. `cuLaunchKernelEx` requires cuda 11.8 or newer.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__EXEC.html)
+#[derive(Clone, Debug)]
+pub struct LaunchConfigEx {
+    /// Grid, block and shared memory configuration.
+    pub base: LaunchConfig,
+
+    /// (x, y, z) dimension of each thread block cluster, in blocks. Requires compute
+    /// capability 9.0 or newer, and each grid dimension must be divisible by it.
+    pub cluster_dim: Option<(u32, u32, u32)>,
+
+    /// Additional attributes of the launch.
+    pub attributes: Vec<LaunchAttribute>,
+
+    /// See [LaunchConfig::auto_raise_smem()].
+    pub auto_raise_smem: bool,
+}
+
+impl From<LaunchConfig> for LaunchConfigEx {
+    fn from(base: LaunchConfig) -> Self {
+        Self {
+            base,
+            cluster_dim: None,
+            attributes: Vec::new(),
+            auto_raise_smem: false,
+        }
+    }
+}
+
+impl LaunchConfigEx {
+    /// Sets [LaunchConfigEx::cluster_dim].
+    pub fn with_cluster_dim(mut self, cluster_dim: (u32, u32, u32)) -> Self {
+        self.cluster_dim = Some(cluster_dim);
+        self
+    }
+
+    /// Appends to [LaunchConfigEx::attributes].
+    pub fn with_attribute(mut self, attribute: LaunchAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    /// Sets [LaunchConfigEx::auto_raise_smem].
+    pub fn with_auto_raise_smem(mut self, auto_raise: bool) -> Self {
+        self.auto_raise_smem = auto_raise;
+        self
+    }
+
+    /// Whether the launch needs `cuLaunchKernelEx`.
+    pub fn is_extended(&self) -> bool {
+        self.cluster_dim.is_some() || !self.attributes.is_empty()
+    }
+
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070"
+    )))]
+    fn raw_attributes(&self) -> Vec<sys::CUlaunchAttribute> {
+        use sys::CUlaunchAttributeID::*;
+        let mut attrs = Vec::with_capacity(self.attributes.len() + 1);
+        if let Some((x, y, z)) = self.cluster_dim {
+            let mut attr = sys::CUlaunchAttribute {
+                id: CU_LAUNCH_ATTRIBUTE_CLUSTER_DIMENSION,
+                ..Default::default()
+            };
+            attr.value.clusterDim.x = x;
+            attr.value.clusterDim.y = y;
+            attr.value.clusterDim.z = z;
+            attrs.push(attr);
+        }
+        for attribute in self.attributes.iter() {
+            let mut attr = sys::CUlaunchAttribute::default();
+            match *attribute {
+                LaunchAttribute::Cooperative(on) => {
+                    attr.id = CU_LAUNCH_ATTRIBUTE_COOPERATIVE;
+                    attr.value.cooperative = on as std::ffi::c_int;
+                }
+                LaunchAttribute::ProgrammaticStreamSerialization(on) => {
+                    attr.id = CU_LAUNCH_ATTRIBUTE_PROGRAMMATIC_STREAM_SERIALIZATION;
+                    attr.value.programmaticStreamSerializationAllowed = on as std::ffi::c_int;
+                }
+                LaunchAttribute::Priority(priority) => {
+                    attr.id = CU_LAUNCH_ATTRIBUTE_PRIORITY;
+                    attr.value.priority = priority;
+                }
+                LaunchAttribute::ClusterSchedulingPolicy(policy) => {
+                    attr.id = CU_LAUNCH_ATTRIBUTE_CLUSTER_SCHEDULING_POLICY_PREFERENCE;
+                    attr.value.clusterSchedulingPolicyPreference = policy;
+                }
+            }
+            attrs.push(attr);
+        }
+        attrs
+    }
 }
 
 /// Consumes a [CudaFunction] to execute asychronously on the device with
@@ -148,9 +744,24 @@ impl LaunchConfig {
 ///     shared_mem_bytes: 0,
 /// };
 /// let params = (1i32, 2u64, 3usize);
-/// unsafe { my_kernel.launch(cfg, params) }.unwrap();
+/// unsafe { my_kernel.launch(dev.default_stream(), cfg, params) }.unwrap();
 /// ```
 ///
+/// # Migrating from `launch(cfg, params)`
+///
+/// Every launch names the stream it runs on, so that generic code takes a [StreamRef]
+/// instead of choosing between two methods:
+///
+/// - `f.launch(cfg, params)` becomes `f.launch(dev.default_stream(), cfg, params)`
+/// - `f.launch_on_stream(&stream, cfg, params)` becomes `f.launch(&stream, cfg, params)`
+/// - `f.launch_cooperative(cfg, params)` becomes
+///   `f.launch_cooperative(dev.default_stream(), cfg, params)`
+/// - `f.launch_ex(cfg, params)` becomes `f.launch_ex(dev.default_stream(), cfg, params)`,
+///   and `f.launch_ex_on_stream(&stream, cfg, params)` becomes
+///   `f.launch_ex(&stream, cfg, params)`
+///
+/// [LaunchAsync::launch_on_stream()] and [LaunchAsync::launch_ex_on_stream()] are deprecated.
+///
 /// # Safety
 ///
 /// This is not safe really ever, because there's no garuntee that `Params`
@@ -163,7 +774,13 @@ impl LaunchConfig {
 ///
 /// See [LaunchAsync::launch] for more details
 pub unsafe trait LaunchAsync<Params> {
-    /// Launches the [CudaFunction] with the corresponding `Params`.
+    /// Launches the [CudaFunction] with the corresponding `Params` on `stream`, either
+    /// [CudaDevice::default_stream()] or a `&`[CudaStream].
+    ///
+    /// Returns [result::DriverError::LaunchConfigExceedsBounds] without launching if the
+    /// block has more threads than the function supports, e.g. because of its
+    /// `__launch_bounds__`, and [result::InvalidLaunch] if it requests more shared memory
+    /// than the function can use. See [LaunchAsync::launch_unchecked()] to skip these checks.
     ///
     /// # Safety
     ///
@@ -203,134 +820,264 @@ pub unsafe trait LaunchAsync<Params> {
     /// on the device's single stream, any kernels launched before
     /// the drop will complete before the value is actually freed.
     ///
-    /// **If you launch a kernel or drop a value on a different stream
-    /// this may not hold**
-    unsafe fn launch(self, cfg: LaunchConfig, params: Params) -> Result<(), result::DriverError>;
-
-    /// Launch the function on a stream concurrent to the device's default
-    /// work stream.
-    ///
-    /// # Safety
-    /// This method is even more unsafe than [LaunchAsync::launch], all the same rules apply,
-    /// except now things are executing in parallel to each other.
-    ///
-    /// That means that if any of the kernels modify the same memory location, you'll get race
-    /// conditions or potentially undefined behavior.
-    unsafe fn launch_on_stream(
+    /// **If you launch a kernel or drop a value on a [CudaStream]
+    /// this may not hold.** Kernels on different streams execute in parallel
+    /// to each other, so if any of them modify the same memory location, you'll
+    /// get race conditions or potentially undefined behavior.
+    unsafe fn launch<'s>(
         self,
-        stream: &CudaStream,
+        stream: impl Into<StreamRef<'s>>,
         cfg: LaunchConfig,
         params: Params,
     ) -> Result<(), result::DriverError>;
 
-    /// Launch the cooperative function on a stream concurrent to the device's default
-    /// work stream.
+    /// [LaunchAsync::launch()] without checking `cfg` against the limits of the function
+    /// first, i.e. without [result::DriverError::LaunchConfigExceedsBounds], the shared memory
+    /// checks of [result::InvalidLaunch] and the `launch-validation` checks. The driver
+    /// still rejects invalid configurations, with a plain
+    /// [sys::CUresult::CUDA_ERROR_INVALID_VALUE].
     ///
-    /// # Safety
-    /// This method is even more unsafe than [LaunchAsync::launch_cooperative], all the same rules apply,
-    /// except now things are executing in parallel to each other.
+    /// The checks cost an atomic load per launch once the function has been launched, so
+    /// this is only worth it for the hottest launch loops. The default implementation is
+    /// [LaunchAsync::launch()], checks included.
     ///
-    /// That means that if any of the kernels modify the same memory location, you'll get race
-    /// conditions or potentially undefined behavior.
-    unsafe fn launch_cooperative(
+    /// # Safety
+    /// All the same rules as [LaunchAsync::launch] apply.
+    unsafe fn launch_unchecked<'s>(
         self,
+        stream: impl Into<StreamRef<'s>>,
         cfg: LaunchConfig,
         params: Params,
-    ) -> Result<(), result::DriverError>;
-}
-
-unsafe impl LaunchAsync<&mut [*mut std::ffi::c_void]> for CudaFunction {
-    #[inline(always)]
-    unsafe fn launch(
-        self,
-        cfg: LaunchConfig,
-        args: &mut [*mut std::ffi::c_void],
-    ) -> Result<(), result::DriverError> {
-        self.launch_async_impl(cfg, args)
+    ) -> Result<(), result::DriverError>
+    where
+        Self: Sized,
+    {
+        self.launch(stream, cfg, params)
     }
 
-    #[inline(always)]
+    /// Launch the function on a stream concurrent to the device's default
+    /// work stream.
+    ///
+    /// # Safety
+    /// All the same rules as [LaunchAsync::launch] apply.
+    #[deprecated(
+        note = "use launch(stream, cfg, params) instead. This will be removed in future versions"
+    )]
     unsafe fn launch_on_stream(
         self,
         stream: &CudaStream,
         cfg: LaunchConfig,
-        args: &mut [*mut std::ffi::c_void],
-    ) -> Result<(), result::DriverError> {
-        self.par_launch_async_impl(stream, cfg, args)
+        params: Params,
+    ) -> Result<(), result::DriverError>
+    where
+        Self: Sized,
+    {
+        self.launch(stream, cfg, params)
     }
 
-    #[inline(always)]
-    unsafe fn launch_cooperative(
+    /// Launches the cooperative function on `stream`.
+    ///
+    /// # Safety
+    /// All the same rules as [LaunchAsync::launch] apply.
+    unsafe fn launch_cooperative<'s>(
         self,
+        stream: impl Into<StreamRef<'s>>,
         cfg: LaunchConfig,
-        args: &mut [*mut std::ffi::c_void],
-    ) -> Result<(), result::DriverError> {
-        self.launch_cooperative_async_impl(cfg, args)
-    }
-}
+        params: Params,
+    ) -> Result<(), result::DriverError>;
 
-unsafe impl LaunchAsync<&mut Vec<*mut std::ffi::c_void>> for CudaFunction {
-    #[inline(always)]
-    unsafe fn launch(
+    /// Launches the function with an extended config on `stream`.
+    ///
+    /// Returns [result::InvalidLaunch::ClustersUnsupported] if [LaunchConfigEx::cluster_dim]
+    /// is set on a device older than compute capability 9.0.
+    ///
+    /// The default implementation launches [LaunchConfigEx::base] with
+    /// [LaunchAsync::launch()], and returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] for
+    /// configs that are [LaunchConfigEx::is_extended()] or set
+    /// [LaunchConfigEx::auto_raise_smem].
+    ///
+    /// # Safety
+    /// All the same rules as [LaunchAsync::launch] apply.
+    unsafe fn launch_ex<'s>(
         self,
-        cfg: LaunchConfig,
-        args: &mut Vec<*mut std::ffi::c_void>,
-    ) -> Result<(), result::DriverError> {
-        self.launch_async_impl(cfg, args)
+        stream: impl Into<StreamRef<'s>>,
+        cfg: LaunchConfigEx,
+        params: Params,
+    ) -> Result<(), result::DriverError>
+    where
+        Self: Sized,
+    {
+        if cfg.is_extended() || cfg.auto_raise_smem {
+            return Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_SUPPORTED,
+            ));
+        }
+        self.launch(stream, cfg.base, params)
     }
 
-    #[inline(always)]
-    unsafe fn launch_on_stream(
+    /// [LaunchAsync::launch_ex()] on `stream`.
+    ///
+    /// # Safety
+    /// All the same rules as [LaunchAsync::launch] apply.
+    #[deprecated(
+        note = "use launch_ex(stream, cfg, params) instead. This will be removed in future versions"
+    )]
+    unsafe fn launch_ex_on_stream(
         self,
         stream: &CudaStream,
-        cfg: LaunchConfig,
-        args: &mut Vec<*mut std::ffi::c_void>,
-    ) -> Result<(), result::DriverError> {
-        self.par_launch_async_impl(stream, cfg, args)
+        cfg: LaunchConfigEx,
+        params: Params,
+    ) -> Result<(), result::DriverError>
+    where
+        Self: Sized,
+    {
+        self.launch_ex(stream, cfg, params)
     }
+}
 
-    #[inline(always)]
-    unsafe fn launch_cooperative(
-        self,
-        cfg: LaunchConfig,
-        args: &mut Vec<*mut std::ffi::c_void>,
-    ) -> Result<(), result::DriverError> {
-        self.launch_cooperative_async_impl(cfg, args)
-    }
+macro_rules! impl_launch_raw {
+    ($Params:ty) => {
+        unsafe impl LaunchAsync<$Params> for CudaFunction {
+            #[inline(always)]
+            unsafe fn launch<'s>(
+                self,
+                stream: impl Into<StreamRef<'s>>,
+                cfg: LaunchConfig,
+                args: $Params,
+            ) -> Result<(), result::DriverError> {
+                #[cfg(feature = "launch-validation")]
+                self.validate_launch(&cfg, args.len(), &[])?;
+                self.launch_async_impl(stream.into(), cfg, args, None, true)
+            }
+
+            #[inline(always)]
+            unsafe fn launch_unchecked<'s>(
+                self,
+                stream: impl Into<StreamRef<'s>>,
+                cfg: LaunchConfig,
+                args: $Params,
+            ) -> Result<(), result::DriverError> {
+                self.launch_async_impl(stream.into(), cfg, args, None, false)
+            }
+
+            #[inline(always)]
+            unsafe fn launch_cooperative<'s>(
+                self,
+                stream: impl Into<StreamRef<'s>>,
+                cfg: LaunchConfig,
+                args: $Params,
+            ) -> Result<(), result::DriverError> {
+                #[cfg(feature = "launch-validation")]
+                self.validate_launch(&cfg, args.len(), &[])?;
+                self.launch_cooperative_async_impl(stream.into(), cfg, args, None)
+            }
+
+            #[inline(always)]
+            unsafe fn launch_ex<'s>(
+                self,
+                stream: impl Into<StreamRef<'s>>,
+                cfg: LaunchConfigEx,
+                args: $Params,
+            ) -> Result<(), result::DriverError> {
+                #[cfg(feature = "launch-validation")]
+                self.validate_launch(&cfg.base, args.len(), &[])?;
+                self.launch_ex_impl(stream.into(), cfg, args, None)
+            }
+        }
+    };
 }
 
+impl_launch_raw!(&mut [*mut std::ffi::c_void]);
+impl_launch_raw!(&mut Vec<*mut std::ffi::c_void>);
+
 macro_rules! impl_launch {
     ([$($Vars:tt),*], [$($Idx:tt),*]) => {
 unsafe impl<$($Vars: DeviceRepr),*> LaunchAsync<($($Vars, )*)> for CudaFunction {
     #[inline(always)]
-    unsafe fn launch(
+    unsafe fn launch<'s>(
         self,
+        stream: impl Into<StreamRef<'s>>,
         cfg: LaunchConfig,
         args: ($($Vars, )*)
     ) -> Result<(), result::DriverError> {
+        let stream = stream.into();
+        #[cfg(feature = "launch-validation")]
+        {
+            let ptrs = [$(args.$Idx.device_ptr_param(), )*];
+            self.validate_launch(&cfg, ptrs.len(), &ptrs)?;
+        }
+        #[cfg(feature = "std")]
+        if self.device.launch_recording() {
+            let recorded = [$(RecordParam::new(&args.$Idx), )*];
+            self.record_launch(stream.cu_stream(), &cfg, false, &recorded)?;
+        }
         let params = &mut [$(args.$Idx.as_kernel_param(), )*];
-        self.launch_async_impl(cfg, params)
+        let tracked = self.device.stream_tracking().then(|| [$(args.$Idx.tracked_access(), )*]);
+        self.launch_async_impl(stream, cfg, params, tracked.as_ref().map(|t| &t[..]), true)
     }
 
     #[inline(always)]
-    unsafe fn launch_cooperative(
+    unsafe fn launch_unchecked<'s>(
         self,
+        stream: impl Into<StreamRef<'s>>,
         cfg: LaunchConfig,
         args: ($($Vars, )*)
     ) -> Result<(), result::DriverError> {
+        let stream = stream.into();
+        #[cfg(feature = "std")]
+        if self.device.launch_recording() {
+            let recorded = [$(RecordParam::new(&args.$Idx), )*];
+            self.record_launch(stream.cu_stream(), &cfg, false, &recorded)?;
+        }
         let params = &mut [$(args.$Idx.as_kernel_param(), )*];
-        self.launch_cooperative_async_impl(cfg, params)
+        let tracked = self.device.stream_tracking().then(|| [$(args.$Idx.tracked_access(), )*]);
+        self.launch_async_impl(stream, cfg, params, tracked.as_ref().map(|t| &t[..]), false)
     }
 
     #[inline(always)]
-    unsafe fn launch_on_stream(
+    unsafe fn launch_cooperative<'s>(
         self,
-        stream: &CudaStream,
+        stream: impl Into<StreamRef<'s>>,
         cfg: LaunchConfig,
         args: ($($Vars, )*)
     ) -> Result<(), result::DriverError> {
+        let stream = stream.into();
+        #[cfg(feature = "launch-validation")]
+        {
+            let ptrs = [$(args.$Idx.device_ptr_param(), )*];
+            self.validate_launch(&cfg, ptrs.len(), &ptrs)?;
+        }
+        #[cfg(feature = "std")]
+        if self.device.launch_recording() {
+            let recorded = [$(RecordParam::new(&args.$Idx), )*];
+            self.record_launch(stream.cu_stream(), &cfg, true, &recorded)?;
+        }
         let params = &mut [$(args.$Idx.as_kernel_param(), )*];
-        self.par_launch_async_impl(stream, cfg, params)
+        let tracked = self.device.stream_tracking().then(|| [$(args.$Idx.tracked_access(), )*]);
+        self.launch_cooperative_async_impl(stream, cfg, params, tracked.as_ref().map(|t| &t[..]))
+    }
+
+    #[inline(always)]
+    unsafe fn launch_ex<'s>(
+        self,
+        stream: impl Into<StreamRef<'s>>,
+        cfg: LaunchConfigEx,
+        args: ($($Vars, )*)
+    ) -> Result<(), result::DriverError> {
+        let stream = stream.into();
+        #[cfg(feature = "launch-validation")]
+        {
+            let ptrs = [$(args.$Idx.device_ptr_param(), )*];
+            self.validate_launch(&cfg.base, ptrs.len(), &ptrs)?;
+        }
+        #[cfg(feature = "std")]
+        if self.device.launch_recording() {
+            let recorded = [$(RecordParam::new(&args.$Idx), )*];
+            self.record_launch(stream.cu_stream(), &cfg.base, false, &recorded)?;
+        }
+        let params = &mut [$(args.$Idx.as_kernel_param(), )*];
+        let tracked = self.device.stream_tracking().then(|| [$(args.$Idx.tracked_access(), )*]);
+        self.launch_ex_impl(stream, cfg, params, tracked.as_ref().map(|t| &t[..]))
     }
 }
     };
@@ -363,8 +1110,8 @@ mod tests {
     use std::time::Instant;
 
     use crate::{
-        driver::{DeviceSlice, DriverError},
-        nvrtc::compile_ptx_with_opts,
+        driver::{CudaSlice, DeviceSlice, DriverError},
+        nvrtc::{compile_ptx, compile_ptx_with_opts},
     };
 
     use super::*;
@@ -413,6 +1160,7 @@ extern \"C\" __global__ void sin_kernel(float *out, const float *inp, size_t num
 
         unsafe {
             sin_kernel.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(10),
                 (&mut b_dev, &a_dev, 10usize),
             )
@@ -443,7 +1191,7 @@ extern \"C\" __global__ void sin_kernel(float *out, const float *inp, size_t num
 
             let sin_kernel = dev.get_func("sin", "sin_kernel").unwrap();
             let cfg = LaunchConfig::for_num_elems(numel as u32);
-            unsafe { sin_kernel.launch(cfg, (&mut b, &a, numel)) }.unwrap();
+            unsafe { sin_kernel.launch(dev.default_stream(), cfg, (&mut b, &a, numel)) }.unwrap();
 
             let b = dev.sync_reclaim(b).unwrap();
             for v in b {
@@ -468,8 +1216,14 @@ extern \"C\" __global__ void sin_kernel(float *out, const float *inp, size_t num
             let mut b_sub = b_dev.try_slice_mut(i * 2..).unwrap();
             assert_eq!(b_sub.len, 10 - 2 * i);
             let f = dev.get_func("sin", "sin_kernel").unwrap();
-            unsafe { f.launch(LaunchConfig::for_num_elems(2), (&mut b_sub, &a_sub, 2usize)) }
-                .unwrap();
+            unsafe {
+                f.launch(
+                    dev.default_stream(),
+                    LaunchConfig::for_num_elems(2),
+                    (&mut b_sub, &a_sub, 2usize),
+                )
+            }
+            .unwrap();
         }
 
         let b_host = dev.sync_reclaim(b_dev).unwrap();
@@ -525,6 +1279,7 @@ extern \"C\" __global__ void floating(float f, double d) {
         let f = dev.get_func("tests", "int_8bit").unwrap();
         unsafe {
             f.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(1),
                 (i8::MIN, i8::MAX, u8::MIN, u8::MAX),
             )
@@ -542,6 +1297,7 @@ extern \"C\" __global__ void floating(float f, double d) {
         let f = dev.get_func("tests", "int_16bit").unwrap();
         unsafe {
             f.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(1),
                 (i16::MIN, i16::MAX, u16::MIN, u16::MAX),
             )
@@ -558,6 +1314,7 @@ extern \"C\" __global__ void floating(float f, double d) {
         let f = dev.get_func("tests", "int_32bit").unwrap();
         unsafe {
             f.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(1),
                 (i32::MIN, i32::MAX, u32::MIN, u32::MAX),
             )
@@ -574,6 +1331,7 @@ extern \"C\" __global__ void floating(float f, double d) {
         let f = dev.get_func("tests", "int_64bit").unwrap();
         unsafe {
             f.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(1),
                 (i64::MIN, i64::MAX, u64::MIN, u64::MAX),
             )
@@ -590,6 +1348,7 @@ extern \"C\" __global__ void floating(float f, double d) {
         let f = dev.get_func("tests", "floating").unwrap();
         unsafe {
             f.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(1),
                 (1.2345678f32, -10.123456789876543f64),
             )
@@ -626,6 +1385,7 @@ extern \"C\" __global__ void halfs(__half h) {
         let f = dev.get_func("tests", "halfs").unwrap();
         unsafe {
             f.launch(
+                dev.default_stream(),
                 LaunchConfig::for_num_elems(1),
                 (half::f16::from_f32(1.234),),
             )
@@ -658,9 +1418,9 @@ extern \"C\" __global__ void slow_worker(const float *data, const size_t len, fl
         {
             // launch two kernels on the default stream
             let f = dev.get_func("tests", "slow_worker").unwrap();
-            unsafe { f.launch(cfg, (&slice, slice.len(), &mut a))? };
+            unsafe { f.launch(dev.default_stream(), cfg, (&slice, slice.len(), &mut a))? };
             let f = dev.get_func("tests", "slow_worker").unwrap();
-            unsafe { f.launch(cfg, (&slice, slice.len(), &mut b))? };
+            unsafe { f.launch(dev.default_stream(), cfg, (&slice, slice.len(), &mut b))? };
             dev.synchronize()?;
         }
         let double_launch_s = start.elapsed().as_secs_f64();
@@ -670,9 +1430,9 @@ extern \"C\" __global__ void slow_worker(const float *data, const size_t len, fl
             // create a new stream & launch them concurrently
             let stream = dev.fork_default_stream()?;
             let f = dev.get_func("tests", "slow_worker").unwrap();
-            unsafe { f.launch(cfg, (&slice, slice.len(), &mut a))? };
+            unsafe { f.launch(dev.default_stream(), cfg, (&slice, slice.len(), &mut a))? };
             let f = dev.get_func("tests", "slow_worker").unwrap();
-            unsafe { f.launch_on_stream(&stream, cfg, (&slice, slice.len(), &mut b))? };
+            unsafe { f.launch(&stream, cfg, (&slice, slice.len(), &mut b))? };
             dev.wait_for(&stream)?;
             dev.synchronize()?;
         }
@@ -686,4 +1446,384 @@ extern \"C\" __global__ void slow_worker(const float *data, const size_t len, fl
         );
         Ok(())
     }
+
+    #[test]
+    fn test_launch_generic_over_stream() {
+        fn sin<'s>(
+            dev: &Arc<CudaDevice>,
+            stream: impl Into<StreamRef<'s>>,
+            b: &mut CudaSlice<f32>,
+            a: &CudaSlice<f32>,
+        ) -> Result<(), DriverError> {
+            let f = dev.get_func("sin", "sin_kernel").unwrap();
+            unsafe { f.launch(stream, LaunchConfig::for_num_elems(10), (b, a, 10usize)) }
+        }
+
+        let ptx = compile_ptx_with_opts(SIN_CU, Default::default()).unwrap();
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(ptx, "sin", &["sin_kernel"]).unwrap();
+        let a_host = [-1.0f32, -0.8, -0.6, -0.4, -0.2, 0.2, 0.4, 0.6, 0.8, 1.0];
+        let a = dev.htod_copy(a_host.to_vec()).unwrap();
+        let check = |b: &CudaSlice<f32>| {
+            let b_host = dev.dtoh_sync_copy(b).unwrap();
+            for (a_i, b_i) in a_host.iter().zip(b_host.iter()) {
+                assert!((b_i - a_i.sin()).abs() <= 1e-6);
+            }
+        };
+
+        let mut b = dev.alloc_zeros::<f32>(10).unwrap();
+        assert_eq!(dev.default_stream().cu_stream(), dev.stream);
+        sin(&dev, dev.default_stream(), &mut b, &a).unwrap();
+        check(&b);
+
+        let stream = dev.fork_default_stream().unwrap();
+        let mut c = dev.alloc_zeros::<f32>(10).unwrap();
+        sin(&dev, &stream, &mut c, &a).unwrap();
+        dev.wait_for(&stream).unwrap();
+        check(&c);
+
+        let mut d = dev.alloc_zeros::<f32>(10).unwrap();
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        let cfg = LaunchConfig::for_num_elems(10);
+        #[allow(deprecated)]
+        unsafe { f.launch_on_stream(&stream, cfg, (&mut d, &a, 10usize)) }.unwrap();
+        dev.wait_for(&stream).unwrap();
+        check(&d);
+    }
+
+    #[cfg(feature = "launch-validation")]
+    #[test]
+    fn test_launch_validation() {
+        use crate::driver::result::InvalidLaunch;
+
+        let ptx = compile_ptx_with_opts(SIN_CU, Default::default()).unwrap();
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(ptx, "sin", &["sin_kernel"]).unwrap();
+        let a = dev.alloc_zeros::<f32>(10).unwrap();
+        let mut b = dev.alloc_zeros::<f32>(10).unwrap();
+
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        let err = unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(10),
+                (&mut b, &a),
+            )
+        }
+        .unwrap_err();
+        assert_eq!(
+            err,
+            DriverError::InvalidLaunch(InvalidLaunch::ParamCount {
+                expected: 3,
+                found: 2
+            })
+        );
+
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        let cfg = LaunchConfig {
+            grid_dim: (0, 1, 1),
+            block_dim: (1, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let err =
+            unsafe { f.launch(dev.default_stream(), cfg, (&mut b, &a, 10usize)) }.unwrap_err();
+        assert!(matches!(
+            err,
+            DriverError::InvalidLaunch(InvalidLaunch::ZeroDim { .. })
+        ));
+
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        let cfg = LaunchConfig {
+            block_dim: (2048, 1, 1),
+            ..LaunchConfig::for_num_elems(10)
+        };
+        let err =
+            unsafe { f.launch(dev.default_stream(), cfg, (&mut b, &a, 10usize)) }.unwrap_err();
+        assert!(matches!(err, DriverError::InvalidLaunch(_)));
+
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(10),
+                (&mut b, &a, 10usize),
+            )
+        }
+        .unwrap();
+    }
+
+    #[test]
+    fn test_launch_bounds() {
+        use crate::driver::result::LaunchBound;
+
+        const BOUNDED_CU: &str = "
+extern \"C\" __global__ void __launch_bounds__(256) bounded(float *out, size_t n) {
+    size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) {
+        out[i] = i;
+    }
+}";
+        let ptx = compile_ptx(BOUNDED_CU).unwrap();
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(ptx, "bounded", &["bounded"]).unwrap();
+        let f = dev.get_func("bounded", "bounded").unwrap();
+        let mut out = dev.alloc_zeros::<f32>(512).unwrap();
+
+        let cfg = LaunchConfig {
+            grid_dim: (1, 1, 1),
+            block_dim: (128, 2, 2),
+            shared_mem_bytes: 0,
+        };
+        let err = unsafe {
+            f.clone()
+                .launch(dev.default_stream(), cfg, (&mut out, 512usize))
+        }
+        .unwrap_err();
+        match err {
+            DriverError::LaunchConfigExceedsBounds {
+                bound,
+                max,
+                requested,
+                function,
+            } => {
+                assert_eq!(bound, LaunchBound::ThreadsPerBlock);
+                assert!(max <= 256);
+                assert_eq!(requested, 512);
+                assert_eq!(function.as_deref(), Some("bounded"));
+            }
+            err => panic!("unexpected {err:?}"),
+        }
+
+        // without the checks the driver rejects the launch on its own
+        let err = unsafe {
+            f.clone()
+                .launch_unchecked(dev.default_stream(), cfg, (&mut out, 512usize))
+        }
+        .unwrap_err();
+        assert_eq!(
+            err,
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+
+        let cfg = LaunchConfig {
+            grid_dim: (2, 1, 1),
+            block_dim: (256, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        unsafe { f.launch(dev.default_stream(), cfg, (&mut out, 512usize)) }.unwrap();
+        let out = dev.dtoh_sync_copy(&out).unwrap();
+        assert_eq!(out[511], 511.0);
+    }
+
+    #[test]
+    fn test_launch_smem_opt_in() {
+        use crate::driver::result::InvalidLaunch;
+        use sys::CUdevice_attribute::*;
+
+        const SMEM: u32 = 64 * 1024;
+        let dev = CudaDevice::new(0).unwrap();
+        let opt_in_max = dev
+            .attribute(CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK_OPTIN)
+            .unwrap() as u32;
+        if opt_in_max < SMEM {
+            // pre Volta devices can't opt in to more than 48 KB
+            return;
+        }
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void smem_sum(float *out, size_t n) {
+                extern __shared__ float buf[];
+                for (size_t i = threadIdx.x; i < n; i += blockDim.x) { buf[i] = 1.0f; }
+                __syncthreads();
+                if (threadIdx.x == 0) {
+                    float sum = 0.0f;
+                    for (size_t i = 0; i < n; i++) { sum += buf[i]; }
+                    *out = sum;
+                }
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "smem", &["smem_sum"]).unwrap();
+        let mut out = dev.alloc_zeros::<f32>(1).unwrap();
+        let n = SMEM as usize / 4;
+        let cfg = LaunchConfig {
+            grid_dim: (1, 1, 1),
+            block_dim: (256, 1, 1),
+            shared_mem_bytes: SMEM,
+        };
+
+        let f = dev.get_func("smem", "smem_sum").unwrap();
+        let err =
+            unsafe { f.clone().launch(dev.default_stream(), cfg, (&mut out, n)) }.unwrap_err();
+        assert!(matches!(
+            err,
+            DriverError::InvalidLaunch(InvalidLaunch::SharedMemNotRaised {
+                requested: SMEM,
+                max: 49152,
+                ..
+            })
+        ));
+
+        unsafe {
+            f.clone().launch_ex(
+                dev.default_stream(),
+                cfg.auto_raise_smem(true),
+                (&mut out, n),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [n as f32]);
+        // the attribute stays raised
+        unsafe { f.launch(dev.default_stream(), cfg, (&mut out, n)) }.unwrap();
+
+        let f = dev.get_func("smem", "smem_sum").unwrap();
+        let cfg = LaunchConfig {
+            shared_mem_bytes: opt_in_max + 1,
+            ..cfg
+        };
+        let err = unsafe {
+            f.launch_ex(
+                dev.default_stream(),
+                cfg.auto_raise_smem(true),
+                (&mut out, n),
+            )
+        }
+        .unwrap_err();
+        assert_eq!(
+            err,
+            DriverError::InvalidLaunch(InvalidLaunch::SharedMem {
+                requested: opt_in_max + 1,
+                max: opt_in_max,
+            })
+        );
+    }
+
+    #[test]
+    fn test_launch_ex_clusters() {
+        use crate::driver::result::InvalidLaunch;
+        use sys::CUdevice_attribute::*;
+
+        let ptx = compile_ptx_with_opts(SIN_CU, Default::default()).unwrap();
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(ptx, "sin", &["sin_kernel"]).unwrap();
+        let a_host = [1.0f32, 2.0, 3.0, 4.0];
+        let a = dev.htod_copy(a_host.to_vec()).unwrap();
+        let mut b = dev.alloc_zeros::<f32>(4).unwrap();
+        let cfg = LaunchConfig {
+            grid_dim: (2, 1, 1),
+            block_dim: (2, 1, 1),
+            shared_mem_bytes: 0,
+        };
+
+        // no extended attributes goes through cuLaunchKernel
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        unsafe { f.launch_ex(dev.default_stream(), cfg.into(), (&mut b, &a, 4usize)) }.unwrap();
+        let b_host = dev.dtoh_sync_copy(&b).unwrap();
+        for (a_i, b_i) in a_host.iter().zip(b_host.iter()) {
+            assert!((a_i.sin() - b_i).abs() <= 1e-6);
+        }
+
+        let major = dev
+            .attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)
+            .unwrap();
+        let minor = dev
+            .attribute(CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)
+            .unwrap();
+        let cfg = LaunchConfigEx::from(cfg).with_cluster_dim((2, 1, 1));
+        let f = dev.get_func("sin", "sin_kernel").unwrap();
+        let res = unsafe { f.launch_ex(dev.default_stream(), cfg, (&mut b, &a, 4usize)) };
+        if major < 9 {
+            assert_eq!(
+                res,
+                Err(DriverError::InvalidLaunch(
+                    InvalidLaunch::ClustersUnsupported {
+                        compute_capability: (major, minor)
+                    }
+                ))
+            );
+        } else {
+            res.unwrap();
+            dev.synchronize().unwrap();
+        }
+    }
+
+    const GRID_STRIDE_CU: &str = "
+extern \"C\" __global__ void fill(unsigned char *out, size_t n, unsigned char value) {
+    GRID_STRIDE_LOOP(i, n) {
+        out[i] = value;
+    }
+}
+extern \"C\" __global__ void count_ne(const unsigned char *x, size_t n, unsigned char value, unsigned long long *count) {
+    GRID_STRIDE_LOOP(i, n) {
+        if (x[i] != value) {
+            atomicAdd(count, 1ull);
+        }
+    }
+}";
+
+    #[test]
+    fn test_grid_stride() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx(std::format!("{GRID_STRIDE_SRC}{GRID_STRIDE_CU}")).unwrap();
+        dev.load_ptx(ptx, "grid_stride", &["fill", "count_ne"])
+            .unwrap();
+        let fill = dev.get_func("grid_stride", "fill").unwrap();
+
+        let invalid = Some(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        assert_eq!(
+            LaunchConfig::grid_stride(&fill, 0, 256, None).err(),
+            invalid
+        );
+        assert_eq!(
+            LaunchConfig::grid_stride(&fill, 17, 100, None).err(),
+            invalid
+        );
+        assert_eq!(LaunchConfig::grid_stride(&fill, 17, 0, None).err(), invalid);
+
+        // small counts launch a single block
+        let cfg = LaunchConfig::grid_stride(&fill, 17, 256, None).unwrap();
+        assert_eq!(cfg.grid_dim, (1, 1, 1));
+        assert_eq!(cfg.block_dim, (256, 1, 1));
+        let mut x = dev.alloc_zeros::<u8>(20).unwrap();
+        unsafe {
+            fill.clone().launch(
+                dev.default_stream(),
+                cfg,
+                (&mut x.slice_mut(..17), 17usize, 3u8),
+            )
+        }
+        .unwrap();
+        let mut expected = [3u8; 20];
+        expected[17..].fill(0);
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), expected);
+
+        let cfg = LaunchConfig::grid_stride(&fill, 1 << 30, 256, Some(7)).unwrap();
+        assert_eq!(cfg.grid_dim, (7, 1, 1));
+    }
+
+    #[test]
+    #[ignore = "needs 5 GB of free device memory"]
+    fn test_grid_stride_over_u32_max() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx(std::format!("{GRID_STRIDE_SRC}{GRID_STRIDE_CU}")).unwrap();
+        dev.load_ptx(ptx, "grid_stride", &["fill", "count_ne"])
+            .unwrap();
+        let fill = dev.get_func("grid_stride", "fill").unwrap();
+        let count_ne = dev.get_func("grid_stride", "count_ne").unwrap();
+
+        const N: usize = 5_000_000_000;
+        let cfg = LaunchConfig::grid_stride(&fill, N, 256, None).unwrap();
+        let num_sms = dev
+            .attribute(sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)
+            .unwrap() as u32;
+        let blocks_per_sm = fill
+            .occupancy_max_active_blocks_per_multiprocessor(256, 0, None)
+            .unwrap();
+        assert_eq!(cfg.grid_dim, (num_sms * blocks_per_sm, 1, 1));
+        let mut x = unsafe { dev.alloc::<u8>(N) }.unwrap();
+        unsafe { fill.launch(dev.default_stream(), cfg, (&mut x, N, 5u8)) }.unwrap();
+        let mut count = dev.alloc_zeros::<u64>(1).unwrap();
+        let cfg = LaunchConfig::grid_stride(&count_ne, N, 256, None).unwrap();
+        unsafe { count_ne.launch(dev.default_stream(), cfg, (&x, N, 5u8, &mut count)) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&count).unwrap(), [0]);
+    }
 }
@@ -0,0 +1,303 @@
+use crate::driver::{
+    result::{DriverError, LaunchRecord},
+    sys,
+};
+
+use super::core::CudaDevice;
+
+use core::marker::PhantomData;
+use std::{string::String, sync::atomic::Ordering, sync::Arc, vec::Vec};
+
+#[cfg(not(feature = "no-std"))]
+std::thread_local! {
+    /// The full path of each active [LaunchScope] of this thread, and the address of the
+    /// device it's for.
+    static SCOPES: core::cell::RefCell<Vec<(usize, Arc<str>)>> =
+        const { core::cell::RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug)]
+struct LaunchEntry {
+    seq: u64,
+    cu_function: sys::CUfunction,
+    grid_dim: (u32, u32, u32),
+    block_dim: (u32, u32, u32),
+    shared_mem_bytes: u32,
+    scope: Option<Arc<str>>,
+}
+
+/// The launches recorded by [CudaDevice::set_launch_tracking()].
+#[derive(Debug, Default)]
+pub(crate) struct LaunchLog {
+    capacity: usize,
+    next_seq: u64,
+    /// A ring buffer, the oldest entry is at `next_seq % capacity` once full.
+    entries: Vec<LaunchEntry>,
+    /// The full path of each active [LaunchScope], without threads to keep them apart.
+    #[cfg(feature = "no-std")]
+    scopes: Vec<Arc<str>>,
+}
+
+impl CudaDevice {
+    /// Records the last `capacity` kernel launches on this device, so that a sticky error
+    /// from a faulting kernel (e.g. an illegal address) returned by [CudaDevice::synchronize()]
+    /// or a later launch is a [DriverError::KernelFault] listing them. `0` disables tracking,
+    /// which is the default.
+    ///
+    /// Launches of functions from [CudaDevice::get_func()] are recorded with their name.
+    /// Use [CudaDevice::scope()] to tag launches with the stage of a pipeline.
+    pub fn set_launch_tracking(&self, capacity: usize) {
+        #[allow(unused_mut)]
+        let mut log = self.launch_log.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut log = log.unwrap();
+        log.capacity = capacity;
+        log.next_seq = 0;
+        log.entries.clear();
+        self.launch_tracking.store(capacity > 0, Ordering::Relaxed);
+    }
+
+    /// The launches currently recorded by [CudaDevice::set_launch_tracking()], oldest first.
+    pub fn recent_launches(&self) -> Vec<LaunchRecord> {
+        let log = self.launch_log.read();
+        #[cfg(not(feature = "no-std"))]
+        let log = log.unwrap();
+        self.launch_records(&log)
+    }
+
+    /// Tags launches recorded by [CudaDevice::set_launch_tracking()] with `name` until the
+    /// returned guard is dropped. Scopes nest, and are recorded joined by `/`.
+    ///
+    /// The scope applies to launches from the calling thread, and guards must be dropped in
+    /// the reverse order they were created.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::CudaDevice;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// dev.set_launch_tracking(64);
+    /// {
+    ///     let _forward = dev.scope("forward");
+    ///     // launches here are recorded with scope "forward"
+    /// }
+    /// ```
+    pub fn scope(&self, name: &str) -> LaunchScope<'_> {
+        let path: Arc<str> = match self.current_scope() {
+            Some(parent) => std::format!("{parent}/{name}").into(),
+            None => name.into(),
+        };
+        #[cfg(not(feature = "no-std"))]
+        SCOPES.with(|scopes| {
+            scopes
+                .borrow_mut()
+                .push((self as *const Self as usize, path))
+        });
+        #[cfg(feature = "no-std")]
+        self.launch_log.write().scopes.push(path);
+        LaunchScope {
+            device: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// The innermost [LaunchScope] of the calling thread on this device.
+    #[cfg(not(feature = "no-std"))]
+    fn current_scope(&self) -> Option<Arc<str>> {
+        let device = self as *const Self as usize;
+        SCOPES.with(|scopes| {
+            scopes
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(d, _)| *d == device)
+                .map(|(_, path)| path.clone())
+        })
+    }
+
+    #[cfg(feature = "no-std")]
+    fn current_scope(&self) -> Option<Arc<str>> {
+        self.launch_log.read().scopes.last().cloned()
+    }
+
+    /// Records a successful launch, or turns a sticky error from the launch into a
+    /// [DriverError::KernelFault], poisoning the device.
+    pub(crate) fn track_launch(
+        &self,
+        cu_function: sys::CUfunction,
+        (grid_dim, block_dim, shared_mem_bytes): ((u32, u32, u32), (u32, u32, u32), u32),
+        res: Result<(), DriverError>,
+    ) -> Result<(), DriverError> {
+        if let Err(e) = res {
+            return Err(self.annotate_error(e));
+        }
+        if !self.launch_tracking.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let scope = self.current_scope();
+        #[allow(unused_mut)]
+        let mut log = self.launch_log.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut log = log.unwrap();
+        if log.capacity == 0 {
+            return Ok(());
+        }
+        let entry = LaunchEntry {
+            seq: log.next_seq,
+            cu_function,
+            grid_dim,
+            block_dim,
+            shared_mem_bytes,
+            scope,
+        };
+        let i = (log.next_seq % log.capacity as u64) as usize;
+        if i < log.entries.len() {
+            log.entries[i] = entry;
+        } else {
+            log.entries.push(entry);
+        }
+        log.next_seq += 1;
+        Ok(())
+    }
+
+    /// Turns a sticky error into a [DriverError::KernelFault] if launch tracking is enabled,
+    /// and poisons the device, see [CudaDevice::is_poisoned()].
+    pub(crate) fn annotate_error(&self, e: DriverError) -> DriverError {
+        self.poison(&e);
+        let code = match e {
+            DriverError::Cuda(code) if e.is_sticky() => code,
+            e => return e,
+        };
+        if !self.launch_tracking.load(Ordering::Relaxed) {
+            return e;
+        }
+        let log = self.launch_log.read();
+        #[cfg(not(feature = "no-std"))]
+        let log = log.unwrap();
+        let recent_launches = self.launch_records(&log).into();
+        DriverError::KernelFault {
+            code,
+            recent_launches,
+        }
+    }
+
+    fn launch_records(&self, log: &LaunchLog) -> Vec<LaunchRecord> {
+        let modules = self.modules.read();
+        #[cfg(not(feature = "no-std"))]
+        let modules = modules.unwrap();
+        let name_of = |cu_function: sys::CUfunction| {
+            modules.values().find_map(|module| {
+                module
+                    .functions
+                    .iter()
+                    .find(|(_, &f)| f == cu_function)
+                    .map(|(name, _)| name.clone())
+            })
+        };
+
+        let mut entries: Vec<&LaunchEntry> = log.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.seq);
+        entries
+            .into_iter()
+            .map(|entry| LaunchRecord {
+                seq: entry.seq,
+                function: name_of(entry.cu_function),
+                grid_dim: entry.grid_dim,
+                block_dim: entry.block_dim,
+                shared_mem_bytes: entry.shared_mem_bytes,
+                scope: entry.scope.as_deref().map(String::from),
+            })
+            .collect()
+    }
+}
+
+/// Tags recorded launches of the thread that created it until dropped, see
+/// [CudaDevice::scope()].
+#[derive(Debug)]
+pub struct LaunchScope<'a> {
+    device: &'a CudaDevice,
+    /// scopes are per thread, so the guard must be dropped on the thread that made it
+    marker: PhantomData<*const ()>,
+}
+
+impl Drop for LaunchScope<'_> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "no-std"))]
+        SCOPES.with(|scopes| {
+            let device = self.device as *const CudaDevice as usize;
+            let mut scopes = scopes.borrow_mut();
+            if let Some(i) = scopes.iter().rposition(|(d, _)| *d == device) {
+                scopes.remove(i);
+            }
+        });
+        #[cfg(feature = "no-std")]
+        self.device.launch_log.write().scopes.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::driver::{result::DriverError, sys, LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    use super::*;
+
+    #[test]
+    fn test_launch_tracking_ring_buffer_and_scopes() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx("extern \"C\" __global__ void noop(int x) {}").unwrap();
+        dev.load_ptx(ptx, "tracking", &["noop"]).unwrap();
+        dev.set_launch_tracking(3);
+
+        let cfg = LaunchConfig::for_num_elems(1);
+        for i in 0..4 {
+            let _stage = dev.scope("stage");
+            let _inner = (i == 3).then(|| dev.scope("last"));
+            let f = dev.get_func("tracking", "noop").unwrap();
+            unsafe { f.launch(dev.default_stream(), cfg, (i,)) }.unwrap();
+        }
+
+        let launches = dev.recent_launches();
+        assert_eq!(
+            launches.iter().map(|l| l.seq).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(launches
+            .iter()
+            .all(|l| l.function.as_deref() == Some("noop")));
+        assert_eq!(launches[1].scope.as_deref(), Some("stage"));
+        assert_eq!(launches[2].scope.as_deref(), Some("stage/last"));
+
+        // scopes of other threads don't apply
+        let f = dev.get_func("tracking", "noop").unwrap();
+        let (entered, exit) = (std::sync::Barrier::new(2), std::sync::Barrier::new(2));
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _other = dev.scope("other");
+                entered.wait();
+                exit.wait();
+            });
+            entered.wait();
+            unsafe { f.clone().launch(dev.default_stream(), cfg, (4,)) }.unwrap();
+            exit.wait();
+        });
+        assert_eq!(dev.recent_launches()[2].scope, None);
+
+        let e = dev.annotate_error(DriverError::Cuda(sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS));
+        match &e {
+            DriverError::KernelFault {
+                code,
+                recent_launches,
+            } => {
+                assert_eq!(*code, sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS);
+                assert_eq!(&recent_launches[..], &dev.recent_launches()[..]);
+            }
+            e => panic!("{e:?}"),
+        }
+        assert_eq!(e.code(), sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS);
+
+        // not a sticky error
+        let e = DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE);
+        assert_eq!(dev.annotate_error(e.clone()), e);
+        dev.set_launch_tracking(0);
+        assert!(dev.recent_launches().is_empty());
+    }
+}
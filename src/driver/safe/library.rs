@@ -0,0 +1,445 @@
+use crate::driver::{result, sys};
+use crate::nvrtc::Ptx;
+
+use super::core::{CudaDevice, CudaFunction};
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use core::ffi::c_void;
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+use std::{collections::BTreeMap, ffi::CString, sync::Arc, vec::Vec};
+
+/// Jit options used when a [CudaLibrary] is loaded from ptx.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__TYPES.html)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JitOptions {
+    /// `CU_JIT_MAX_REGISTERS`: max number of registers a thread may use.
+    pub max_registers: Option<u32>,
+    /// `CU_JIT_OPTIMIZATION_LEVEL`: 0 to 4, where 4 is the default.
+    pub optimization_level: Option<u32>,
+    /// `CU_JIT_GENERATE_LINE_INFO`: generate line number information.
+    pub generate_line_info: bool,
+}
+
+impl JitOptions {
+    pub(crate) fn to_raw(self) -> (Vec<sys::CUjit_option>, Vec<*mut c_void>) {
+        use sys::CUjit_option::*;
+        // jit option values are passed by value, cast to a pointer.
+        let int = |n: u32| n as usize as *mut c_void;
+        let mut options = Vec::new();
+        let mut values = Vec::new();
+        if let Some(n) = self.max_registers {
+            options.push(CU_JIT_MAX_REGISTERS);
+            values.push(int(n));
+        }
+        if let Some(n) = self.optimization_level {
+            options.push(CU_JIT_OPTIMIZATION_LEVEL);
+            values.push(int(n));
+        }
+        if self.generate_line_info {
+            options.push(CU_JIT_GENERATE_LINE_INFO);
+            values.push(int(1));
+        }
+        (options, values)
+    }
+}
+
+/// Device code that is loaded once and shared by every [CudaDevice] in the process.
+///
+/// On cuda 12 drivers this is a `CUlibrary`: the code is loaded into the driver once
+/// and each kernel is only loaded into a context the first time it is used there.
+/// On older drivers (detected at runtime) it falls back to loading a module into each
+/// device's context the first time a kernel is used on that device.
+///
+/// ```rust
+/// # use cudarc::{driver::*, nvrtc::*};
+/// let ptx = compile_ptx("extern \"C\" __global__ void my_kernel(float *out) { }").unwrap();
+/// let library = CudaLibrary::load(ptx, JitOptions::default()).unwrap();
+/// let kernel = library.kernel("my_kernel").unwrap();
+/// let dev = CudaDevice::new(0).unwrap();
+/// let mut out = dev.alloc_zeros::<f32>(1).unwrap();
+/// let f = kernel.function(&dev).unwrap();
+/// unsafe { f.launch(dev.default_stream(), LaunchConfig::for_num_elems(1), (&mut out,)) }.unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CudaLibrary {
+    inner: LibraryKind,
+    #[cfg(feature = "launch-validation")]
+    ptx_src: Option<std::string::String>,
+}
+
+#[derive(Debug)]
+enum LibraryKind {
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080"
+    )))]
+    Library(sys::CUlibrary),
+    Modules {
+        code: Vec<u8>,
+        options: JitOptions,
+        /// Keyed by the address of the context, which the device keeps alive.
+        modules: RwLock<BTreeMap<usize, (Arc<CudaDevice>, sys::CUmodule)>>,
+    },
+}
+
+unsafe impl Send for CudaLibrary {}
+unsafe impl Sync for CudaLibrary {}
+
+impl CudaLibrary {
+    /// Loads `ptx` with `options`.
+    pub fn load(ptx: Ptx, options: JitOptions) -> Result<Arc<Self>, DriverError> {
+        let ptx = ptx.read()?;
+        #[cfg(feature = "launch-validation")]
+        let ptx_src = super::ptx::ptx_source(&ptx);
+        let code = ptx.into_image()?;
+        #[allow(unused_mut)]
+        let mut library = Self::load_impl(code, options)?;
+        #[cfg(feature = "launch-validation")]
+        {
+            library.ptx_src = ptx_src;
+        }
+        Ok(Arc::new(library))
+    }
+
+    /// Loads a cubin or fatbin image. `options` only apply to ptx embedded in a fatbin.
+    pub fn load_data(data: &[u8], options: JitOptions) -> Result<Arc<Self>, DriverError> {
+        Self::load_impl(data.to_vec(), options).map(Arc::new)
+    }
+
+    fn load_impl(code: Vec<u8>, options: JitOptions) -> Result<Self, DriverError> {
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        if result::library::is_supported() {
+            let (mut opts, mut values) = options.to_raw();
+            let library = unsafe {
+                result::library::load_data(code.as_ptr() as *const _, &mut opts, &mut values)
+            }?;
+            return Ok(Self {
+                inner: LibraryKind::Library(library),
+                #[cfg(feature = "launch-validation")]
+                ptx_src: None,
+            });
+        }
+        Ok(Self {
+            inner: LibraryKind::Modules {
+                code,
+                options,
+                modules: RwLock::new(BTreeMap::new()),
+            },
+            #[cfg(feature = "launch-validation")]
+            ptx_src: None,
+        })
+    }
+
+    /// Whether this library is a `CUlibrary` rather than the per device module fallback.
+    pub fn is_context_independent(&self) -> bool {
+        !matches!(self.inner, LibraryKind::Modules { .. })
+    }
+
+    /// Returns the kernel named `name`.
+    ///
+    /// With the per device module fallback, a missing kernel is only reported
+    /// by [CudaKernel::function()].
+    pub fn kernel(self: &Arc<Self>, name: &str) -> Result<CudaKernel, DriverError> {
+        let name = CString::new(name).unwrap();
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let kernel = match self.inner {
+            LibraryKind::Library(library) => {
+                Some(unsafe { result::library::get_kernel(library, name.clone()) }?)
+            }
+            LibraryKind::Modules { .. } => None,
+        };
+        #[cfg(feature = "launch-validation")]
+        let num_params = self
+            .ptx_src
+            .as_deref()
+            .and_then(|src| super::ptx::count_entry_params(src, name.to_str().unwrap()));
+        Ok(CudaKernel {
+            library: self.clone(),
+            name,
+            #[cfg(not(any(
+                feature = "cuda-11040",
+                feature = "cuda-11050",
+                feature = "cuda-11060",
+                feature = "cuda-11070",
+                feature = "cuda-11080"
+            )))]
+            kernel,
+            functions: RwLock::new(BTreeMap::new()),
+            #[cfg(feature = "launch-validation")]
+            num_params,
+        })
+    }
+
+    /// Returns the module loaded into `dev`'s context, loading it if needed.
+    /// `dev` must be bound to the calling thread.
+    fn module_for(
+        dev: &Arc<CudaDevice>,
+        code: &[u8],
+        options: JitOptions,
+        modules: &RwLock<BTreeMap<usize, (Arc<CudaDevice>, sys::CUmodule)>>,
+    ) -> Result<sys::CUmodule, DriverError> {
+        #[allow(unused_mut)]
+        let mut modules = modules.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut modules = modules.unwrap();
+        if let Some((_, module)) = modules.get(&(dev.cu_ctx as usize)) {
+            return Ok(*module);
+        }
+        let (mut opts, mut values) = options.to_raw();
+        let module = unsafe {
+            result::module::load_data_ex(code.as_ptr() as *const _, &mut opts, &mut values)
+        }?;
+        modules.insert(dev.cu_ctx as usize, (dev.clone(), module));
+        Ok(module)
+    }
+}
+
+impl Drop for CudaLibrary {
+    fn drop(&mut self) {
+        match &mut self.inner {
+            #[cfg(not(any(
+                feature = "cuda-11040",
+                feature = "cuda-11050",
+                feature = "cuda-11060",
+                feature = "cuda-11070",
+                feature = "cuda-11080"
+            )))]
+            LibraryKind::Library(library) => {
+                unsafe { result::library::unload(*library) }.or_handle("CudaLibrary::drop");
+            }
+            LibraryKind::Modules { modules, .. } => {
+                let modules = RwLock::get_mut(modules);
+                #[cfg(not(feature = "no-std"))]
+                let modules = modules.unwrap();
+                for (_, (dev, module)) in modules.iter() {
+                    let _ctx = dev.bind_scoped_or_handle("CudaLibrary::drop");
+                    unsafe { result::module::unload(*module) }.or_handle("CudaLibrary::drop");
+                }
+            }
+        }
+    }
+}
+
+/// A kernel of a [CudaLibrary] that can be launched on any [CudaDevice].
+///
+/// Use [CudaKernel::function()] to get a [CudaFunction] for a specific device,
+/// which can then be launched on any of that device's streams.
+#[derive(Debug)]
+pub struct CudaKernel {
+    library: Arc<CudaLibrary>,
+    name: CString,
+    #[cfg(not(any(
+        feature = "cuda-11040",
+        feature = "cuda-11050",
+        feature = "cuda-11060",
+        feature = "cuda-11070",
+        feature = "cuda-11080"
+    )))]
+    kernel: Option<sys::CUkernel>,
+    /// Keyed by the address of the context, which the device keeps alive, as functions of
+    /// a kernel differ between contexts.
+    functions: RwLock<BTreeMap<usize, (Arc<CudaDevice>, sys::CUfunction)>>,
+    #[cfg(feature = "launch-validation")]
+    pub(crate) num_params: Option<usize>,
+}
+
+unsafe impl Send for CudaKernel {}
+unsafe impl Sync for CudaKernel {}
+
+impl CudaKernel {
+    /// The library this kernel belongs to.
+    pub fn library(&self) -> &Arc<CudaLibrary> {
+        &self.library
+    }
+
+    /// The `CUkernel`, if the library is [CudaLibrary::is_context_independent()].
+    #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+    pub(crate) fn cu_kernel(&self) -> Option<sys::CUkernel> {
+        self.kernel
+    }
+
+    /// Returns the [CudaFunction] for this kernel on `dev`. The first call per context
+    /// loads the kernel into the device's context, later calls are cached.
+    ///
+    /// The function keeps the library loaded, and the kernel keeps `dev` alive.
+    pub fn function(&self, dev: &Arc<CudaDevice>) -> Result<CudaFunction, DriverError> {
+        let cached = {
+            let functions = self.functions.read();
+            #[cfg(not(feature = "no-std"))]
+            let functions = functions.unwrap();
+            functions.get(&(dev.cu_ctx as usize)).map(|(_, f)| *f)
+        };
+        let cu_function = match cached {
+            Some(cu_function) => cu_function,
+            None => {
+                let _ctx = dev.bind_scoped()?;
+                let cu_function = self.load_function(dev)?;
+                #[allow(unused_mut)]
+                let mut functions = self.functions.write();
+                #[cfg(not(feature = "no-std"))]
+                let mut functions = functions.unwrap();
+                functions.insert(dev.cu_ctx as usize, (dev.clone(), cu_function));
+                cu_function
+            }
+        };
+        Ok(CudaFunction {
+            cu_function,
+            device: dev.clone(),
+            max_dynamic_smem: Default::default(),
+            max_threads_per_block: Default::default(),
+            required_cluster_width: Arc::new(u32::MAX.into()),
+            name: self.function_name(),
+            #[cfg(feature = "launch-validation")]
+            num_params: self.num_params,
+            #[cfg(feature = "launch-validation")]
+            queried_num_params: Default::default(),
+            param_sizes: None,
+            library: Some(self.library.clone()),
+        })
+    }
+
+    /// The name of the kernel, for the [CudaFunction]s loaded from it.
+    pub(crate) fn function_name(&self) -> Option<Arc<str>> {
+        self.name.to_str().ok().map(Arc::from)
+    }
+
+    fn load_function(&self, dev: &Arc<CudaDevice>) -> Result<sys::CUfunction, DriverError> {
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        if let Some(kernel) = self.kernel {
+            return unsafe { result::library::kernel_get_function(kernel) };
+        }
+        match &self.library.inner {
+            LibraryKind::Modules {
+                code,
+                options,
+                modules,
+            } => {
+                let module = CudaLibrary::module_for(dev, code, *options, modules)?;
+                unsafe { result::module::get_function(module, self.name.clone()) }
+            }
+            #[cfg(not(any(
+                feature = "cuda-11040",
+                feature = "cuda-11050",
+                feature = "cuda-11060",
+                feature = "cuda-11070",
+                feature = "cuda-11080"
+            )))]
+            LibraryKind::Library(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    const SRC: &str = "
+extern \"C\" __global__ void fill(float *out, const float value, const size_t n) {
+    unsigned int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) {
+        out[i] = value;
+    }
+}";
+
+    #[test]
+    fn test_library_kernel_on_every_device() {
+        let ptx = compile_ptx(SRC).unwrap();
+        let library = CudaLibrary::load(ptx, JitOptions::default()).unwrap();
+        let kernel = library.kernel("fill").unwrap();
+        for ordinal in 0..CudaDevice::count().unwrap() as usize {
+            let dev = CudaDevice::new(ordinal).unwrap();
+            let mut out = dev.alloc_zeros::<f32>(100).unwrap();
+            let f = kernel.function(&dev).unwrap();
+            unsafe {
+                f.launch(
+                    dev.default_stream(),
+                    LaunchConfig::for_num_elems(100),
+                    (&mut out, 2.0f32, 100usize),
+                )
+            }
+            .unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [2.0; 100]);
+        }
+    }
+
+    #[test]
+    fn test_function_outlives_library() {
+        let dev = CudaDevice::new(0).unwrap();
+        let f = {
+            let library =
+                CudaLibrary::load(compile_ptx(SRC).unwrap(), JitOptions::default()).unwrap();
+            let kernel = library.kernel("fill").unwrap();
+            kernel.function(&dev).unwrap()
+        };
+        let mut out = dev.alloc_zeros::<f32>(10).unwrap();
+        unsafe {
+            f.clone().launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(10),
+                (&mut out, 4.0f32, 10usize),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [4.0; 10]);
+    }
+
+    #[test]
+    fn test_module_fallback() {
+        let ptx = compile_ptx(SRC).unwrap();
+        let code = CString::new(ptx.to_src().unwrap())
+            .unwrap()
+            .into_bytes_with_nul();
+        let library = Arc::new(CudaLibrary {
+            inner: LibraryKind::Modules {
+                code,
+                options: JitOptions::default(),
+                modules: RwLock::new(BTreeMap::new()),
+            },
+            #[cfg(feature = "launch-validation")]
+            ptx_src: None,
+        });
+        assert!(!library.is_context_independent());
+        let dev = CudaDevice::new(0).unwrap();
+        let kernel = library.kernel("fill").unwrap();
+        let mut out = dev.alloc_zeros::<f32>(10).unwrap();
+        let f = kernel.function(&dev).unwrap();
+        unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(10),
+                (&mut out, 3.0f32, 10usize),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [3.0; 10]);
+        assert!(library.kernel("missing").unwrap().function(&dev).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use crate::driver::{
+    result::{self, DeviceLimitError},
+    sys::{self, CUdevice_attribute},
+};
+
+use super::core::CudaDevice;
+use super::DriverError;
+
+/// The limits on the sizes of textures, arrays and surfaces of a device, from
+/// [CudaDevice::limits()].
+///
+/// Exceeding them makes the driver return [sys::CUresult::CUDA_ERROR_INVALID_VALUE] without
+/// saying which limit was hit. The `check_*` methods return a [DriverError::DeviceLimit]
+/// naming it instead, so shapes can be validated up front. Widths and heights are in
+/// elements, pitches and alignments in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceLimits {
+    pub max_texture_1d_width: usize,
+    /// Deprecated by the driver in favor of the per format
+    /// [CudaDevice::texture_1d_linear_max_width()].
+    pub max_texture_1d_linear_width: usize,
+    pub max_texture_2d_width: usize,
+    pub max_texture_2d_height: usize,
+    pub max_texture_2d_linear_width: usize,
+    pub max_texture_2d_linear_height: usize,
+    pub max_texture_2d_linear_pitch: usize,
+    pub max_texture_2d_layered_width: usize,
+    pub max_texture_2d_layered_height: usize,
+    pub max_texture_2d_layered_layers: usize,
+    pub max_texture_3d_width: usize,
+    pub max_texture_3d_height: usize,
+    pub max_texture_3d_depth: usize,
+    pub max_surface_2d_width: usize,
+    pub max_surface_2d_height: usize,
+    pub max_surface_2d_layered_width: usize,
+    pub max_surface_2d_layered_height: usize,
+    pub max_surface_2d_layered_layers: usize,
+    /// The alignment of the start of memory bound to a texture.
+    pub texture_alignment: usize,
+    /// The alignment of the pitch of 2D textures over linear memory.
+    pub texture_pitch_alignment: usize,
+}
+
+impl CudaDevice {
+    /// Queries the [DeviceLimits] of the device.
+    pub fn limits(&self) -> Result<DeviceLimits, DriverError> {
+        use CUdevice_attribute::*;
+        let get = |attrib| self.attribute(attrib).map(|value| value as usize);
+        Ok(DeviceLimits {
+            max_texture_1d_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE1D_WIDTH)?,
+            max_texture_1d_linear_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE1D_LINEAR_WIDTH)?,
+            max_texture_2d_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_WIDTH)?,
+            max_texture_2d_height: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_HEIGHT)?,
+            max_texture_2d_linear_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_LINEAR_WIDTH)?,
+            max_texture_2d_linear_height: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_LINEAR_HEIGHT)?,
+            max_texture_2d_linear_pitch: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_LINEAR_PITCH)?,
+            max_texture_2d_layered_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_LAYERED_WIDTH)?,
+            max_texture_2d_layered_height: get(
+                CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_LAYERED_HEIGHT,
+            )?,
+            max_texture_2d_layered_layers: get(
+                CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE2D_LAYERED_LAYERS,
+            )?,
+            max_texture_3d_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE3D_WIDTH)?,
+            max_texture_3d_height: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE3D_HEIGHT)?,
+            max_texture_3d_depth: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_TEXTURE3D_DEPTH)?,
+            max_surface_2d_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_SURFACE2D_WIDTH)?,
+            max_surface_2d_height: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_SURFACE2D_HEIGHT)?,
+            max_surface_2d_layered_width: get(CU_DEVICE_ATTRIBUTE_MAXIMUM_SURFACE2D_LAYERED_WIDTH)?,
+            max_surface_2d_layered_height: get(
+                CU_DEVICE_ATTRIBUTE_MAXIMUM_SURFACE2D_LAYERED_HEIGHT,
+            )?,
+            max_surface_2d_layered_layers: get(
+                CU_DEVICE_ATTRIBUTE_MAXIMUM_SURFACE2D_LAYERED_LAYERS,
+            )?,
+            texture_alignment: get(CU_DEVICE_ATTRIBUTE_TEXTURE_ALIGNMENT)?,
+            texture_pitch_alignment: get(CU_DEVICE_ATTRIBUTE_TEXTURE_PITCH_ALIGNMENT)?,
+        })
+    }
+
+    /// The widest 1D texture over linear memory of `num_channels` elements of `format`, in
+    /// elements. See `cuDeviceGetTexture1DLinearMaxWidth`.
+    pub fn texture_1d_linear_max_width(
+        &self,
+        format: sys::CUarray_format,
+        num_channels: u32,
+    ) -> Result<usize, DriverError> {
+        result::device::get_texture_1d_linear_max_width(self.cu_device, format, num_channels)
+    }
+}
+
+fn at_most(what: &'static str, value: usize, max: usize) -> Result<(), DeviceLimitError> {
+    if value > max {
+        return Err(DeviceLimitError::TooLarge { what, value, max });
+    }
+    Ok(())
+}
+
+fn aligned(what: &'static str, value: usize, align: usize) -> Result<(), DeviceLimitError> {
+    if align != 0 && !value.is_multiple_of(align) {
+        return Err(DeviceLimitError::Misaligned { what, value, align });
+    }
+    Ok(())
+}
+
+impl DeviceLimits {
+    /// Checks a 1D texture over `width` elements of linear memory.
+    pub fn check_texture_1d_linear(&self, width: usize) -> Result<(), DriverError> {
+        at_most(
+            "1D linear texture width",
+            width,
+            self.max_texture_1d_linear_width,
+        )?;
+        Ok(())
+    }
+
+    /// Checks a 2D texture over linear memory with rows of `pitch` bytes, starting at
+    /// device address `ptr`.
+    pub fn check_texture_2d_linear(
+        &self,
+        ptr: sys::CUdeviceptr,
+        width: usize,
+        height: usize,
+        pitch: usize,
+    ) -> Result<(), DriverError> {
+        at_most(
+            "2D linear texture width",
+            width,
+            self.max_texture_2d_linear_width,
+        )?;
+        at_most(
+            "2D linear texture height",
+            height,
+            self.max_texture_2d_linear_height,
+        )?;
+        at_most(
+            "2D linear texture pitch",
+            pitch,
+            self.max_texture_2d_linear_pitch,
+        )?;
+        aligned(
+            "2D linear texture pitch",
+            pitch,
+            self.texture_pitch_alignment,
+        )?;
+        aligned("texture address", ptr as usize, self.texture_alignment)?;
+        Ok(())
+    }
+
+    /// Checks a 1D or 2D array read through a texture. `height` is 0 for 1D arrays.
+    pub fn check_array_2d(&self, width: usize, height: usize) -> Result<(), DriverError> {
+        if height == 0 {
+            at_most("1D array width", width, self.max_texture_1d_width)?;
+        } else {
+            at_most("2D array width", width, self.max_texture_2d_width)?;
+            at_most("2D array height", height, self.max_texture_2d_height)?;
+        }
+        Ok(())
+    }
+
+    /// Checks a layered 2D array of `layers` layers read through a texture.
+    pub fn check_layered_array_2d(
+        &self,
+        width: usize,
+        height: usize,
+        layers: usize,
+    ) -> Result<(), DriverError> {
+        at_most(
+            "layered 2D array width",
+            width,
+            self.max_texture_2d_layered_width,
+        )?;
+        at_most(
+            "layered 2D array height",
+            height,
+            self.max_texture_2d_layered_height,
+        )?;
+        at_most(
+            "layered 2D array layers",
+            layers,
+            self.max_texture_2d_layered_layers,
+        )?;
+        Ok(())
+    }
+
+    /// Checks a 3D array read through a texture.
+    pub fn check_array_3d(
+        &self,
+        width: usize,
+        height: usize,
+        depth: usize,
+    ) -> Result<(), DriverError> {
+        at_most("3D array width", width, self.max_texture_3d_width)?;
+        at_most("3D array height", height, self.max_texture_3d_height)?;
+        at_most("3D array depth", depth, self.max_texture_3d_depth)?;
+        Ok(())
+    }
+
+    /// Checks a 2D array written through a surface, with `layers` layers if it is layered.
+    pub fn check_surface_2d(
+        &self,
+        width: usize,
+        height: usize,
+        layers: Option<usize>,
+    ) -> Result<(), DriverError> {
+        match layers {
+            None => {
+                at_most("2D surface width", width, self.max_surface_2d_width)?;
+                at_most("2D surface height", height, self.max_surface_2d_height)?;
+            }
+            Some(layers) => {
+                at_most(
+                    "layered 2D surface width",
+                    width,
+                    self.max_surface_2d_layered_width,
+                )?;
+                at_most(
+                    "layered 2D surface height",
+                    height,
+                    self.max_surface_2d_layered_height,
+                )?;
+                at_most(
+                    "layered 2D surface layers",
+                    layers,
+                    self.max_surface_2d_layered_layers,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> DeviceLimits {
+        DeviceLimits {
+            max_texture_1d_width: 131072,
+            max_texture_1d_linear_width: 268435456,
+            max_texture_2d_width: 131072,
+            max_texture_2d_height: 65536,
+            max_texture_2d_linear_width: 131072,
+            max_texture_2d_linear_height: 65000,
+            max_texture_2d_linear_pitch: 2097120,
+            max_texture_2d_layered_width: 32768,
+            max_texture_2d_layered_height: 32768,
+            max_texture_2d_layered_layers: 2048,
+            max_texture_3d_width: 16384,
+            max_texture_3d_height: 16384,
+            max_texture_3d_depth: 16384,
+            max_surface_2d_width: 131072,
+            max_surface_2d_height: 65536,
+            max_surface_2d_layered_width: 32768,
+            max_surface_2d_layered_height: 32768,
+            max_surface_2d_layered_layers: 2048,
+            texture_alignment: 512,
+            texture_pitch_alignment: 32,
+        }
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let limits = limits();
+        assert!(limits.check_texture_1d_linear(1 << 20).is_ok());
+        assert_eq!(
+            limits.check_texture_1d_linear(1 << 30),
+            Err(DriverError::DeviceLimit(DeviceLimitError::TooLarge {
+                what: "1D linear texture width",
+                value: 1 << 30,
+                max: 268435456,
+            }))
+        );
+        assert!(limits.check_texture_2d_linear(0, 1024, 1024, 4096).is_ok());
+        let e = limits
+            .check_texture_2d_linear(0, 1024, 1024, 4100)
+            .unwrap_err();
+        assert_eq!(e.code(), sys::CUresult::CUDA_ERROR_INVALID_VALUE);
+        #[cfg(feature = "std")]
+        assert_eq!(
+            e.to_string(),
+            "DeviceLimit(2D linear texture pitch 4100 is not a multiple of 32)"
+        );
+        assert!(limits.check_texture_2d_linear(256, 16, 16, 64).is_err());
+        assert!(limits.check_array_2d(131072, 0).is_ok());
+        assert!(limits.check_array_2d(1024, 65537).is_err());
+        assert!(limits.check_layered_array_2d(1024, 1024, 2049).is_err());
+        assert!(limits.check_array_3d(16384, 16384, 16384).is_ok());
+        assert!(limits.check_surface_2d(40000, 1024, None).is_ok());
+        assert!(limits.check_surface_2d(40000, 1024, Some(1)).is_err());
+    }
+
+    #[test]
+    fn test_device_limits() {
+        let dev = CudaDevice::new(0).unwrap();
+        let limits = dev.limits().unwrap();
+        assert!(limits.max_texture_2d_width > 0);
+        assert!(limits.texture_pitch_alignment.is_power_of_two());
+        let width = dev
+            .texture_1d_linear_max_width(sys::CUarray_format::CU_AD_FORMAT_FLOAT, 1)
+            .unwrap();
+        assert!(width > 0);
+    }
+}
@@ -0,0 +1,396 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaStream;
+use super::device_ptr::{DevicePtr, DevicePtrMut};
+use super::DriverError;
+use crate::driver::result::InvalidMemOp;
+
+/// The condition a [CudaStream::wait_value_32()] or [CudaStream::wait_value_64()]
+/// waits for, comparing the value in memory (`*addr`) against the given `value`.
+///
+/// See [cuda docs](https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MEMOP.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WaitCond {
+    /// `(int)(*addr - value) >= 0`, i.e. greater or equal with wrap around.
+    Geq,
+    /// `*addr == value`
+    Eq,
+    /// `(*addr & value) != 0`
+    And,
+    /// `~(*addr | value) != 0`. Requires `CU_DEVICE_ATTRIBUTE_CAN_USE_STREAM_WAIT_VALUE_NOR`.
+    Nor,
+}
+
+impl From<WaitCond> for sys::CUstreamWaitValue_flags {
+    fn from(value: WaitCond) -> Self {
+        match value {
+            WaitCond::Geq => sys::CUstreamWaitValue_flags::CU_STREAM_WAIT_VALUE_GEQ,
+            WaitCond::Eq => sys::CUstreamWaitValue_flags::CU_STREAM_WAIT_VALUE_EQ,
+            WaitCond::And => sys::CUstreamWaitValue_flags::CU_STREAM_WAIT_VALUE_AND,
+            WaitCond::Nor => sys::CUstreamWaitValue_flags::CU_STREAM_WAIT_VALUE_NOR,
+        }
+    }
+}
+
+/// A single operation of a [CudaStream::batch_mem_op()].
+///
+/// Addresses are raw device pointers so that one batch can reference several
+/// allocations; use the constructors to build operations from slices. Nothing keeps the
+/// slices alive or borrowed once the op is built, which is why
+/// [CudaStream::batch_mem_op()] is unsafe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemOp {
+    /// See [CudaStream::wait_value_32()].
+    WaitValue32 {
+        addr: sys::CUdeviceptr,
+        value: u32,
+        cond: WaitCond,
+    },
+    /// See [CudaStream::wait_value_64()].
+    WaitValue64 {
+        addr: sys::CUdeviceptr,
+        value: u64,
+        cond: WaitCond,
+    },
+    /// See [CudaStream::write_value_32()].
+    WriteValue32 { addr: sys::CUdeviceptr, value: u32 },
+    /// See [CudaStream::write_value_64()].
+    WriteValue64 { addr: sys::CUdeviceptr, value: u64 },
+    /// Makes remote writes (e.g. from an RDMA NIC) that completed before this
+    /// operation visible to work queued after it.
+    /// Requires `CU_DEVICE_ATTRIBUTE_CAN_FLUSH_REMOTE_WRITES`.
+    FlushRemoteWrites,
+}
+
+impl MemOp {
+    /// [MemOp::WaitValue32] on the first element of `addr`.
+    pub fn wait_value_32<A: DevicePtr<u32>>(addr: &A, value: u32, cond: WaitCond) -> Self {
+        Self::WaitValue32 {
+            addr: *addr.device_ptr(),
+            value,
+            cond,
+        }
+    }
+
+    /// [MemOp::WaitValue64] on the first element of `addr`.
+    pub fn wait_value_64<A: DevicePtr<u64>>(addr: &A, value: u64, cond: WaitCond) -> Self {
+        Self::WaitValue64 {
+            addr: *addr.device_ptr(),
+            value,
+            cond,
+        }
+    }
+
+    /// [MemOp::WriteValue32] to the first element of `addr`.
+    pub fn write_value_32<A: DevicePtrMut<u32>>(addr: &mut A, value: u32) -> Self {
+        Self::WriteValue32 {
+            addr: *addr.device_ptr_mut(),
+            value,
+        }
+    }
+
+    /// [MemOp::WriteValue64] to the first element of `addr`.
+    pub fn write_value_64<A: DevicePtrMut<u64>>(addr: &mut A, value: u64) -> Self {
+        Self::WriteValue64 {
+            addr: *addr.device_ptr_mut(),
+            value,
+        }
+    }
+
+    fn addr(&self) -> Option<(sys::CUdeviceptr, usize)> {
+        match self {
+            Self::WaitValue32 { addr, .. } | Self::WriteValue32 { addr, .. } => Some((*addr, 4)),
+            Self::WaitValue64 { addr, .. } | Self::WriteValue64 { addr, .. } => Some((*addr, 8)),
+            Self::FlushRemoteWrites => None,
+        }
+    }
+
+    fn params(&self) -> sys::CUstreamBatchMemOpParams {
+        use sys::CUstreamBatchMemOpType::*;
+        let mut params = sys::CUstreamBatchMemOpParams::default();
+        match *self {
+            Self::WaitValue32 { addr, value, cond } => {
+                params.waitValue.operation = CU_STREAM_MEM_OP_WAIT_VALUE_32;
+                params.waitValue.address = addr;
+                params.waitValue.__bindgen_anon_1.value = value;
+                params.waitValue.flags = sys::CUstreamWaitValue_flags::from(cond) as u32;
+            }
+            Self::WaitValue64 { addr, value, cond } => {
+                params.waitValue.operation = CU_STREAM_MEM_OP_WAIT_VALUE_64;
+                params.waitValue.address = addr;
+                params.waitValue.__bindgen_anon_1.value64 = value;
+                params.waitValue.flags = sys::CUstreamWaitValue_flags::from(cond) as u32;
+            }
+            Self::WriteValue32 { addr, value } => {
+                params.writeValue.operation = CU_STREAM_MEM_OP_WRITE_VALUE_32;
+                params.writeValue.address = addr;
+                params.writeValue.__bindgen_anon_1.value = value;
+            }
+            Self::WriteValue64 { addr, value } => {
+                params.writeValue.operation = CU_STREAM_MEM_OP_WRITE_VALUE_64;
+                params.writeValue.address = addr;
+                params.writeValue.__bindgen_anon_1.value64 = value;
+            }
+            Self::FlushRemoteWrites => {
+                params.flushRemoteWrites.operation = CU_STREAM_MEM_OP_FLUSH_REMOTE_WRITES;
+            }
+        }
+        params
+    }
+}
+
+impl CudaStream {
+    /// Enqueues all of `ops` on the stream at once, which is cheaper than enqueueing
+    /// them one at a time.
+    ///
+    /// Before anything is enqueued, every referenced address is checked to be accessible
+    /// from the stream's device and aligned to 4 (32 bit ops) or 8 (64 bit ops) bytes.
+    /// Returns [DriverError::InvalidMemOp] if any check fails, or
+    /// [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device can't run one of the ops.
+    ///
+    /// # Safety
+    /// 1. Every address of `ops` must point into device memory that stays allocated until
+    ///    the ops have run on the stream.
+    /// 2. Nothing may read or write the targets of the write ops while they run, e.g. the
+    ///    slices the ops were built from must not be used on another stream.
+    pub unsafe fn batch_mem_op(&self, ops: &[MemOp]) -> Result<(), DriverError> {
+        use sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_CAN_FLUSH_REMOTE_WRITES;
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let is_64 = ops
+            .iter()
+            .any(|op| matches!(op, MemOp::WaitValue64 { .. } | MemOp::WriteValue64 { .. }));
+        let nor = ops.iter().any(|op| {
+            matches!(
+                op,
+                MemOp::WaitValue32 {
+                    cond: WaitCond::Nor,
+                    ..
+                } | MemOp::WaitValue64 {
+                    cond: WaitCond::Nor,
+                    ..
+                }
+            )
+        });
+        self.check_mem_ops_supported(is_64, nor)?;
+        if ops.contains(&MemOp::FlushRemoteWrites)
+            && self
+                .device
+                .attribute(CU_DEVICE_ATTRIBUTE_CAN_FLUSH_REMOTE_WRITES)?
+                == 0
+        {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED));
+        }
+
+        let mut params = std::vec::Vec::with_capacity(ops.len());
+        for (i, op) in ops.iter().enumerate() {
+            if let Some((addr, align)) = op.addr() {
+                if !(addr as usize).is_multiple_of(align) {
+                    return Err(InvalidMemOp::Misaligned { op: i, addr, align }.into());
+                }
+                let mapped = unsafe {
+                    result::pointer::get_attribute::<sys::CUdeviceptr>(
+                        addr,
+                        sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_DEVICE_POINTER,
+                    )
+                };
+                if mapped.is_err() {
+                    return Err(InvalidMemOp::Inaccessible { op: i, addr }.into());
+                }
+            }
+            params.push(op.params());
+        }
+        unsafe { result::stream::batch_mem_op(self.stream, &mut params) }
+    }
+
+    /// Blocks the stream until the `u32` at `addr` satisfies `cond` with respect to `value`.
+    /// No host round trip is involved, so `addr` can be written by the host through
+    /// mapped memory or by a third party device (e.g. an RDMA NIC).
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device can't use stream memory
+    /// operations. **Work queued after this will not run until the condition is met.**
+    pub fn wait_value_32<A: DevicePtr<u32>>(
+        &self,
+        addr: &A,
+        value: u32,
+        cond: WaitCond,
+    ) -> Result<(), DriverError> {
+        self.check_mem_ops_supported(false, cond == WaitCond::Nor)?;
+        unsafe {
+            result::stream::wait_value_32(self.stream, *addr.device_ptr(), value, cond.into())
+        }
+    }
+
+    /// 64 bit version of [CudaStream::wait_value_32()].
+    pub fn wait_value_64<A: DevicePtr<u64>>(
+        &self,
+        addr: &A,
+        value: u64,
+        cond: WaitCond,
+    ) -> Result<(), DriverError> {
+        self.check_mem_ops_supported(true, cond == WaitCond::Nor)?;
+        unsafe {
+            result::stream::wait_value_64(self.stream, *addr.device_ptr(), value, cond.into())
+        }
+    }
+
+    /// Writes `value` to `addr` once all prior work on the stream has completed.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device can't use stream memory
+    /// operations.
+    pub fn write_value_32<A: DevicePtrMut<u32>>(
+        &self,
+        addr: &mut A,
+        value: u32,
+    ) -> Result<(), DriverError> {
+        self.check_mem_ops_supported(false, false)?;
+        unsafe {
+            result::stream::write_value_32(
+                self.stream,
+                *addr.device_ptr_mut(),
+                value,
+                sys::CUstreamWriteValue_flags::CU_STREAM_WRITE_VALUE_DEFAULT,
+            )
+        }
+    }
+
+    /// 64 bit version of [CudaStream::write_value_32()].
+    pub fn write_value_64<A: DevicePtrMut<u64>>(
+        &self,
+        addr: &mut A,
+        value: u64,
+    ) -> Result<(), DriverError> {
+        self.check_mem_ops_supported(true, false)?;
+        unsafe {
+            result::stream::write_value_64(
+                self.stream,
+                *addr.device_ptr_mut(),
+                value,
+                sys::CUstreamWriteValue_flags::CU_STREAM_WRITE_VALUE_DEFAULT,
+            )
+        }
+    }
+
+    pub(crate) fn check_mem_ops_supported(
+        &self,
+        is_64: bool,
+        nor: bool,
+    ) -> Result<(), DriverError> {
+        use sys::CUdevice_attribute::*;
+        let _ctx = self.device.bind_scoped()?;
+
+        // 32 bit memory operations are always available with the v2 api in cuda 12.
+        #[cfg(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        ))]
+        let mut required = std::vec![CU_DEVICE_ATTRIBUTE_CAN_USE_STREAM_MEM_OPS];
+        #[cfg(not(any(
+            feature = "cuda-11040",
+            feature = "cuda-11050",
+            feature = "cuda-11060",
+            feature = "cuda-11070",
+            feature = "cuda-11080"
+        )))]
+        let mut required = std::vec::Vec::new();
+        if is_64 {
+            required.push(CU_DEVICE_ATTRIBUTE_CAN_USE_64_BIT_STREAM_MEM_OPS);
+        }
+        if nor {
+            required.push(CU_DEVICE_ATTRIBUTE_CAN_USE_STREAM_WAIT_VALUE_NOR);
+        }
+        for attr in required {
+            if self.device.attribute(attr)? == 0 {
+                return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{sys::lib, CudaDevice};
+
+    #[test]
+    fn test_wait_for_host_written_value() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+
+        let mut host_ptr = std::ptr::null_mut();
+        let mut dptr = 0;
+        unsafe {
+            lib()
+                .cuMemHostAlloc(&mut host_ptr, 4, sys::CU_MEMHOSTALLOC_DEVICEMAP)
+                .result()
+                .unwrap();
+            std::ptr::write_volatile(host_ptr as *mut u32, 0);
+            lib()
+                .cuMemHostGetDevicePointer_v2(&mut dptr, host_ptr, 0)
+                .result()
+                .unwrap();
+        }
+        let doorbell = unsafe { dev.upgrade_device_ptr::<u32>(dptr, 1) };
+        let mut done = dev.alloc_zeros::<u32>(1).unwrap();
+        dev.synchronize().unwrap();
+
+        stream.wait_value_32(&doorbell, 1, WaitCond::Geq).unwrap();
+        stream.write_value_32(&mut done, 7).unwrap();
+
+        let addr = host_ptr as usize;
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            unsafe { std::ptr::write_volatile(addr as *mut u32, 1) };
+        })
+        .join()
+        .unwrap();
+
+        dev.wait_for(&stream).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&done).unwrap(), [7]);
+
+        doorbell.leak();
+        unsafe { lib().cuMemFreeHost(host_ptr).result().unwrap() };
+    }
+
+    #[test]
+    fn test_batch_mem_op() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        let mut a = dev.alloc_zeros::<u32>(1).unwrap();
+        let mut b = dev.alloc_zeros::<u64>(1).unwrap();
+
+        unsafe {
+            stream.batch_mem_op(&[
+                MemOp::write_value_32(&mut a, 3),
+                MemOp::write_value_64(&mut b, 5),
+                MemOp::wait_value_32(&a, 3, WaitCond::Eq),
+            ])
+        }
+        .unwrap();
+        dev.wait_for(&stream).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), [3]);
+        assert_eq!(dev.dtoh_sync_copy(&b).unwrap(), [5]);
+
+        let addr = *b.device_ptr() + 4;
+        assert_eq!(
+            unsafe { stream.batch_mem_op(&[MemOp::WriteValue64 { addr, value: 1 }]) },
+            Err(DriverError::InvalidMemOp(InvalidMemOp::Misaligned {
+                op: 0,
+                addr,
+                align: 8
+            }))
+        );
+        assert_eq!(
+            unsafe { stream.batch_mem_op(&[MemOp::WriteValue32 { addr: 4, value: 1 }]) },
+            Err(DriverError::InvalidMemOp(InvalidMemOp::Inaccessible {
+                op: 0,
+                addr: 4
+            }))
+        );
+    }
+}
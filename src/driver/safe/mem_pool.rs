@@ -0,0 +1,512 @@
+use crate::driver::{result, sys};
+
+use super::alloc::{DeviceRepr, ValidAsZeroBits};
+use super::core::{CudaDevice, CudaSlice};
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use core::{ffi::c_void, panic::Location};
+use std::sync::Arc;
+
+/// The `cuuint64_t` attributes of a [CudaMemPool], see [CudaMemPool::attribute()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PoolAttribute {
+    /// Bytes of reserved memory the pool holds on to when the stream synchronizes,
+    /// 0 by default. See [CudaMemPool::set_release_threshold()].
+    ReleaseThreshold,
+    /// Bytes of memory the pool currently has reserved from the device.
+    ReservedMemCurrent,
+    /// High-water mark of [PoolAttribute::ReservedMemCurrent].
+    ReservedMemHigh,
+    /// Bytes of memory currently allocated from the pool.
+    UsedMemCurrent,
+    /// High-water mark of [PoolAttribute::UsedMemCurrent].
+    UsedMemHigh,
+}
+
+impl PoolAttribute {
+    fn to_sys(self) -> sys::CUmemPool_attribute {
+        use sys::CUmemPool_attribute::*;
+        match self {
+            Self::ReleaseThreshold => CU_MEMPOOL_ATTR_RELEASE_THRESHOLD,
+            Self::ReservedMemCurrent => CU_MEMPOOL_ATTR_RESERVED_MEM_CURRENT,
+            Self::ReservedMemHigh => CU_MEMPOOL_ATTR_RESERVED_MEM_HIGH,
+            Self::UsedMemCurrent => CU_MEMPOOL_ATTR_USED_MEM_CURRENT,
+            Self::UsedMemHigh => CU_MEMPOOL_ATTR_USED_MEM_HIGH,
+        }
+    }
+}
+
+/// The kinds of OS handle a [CudaMemPool] can be shared with other processes through.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShareableHandleType {
+    /// A file descriptor, on linux.
+    PosixFileDescriptor,
+    /// A `HANDLE`, on windows.
+    Win32,
+    /// A global `D3DKMT_HANDLE`, on windows.
+    Win32Kmt,
+}
+
+impl ShareableHandleType {
+    fn to_sys(self) -> sys::CUmemAllocationHandleType {
+        use sys::CUmemAllocationHandleType::*;
+        match self {
+            Self::PosixFileDescriptor => CU_MEM_HANDLE_TYPE_POSIX_FILE_DESCRIPTOR,
+            Self::Win32 => CU_MEM_HANDLE_TYPE_WIN32,
+            Self::Win32Kmt => CU_MEM_HANDLE_TYPE_WIN32_KMT,
+        }
+    }
+}
+
+/// An exported [CudaMemPool], see [CudaMemPool::export_shareable()].
+///
+/// The handle is owned by the process that exported it, which should close it once
+/// the other process has it. File descriptors have to be passed to the other process
+/// over a unix socket or by inheritance.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShareableHandle {
+    PosixFileDescriptor(i32),
+    Win32(usize),
+    Win32Kmt(usize),
+}
+
+impl ShareableHandle {
+    /// The type of the handle.
+    pub fn handle_type(&self) -> ShareableHandleType {
+        match self {
+            Self::PosixFileDescriptor(_) => ShareableHandleType::PosixFileDescriptor,
+            Self::Win32(_) => ShareableHandleType::Win32,
+            Self::Win32Kmt(_) => ShareableHandleType::Win32Kmt,
+        }
+    }
+}
+
+/// An allocation from an exported [CudaMemPool], created with [CudaMemPool::export_pointer()].
+///
+/// Other processes that imported the pool open it with [CudaMemPool::import_pointer()],
+/// without mapping any memory themselves.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PoolPtrExport {
+    data: [u64; 8],
+    num_bytes: usize,
+}
+
+impl PoolPtrExport {
+    /// Size in bytes of the exported allocation.
+    pub fn num_bytes(&self) -> usize {
+        self.num_bytes
+    }
+
+    fn to_sys(self) -> sys::CUmemPoolPtrExportData {
+        let mut reserved = [0; 64];
+        for (chunk, word) in reserved.chunks_exact_mut(8).zip(self.data) {
+            chunk.copy_from_slice(&word.to_ne_bytes());
+        }
+        sys::CUmemPoolPtrExportData { reserved }
+    }
+}
+
+/// A stream-ordered memory pool on a device.
+///
+/// Get the pool all async allocations come from with [CudaDevice::default_mem_pool()], or
+/// create a separate one with [CudaDevice::create_mem_pool()] and allocate from it with
+/// [CudaDevice::alloc_from_pool()], e.g. to keep a cache's memory apart from transient
+/// allocations.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let pool = dev.default_mem_pool().unwrap();
+/// let used = pool.attribute(PoolAttribute::UsedMemCurrent).unwrap();
+/// pool.trim_to(0).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CudaMemPool {
+    pub(crate) cu_pool: sys::CUmemoryPool,
+    pub(crate) device: Arc<CudaDevice>,
+    /// Whether the pool was created or imported, and so is destroyed on drop.
+    owned: bool,
+}
+
+unsafe impl Send for CudaMemPool {}
+unsafe impl Sync for CudaMemPool {}
+
+impl CudaDevice {
+    /// The pool that this device's allocations come from when [CudaDevice::is_async].
+    pub fn default_mem_pool(self: &Arc<Self>) -> Result<CudaMemPool, DriverError> {
+        result::require(result::ApiGroup::MemoryPools)?;
+        let cu_pool = result::mem_pool::get_default(self.cu_device)?;
+        Ok(CudaMemPool {
+            cu_pool,
+            device: self.clone(),
+            owned: false,
+        })
+    }
+
+    /// Creates a new pool of device memory, destroyed when the [CudaMemPool] is dropped.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device doesn't support
+    /// memory pools.
+    pub fn create_mem_pool(self: &Arc<Self>) -> Result<CudaMemPool, DriverError> {
+        self.create_mem_pool_with_handle_type(
+            sys::CUmemAllocationHandleType::CU_MEM_HANDLE_TYPE_NONE,
+        )
+    }
+
+    /// Creates a pool that can be shared with other processes, see
+    /// [CudaMemPool::export_shareable()].
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device doesn't support
+    /// memory pools, or `handle_type` on this platform.
+    pub fn create_shareable_mem_pool(
+        self: &Arc<Self>,
+        handle_type: ShareableHandleType,
+    ) -> Result<CudaMemPool, DriverError> {
+        self.create_mem_pool_with_handle_type(handle_type.to_sys())
+    }
+
+    fn create_mem_pool_with_handle_type(
+        self: &Arc<Self>,
+        handle_type: sys::CUmemAllocationHandleType,
+    ) -> Result<CudaMemPool, DriverError> {
+        result::require(result::ApiGroup::MemoryPools)?;
+        if !self.is_async {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED));
+        }
+        let _ctx = self.bind_scoped()?;
+        let props = sys::CUmemPoolProps {
+            allocType: sys::CUmemAllocationType::CU_MEM_ALLOCATION_TYPE_PINNED,
+            handleTypes: handle_type,
+            location: sys::CUmemLocation {
+                type_: sys::CUmemLocationType::CU_MEM_LOCATION_TYPE_DEVICE,
+                id: self.physical_ordinal as i32,
+            },
+            ..Default::default()
+        };
+        let cu_pool = unsafe { result::mem_pool::create(&props) }?;
+        Ok(CudaMemPool {
+            cu_pool,
+            device: self.clone(),
+            owned: true,
+        })
+    }
+
+    /// Allocates `len` elements from `pool`, ordered on this device's stream. The memory
+    /// is returned to `pool` when the [CudaSlice] is dropped.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_DEVICE] if `pool` belongs to another device.
+    ///
+    /// # Safety
+    /// This is unsafe because the device memory is unset after this call.
+    #[track_caller]
+    pub unsafe fn alloc_from_pool<T: DeviceRepr>(
+        self: &Arc<Self>,
+        pool: &CudaMemPool,
+        len: usize,
+    ) -> Result<CudaSlice<T>, DriverError> {
+        if pool.device.physical_ordinal != self.physical_ordinal {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_DEVICE));
+        }
+        let _ctx = self.bind_scoped()?;
+        let cu_device_ptr = result::mem_pool::alloc_async(
+            pool.cu_pool,
+            len * std::mem::size_of::<T>(),
+            self.stream,
+        )?;
+        Ok(self.tracked(CudaSlice {
+            cu_device_ptr,
+            len,
+            device: self.clone(),
+            host_buf: None,
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator: None,
+        }))
+    }
+
+    /// Allocates `len` elements from `pool` and memsets them to all 0s,
+    /// see [CudaDevice::alloc_from_pool()].
+    #[track_caller]
+    pub fn alloc_zeros_from_pool<T: ValidAsZeroBits + DeviceRepr>(
+        self: &Arc<Self>,
+        pool: &CudaMemPool,
+        len: usize,
+    ) -> Result<CudaSlice<T>, DriverError> {
+        let mut dst = unsafe { self.alloc_from_pool(pool, len) }?;
+        self.memset_zeros(&mut dst)?;
+        Ok(dst)
+    }
+}
+
+impl CudaMemPool {
+    /// Get a clone of the underlying [CudaDevice].
+    pub fn device(&self) -> Arc<CudaDevice> {
+        self.device.clone()
+    }
+
+    /// Exports a pool created with [CudaDevice::create_shareable_mem_pool()], for another
+    /// process to open with [CudaMemPool::import_shareable()].
+    pub fn export_shareable(
+        &self,
+        handle_type: ShareableHandleType,
+    ) -> Result<ShareableHandle, DriverError> {
+        let sys_type = handle_type.to_sys();
+        Ok(match handle_type {
+            ShareableHandleType::PosixFileDescriptor => {
+                let mut fd: core::ffi::c_int = -1;
+                unsafe {
+                    result::mem_pool::export_to_shareable_handle(
+                        self.cu_pool,
+                        sys_type,
+                        &mut fd as *mut core::ffi::c_int as *mut c_void,
+                    )
+                }?;
+                ShareableHandle::PosixFileDescriptor(fd)
+            }
+            ShareableHandleType::Win32 | ShareableHandleType::Win32Kmt => {
+                let mut handle: *mut c_void = std::ptr::null_mut();
+                unsafe {
+                    result::mem_pool::export_to_shareable_handle(
+                        self.cu_pool,
+                        sys_type,
+                        &mut handle as *mut *mut c_void as *mut c_void,
+                    )
+                }?;
+                match handle_type {
+                    ShareableHandleType::Win32 => ShareableHandle::Win32(handle as usize),
+                    _ => ShareableHandle::Win32Kmt(handle as usize),
+                }
+            }
+        })
+    }
+
+    /// Opens a pool exported by another process with [CudaMemPool::export_shareable()].
+    /// Allocations from it are opened with [CudaMemPool::import_pointer()].
+    pub fn import_shareable(
+        device: &Arc<CudaDevice>,
+        handle: ShareableHandle,
+    ) -> Result<CudaMemPool, DriverError> {
+        let _ctx = device.bind_scoped()?;
+        let raw = match handle {
+            ShareableHandle::PosixFileDescriptor(fd) => fd as isize as *mut c_void,
+            ShareableHandle::Win32(handle) | ShareableHandle::Win32Kmt(handle) => {
+                handle as *mut c_void
+            }
+        };
+        let cu_pool = unsafe {
+            result::mem_pool::import_from_shareable_handle(raw, handle.handle_type().to_sys())
+        }?;
+        Ok(CudaMemPool {
+            cu_pool,
+            device: device.clone(),
+            owned: true,
+        })
+    }
+
+    /// Exports `slice`, which must have been allocated from this pool with
+    /// [CudaDevice::alloc_from_pool()], for processes that imported the pool.
+    ///
+    /// `slice` must outlive every import of it, and be synchronized with any work
+    /// the other processes do on it.
+    pub fn export_pointer<T>(&self, slice: &CudaSlice<T>) -> Result<PoolPtrExport, DriverError> {
+        let export = unsafe { result::mem_pool::export_pointer(slice.cu_device_ptr) }?;
+        let mut data = [0; 8];
+        for (word, chunk) in data.iter_mut().zip(export.reserved.chunks_exact(8)) {
+            *word = u64::from_ne_bytes(chunk.try_into().unwrap());
+        }
+        Ok(PoolPtrExport {
+            data,
+            num_bytes: slice.len * std::mem::size_of::<T>(),
+        })
+    }
+
+    /// Opens an allocation exported with [CudaMemPool::export_pointer()] from the pool this
+    /// pool was imported from. The import is released when the [CudaSlice] is dropped, which
+    /// must happen before the exporting process frees the allocation.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the size of the allocation isn't
+    /// a multiple of the size of `T`.
+    ///
+    /// # Safety
+    /// The memory must be valid for type `T`.
+    #[track_caller]
+    pub unsafe fn import_pointer<T>(
+        &self,
+        export: &PoolPtrExport,
+    ) -> Result<CudaSlice<T>, DriverError> {
+        let size = std::mem::size_of::<T>();
+        if size == 0 || !export.num_bytes.is_multiple_of(size) {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        }
+        let _ctx = self.device.bind_scoped()?;
+        let cu_device_ptr = result::mem_pool::import_pointer(self.cu_pool, export.to_sys())?;
+        Ok(self.device.tracked(CudaSlice {
+            cu_device_ptr,
+            len: export.num_bytes / size,
+            device: self.device.clone(),
+            host_buf: None,
+            location: Location::caller(),
+            last_write: Default::default(),
+            aligned_base: None,
+            allocator: None,
+        }))
+    }
+
+    /// Reads one of the pool's counters or its release threshold.
+    pub fn attribute(&self, attr: PoolAttribute) -> Result<u64, DriverError> {
+        unsafe { result::mem_pool::get_attribute_u64(self.cu_pool, attr.to_sys()) }
+    }
+
+    /// Keeps up to `bytes` of reserved memory in the pool when the stream synchronizes,
+    /// instead of releasing it back to the device.
+    pub fn set_release_threshold(&self, bytes: u64) -> Result<(), DriverError> {
+        unsafe {
+            result::mem_pool::set_attribute_u64(
+                self.cu_pool,
+                PoolAttribute::ReleaseThreshold.to_sys(),
+                bytes,
+            )
+        }
+    }
+
+    /// Resets [PoolAttribute::ReservedMemHigh] and [PoolAttribute::UsedMemHigh] to 0.
+    pub fn reset_high_watermarks(&self) -> Result<(), DriverError> {
+        for attr in [PoolAttribute::ReservedMemHigh, PoolAttribute::UsedMemHigh] {
+            unsafe { result::mem_pool::set_attribute_u64(self.cu_pool, attr.to_sys(), 0) }?;
+        }
+        Ok(())
+    }
+
+    /// Releases reserved memory back to the device until the pool holds at most `bytes`
+    /// that aren't in use. Memory freed on a stream is only released once the free completes.
+    pub fn trim_to(&self, bytes: usize) -> Result<(), DriverError> {
+        unsafe { result::mem_pool::trim_to(self.cu_pool, bytes) }
+    }
+}
+
+impl Drop for CudaMemPool {
+    fn drop(&mut self) {
+        if self.owned {
+            // outstanding allocations keep the pool's memory alive until they're freed
+            unsafe { result::mem_pool::destroy(self.cu_pool) }.or_handle("CudaMemPool::drop");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_mem_pool_stats() {
+        let dev = CudaDevice::new(0).unwrap();
+        let pool = dev.default_mem_pool().unwrap();
+        pool.trim_to(0).unwrap();
+        pool.reset_high_watermarks().unwrap();
+
+        let a = dev.alloc_zeros::<f32>(1 << 20).unwrap();
+        let used = pool.attribute(PoolAttribute::UsedMemCurrent).unwrap();
+        assert!(used >= 4 << 20);
+        assert!(pool.attribute(PoolAttribute::ReservedMemCurrent).unwrap() >= used);
+        drop(a);
+        dev.synchronize().unwrap();
+        assert!(pool.attribute(PoolAttribute::UsedMemCurrent).unwrap() < used);
+        assert!(pool.attribute(PoolAttribute::UsedMemHigh).unwrap() >= used);
+
+        pool.set_release_threshold(u64::MAX).unwrap();
+        assert_eq!(
+            pool.attribute(PoolAttribute::ReleaseThreshold).unwrap(),
+            u64::MAX
+        );
+        pool.set_release_threshold(0).unwrap();
+    }
+
+    #[test]
+    fn test_alloc_from_custom_pool() {
+        let dev = CudaDevice::new(0).unwrap();
+        let pool = dev.create_mem_pool().unwrap();
+        pool.set_release_threshold(u64::MAX).unwrap();
+
+        let a = dev.alloc_zeros_from_pool::<u32>(&pool, 1000).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), [0; 1000]);
+        assert!(pool.attribute(PoolAttribute::UsedMemCurrent).unwrap() >= 4000);
+        drop(a);
+        dev.synchronize().unwrap();
+
+        // released memory stays reserved in the pool until trimmed
+        assert_eq!(pool.attribute(PoolAttribute::UsedMemCurrent).unwrap(), 0);
+        assert!(pool.attribute(PoolAttribute::ReservedMemCurrent).unwrap() > 0);
+        pool.trim_to(0).unwrap();
+        assert_eq!(
+            pool.attribute(PoolAttribute::ReservedMemCurrent).unwrap(),
+            0
+        );
+    }
+
+    #[cfg(unix)]
+    const EXPORT_VAR: &str = "CUDARC_TEST_POOL_PTR_EXPORT";
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ipc_mem_pool_two_processes() {
+        use std::os::fd::{FromRawFd, OwnedFd};
+        use std::process::{Command, Stdio};
+
+        if let Ok(export) = std::env::var(EXPORT_VAR) {
+            // the spawned importer, which inherited the pool's fd as its stdin
+            let words: std::vec::Vec<u64> = export.split(',').map(|w| w.parse().unwrap()).collect();
+            let export = PoolPtrExport {
+                data: words[..8].try_into().unwrap(),
+                num_bytes: words[8] as usize,
+            };
+            let dev = CudaDevice::new(0).unwrap();
+            let pool = CudaMemPool::import_shareable(&dev, ShareableHandle::PosixFileDescriptor(0))
+                .unwrap();
+            let mut buf = unsafe { pool.import_pointer::<u32>(&export) }.unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&buf).unwrap(), [1, 2, 3, 4]);
+            dev.htod_sync_copy_into(&[5, 6, 7, 8], &mut buf).unwrap();
+            drop(buf);
+            dev.synchronize().unwrap();
+            return;
+        }
+
+        let dev = CudaDevice::new(0).unwrap();
+        let pool = dev
+            .create_shareable_mem_pool(ShareableHandleType::PosixFileDescriptor)
+            .unwrap();
+        let mut buf = unsafe { dev.alloc_from_pool::<u32>(&pool, 4) }.unwrap();
+        dev.htod_sync_copy_into(&[1, 2, 3, 4], &mut buf).unwrap();
+        let export = pool.export_pointer(&buf).unwrap();
+        assert_eq!(export.num_bytes(), 16);
+        let ShareableHandle::PosixFileDescriptor(fd) = pool
+            .export_shareable(ShareableHandleType::PosixFileDescriptor)
+            .unwrap()
+        else {
+            unreachable!()
+        };
+
+        // cuda can't be used after `fork`, so re-run this test binary instead
+        let mut words: std::vec::Vec<std::string::String> = export
+            .data
+            .iter()
+            .map(std::string::ToString::to_string)
+            .collect();
+        words.push(std::string::ToString::to_string(&export.num_bytes));
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "driver::safe::mem_pool::tests::test_ipc_mem_pool_two_processes",
+            ])
+            .env(EXPORT_VAR, words.join(","))
+            .stdin(Stdio::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(dev.dtoh_sync_copy(&buf).unwrap(), [5, 6, 7, 8]);
+    }
+}
@@ -1,19 +1,146 @@
 //! Safe abstractions over [crate::driver::result] provided by [CudaSlice], [CudaDevice], [CudaStream], and more.
 
+pub(crate) mod abi;
 pub(crate) mod alloc;
+pub(crate) mod allocator;
+pub(crate) mod allowlist;
+pub(crate) mod arch_dispatch;
+pub(crate) mod batch_copy;
+pub(crate) mod cache_config;
+pub(crate) mod chunked;
+pub(crate) mod context;
 pub(crate) mod core;
+#[cfg(feature = "cpu-fallback")]
+pub(crate) mod cpu;
+pub(crate) mod deferred_free;
+pub(crate) mod determinism;
+pub(crate) mod device_log;
+pub(crate) mod device_pool;
 pub(crate) mod device_ptr;
+#[cfg(feature = "std")]
+pub(crate) mod diagnose;
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "cuda-11040", feature = "cuda-11050", feature = "cuda-11060"))
+))]
+pub(crate) mod dmabuf;
+pub(crate) mod error_flag;
+pub(crate) mod error_handler;
 pub(crate) mod external_memory;
+pub(crate) mod external_semaphore;
+pub(crate) mod graph;
+#[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+pub(crate) mod green_ctx;
+pub(crate) mod init_hooks;
 pub(crate) mod launch;
+pub(crate) mod launch_log;
+pub(crate) mod library;
+pub(crate) mod limits;
+pub(crate) mod mem_op;
+pub(crate) mod mem_pool;
+pub(crate) mod ops;
+pub(crate) mod perf_hints;
+pub(crate) mod philox;
+pub(crate) mod pinned;
+pub(crate) mod pitched;
+pub(crate) mod pointer;
+pub(crate) mod poison;
+pub(crate) mod printf;
 pub(crate) mod profile;
+pub(crate) mod ptr_array;
 pub(crate) mod ptx;
+#[cfg(feature = "std")]
+pub(crate) mod recorder;
+pub(crate) mod reduce;
+pub(crate) mod ring_buffer;
+pub(crate) mod scan;
+pub(crate) mod scratch;
+pub(crate) mod smem;
+pub(crate) mod sort;
+pub(crate) mod staging;
+pub(crate) mod stream_tracking;
+pub(crate) mod sync_debug;
+pub(crate) mod task_graph;
+pub(crate) mod template;
 pub(crate) mod threading;
+pub(crate) mod transfer;
+pub(crate) mod typed;
+pub(crate) mod work_token;
 
-pub use self::alloc::{DeviceRepr, ValidAsZeroBits};
-pub use self::core::{CudaDevice, CudaFunction, CudaSlice, CudaStream, CudaView, CudaViewMut};
+pub use self::abi::{AbiError, AbiHash, AbiHasher, ABI_HASH_SRC};
+#[cfg(feature = "allocation-tracking")]
+pub use self::alloc::AllocStats;
+pub use self::alloc::{DeviceRepr, PendingValue, ValidAsZeroBits};
+pub use self::allocator::{AllocatorStats, CachingAllocator, DeviceAllocator};
+pub use self::allowlist::{
+    clear_device_allowlist, device_allowlist, set_device_allowlist, set_device_allowlist_by_uuid,
+};
+pub use self::arch_dispatch::{ArchDispatch, ArchVariant};
+pub use self::batch_copy::CopyJob;
+pub use self::cache_config::{CacheConfig, SharedMemCarveout};
+pub use self::chunked::ChunkConfig;
+pub use self::context::{ContextGuard, ContextKind};
+pub use self::core::{
+    CudaDevice, CudaFunction, CudaSlice, CudaStream, CudaView, CudaViewMut, StreamRef,
+};
+#[cfg(feature = "cpu-fallback")]
+pub use self::cpu::{CpuDevice, Device, DeviceBuffer, DeviceOps, HostDtype};
+pub use self::determinism::Determinism;
+pub use self::device_log::{DeviceLog, LogRecord, DEVICE_LOG_SRC};
+pub use self::device_pool::CudaDevicePool;
 pub use self::device_ptr::{DevicePtr, DevicePtrMut, DeviceSlice};
-pub use self::external_memory::{ExternalMemory, MappedBuffer, ExternalMemoryType, MipMappedArray};
-pub use self::launch::{LaunchAsync, LaunchConfig};
+#[cfg(feature = "std")]
+pub use self::diagnose::{diagnose, DeviceNode, DeviceSummary, DiagnosticsReport, Problem};
+pub use self::error_flag::{DeviceAssert, DeviceErrorFlag, DEVICE_ASSERT_SRC};
+pub use self::error_handler::{log_error, panic_on_error, set_error_handler, ErrorHandler};
+pub use self::external_memory::{
+    ExternalMemory, ExternalMemoryType, KeepAlive, MapFlags, MappedBuffer, MipMappedArray,
+};
+pub use self::external_semaphore::{ExternalSemaphore, ExternalSemaphoreType};
+pub use self::graph::{CudaGraph, GraphNode};
+#[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+pub use self::green_ctx::GreenContext;
+pub use self::init_hooks::{register_device_init_hook, DeviceInitHook};
+pub use self::launch::{
+    LaunchAsync, LaunchAttribute, LaunchConfig, LaunchConfigEx, GRID_STRIDE_SRC,
+};
+pub use self::launch_log::LaunchScope;
+pub use self::library::{CudaKernel, CudaLibrary, JitOptions};
+pub use self::limits::DeviceLimits;
+pub use self::mem_op::{MemOp, WaitCond};
+pub use self::mem_pool::{
+    CudaMemPool, PoolAttribute, PoolPtrExport, ShareableHandle, ShareableHandleType,
+};
+pub use self::ops::{
+    ApplyDtype, BinaryOp, GridPadding, GridSampleConfig, GridSampleMode, IndexCheck, OutOfRange,
+    UnaryOp, INDEX_OUT_OF_BOUNDS,
+};
+pub use self::perf_hints::PerfHints;
+pub use self::philox::PhiloxState;
+pub use self::pinned::PinnedBuffer;
+pub use self::pitched::PitchedCudaSlice;
+pub use self::pointer::{ForeignCudaSlice, PointerAttributes};
+#[cfg(all(unix, feature = "std"))]
+pub use self::printf::PrintfCapture;
 pub use self::profile::{profiler_start, profiler_stop, Profiler};
+pub use self::ptr_array::PtrArray;
+pub use self::ptx::FunctionInfo;
+#[cfg(feature = "std")]
+pub use self::recorder::{CapturedLaunch, LaunchRecorder, RecordedArg};
+pub use self::reduce::ReduceDtype;
+pub use self::ring_buffer::{DeviceRingBuffer, RingBufferError, RingHeader, RingOverrun};
+pub use self::scan::ScanDtype;
+pub use self::scratch::{ScratchArena, ScratchSlice, ScratchStats, DEFAULT_SCRATCH_LIMIT};
+pub use self::smem::{BuiltSmemLayout, SmemArray, SmemLayout};
+pub use self::sort::SortKey;
+pub use self::stream_tracking::LastWrite;
+pub use self::sync_debug::SyncDebug;
+pub use self::task_graph::{TaskGraph, TaskGraphError, TaskId, TaskRun};
+pub use self::template::{KernelTemplate, TemplateError};
+pub use self::transfer::PendingTransfer;
+pub use self::typed::{KernelArg, KernelParam, KernelSignature, TypedFunction};
+pub use self::work_token::{AcquirePolicy, BufferRing, WorkToken};
 
-pub use crate::driver::result::DriverError;
+pub use crate::driver::result::{
+    ArchRange, DeviceLimitError, DriverError, ErrorKind, LaunchBound, LaunchRecord,
+};
@@ -0,0 +1,1360 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction};
+use super::device_ptr::{DevicePtr, DevicePtrMut};
+use super::error_flag::{DeviceErrorFlag, DEVICE_ASSERT_SRC};
+use super::{DeviceRepr, LaunchAsync, LaunchConfig};
+
+use std::{string::String, sync::Arc};
+
+/// Element types supported by [CudaDevice::apply_unary()] and [CudaDevice::apply_binary()].
+///
+/// # Safety
+/// [ApplyDtype::C_TYPE] must be the C type with the same layout as `Self`.
+pub unsafe trait ApplyDtype: DeviceRepr + Copy {
+    /// The name of the type in CUDA C, e.g. `float`.
+    const C_TYPE: &'static str;
+    /// Converts the scalar of e.g. [UnaryOp::AddScalar].
+    fn from_f64(value: f64) -> Self;
+}
+
+unsafe impl ApplyDtype for f32 {
+    const C_TYPE: &'static str = "float";
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+unsafe impl ApplyDtype for f64 {
+    const C_TYPE: &'static str = "double";
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+macro_rules! elementwise_ops {
+    (
+        $(#[$unary_meta:meta])* enum $Unary:ident {
+            $($(#[$umeta:meta])* $uop:ident $(($scalar:ident))? => $uname:literal, $uexpr:literal;)+
+        }
+        $(#[$binary_meta:meta])* enum $Binary:ident {
+            $($(#[$bmeta:meta])* $bop:ident => $bname:literal, $bexpr:literal;)+
+        }
+    ) => {
+        $(#[$unary_meta])*
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum $Unary {
+            $($(#[$umeta])* $uop $(($scalar))?,)+
+        }
+
+        impl $Unary {
+            fn kernel_name(&self) -> &'static str {
+                match self {
+                    $(Self::$uop { .. } => concat!("unary_", $uname),)+
+                }
+            }
+
+            fn scalar(&self) -> f64 {
+                match *self {
+                    $($(Self::$uop(s) => s as $scalar,)?)+
+                    _ => 0.0,
+                }
+            }
+        }
+
+        $(#[$binary_meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $Binary {
+            $($(#[$bmeta])* $bop,)+
+        }
+
+        impl $Binary {
+            fn kernel_name(&self) -> &'static str {
+                match self {
+                    $(Self::$bop => concat!("binary_", $bname),)+
+                }
+            }
+        }
+
+        const KERNEL_NAMES: &[&str] = &[
+            $(concat!("unary_", $uname),)+
+            $(concat!("binary_", $bname),)+
+        ];
+
+        /// The source of every kernel for `T`, which is typedef'd to the element type.
+        fn kernels_src(c_type: &str) -> String {
+            let mut src = std::format!("typedef {c_type} T;\n");
+            $(src.push_str(&std::format!(
+                "extern \"C\" __global__ void unary_{}(const T *x, T *out, const size_t n, const T s) {{
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {{
+        const T v = x[i];
+        out[i] = {};
+    }}
+}}
+",
+                $uname, $uexpr
+            ));)+
+            $(src.push_str(&std::format!(
+                "extern \"C\" __global__ void binary_{}(const T *x, const T *y, T *out, const size_t n) {{
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {{
+        const T a = x[i];
+        const T b = y[i];
+        out[i] = {};
+    }}
+}}
+",
+                $bname, $bexpr
+            ));)+
+            src
+        }
+    };
+}
+
+elementwise_ops! {
+    /// An elementwise operation for [CudaDevice::apply_unary()]. `v` below is the input element.
+    enum UnaryOp {
+        /// `-v`
+        Neg => "neg", "-v";
+        /// `|v|`
+        Abs => "abs", "fabs(v)";
+        /// `e^v`
+        Exp => "exp", "exp(v)";
+        /// `ln(v)`
+        Log => "log", "log(v)";
+        /// `sqrt(v)`
+        Sqrt => "sqrt", "sqrt(v)";
+        /// `1 / v`
+        Recip => "recip", "T(1) / v";
+        /// `v * v`
+        Square => "square", "v * v";
+        Sin => "sin", "sin(v)";
+        Cos => "cos", "cos(v)";
+        Tanh => "tanh", "tanh(v)";
+        /// `1 / (1 + e^-v)`
+        Sigmoid => "sigmoid", "T(1) / (T(1) + exp(-v))";
+        /// `max(v, 0)`
+        Relu => "relu", "v > T(0) ? v : T(0)";
+        /// The tanh approximation of gelu.
+        Gelu => "gelu", "T(0.5) * v * (T(1) + tanh(T(0.7978845608028654) * (v + T(0.044715) * v * v * v)))";
+        Floor => "floor", "floor(v)";
+        Ceil => "ceil", "ceil(v)";
+        /// `v + s`
+        AddScalar(f64) => "add_scalar", "v + s";
+        /// `v * s`
+        MulScalar(f64) => "mul_scalar", "v * s";
+        /// `v^s`
+        PowScalar(f64) => "pow_scalar", "pow(v, s)";
+        /// `max(v, s)`
+        MaxScalar(f64) => "max_scalar", "fmax(v, s)";
+        /// `min(v, s)`
+        MinScalar(f64) => "min_scalar", "fmin(v, s)";
+    }
+
+    /// An elementwise operation for [CudaDevice::apply_binary()]. `a` and `b` below are the
+    /// elements of the first and second input.
+    enum BinaryOp {
+        /// `a + b`
+        Add => "add", "a + b";
+        /// `a - b`
+        Sub => "sub", "a - b";
+        /// `a * b`
+        Mul => "mul", "a * b";
+        /// `a / b`
+        Div => "div", "a / b";
+        /// `max(a, b)`
+        Max => "max", "fmax(a, b)";
+        /// `min(a, b)`
+        Min => "min", "fmin(a, b)";
+        /// `a^b`
+        Pow => "pow", "pow(a, b)";
+    }
+}
+
+impl CudaDevice {
+    /// Sets `out[i] = op(x[i])`, e.g. `dev.apply_unary(UnaryOp::Exp, &x, &mut out)`.
+    ///
+    /// The kernels for `T` are compiled with nvrtc the first time an op is applied to `T`,
+    /// and loaded into a module named `cudarc_ops_<T>`.
+    /// See [CudaDevice::apply_unary_in_place()] to overwrite `x`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the lengths differ.
+    pub fn apply_unary<T: ApplyDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        op: UnaryOp,
+        x: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        check_len(x.len(), out.len())?;
+        let (x, n) = (*x.device_ptr(), x.len());
+        unsafe { self.launch_unary::<T>(op, x, *out.device_ptr_mut(), n) }
+    }
+
+    /// Sets `x[i] = op(x[i])`.
+    pub fn apply_unary_in_place<T: ApplyDtype, X: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        op: UnaryOp,
+        x: &mut X,
+    ) -> Result<(), result::DriverError> {
+        let n = x.len();
+        let x = *x.device_ptr_mut();
+        unsafe { self.launch_unary::<T>(op, x, x, n) }
+    }
+
+    /// Sets `out[i] = op(x[i], y[i])`, see [CudaDevice::apply_unary()].
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the lengths differ.
+    pub fn apply_binary<T: ApplyDtype, X: DevicePtr<T>, Y: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        op: BinaryOp,
+        x: &X,
+        y: &Y,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        check_len(x.len(), y.len())?;
+        check_len(x.len(), out.len())?;
+        let (x, y, n) = (*x.device_ptr(), *y.device_ptr(), x.len());
+        unsafe { self.launch_binary::<T>(op, x, y, *out.device_ptr_mut(), n) }
+    }
+
+    /// Sets `x[i] = op(x[i], y[i])`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the lengths differ.
+    pub fn apply_binary_in_place<T: ApplyDtype, X: DevicePtrMut<T>, Y: DevicePtr<T>>(
+        self: &Arc<Self>,
+        op: BinaryOp,
+        x: &mut X,
+        y: &Y,
+    ) -> Result<(), result::DriverError> {
+        check_len(x.len(), y.len())?;
+        let n = x.len();
+        let x = *x.device_ptr_mut();
+        unsafe { self.launch_binary::<T>(op, x, *y.device_ptr(), x, n) }
+    }
+
+    /// # Safety
+    /// `x` and `out` must hold `n` elements of `T`.
+    unsafe fn launch_unary<T: ApplyDtype>(
+        self: &Arc<Self>,
+        op: UnaryOp,
+        x: sys::CUdeviceptr,
+        out: sys::CUdeviceptr,
+        n: usize,
+    ) -> Result<(), result::DriverError> {
+        if n == 0 {
+            return Ok(());
+        }
+        let f = self.ops_function::<T>(op.kernel_name())?;
+        f.launch(
+            self.default_stream(),
+            elementwise_cfg(n),
+            (x, out, n, T::from_f64(op.scalar())),
+        )
+    }
+
+    /// # Safety
+    /// `x`, `y` and `out` must hold `n` elements of `T`.
+    unsafe fn launch_binary<T: ApplyDtype>(
+        self: &Arc<Self>,
+        op: BinaryOp,
+        x: sys::CUdeviceptr,
+        y: sys::CUdeviceptr,
+        out: sys::CUdeviceptr,
+        n: usize,
+    ) -> Result<(), result::DriverError> {
+        if n == 0 {
+            return Ok(());
+        }
+        let f = self.ops_function::<T>(op.kernel_name())?;
+        f.launch(self.default_stream(), elementwise_cfg(n), (x, y, out, n))
+    }
+
+    fn ops_function<T: ApplyDtype>(
+        self: &Arc<Self>,
+        name: &str,
+    ) -> Result<super::CudaFunction, result::DriverError> {
+        let module = std::format!("cudarc_ops_{}", T::C_TYPE);
+        self.load_builtin(&module, || kernels_src(T::C_TYPE), KERNEL_NAMES, name)
+    }
+}
+
+/// The `code` of the [super::DeviceAssert] reported by [IndexCheck::Flag].
+pub const INDEX_OUT_OF_BOUNDS: u32 = 0x1d0b;
+
+/// How [CudaDevice::gather_rows_checked()] and [CudaDevice::scatter_rows_checked()]
+/// handle row indices that are out of bounds.
+#[derive(Debug, Clone, Copy)]
+pub enum IndexCheck<'a> {
+    /// Rows with an out of bounds index are skipped on the device, leaving the
+    /// corresponding output untouched.
+    Skip,
+    /// Copies the indices to the host and returns
+    /// [sys::CUresult::CUDA_ERROR_INVALID_VALUE] before launching if any is out of
+    /// bounds. Synchronizes with the device.
+    Host,
+    /// Skips the rows like [IndexCheck::Skip], and records the first one in the flag with
+    /// code [INDEX_OUT_OF_BOUNDS], without synchronizing.
+    Flag(&'a DeviceErrorFlag),
+}
+
+/// The unsigned integer types the row kernels copy elements as, by size.
+const ROW_WORDS: [(usize, &str); 4] = [
+    (1, "unsigned char"),
+    (2, "unsigned short"),
+    (4, "unsigned int"),
+    (8, "unsigned long long"),
+];
+
+/// The source of [CudaDevice::gather_rows()] and [CudaDevice::scatter_rows()] for every
+/// element size. A null `flag` skips out of bounds rows silently.
+fn rows_src() -> String {
+    let mut src = String::from(DEVICE_ASSERT_SRC);
+    for (size, word) in ROW_WORDS {
+        src.push_str(&std::format!(
+            "extern \"C\" __global__ void gather_rows_{size}(const {word} *src, const unsigned int *idx, {word} *out, const size_t rows, const size_t row_len, const size_t src_rows, unsigned int *flag) {{
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < rows * row_len; i += (size_t)blockDim.x * gridDim.x) {{
+        const size_t r = i / row_len;
+        const size_t j = idx[r];
+        if (j >= src_rows) {{
+            if (flag) {{ CUDARC_ASSERT(flag, false, {INDEX_OUT_OF_BOUNDS}u); }}
+            continue;
+        }}
+        out[i] = src[j * row_len + (i - r * row_len)];
+    }}
+}}
+extern \"C\" __global__ void scatter_rows_{size}(const {word} *src, const unsigned int *idx, {word} *out, const size_t rows, const size_t row_len, const size_t out_rows, unsigned int *flag) {{
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < rows * row_len; i += (size_t)blockDim.x * gridDim.x) {{
+        const size_t r = i / row_len;
+        const size_t j = idx[r];
+        if (j >= out_rows) {{
+            if (flag) {{ CUDARC_ASSERT(flag, false, {INDEX_OUT_OF_BOUNDS}u); }}
+            continue;
+        }}
+        out[j * row_len + (i - r * row_len)] = src[i];
+    }}
+}}
+"
+        ));
+    }
+    src
+}
+
+const ROW_KERNEL_NAMES: &[&str] = &[
+    "gather_rows_1",
+    "gather_rows_2",
+    "gather_rows_4",
+    "gather_rows_8",
+    "scatter_rows_1",
+    "scatter_rows_2",
+    "scatter_rows_4",
+    "scatter_rows_8",
+];
+
+impl CudaDevice {
+    /// Sets row `i` of `out` to row `indices[i]` of `src`, where rows are `row_len`
+    /// elements long, e.g. to reorder the beams of a `[batch * beam, hidden]` tensor.
+    /// Indices may repeat and be in any order.
+    ///
+    /// Out of bounds indices are skipped, see [CudaDevice::gather_rows_checked()] to detect
+    /// them. `T` is copied bitwise and can be any type of 1, 2, 4 or 8 bytes, e.g. `f32`,
+    /// `half::f16` or `half::bf16`. The kernels are compiled with nvrtc on first use, and
+    /// loaded into a module named `cudarc_rows`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `row_len` is 0, `src` isn't a
+    /// whole number of rows, `out` isn't `indices.len()` rows or `T` has another size.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let src = dev.htod_sync_copy(&[0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+    /// let indices = dev.htod_sync_copy(&[2u32, 0, 2]).unwrap();
+    /// let mut out = dev.alloc_zeros::<f32>(6).unwrap();
+    /// dev.gather_rows(&src, &indices, 2, &mut out).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [4.0, 5.0, 0.0, 1.0, 4.0, 5.0]);
+    /// ```
+    pub fn gather_rows<
+        T: DeviceRepr,
+        Src: DevicePtr<T>,
+        I: DevicePtr<u32>,
+        Out: DevicePtrMut<T>,
+    >(
+        self: &Arc<Self>,
+        src: &Src,
+        indices: &I,
+        row_len: usize,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.gather_rows_checked(src, indices, row_len, out, IndexCheck::Skip)
+    }
+
+    /// [CudaDevice::gather_rows()], handling out of bounds indices according to `check`.
+    pub fn gather_rows_checked<
+        T: DeviceRepr,
+        Src: DevicePtr<T>,
+        I: DevicePtr<u32>,
+        Out: DevicePtrMut<T>,
+    >(
+        self: &Arc<Self>,
+        src: &Src,
+        indices: &I,
+        row_len: usize,
+        out: &mut Out,
+        check: IndexCheck,
+    ) -> Result<(), result::DriverError> {
+        let src_rows = whole_rows(src.len(), row_len)?;
+        check_len(indices.len() * row_len, out.len())?;
+        let (src, out) = (*src.device_ptr(), *out.device_ptr_mut());
+        unsafe {
+            self.launch_rows::<T, I>("gather_rows", src, indices, out, row_len, src_rows, check)
+        }
+    }
+
+    /// Sets row `indices[i]` of `out` to row `i` of `src`, the inverse of
+    /// [CudaDevice::gather_rows()]. Rows of `out` that no index refers to are untouched.
+    ///
+    /// If an index repeats, which of its rows of `src` is written is unspecified. Out of
+    /// bounds indices are skipped, see [CudaDevice::scatter_rows_checked()] to detect them.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `row_len` is 0, `out` isn't a
+    /// whole number of rows, `src` isn't `indices.len()` rows or `T` isn't supported by
+    /// [CudaDevice::gather_rows()].
+    pub fn scatter_rows<
+        T: DeviceRepr,
+        Src: DevicePtr<T>,
+        I: DevicePtr<u32>,
+        Out: DevicePtrMut<T>,
+    >(
+        self: &Arc<Self>,
+        src: &Src,
+        indices: &I,
+        row_len: usize,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.scatter_rows_checked(src, indices, row_len, out, IndexCheck::Skip)
+    }
+
+    /// [CudaDevice::scatter_rows()], handling out of bounds indices according to `check`.
+    pub fn scatter_rows_checked<
+        T: DeviceRepr,
+        Src: DevicePtr<T>,
+        I: DevicePtr<u32>,
+        Out: DevicePtrMut<T>,
+    >(
+        self: &Arc<Self>,
+        src: &Src,
+        indices: &I,
+        row_len: usize,
+        out: &mut Out,
+        check: IndexCheck,
+    ) -> Result<(), result::DriverError> {
+        let out_rows = whole_rows(out.len(), row_len)?;
+        check_len(indices.len() * row_len, src.len())?;
+        let (src, out) = (*src.device_ptr(), *out.device_ptr_mut());
+        unsafe {
+            self.launch_rows::<T, I>("scatter_rows", src, indices, out, row_len, out_rows, check)
+        }
+    }
+
+    /// # Safety
+    /// `src` and `out` must hold `indices.len()` rows of `row_len` elements of `T` and
+    /// `bound` rows, in the order of the kernel's parameters.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn launch_rows<T, I: DevicePtr<u32>>(
+        self: &Arc<Self>,
+        kernel: &str,
+        src: sys::CUdeviceptr,
+        indices: &I,
+        out: sys::CUdeviceptr,
+        row_len: usize,
+        bound: usize,
+        check: IndexCheck,
+    ) -> Result<(), result::DriverError> {
+        let size = std::mem::size_of::<T>();
+        if !ROW_WORDS.iter().any(|&(s, _)| s == size) {
+            return Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+            ));
+        }
+        let flag = match check {
+            IndexCheck::Skip => 0,
+            IndexCheck::Host => {
+                let host = self.dtoh_sync_copy(indices)?;
+                if host.iter().any(|&j| j as usize >= bound) {
+                    return Err(result::DriverError::Cuda(
+                        sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+                    ));
+                }
+                0
+            }
+            IndexCheck::Flag(flag) => flag.device_ptr(),
+        };
+        let n = indices.len() * row_len;
+        if n == 0 {
+            return Ok(());
+        }
+        let name = std::format!("{kernel}_{size}");
+        let f = self.load_builtin("cudarc_rows", rows_src, ROW_KERNEL_NAMES, &name)?;
+        let rows = indices.len();
+        let indices = *indices.device_ptr();
+        f.launch(
+            self.default_stream(),
+            elementwise_cfg(n),
+            (src, indices, out, rows, row_len, bound, flag),
+        )
+    }
+}
+
+/// What [CudaDevice::histogram_range()] does with values outside of its range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfRange {
+    /// Values below the range are counted in the first bin, and values above it in the
+    /// last one.
+    Clamp,
+    /// Values outside of the range aren't counted in any bin.
+    Ignore,
+}
+
+/// The shared memory a block can use without opting in, which the bins counted in shared
+/// memory have to fit in together with the static shared memory of the kernel. Larger
+/// histograms are counted in global memory directly.
+const PRIVATE_BINS_BYTES: usize = 48 * 1024;
+
+/// The source of [CudaDevice::histogram()] and [CudaDevice::histogram_range()]. Each block
+/// counts into its own copy of the bins in shared memory if `private` is set. `ignored`
+/// counts the floats that aren't in any bin.
+const HISTOGRAM_SRC: &str = "
+extern \"C\" __global__ void histogram_u32(const unsigned int *x, const size_t n, const unsigned int num_bins, const int private_bins, unsigned int *out) {
+    extern __shared__ unsigned int local[];
+    unsigned int *bins = private_bins ? local : out;
+    if (private_bins) {
+        for (unsigned int b = threadIdx.x; b < num_bins; b += blockDim.x) local[b] = 0;
+        __syncthreads();
+    }
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {
+        const unsigned int v = x[i];
+        if (v < num_bins) atomicAdd(&bins[v], 1u);
+    }
+    if (private_bins) {
+        __syncthreads();
+        for (unsigned int b = threadIdx.x; b < num_bins; b += blockDim.x) {
+            if (local[b]) atomicAdd(&out[b], local[b]);
+        }
+    }
+}
+extern \"C\" __global__ void histogram_f32(const float *x, const size_t n, const float lo, const float hi, const unsigned int num_bins, const int clamp, const int private_bins, unsigned int *out, unsigned int *ignored) {
+    extern __shared__ unsigned int local[];
+    __shared__ unsigned int skipped;
+    unsigned int *bins = private_bins ? local : out;
+    if (threadIdx.x == 0) skipped = 0;
+    if (private_bins) {
+        for (unsigned int b = threadIdx.x; b < num_bins; b += blockDim.x) local[b] = 0;
+    }
+    __syncthreads();
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {
+        const float v = x[i];
+        unsigned int b;
+        if (v >= lo && v <= hi) {
+            b = (unsigned int)((v - lo) / (hi - lo) * (float)num_bins);
+            if (b >= num_bins) b = num_bins - 1;
+        } else if (clamp && v < lo) {
+            b = 0;
+        } else if (clamp && v > hi) {
+            b = num_bins - 1;
+        } else {
+            atomicAdd(&skipped, 1u);
+            continue;
+        }
+        atomicAdd(&bins[b], 1u);
+    }
+    __syncthreads();
+    if (private_bins) {
+        for (unsigned int b = threadIdx.x; b < num_bins; b += blockDim.x) {
+            if (local[b]) atomicAdd(&out[b], local[b]);
+        }
+    }
+    if (threadIdx.x == 0 && skipped) atomicAdd(ignored, skipped);
+}
+";
+
+const HISTOGRAM_KERNEL_NAMES: &[&str] = &["histogram_u32", "histogram_f32"];
+
+impl CudaDevice {
+    /// Sets `out[b]` to the number of `values` equal to `b`, like numpy's `bincount` with
+    /// `minlength = out.len()`. Values of `out.len()` or more aren't counted.
+    ///
+    /// Each block counts into shared memory if `out` fits in 48 KB of it, i.e. has at most
+    /// 12288 bins, and adds its counts to `out` at the end. The counts are exact, so they
+    /// are the same on every call even though they are added up with atomics. The kernels
+    /// are compiled with nvrtc on first use, and loaded into a module named
+    /// `cudarc_histogram`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `out` is empty or has more than
+    /// `u32::MAX` bins.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let values = dev.htod_sync_copy(&[1u32, 3, 1, 0, 7]).unwrap();
+    /// let mut counts = dev.alloc_zeros::<u32>(4).unwrap();
+    /// dev.histogram(&values, &mut counts).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&counts).unwrap(), [1, 2, 0, 1]);
+    /// ```
+    pub fn histogram<X: DevicePtr<u32>, Out: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        values: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        histogram_cfg(values.len(), out.len(), 0)?;
+        self.memset_zeros(out)?;
+        if values.len() == 0 {
+            return Ok(());
+        }
+        let f = self.histogram_function("histogram_u32")?;
+        let (num_bins, private_bins, cfg) =
+            histogram_cfg(values.len(), out.len(), static_shared_bytes(&f)?)?;
+        let (x, n) = (*values.device_ptr(), values.len());
+        let out = *out.device_ptr_mut();
+        unsafe {
+            f.launch(
+                self.default_stream(),
+                cfg,
+                (x, n, num_bins, private_bins as i32, out),
+            )
+        }
+    }
+
+    /// Splits `[min, max]` into `out.len()` bins of equal width, and sets `out[b]` to the
+    /// number of `values` in bin `b`. Values equal to `max` are counted in the last bin.
+    ///
+    /// Values outside of the range are handled according to `out_of_range`, and NaNs are
+    /// never counted. Returns the number of values that weren't counted in any bin, after
+    /// waiting for it to be copied to the host.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `min` and `max` aren't finite,
+    /// `min >= max`, or `out` isn't supported by [CudaDevice::histogram()].
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let values = dev.htod_sync_copy(&[0.1f32, 0.6, 1.0, 2.5, -1.0]).unwrap();
+    /// let mut counts = dev.alloc_zeros::<u32>(2).unwrap();
+    /// let ignored = dev
+    ///     .histogram_range(&values, 0.0, 1.0, &mut counts, OutOfRange::Ignore)
+    ///     .unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&counts).unwrap(), [1, 2]);
+    /// assert_eq!(ignored, 2);
+    /// ```
+    pub fn histogram_range<X: DevicePtr<f32>, Out: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        values: &X,
+        min: f32,
+        max: f32,
+        out: &mut Out,
+        out_of_range: OutOfRange,
+    ) -> Result<usize, result::DriverError> {
+        if !(min.is_finite() && max.is_finite() && min < max) {
+            return Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+            ));
+        }
+        histogram_cfg(values.len(), out.len(), 0)?;
+        self.memset_zeros(out)?;
+        if values.len() == 0 {
+            return Ok(0);
+        }
+        let mut ignored = self.alloc_zeros::<u32>(1)?;
+        let f = self.histogram_function("histogram_f32")?;
+        let (num_bins, private_bins, cfg) =
+            histogram_cfg(values.len(), out.len(), static_shared_bytes(&f)?)?;
+        let (x, n) = (*values.device_ptr(), values.len());
+        let out = *out.device_ptr_mut();
+        let clamp = (out_of_range == OutOfRange::Clamp) as i32;
+        let params = (
+            x,
+            n,
+            min,
+            max,
+            num_bins,
+            clamp,
+            private_bins as i32,
+            out,
+            &mut ignored,
+        );
+        unsafe { f.launch(self.default_stream(), cfg, params) }?;
+        let ignored = unsafe { self.readback_scalar::<u32>(*ignored.device_ptr(), self.stream) }?;
+        Ok(ignored.wait()? as usize)
+    }
+
+    fn histogram_function(
+        self: &Arc<Self>,
+        name: &str,
+    ) -> Result<CudaFunction, result::DriverError> {
+        self.load_builtin(
+            "cudarc_histogram",
+            || HISTOGRAM_SRC,
+            HISTOGRAM_KERNEL_NAMES,
+            name,
+        )
+    }
+}
+
+/// How [CudaDevice::grid_sample()] interpolates the input at a point of the grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GridSampleMode {
+    /// The weighted average of the 4 nearest pixels.
+    #[default]
+    Bilinear,
+    /// The nearest pixel, rounding halfway points to even.
+    Nearest,
+}
+
+/// What [CudaDevice::grid_sample()] reads for points outside of the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GridPadding {
+    /// Zeros, like the cuDNN spatial transformer sampler.
+    #[default]
+    Zeros,
+    /// The nearest pixel on the border.
+    Border,
+    /// The input mirrored at its borders.
+    Reflection,
+}
+
+/// The options of [CudaDevice::grid_sample()]. The default matches the sampler of
+/// `cudnn::SpatialTfSamplerForward`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GridSampleConfig {
+    pub mode: GridSampleMode,
+    pub padding: GridPadding,
+    /// Whether `-1` and `1` are the centers of the corner pixels of the input, instead of
+    /// their outer edges.
+    pub align_corners: bool,
+}
+
+impl Default for GridSampleConfig {
+    fn default() -> Self {
+        Self {
+            mode: GridSampleMode::Bilinear,
+            padding: GridPadding::Zeros,
+            align_corners: true,
+        }
+    }
+}
+
+/// One thread per output element. The coordinates follow `torch.nn.functional.grid_sample`.
+const GRID_SAMPLE_SRC: &str = r#"
+__device__ T reflect(T x, const T twice_low, const T twice_high) {
+    if (twice_low == twice_high) return 0;
+    const T low = twice_low / 2;
+    const T span = (twice_high - twice_low) / 2;
+    x = fabs(x - low);
+    const T extra = fmod(x, span);
+    const long long flips = (long long)floor(x / span);
+    return (flips % 2 == 0) ? extra + low : span - extra + low;
+}
+
+__device__ T source_index(const T g, const long long size, const int padding, const int align) {
+    T x = align ? (g + 1) / 2 * (size - 1) : ((g + 1) * size - 1) / 2;
+    if (padding == 2) {
+        x = align ? reflect(x, 0, 2 * (size - 1)) : reflect(x, -1, 2 * size - 1);
+    }
+    if (padding >= 1) {
+        x = fmin((T)(size - 1), fmax(x, (T)0));
+    }
+    return x;
+}
+
+__device__ T tap(const T *img, const long long y, const long long x, const long long h, const long long w) {
+    return (y >= 0 && y < h && x >= 0 && x < w) ? img[y * w + x] : (T)0;
+}
+
+extern "C" __global__ void grid_sample(
+    const T *input, const T *grid, T *out,
+    const size_t n, const size_t c, const long long h, const long long w, const size_t p,
+    const int mode, const int padding, const int align
+) {
+    const size_t total = n * c * p;
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < total; i += (size_t)blockDim.x * gridDim.x) {
+        const size_t pt = i % p;
+        const size_t ch = (i / p) % c;
+        const size_t b = i / (p * c);
+        const T *img = input + (b * c + ch) * h * w;
+        const T ix = source_index(grid[(b * p + pt) * 2], w, padding, align);
+        const T iy = source_index(grid[(b * p + pt) * 2 + 1], h, padding, align);
+        if (mode == 1) {
+            out[i] = tap(img, (long long)nearbyint(iy), (long long)nearbyint(ix), h, w);
+        } else {
+            const T x0 = floor(ix), y0 = floor(iy);
+            const T tx = ix - x0, ty = iy - y0;
+            const long long x = (long long)x0, y = (long long)y0;
+            out[i] = tap(img, y, x, h, w) * (1 - tx) * (1 - ty)
+                + tap(img, y, x + 1, h, w) * tx * (1 - ty)
+                + tap(img, y + 1, x, h, w) * (1 - tx) * ty
+                + tap(img, y + 1, x + 1, h, w) * tx * ty;
+        }
+    }
+}
+"#;
+
+impl CudaDevice {
+    /// Samples the NCHW `input` of dimensions `input_dims` at the points of `grid` into
+    /// `out`, like `torch.nn.functional.grid_sample`.
+    ///
+    /// `grid` holds `n * p * 2` elements, the `(x, y)` coordinates of `p` points per image,
+    /// e.g. `p = out_h * out_w` for a grid of the cuDNN spatial transformer. `-1` and `1`
+    /// are the first and last pixels along each axis, see
+    /// [GridSampleConfig::align_corners]. `out` holds `n * c * p` elements, the `c`
+    /// channels of each image at each of its points.
+    ///
+    /// Unlike `cudnn::SpatialTfSamplerForward`, it supports nearest sampling, and
+    /// border and reflection padding. The kernel for `T` is compiled with nvrtc on first use
+    /// into a module named `cudarc_grid_sample_<T>`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the lengths don't match the
+    /// dimensions.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let input = dev.htod_sync_copy(&[0.0f32, 1.0, 2.0, 3.0]).unwrap();
+    /// // the center, and a point past the right border
+    /// let grid = dev.htod_sync_copy(&[0.0f32, 0.0, 3.0, -1.0]).unwrap();
+    /// let mut out = dev.alloc_zeros::<f32>(2).unwrap();
+    /// let cfg = GridSampleConfig {
+    ///     padding: GridPadding::Border,
+    ///     ..Default::default()
+    /// };
+    /// dev.grid_sample(&input, [1, 1, 2, 2], &grid, &mut out, cfg).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [1.5, 1.0]);
+    /// ```
+    pub fn grid_sample<T: ApplyDtype, X: DevicePtr<T>, G: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        input: &X,
+        input_dims: [usize; 4],
+        grid: &G,
+        out: &mut Out,
+        cfg: GridSampleConfig,
+    ) -> Result<(), result::DriverError> {
+        let [n, c, h, w] = input_dims;
+        let p = grid.len().checked_div(2 * n).unwrap_or(0);
+        let input_len = [c, h, w].iter().try_fold(n, |len, &d| len.checked_mul(d));
+        let out_len = n.checked_mul(c).and_then(|nc| nc.checked_mul(p));
+        if input_len != Some(input.len()) || grid.len() != 2 * n * p || out_len != Some(out.len()) {
+            return Err(invalid_value());
+        }
+        if out.len() == 0 {
+            return Ok(());
+        }
+        let f = self.grid_sample_function::<T>()?;
+        let mode = match cfg.mode {
+            GridSampleMode::Bilinear => 0i32,
+            GridSampleMode::Nearest => 1,
+        };
+        let padding = match cfg.padding {
+            GridPadding::Zeros => 0i32,
+            GridPadding::Border => 1,
+            GridPadding::Reflection => 2,
+        };
+        let params = (
+            *input.device_ptr(),
+            *grid.device_ptr(),
+            *out.device_ptr_mut(),
+            n,
+            c,
+            h as i64,
+            w as i64,
+            p,
+            mode,
+            padding,
+            cfg.align_corners as i32,
+        );
+        let cfg = elementwise_cfg(n * c * p);
+        unsafe { f.launch(self.default_stream(), cfg, params) }
+    }
+
+    fn grid_sample_function<T: ApplyDtype>(
+        self: &Arc<Self>,
+    ) -> Result<CudaFunction, result::DriverError> {
+        let module = std::format!("cudarc_grid_sample_{}", T::C_TYPE);
+        self.load_builtin(
+            &module,
+            || std::format!("typedef {} T;\n{GRID_SAMPLE_SRC}", T::C_TYPE),
+            &["grid_sample"],
+            "grid_sample",
+        )
+    }
+}
+
+/// The `CU_FUNC_ATTRIBUTE_SHARED_SIZE_BYTES` of `f`, e.g. the `skipped` counter of
+/// `histogram_f32`.
+fn static_shared_bytes(f: &CudaFunction) -> Result<usize, result::DriverError> {
+    let bytes = unsafe {
+        result::function::get_function_attribute(
+            f.cu_function,
+            sys::CUfunction_attribute::CU_FUNC_ATTRIBUTE_SHARED_SIZE_BYTES,
+        )
+    }?;
+    Ok(bytes as usize)
+}
+
+/// The number of bins, whether they fit in shared memory next to the `static_smem` bytes
+/// of the kernel, and the launch config of the histogram kernels.
+fn histogram_cfg(
+    n: usize,
+    num_bins: usize,
+    static_smem: usize,
+) -> Result<(u32, bool, LaunchConfig), result::DriverError> {
+    if num_bins == 0 || num_bins > u32::MAX as usize {
+        return Err(result::DriverError::Cuda(
+            sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+        ));
+    }
+    let private_bins = num_bins * 4 + static_smem <= PRIVATE_BINS_BYTES;
+    // few blocks, so that the shared bins are merged into `out` rarely
+    let mut cfg = LaunchConfig::for_num_elems(n.min(1 << 18) as u32);
+    if private_bins {
+        cfg.shared_mem_bytes = num_bins as u32 * 4;
+    }
+    Ok((num_bins as u32, private_bins, cfg))
+}
+
+/// The number of `row_len` rows in `len` elements.
+fn whole_rows(len: usize, row_len: usize) -> Result<usize, result::DriverError> {
+    if row_len > 0 && len.is_multiple_of(row_len) {
+        Ok(len / row_len)
+    } else {
+        Err(invalid_value())
+    }
+}
+
+pub(crate) fn check_len(a: usize, b: usize) -> Result<(), result::DriverError> {
+    if a == b {
+        Ok(())
+    } else {
+        Err(invalid_value())
+    }
+}
+
+/// The error of the builtin ops for arguments they can't run with.
+pub(crate) fn invalid_value() -> result::DriverError {
+    result::DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+}
+
+/// The kernels loop over the elements, so the grid doesn't need to cover all of them.
+pub(crate) fn elementwise_cfg(n: usize) -> LaunchConfig {
+    LaunchConfig::for_num_elems(n.min(1 << 24) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_kernel_names() {
+        assert_eq!(UnaryOp::Exp.kernel_name(), "unary_exp");
+        assert_eq!(UnaryOp::PowScalar(2.0).kernel_name(), "unary_pow_scalar");
+        assert_eq!(UnaryOp::PowScalar(2.0).scalar(), 2.0);
+        assert_eq!(UnaryOp::Relu.scalar(), 0.0);
+        assert_eq!(BinaryOp::Max.kernel_name(), "binary_max");
+        let src = kernels_src("float");
+        assert!(src.starts_with("typedef float T;"));
+        for name in KERNEL_NAMES {
+            assert!(src.contains(&std::format!("void {name}(")), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_apply_unary() {
+        let dev = CudaDevice::new(0).unwrap();
+        let host = [-2.0f32, -0.5, 0.0, 1.0, 4.0];
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let mut out = dev.alloc_zeros::<f32>(5).unwrap();
+
+        dev.apply_unary(UnaryOp::Relu, &x, &mut out).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [0.0, 0.0, 0.0, 1.0, 4.0]);
+
+        dev.apply_unary(UnaryOp::Exp, &x, &mut out).unwrap();
+        let expected: Vec<f32> = host.iter().map(|v| v.exp()).collect();
+        for (a, b) in dev.dtoh_sync_copy(&out).unwrap().iter().zip(expected) {
+            assert!((a - b).abs() <= 1e-5 * b, "{a} != {b}");
+        }
+
+        dev.apply_unary(
+            UnaryOp::MulScalar(3.0),
+            &x.slice(1..3),
+            &mut out.slice_mut(..2),
+        )
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap()[..2], [-1.5, 0.0]);
+
+        let mut short = dev.alloc_zeros::<f32>(4).unwrap();
+        assert!(dev.apply_unary(UnaryOp::Abs, &x, &mut short).is_err());
+    }
+
+    #[test]
+    fn test_apply_in_place() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut x = dev.htod_sync_copy(&[1.0f64, 4.0, 9.0]).unwrap();
+        dev.apply_unary_in_place(UnaryOp::Sqrt, &mut x).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), [1.0, 2.0, 3.0]);
+
+        let y = dev.htod_sync_copy(&[2.0f64, 2.0, 0.5]).unwrap();
+        dev.apply_binary_in_place(BinaryOp::Pow, &mut x, &y)
+            .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), [1.0, 4.0, 3f64.sqrt()]);
+
+        let mut out = dev.alloc_zeros::<f64>(3).unwrap();
+        dev.apply_binary(BinaryOp::Max, &x, &y, &mut out).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [2.0, 4.0, 3f64.sqrt()]);
+
+        let short = dev.alloc_zeros::<f64>(2).unwrap();
+        assert!(dev
+            .apply_binary(BinaryOp::Add, &x, &short, &mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn test_rows_src() {
+        let src = rows_src();
+        for name in ROW_KERNEL_NAMES {
+            assert!(src.contains(&std::format!("void {name}(")), "{name}");
+        }
+        assert_eq!(whole_rows(6, 3), Ok(2));
+        assert!(whole_rows(6, 4).is_err());
+        assert!(whole_rows(6, 0).is_err());
+    }
+
+    #[test]
+    fn test_gather_scatter_rows() {
+        let dev = CudaDevice::new(0).unwrap();
+        let host: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        let src = dev.htod_sync_copy(&host).unwrap();
+        // out of order, with duplicates
+        let indices = dev.htod_sync_copy(&[3u32, 1, 1, 0, 3]).unwrap();
+        let mut out = dev.alloc_zeros::<f32>(15).unwrap();
+        dev.gather_rows(&src, &indices, 3, &mut out).unwrap();
+        assert_eq!(
+            dev.dtoh_sync_copy(&out).unwrap(),
+            [9., 10., 11., 3., 4., 5., 3., 4., 5., 0., 1., 2., 9., 10., 11.]
+        );
+
+        // the duplicated rows are equal, so the result is the same whichever is written
+        let mut back = dev.alloc_zeros::<f32>(12).unwrap();
+        dev.scatter_rows(&out, &indices, 3, &mut back).unwrap();
+        let mut expected = host.clone();
+        expected[6..9].fill(0.0);
+        assert_eq!(dev.dtoh_sync_copy(&back).unwrap(), expected);
+
+        let mut short = dev.alloc_zeros::<f32>(14).unwrap();
+        assert!(dev.gather_rows(&src, &indices, 3, &mut short).is_err());
+        assert!(dev.gather_rows(&src, &indices, 5, &mut out).is_err());
+    }
+
+    #[cfg(feature = "f16")]
+    #[test]
+    fn test_gather_rows_half() {
+        let dev = CudaDevice::new(0).unwrap();
+        let indices = dev.htod_sync_copy(&[1u32, 0, 1]).unwrap();
+
+        let host: Vec<half::f16> = (0..4).map(|i| half::f16::from_f32(i as f32)).collect();
+        let src = dev.htod_sync_copy(&host).unwrap();
+        let mut out = dev.alloc_zeros::<half::f16>(6).unwrap();
+        dev.gather_rows(&src, &indices, 2, &mut out).unwrap();
+        let out = dev.dtoh_sync_copy(&out).unwrap();
+        assert_eq!(out, [host[2], host[3], host[0], host[1], host[2], host[3]]);
+
+        let host: Vec<half::bf16> = (0..4).map(|i| half::bf16::from_f32(i as f32)).collect();
+        let src = dev.htod_sync_copy(&host).unwrap();
+        let mut out = dev.alloc_zeros::<half::bf16>(6).unwrap();
+        dev.gather_rows(&src, &indices, 2, &mut out).unwrap();
+        let out = dev.dtoh_sync_copy(&out).unwrap();
+        assert_eq!(out, [host[2], host[3], host[0], host[1], host[2], host[3]]);
+    }
+
+    #[test]
+    fn test_gather_rows_out_of_bounds() {
+        let dev = CudaDevice::new(0).unwrap();
+        let src = dev.htod_sync_copy(&[1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        let indices = dev.htod_sync_copy(&[1u32, 2]).unwrap();
+        let mut out = dev.alloc_zeros::<f32>(4).unwrap();
+
+        dev.gather_rows(&src, &indices, 2, &mut out).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [3.0, 4.0, 0.0, 0.0]);
+
+        assert_eq!(
+            dev.gather_rows_checked(&src, &indices, 2, &mut out, IndexCheck::Host),
+            Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_INVALID_VALUE
+            ))
+        );
+
+        let flag = dev.alloc_error_flag().unwrap();
+        dev.gather_rows_checked(&src, &indices, 2, &mut out, IndexCheck::Flag(&flag))
+            .unwrap();
+        dev.synchronize().unwrap();
+        let assert = flag.check().unwrap_err();
+        assert_eq!(assert.code, INDEX_OUT_OF_BOUNDS);
+
+        flag.reset();
+        let mut back = dev.alloc_zeros::<f32>(4).unwrap();
+        dev.scatter_rows_checked(&out, &indices, 2, &mut back, IndexCheck::Flag(&flag))
+            .unwrap();
+        dev.synchronize().unwrap();
+        assert_eq!(flag.check().unwrap_err().code, INDEX_OUT_OF_BOUNDS);
+        assert_eq!(dev.dtoh_sync_copy(&back).unwrap(), [0.0, 0.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_histogram_src() {
+        for name in HISTOGRAM_KERNEL_NAMES {
+            assert!(
+                HISTOGRAM_SRC.contains(&std::format!("void {name}(")),
+                "{name}"
+            );
+        }
+        assert!(histogram_cfg(10, 0, 0).is_err());
+        let (_, private_bins, cfg) = histogram_cfg(10, 12288, 0).unwrap();
+        assert!(private_bins);
+        assert_eq!(cfg.shared_mem_bytes, 48 * 1024);
+        let (_, private_bins, cfg) = histogram_cfg(10, 12289, 0).unwrap();
+        assert!(!private_bins);
+        assert_eq!(cfg.shared_mem_bytes, 0);
+        // the static shared memory counts against the same 48 KB
+        let (_, private_bins, cfg) = histogram_cfg(10, 12287, 4).unwrap();
+        assert!(private_bins);
+        assert_eq!(cfg.shared_mem_bytes, 48 * 1024 - 4);
+        let (_, private_bins, _) = histogram_cfg(10, 12288, 4).unwrap();
+        assert!(!private_bins);
+    }
+
+    #[test]
+    fn test_histogram_skewed() {
+        let dev = CudaDevice::new(0).unwrap();
+        // most values in a few bins, so that the atomics contend
+        let host: Vec<u32> = (0..1_000_003u32)
+            .map(|i| {
+                let h = i.wrapping_mul(2_654_435_761);
+                if h % 10 < 8 {
+                    h % 3
+                } else {
+                    h % 20_000
+                }
+            })
+            .collect();
+        let values = dev.htod_sync_copy(&host).unwrap();
+        for num_bins in [1, 7, 256, 12288, 12289, 20_000] {
+            let mut expected = std::vec![0u32; num_bins];
+            for &v in &host {
+                if (v as usize) < num_bins {
+                    expected[v as usize] += 1;
+                }
+            }
+            let mut out = dev.alloc_zeros::<u32>(num_bins).unwrap();
+            for _ in 0..2 {
+                dev.histogram(&values, &mut out).unwrap();
+                assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), expected, "{num_bins}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_histogram_range() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (lo, hi, num_bins) = (-2.0f32, 3.0f32, 10);
+        let mut host: Vec<f32> = (0..300_000u32)
+            .map(|i| {
+                let h = i.wrapping_mul(2_654_435_761) % 1000;
+                // skewed towards the low end, with some values out of range
+                (h * h) as f32 / 150_000.0 - 2.5
+            })
+            .collect();
+        host.extend([lo, hi, f32::NAN, f32::INFINITY, f32::NEG_INFINITY]);
+        let values = dev.htod_sync_copy(&host).unwrap();
+        let mut out = dev.alloc_zeros::<u32>(num_bins).unwrap();
+
+        for out_of_range in [OutOfRange::Ignore, OutOfRange::Clamp] {
+            let mut expected = std::vec![0u32; num_bins];
+            let mut ignored = 0;
+            for &v in &host {
+                let b = if v >= lo && v <= hi {
+                    let b = ((v - lo) / (hi - lo) * num_bins as f32) as usize;
+                    b.min(num_bins - 1)
+                } else if out_of_range == OutOfRange::Clamp && v < lo {
+                    0
+                } else if out_of_range == OutOfRange::Clamp && v > hi {
+                    num_bins - 1
+                } else {
+                    ignored += 1;
+                    continue;
+                };
+                expected[b] += 1;
+            }
+            let count = dev
+                .histogram_range(&values, lo, hi, &mut out, out_of_range)
+                .unwrap();
+            assert_eq!(count, ignored);
+            assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), expected);
+        }
+        assert!(dev
+            .histogram_range(&values, 1.0, 1.0, &mut out, OutOfRange::Clamp)
+            .is_err());
+
+        // the most bins that fit in shared memory without the static `skipped` counter
+        let mut out = dev.alloc_zeros::<u32>(12288).unwrap();
+        let ignored = dev
+            .histogram_range(&values, lo, hi, &mut out, OutOfRange::Ignore)
+            .unwrap();
+        let counted: u32 = dev.dtoh_sync_copy(&out).unwrap().iter().sum();
+        assert_eq!(counted as usize + ignored, host.len());
+    }
+
+    fn reflect(x: f64, twice_low: f64, twice_high: f64) -> f64 {
+        if twice_low == twice_high {
+            return 0.0;
+        }
+        let low = twice_low / 2.0;
+        let span = (twice_high - twice_low) / 2.0;
+        let x = (x - low).abs();
+        let extra = x % span;
+        if (x / span).floor() as i64 % 2 == 0 {
+            extra + low
+        } else {
+            span - extra + low
+        }
+    }
+
+    fn source_index(g: f64, size: usize, cfg: GridSampleConfig) -> f64 {
+        let size = size as f64;
+        let mut x = if cfg.align_corners {
+            (g + 1.0) / 2.0 * (size - 1.0)
+        } else {
+            ((g + 1.0) * size - 1.0) / 2.0
+        };
+        if cfg.padding == GridPadding::Reflection {
+            x = if cfg.align_corners {
+                reflect(x, 0.0, 2.0 * (size - 1.0))
+            } else {
+                reflect(x, -1.0, 2.0 * size - 1.0)
+            };
+        }
+        if cfg.padding != GridPadding::Zeros {
+            x = x.max(0.0).min(size - 1.0);
+        }
+        x
+    }
+
+    /// A reference implementation on the host, for one channel.
+    fn grid_sample_host(
+        img: &[f64],
+        h: usize,
+        w: usize,
+        grid: &[f64],
+        cfg: GridSampleConfig,
+    ) -> Vec<f64> {
+        let tap = |y: f64, x: f64| {
+            if y >= 0.0 && x >= 0.0 && y < h as f64 && x < w as f64 {
+                img[y as usize * w + x as usize]
+            } else {
+                0.0
+            }
+        };
+        grid.chunks(2)
+            .map(|g| {
+                let ix = source_index(g[0], w, cfg);
+                let iy = source_index(g[1], h, cfg);
+                match cfg.mode {
+                    GridSampleMode::Nearest => tap(iy.round_ties_even(), ix.round_ties_even()),
+                    GridSampleMode::Bilinear => {
+                        let (x0, y0) = (ix.floor(), iy.floor());
+                        let (tx, ty) = (ix - x0, iy - y0);
+                        tap(y0, x0) * (1.0 - tx) * (1.0 - ty)
+                            + tap(y0, x0 + 1.0) * tx * (1.0 - ty)
+                            + tap(y0 + 1.0, x0) * (1.0 - tx) * ty
+                            + tap(y0 + 1.0, x0 + 1.0) * tx * ty
+                    }
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_grid_sample_matches_host() {
+        let dev = CudaDevice::new(0).unwrap();
+        let [n, c, h, w] = [2, 3, 4, 5];
+        let (out_h, out_w) = (6, 7);
+        let input: Vec<f64> = (0..n * c * h * w)
+            .map(|i| ((i * 37) % 23) as f64 - 11.0)
+            .collect();
+        // an affine grid per image that zooms out past the borders, with a rotation
+        let thetas = [
+            [1.6, 0.3, 0.1, -0.4, 1.4, -0.2],
+            [-0.9, 0.5, 0.3, 0.2, 2.1, 0.6],
+        ];
+        let mut grid = Vec::new();
+        for theta in thetas {
+            for i in 0..out_h {
+                for j in 0..out_w {
+                    let xt = -1.0 + 2.0 * j as f64 / (out_w - 1) as f64;
+                    let yt = -1.0 + 2.0 * i as f64 / (out_h - 1) as f64;
+                    grid.push(theta[0] * xt + theta[1] * yt + theta[2]);
+                    grid.push(theta[3] * xt + theta[4] * yt + theta[5]);
+                }
+            }
+        }
+        let p = out_h * out_w;
+        let input_dev = dev.htod_sync_copy(&input).unwrap();
+        let grid_dev = dev.htod_sync_copy(&grid).unwrap();
+        let mut out = dev.alloc_zeros::<f64>(n * c * p).unwrap();
+
+        for mode in [GridSampleMode::Bilinear, GridSampleMode::Nearest] {
+            for padding in [
+                GridPadding::Zeros,
+                GridPadding::Border,
+                GridPadding::Reflection,
+            ] {
+                for align_corners in [true, false] {
+                    let cfg = GridSampleConfig {
+                        mode,
+                        padding,
+                        align_corners,
+                    };
+                    dev.grid_sample(&input_dev, [n, c, h, w], &grid_dev, &mut out, cfg)
+                        .unwrap();
+                    let got = dev.dtoh_sync_copy(&out).unwrap();
+                    for b in 0..n {
+                        for ch in 0..c {
+                            let img = &input[(b * c + ch) * h * w..][..h * w];
+                            let expected =
+                                grid_sample_host(img, h, w, &grid[b * p * 2..][..p * 2], cfg);
+                            let got = &got[(b * c + ch) * p..][..p];
+                            for (g, e) in got.iter().zip(expected.iter()) {
+                                assert!((g - e).abs() < 1e-9, "{cfg:?}: {got:?} vs {expected:?}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let invalid = Err(result::DriverError::Cuda(
+            sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+        ));
+        let cfg = GridSampleConfig::default();
+        let dims = [n, c, h, w + 1];
+        assert_eq!(
+            dev.grid_sample(&input_dev, dims, &grid_dev, &mut out, cfg),
+            invalid
+        );
+        let mut short = dev.alloc_zeros::<f64>(n * c * p - 1).unwrap();
+        let dims = [n, c, h, w];
+        assert_eq!(
+            dev.grid_sample(&input_dev, dims, &grid_dev, &mut short, cfg),
+            invalid
+        );
+        let odd = grid_dev.slice(1..);
+        assert_eq!(
+            dev.grid_sample(&input_dev, dims, &odd, &mut out, cfg),
+            invalid
+        );
+    }
+}
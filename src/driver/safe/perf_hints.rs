@@ -0,0 +1,152 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+
+use core::ffi::{c_uint, c_void};
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::vec::Vec;
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// What [CudaDevice::perf_hints()] found so far, while [CudaDevice::set_perf_hints()] was
+/// enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PerfHints {
+    /// The number of async copies from pageable host memory, which the driver performs
+    /// synchronously.
+    pub pageable_copies: u64,
+    /// Where those copies were called from, in the order they were first seen.
+    pub pageable_call_sites: Vec<&'static Location<'static>>,
+}
+
+/// The state behind [CudaDevice::set_perf_hints()].
+#[derive(Debug, Default)]
+pub(crate) struct PerfHintState {
+    enabled: AtomicBool,
+    pageable_copies: AtomicU64,
+    /// Every call site is only warned about once.
+    pageable_call_sites: RwLock<Vec<&'static Location<'static>>>,
+}
+
+impl CudaDevice {
+    /// Reports async copies that can't overlap with other work, e.g. [CudaDevice::htod_copy()]
+    /// of a plain [Vec] in a loop. Disabled by default, as every copy then queries the driver
+    /// about its host memory.
+    ///
+    /// The driver copies from pageable memory synchronously, so each such copy is counted in
+    /// [CudaDevice::perf_hints()], and the first one of each call site emits a `tracing`
+    /// warning with target `cudarc::perf` if the `tracing` feature is enabled, and otherwise
+    /// prints to stderr. Copy from a [super::PinnedBuffer] or use
+    /// [CudaDevice::htod_transfer()] instead.
+    pub fn set_perf_hints(&self, enabled: bool) {
+        self.perf_hints.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The hints collected since the device was created, see [CudaDevice::set_perf_hints()].
+    pub fn perf_hints(&self) -> PerfHints {
+        let sites = self.perf_hints.pageable_call_sites.read();
+        #[cfg(not(feature = "no-std"))]
+        let sites = sites.unwrap();
+        PerfHints {
+            pageable_copies: self.perf_hints.pageable_copies.load(Ordering::Relaxed),
+            pageable_call_sites: sites.clone(),
+        }
+    }
+
+    /// Reports `op`, called from `location`, if it copies from pageable `host` memory. The
+    /// context must be bound.
+    #[cfg_attr(
+        not(any(feature = "tracing", feature = "std")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn check_pageable(
+        &self,
+        op: &'static str,
+        host: *const c_void,
+        location: &'static Location<'static>,
+    ) {
+        if !self.perf_hints.enabled.load(Ordering::Relaxed) || !is_pageable(host) {
+            return;
+        }
+        self.perf_hints
+            .pageable_copies
+            .fetch_add(1, Ordering::Relaxed);
+        {
+            #[allow(unused_mut)]
+            let mut sites = self.perf_hints.pageable_call_sites.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut sites = sites.unwrap();
+            if sites.contains(&location) {
+                return;
+            }
+            sites.push(location);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            target: "cudarc::perf",
+            op,
+            location = %location,
+            ordinal = self.ordinal(),
+            "async copy from pageable host memory runs synchronously"
+        );
+        #[cfg(all(not(feature = "tracing"), feature = "std"))]
+        std::eprintln!(
+            "cudarc: async copy from pageable host memory runs synchronously on device {} in {op} at {location}",
+            self.ordinal()
+        );
+    }
+}
+
+/// Whether the driver doesn't know `host`, i.e. it is neither page-locked nor registered.
+/// Errors count as page-locked, so that no hint is reported for them.
+pub(crate) fn is_pageable(host: *const c_void) -> bool {
+    let mut memory_type: c_uint = 0;
+    let mut attributes = [sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_MEMORY_TYPE];
+    let mut data = [&mut memory_type as *mut c_uint as *mut c_void];
+    let ptr = host as sys::CUdeviceptr;
+    unsafe { result::pointer::get_attributes(ptr, &mut attributes, &mut data) }
+        .is_ok_and(|_| memory_type == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    #[test]
+    fn test_is_pageable() {
+        let dev = CudaDevice::new(0).unwrap();
+        let pinned = dev.alloc_pinned::<f32>(16).unwrap();
+        let pageable = [0.0f32; 16];
+        assert!(!is_pageable(pinned.as_slice().as_ptr() as *const c_void));
+        assert!(is_pageable(pageable.as_ptr() as *const c_void));
+    }
+
+    #[test]
+    fn test_perf_hints_count_pageable_copies() {
+        let dev = CudaDevice::new(0).unwrap();
+        let _ = dev.htod_copy(vec![1.0f32; 16]).unwrap();
+        assert_eq!(dev.perf_hints(), PerfHints::default());
+
+        dev.set_perf_hints(true);
+        for _ in 0..3 {
+            let _ = dev.htod_copy(vec![1.0f32; 16]).unwrap();
+        }
+        let hints = dev.perf_hints();
+        assert_eq!(hints.pageable_copies, 3);
+        assert_eq!(hints.pageable_call_sites.len(), 1);
+        assert_eq!(hints.pageable_call_sites[0].file(), file!());
+
+        // page-locked in place, so not counted
+        let _ = dev
+            .htod_transfer(vec![1.0f32; 4096])
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(dev.perf_hints().pageable_copies, 3);
+    }
+}
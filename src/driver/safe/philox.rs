@@ -0,0 +1,376 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction};
+use super::device_ptr::{DevicePtr, DevicePtrMut};
+use super::ops::{check_len, elementwise_cfg, ApplyDtype};
+use super::{LaunchAsync, LaunchConfig};
+
+use std::{string::String, sync::Arc};
+
+/// The position in a Philox4x32-10 stream, used by [CudaDevice::uniform_from_state()] and
+/// [CudaDevice::dropout()].
+///
+/// Element `i` of an op gets the 32-bit value number `offset + i` of the stream of `seed`
+/// and `subsequence`. Its number only depends on the state and `i`, not on the device,
+/// the launch config or what was generated before, so the same state always gives the
+/// same bits. Call [PhiloxState::advance()] between ops to get fresh numbers.
+///
+/// [PhiloxState::uniform_at()] computes the same numbers on the host.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhiloxState {
+    /// The key of the generator.
+    pub seed: u64,
+    /// Selects one of 2^64 independent streams for the seed, e.g. one per rank.
+    pub subsequence: u64,
+    /// The number of 32-bit values already used from the stream.
+    pub offset: u64,
+}
+
+impl PhiloxState {
+    pub fn new(seed: u64, subsequence: u64, offset: u64) -> Self {
+        Self {
+            seed,
+            subsequence,
+            offset,
+        }
+    }
+
+    /// Moves past `n` values, e.g. the elements of the last op.
+    pub fn advance(&mut self, n: u64) {
+        self.offset = self.offset.wrapping_add(n);
+    }
+
+    /// The 32-bit value of element `i`.
+    pub fn u32_at(&self, i: u64) -> u32 {
+        let v = self.offset.wrapping_add(i);
+        let block = v / 4;
+        let ctr = [
+            block as u32,
+            (block >> 32) as u32,
+            self.subsequence as u32,
+            (self.subsequence >> 32) as u32,
+        ];
+        philox4x32_10(ctr, [self.seed as u32, (self.seed >> 32) as u32])[(v % 4) as usize]
+    }
+
+    /// The uniform number in `[0, 1)` of element `i`, as generated by
+    /// [CudaDevice::uniform_from_state()].
+    pub fn uniform_at(&self, i: u64) -> f32 {
+        (self.u32_at(i) >> 8) as f32 * UNIFORM_SCALE
+    }
+}
+
+/// Maps the upper 24 bits of a value to `[0, 1)`, which is exact in an `f32`.
+const UNIFORM_SCALE: f32 = 1.0 / (1 << 24) as f32;
+
+/// The Philox4x32 generator with 10 rounds, as in Random123 and curand.
+fn philox4x32_10(mut ctr: [u32; 4], mut key: [u32; 2]) -> [u32; 4] {
+    for round in 0..10 {
+        if round > 0 {
+            key[0] = key[0].wrapping_add(0x9E3779B9);
+            key[1] = key[1].wrapping_add(0xBB67AE85);
+        }
+        let p0 = 0xD2511F53u64 * ctr[0] as u64;
+        let p1 = 0xCD9E8D57u64 * ctr[2] as u64;
+        ctr = [
+            (p1 >> 32) as u32 ^ ctr[1] ^ key[0],
+            p1 as u32,
+            (p0 >> 32) as u32 ^ ctr[3] ^ key[1],
+            p0 as u32,
+        ];
+    }
+    ctr
+}
+
+const PHILOX_SRC: &str = "
+__device__ __forceinline__ unsigned int philox_u32(
+    unsigned long long seed, unsigned long long sub, unsigned long long v
+) {
+    unsigned long long block = v / 4;
+    unsigned int c0 = (unsigned int)block, c1 = (unsigned int)(block >> 32);
+    unsigned int c2 = (unsigned int)sub, c3 = (unsigned int)(sub >> 32);
+    unsigned int k0 = (unsigned int)seed, k1 = (unsigned int)(seed >> 32);
+    #pragma unroll
+    for (int round = 0; round < 10; round++) {
+        if (round > 0) {
+            k0 += 0x9E3779B9u;
+            k1 += 0xBB67AE85u;
+        }
+        unsigned int hi0 = __umulhi(0xD2511F53u, c0), lo0 = 0xD2511F53u * c0;
+        unsigned int hi1 = __umulhi(0xCD9E8D57u, c2), lo1 = 0xCD9E8D57u * c2;
+        c0 = hi1 ^ c1 ^ k0;
+        c1 = lo1;
+        c2 = hi0 ^ c3 ^ k1;
+        c3 = lo0;
+    }
+    switch (v % 4) {
+        case 0: return c0;
+        case 1: return c1;
+        case 2: return c2;
+        default: return c3;
+    }
+}
+
+__device__ __forceinline__ float philox_uniform(
+    unsigned long long seed, unsigned long long sub, unsigned long long v
+) {
+    return (float)(philox_u32(seed, sub, v) >> 8) * 5.9604644775390625e-8f;
+}
+
+extern \"C\" __global__ void philox_uniform_f32(
+    float *out, size_t n, unsigned long long seed, unsigned long long sub, unsigned long long offset
+) {
+    for (size_t i = blockIdx.x * (size_t)blockDim.x + threadIdx.x; i < n; i += (size_t)gridDim.x * blockDim.x) {
+        out[i] = philox_uniform(seed, sub, offset + i);
+    }
+}
+";
+
+const DROPOUT_TYPES: [&str; 2] = ["float", "double"];
+
+const KERNEL_NAMES: &[&str] = &[
+    "philox_uniform_f32",
+    "philox_dropout_float",
+    "philox_dropout_double",
+];
+
+fn kernels_src() -> String {
+    let mut src = String::from(PHILOX_SRC);
+    for t in DROPOUT_TYPES {
+        src += &std::format!(
+            "
+extern \"C\" __global__ void philox_dropout_{t}(
+    const {t} *x, {t} *out, size_t n, float p, {t} scale,
+    unsigned long long seed, unsigned long long sub, unsigned long long offset
+) {{
+    for (size_t i = blockIdx.x * (size_t)blockDim.x + threadIdx.x; i < n; i += (size_t)gridDim.x * blockDim.x) {{
+        out[i] = philox_uniform(seed, sub, offset + i) < p ? ({t})0 : x[i] * scale;
+    }}
+}}
+"
+        );
+    }
+    src
+}
+
+impl CudaDevice {
+    /// Fills `out` with uniform numbers in `[0, 1)`, where `out[i]` is
+    /// [PhiloxState::uniform_at()]`(i)`. The numbers are the same for the same `state` on
+    /// every device.
+    ///
+    /// The kernels are compiled with nvrtc on first use, and loaded into a module named
+    /// `cudarc_philox`.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let mut state = PhiloxState::new(42, 0, 0);
+    /// let mut out = dev.alloc_zeros::<f32>(1000).unwrap();
+    /// dev.uniform_from_state(&mut out, state).unwrap();
+    /// state.advance(1000);
+    /// assert_eq!(dev.dtoh_sync_copy(&out).unwrap()[7], PhiloxState::new(42, 0, 0).uniform_at(7));
+    /// ```
+    pub fn uniform_from_state<Out: DevicePtrMut<f32>>(
+        self: &Arc<Self>,
+        out: &mut Out,
+        state: PhiloxState,
+    ) -> Result<(), result::DriverError> {
+        let cfg = elementwise_cfg(out.len());
+        unsafe { self.launch_uniform(out, state, cfg) }
+    }
+
+    /// Sets `out[i]` to 0 with probability `p`, and to `x[i] / (1 - p)` otherwise. Element
+    /// `i` is dropped if [PhiloxState::uniform_at()]`(i) < p`, so the mask only depends on
+    /// `state`, see [CudaDevice::uniform_from_state()].
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `p` isn't in `[0, 1]`, or the
+    /// lengths differ.
+    pub fn dropout<T: ApplyDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+        p: f32,
+        state: PhiloxState,
+    ) -> Result<(), result::DriverError> {
+        let cfg = elementwise_cfg(x.len());
+        unsafe { self.launch_dropout(x, out, p, state, cfg) }
+    }
+
+    /// # Safety
+    /// `cfg` must be a one dimensional config.
+    unsafe fn launch_uniform<Out: DevicePtrMut<f32>>(
+        self: &Arc<Self>,
+        out: &mut Out,
+        state: PhiloxState,
+        cfg: LaunchConfig,
+    ) -> Result<(), result::DriverError> {
+        let n = out.len();
+        if n == 0 {
+            return Ok(());
+        }
+        let f = self.philox_function("philox_uniform_f32")?;
+        let params = (
+            *out.device_ptr_mut(),
+            n,
+            state.seed,
+            state.subsequence,
+            state.offset,
+        );
+        f.launch(self.default_stream(), cfg, params)
+    }
+
+    /// # Safety
+    /// `cfg` must be a one dimensional config.
+    unsafe fn launch_dropout<T: ApplyDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+        p: f32,
+        state: PhiloxState,
+        cfg: LaunchConfig,
+    ) -> Result<(), result::DriverError> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_INVALID_VALUE,
+            ));
+        }
+        check_len(x.len(), out.len())?;
+        let n = x.len();
+        if n == 0 {
+            return Ok(());
+        }
+        // every element is dropped if `p` is 1
+        let scale = if p < 1.0 { 1.0 / (1.0 - p as f64) } else { 0.0 };
+        let f = self.philox_function(&std::format!("philox_dropout_{}", T::C_TYPE))?;
+        let params = (
+            *x.device_ptr(),
+            *out.device_ptr_mut(),
+            n,
+            p,
+            T::from_f64(scale),
+            state.seed,
+            state.subsequence,
+            state.offset,
+        );
+        f.launch(self.default_stream(), cfg, params)
+    }
+
+    fn philox_function(self: &Arc<Self>, name: &str) -> Result<CudaFunction, result::DriverError> {
+        self.load_builtin("cudarc_philox", kernels_src, KERNEL_NAMES, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_philox_known_answers() {
+        // from the Random123 known answer tests
+        assert_eq!(
+            philox4x32_10([0; 4], [0; 2]),
+            [0x6627e8d5, 0xe169c58d, 0xbc57ac4c, 0x9b00dbd8]
+        );
+        assert_eq!(
+            philox4x32_10([u32::MAX; 4], [u32::MAX; 2]),
+            [0x408f276d, 0x41c83b0e, 0xa20bc7c6, 0x6d5451fd]
+        );
+        assert_eq!(
+            philox4x32_10(
+                [0x243f6a88, 0x85a308d3, 0x13198a2e, 0x03707344],
+                [0xa4093822, 0x299f31d0]
+            ),
+            [0xd16cfe09, 0x94fdcceb, 0x5001e420, 0x24126ea1]
+        );
+    }
+
+    #[test]
+    fn test_state_offsets() {
+        let state = PhiloxState::new(7, 3, 0);
+        let mut advanced = state;
+        advanced.advance(5);
+        assert_eq!(advanced.u32_at(0), state.u32_at(5));
+        assert_ne!(
+            PhiloxState::new(7, 4, 0).u32_at(0),
+            state.u32_at(0),
+            "subsequences are independent"
+        );
+        assert!((0..1000).all(|i| (0.0..1.0).contains(&state.uniform_at(i))));
+    }
+
+    fn launch_configs(n: u32) -> [LaunchConfig; 2] {
+        [
+            LaunchConfig::for_num_elems(n),
+            LaunchConfig {
+                grid_dim: (3, 1, 1),
+                block_dim: (64, 1, 1),
+                shared_mem_bytes: 0,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_uniform_independent_of_launch_config() {
+        let dev = CudaDevice::new(0).unwrap();
+        let n = 10_000;
+        let state = PhiloxState::new(0x1234_5678_9abc_def0, 2, 13);
+        let expected: Vec<f32> = (0..n as u64).map(|i| state.uniform_at(i)).collect();
+        for cfg in launch_configs(n) {
+            let mut out = dev.alloc_zeros::<f32>(n as usize).unwrap();
+            unsafe { dev.launch_uniform(&mut out, state, cfg) }.unwrap();
+            let out = dev.dtoh_sync_copy(&out).unwrap();
+            assert!(out
+                .iter()
+                .zip(&expected)
+                .all(|(a, b)| a.to_bits() == b.to_bits()));
+        }
+        // a view starting at element 100 matches the state advanced by 100
+        let mut out = dev.alloc_zeros::<f32>(n as usize).unwrap();
+        let mut advanced = state;
+        advanced.advance(100);
+        dev.uniform_from_state(&mut out.slice_mut(100..), advanced)
+            .unwrap();
+        assert_eq!(
+            &dev.dtoh_sync_copy(&out).unwrap()[100..],
+            &expected[..n as usize - 100]
+        );
+    }
+
+    #[test]
+    fn test_dropout_independent_of_launch_config() {
+        let dev = CudaDevice::new(0).unwrap();
+        let n = 10_000;
+        let p = 0.3;
+        let state = PhiloxState::new(99, 0, 0);
+        let x_host: Vec<f32> = (0..n).map(|i| i as f32 - 5000.0).collect();
+        let x = dev.htod_sync_copy(&x_host).unwrap();
+        let scale = (1.0 / (1.0 - p as f64)) as f32;
+        let expected: Vec<f32> = x_host
+            .iter()
+            .enumerate()
+            .map(|(i, x)| match state.uniform_at(i as u64) < p {
+                true => 0.0,
+                false => x * scale,
+            })
+            .collect();
+        for cfg in launch_configs(n) {
+            let mut out = dev.alloc_zeros::<f32>(n as usize).unwrap();
+            unsafe { dev.launch_dropout(&x, &mut out, p, state, cfg) }.unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), expected);
+        }
+        let dropped = expected.iter().filter(|&&v| v == 0.0).count();
+        assert!((2700..3300).contains(&dropped), "{dropped}");
+
+        let mut out = dev.alloc_zeros::<f32>(n as usize).unwrap();
+        dev.dropout(&x, &mut out, 1.0, state).unwrap();
+        assert!(dev.dtoh_sync_copy(&out).unwrap().iter().all(|&v| v == 0.0));
+        assert_eq!(
+            dev.dropout(&x, &mut out, 1.5, state),
+            Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_INVALID_VALUE
+            ))
+        );
+    }
+}
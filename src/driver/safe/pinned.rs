@@ -0,0 +1,75 @@
+use crate::driver::result;
+
+use super::alloc::{DeviceRepr, ValidAsZeroBits};
+use super::core::CudaDevice;
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use std::sync::Arc;
+
+/// Page-locked host memory, allocated with [CudaDevice::alloc_pinned()].
+///
+/// Copies into and out of it are truly asynchronous, unlike copies from pageable memory,
+/// so it can be used with [CudaDevice::htod_copy_on_stream()] and
+/// [CudaDevice::dtoh_copy_on_stream()], including while capturing a graph.
+#[derive(Debug)]
+pub struct PinnedBuffer<T> {
+    ptr: *mut T,
+    len: usize,
+    device: Arc<CudaDevice>,
+}
+
+unsafe impl<T: Send> Send for PinnedBuffer<T> {}
+unsafe impl<T: Sync> Sync for PinnedBuffer<T> {}
+
+impl CudaDevice {
+    /// Allocates `len` zeroed elements of page-locked host memory.
+    pub fn alloc_pinned<T: ValidAsZeroBits + DeviceRepr>(
+        self: &Arc<Self>,
+        len: usize,
+    ) -> Result<PinnedBuffer<T>, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let num_bytes = len * std::mem::size_of::<T>();
+        let ptr = unsafe { result::malloc_host(num_bytes.max(1), 0) }? as *mut T;
+        unsafe { std::ptr::write_bytes(ptr, 0, len) };
+        Ok(PinnedBuffer {
+            ptr,
+            len,
+            device: self.clone(),
+        })
+    }
+}
+
+impl<T> PinnedBuffer<T> {
+    /// Number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the buffer has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The host data.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// The host data.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    /// The device the buffer was allocated with.
+    pub fn device(&self) -> Arc<CudaDevice> {
+        self.device.clone()
+    }
+}
+
+impl<T> Drop for PinnedBuffer<T> {
+    fn drop(&mut self) {
+        let _ctx = self.device.bind_scoped_or_handle("PinnedBuffer::drop");
+        unsafe { result::free_host(self.ptr as *mut _) }.or_handle("PinnedBuffer::drop");
+    }
+}
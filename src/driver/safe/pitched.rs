@@ -0,0 +1,341 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::CudaDevice;
+use super::device_ptr::{DevicePtr, DevicePtrMut, DeviceSlice};
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use core::marker::PhantomData;
+use std::{sync::Arc, vec::Vec};
+
+impl CudaDevice {
+    /// Allocates `height` rows of `width` elements each, with the pitch between rows chosen
+    /// by the driver so that every row is aligned for coalesced access.
+    ///
+    /// Kernels index row `y` at `y * pitch_in_elems() + x`, see
+    /// [PitchedCudaSlice::pitch_in_elems()].
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the size of a row overflows, or if
+    /// the pitch picked by the driver is not a multiple of the size of `T`.
+    ///
+    /// # Safety
+    /// This is unsafe because the memory is unset.
+    pub unsafe fn alloc_pitched<T: DeviceRepr>(
+        self: &Arc<Self>,
+        width: usize,
+        height: usize,
+    ) -> Result<PitchedCudaSlice<T>, DriverError> {
+        let elem_size = std::mem::size_of::<T>();
+        let access_size = [16, 8, 4]
+            .into_iter()
+            .find(|&n| elem_size.is_multiple_of(n))
+            .unwrap_or(4);
+        let width_bytes = row_bytes::<T>(width)?;
+        let _ctx = self.bind_scoped()?;
+        let (cu_device_ptr, pitch) = result::malloc_pitch(width_bytes, height, access_size as u32)?;
+        let slice = PitchedCudaSlice {
+            cu_device_ptr,
+            pitch,
+            width,
+            height,
+            device: self.clone(),
+            marker: PhantomData,
+        };
+        if elem_size == 0 || !pitch.is_multiple_of(elem_size) {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        }
+        Ok(slice)
+    }
+
+    /// Allocates a [PitchedCudaSlice] with every element set to zero.
+    pub fn alloc_zeros_pitched<T: DeviceRepr + super::ValidAsZeroBits>(
+        self: &Arc<Self>,
+        width: usize,
+        height: usize,
+    ) -> Result<PitchedCudaSlice<T>, DriverError> {
+        let mut dst = unsafe { self.alloc_pitched(width, height) }?;
+        self.memset_zeros(&mut dst)?;
+        Ok(dst)
+    }
+
+    /// Sets the `width * height` elements of `dst` to `value`, leaving the padding at the end
+    /// of each row untouched. Uses `cuMemsetD2D8/16/32` depending on the size of `T`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the size of `T` is not 1, 2 or 4.
+    pub fn memset_2d<T: DeviceRepr + Copy>(
+        self: &Arc<Self>,
+        dst: &mut PitchedCudaSlice<T>,
+        value: T,
+    ) -> Result<(), DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let (ptr, pitch, width, height) = (dst.cu_device_ptr, dst.pitch, dst.width, dst.height);
+        unsafe {
+            match std::mem::size_of::<T>() {
+                1 => {
+                    let value = core::mem::transmute_copy::<T, u8>(&value);
+                    result::memset_d2d8_async(ptr, pitch, value, width, height, self.stream)
+                }
+                2 => {
+                    let value = core::mem::transmute_copy::<T, u16>(&value);
+                    result::memset_d2d16_async(ptr, pitch, value, width, height, self.stream)
+                }
+                4 => {
+                    let value = core::mem::transmute_copy::<T, u32>(&value);
+                    result::memset_d2d32_async(ptr, pitch, value, width, height, self.stream)
+                }
+                _ => Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)),
+            }
+        }
+    }
+
+    /// Copies the rows of `src`, `dst.width()` elements each, into `dst`.
+    ///
+    /// # Panics
+    ///
+    /// If `src.len()` is not `dst.width() * dst.height()`.
+    pub fn htod_sync_copy_2d<T: DeviceRepr>(
+        self: &Arc<Self>,
+        src: &[T],
+        dst: &mut PitchedCudaSlice<T>,
+    ) -> Result<(), DriverError> {
+        assert_eq!(src.len(), dst.width * dst.height);
+        let width_bytes = row_bytes::<T>(dst.width)?;
+        let copy = Memcpy2D::new(width_bytes, dst.height)
+            .src_host(src.as_ptr() as *const _, width_bytes)
+            .dst_device(dst.cu_device_ptr, dst.pitch);
+        let _ctx = self.bind_scoped()?;
+        unsafe { result::memcpy_2d_async(&copy.0, self.stream) }?;
+        self.synchronize()
+    }
+
+    /// Copies the `width * height` elements of `src` into a [Vec], without the row padding.
+    pub fn dtoh_sync_copy_2d<T: DeviceRepr>(
+        self: &Arc<Self>,
+        src: &PitchedCudaSlice<T>,
+    ) -> Result<Vec<T>, DriverError> {
+        let len = src.width * src.height;
+        let mut dst = Vec::with_capacity(len);
+        let width_bytes = row_bytes::<T>(src.width)?;
+        let copy = Memcpy2D::new(width_bytes, src.height)
+            .src_device(src.cu_device_ptr, src.pitch)
+            .dst_host(dst.as_mut_ptr() as *mut _, width_bytes);
+        let _ctx = self.bind_scoped()?;
+        unsafe { result::memcpy_2d_async(&copy.0, self.stream) }?;
+        self.synchronize()?;
+        unsafe { dst.set_len(len) };
+        Ok(dst)
+    }
+}
+
+/// The number of bytes in a row of `width` elements, [sys::CUresult::CUDA_ERROR_INVALID_VALUE]
+/// if it overflows.
+fn row_bytes<T>(width: usize) -> Result<usize, DriverError> {
+    width
+        .checked_mul(std::mem::size_of::<T>())
+        .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))
+}
+
+/// Builds the [sys::CUDA_MEMCPY2D] of a copy of `height` rows of `width_bytes` each.
+struct Memcpy2D(sys::CUDA_MEMCPY2D);
+
+impl Memcpy2D {
+    fn new(width_bytes: usize, height: usize) -> Self {
+        Self(sys::CUDA_MEMCPY2D {
+            WidthInBytes: width_bytes,
+            Height: height,
+            ..Default::default()
+        })
+    }
+
+    fn src_host(mut self, ptr: *const std::ffi::c_void, pitch: usize) -> Self {
+        self.0.srcMemoryType = sys::CUmemorytype::CU_MEMORYTYPE_HOST;
+        self.0.srcHost = ptr;
+        self.0.srcPitch = pitch;
+        self
+    }
+
+    fn src_device(mut self, ptr: sys::CUdeviceptr, pitch: usize) -> Self {
+        self.0.srcMemoryType = sys::CUmemorytype::CU_MEMORYTYPE_DEVICE;
+        self.0.srcDevice = ptr;
+        self.0.srcPitch = pitch;
+        self
+    }
+
+    fn dst_host(mut self, ptr: *mut std::ffi::c_void, pitch: usize) -> Self {
+        self.0.dstMemoryType = sys::CUmemorytype::CU_MEMORYTYPE_HOST;
+        self.0.dstHost = ptr;
+        self.0.dstPitch = pitch;
+        self
+    }
+
+    fn dst_device(mut self, ptr: sys::CUdeviceptr, pitch: usize) -> Self {
+        self.0.dstMemoryType = sys::CUmemorytype::CU_MEMORYTYPE_DEVICE;
+        self.0.dstDevice = ptr;
+        self.0.dstPitch = pitch;
+        self
+    }
+}
+
+/// `height` rows of `width` elements each, allocated with [CudaDevice::alloc_pitched()].
+///
+/// Rows are [PitchedCudaSlice::pitch()] bytes apart, which may be more than `width` elements.
+/// As a [DevicePtr] it covers the padding too, i.e. [DeviceSlice::len()] is
+/// `pitch_in_elems() * height()`.
+#[derive(Debug)]
+pub struct PitchedCudaSlice<T> {
+    pub(crate) cu_device_ptr: sys::CUdeviceptr,
+    pub(crate) pitch: usize,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) device: Arc<CudaDevice>,
+    marker: PhantomData<*const T>,
+}
+
+unsafe impl<T: Send> Send for PitchedCudaSlice<T> {}
+unsafe impl<T: Sync> Sync for PitchedCudaSlice<T> {}
+
+impl<T> PitchedCudaSlice<T> {
+    /// Get a clone of the underlying [CudaDevice].
+    pub fn device(&self) -> Arc<CudaDevice> {
+        self.device.clone()
+    }
+
+    /// The number of bytes between the start of consecutive rows.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// The number of elements between the start of consecutive rows.
+    pub fn pitch_in_elems(&self) -> usize {
+        self.pitch / std::mem::size_of::<T>()
+    }
+
+    /// The number of elements in each row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl<T> DeviceSlice<T> for PitchedCudaSlice<T> {
+    fn len(&self) -> usize {
+        self.pitch_in_elems() * self.height
+    }
+}
+
+impl<T> DevicePtr<T> for PitchedCudaSlice<T> {
+    fn device_ptr(&self) -> &sys::CUdeviceptr {
+        &self.cu_device_ptr
+    }
+}
+
+impl<T> DevicePtrMut<T> for PitchedCudaSlice<T> {
+    fn device_ptr_mut(&mut self) -> &mut sys::CUdeviceptr {
+        &mut self.cu_device_ptr
+    }
+}
+
+unsafe impl<T: DeviceRepr> DeviceRepr for &PitchedCudaSlice<T> {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.cu_device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.cu_device_ptr)
+    }
+}
+
+unsafe impl<T: DeviceRepr> DeviceRepr for &mut PitchedCudaSlice<T> {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.cu_device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.cu_device_ptr)
+    }
+}
+
+impl<T> Drop for PitchedCudaSlice<T> {
+    fn drop(&mut self) {
+        // pitched allocations don't come from the stream ordered allocator
+        self.device
+            .synchronize()
+            .and_then(|_| unsafe { result::free_sync(self.cu_device_ptr) })
+            .or_handle("PitchedCudaSlice::drop");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_pitched_memset_and_copy() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut a = unsafe { dev.alloc_pitched::<f32>(33, 7) }.unwrap();
+        assert_eq!((a.width(), a.height()), (33, 7));
+        assert!(a.pitch() >= 33 * 4);
+        assert_eq!(a.pitch_in_elems() * 4, a.pitch());
+        assert_eq!(a.len(), a.pitch_in_elems() * 7);
+
+        dev.memset_2d(&mut a, 1.5f32).unwrap();
+        assert_eq!(dev.dtoh_sync_copy_2d(&a).unwrap(), [1.5; 33 * 7]);
+
+        let host: Vec<f32> = (0..33 * 7).map(|i| i as f32).collect();
+        dev.htod_sync_copy_2d(&host, &mut a).unwrap();
+        assert_eq!(dev.dtoh_sync_copy_2d(&a).unwrap(), host);
+
+        let mut h = dev.alloc_zeros_pitched::<u16>(5, 3).unwrap();
+        dev.memset_2d(&mut h, 7u16).unwrap();
+        assert_eq!(dev.dtoh_sync_copy_2d(&h).unwrap(), [7; 15]);
+
+        let mut d = dev.alloc_zeros_pitched::<f64>(4, 4).unwrap();
+        assert_eq!(
+            dev.memset_2d(&mut d, 1.0),
+            Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))
+        );
+        assert_eq!(dev.dtoh_sync_copy_2d(&d).unwrap(), [0.0; 16]);
+
+        assert_eq!(
+            unsafe { dev.alloc_pitched::<f32>(usize::MAX / 2, 1) }.unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+    }
+
+    #[test]
+    fn test_pitched_launch() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void add_row(float *x, size_t pitch, size_t width) {
+                size_t col = threadIdx.x;
+                size_t row = blockIdx.x;
+                if (col < width) { x[row * pitch + col] += (float)row; }
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "pitched", &["add_row"]).unwrap();
+        let f = dev.get_func("pitched", "add_row").unwrap();
+
+        let mut a = dev.alloc_zeros_pitched::<f32>(10, 4).unwrap();
+        let cfg = LaunchConfig {
+            grid_dim: (4, 1, 1),
+            block_dim: (32, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let pitch = a.pitch_in_elems();
+        unsafe { f.launch(dev.default_stream(), cfg, (&mut a, pitch, 10usize)) }.unwrap();
+        let expected: Vec<f32> = (0..40).map(|i| (i / 10) as f32).collect();
+        assert_eq!(dev.dtoh_sync_copy_2d(&a).unwrap(), expected);
+    }
+}
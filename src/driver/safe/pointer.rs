@@ -0,0 +1,244 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::allowlist;
+use super::core::CudaDevice;
+use super::device_ptr::{DevicePtr, DevicePtrMut, DeviceSlice};
+use super::DriverError;
+
+use core::ffi::{c_int, c_uint, c_void};
+use std::{marker::PhantomData, sync::Arc};
+
+/// What the driver knows about a [sys::CUdeviceptr], see [CudaDevice::pointer_attributes()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointerAttributes {
+    /// [sys::CUmemorytype::CU_MEMORYTYPE_HOST] for mapped page-locked host memory,
+    /// [sys::CUmemorytype::CU_MEMORYTYPE_DEVICE] for device and managed memory.
+    pub memory_type: sys::CUmemorytype,
+    /// Ordinal of the device the memory was allocated on, as in [CudaDevice::ordinal()]. `-1`
+    /// for a device that isn't allowed by [super::set_device_allowlist()].
+    pub device_ordinal: i32,
+    /// Whether the memory was allocated with `cuMemAllocManaged`.
+    pub is_managed: bool,
+    /// Start of the allocation containing the pointer.
+    pub range_start: sys::CUdeviceptr,
+    /// Size in bytes of the allocation containing the pointer.
+    pub range_size: usize,
+}
+
+impl CudaDevice {
+    /// Queries the driver about `ptr`, e.g. a pointer received from another library.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `ptr` wasn't allocated through CUDA.
+    pub fn pointer_attributes(
+        &self,
+        ptr: sys::CUdeviceptr,
+    ) -> Result<PointerAttributes, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let mut memory_type: c_uint = 0;
+        let mut device_ordinal: c_int = -2;
+        let mut is_managed: c_uint = 0;
+        let mut attributes = [
+            sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_MEMORY_TYPE,
+            sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_DEVICE_ORDINAL,
+            sys::CUpointer_attribute::CU_POINTER_ATTRIBUTE_IS_MANAGED,
+        ];
+        let mut data = [
+            &mut memory_type as *mut c_uint as *mut c_void,
+            &mut device_ordinal as *mut c_int as *mut c_void,
+            &mut is_managed as *mut c_uint as *mut c_void,
+        ];
+        unsafe { result::pointer::get_attributes(ptr, &mut attributes, &mut data) }?;
+        let memory_type = match memory_type {
+            1 => sys::CUmemorytype::CU_MEMORYTYPE_HOST,
+            2 => sys::CUmemorytype::CU_MEMORYTYPE_DEVICE,
+            3 => sys::CUmemorytype::CU_MEMORYTYPE_ARRAY,
+            4 => sys::CUmemorytype::CU_MEMORYTYPE_UNIFIED,
+            _ => return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)),
+        };
+        // in the same ordinals as `CudaDevice::ordinal()`
+        let device_ordinal = match usize::try_from(device_ordinal) {
+            Ok(physical) => allowlist::to_visible(physical)?.map_or(-1, |ordinal| ordinal as c_int),
+            Err(_) => device_ordinal,
+        };
+        let (range_start, range_size) = unsafe { result::pointer::get_address_range(ptr) }?;
+        Ok(PointerAttributes {
+            memory_type,
+            device_ordinal,
+            is_managed: is_managed != 0,
+            range_start,
+            range_size,
+        })
+    }
+
+    /// Wraps `len` elements at `ptr`, allocated elsewhere (e.g. by another library), in a
+    /// [ForeignCudaSlice] that can be used with this device's copies and launches.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_DEVICE] if `ptr` belongs to another device,
+    /// and [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `ptr` is unknown to the driver or the
+    /// allocation containing it is smaller than `len` elements.
+    ///
+    /// # Safety
+    /// 1. The memory must stay allocated for as long as the [ForeignCudaSlice] is used
+    /// 2. The memory must be valid for type `T`
+    pub unsafe fn upgrade_foreign_ptr<T>(
+        self: &Arc<Self>,
+        ptr: sys::CUdeviceptr,
+        len: usize,
+    ) -> Result<ForeignCudaSlice<T>, DriverError> {
+        let attributes = self.pointer_attributes(ptr)?;
+        if attributes.device_ordinal != self.ordinal() as i32 {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_DEVICE));
+        }
+        let range_end = attributes.range_start + attributes.range_size as sys::CUdeviceptr;
+        let end = len
+            .checked_mul(std::mem::size_of::<T>())
+            .and_then(|num_bytes| ptr.checked_add(num_bytes as sys::CUdeviceptr));
+        match end {
+            Some(end) if end <= range_end => (),
+            _ => return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)),
+        }
+        Ok(ForeignCudaSlice {
+            ptr,
+            len,
+            device: self.clone(),
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Device memory owned by someone else, created with [CudaDevice::upgrade_foreign_ptr()].
+///
+/// Unlike a [super::CudaSlice], the memory is not freed on drop.
+#[derive(Debug)]
+pub struct ForeignCudaSlice<T> {
+    ptr: sys::CUdeviceptr,
+    len: usize,
+    device: Arc<CudaDevice>,
+    marker: PhantomData<*const T>,
+}
+
+unsafe impl<T: Send> Send for ForeignCudaSlice<T> {}
+unsafe impl<T: Sync> Sync for ForeignCudaSlice<T> {}
+
+impl<T> ForeignCudaSlice<T> {
+    /// Get a clone of the underlying [CudaDevice].
+    pub fn device(&self) -> Arc<CudaDevice> {
+        self.device.clone()
+    }
+}
+
+impl<T> DeviceSlice<T> for ForeignCudaSlice<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<T> DevicePtr<T> for ForeignCudaSlice<T> {
+    fn device_ptr(&self) -> &sys::CUdeviceptr {
+        &self.ptr
+    }
+}
+
+impl<T> DevicePtrMut<T> for ForeignCudaSlice<T> {
+    fn device_ptr_mut(&mut self) -> &mut sys::CUdeviceptr {
+        &mut self.ptr
+    }
+}
+
+unsafe impl<T: DeviceRepr> DeviceRepr for &ForeignCudaSlice<T> {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.ptr)
+    }
+}
+
+unsafe impl<T: DeviceRepr> DeviceRepr for &mut ForeignCudaSlice<T> {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_pointer_attributes() {
+        let dev = CudaDevice::new(0).unwrap();
+        let a = dev.alloc_zeros::<f32>(100).unwrap();
+        let attributes = dev.pointer_attributes(*a.device_ptr()).unwrap();
+        assert_eq!(
+            attributes.memory_type,
+            sys::CUmemorytype::CU_MEMORYTYPE_DEVICE
+        );
+        assert_eq!(attributes.device_ordinal, 0);
+        assert!(!attributes.is_managed);
+        assert_eq!(attributes.range_start, *a.device_ptr());
+        assert!(attributes.range_size >= 400);
+
+        let inner = dev.pointer_attributes(*a.device_ptr() + 40).unwrap();
+        assert_eq!(inner.range_start, *a.device_ptr());
+
+        let flag = dev.alloc_error_flag().unwrap();
+        let attributes = dev.pointer_attributes(flag.device_ptr()).unwrap();
+        assert_eq!(
+            attributes.memory_type,
+            sys::CUmemorytype::CU_MEMORYTYPE_HOST
+        );
+
+        assert_eq!(
+            dev.pointer_attributes(0x1000).unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+    }
+
+    #[test]
+    fn test_upgrade_foreign_ptr() {
+        let dev = CudaDevice::new(0).unwrap();
+        let a = dev.htod_sync_copy(&[1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        let ptr = *a.device_ptr();
+
+        let too_long = unsafe { dev.upgrade_foreign_ptr::<f32>(ptr + 8, 4) };
+        assert_eq!(
+            too_long.unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+
+        let mut foreign = unsafe { dev.upgrade_foreign_ptr::<f32>(ptr + 4, 3) }.unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&foreign).unwrap(), [2.0, 3.0, 4.0]);
+
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void negate(float *x, size_t n) {
+                size_t i = blockIdx.x * blockDim.x + threadIdx.x;
+                if (i < n) { x[i] = -x[i]; }
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "foreign", &["negate"]).unwrap();
+        let f = dev.get_func("foreign", "negate").unwrap();
+        unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(3),
+                (&mut foreign, 3usize),
+            )
+        }
+        .unwrap();
+        drop(foreign);
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), [1.0, -2.0, -3.0, -4.0]);
+    }
+}
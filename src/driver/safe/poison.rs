@@ -0,0 +1,198 @@
+use crate::driver::{result, sys};
+
+use super::context::ContextKind;
+use super::core::CudaDevice;
+use super::DriverError;
+
+use core::sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+impl CudaDevice {
+    /// Whether a [DriverError::is_sticky()] error was returned by an operation on this device,
+    /// e.g. by [CudaDevice::synchronize()] after a kernel accessed an illegal address.
+    ///
+    /// The context can't be used after a sticky error, so once the device is poisoned
+    /// every operation fails with [DriverError::ContextPoisoned] without calling into the
+    /// driver. See [CudaDevice::try_recover()].
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Fails with [DriverError::ContextPoisoned] if the device [CudaDevice::is_poisoned()].
+    #[inline]
+    pub(crate) fn check_poisoned(&self) -> Result<(), DriverError> {
+        if !self.is_poisoned() {
+            return Ok(());
+        }
+        let sticky_error = self.sticky_error.read();
+        #[cfg(not(feature = "no-std"))]
+        let sticky_error = sticky_error.unwrap();
+        Err(DriverError::ContextPoisoned {
+            original: sticky_error.unwrap_or(sys::CUresult::CUDA_ERROR_UNKNOWN),
+        })
+    }
+
+    /// Poisons the device if `e` is the first sticky error observed on it.
+    pub(crate) fn poison(&self, e: &DriverError) {
+        if !e.is_sticky() || matches!(e, DriverError::ContextPoisoned { .. }) {
+            return;
+        }
+        #[allow(unused_mut)]
+        let mut sticky_error = self.sticky_error.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut sticky_error = sticky_error.unwrap();
+        sticky_error.get_or_insert(e.code());
+        self.poisoned.store(true, Ordering::Release);
+    }
+
+    /// Resets the device's primary context with `cuDevicePrimaryCtxReset` if it
+    /// [CudaDevice::is_poisoned()], so that it can be used again. Does nothing if it isn't.
+    ///
+    /// Everything the context held is destroyed: the modules loaded into the device, its
+    /// staging buffers, deferred frees and scratch memory are forgotten, the allocator of
+    /// [CudaDevice::set_allocator()] is removed and its stream is recreated. Returns
+    /// [sys::CUresult::CUDA_ERROR_CONTEXT_ALREADY_IN_USE] if anything else still holds the
+    /// [Arc] of this device, e.g. a [super::CudaSlice], [super::CudaStream],
+    /// [super::WorkToken], [super::CudaFunction] or a [super::CudaKernel] that cached its
+    /// function on the device, and [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if the device
+    /// doesn't run in the primary context, see [CudaDevice::context_kind()].
+    ///
+    /// # Safety
+    /// This is highly unsafe. The primary context is shared by everything in the process
+    /// using the same device ordinal: other [CudaDevice]s created for it, libraries like
+    /// cuBLAS or the CUDA runtime, and raw handles taken from any of them. The caller must
+    /// guarantee none of them exist, they are all invalidated by the reset.
+    pub unsafe fn try_recover(self: &mut Arc<Self>) -> Result<(), DriverError> {
+        if !self.is_poisoned() {
+            return Ok(());
+        }
+        if self.ctx_kind != ContextKind::Primary {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED));
+        }
+        let device = Arc::get_mut(self).ok_or(DriverError::Cuda(
+            sys::CUresult::CUDA_ERROR_CONTEXT_ALREADY_IN_USE,
+        ))?;
+
+        // freed by the reset, destroying them afterwards would fail
+        let modules = RwLock::get_mut(&mut device.modules);
+        #[cfg(not(feature = "no-std"))]
+        let modules = modules.unwrap();
+        modules.clear();
+        let replaced_modules = RwLock::get_mut(&mut device.replaced_modules);
+        #[cfg(not(feature = "no-std"))]
+        let replaced_modules = replaced_modules.unwrap();
+        replaced_modules.clear();
+        let pinned_slots = RwLock::get_mut(&mut device.pinned_slots);
+        #[cfg(not(feature = "no-std"))]
+        let pinned_slots = pinned_slots.unwrap();
+        core::mem::forget(core::mem::take(pinned_slots));
+        let event_pool = RwLock::get_mut(&mut device.event_pool);
+        #[cfg(not(feature = "no-std"))]
+        let event_pool = event_pool.unwrap();
+        event_pool.clear();
+        device.staging_pool.forget();
+        device.deferred_frees.forget();
+        device.scratch.forget();
+        // dropped while the context is still poisoned, so that if this is the last reference
+        // to it, the allocator's frees fail instead of freeing what the new context allocates
+        // at the same addresses
+        let allocator = RwLock::get_mut(&mut device.allocator);
+        #[cfg(not(feature = "no-std"))]
+        let allocator = allocator.unwrap();
+        allocator.take();
+
+        result::primary_ctx::reset(device.cu_device)?;
+        result::ctx::set_current(device.cu_ctx)?;
+        device.event = result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?;
+        if !device.stream.is_null() {
+            device.stream = result::stream::create(result::stream::StreamKind::NonBlocking)?;
+        }
+
+        let sticky_error = RwLock::get_mut(&mut device.sticky_error);
+        #[cfg(not(feature = "no-std"))]
+        let sticky_error = sticky_error.unwrap();
+        *sticky_error = None;
+        device.poisoned.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_is_sticky() {
+        assert!(DriverError::Cuda(sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS).is_sticky());
+        assert!(DriverError::Cuda(sys::CUresult::CUDA_ERROR_ECC_UNCORRECTABLE).is_sticky());
+        assert!(DriverError::Cuda(sys::CUresult::CUDA_ERROR_LAUNCH_TIMEOUT).is_sticky());
+        assert!(DriverError::ContextPoisoned {
+            original: sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS
+        }
+        .is_sticky());
+        assert!(!DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY).is_sticky());
+        assert!(!DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE).is_sticky());
+        assert_eq!(
+            DriverError::ContextPoisoned {
+                original: sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS
+            }
+            .code(),
+            sys::CUresult::CUDA_ERROR_ILLEGAL_ADDRESS
+        );
+    }
+
+    #[test]
+    #[ignore = "faults and resets the primary context, must be executed by itself"]
+    fn test_poisoned_after_illegal_address() {
+        let mut dev = CudaDevice::new(0).unwrap();
+        // fill the staging pool and the scratch arena, which the reset frees
+        let host: std::vec::Vec<f32> = (0..1 << 20).map(|i| i as f32).collect();
+        let a = dev.htod_sync_copy(&host).unwrap();
+        drop(a);
+        drop(dev.scratch(1 << 20).unwrap());
+        let ptx =
+            compile_ptx("extern \"C\" __global__ void fault(float *x) { x[0] = 1.0f; }").unwrap();
+        dev.load_ptx(ptx, "poison", &["fault"]).unwrap();
+        let f = dev.get_func("poison", "fault").unwrap();
+        unsafe {
+            f.clone().launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(1),
+                (8usize,),
+            )
+        }
+        .unwrap();
+
+        let err = dev.synchronize().unwrap_err();
+        assert!(err.is_sticky());
+        assert!(dev.is_poisoned());
+        let poisoned = DriverError::ContextPoisoned {
+            original: err.code(),
+        };
+        assert_eq!(dev.alloc_zeros::<f32>(4).unwrap_err(), poisoned);
+        assert_eq!(dev.synchronize(), Err(poisoned));
+
+        assert_eq!(
+            unsafe { dev.try_recover() },
+            Err(DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_CONTEXT_ALREADY_IN_USE
+            ))
+        );
+        drop(f);
+        unsafe { dev.try_recover() }.unwrap();
+        assert!(!dev.is_poisoned());
+        let a = dev.htod_sync_copy(&[1.0f32, 2.0]).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), [1.0, 2.0]);
+        let a = dev.htod_sync_copy(&host).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), host);
+        assert_eq!(dev.scratch_arena().stats().bytes_reserved, 0);
+        drop(dev.scratch(1 << 20).unwrap());
+    }
+}
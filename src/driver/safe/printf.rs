@@ -0,0 +1,155 @@
+use crate::driver::{result, sys};
+
+use super::core::CudaDevice;
+use super::DriverError;
+
+impl CudaDevice {
+    /// Blocks until all work on the device has completed, which also flushes the
+    /// output of `printf` calls in kernels to stdout.
+    ///
+    /// Kernel `printf` output is buffered on the device (see [CudaDevice::set_printf_fifo_size()])
+    /// and only written to the process' stdout when the context synchronizes, a blocking
+    /// copy completes, or a module is loaded or unloaded. Until one of those happens,
+    /// the output will not show up, and when it does it is not ordered with output written
+    /// from rust. Use [PrintfCapture] to capture it instead.
+    pub fn flush_printf(&self) -> Result<(), DriverError> {
+        let _ctx = self.bind_scoped()?;
+        result::ctx::synchronize()
+    }
+
+    /// Sets the size in bytes of the device buffer that kernel `printf` output is written to
+    /// before being flushed. Output past this size is dropped. The default is 1MB.
+    ///
+    /// Must be called before launching a kernel that uses `printf`.
+    pub fn set_printf_fifo_size(&self, bytes: usize) -> Result<(), DriverError> {
+        let _ctx = self.bind_scoped()?;
+        result::ctx::set_limit(sys::CUlimit::CU_LIMIT_PRINTF_FIFO_SIZE, bytes)
+    }
+
+    /// Returns the size in bytes of the kernel `printf` buffer.
+    pub fn printf_fifo_size(&self) -> Result<usize, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        result::ctx::get_limit(sys::CUlimit::CU_LIMIT_PRINTF_FIFO_SIZE)
+    }
+}
+
+/// Captures kernel `printf` output into a [String], e.g. for assertions in tests.
+///
+/// [PrintfCapture::flush()] redirects the process' stdout file descriptor to a temporary
+/// file while flushing the device, so **anything else written to stdout from any thread
+/// during the flush is captured too**.
+///
+/// ```rust
+/// # use cudarc::{driver::*, nvrtc::*};
+/// let dev = CudaDevice::new(0).unwrap();
+/// let ptx = compile_ptx("extern \"C\" __global__ void hi(int x) { printf(\"hi %d\\n\", x); }").unwrap();
+/// dev.load_ptx(ptx, "m", &["hi"]).unwrap();
+/// let f = dev.get_func("m", "hi").unwrap();
+/// unsafe { f.launch(dev.default_stream(), LaunchConfig::for_num_elems(1), (7i32,)) }.unwrap();
+/// let out = PrintfCapture::flush(&dev).unwrap();
+/// assert_eq!(out, "hi 7\n");
+/// ```
+#[cfg(all(unix, feature = "std"))]
+#[derive(Debug)]
+pub struct PrintfCapture;
+
+#[cfg(all(unix, feature = "std"))]
+impl PrintfCapture {
+    /// Calls [CudaDevice::flush_printf()] with stdout redirected, and returns
+    /// everything that was written.
+    pub fn flush(dev: &CudaDevice) -> std::io::Result<std::string::String> {
+        use core::ffi::{c_int, c_void};
+        use std::io::{Read, Seek, Write};
+        use std::os::fd::AsRawFd;
+
+        extern "C" {
+            fn dup(fd: c_int) -> c_int;
+            fn dup2(src: c_int, dst: c_int) -> c_int;
+            fn close(fd: c_int) -> c_int;
+            fn fflush(stream: *mut c_void) -> c_int;
+        }
+        const STDOUT: c_int = 1;
+
+        // only one capture can own stdout at a time
+        static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let path = std::env::temp_dir().join(std::format!(
+            "cudarc-printf-{}-{:p}",
+            std::process::id(),
+            dev
+        ));
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+
+        std::io::stdout().flush()?;
+        let saved = unsafe { dup(STDOUT) };
+        if saved < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if unsafe { dup2(file.as_raw_fd(), STDOUT) } < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { close(saved) };
+            return Err(err);
+        }
+
+        let flushed = dev.flush_printf();
+        unsafe { fflush(std::ptr::null_mut()) };
+        let restored = unsafe { dup2(saved, STDOUT) };
+        unsafe { close(saved) };
+        if restored < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        flushed.map_err(std::io::Error::other)?;
+
+        let mut out = std::string::String::new();
+        file.rewind()?;
+        file.read_to_string(&mut out)?;
+        Ok(out)
+    }
+}
+
+#[cfg(all(test, unix, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_printf_capture() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.set_printf_fifo_size(1 << 22).unwrap();
+        assert!(dev.printf_fifo_size().unwrap() >= 1 << 22);
+
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void say(int n) { printf(\"thread %d of %d\\n\", threadIdx.x, n); }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "printf", &["say"]).unwrap();
+        let f = dev.get_func("printf", "say").unwrap();
+        let cfg = LaunchConfig {
+            grid_dim: (1, 1, 1),
+            block_dim: (4, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        unsafe { f.launch(dev.default_stream(), cfg, (4i32,)) }.unwrap();
+
+        let out = PrintfCapture::flush(&dev).unwrap();
+        let mut lines: std::vec::Vec<_> = out.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "thread 0 of 4",
+                "thread 1 of 4",
+                "thread 2 of 4",
+                "thread 3 of 4"
+            ]
+        );
+    }
+}
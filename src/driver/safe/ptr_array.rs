@@ -0,0 +1,150 @@
+use crate::driver::sys;
+
+use super::alloc::DeviceRepr;
+use super::core::{CudaDevice, CudaSlice};
+use super::device_ptr::{DevicePtr, DeviceSlice};
+use super::DriverError;
+
+use core::marker::PhantomData;
+use std::{sync::Arc, vec::Vec};
+
+impl CudaDevice {
+    /// Uploads the device pointers of `slices` into an array on the device, for batched APIs
+    /// and kernels that take a `T **`.
+    ///
+    /// The returned [PtrArray] borrows `slices`, so they can't be dropped while the pointers
+    /// to them are still usable.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let a = dev.alloc_zeros::<f32>(10).unwrap();
+    /// let b = dev.alloc_zeros::<f32>(10).unwrap();
+    /// let ptrs = dev.alloc_ptr_array(&[&a, &b]).unwrap();
+    /// assert_eq!(ptrs.len(), 2);
+    /// ```
+    pub fn alloc_ptr_array<'a, T>(
+        self: &Arc<Self>,
+        slices: &[&'a dyn DevicePtr<T>],
+    ) -> Result<PtrArray<'a, T>, DriverError> {
+        let ptrs: Vec<sys::CUdeviceptr> = slices.iter().map(|s| *s.device_ptr()).collect();
+        Ok(PtrArray {
+            ptrs: self.htod_sync_copy(&ptrs)?,
+            marker: PhantomData,
+        })
+    }
+
+    /// Like [CudaDevice::alloc_ptr_array()], but each pointer is to the element at the given
+    /// offset into its slice, e.g. to batch over the tiles of one allocation.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if an offset is not within its slice.
+    pub fn alloc_ptr_array_at<'a, T>(
+        self: &Arc<Self>,
+        slices: &[(&'a dyn DevicePtr<T>, usize)],
+    ) -> Result<PtrArray<'a, T>, DriverError> {
+        let ptrs = slices
+            .iter()
+            .map(|(s, offset)| s.try_at(*offset))
+            .collect::<Option<Vec<sys::CUdeviceptr>>>()
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE))?;
+        Ok(PtrArray {
+            ptrs: self.htod_sync_copy(&ptrs)?,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// An array of device pointers on the device, created with [CudaDevice::alloc_ptr_array()].
+///
+/// Borrows the slices it points into for `'a`. It's passed to kernels as a `T **` through
+/// `&PtrArray`, and to other APIs through [DevicePtr]. Writing through the pointers from a
+/// kernel is only sound if nothing else reads the slices while it runs, which the borrow
+/// doesn't guarantee.
+#[derive(Debug)]
+pub struct PtrArray<'a, T> {
+    pub(crate) ptrs: CudaSlice<sys::CUdeviceptr>,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T> PtrArray<'a, T> {
+    /// The underlying array of pointers.
+    pub fn as_slice(&self) -> &CudaSlice<sys::CUdeviceptr> {
+        &self.ptrs
+    }
+}
+
+impl<'a, T> DeviceSlice<sys::CUdeviceptr> for PtrArray<'a, T> {
+    fn len(&self) -> usize {
+        self.ptrs.len
+    }
+}
+
+impl<'a, T> DevicePtr<sys::CUdeviceptr> for PtrArray<'a, T> {
+    fn device_ptr(&self) -> &sys::CUdeviceptr {
+        &self.ptrs.cu_device_ptr
+    }
+}
+
+unsafe impl<'a, 'b, T> DeviceRepr for &'b PtrArray<'a, T> {
+    #[inline(always)]
+    fn as_kernel_param(&self) -> *mut std::ffi::c_void {
+        (&self.ptrs.cu_device_ptr) as *const sys::CUdeviceptr as *mut std::ffi::c_void
+    }
+
+    #[inline(always)]
+    fn device_ptr_param(&self) -> Option<sys::CUdeviceptr> {
+        Some(self.ptrs.cu_device_ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_ptr_array() {
+        let dev = CudaDevice::new(0).unwrap();
+        let a = dev.htod_sync_copy(&[1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        let b = dev.htod_sync_copy(&[10.0f32, 20.0, 30.0, 40.0]).unwrap();
+        let view = a.slice(2..);
+
+        let ptrs = dev.alloc_ptr_array(&[&a, &b, &view]).unwrap();
+        assert_eq!(ptrs.len(), 3);
+        assert_eq!(
+            dev.dtoh_sync_copy(ptrs.as_slice()).unwrap(),
+            [a.cu_device_ptr, b.cu_device_ptr, a.cu_device_ptr + 8]
+        );
+
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void first(const float * const *xs, float *out, int n) {
+                int i = threadIdx.x;
+                if (i < n) { out[i] = xs[i][0]; }
+            }",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "ptr_array", &["first"]).unwrap();
+        let f = dev.get_func("ptr_array", "first").unwrap();
+        let mut out = dev.alloc_zeros::<f32>(3).unwrap();
+        unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(3),
+                (&ptrs, &mut out, 3i32),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [1.0, 10.0, 3.0]);
+
+        let at = dev.alloc_ptr_array_at(&[(&a, 1), (&b, 3)]).unwrap();
+        assert_eq!(
+            dev.dtoh_sync_copy(at.as_slice()).unwrap(),
+            [a.cu_device_ptr + 4, b.cu_device_ptr + 12]
+        );
+        assert_eq!(
+            dev.alloc_ptr_array_at(&[(&a, 0), (&b, 4)]).unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+    }
+}
@@ -1,12 +1,25 @@
 use crate::{
-    driver::result,
-    nvrtc::{Ptx, PtxKind},
+    driver::{result, sys},
+    nvrtc::{compile_ptx, CompileError, Ptx, PtxKind},
 };
 
-use super::core::{CudaDevice, CudaModule};
+use super::core::{CudaDevice, CudaFunction, CudaModule};
 
 use std::ffi::CString;
-use std::{collections::BTreeMap, sync::Arc};
+use std::panic::Location;
+use std::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+/// The name and resource usage of a kernel in a loaded module, see [CudaDevice::module_functions()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// Number of registers used by each thread.
+    pub num_regs: i32,
+    /// The maximum block size the kernel can be launched with on this device.
+    pub max_threads_per_block: i32,
+    /// Statically allocated shared memory per block, in bytes.
+    pub shared_size_bytes: usize,
+}
 
 impl CudaDevice {
     /// Dynamically load a set of [crate::driver::CudaFunction] from a jit compiled ptx.
@@ -14,44 +27,496 @@ impl CudaDevice {
     /// - `ptx` contains the compilex ptx
     /// - `module_name` is a unique identifier used to access the module later on with [CudaDevice::get_func()]
     /// - `func_names` is a slice of function names to load into the module during build.
+    #[track_caller]
     pub fn load_ptx(
         self: &Arc<Self>,
         ptx: Ptx,
         module_name: &str,
         func_names: &[&'static str],
     ) -> Result<(), result::DriverError> {
-        self.bind_to_thread()?;
-
-        let cu_module = match ptx.0 {
-            PtxKind::Image(image) => unsafe {
-                result::module::load_data(image.as_ptr() as *const _)
-            },
-            PtxKind::Src(src) => {
-                let c_src = CString::new(src).unwrap();
-                unsafe { result::module::load_data(c_src.as_ptr() as *const _) }
-            }
-            PtxKind::File(path) => {
-                let name_c = CString::new(path.to_str().unwrap()).unwrap();
-                result::module::load(name_c)
-            }
-        }?;
+        let _ctx = self.bind_scoped()?;
+        let ptx = ptx.read()?;
+        let src = ptx_source(&ptx);
+        let cu_module = load_module(ptx)?;
+        self.implicit_sync("load_ptx", Location::caller());
+        self.register_module(module_name, cu_module, func_names, src.as_deref())
+    }
+
+    /// Like [CudaDevice::load_ptx()], but loads every kernel in `ptx`, so the names
+    /// don't need to be known up front. Use [CudaDevice::module_functions()] to list them.
+    ///
+    /// Kernels are enumerated by the driver on cuda 12.4+, and otherwise found by
+    /// looking for `.entry` directives in the ptx source. Templated kernels are
+    /// registered under their mangled names.
+    #[track_caller]
+    pub fn load_ptx_all(
+        self: &Arc<Self>,
+        ptx: Ptx,
+        module_name: &str,
+    ) -> Result<(), result::DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let ptx = ptx.read()?;
+        let src = ptx_source(&ptx);
+        let cu_module = load_module(ptx)?;
+        self.implicit_sync("load_ptx_all", Location::caller());
+
+        #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+        if result::module::can_enumerate_functions() {
+            let names = unsafe { result::module::enumerate_functions(cu_module) }.and_then(|fs| {
+                fs.into_iter()
+                    .map(|f| unsafe { result::function::get_name(f) })
+                    .collect::<Result<Vec<_>, _>>()
+            });
+            let names = match names {
+                Ok(names) => names,
+                Err(e) => {
+                    unsafe { result::module::unload(cu_module) }?;
+                    return Err(e);
+                }
+            };
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            return self.register_module(module_name, cu_module, &names, src.as_deref());
+        }
+
+        let Some(src) = src else {
+            unsafe { result::module::unload(cu_module) }?;
+            return Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_SUPPORTED,
+            ));
+        };
+        let names = entry_names(&src);
+        self.register_module(module_name, cu_module, &names, Some(&src))
+    }
+
+    /// Lists the kernels loaded into the module `module_name`, sorted by name.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_NOT_FOUND] if there is no such module.
+    pub fn module_functions(
+        &self,
+        module_name: &str,
+    ) -> Result<Vec<FunctionInfo>, result::DriverError> {
+        use sys::CUfunction_attribute_enum::*;
+
+        let module = self.module(module_name).ok_or(result::DriverError::Cuda(
+            sys::CUresult::CUDA_ERROR_NOT_FOUND,
+        ))?;
+        let _ctx = self.bind_scoped()?;
+        module
+            .functions
+            .iter()
+            .map(|(name, &f)| {
+                let attr = |attr| unsafe { result::function::get_function_attribute(f, attr) };
+                Ok(FunctionInfo {
+                    name: name.clone(),
+                    num_regs: attr(CU_FUNC_ATTRIBUTE_NUM_REGS)?,
+                    max_threads_per_block: attr(CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK)?,
+                    shared_size_bytes: attr(CU_FUNC_ATTRIBUTE_SHARED_SIZE_BYTES)? as usize,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the function `name` of the builtin module `module_name`, compiling the source
+    /// returned by `src` and loading `func_names` from it on first use. Used for the kernels
+    /// cudarc ships as source, e.g. the sort and scan kernels.
+    ///
+    /// Fails with [result::DriverError::BuiltinCompile] if nvrtc can't compile the source.
+    pub(crate) fn load_builtin<S: AsRef<str>>(
+        self: &Arc<Self>,
+        module_name: &str,
+        src: impl FnOnce() -> S,
+        func_names: &[&'static str],
+        name: &str,
+    ) -> Result<CudaFunction, result::DriverError> {
+        if !self.has_func(module_name, name) {
+            let ptx = compile_ptx(src()).map_err(|e| {
+                result::DriverError::BuiltinCompile(match e {
+                    CompileError::CreationError(nvrtc)
+                    | CompileError::CompileError { nvrtc, .. }
+                    | CompileError::GetLogError(nvrtc)
+                    | CompileError::GetPtxError(nvrtc)
+                    | CompileError::DestroyError(nvrtc) => nvrtc,
+                })
+            })?;
+            self.load_ptx(ptx, module_name, func_names)?;
+        }
+        self.get_func(module_name, name)
+            .ok_or(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_FOUND,
+            ))
+    }
+
+    /// Registers a loaded module under `module_name`. A module already registered under the
+    /// name is kept alive until the device is dropped, since [crate::driver::CudaFunction]s
+    /// from it may still be in use.
+    ///
+    /// `cu_module` is unloaded if one of `func_names` can't be found in it.
+    pub(crate) fn register_module(
+        &self,
+        module_name: &str,
+        cu_module: sys::CUmodule,
+        func_names: &[&str],
+        src: Option<&str>,
+    ) -> Result<(), result::DriverError> {
+        #[cfg(feature = "launch-validation")]
+        let num_params = src
+            .map(|src| {
+                func_names
+                    .iter()
+                    .filter_map(|&name| count_entry_params(src, name).map(|n| (name.into(), n)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let param_sizes = src
+            .map(|src| {
+                func_names
+                    .iter()
+                    .filter_map(|&name| {
+                        entry_param_sizes(src, name).map(|sizes| (name.into(), sizes.into()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
         let mut functions = BTreeMap::new();
         for &fn_name in func_names.iter() {
             let fn_name_c = CString::new(fn_name).unwrap();
-            let cu_function = unsafe { result::module::get_function(cu_module, fn_name_c) }?;
-            functions.insert(fn_name, cu_function);
+            match unsafe { result::module::get_function(cu_module, fn_name_c) } {
+                Ok(cu_function) => functions.insert(fn_name.into(), cu_function),
+                Err(e) => {
+                    unsafe { result::module::unload(cu_module) }?;
+                    return Err(e);
+                }
+            };
         }
-        let module = CudaModule {
+        let module = Arc::new(CudaModule {
             cu_module,
             functions,
-        };
+            #[cfg(feature = "launch-validation")]
+            num_params,
+            param_sizes,
+        });
         #[allow(unused_mut)]
-        {
+        let replaced = {
             let mut modules = self.modules.write();
             #[cfg(not(feature = "no-std"))]
             let mut modules = modules.unwrap();
-            modules.insert(module_name.into(), module);
+            modules.insert(module_name.into(), module)
+        };
+        if let Some(replaced) = replaced {
+            #[allow(unused_mut)]
+            let mut replaced_modules = self.replaced_modules.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut replaced_modules = replaced_modules.unwrap();
+            replaced_modules.push(replaced);
         }
         Ok(())
     }
 }
+
+pub(crate) fn load_module(ptx: Ptx) -> Result<sys::CUmodule, result::DriverError> {
+    match ptx.0 {
+        PtxKind::Image(image) => unsafe { result::module::load_data(image.as_ptr() as *const _) },
+        PtxKind::Src(src) => {
+            let c_src = CString::new(src).unwrap();
+            unsafe { result::module::load_data(c_src.as_ptr() as *const _) }
+        }
+        PtxKind::File(path) => {
+            let name_c = CString::new(path.to_str().unwrap()).unwrap();
+            result::module::load(name_c)
+        }
+        PtxKind::Compressed(..) => unreachable!("decompressed by Ptx::read()"),
+    }
+}
+
+/// Returns the text of the ptx, if it is available without the driver.
+pub(crate) fn ptx_source(ptx: &Ptx) -> Option<std::string::String> {
+    match &ptx.0 {
+        PtxKind::Image(image) => {
+            let bytes: std::vec::Vec<u8> = image
+                .iter()
+                .take_while(|&&c| c != 0)
+                .map(|&c| c as u8)
+                .collect();
+            std::string::String::from_utf8(bytes).ok()
+        }
+        PtxKind::Src(src) => Some(src.clone()),
+        #[cfg(not(feature = "no-std"))]
+        PtxKind::File(path) => std::fs::read_to_string(path).ok(),
+        #[cfg(feature = "no-std")]
+        PtxKind::File(_) => None,
+        PtxKind::Compressed(..) => None,
+    }
+}
+
+/// Counts the `.param`s declared by the `.entry` named `name` in `ptx`.
+#[cfg(feature = "launch-validation")]
+pub(crate) fn count_entry_params(ptx: &str, name: &str) -> Option<usize> {
+    entry_params(ptx, name).map(|args| args.matches(".param").count())
+}
+
+/// The size in bytes of each `.param` declared by the `.entry` named `name` in `ptx`,
+/// e.g. 4 for `.param .u32 n` and 16 for `.param .align 8 .b8 pair[16]`.
+pub(crate) fn entry_param_sizes(ptx: &str, name: &str) -> Option<Vec<usize>> {
+    entry_params(ptx, name)?
+        .split(',')
+        .filter(|decl| decl.contains(".param"))
+        .map(param_size)
+        .collect()
+}
+
+fn param_size(decl: &str) -> Option<usize> {
+    let mut width = None;
+    let mut count = 1;
+    for token in decl.split_whitespace() {
+        if let Some(ty) = token.strip_prefix('.') {
+            // `.b8`, `.u32`, `.f64` etc, other directives like `.align` or `.ptr` don't parse
+            if ty.starts_with(['b', 's', 'u', 'f']) {
+                if let Ok(bits) = ty[1..].parse::<usize>() {
+                    width = Some(bits / 8);
+                }
+            }
+        } else if let Some((_, len)) = token.split_once('[') {
+            count = len.strip_suffix(']')?.parse().ok()?;
+        }
+    }
+    Some(width? * count)
+}
+
+/// The text between the parentheses of the `.entry` named `name` in `ptx`.
+fn entry_params<'a>(ptx: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = ptx;
+    while let Some(i) = rest.find(".entry") {
+        rest = &rest[i + ".entry".len()..];
+        let decl = rest.trim_start();
+        let Some(args) = decl.strip_prefix(name) else {
+            continue;
+        };
+        let Some(args) = args.trim_start().strip_prefix('(') else {
+            continue;
+        };
+        return Some(&args[..args.find(')')?]);
+    }
+    None
+}
+
+/// Returns the names of the `.entry` directives in `ptx`, in order.
+fn entry_names(ptx: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = ptx;
+    while let Some(i) = rest.find(".entry") {
+        rest = &rest[i + ".entry".len()..];
+        if !rest.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let decl = rest.trim_start();
+        let len = decl
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '%')))
+            .unwrap_or(decl.len());
+        if len > 0 {
+            names.push(&decl[..len]);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+
+    #[test]
+    fn test_entry_names() {
+        let ptx = "
+.visible .entry sin_kernel_half()
+{
+}
+// .entry_not_a_kernel
+.weak .entry _Z5scaleIfEvPT_S0_(
+    .param .u64 _Z5scaleIfEvPT_S0__param_0,
+    .param .f32 _Z5scaleIfEvPT_S0__param_1
+)
+{
+}
+.visible .entry sin_kernel(.param .u64 sin_kernel_param_0)
+{
+}";
+        assert_eq!(
+            entry_names(ptx),
+            ["sin_kernel_half", "_Z5scaleIfEvPT_S0_", "sin_kernel"]
+        );
+        assert!(entry_names(".func helper()").is_empty());
+    }
+
+    const KERNELS: &str = "
+template <typename T>
+__global__ void scale(T *x, const T a) { x[threadIdx.x] *= a; }
+template __global__ void scale<float>(float *, const float);
+template __global__ void scale<double>(double *, const double);
+
+extern \"C\" __global__ void fill(int *x, const int v) {
+    __shared__ int tile[64];
+    tile[threadIdx.x] = v;
+    __syncthreads();
+    x[threadIdx.x] = tile[63 - threadIdx.x];
+}
+
+namespace ops {
+__global__ void negate(float *x) { x[threadIdx.x] = -x[threadIdx.x]; }
+}";
+
+    #[test]
+    fn test_entry_names_nvrtc() {
+        let ptx = crate::nvrtc::compile_ptx(KERNELS)
+            .unwrap()
+            .to_src()
+            .unwrap();
+        let mut names = entry_names(&ptx);
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "_Z5scaleIdEvPT_S0_",
+                "_Z5scaleIfEvPT_S0_",
+                "_ZN3ops6negateEPf",
+                "fill"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_ptx_all() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = crate::nvrtc::compile_ptx(KERNELS).unwrap();
+        dev.load_ptx_all(ptx, "all").unwrap();
+        assert!(dev.has_func("all", "fill"));
+        assert!(dev.has_func("all", "_Z5scaleIfEvPT_S0_"));
+
+        let infos = dev.module_functions("all").unwrap();
+        let names: Vec<&str> = infos.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "_Z5scaleIdEvPT_S0_",
+                "_Z5scaleIfEvPT_S0_",
+                "_ZN3ops6negateEPf",
+                "fill"
+            ]
+        );
+        let fill = infos.iter().find(|f| f.name == "fill").unwrap();
+        assert_eq!(fill.shared_size_bytes, 64 * 4);
+        assert!(fill.num_regs > 0);
+        assert!(fill.max_threads_per_block > 0);
+
+        assert!(matches!(
+            dev.module_functions("missing"),
+            Err(result::DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_FOUND
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_load_ptx_missing_function() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = crate::nvrtc::compile_ptx(KERNELS).unwrap();
+        assert!(dev.load_ptx(ptx, "partial", &["fill", "missing"]).is_err());
+        assert!(!dev.has_func("partial", "fill"));
+    }
+
+    #[test]
+    fn test_concurrent_load_ptx() {
+        const FUNCS: [&str; 8] = [
+            "fill_0", "fill_1", "fill_2", "fill_3", "fill_4", "fill_5", "fill_6", "fill_7",
+        ];
+        let dev = CudaDevice::new(0).unwrap();
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let dev = dev.clone();
+                std::thread::spawn(move || {
+                    let src = std::format!(
+                        "extern \"C\" __global__ void fill_{i}(int *x) {{ x[threadIdx.x] = {i}; }}"
+                    );
+                    let ptx = crate::nvrtc::compile_ptx(src).unwrap();
+                    let module = std::format!("m{i}");
+                    dev.load_ptx(ptx, &module, &[FUNCS[i]]).unwrap();
+                    let f = dev.get_func(&module, FUNCS[i]).unwrap();
+                    let mut buf = dev.alloc_zeros::<i32>(32).unwrap();
+                    unsafe {
+                        f.launch(
+                            dev.default_stream(),
+                            LaunchConfig::for_num_elems(32),
+                            (&mut buf,),
+                        )
+                    }
+                    .unwrap();
+                    dev.dtoh_sync_copy(&buf).unwrap()
+                })
+            })
+            .collect();
+        for (i, t) in threads.into_iter().enumerate() {
+            assert_eq!(t.join().unwrap(), [i as i32; 32]);
+        }
+    }
+
+    #[test]
+    fn test_reload_keeps_old_functions() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = crate::nvrtc::compile_ptx(KERNELS).unwrap();
+        dev.load_ptx(ptx.clone(), "reload", &["fill"]).unwrap();
+        let old = dev.get_func("reload", "fill").unwrap();
+        dev.load_ptx(ptx, "reload", &["fill"]).unwrap();
+        let mut buf = dev.alloc_zeros::<i32>(64).unwrap();
+        unsafe {
+            old.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(64),
+                (&mut buf, 3),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&buf).unwrap(), [3; 64]);
+    }
+
+    #[cfg(feature = "launch-validation")]
+    #[test]
+    fn test_count_entry_params() {
+        let ptx = "
+.visible .entry sin_kernel_half()
+{
+}
+.visible .entry sin_kernel(
+    .param .u64 sin_kernel_param_0,
+    .param .u64 sin_kernel_param_1,
+    .param .u64 sin_kernel_param_2
+)
+{
+}";
+        assert_eq!(count_entry_params(ptx, "sin_kernel"), Some(3));
+        assert_eq!(count_entry_params(ptx, "sin_kernel_half"), Some(0));
+        assert_eq!(count_entry_params(ptx, "cos_kernel"), None);
+    }
+
+    #[test]
+    fn test_entry_param_sizes() {
+        let ptx = "
+.visible .entry empty()
+{
+}
+.visible .entry mixed(
+    .param .u32 mixed_param_0,
+    .param .u64 .ptr .global .align 4 mixed_param_1,
+    .param .align 8 .b8 mixed_param_2[24],
+    .param .f16 mixed_param_3,
+    .param .s8 mixed_param_4
+)
+{
+}";
+        assert_eq!(entry_param_sizes(ptx, "empty"), Some(Vec::new()));
+        assert_eq!(
+            entry_param_sizes(ptx, "mixed"),
+            Some(std::vec![4, 8, 24, 2, 1])
+        );
+        assert_eq!(entry_param_sizes(ptx, "missing"), None);
+    }
+}
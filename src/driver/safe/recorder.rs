@@ -0,0 +1,613 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::{CudaDevice, CudaFunction, CudaSlice};
+use super::launch::LaunchConfig;
+use super::DriverError;
+
+use core::ffi::c_void;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::string::String;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{format, vec, vec::Vec};
+
+/// Captures the inputs of kernel launches into a directory, so that a kernel that
+/// intermittently produces wrong results can be reproduced offline with
+/// [LaunchRecorder::replay()]. Attach it with [CudaDevice::set_launch_recorder()].
+///
+/// Before each selected launch the params passed by value are copied, and the contents of
+/// [CudaSlice]s, [super::CudaView]s and [super::CudaViewMut]s passed to it are copied to the
+/// host, after synchronizing the launching stream. Each launch is written to its own
+/// `<seq>-<function>.launch` file, see [CapturedLaunch::write_to()].
+///
+/// Only functions from [CudaDevice::get_func()] can be selected, and only the base
+/// [LaunchConfig] of a [super::LaunchConfigEx] is captured. Other params that refer to
+/// device memory, e.g. a [super::PtrArray], are captured as the pointer they pass.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// # use std::sync::Arc;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let dir = std::env::temp_dir().join("cudarc-recorder-doc");
+/// let recorder = LaunchRecorder::new(&dir)
+///     .unwrap()
+///     .with_filter("softmax_*")
+///     .with_max_bytes(1 << 20);
+/// dev.set_launch_recorder(Some(Arc::new(recorder)));
+/// ```
+#[derive(Debug)]
+pub struct LaunchRecorder {
+    dir: PathBuf,
+    filters: Vec<String>,
+    max_bytes: usize,
+    next_seq: AtomicU64,
+    /// The first error writing a launch to `dir`.
+    error: Mutex<Option<io::Error>>,
+}
+
+/// A kernel param captured by a [LaunchRecorder].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedArg {
+    /// The bytes of a param passed by value.
+    Scalar(Vec<u8>),
+    /// A device buffer of `num_bytes`. `contents` is what it held before the launch, or
+    /// `None` if it didn't fit within [LaunchRecorder::with_max_bytes()].
+    Buffer {
+        num_bytes: usize,
+        contents: Option<Vec<u8>>,
+    },
+}
+
+/// A launch captured by a [LaunchRecorder].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedLaunch {
+    /// The number of launches captured before this one by the same recorder.
+    pub seq: u64,
+    /// The names the function was loaded with, see [CudaDevice::get_func()].
+    pub module: String,
+    pub function: String,
+    pub grid_dim: (u32, u32, u32),
+    pub block_dim: (u32, u32, u32),
+    pub shared_mem_bytes: u32,
+    /// Whether it was launched with [super::LaunchAsync::launch_cooperative()].
+    pub cooperative: bool,
+    pub args: Vec<RecordedArg>,
+}
+
+/// A kernel param as seen by [LaunchRecorder], made by the [super::LaunchAsync] impls.
+pub(crate) struct RecordParam {
+    ptr: *const u8,
+    size: usize,
+    buffer: Option<(sys::CUdeviceptr, usize)>,
+}
+
+impl RecordParam {
+    #[inline(always)]
+    pub(crate) fn new<T: DeviceRepr>(param: &T) -> Self {
+        Self {
+            ptr: param.as_kernel_param() as *const u8,
+            size: core::mem::size_of::<T>(),
+            buffer: param.buffer_param(),
+        }
+    }
+}
+
+impl LaunchRecorder {
+    /// The default of [LaunchRecorder::with_max_bytes()].
+    pub const DEFAULT_MAX_BYTES: usize = 64 << 20;
+
+    /// Records every launch into `dir`, creating it if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            filters: Vec::new(),
+            max_bytes: Self::DEFAULT_MAX_BYTES,
+            next_seq: AtomicU64::new(0),
+            error: Mutex::new(None),
+        })
+    }
+
+    /// Only records functions whose name matches `glob`, where `*` matches any number of
+    /// characters and `?` any one character. With several filters a function is recorded
+    /// if it matches any of them.
+    pub fn with_filter(mut self, glob: &str) -> Self {
+        self.filters.push(glob.into());
+        self
+    }
+
+    /// The most bytes of device buffers copied per launch, buffers past it are recorded
+    /// without their contents. Defaults to [LaunchRecorder::DEFAULT_MAX_BYTES].
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// The directory launches are written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether launches of `function` are recorded.
+    pub fn is_selected(&self, function: &str) -> bool {
+        self.filters.is_empty()
+            || self
+                .filters
+                .iter()
+                .any(|glob| glob_match(glob.as_bytes(), function.as_bytes()))
+    }
+
+    /// Takes the first error from writing a launch, since the last call. Launches are not
+    /// failed because they couldn't be written.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.error.lock().unwrap().take()
+    }
+
+    /// Reads all launches in [LaunchRecorder::dir()], in the order they were recorded.
+    pub fn records(&self) -> io::Result<Vec<CapturedLaunch>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "launch") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.iter().map(CapturedLaunch::load).collect()
+    }
+
+    /// Launches `launch.function` again on `device` with the captured params, uploading the
+    /// captured contents into newly allocated buffers. Buffers recorded without contents are
+    /// zeroed. Returns the contents of each buffer after the launch, `None` for the params
+    /// passed by value, for comparing with the outputs of the original launch.
+    ///
+    /// The module the function was in must be loaded into `device` under the same name,
+    /// otherwise [sys::CUresult::CUDA_ERROR_NOT_FOUND] is returned.
+    ///
+    /// # Safety
+    /// The same as [super::LaunchAsync::launch()]. The params of `launch` must match the
+    /// function's, e.g. because it was captured from the same kernel source.
+    pub unsafe fn replay(
+        device: &Arc<CudaDevice>,
+        launch: &CapturedLaunch,
+    ) -> Result<Vec<Option<Vec<u8>>>, DriverError> {
+        let f = device
+            .get_func(&launch.module, &launch.function)
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_FOUND))?;
+        let mut buffers: Vec<Option<CudaSlice<u8>>> = Vec::with_capacity(launch.args.len());
+        for arg in &launch.args {
+            buffers.push(match arg {
+                RecordedArg::Scalar(_) => None,
+                RecordedArg::Buffer {
+                    num_bytes,
+                    contents,
+                } => {
+                    let mut buf = device.alloc_zeros::<u8>((*num_bytes).max(1))?;
+                    if let Some(contents) = contents {
+                        device
+                            .htod_sync_copy_into(contents, &mut buf.slice_mut(..contents.len()))?;
+                    }
+                    Some(buf)
+                }
+            });
+        }
+
+        let mut ptrs: Vec<sys::CUdeviceptr> = buffers
+            .iter()
+            .map(|buf| buf.as_ref().map_or(0, |buf| buf.cu_device_ptr))
+            .collect();
+        let mut params: Vec<*mut c_void> = launch
+            .args
+            .iter()
+            .zip(ptrs.iter_mut())
+            .map(|(arg, ptr)| match arg {
+                RecordedArg::Scalar(bytes) => bytes.as_ptr() as *mut c_void,
+                RecordedArg::Buffer { .. } => ptr as *mut sys::CUdeviceptr as *mut c_void,
+            })
+            .collect();
+        let _ctx = device.bind_scoped()?;
+        let launch_fn = if launch.cooperative {
+            result::launch_cooperative_kernel
+        } else {
+            result::launch_kernel
+        };
+        let res = launch_fn(
+            f.cu_function,
+            launch.grid_dim,
+            launch.block_dim,
+            launch.shared_mem_bytes,
+            device.stream,
+            &mut params,
+        );
+        let dims = (launch.grid_dim, launch.block_dim, launch.shared_mem_bytes);
+        device.track_launch(f.cu_function, dims, res)?;
+
+        buffers
+            .iter()
+            .zip(&launch.args)
+            .map(|(buf, arg)| match (buf, arg) {
+                (Some(buf), RecordedArg::Buffer { num_bytes, .. }) => {
+                    device.dtoh_sync_copy(&buf.slice(..*num_bytes)).map(Some)
+                }
+                _ => Ok(None),
+            })
+            .collect()
+    }
+
+    fn save(&self, launch: &CapturedLaunch) {
+        let path = self
+            .dir
+            .join(format!("{:08}-{}.launch", launch.seq, launch.function));
+        let res = fs::File::create(path).and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            launch.write_to(&mut writer)?;
+            writer.flush()
+        });
+        if let Err(e) = res {
+            self.error.lock().unwrap().get_or_insert(e);
+        }
+    }
+}
+
+impl CudaDevice {
+    /// Attaches a [LaunchRecorder] that captures the launches it selects on this device, or
+    /// detaches it with `None`. Launches don't check for a recorder beyond an atomic load
+    /// while none is attached.
+    pub fn set_launch_recorder(&self, recorder: Option<Arc<LaunchRecorder>>) {
+        #[allow(unused_mut)]
+        let mut current = self.launch_recorder.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut current = current.unwrap();
+        self.launch_recording
+            .store(recorder.is_some(), Ordering::Relaxed);
+        *current = recorder;
+    }
+
+    /// The [LaunchRecorder] attached with [CudaDevice::set_launch_recorder()].
+    pub fn launch_recorder(&self) -> Option<Arc<LaunchRecorder>> {
+        let current = self.launch_recorder.read();
+        #[cfg(not(feature = "no-std"))]
+        let current = current.unwrap();
+        current.clone()
+    }
+
+    /// Whether a [LaunchRecorder] is attached.
+    #[inline(always)]
+    pub(crate) fn launch_recording(&self) -> bool {
+        self.launch_recording.load(Ordering::Relaxed)
+    }
+
+    /// The module and function name `cu_function` was loaded with.
+    fn function_name(&self, cu_function: sys::CUfunction) -> Option<(String, String)> {
+        let modules = self.modules.read();
+        #[cfg(not(feature = "no-std"))]
+        let modules = modules.unwrap();
+        modules.iter().find_map(|(module_name, module)| {
+            module
+                .functions
+                .iter()
+                .find(|(_, &f)| f == cu_function)
+                .map(|(name, _)| (module_name.clone(), name.clone()))
+        })
+    }
+}
+
+impl CudaFunction {
+    /// Captures a launch of this function on `stream` with the attached [LaunchRecorder], if
+    /// it selects this function.
+    pub(crate) fn record_launch(
+        &self,
+        stream: sys::CUstream,
+        cfg: &LaunchConfig,
+        cooperative: bool,
+        params: &[RecordParam],
+    ) -> Result<(), DriverError> {
+        let Some(recorder) = self.device.launch_recorder() else {
+            return Ok(());
+        };
+        let Some((module, function)) = self.device.function_name(self.cu_function) else {
+            return Ok(());
+        };
+        if !recorder.is_selected(&function) {
+            return Ok(());
+        }
+
+        let _ctx = self.device.bind_scoped()?;
+        let mut budget = recorder.max_bytes;
+        let mut args = Vec::with_capacity(params.len());
+        for param in params {
+            args.push(match param.buffer {
+                Some((ptr, num_bytes)) if num_bytes <= budget => {
+                    budget -= num_bytes;
+                    let mut contents = vec![0u8; num_bytes];
+                    unsafe { result::memcpy_dtoh_async(&mut contents, ptr, stream) }?;
+                    RecordedArg::Buffer {
+                        num_bytes,
+                        contents: Some(contents),
+                    }
+                }
+                Some((_, num_bytes)) => RecordedArg::Buffer {
+                    num_bytes,
+                    contents: None,
+                },
+                None => RecordedArg::Scalar(
+                    unsafe { core::slice::from_raw_parts(param.ptr, param.size) }.to_vec(),
+                ),
+            });
+        }
+        unsafe { result::stream::synchronize(stream) }?;
+
+        recorder.save(&CapturedLaunch {
+            seq: recorder.next_seq.fetch_add(1, Ordering::Relaxed),
+            module,
+            function,
+            grid_dim: cfg.grid_dim,
+            block_dim: cfg.block_dim,
+            shared_mem_bytes: cfg.shared_mem_bytes,
+            cooperative,
+            args,
+        });
+        Ok(())
+    }
+}
+
+impl CapturedLaunch {
+    const MAGIC: &'static [u8; 8] = b"CUDARCL1";
+
+    /// Serializes the launch. All integers are little endian:
+    /// 1. the 8 bytes `CUDARCL1`
+    /// 2. `seq` as a u64
+    /// 3. `module` and `function`, each as a u64 length followed by utf-8
+    /// 4. `grid_dim`, `block_dim` and `shared_mem_bytes` as 7 u32s
+    /// 5. `cooperative` as a u8
+    /// 6. the number of args as a u64, then for each a u8 tag, a u64 length and the bytes:
+    ///    tag 0 for a [RecordedArg::Scalar], 1 for a [RecordedArg::Buffer] with contents, and
+    ///    2 for one without, whose length is `num_bytes` and which has no bytes following.
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(Self::MAGIC)?;
+        w.write_all(&self.seq.to_le_bytes())?;
+        for name in [&self.module, &self.function] {
+            w.write_all(&(name.len() as u64).to_le_bytes())?;
+            w.write_all(name.as_bytes())?;
+        }
+        let (g, b) = (self.grid_dim, self.block_dim);
+        for x in [g.0, g.1, g.2, b.0, b.1, b.2, self.shared_mem_bytes] {
+            w.write_all(&x.to_le_bytes())?;
+        }
+        w.write_all(&[self.cooperative as u8])?;
+        w.write_all(&(self.args.len() as u64).to_le_bytes())?;
+        for arg in &self.args {
+            let (tag, len, bytes): (u8, usize, &[u8]) = match arg {
+                RecordedArg::Scalar(bytes) => (0, bytes.len(), bytes),
+                RecordedArg::Buffer {
+                    contents: Some(contents),
+                    ..
+                } => (1, contents.len(), contents),
+                RecordedArg::Buffer {
+                    num_bytes,
+                    contents: None,
+                } => (2, *num_bytes, &[]),
+            };
+            w.write_all(&[tag])?;
+            w.write_all(&(len as u64).to_le_bytes())?;
+            w.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a launch written by [CapturedLaunch::write_to()].
+    pub fn read_from(r: &mut impl Read) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != Self::MAGIC {
+            return Err(invalid("not a recorded launch"));
+        }
+        let seq = read_u64(r)?;
+        let mut names = [String::new(), String::new()];
+        for name in names.iter_mut() {
+            let len = read_u64(r)?;
+            *name = String::from_utf8(read_bytes(r, len)?)
+                .map_err(|_| invalid("function name is not utf-8"))?;
+        }
+        let [module, function] = names;
+        let mut dims = [0u32; 7];
+        for x in dims.iter_mut() {
+            let mut buf = [0; 4];
+            r.read_exact(&mut buf)?;
+            *x = u32::from_le_bytes(buf);
+        }
+        let mut cooperative = [0];
+        r.read_exact(&mut cooperative)?;
+        let num_args = read_u64(r)?;
+        let mut args = Vec::new();
+        for _ in 0..num_args {
+            let mut tag = [0];
+            r.read_exact(&mut tag)?;
+            let len = read_u64(r)?;
+            args.push(match tag[0] {
+                0 => RecordedArg::Scalar(read_bytes(r, len)?),
+                1 => RecordedArg::Buffer {
+                    num_bytes: len as usize,
+                    contents: Some(read_bytes(r, len)?),
+                },
+                2 => RecordedArg::Buffer {
+                    num_bytes: len as usize,
+                    contents: None,
+                },
+                _ => return Err(invalid("unknown arg tag")),
+            });
+        }
+        Ok(Self {
+            seq,
+            module,
+            function,
+            grid_dim: (dims[0], dims[1], dims[2]),
+            block_dim: (dims[3], dims[4], dims[5]),
+            shared_mem_bytes: dims[6],
+            cooperative: cooperative[0] != 0,
+            args,
+        })
+    }
+
+    /// Reads a launch from a file written by a [LaunchRecorder].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = io::BufReader::new(fs::File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Reads `len` bytes without trusting `len` for the allocation.
+fn read_bytes(r: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// Matches `name` against `glob`, with `*` and `?` as wildcards.
+fn glob_match(glob: &[u8], name: &[u8]) -> bool {
+    match (glob.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            glob_match(rest, name) || (!name.is_empty() && glob_match(glob, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => glob_match(rest, name_rest),
+        (Some((c, rest)), Some((n, name_rest))) => c == n && glob_match(rest, name_rest),
+        (Some(_), None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::LaunchAsync;
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"softmax_*", b"softmax_f32"));
+        assert!(glob_match(b"*_f?2", b"softmax_f32"));
+        assert!(!glob_match(b"softmax_*", b"log_softmax_f32"));
+        assert!(!glob_match(b"a?", b"a"));
+        assert!(glob_match(b"exact", b"exact"));
+    }
+
+    #[test]
+    fn test_captured_launch_round_trip() {
+        let launch = CapturedLaunch {
+            seq: 3,
+            module: "m".into(),
+            function: "f".into(),
+            grid_dim: (1, 2, 3),
+            block_dim: (4, 5, 6),
+            shared_mem_bytes: 7,
+            cooperative: true,
+            args: vec![
+                RecordedArg::Scalar(vec![1, 2, 3, 4]),
+                RecordedArg::Buffer {
+                    num_bytes: 2,
+                    contents: Some(vec![5, 6]),
+                },
+                RecordedArg::Buffer {
+                    num_bytes: 1000,
+                    contents: None,
+                },
+            ],
+        };
+        let mut bytes = Vec::new();
+        launch.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            CapturedLaunch::read_from(&mut bytes.as_slice()).unwrap(),
+            launch
+        );
+        let truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(
+            CapturedLaunch::read_from(&mut &truncated[..])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx(
+            "extern \"C\" __global__ void axpy(float a, const float *x, float *y, int n) {
+                int i = blockIdx.x * blockDim.x + threadIdx.x;
+                if (i < n) { y[i] += a * x[i]; }
+            }
+            extern \"C\" __global__ void other(int n) {}",
+        )
+        .unwrap();
+        dev.load_ptx(ptx, "recorder", &["axpy", "other"]).unwrap();
+        let dir = std::env::temp_dir().join(format!("cudarc-recorder-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let recorder = Arc::new(
+            LaunchRecorder::new(&dir)
+                .unwrap()
+                .with_filter("ax*")
+                .with_max_bytes(4 * 256 + 4 * 100),
+        );
+        dev.set_launch_recorder(Some(recorder.clone()));
+
+        let n = 256;
+        let x = dev.htod_sync_copy(&vec![2.0f32; n]).unwrap();
+        let mut y = dev.htod_sync_copy(&vec![1.0f32; n]).unwrap();
+        let cfg = LaunchConfig::for_num_elems(n as u32);
+        let axpy = dev.get_func("recorder", "axpy").unwrap();
+        unsafe { axpy.launch(dev.default_stream(), cfg, (3.0f32, &x, &mut y, n as i32)) }.unwrap();
+        let other = dev.get_func("recorder", "other").unwrap();
+        unsafe { other.launch(dev.default_stream(), cfg, (0i32,)) }.unwrap();
+        let outputs = dev.dtoh_sync_copy(&y).unwrap();
+        dev.set_launch_recorder(None);
+        assert!(recorder.take_error().is_none());
+
+        let records = recorder.records().unwrap();
+        assert_eq!(records.len(), 1);
+        let launch = &records[0];
+        assert_eq!(launch.function, "axpy");
+        assert_eq!(
+            launch.args[0],
+            RecordedArg::Scalar(3.0f32.to_le_bytes().to_vec())
+        );
+        // `y` didn't fit in the budget after `x`
+        assert_eq!(
+            launch.args[2],
+            RecordedArg::Buffer {
+                num_bytes: 4 * n,
+                contents: None
+            }
+        );
+
+        // replay with the missing contents of `y` filled in
+        let mut launch = launch.clone();
+        launch.args[2] = RecordedArg::Buffer {
+            num_bytes: 4 * n,
+            contents: Some(1.0f32.to_le_bytes().repeat(n)),
+        };
+        let replayed = unsafe { LaunchRecorder::replay(&dev, &launch) }.unwrap();
+        assert_eq!(replayed[0], None);
+        assert_eq!(
+            replayed[1].as_deref(),
+            Some(&2.0f32.to_le_bytes().repeat(n)[..])
+        );
+        let y_bytes: Vec<u8> = outputs.iter().flat_map(|y| y.to_le_bytes()).collect();
+        assert_eq!(replayed[2], Some(y_bytes));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,738 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction};
+use super::determinism::Determinism;
+use super::device_ptr::{DevicePtr, DevicePtrMut};
+use super::ops::invalid_value;
+use super::{DeviceRepr, LaunchAsync, LaunchConfig};
+
+use std::{string::String, sync::Arc};
+
+/// Element types of [CudaDevice::argmax()], [CudaDevice::top_k()], [CudaDevice::sum()] and
+/// the other reductions.
+///
+/// # Safety
+/// [ReduceDtype::NAME] must be the name of the reduction kernels reading `Self`.
+pub unsafe trait ReduceDtype: DeviceRepr + Copy {
+    /// The suffix of the reduction kernels for the type.
+    const NAME: &'static str;
+}
+
+unsafe impl ReduceDtype for f32 {
+    const NAME: &'static str = "f32";
+}
+
+#[cfg(feature = "f16")]
+unsafe impl ReduceDtype for half::f16 {
+    const NAME: &'static str = "f16";
+}
+
+#[cfg(feature = "f16")]
+unsafe impl ReduceDtype for half::bf16 {
+    const NAME: &'static str = "bf16";
+}
+
+/// The element types, with the C type of their bits and how to widen those to a float.
+/// Half types are converted without `cuda_fp16.h`, so nvrtc needs no include paths.
+const DTYPES: [(&str, &str, &str); 3] = [
+    ("f32", "unsigned int", "return __uint_as_float(b);"),
+    (
+        "f16",
+        "unsigned short",
+        "float f; asm(\"cvt.f32.f16 %0, %1;\" : \"=f\"(f) : \"h\"(b)); return f;",
+    ),
+    (
+        "bf16",
+        "unsigned short",
+        "return __uint_as_float(((unsigned int)b) << 16);",
+    ),
+];
+
+const KERNEL_NAMES: &[&str] = &[
+    "topk_partial_f32",
+    "topk_final_f32",
+    "topk_partial_f16",
+    "topk_final_f16",
+    "topk_partial_bf16",
+    "topk_final_bf16",
+    "sum_f32",
+    "sum_f16",
+    "sum_bf16",
+];
+
+/// Threads of each block of the reduction kernels.
+const BLOCK: u32 = 256;
+
+/// Elements summed by each block of the sum kernels.
+const SUM_TILE: usize = 16 * BLOCK as usize;
+
+/// The source of the reduction kernels. Both passes select the `k` best elements one after
+/// the other, each with a block reduction over the elements that rank after the previous
+/// one: the first pass for chunks of each row, the second over the candidates of all
+/// chunks of a row. The sums add up a tile of a row in each block, with a fixed order
+/// within the block.
+fn reduce_src() -> String {
+    let mut src = std::format!(
+        "#define BLOCK {BLOCK}
+#define SUM_TILE {SUM_TILE}
+#define EMPTY 0xffffffffu
+// NaN ranks first, then larger values, then lower indices. EMPTY marks a missing element.
+__device__ __forceinline__ bool topk_better(float a, unsigned int ia, float b, unsigned int ib) {{
+    if (ib == EMPTY) return ia != EMPTY;
+    if (ia == EMPTY) return false;
+    const bool na = isnan(a), nb = isnan(b);
+    if (na != nb) return na;
+    if (!na && a != b) return a > b;
+    return ia < ib;
+}}
+// Reduces the candidate (v, i) of every thread to the best one, returned to all threads.
+__device__ void topk_block_best(float &v, unsigned int &i) {{
+    __shared__ float sv[BLOCK];
+    __shared__ unsigned int si[BLOCK];
+    sv[threadIdx.x] = v;
+    si[threadIdx.x] = i;
+    __syncthreads();
+    for (unsigned int s = BLOCK / 2; s > 0; s >>= 1) {{
+        if (threadIdx.x < s && topk_better(sv[threadIdx.x + s], si[threadIdx.x + s], sv[threadIdx.x], si[threadIdx.x])) {{
+            sv[threadIdx.x] = sv[threadIdx.x + s];
+            si[threadIdx.x] = si[threadIdx.x + s];
+        }}
+        __syncthreads();
+    }}
+    v = sv[0];
+    i = si[0];
+    __syncthreads();
+}}
+// Sums the value of every thread in a fixed tree, returned to thread 0.
+__device__ float sum_block(float v) {{
+    __shared__ float s[BLOCK];
+    s[threadIdx.x] = v;
+    __syncthreads();
+    for (unsigned int o = BLOCK / 2; o > 0; o >>= 1) {{
+        if (threadIdx.x < o) s[threadIdx.x] += s[threadIdx.x + o];
+        __syncthreads();
+    }}
+    return s[0];
+}}
+"
+    );
+    for (name, word, widen) in DTYPES {
+        src.push_str(&std::format!(
+            "__device__ __forceinline__ float topk_load_{name}(const {word} b) {{ {widen} }}
+// Writes the `k` best elements of chunk `blockIdx.y` of row `blockIdx.x` to the row's candidates.
+extern \"C\" __global__ void topk_partial_{name}(const {word} *x, const size_t cols, const unsigned int k, const int minimize, float *cand_v, unsigned int *cand_i) {{
+    const size_t chunk = (cols + gridDim.y - 1) / gridDim.y;
+    const size_t begin = blockIdx.y * chunk;
+    const size_t end = begin + chunk < cols ? begin + chunk : cols;
+    const {word} *row = x + blockIdx.x * cols;
+    const size_t out = ((size_t)blockIdx.x * gridDim.y + blockIdx.y) * k;
+    float pv = 0.0f;
+    unsigned int pi = EMPTY;
+    for (unsigned int j = 0; j < k; j++) {{
+        float bv = 0.0f;
+        unsigned int bi = EMPTY;
+        for (size_t c = begin + threadIdx.x; c < end; c += BLOCK) {{
+            float v = topk_load_{name}(row[c]);
+            if (minimize) v = -v;
+            if ((j == 0 || topk_better(pv, pi, v, (unsigned int)c)) && topk_better(v, (unsigned int)c, bv, bi)) {{
+                bv = v;
+                bi = (unsigned int)c;
+            }}
+        }}
+        topk_block_best(bv, bi);
+        if (threadIdx.x == 0) {{
+            cand_v[out + j] = bv;
+            cand_i[out + j] = bi;
+        }}
+        pv = bv;
+        pi = bi;
+    }}
+}}
+// Selects the `k` best of the `chunks * k` candidates of row `blockIdx.x`, writing the
+// indices and, unless null, the original elements.
+extern \"C\" __global__ void topk_final_{name}(const {word} *x, const size_t cols, const unsigned int k, const unsigned int chunks, const float *cand_v, const unsigned int *cand_i, unsigned int *out_idx, {word} *out_vals) {{
+    const size_t n = (size_t)chunks * k;
+    const float *vs = cand_v + blockIdx.x * n;
+    const unsigned int *is = cand_i + blockIdx.x * n;
+    float pv = 0.0f;
+    unsigned int pi = EMPTY;
+    for (unsigned int j = 0; j < k; j++) {{
+        float bv = 0.0f;
+        unsigned int bi = EMPTY;
+        for (size_t c = threadIdx.x; c < n; c += BLOCK) {{
+            const float v = vs[c];
+            const unsigned int i = is[c];
+            if ((j == 0 || topk_better(pv, pi, v, i)) && topk_better(v, i, bv, bi)) {{
+                bv = v;
+                bi = i;
+            }}
+        }}
+        topk_block_best(bv, bi);
+        if (threadIdx.x == 0) {{
+            out_idx[blockIdx.x * k + j] = bi;
+            if (out_vals) out_vals[blockIdx.x * k + j] = x[blockIdx.x * cols + bi];
+        }}
+        pv = bv;
+        pi = bi;
+    }}
+}}
+// Block `b` sums tile `b % tiles` of row `b / tiles`, into `out[b]`, or with `atomic` by
+// adding to `out[b / tiles]`.
+extern \"C\" __global__ void sum_{name}(const {word} *x, const size_t cols, const unsigned int tiles, float *out, const int atomic) {{
+    const size_t row = blockIdx.x / tiles;
+    const size_t begin = (size_t)(blockIdx.x % tiles) * SUM_TILE;
+    const {word} *xs = x + row * cols;
+    float v = 0.0f;
+    for (size_t c = begin + threadIdx.x; c < begin + SUM_TILE && c < cols; c += BLOCK) {{
+        v += topk_load_{name}(xs[c]);
+    }}
+    v = sum_block(v);
+    if (threadIdx.x == 0) {{
+        if (atomic) atomicAdd(&out[row], v);
+        else out[blockIdx.x] = v;
+    }}
+}}
+"
+        ));
+    }
+    src
+}
+
+/// The number of chunks the first pass splits each row of `cols` elements into.
+fn num_chunks(rows: usize, cols: usize) -> u32 {
+    // enough blocks to fill the device for a single row, while keeping the second pass short
+    let by_len = cols.div_ceil(16 * BLOCK as usize);
+    let by_rows = (1024 / rows.max(1)).max(1);
+    by_len.min(by_rows).clamp(1, 64) as u32
+}
+
+impl CudaDevice {
+    /// Sets `out_idx[0]` to the index of the largest element of `x`.
+    ///
+    /// Ties resolve to the lowest index, and NaN is larger than any other value, so the
+    /// index of the first NaN is returned if there is one.
+    ///
+    /// Runs on the device's stream, so the index can be read back without synchronizing
+    /// with [CudaDevice::dtoh_scalar_async()] on a stream forked afterwards. The partial
+    /// results are kept in [CudaDevice::scratch_arena()]. The kernels are compiled with
+    /// nvrtc on first use, and loaded into a module named `cudarc_reduce`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `x` is empty or has more than
+    /// [u32::MAX] elements, or `out_idx` doesn't hold 1 element.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let logits = dev.htod_sync_copy(&[0.5f32, 2.0, -1.0, 2.0]).unwrap();
+    /// let mut token = dev.alloc_zeros::<u32>(1).unwrap();
+    /// dev.argmax(&logits, &mut token).unwrap();
+    /// let stream = dev.fork_default_stream().unwrap();
+    /// let pending = dev.dtoh_scalar_async(&token, 0, &stream).unwrap().unwrap();
+    /// assert_eq!(pending.wait().unwrap(), 1);
+    /// ```
+    pub fn argmax<T: ReduceDtype, X: DevicePtr<T>, Idx: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        x: &X,
+        out_idx: &mut Idx,
+    ) -> Result<(), result::DriverError> {
+        self.argmax_rows(x, x.len(), out_idx)
+    }
+
+    /// [CudaDevice::argmax()] of each row of `x`, viewed as `[x.len() / cols, cols]`, into
+    /// the corresponding element of `out_idx`. The indices are within the row.
+    pub fn argmax_rows<T: ReduceDtype, X: DevicePtr<T>, Idx: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        out_idx: &mut Idx,
+    ) -> Result<(), result::DriverError> {
+        let rows = check_rows(x.len(), cols, 1, out_idx.len())?;
+        let (x, out_idx) = (*x.device_ptr(), *out_idx.device_ptr_mut());
+        unsafe { self.launch_top_k::<T>(x, rows, cols, 1, false, out_idx, 0) }
+    }
+
+    /// Sets `out_idx[0]` to the index of the smallest element of `x`. Like
+    /// [CudaDevice::argmax()], ties resolve to the lowest index and NaN is returned first.
+    pub fn argmin<T: ReduceDtype, X: DevicePtr<T>, Idx: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        x: &X,
+        out_idx: &mut Idx,
+    ) -> Result<(), result::DriverError> {
+        self.argmin_rows(x, x.len(), out_idx)
+    }
+
+    /// [CudaDevice::argmin()] of each row of `x`, see [CudaDevice::argmax_rows()].
+    pub fn argmin_rows<T: ReduceDtype, X: DevicePtr<T>, Idx: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        out_idx: &mut Idx,
+    ) -> Result<(), result::DriverError> {
+        let rows = check_rows(x.len(), cols, 1, out_idx.len())?;
+        let (x, out_idx) = (*x.device_ptr(), *out_idx.device_ptr_mut());
+        unsafe { self.launch_top_k::<T>(x, rows, cols, 1, true, out_idx, 0) }
+    }
+
+    /// Sets `out[0]` to the largest element of `x`, see [CudaDevice::argmax()].
+    pub fn max<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.max_rows(x, x.len(), out)
+    }
+
+    /// [CudaDevice::max()] of each row of `x`, see [CudaDevice::argmax_rows()].
+    pub fn max_rows<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.extremum_rows(x, cols, false, out)
+    }
+
+    /// Sets `out[0]` to the smallest element of `x`, see [CudaDevice::argmin()].
+    pub fn min<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.min_rows(x, x.len(), out)
+    }
+
+    /// [CudaDevice::min()] of each row of `x`, see [CudaDevice::argmax_rows()].
+    pub fn min_rows<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.extremum_rows(x, cols, true, out)
+    }
+
+    fn extremum_rows<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        minimize: bool,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        let rows = check_rows(x.len(), cols, 1, out.len())?;
+        let idx = self.scratch(rows * std::mem::size_of::<u32>())?;
+        let (x, out) = (*x.device_ptr(), *out.device_ptr_mut());
+        unsafe { self.launch_top_k::<T>(x, rows, cols, 1, minimize, *idx.device_ptr(), out) }
+    }
+
+    /// Sets `out_idx` to the indices of the `k` largest elements of `x`, from the largest
+    /// down, and `out_vals` to the elements. Elements rank like in [CudaDevice::argmax()],
+    /// so equal elements are in the order of their indices.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `k` is 0 or larger than `x`, or
+    /// `out_vals` or `out_idx` don't hold `k` elements.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let logits = dev.htod_sync_copy(&[0.5f32, 2.0, -1.0, 3.0]).unwrap();
+    /// let mut vals = dev.alloc_zeros::<f32>(2).unwrap();
+    /// let mut idx = dev.alloc_zeros::<u32>(2).unwrap();
+    /// dev.top_k(&logits, 2, &mut vals, &mut idx).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [3, 1]);
+    /// assert_eq!(dev.dtoh_sync_copy(&vals).unwrap(), [3.0, 2.0]);
+    /// ```
+    pub fn top_k<T: ReduceDtype, X: DevicePtr<T>, Vals: DevicePtrMut<T>, Idx: DevicePtrMut<u32>>(
+        self: &Arc<Self>,
+        x: &X,
+        k: usize,
+        out_vals: &mut Vals,
+        out_idx: &mut Idx,
+    ) -> Result<(), result::DriverError> {
+        self.top_k_rows(x, x.len(), k, out_vals, out_idx)
+    }
+
+    /// [CudaDevice::top_k()] of each row of `x`, viewed as `[x.len() / cols, cols]`, into
+    /// `[rows, k]` outputs. The indices are within the row.
+    pub fn top_k_rows<
+        T: ReduceDtype,
+        X: DevicePtr<T>,
+        Vals: DevicePtrMut<T>,
+        Idx: DevicePtrMut<u32>,
+    >(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        k: usize,
+        out_vals: &mut Vals,
+        out_idx: &mut Idx,
+    ) -> Result<(), result::DriverError> {
+        let rows = check_rows(x.len(), cols, k, out_idx.len())?;
+        if out_vals.len() != out_idx.len() {
+            return Err(invalid_value());
+        }
+        let (x, out_idx) = (*x.device_ptr(), *out_idx.device_ptr_mut());
+        let out_vals = *out_vals.device_ptr_mut();
+        unsafe { self.launch_top_k::<T>(x, rows, cols, k, false, out_idx, out_vals) }
+    }
+
+    /// Sets `out[0]` to the sum of `x`, added up in `f32`.
+    ///
+    /// Blocks sum tiles of `x` in a fixed order, and how the sums of the tiles are combined
+    /// depends on [CudaDevice::determinism()]: with [Determinism::Fast] they are added with
+    /// atomics, so the result can change from run to run by rounding, and with
+    /// [Determinism::Bitwise] in a fixed tree, so it only depends on the length of `x`.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `x` is empty or has more than
+    /// [u32::MAX] elements, or `out` doesn't hold 1 element.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// dev.set_determinism(Determinism::Bitwise);
+    /// let x = dev.htod_sync_copy(&[0.5f32, 2.0, -1.0, 3.0]).unwrap();
+    /// let mut out = dev.alloc_zeros::<f32>(1).unwrap();
+    /// dev.sum(&x, &mut out).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [4.5]);
+    /// ```
+    pub fn sum<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<f32>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        self.sum_rows(x, x.len(), out)
+    }
+
+    /// [CudaDevice::sum()] of each row of `x`, see [CudaDevice::argmax_rows()].
+    pub fn sum_rows<T: ReduceDtype, X: DevicePtr<T>, Out: DevicePtrMut<f32>>(
+        self: &Arc<Self>,
+        x: &X,
+        cols: usize,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        let rows = check_rows(x.len(), cols, 1, out.len())?;
+        let (x, out) = (*x.device_ptr(), *out.device_ptr_mut());
+        let mode = self.determinism();
+        unsafe { self.launch_sum::<T>(x, rows, cols, out, mode) }
+    }
+
+    /// # Safety
+    /// `x` must hold `rows * cols` elements, and `out` `rows`.
+    unsafe fn launch_sum<T: ReduceDtype>(
+        self: &Arc<Self>,
+        x: sys::CUdeviceptr,
+        rows: usize,
+        cols: usize,
+        out: sys::CUdeviceptr,
+        mode: Determinism,
+    ) -> Result<(), result::DriverError> {
+        if rows == 0 {
+            return Ok(());
+        }
+        let tiles = cols.div_ceil(SUM_TILE);
+        let blocks = rows
+            .checked_mul(tiles)
+            .filter(|&blocks| blocks <= i32::MAX as usize)
+            .ok_or_else(invalid_value)?;
+        let f = self.reduce_function(&std::format!("sum_{}", T::NAME))?;
+        let cfg = LaunchConfig {
+            grid_dim: (blocks as u32, 1, 1),
+            block_dim: (BLOCK, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        if tiles == 1 {
+            return f.launch(self.default_stream(), cfg, (x, cols, 1u32, out, 0i32));
+        }
+        match mode {
+            Determinism::Fast => {
+                let num_bytes = rows * std::mem::size_of::<f32>();
+                result::memset_d8_async(out, 0, num_bytes, self.stream)?;
+                f.launch(
+                    self.default_stream(),
+                    cfg,
+                    (x, cols, tiles as u32, out, 1i32),
+                )
+            }
+            Determinism::Bitwise => {
+                let partials = self.scratch(blocks * std::mem::size_of::<f32>())?;
+                f.launch(
+                    self.default_stream(),
+                    cfg,
+                    (x, cols, tiles as u32, *partials.device_ptr(), 0i32),
+                )?;
+                self.launch_sum::<f32>(*partials.device_ptr(), rows, tiles, out, mode)
+            }
+        }
+    }
+
+    /// # Safety
+    /// `x` must hold `rows * cols` elements, and `out_idx` and `out_vals`, unless 0,
+    /// `rows * k`.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn launch_top_k<T: ReduceDtype>(
+        self: &Arc<Self>,
+        x: sys::CUdeviceptr,
+        rows: usize,
+        cols: usize,
+        k: usize,
+        minimize: bool,
+        out_idx: sys::CUdeviceptr,
+        out_vals: sys::CUdeviceptr,
+    ) -> Result<(), result::DriverError> {
+        if rows == 0 {
+            return Ok(());
+        }
+        let chunks = num_chunks(rows, cols);
+        let num_candidates = rows * chunks as usize * k;
+        let cand_v = self.scratch(num_candidates * std::mem::size_of::<f32>())?;
+        let cand_i = self.scratch(num_candidates * std::mem::size_of::<u32>())?;
+        let (cand_v, cand_i) = (cand_v.device_ptr(), cand_i.device_ptr());
+        let partial = self.reduce_function(&std::format!("topk_partial_{}", T::NAME))?;
+        let cfg = LaunchConfig {
+            grid_dim: (rows as u32, chunks, 1),
+            block_dim: (BLOCK, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let (k, minimize) = (k as u32, minimize as i32);
+        partial.launch(
+            self.default_stream(),
+            cfg,
+            (x, cols, k, minimize, *cand_v, *cand_i),
+        )?;
+
+        let last = self.reduce_function(&std::format!("topk_final_{}", T::NAME))?;
+        let cfg = LaunchConfig {
+            grid_dim: (rows as u32, 1, 1),
+            block_dim: (BLOCK, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        last.launch(
+            self.default_stream(),
+            cfg,
+            (x, cols, k, chunks, *cand_v, *cand_i, out_idx, out_vals),
+        )
+    }
+
+    fn reduce_function(self: &Arc<Self>, name: &str) -> Result<CudaFunction, result::DriverError> {
+        self.load_builtin("cudarc_reduce", reduce_src, KERNEL_NAMES, name)
+    }
+}
+
+/// The number of rows of `cols` elements in `len`, checking that the output holds `k`
+/// elements for each and the rows are addressable by the kernels.
+fn check_rows(
+    len: usize,
+    cols: usize,
+    k: usize,
+    out_len: usize,
+) -> Result<usize, result::DriverError> {
+    if cols == 0 || !len.is_multiple_of(cols) || k == 0 || k > cols {
+        return Err(invalid_value());
+    }
+    let rows = len / cols;
+    if rows * k != out_len || u32::try_from(cols).is_err() || u32::try_from(rows).is_err() {
+        return Err(invalid_value());
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_check_rows() {
+        assert_eq!(check_rows(12, 4, 1, 3), Ok(3));
+        assert_eq!(check_rows(12, 4, 2, 6), Ok(3));
+        assert!(check_rows(12, 5, 1, 2).is_err());
+        assert!(check_rows(12, 4, 5, 15).is_err());
+        assert!(check_rows(12, 4, 0, 0).is_err());
+        assert!(check_rows(12, 0, 1, 0).is_err());
+        assert!(check_rows(0, 4, 1, 0).is_ok());
+        assert_eq!(num_chunks(1, 10), 1);
+        assert_eq!(num_chunks(1, 1 << 20), 64);
+        assert_eq!(num_chunks(1024, 1 << 20), 1);
+        let src = reduce_src();
+        for name in KERNEL_NAMES {
+            assert!(src.contains(&std::format!("void {name}(")), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_argmax_ties_resolve_to_lowest_index() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut host = std::vec![0.0f32; 100_000];
+        host[70_000] = 5.0;
+        host[30_001] = 5.0;
+        host[99_999] = 5.0;
+        host[12] = -7.0;
+        host[50_000] = -7.0;
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let mut idx = dev.alloc_zeros::<u32>(1).unwrap();
+        let mut val = dev.alloc_zeros::<f32>(1).unwrap();
+
+        dev.argmax(&x, &mut idx).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [30_001]);
+        dev.argmin(&x, &mut idx).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [12]);
+        dev.max(&x, &mut val).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&val).unwrap(), [5.0]);
+        dev.min(&x, &mut val).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&val).unwrap(), [-7.0]);
+
+        host[80_000] = f32::NAN;
+        let x = dev.htod_sync_copy(&host).unwrap();
+        dev.argmax(&x, &mut idx).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [80_000]);
+
+        let mut two = dev.alloc_zeros::<u32>(2).unwrap();
+        assert!(dev.argmax(&x, &mut two).is_err());
+    }
+
+    #[test]
+    fn test_argmax_rows_async_readback() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (rows, cols) = (5, 3001);
+        let host: Vec<f32> = (0..rows * cols)
+            .map(|i| ((i * 7919) % 1009) as f32)
+            .collect();
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let mut idx = dev.alloc_zeros::<u32>(rows).unwrap();
+        dev.argmax_rows(&x, cols, &mut idx).unwrap();
+
+        let stream = dev.fork_default_stream().unwrap();
+        let pending = dev.dtoh_scalar_async(&idx, 4, &stream).unwrap().unwrap();
+        let expected: Vec<u32> = host
+            .chunks(cols)
+            .map(|row| {
+                let max = row.iter().cloned().fold(f32::MIN, f32::max);
+                row.iter().position(|&v| v == max).unwrap() as u32
+            })
+            .collect();
+        assert_eq!(pending.wait().unwrap(), expected[4]);
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_top_k_rows() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (rows, cols, k) = (3, 50_000, 40);
+        // few distinct values, so there are many ties
+        let host: Vec<f32> = (0..rows * cols)
+            .map(|i| ((i * 104_729) % 997) as f32)
+            .collect();
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let mut vals = dev.alloc_zeros::<f32>(rows * k).unwrap();
+        let mut idx = dev.alloc_zeros::<u32>(rows * k).unwrap();
+        dev.top_k_rows(&x, cols, k, &mut vals, &mut idx).unwrap();
+
+        let mut expected_idx = Vec::new();
+        for row in host.chunks(cols) {
+            let mut order: Vec<u32> = (0..cols as u32).collect();
+            // stable, so ties stay in index order
+            order.sort_by(|&a, &b| row[b as usize].total_cmp(&row[a as usize]));
+            expected_idx.extend_from_slice(&order[..k]);
+        }
+        let expected_vals: Vec<f32> = expected_idx
+            .chunks(k)
+            .enumerate()
+            .flat_map(|(r, idx)| idx.iter().map(move |&i| (r, i)))
+            .map(|(r, i)| host[r * cols + i as usize])
+            .collect();
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), expected_idx);
+        assert_eq!(dev.dtoh_sync_copy(&vals).unwrap(), expected_vals);
+
+        let mut short = dev.alloc_zeros::<u32>(rows * k - 1).unwrap();
+        assert!(dev.top_k_rows(&x, cols, k, &mut vals, &mut short).is_err());
+    }
+
+    /// Values of very different magnitudes, so the order they are added in shows.
+    fn sum_input(n: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| ((i * 7919) % 1009) as f32 * 10f32.powi((i % 7) as i32 - 3))
+            .collect()
+    }
+
+    #[test]
+    fn test_sum_rows_matches_host() {
+        let dev = CudaDevice::new(0).unwrap();
+        for mode in [Determinism::Fast, Determinism::Bitwise] {
+            dev.set_determinism(mode);
+            assert_eq!(dev.determinism(), mode);
+            for (rows, cols) in [(1, 1), (3, SUM_TILE), (2, SUM_TILE + 1), (4, 300_001)] {
+                let host = sum_input(rows * cols);
+                let x = dev.htod_sync_copy(&host).unwrap();
+                let mut out = dev.alloc_zeros::<f32>(rows).unwrap();
+                dev.sum_rows(&x, cols, &mut out).unwrap();
+                let out = dev.dtoh_sync_copy(&out).unwrap();
+                for (row, sum) in host.chunks(cols).zip(out) {
+                    let expected: f64 = row.iter().map(|&v| v as f64).sum();
+                    assert!(
+                        (sum as f64 - expected).abs() <= 1e-4 * expected.abs(),
+                        "{mode:?}"
+                    );
+                }
+            }
+        }
+        let x = dev.alloc_zeros::<f32>(4).unwrap();
+        let mut two = dev.alloc_zeros::<f32>(2).unwrap();
+        assert!(dev.sum(&x, &mut two).is_err());
+    }
+
+    fn repeated_sums(dev: &Arc<CudaDevice>, mode: Determinism) -> Vec<u32> {
+        dev.set_determinism(mode);
+        let x = dev.htod_sync_copy(&sum_input(1 << 22)).unwrap();
+        let mut out = dev.alloc_zeros::<f32>(1).unwrap();
+        (0..100)
+            .map(|_| {
+                dev.sum(&x, &mut out).unwrap();
+                dev.dtoh_sync_copy(&out).unwrap()[0].to_bits()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sum_bitwise_is_repeatable() {
+        let dev = CudaDevice::new(0).unwrap();
+        let sums = repeated_sums(&dev, Determinism::Bitwise);
+        assert!(sums.iter().all(|&s| s == sums[0]), "{sums:?}");
+    }
+
+    #[test]
+    fn test_sum_fast_matches_bitwise() {
+        let dev = CudaDevice::new(0).unwrap();
+        let bitwise = f32::from_bits(repeated_sums(&dev, Determinism::Bitwise)[0]);
+        // whether the order of the atomics varies is up to the hardware, only the rounding may
+        for fast in repeated_sums(&dev, Determinism::Fast) {
+            let fast = f32::from_bits(fast);
+            assert!(
+                (fast - bitwise).abs() <= 1e-4 * bitwise.abs(),
+                "{fast} vs {bitwise}"
+            );
+        }
+    }
+
+    #[cfg(feature = "f16")]
+    #[test]
+    fn test_top_k_half() {
+        let dev = CudaDevice::new(0).unwrap();
+        let values = [0.5f32, -2.0, 3.0, 3.0, 1.0];
+        let f16: Vec<half::f16> = values.iter().map(|&v| half::f16::from_f32(v)).collect();
+        let bf16: Vec<half::bf16> = values.iter().map(|&v| half::bf16::from_f32(v)).collect();
+        let mut idx = dev.alloc_zeros::<u32>(3).unwrap();
+
+        let x = dev.htod_sync_copy(&f16).unwrap();
+        let mut vals = dev.alloc_zeros::<half::f16>(3).unwrap();
+        dev.top_k(&x, 3, &mut vals, &mut idx).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [2, 3, 4]);
+        assert_eq!(dev.dtoh_sync_copy(&vals).unwrap(), [f16[2], f16[3], f16[4]]);
+
+        let x = dev.htod_sync_copy(&bf16).unwrap();
+        let mut vals = dev.alloc_zeros::<half::bf16>(3).unwrap();
+        dev.top_k(&x, 3, &mut vals, &mut idx).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [2, 3, 4]);
+        let mut one = dev.alloc_zeros::<u32>(1).unwrap();
+        dev.argmin(&x, &mut one).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&one).unwrap(), [1]);
+    }
+}
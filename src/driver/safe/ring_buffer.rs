@@ -0,0 +1,432 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::{CudaDevice, CudaSlice, CudaStream, CudaView};
+use super::device_ptr::DeviceSlice;
+use super::perf_hints::is_pageable;
+use super::DriverError;
+
+use core::ffi::c_void;
+use core::ops::Range;
+use std::sync::Arc;
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// What [DeviceRingBuffer::push()] does when the pushed elements don't fit next to the
+/// ones not yet consumed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RingOverrun {
+    /// The oldest elements are overwritten, and counted as consumed.
+    #[default]
+    Overwrite,
+    /// The push fails with [RingBufferError::Overrun], without copying anything.
+    Error,
+}
+
+/// Errors of [DeviceRingBuffer::push()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RingBufferError {
+    /// With [RingOverrun::Error], `pushed` elements were pushed while only `free` fit.
+    Overrun {
+        pushed: usize,
+        free: usize,
+    },
+    Driver(DriverError),
+}
+
+impl From<DriverError> for RingBufferError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for RingBufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RingBufferError {}
+
+/// The positions of a [DeviceRingBuffer] as kernels see them, see
+/// [DeviceRingBuffer::with_device_header()]. Positions count every element ever pushed,
+/// the element at position `p` is at index `p % capacity`. In cuda:
+///
+/// ```cuda
+/// struct RingHeader {
+///     unsigned long long head;
+///     unsigned long long tail;
+///     unsigned long long capacity;
+/// };
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RingHeader {
+    /// Position of the oldest element not consumed.
+    pub head: u64,
+    /// Position after the newest element.
+    pub tail: u64,
+    pub capacity: u64,
+}
+
+unsafe impl DeviceRepr for RingHeader {}
+
+/// A fixed-capacity ring of device memory that the host appends to with
+/// [DeviceRingBuffer::push()], and kernels read a window of with
+/// [DeviceRingBuffer::window()], e.g. for streaming audio frames into a pipeline.
+///
+/// The host tracks the head (the oldest element not [DeviceRingBuffer::consume()]d yet)
+/// and the tail (after the newest element pushed). Pushes and windows that cross the end
+/// of the ring are split in two.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let stream = dev.fork_default_stream().unwrap();
+/// let ring = DeviceRingBuffer::<f32>::new(&dev, 48_000).unwrap();
+/// ring.push(&[0.0; 480], &stream).unwrap();
+/// let (first, rest) = ring.window(480);
+/// assert_eq!(first.len() + rest.map_or(0, |r| r.len()), 480);
+/// ```
+#[derive(Debug)]
+pub struct DeviceRingBuffer<T> {
+    data: CudaSlice<T>,
+    header: Option<CudaSlice<RingHeader>>,
+    overrun: RingOverrun,
+    /// `(head, tail)`, see [RingHeader].
+    positions: RwLock<(u64, u64)>,
+}
+
+impl<T: DeviceRepr> DeviceRingBuffer<T> {
+    /// Allocates a ring of `capacity` elements. Fails with
+    /// [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `capacity` is 0.
+    pub fn new(device: &Arc<CudaDevice>, capacity: usize) -> Result<Self, DriverError> {
+        if capacity == 0 {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        }
+        Ok(Self {
+            // only elements that were pushed can be read
+            data: unsafe { device.alloc(capacity) }?,
+            header: None,
+            overrun: RingOverrun::default(),
+            positions: RwLock::new((0, 0)),
+        })
+    }
+
+    /// Sets what [DeviceRingBuffer::push()] does when the ring is full, see [RingOverrun].
+    pub fn with_overrun(mut self, overrun: RingOverrun) -> Self {
+        self.overrun = overrun;
+        self
+    }
+
+    /// Allocates a [RingHeader] on the device, which [DeviceRingBuffer::push()] and
+    /// [DeviceRingBuffer::consume()] update in order on their stream, for kernels that need
+    /// the positions. Pass it with [DeviceRingBuffer::header()].
+    pub fn with_device_header(mut self) -> Result<Self, DriverError> {
+        let header = RingHeader {
+            capacity: self.capacity() as u64,
+            ..Default::default()
+        };
+        self.header = Some(self.data.device().htod_sync_copy(&[header])?);
+        Ok(self)
+    }
+
+    /// The [RingHeader] allocated by [DeviceRingBuffer::with_device_header()].
+    pub fn header(&self) -> Option<&CudaSlice<RingHeader>> {
+        self.header.as_ref()
+    }
+
+    /// All of the ring, indexed by position modulo the capacity.
+    ///
+    /// # Safety
+    /// Only the elements of a [DeviceRingBuffer::window()] may be read: the others haven't
+    /// been pushed yet, so their memory is uninitialized.
+    pub unsafe fn data(&self) -> &CudaSlice<T> {
+        &self.data
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The position of the oldest element not consumed, see [RingHeader::head].
+    pub fn head(&self) -> u64 {
+        self.positions().0
+    }
+
+    /// The position after the newest element, i.e. the number of elements ever pushed.
+    pub fn tail(&self) -> u64 {
+        self.positions().1
+    }
+
+    /// The number of elements pushed and not consumed.
+    pub fn len(&self) -> usize {
+        let (head, tail) = self.positions();
+        (tail - head) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn positions(&self) -> (u64, u64) {
+        let positions = self.positions.read();
+        #[cfg(not(feature = "no-std"))]
+        let positions = positions.unwrap();
+        *positions
+    }
+
+    /// Copies `host` after the newest element on `stream`, in one copy or two if it wraps
+    /// around the end of the ring.
+    ///
+    /// If `host` doesn't fit next to the elements not consumed, [RingOverrun::Overwrite]
+    /// overwrites the oldest ones, and only copies the last [DeviceRingBuffer::capacity()]
+    /// elements if `host` is longer than that.
+    ///
+    /// Pageable memory is staged by the driver before this returns. Page-locked memory is
+    /// copied asynchronously, so this waits for `stream` in that case, as `host` could be
+    /// changed as soon as it returns. The copies are a write to the ring for
+    /// [CudaDevice::set_stream_tracking()].
+    pub fn push(&self, host: &[T], stream: &CudaStream) -> Result<(), RingBufferError> {
+        let capacity = self.capacity();
+        #[allow(unused_mut)]
+        let mut positions = self.positions.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut positions = positions.unwrap();
+        let (head, tail) = *positions;
+        let free = capacity - (tail - head) as usize;
+        if host.len() > free && self.overrun == RingOverrun::Error {
+            return Err(RingBufferError::Overrun {
+                pushed: host.len(),
+                free,
+            });
+        }
+
+        let _ctx = stream.bind_scoped()?;
+        let device = self.data.device();
+        device.wait_for_write(stream.stream, &self.data)?;
+        let skipped = host.len().saturating_sub(capacity);
+        let copied = &host[skipped..];
+        let (first, second) = ring_ranges(tail + skipped as u64, copied.len(), capacity);
+        let (copied_first, copied_second) = copied.split_at(first.len());
+        unsafe {
+            let dst = self.data.cu_device_ptr + (first.start * core::mem::size_of::<T>()) as u64;
+            result::memcpy_htod_async(dst, copied_first, stream.stream)?;
+            if second.is_some() {
+                result::memcpy_htod_async(self.data.cu_device_ptr, copied_second, stream.stream)?;
+            }
+        }
+        device.record_write(stream.stream, &self.data)?;
+
+        let tail = tail + host.len() as u64;
+        let head = head.max(tail.saturating_sub(capacity as u64));
+        *positions = (head, tail);
+        self.write_header(head, tail, stream)?;
+        if !host.is_empty() && !is_pageable(host.as_ptr() as *const c_void) {
+            unsafe { result::stream::synchronize(stream.stream) }?;
+        }
+        Ok(())
+    }
+
+    /// Marks the `n` oldest elements as consumed, e.g. once a kernel reading them has been
+    /// launched, which frees their space for [DeviceRingBuffer::push()]. The device header
+    /// is updated on `stream`.
+    ///
+    /// # Panics
+    /// If fewer than `n` elements are not consumed.
+    pub fn consume(&self, n: usize, stream: &CudaStream) -> Result<(), DriverError> {
+        #[allow(unused_mut)]
+        let mut positions = self.positions.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut positions = positions.unwrap();
+        let (head, tail) = *positions;
+        assert!(
+            n as u64 <= tail - head,
+            "consuming {n} of {} elements",
+            tail - head
+        );
+        *positions = (head + n as u64, tail);
+        let _ctx = match self.header {
+            Some(_) => Some(stream.bind_scoped()?),
+            None => None,
+        };
+        self.write_header(head + n as u64, tail, stream)
+    }
+
+    fn write_header(&self, head: u64, tail: u64, stream: &CudaStream) -> Result<(), DriverError> {
+        let Some(header) = &self.header else {
+            return Ok(());
+        };
+        let value = [RingHeader {
+            head,
+            tail,
+            capacity: self.capacity() as u64,
+        }];
+        let device = header.device();
+        device.wait_for_write(stream.stream, header)?;
+        // staged by the driver before returning, so `value` can go out of scope
+        unsafe { result::memcpy_htod_async(header.cu_device_ptr, &value, stream.stream) }?;
+        device.record_write(stream.stream, header)
+    }
+
+    /// The newest `len` elements, oldest first, as the part up to the end of the ring and,
+    /// if the window wraps around, the part from its start.
+    ///
+    /// Elements that were consumed are included as long as they haven't been overwritten.
+    ///
+    /// # Panics
+    /// If `len` is more than the capacity, or than the number of elements ever pushed.
+    pub fn window(&self, len: usize) -> (CudaView<'_, T>, Option<CudaView<'_, T>>) {
+        let tail = self.tail();
+        assert!(
+            len <= self.capacity() && len as u64 <= tail,
+            "window of {len} elements, but {tail} were pushed into a ring of {}",
+            self.capacity()
+        );
+        let (first, second) = ring_ranges(tail - len as u64, len, self.capacity());
+        (self.data.slice(first), second.map(|r| self.data.slice(r)))
+    }
+}
+
+/// The indices of `len` elements starting at `position` in a ring of `capacity`, split at
+/// the end of the ring. `len` must be at most `capacity`.
+fn ring_ranges(position: u64, len: usize, capacity: usize) -> (Range<usize>, Option<Range<usize>>) {
+    let start = (position % capacity as u64) as usize;
+    if start + len <= capacity {
+        (start..start + len, None)
+    } else {
+        (start..capacity, Some(0..start + len - capacity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_ring_ranges() {
+        assert_eq!(ring_ranges(0, 3, 10), (0..3, None));
+        assert_eq!(ring_ranges(7, 3, 10), (7..10, None));
+        assert_eq!(ring_ranges(9, 3, 10), (9..10, Some(0..2)));
+        assert_eq!(ring_ranges(21, 10, 10), (1..10, Some(0..1)));
+        assert_eq!(ring_ranges(5, 0, 10), (5..5, None));
+    }
+
+    fn read_window(ring: &DeviceRingBuffer<u32>, len: usize) -> Vec<u32> {
+        let dev = unsafe { ring.data() }.device();
+        let (first, second) = ring.window(len);
+        let mut out = dev.dtoh_sync_copy(&first).unwrap();
+        if let Some(second) = second {
+            out.extend(dev.dtoh_sync_copy(&second).unwrap());
+        }
+        out
+    }
+
+    #[test]
+    fn test_push_wraps_around() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        // 10 isn't divisible by the push size, so pushes wrap at every offset
+        let ring = DeviceRingBuffer::<u32>::new(&dev, 10).unwrap();
+        let mut next = 0;
+        for _ in 0..20 {
+            let frame: Vec<u32> = (next..next + 3).collect();
+            next += 3;
+            ring.push(&frame, &stream).unwrap();
+            dev.wait_for(&stream).unwrap();
+            let len = ring.len();
+            assert_eq!(ring.tail(), next as u64);
+            assert_eq!(len, (next as usize).min(10));
+            let expected: Vec<u32> = (next - len as u32..next).collect();
+            assert_eq!(read_window(&ring, len), expected);
+            assert_eq!(read_window(&ring, 4.min(len)), expected[len - 4.min(len)..]);
+        }
+
+        // longer than the ring, only the end is kept
+        let frame: Vec<u32> = (next..next + 23).collect();
+        ring.push(&frame, &stream).unwrap();
+        dev.wait_for(&stream).unwrap();
+        assert_eq!(read_window(&ring, 10), frame[13..]);
+        assert_eq!(
+            (ring.head(), ring.tail()),
+            (next as u64 + 13, next as u64 + 23)
+        );
+    }
+
+    #[test]
+    fn test_overrun_error() {
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(
+            DeviceRingBuffer::<u32>::new(&dev, 0).unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)
+        );
+        let stream = dev.fork_default_stream().unwrap();
+        let ring = DeviceRingBuffer::<u32>::new(&dev, 7)
+            .unwrap()
+            .with_overrun(RingOverrun::Error)
+            .with_device_header()
+            .unwrap();
+        ring.push(&[1, 2, 3], &stream).unwrap();
+        ring.push(&[4, 5, 6], &stream).unwrap();
+        assert_eq!(
+            ring.push(&[7, 8, 9], &stream),
+            Err(RingBufferError::Overrun { pushed: 3, free: 1 })
+        );
+        assert_eq!(ring.tail(), 6);
+
+        ring.consume(4, &stream).unwrap();
+        ring.push(&[7, 8, 9], &stream).unwrap();
+        dev.wait_for(&stream).unwrap();
+        assert_eq!(read_window(&ring, 5), [5, 6, 7, 8, 9]);
+        assert_eq!(ring.len(), 5);
+        let header = dev.dtoh_sync_copy(ring.header().unwrap()).unwrap();
+        assert_eq!(
+            header,
+            [RingHeader {
+                head: 4,
+                tail: 9,
+                capacity: 7
+            }]
+        );
+    }
+
+    #[test]
+    fn test_push_pinned() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        let ring = DeviceRingBuffer::<u32>::new(&dev, 5).unwrap();
+        let mut pinned = dev.alloc_pinned::<u32>(4).unwrap();
+        for i in 0..3 {
+            pinned.as_mut_slice().fill(i);
+            ring.push(pinned.as_slice(), &stream).unwrap();
+        }
+        dev.wait_for(&stream).unwrap();
+        assert_eq!(read_window(&ring, 5), [1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_push_is_tracked() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.set_stream_tracking(true);
+        let stream = dev.fork_default_stream().unwrap();
+        let ring = DeviceRingBuffer::<u32>::new(&dev, 1 << 20)
+            .unwrap()
+            .with_device_header()
+            .unwrap();
+        let frame: Vec<u32> = (0..1 << 20).collect();
+        ring.push(&frame, &stream).unwrap();
+        ring.consume(1 << 19, &stream).unwrap();
+        // read on the default stream without waiting for `stream`, views aren't tracked
+        assert_eq!(dev.dtoh_sync_copy(unsafe { ring.data() }).unwrap(), frame);
+        let header = dev.dtoh_sync_copy(ring.header().unwrap()).unwrap();
+        assert_eq!((header[0].head, header[0].tail), (1 << 19, 1 << 20));
+        dev.set_stream_tracking(false);
+    }
+}
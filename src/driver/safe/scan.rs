@@ -0,0 +1,425 @@
+use crate::driver::{result, sys};
+
+use super::alloc::PendingValue;
+use super::core::{CudaDevice, CudaFunction};
+use super::device_ptr::{DevicePtr, DevicePtrMut};
+use super::ops::invalid_value;
+use super::{DeviceRepr, LaunchAsync, LaunchConfig};
+
+use std::{string::String, sync::Arc};
+
+/// Element types of [CudaDevice::scan_exclusive()] and [CudaDevice::scan_inclusive()].
+///
+/// # Safety
+/// [ScanDtype::NAME] must be the name of the scan kernels for `Self`.
+pub unsafe trait ScanDtype: DeviceRepr + Copy {
+    /// The suffix of the scan kernels for the type.
+    const NAME: &'static str;
+}
+
+unsafe impl ScanDtype for u32 {
+    const NAME: &'static str = "u32";
+}
+
+unsafe impl ScanDtype for i64 {
+    const NAME: &'static str = "i64";
+}
+
+unsafe impl ScanDtype for f32 {
+    const NAME: &'static str = "f32";
+}
+
+/// The element types with their C type.
+const DTYPES: [(&str, &str); 3] = [
+    ("u32", "unsigned int"),
+    ("i64", "long long"),
+    ("f32", "float"),
+];
+
+/// The value types [CudaDevice::compact()] copies bitwise, by size.
+const WORDS: [(usize, &str); 4] = [
+    (1, "unsigned char"),
+    (2, "unsigned short"),
+    (4, "unsigned int"),
+    (8, "unsigned long long"),
+];
+
+const KERNEL_NAMES: &[&str] = &[
+    "scan_block_u32",
+    "scan_add_u32",
+    "scan_block_i64",
+    "scan_add_i64",
+    "scan_block_f32",
+    "scan_add_f32",
+    "compact_flags",
+    "compact_count",
+    "compact_1",
+    "compact_2",
+    "compact_4",
+    "compact_8",
+];
+
+/// Threads of each block of the scan kernels.
+const BLOCK: usize = 256;
+
+/// Consecutive elements scanned by each thread.
+const ITEMS: usize = 4;
+
+/// Elements scanned by each block.
+const TILE: usize = BLOCK * ITEMS;
+
+/// The source of the scan kernels. Each block scans a tile and writes its total to
+/// `sums`, the sums are scanned the same way, and are then added to all but the first tile.
+fn scan_src() -> String {
+    let mut src = std::format!("#define BLOCK {BLOCK}\n#define ITEMS {ITEMS}\n");
+    for (name, c_type) in DTYPES {
+        src.push_str(&std::format!(
+            "extern \"C\" __global__ void scan_block_{name}(const {c_type} *x, {c_type} *out, const size_t n, const int inclusive, {c_type} *sums) {{
+    __shared__ {c_type} totals[BLOCK];
+    const size_t start = (size_t)blockIdx.x * BLOCK * ITEMS + threadIdx.x * ITEMS;
+    {c_type} items[ITEMS];
+    {c_type} total = 0;
+    for (int j = 0; j < ITEMS; j++) {{
+        const size_t i = start + j;
+        total += i < n ? x[i] : ({c_type})0;
+        items[j] = total;
+    }}
+    totals[threadIdx.x] = total;
+    __syncthreads();
+    for (unsigned int off = 1; off < BLOCK; off <<= 1) {{
+        const {c_type} t = threadIdx.x >= off ? totals[threadIdx.x - off] : ({c_type})0;
+        __syncthreads();
+        totals[threadIdx.x] += t;
+        __syncthreads();
+    }}
+    const {c_type} before = totals[threadIdx.x] - total;
+    for (int j = 0; j < ITEMS; j++) {{
+        const size_t i = start + j;
+        if (i < n) {{
+            const {c_type} prev = j == 0 ? ({c_type})0 : items[j - 1];
+            out[i] = before + (inclusive ? items[j] : prev);
+        }}
+    }}
+    if (sums && threadIdx.x == BLOCK - 1) sums[blockIdx.x] = totals[BLOCK - 1];
+}}
+extern \"C\" __global__ void scan_add_{name}({c_type} *out, const size_t n, const {c_type} *sums) {{
+    const {c_type} s = sums[blockIdx.x + 1];
+    const size_t start = (size_t)(blockIdx.x + 1) * BLOCK * ITEMS;
+    for (unsigned int j = threadIdx.x; j < BLOCK * ITEMS; j += BLOCK) {{
+        if (start + j < n) out[start + j] += s;
+    }}
+}}
+"
+        ));
+    }
+    src.push_str(
+        "extern \"C\" __global__ void compact_flags(const unsigned char *mask, unsigned int *flags, const size_t n) {
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {
+        flags[i] = mask[i] != 0;
+    }
+}
+extern \"C\" __global__ void compact_count(const unsigned char *mask, const unsigned int *pos, const size_t n, unsigned int *count) {
+    *count = pos[n - 1] + (mask[n - 1] != 0);
+}
+",
+    );
+    for (size, word) in WORDS {
+        src.push_str(&std::format!(
+            "extern \"C\" __global__ void compact_{size}(const {word} *x, const unsigned char *mask, const unsigned int *pos, const size_t n, {word} *out, const size_t out_len) {{
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {{
+        if (mask[i] && pos[i] < out_len) out[pos[i]] = x[i];
+    }}
+}}
+"
+        ));
+    }
+    src
+}
+
+impl CudaDevice {
+    /// Sets `out[i]` to the sum of `x[..i]`, so `out[0]` is 0.
+    ///
+    /// The scan is work-efficient: each block scans a tile of the input, the totals of the
+    /// tiles are scanned recursively, and then added back. `u32` sums wrap around. `f32` sums
+    /// are added in a different order than a sequential sum, so they can differ from it by
+    /// rounding, but the order is the same on every call. The totals are kept in
+    /// [CudaDevice::scratch_arena()]. The kernels are compiled with nvrtc on first use, and
+    /// loaded into a module named `cudarc_scan`.
+    ///
+    /// `x` and `out` may alias. Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the
+    /// lengths differ.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let x = dev.htod_sync_copy(&[3u32, 1, 4, 1, 5]).unwrap();
+    /// let mut out = dev.alloc_zeros::<u32>(5).unwrap();
+    /// dev.scan_exclusive(&x, &mut out).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), [0, 3, 4, 8, 9]);
+    /// ```
+    pub fn scan_exclusive<T: ScanDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        if x.len() != out.len() {
+            return Err(invalid_value());
+        }
+        let n = x.len();
+        unsafe { self.scan::<T>(*x.device_ptr(), *out.device_ptr_mut(), n, false) }
+    }
+
+    /// Sets `out[i]` to the sum of `x[..=i]`, see [CudaDevice::scan_exclusive()].
+    pub fn scan_inclusive<T: ScanDtype, X: DevicePtr<T>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        x: &X,
+        out: &mut Out,
+    ) -> Result<(), result::DriverError> {
+        if x.len() != out.len() {
+            return Err(invalid_value());
+        }
+        let n = x.len();
+        unsafe { self.scan::<T>(*x.device_ptr(), *out.device_ptr_mut(), n, true) }
+    }
+
+    /// Scans the `n` elements at `x` into `out`.
+    ///
+    /// # Safety
+    /// `x` and `out` must hold `n` elements of `T`.
+    unsafe fn scan<T: ScanDtype>(
+        self: &Arc<Self>,
+        x: sys::CUdeviceptr,
+        out: sys::CUdeviceptr,
+        n: usize,
+        inclusive: bool,
+    ) -> Result<(), result::DriverError> {
+        if n == 0 {
+            return Ok(());
+        }
+        let num_blocks = n.div_ceil(TILE);
+        let cfg = LaunchConfig {
+            grid_dim: (num_blocks as u32, 1, 1),
+            block_dim: (BLOCK as u32, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let block = self.scan_function(&std::format!("scan_block_{}", T::NAME))?;
+        if num_blocks == 1 {
+            return block.launch(
+                self.default_stream(),
+                cfg,
+                (x, out, n, inclusive as i32, 0u64),
+            );
+        }
+
+        let sums = self.scratch(num_blocks * std::mem::size_of::<T>())?;
+        let sums_ptr = *sums.device_ptr();
+        block.launch(
+            self.default_stream(),
+            cfg,
+            (x, out, n, inclusive as i32, sums_ptr),
+        )?;
+        self.scan::<T>(sums_ptr, sums_ptr, num_blocks, false)?;
+        let add = self.scan_function(&std::format!("scan_add_{}", T::NAME))?;
+        let cfg = LaunchConfig {
+            grid_dim: (num_blocks as u32 - 1, 1, 1),
+            ..cfg
+        };
+        add.launch(self.default_stream(), cfg, (out, n, sums_ptr))
+    }
+
+    /// Copies the elements of `data` whose `mask` is non-zero to the front of `out`, in
+    /// order, and returns how many there are.
+    ///
+    /// Only the first `out.len()` of them are written, so `out` as long as `data` always
+    /// fits. Waits for the count to be copied to the host, see [CudaDevice::compact_async()]
+    /// to keep going. `T` is copied bitwise and can be any type of 1, 2, 4 or 8 bytes.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if `data` and `mask` have different
+    /// lengths, or `T` another size.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let data = dev.htod_sync_copy(&[1.0f32, 2.0, 3.0, 4.0]).unwrap();
+    /// let mask = dev.htod_sync_copy(&[1u8, 0, 0, 1]).unwrap();
+    /// let mut out = dev.alloc_zeros::<f32>(4).unwrap();
+    /// assert_eq!(dev.compact(&data, &mask, &mut out).unwrap(), 2);
+    /// assert_eq!(dev.dtoh_sync_copy(&out).unwrap()[..2], [1.0, 4.0]);
+    /// ```
+    pub fn compact<T: DeviceRepr, X: DevicePtr<T>, M: DevicePtr<u8>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        data: &X,
+        mask: &M,
+        out: &mut Out,
+    ) -> Result<usize, result::DriverError> {
+        match self.compact_async(data, mask, out)? {
+            Some(count) => Ok(count.wait()? as usize),
+            None => Ok(0),
+        }
+    }
+
+    /// [CudaDevice::compact()], returning the count as a [PendingValue] read back on the
+    /// device's stream once the compaction has completed, or `None` if `data` is empty.
+    pub fn compact_async<T: DeviceRepr, X: DevicePtr<T>, M: DevicePtr<u8>, Out: DevicePtrMut<T>>(
+        self: &Arc<Self>,
+        data: &X,
+        mask: &M,
+        out: &mut Out,
+    ) -> Result<Option<PendingValue<u32>>, result::DriverError> {
+        let n = data.len();
+        let size = std::mem::size_of::<T>();
+        if mask.len() != n || !WORDS.iter().any(|&(s, _)| s == size) {
+            return Err(invalid_value());
+        }
+        if n == 0 {
+            return Ok(None);
+        }
+        let cfg = LaunchConfig::for_num_elems(n.min(1 << 24) as u32);
+        let pos = self.scratch(n * std::mem::size_of::<u32>())?;
+        let count = self.scratch(std::mem::size_of::<u32>())?;
+        let (pos, count) = (*pos.device_ptr(), *count.device_ptr());
+        let mask = *mask.device_ptr();
+        let flags = self.scan_function("compact_flags")?;
+        unsafe { flags.launch(self.default_stream(), cfg, (mask, pos, n)) }?;
+        unsafe { self.scan::<u32>(pos, pos, n, false) }?;
+        let compact = self.scan_function(&std::format!("compact_{size}"))?;
+        let out_len = out.len();
+        let (data, out) = (*data.device_ptr(), *out.device_ptr_mut());
+        unsafe {
+            compact.launch(
+                self.default_stream(),
+                cfg,
+                (data, mask, pos, n, out, out_len),
+            )
+        }?;
+        let single = LaunchConfig {
+            grid_dim: (1, 1, 1),
+            block_dim: (1, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let counter = self.scan_function("compact_count")?;
+        unsafe { counter.launch(self.default_stream(), single, (mask, pos, n, count)) }?;
+        unsafe { self.readback_scalar(count, self.stream) }.map(Some)
+    }
+
+    fn scan_function(self: &Arc<Self>, name: &str) -> Result<CudaFunction, result::DriverError> {
+        self.load_builtin("cudarc_scan", scan_src, KERNEL_NAMES, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_kernel_names() {
+        let src = scan_src();
+        for name in KERNEL_NAMES {
+            assert!(src.contains(&std::format!("void {name}(")), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_scan_around_block_boundaries() {
+        let dev = CudaDevice::new(0).unwrap();
+        let lens = [
+            0,
+            1,
+            ITEMS - 1,
+            TILE - 1,
+            TILE,
+            TILE + 1,
+            TILE * TILE - 1,
+            TILE * TILE,
+            TILE * TILE + 1,
+            3_000_001,
+        ];
+        for n in lens {
+            let host: Vec<u32> = (0..n as u32)
+                .map(|i| i.wrapping_mul(2_654_435_761) % 17)
+                .collect();
+            let x = dev.htod_sync_copy(&host).unwrap();
+            let mut out = dev.alloc_zeros::<u32>(n).unwrap();
+
+            dev.scan_exclusive(&x, &mut out).unwrap();
+            let mut sum = 0u32;
+            let expected: Vec<u32> = host
+                .iter()
+                .map(|v| {
+                    let before = sum;
+                    sum = sum.wrapping_add(*v);
+                    before
+                })
+                .collect();
+            assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), expected, "n = {n}");
+
+            dev.scan_inclusive(&x, &mut out).unwrap();
+            let expected: Vec<u32> = host
+                .iter()
+                .scan(0u32, |s, v| {
+                    *s = s.wrapping_add(*v);
+                    Some(*s)
+                })
+                .collect();
+            assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), expected, "n = {n}");
+        }
+    }
+
+    #[test]
+    fn test_scan_i64_f32_in_place() {
+        let dev = CudaDevice::new(0).unwrap();
+        let n = TILE * 3 + 5;
+        let host: Vec<i64> = (0..n as i64).map(|i| (i % 7 - 3) << 33).collect();
+        let mut x = dev.htod_sync_copy(&host).unwrap();
+        dev.scan_inclusive(&x.clone(), &mut x).unwrap();
+        let expected: Vec<i64> = host
+            .iter()
+            .scan(0, |s, v| {
+                *s += v;
+                Some(*s)
+            })
+            .collect();
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), expected);
+
+        // small integers, so every partial sum is exact
+        let host: Vec<f32> = (0..n).map(|i| (i % 5) as f32).collect();
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let mut out = dev.alloc_zeros::<f32>(n).unwrap();
+        dev.scan_exclusive(&x, &mut out).unwrap();
+        let expected: Vec<f32> = (0..n)
+            .map(|i| host[..i].iter().map(|&v| v as f64).sum::<f64>() as f32)
+            .collect();
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap(), expected);
+
+        let mut short = dev.alloc_zeros::<f32>(n - 1).unwrap();
+        assert!(dev.scan_exclusive(&x, &mut short).is_err());
+    }
+
+    #[test]
+    fn test_compact() {
+        let dev = CudaDevice::new(0).unwrap();
+        let n = 100_003;
+        let host: Vec<f64> = (0..n).map(|i| i as f64).collect();
+        let mask: Vec<u8> = (0..n)
+            .map(|i| (i % 3 == 0 || i == n - 1) as u8 * 7)
+            .collect();
+        let data = dev.htod_sync_copy(&host).unwrap();
+        let mask_dev = dev.htod_sync_copy(&mask).unwrap();
+        let mut out = dev.alloc_zeros::<f64>(n).unwrap();
+        let kept = dev.compact(&data, &mask_dev, &mut out).unwrap();
+        let expected: Vec<f64> = host
+            .iter()
+            .zip(&mask)
+            .filter(|(_, &m)| m != 0)
+            .map(|(&v, _)| v)
+            .collect();
+        assert_eq!(kept, expected.len());
+        assert_eq!(dev.dtoh_sync_copy(&out).unwrap()[..kept], expected);
+
+        let empty = dev.alloc_zeros::<f64>(0).unwrap();
+        let no_mask = dev.alloc_zeros::<u8>(0).unwrap();
+        assert_eq!(dev.compact(&empty, &no_mask, &mut out).unwrap(), 0);
+        assert!(dev.compact(&data, &no_mask, &mut out).is_err());
+    }
+}
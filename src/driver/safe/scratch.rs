@@ -0,0 +1,684 @@
+use crate::driver::{result, sys};
+
+use super::allocator::DeviceAllocator;
+use super::core::{CudaDevice, CudaStream, StreamRef};
+use super::device_ptr::{DevicePtr, DevicePtrMut, DeviceSlice};
+use super::error_handler::{handle_error, OrHandle};
+use super::DriverError;
+
+use std::{sync::Arc, vec::Vec};
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// The default [ScratchArena::limit()] of each stream, 64 MiB.
+pub const DEFAULT_SCRATCH_LIMIT: usize = 64 << 20;
+
+/// The alignment of each [ScratchSlice], like the driver's allocations.
+const SCRATCH_ALIGN: usize = 256;
+
+/// The smallest chunk a [ScratchArena] allocates.
+const MIN_CHUNK_BYTES: usize = 1 << 20;
+
+/// The counters of a [ScratchArena], to tune its [ScratchArena::set_limit()] with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScratchStats {
+    /// Slices served from the chunks of the arena.
+    pub hits: u64,
+    /// Slices allocated normally because the arena was at its limit.
+    pub fallbacks: u64,
+    /// Chunks of device memory allocated for the arena.
+    pub chunks: u64,
+    /// Times the slices handed out were retired, by [ScratchArena::reset()] or to make
+    /// room for a slice that didn't fit.
+    pub resets: u64,
+    /// The bytes of the chunks of the arena.
+    pub bytes_reserved: usize,
+    /// The most bytes handed out between two resets, including fallbacks.
+    pub peak_bytes: usize,
+}
+
+#[derive(Debug)]
+struct Chunk {
+    ptr: sys::CUdeviceptr,
+    bytes: usize,
+    allocator: Option<Arc<dyn DeviceAllocator>>,
+}
+
+impl Chunk {
+    /// # Safety
+    /// No work may use the chunk anymore, and the context must be bound.
+    unsafe fn free(self, stream: sys::CUstream) -> Result<(), DriverError> {
+        match self.allocator {
+            Some(allocator) => allocator.free(self.ptr, self.bytes, stream),
+            None => result::free_sync(self.ptr),
+        }
+    }
+}
+
+/// The chunks of the slices handed out before a reset. They are reused once `event` has
+/// completed, which is only recorded once the last of the `live` slices has been dropped.
+#[derive(Debug)]
+struct Retired {
+    epoch: u64,
+    chunks: Vec<Chunk>,
+    live: usize,
+    event: Option<sys::CUevent>,
+}
+
+#[derive(Debug)]
+struct ArenaState {
+    /// The chunks of the current epoch. Slices are bumped off the last one.
+    current: Vec<Chunk>,
+    offset: usize,
+    epoch: u64,
+    /// Slices of the current epoch not yet dropped.
+    live: usize,
+    /// Bytes handed out in the current epoch.
+    epoch_bytes: usize,
+    retired: Vec<Retired>,
+    free: Vec<Chunk>,
+    limit: usize,
+    stats: ScratchStats,
+}
+
+/// The memory of the [ScratchArena] of a stream.
+#[derive(Debug)]
+pub(crate) struct ScratchState(RwLock<ArenaState>);
+
+impl Default for ScratchState {
+    fn default() -> Self {
+        Self(RwLock::new(ArenaState::new(DEFAULT_SCRATCH_LIMIT)))
+    }
+}
+
+impl ScratchState {
+    /// Waits for `stream` and frees all the chunks, which needs the context of the stream
+    /// to be bound.
+    pub(crate) fn clear(&mut self, stream: sys::CUstream, name: &'static str) {
+        let state = RwLock::get_mut(&mut self.0);
+        #[cfg(not(feature = "no-std"))]
+        let state = state.unwrap();
+        if state.current.is_empty() && state.retired.is_empty() && state.free.is_empty() {
+            return;
+        }
+        unsafe { result::stream::synchronize(stream) }.or_handle(name);
+        let retired = state.retired.drain(..).flat_map(|retired| {
+            if let Some(event) = retired.event {
+                unsafe { result::event::destroy(event) }.or_handle(name);
+            }
+            retired.chunks
+        });
+        let chunks: Vec<_> = retired
+            .chain(state.current.drain(..))
+            .chain(state.free.drain(..))
+            .collect();
+        for chunk in chunks {
+            unsafe { chunk.free(stream) }.or_handle(name);
+        }
+        state.stats.bytes_reserved = 0;
+    }
+
+    /// Forgets all the chunks and events without freeing them, after the reset of their
+    /// context freed them. The limit is kept.
+    pub(crate) fn forget(&mut self) {
+        let state = RwLock::get_mut(&mut self.0);
+        #[cfg(not(feature = "no-std"))]
+        let state = state.unwrap();
+        *state = ArenaState::new(state.limit);
+    }
+}
+
+impl ArenaState {
+    fn new(limit: usize) -> Self {
+        Self {
+            current: Vec::new(),
+            offset: 0,
+            epoch: 0,
+            live: 0,
+            epoch_bytes: 0,
+            retired: Vec::new(),
+            free: Vec::new(),
+            limit,
+            stats: Default::default(),
+        }
+    }
+
+    /// Bumps `bytes` off the last chunk, if they fit.
+    fn bump(&mut self, bytes: usize) -> Option<sys::CUdeviceptr> {
+        let chunk = self.current.last()?;
+        if chunk.bytes - self.offset < bytes {
+            return None;
+        }
+        let ptr = chunk.ptr + self.offset as sys::CUdeviceptr;
+        self.offset += bytes;
+        Some(ptr)
+    }
+
+    /// Moves the chunks of the retired epochs whose events have completed to `free`.
+    fn reclaim(&mut self, device: &CudaDevice) {
+        let mut i = 0;
+        while i < self.retired.len() {
+            let done = match self.retired[i].event {
+                // errors are reported by the next synchronization
+                Some(event) => unsafe { result::event::query(event) }.unwrap_or(false),
+                None => false,
+            };
+            if !done {
+                i += 1;
+                continue;
+            }
+            let retired = self.retired.swap_remove(i);
+            device.return_event(retired.event.unwrap());
+            self.free.extend(retired.chunks);
+        }
+    }
+
+    /// Starts a new epoch, the chunks of the current one being reused once the work
+    /// enqueued on `stream` until its last slice is dropped has completed.
+    fn retire(&mut self, device: &CudaDevice, stream: sys::CUstream) -> Result<(), DriverError> {
+        self.epoch_bytes = 0;
+        if self.current.is_empty() {
+            return Ok(());
+        }
+        let event = match self.live {
+            0 => Some(record_event(device, stream)?),
+            _ => None,
+        };
+        self.retired.push(Retired {
+            epoch: self.epoch,
+            chunks: std::mem::take(&mut self.current),
+            live: self.live,
+            event,
+        });
+        self.offset = 0;
+        self.epoch += 1;
+        self.live = 0;
+        self.stats.resets += 1;
+        Ok(())
+    }
+
+    /// Starts a chunk of at least `bytes` for the current epoch, reusing a free one if
+    /// possible, and bumps `bytes` off it. `None` if the arena is at its limit.
+    fn take_chunk(
+        &mut self,
+        device: &CudaDevice,
+        stream: sys::CUstream,
+        bytes: usize,
+    ) -> Result<Option<sys::CUdeviceptr>, DriverError> {
+        let reused = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.bytes >= bytes)
+            .min_by_key(|(_, chunk)| chunk.bytes)
+            .map(|(i, _)| i);
+        let chunk = match reused {
+            Some(i) => self.free.swap_remove(i),
+            None => {
+                let Some(size) = grow_bytes(self.stats.bytes_reserved, bytes, self.limit) else {
+                    return Ok(None);
+                };
+                let (ptr, allocator) = device.alloc_with_allocator(size, stream, || unsafe {
+                    device.malloc_sync_reaping(size)
+                })?;
+                self.stats.chunks += 1;
+                self.stats.bytes_reserved += size;
+                Chunk {
+                    ptr,
+                    bytes: size,
+                    allocator,
+                }
+            }
+        };
+        self.current.push(chunk);
+        self.offset = 0;
+        Ok(self.bump(bytes))
+    }
+}
+
+/// The size of the next chunk of an arena with `reserved` bytes of chunks, which doubles
+/// them up to `limit`, or `None` if a chunk of `bytes` would be over the limit.
+fn grow_bytes(reserved: usize, bytes: usize, limit: usize) -> Option<usize> {
+    let room = limit.checked_sub(reserved)?;
+    let size = bytes.max(MIN_CHUNK_BYTES).max(reserved).min(room);
+    (size >= bytes).then_some(size)
+}
+
+/// Takes an event from the device's pool and records it on `stream`.
+fn record_event(device: &CudaDevice, stream: sys::CUstream) -> Result<sys::CUevent, DriverError> {
+    let event = device.take_event()?;
+    if let Err(e) = unsafe { result::event::record(event, stream) } {
+        device.return_event(event);
+        return Err(e);
+    }
+    Ok(event)
+}
+
+/// A bump allocator of temporary device memory attached to a stream, for the scratch
+/// space of the work enqueued on it like the partial results of a reduction. Get it with
+/// [CudaStream::scratch_arena()], or [CudaDevice::scratch_arena()] for the device's work
+/// stream, which the builtin reductions, scans and sorts allocate their scratch space from.
+///
+/// [ScratchArena::alloc()] hands out consecutive slices of large chunks of device memory.
+/// [ScratchArena::reset()] records an event on the stream, and once it has completed the
+/// chunks of the slices handed out before are reused. Slices that are still alive at the
+/// reset keep theirs until they are dropped, and the event is only recorded then. When a
+/// slice doesn't fit in the current chunk, the arena resets itself before allocating a
+/// new chunk, so that it recycles its memory without ever calling reset.
+///
+/// Chunks double the memory of the arena up to [ScratchArena::limit()], after which
+/// slices are allocated and freed normally. [ScratchArena::stats()] counts both.
+///
+/// The memory is only ordered with the work enqueued on the stream of the arena. Don't use
+/// a slice on another stream, unless that stream's work is waited for on the arena's
+/// stream before the slice is dropped.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let stream = dev.fork_default_stream().unwrap();
+/// let partials = stream.scratch(4096).unwrap();
+/// assert_eq!(partials.len(), 4096);
+/// drop(partials);
+/// stream.scratch_arena().reset().unwrap();
+/// assert_eq!(stream.scratch_arena().stats().hits, 1);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ScratchArena<'a> {
+    stream: StreamRef<'a>,
+}
+
+impl<'a> ScratchArena<'a> {
+    fn state(&self) -> &'a ScratchState {
+        match self.stream {
+            StreamRef::Default(device) => &device.scratch,
+            StreamRef::Stream(stream) => &stream.scratch,
+        }
+    }
+
+    /// Hands out `bytes` of uninitialized device memory, aligned to 256 bytes, for the
+    /// work enqueued on the arena's stream.
+    pub fn alloc(&self, bytes: usize) -> Result<ScratchSlice<'a>, DriverError> {
+        let device = self.stream.device();
+        let stream = self.stream.cu_stream();
+        let rounded = bytes
+            .max(1)
+            .checked_next_multiple_of(SCRATCH_ALIGN)
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_OUT_OF_MEMORY))?;
+        let _ctx = self.stream.bind_scoped()?;
+        {
+            #[allow(unused_mut)]
+            let mut state = self.state().0.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut state = state.unwrap();
+            state.reclaim(device);
+            let ptr = match state.bump(rounded) {
+                Some(ptr) => Some(ptr),
+                None => {
+                    state.retire(device, stream)?;
+                    state.take_chunk(device, stream, rounded)?
+                }
+            };
+            state.epoch_bytes = state.epoch_bytes.saturating_add(rounded);
+            state.stats.peak_bytes = state.stats.peak_bytes.max(state.epoch_bytes);
+            if let Some(ptr) = ptr {
+                state.live += 1;
+                state.stats.hits += 1;
+                return Ok(ScratchSlice {
+                    stream: self.stream,
+                    ptr,
+                    len: bytes,
+                    bytes: rounded,
+                    epoch: Some(state.epoch),
+                    allocator: None,
+                });
+            }
+            state.stats.fallbacks += 1;
+        }
+        let (ptr, allocator) = device.alloc_with_allocator(rounded, stream, || unsafe {
+            if device.is_async {
+                result::malloc_async(stream, rounded)
+            } else {
+                device.malloc_sync_reaping(rounded)
+            }
+        })?;
+        Ok(ScratchSlice {
+            stream: self.stream,
+            ptr,
+            len: bytes,
+            bytes: rounded,
+            epoch: None,
+            allocator,
+        })
+    }
+
+    /// Retires the slices handed out so far, so that their memory is reused once the work
+    /// enqueued on the stream until they are all dropped has completed.
+    pub fn reset(&self) -> Result<(), DriverError> {
+        let _ctx = self.stream.bind_scoped()?;
+        #[allow(unused_mut)]
+        let mut state = self.state().0.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        state.reclaim(self.stream.device());
+        state.retire(self.stream.device(), self.stream.cu_stream())
+    }
+
+    /// Frees the chunks that aren't used by any slice, nor by work that hasn't completed.
+    pub fn release(&self) -> Result<(), DriverError> {
+        let _ctx = self.stream.bind_scoped()?;
+        #[allow(unused_mut)]
+        let mut state = self.state().0.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        state.reclaim(self.stream.device());
+        while let Some(chunk) = state.free.pop() {
+            let bytes = chunk.bytes;
+            unsafe { chunk.free(self.stream.cu_stream()) }?;
+            state.stats.bytes_reserved -= bytes;
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> ScratchStats {
+        let state = self.state().0.read();
+        #[cfg(not(feature = "no-std"))]
+        let state = state.unwrap();
+        state.stats
+    }
+
+    /// The most bytes of chunks the arena allocates, [DEFAULT_SCRATCH_LIMIT] unless set
+    /// with [ScratchArena::set_limit()].
+    pub fn limit(&self) -> usize {
+        let state = self.state().0.read();
+        #[cfg(not(feature = "no-std"))]
+        let state = state.unwrap();
+        state.limit
+    }
+
+    /// Sets [ScratchArena::limit()]. Chunks allocated already are kept until
+    /// [ScratchArena::release()], or until the stream is dropped.
+    pub fn set_limit(&self, bytes: usize) {
+        #[allow(unused_mut)]
+        let mut state = self.state().0.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        state.limit = bytes;
+    }
+}
+
+/// Temporary device memory of a [ScratchArena], from [CudaStream::scratch()] or
+/// [CudaDevice::scratch()]. Its memory can be handed out again once the work enqueued on
+/// the arena's stream until it is dropped has completed.
+#[derive(Debug)]
+pub struct ScratchSlice<'a> {
+    stream: StreamRef<'a>,
+    ptr: sys::CUdeviceptr,
+    len: usize,
+    /// `len` rounded up to the alignment.
+    bytes: usize,
+    /// The epoch of the arena the slice was handed out in, `None` for fallbacks.
+    epoch: Option<u64>,
+    allocator: Option<Arc<dyn DeviceAllocator>>,
+}
+
+impl DeviceSlice<u8> for ScratchSlice<'_> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl DevicePtr<u8> for ScratchSlice<'_> {
+    fn device_ptr(&self) -> &sys::CUdeviceptr {
+        &self.ptr
+    }
+}
+
+impl DevicePtrMut<u8> for ScratchSlice<'_> {
+    fn device_ptr_mut(&mut self) -> &mut sys::CUdeviceptr {
+        &mut self.ptr
+    }
+}
+
+impl Drop for ScratchSlice<'_> {
+    fn drop(&mut self) {
+        let device = self.stream.device();
+        let stream = self.stream.cu_stream();
+        let _ctx = self.stream.bind_scoped_or_handle("ScratchSlice::drop");
+        let Some(epoch) = self.epoch else {
+            let res = unsafe {
+                match self.allocator.take() {
+                    Some(allocator) => allocator.free(self.ptr, self.bytes, stream),
+                    None if device.is_async => result::free_async(self.ptr, stream),
+                    None => device.defer_free(self.ptr, stream),
+                }
+            };
+            res.or_handle("ScratchSlice::drop");
+            return;
+        };
+        let arena = ScratchArena {
+            stream: self.stream,
+        };
+        #[allow(unused_mut)]
+        let mut state = arena.state().0.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut state = state.unwrap();
+        if epoch == state.epoch {
+            state.live -= 1;
+            return;
+        }
+        let Some(retired) = state.retired.iter_mut().find(|r| r.epoch == epoch) else {
+            return;
+        };
+        retired.live -= 1;
+        if retired.live == 0 {
+            match record_event(device, stream) {
+                Ok(event) => retired.event = Some(event),
+                // the chunks are only freed with the stream
+                Err(e) => handle_error(e, "ScratchSlice::drop"),
+            }
+        }
+    }
+}
+
+impl CudaDevice {
+    /// The [ScratchArena] of the device's work stream.
+    pub fn scratch_arena(&self) -> ScratchArena<'_> {
+        ScratchArena {
+            stream: self.default_stream(),
+        }
+    }
+
+    /// [ScratchArena::alloc()] of `bytes` on [CudaDevice::scratch_arena()].
+    pub fn scratch(&self, bytes: usize) -> Result<ScratchSlice<'_>, DriverError> {
+        self.scratch_arena().alloc(bytes)
+    }
+}
+
+impl CudaStream {
+    /// The [ScratchArena] of the stream.
+    pub fn scratch_arena(&self) -> ScratchArena<'_> {
+        ScratchArena {
+            stream: self.into(),
+        }
+    }
+
+    /// [ScratchArena::alloc()] of `bytes` on [CudaStream::scratch_arena()].
+    pub fn scratch(&self, bytes: usize) -> Result<ScratchSlice<'_>, DriverError> {
+        self.scratch_arena().alloc(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    #[test]
+    fn test_grow_bytes() {
+        let limit = 16 << 20;
+        assert_eq!(grow_bytes(0, 256, limit), Some(MIN_CHUNK_BYTES));
+        assert_eq!(grow_bytes(0, 3 << 20, limit), Some(3 << 20));
+        assert_eq!(grow_bytes(3 << 20, 256, limit), Some(3 << 20));
+        assert_eq!(grow_bytes(12 << 20, 256, limit), Some(4 << 20));
+        assert_eq!(grow_bytes(12 << 20, 5 << 20, limit), None);
+        assert_eq!(grow_bytes(limit, 256, limit), None);
+        assert_eq!(grow_bytes(0, 256, 0), None);
+        assert_eq!(grow_bytes(0, 256, 512), Some(512));
+    }
+
+    #[test]
+    fn test_scratch_reuse_after_reset() {
+        let dev = CudaDevice::new(0).unwrap();
+        let stream = dev.fork_default_stream().unwrap();
+        let arena = stream.scratch_arena();
+
+        let a = arena.alloc(100).unwrap();
+        let b = arena.alloc(1000).unwrap();
+        assert_eq!(*b.device_ptr(), *a.device_ptr() + 256);
+        let a_ptr = *a.device_ptr();
+        drop(a);
+        // `b` keeps the chunk from being reused after the reset
+        arena.reset().unwrap();
+        let c = arena.alloc(100).unwrap();
+        assert_ne!(*c.device_ptr(), a_ptr);
+        drop(b);
+        drop(c);
+        arena.reset().unwrap();
+        unsafe { result::stream::synchronize(stream.stream) }.unwrap();
+        // both chunks are free again
+        let d = arena.alloc(100).unwrap();
+        assert_eq!(arena.stats().chunks, 2);
+        drop(d);
+
+        let stats = arena.stats();
+        assert_eq!((stats.hits, stats.fallbacks, stats.resets), (4, 0, 2));
+        assert_eq!(stats.bytes_reserved, 2 * MIN_CHUNK_BYTES);
+        assert_eq!(stats.peak_bytes, 256 + 1024);
+
+        arena.set_limit(0);
+        arena.reset().unwrap();
+        unsafe { result::stream::synchronize(stream.stream) }.unwrap();
+        arena.release().unwrap();
+        assert_eq!(arena.stats().bytes_reserved, 0);
+        let e = arena.alloc(10).unwrap();
+        assert_eq!(e.len(), 10);
+        assert_eq!(arena.stats().fallbacks, 1);
+    }
+
+    #[test]
+    fn test_scratch_ops_back_to_back() {
+        let dev = CudaDevice::new(0).unwrap();
+        let n = 5000;
+        let host: Vec<u32> = (0..n as u32).map(|i| (i * 7919) % 1013).collect();
+        let x = dev.htod_sync_copy(&host).unwrap();
+        let mut expected_scan = std::vec![0u32; n];
+        for i in 1..n {
+            expected_scan[i] = expected_scan[i - 1] + host[i - 1];
+        }
+        let mut expected_sort = host.clone();
+        expected_sort.sort();
+        let floats: Vec<f32> = host.iter().map(|&v| v as f32).collect();
+        let xf = dev.htod_sync_copy(&floats).unwrap();
+        let expected_max = host
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, &v)| (v, n - i))
+            .unwrap()
+            .0;
+
+        // the arena at its default limit, and one that only falls back
+        for limit in [DEFAULT_SCRATCH_LIMIT, 0] {
+            dev.scratch_arena().set_limit(limit);
+            for _ in 0..4 {
+                let mut scanned = dev.alloc_zeros::<u32>(n).unwrap();
+                let mut sorted = x.clone();
+                let mut idx = dev.alloc_zeros::<u32>(1).unwrap();
+                dev.scan_exclusive(&x, &mut scanned).unwrap();
+                dev.sort(&mut sorted).unwrap();
+                dev.argmax(&xf, &mut idx).unwrap();
+                let order = dev.argsort(&x).unwrap();
+                assert_eq!(dev.dtoh_sync_copy(&scanned).unwrap(), expected_scan);
+                assert_eq!(dev.dtoh_sync_copy(&sorted).unwrap(), expected_sort);
+                assert_eq!(dev.dtoh_sync_copy(&idx).unwrap(), [expected_max as u32]);
+                let order = dev.dtoh_sync_copy(&order).unwrap();
+                let by_order: Vec<u32> = order.iter().map(|&i| host[i as usize]).collect();
+                assert_eq!(by_order, expected_sort);
+            }
+        }
+        let stats = dev.scratch_arena().stats();
+        assert!(stats.hits > 0);
+        assert!(stats.fallbacks > 0);
+        assert!(stats.resets > 0);
+    }
+
+    const FILL_CU: &str = "
+extern \"C\" __global__ void fill(unsigned int *buf, size_t n, unsigned int value) {
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += blockDim.x * gridDim.x) {
+        buf[i] = value + i;
+    }
+}
+extern \"C\" __global__ void check(const unsigned int *buf, size_t n, unsigned int value, unsigned int *bad) {
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += blockDim.x * gridDim.x) {
+        if (buf[i] != value + i) {
+            atomicAdd(bad, 1);
+        }
+    }
+}";
+
+    #[test]
+    fn test_scratch_on_two_streams() {
+        let dev = CudaDevice::new(0).unwrap();
+        let ptx = compile_ptx(FILL_CU).unwrap();
+        dev.load_ptx(ptx, "scratch_fill", &["fill", "check"])
+            .unwrap();
+        let fill = dev.get_func("scratch_fill", "fill").unwrap();
+        let check = dev.get_func("scratch_fill", "check").unwrap();
+        let bad = dev.alloc_zeros::<u32>(2).unwrap();
+        let bad_ptrs = [bad.try_at(0).unwrap(), bad.try_at(1).unwrap()];
+
+        let streams = [
+            dev.fork_default_stream().unwrap(),
+            dev.fork_default_stream().unwrap(),
+        ];
+        let n = 1 << 16;
+        let cfg = LaunchConfig::for_num_elems(1024);
+        for i in 0..64u32 {
+            for (s, stream) in streams.iter().enumerate() {
+                let value = (s as u32) << 24 | i << 16;
+                let a = stream.scratch(n * 4).unwrap();
+                let b = stream.scratch(n * 4).unwrap();
+                unsafe {
+                    fill.clone()
+                        .launch(stream, cfg, (*a.device_ptr(), n, value))
+                        .unwrap();
+                    fill.clone()
+                        .launch(stream, cfg, (*b.device_ptr(), n, !value))
+                        .unwrap();
+                    check
+                        .clone()
+                        .launch(stream, cfg, (*a.device_ptr(), n, value, bad_ptrs[s]))
+                        .unwrap();
+                    check
+                        .clone()
+                        .launch(stream, cfg, (*b.device_ptr(), n, !value, bad_ptrs[s]))
+                        .unwrap();
+                }
+                if i % 3 == 0 {
+                    stream.scratch_arena().reset().unwrap();
+                }
+            }
+        }
+        for stream in streams.iter() {
+            let stats = stream.scratch_arena().stats();
+            assert_eq!(stats.hits, 128);
+            assert!(stats.bytes_reserved <= DEFAULT_SCRATCH_LIMIT);
+        }
+        drop(streams);
+        assert_eq!(dev.dtoh_sync_copy(&bad).unwrap(), [0, 0]);
+    }
+}
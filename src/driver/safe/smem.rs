@@ -0,0 +1,226 @@
+use crate::driver::sys;
+
+use super::{DriverError, LaunchConfig};
+
+use std::vec::Vec;
+
+/// The dynamic shared memory of a kernel as a sequence of named arrays, so that its size
+/// and the offset of each array are computed in one place instead of by hand:
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let (tile_m, tile_n, tile_k) = (128, 128, 32);
+/// let layout = SmemLayout::new()
+///     .array::<f32>("tile_a", tile_m * tile_k)
+///     .array::<u8>("flags", 3)
+///     .array::<f64>("acc", tile_m)
+///     .build()
+///     .unwrap();
+/// assert_eq!(layout.offset("acc"), Some(16392));
+/// assert_eq!(layout.bytes(), 16392 + 8 * tile_m as u32);
+/// let cfg = LaunchConfig::for_num_elems(tile_m as u32 * tile_n as u32).with_smem_layout(&layout);
+/// assert_eq!(cfg.shared_mem_bytes, layout.bytes());
+/// ```
+///
+/// Each array starts at the next multiple of the alignment of its type, or the one given to
+/// [SmemLayout::array_aligned()]. [SmemLayout::build()] checks the layout, and the
+/// [BuiltSmemLayout] it returns is what [LaunchConfig::with_smem_layout()] takes. Pass the
+/// offsets to the kernel in its params, e.g. from [BuiltSmemLayout::offsets()], and index a
+/// byte array declared with at least [BuiltSmemLayout::align()]:
+///
+/// ```cuda
+/// extern __shared__ __align__(16) unsigned char smem[];
+/// float *tile_a = (float *)(smem + params.tile_a_offset);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmemLayout {
+    arrays: Vec<SmemArray>,
+    end: usize,
+    align: usize,
+    invalid: bool,
+}
+
+/// A [SmemLayout] checked by [SmemLayout::build()], with its size padded to a multiple of
+/// its alignment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltSmemLayout {
+    arrays: Vec<SmemArray>,
+    bytes: u32,
+    align: usize,
+}
+
+/// One array of a [SmemLayout].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmemArray {
+    pub name: &'static str,
+    /// The offset in bytes from the start of the dynamic shared memory.
+    pub offset: usize,
+    pub bytes: usize,
+    pub align: usize,
+}
+
+impl SmemLayout {
+    pub fn new() -> Self {
+        Self {
+            align: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Appends an array of `len` elements of `T`, aligned to `T`.
+    pub fn array<T>(self, name: &'static str, len: usize) -> Self {
+        self.array_aligned::<T>(name, len, core::mem::align_of::<T>())
+    }
+
+    /// Appends an array of `len` elements of `T`, aligned to `align` bytes, e.g. 16 for
+    /// vectorized loads. `align` must be a power of two, and at least the alignment of `T`.
+    pub fn array_aligned<T>(mut self, name: &'static str, len: usize, align: usize) -> Self {
+        let bytes = len.checked_mul(core::mem::size_of::<T>());
+        let offset = align
+            .is_power_of_two()
+            .then(|| self.end.checked_next_multiple_of(align))
+            .flatten();
+        match (offset, bytes) {
+            (Some(offset), Some(bytes))
+                if align >= core::mem::align_of::<T>()
+                    && self.arrays.iter().all(|a| a.name != name) =>
+            {
+                self.end = offset.saturating_add(bytes);
+                self.align = self.align.max(align);
+                self.arrays.push(SmemArray {
+                    name,
+                    offset,
+                    bytes,
+                    align,
+                });
+            }
+            _ => self.invalid = true,
+        }
+        self
+    }
+
+    /// Checks the layout, padding its size to a multiple of the largest alignment of the
+    /// arrays.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if two arrays have the same name,
+    /// an alignment isn't valid, or the total is more than [u32::MAX] bytes.
+    pub fn build(self) -> Result<BuiltSmemLayout, DriverError> {
+        let bytes = self
+            .end
+            .checked_next_multiple_of(self.align)
+            .and_then(|end| u32::try_from(end).ok());
+        match bytes {
+            Some(bytes) if !self.invalid => Ok(BuiltSmemLayout {
+                arrays: self.arrays,
+                bytes,
+                align: self.align,
+            }),
+            _ => Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)),
+        }
+    }
+}
+
+impl BuiltSmemLayout {
+    /// The total size, for [LaunchConfig::shared_mem_bytes].
+    pub fn bytes(&self) -> u32 {
+        self.bytes
+    }
+
+    /// The largest alignment of the arrays, which the start of the dynamic shared memory
+    /// must be aligned to.
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    /// The offset in bytes of the array named `name`.
+    pub fn offset(&self, name: &str) -> Option<usize> {
+        self.get(name).map(|a| a.offset)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SmemArray> {
+        self.arrays.iter().find(|a| a.name == name)
+    }
+
+    /// The arrays in the order they were added.
+    pub fn arrays(&self) -> &[SmemArray] {
+        &self.arrays
+    }
+
+    /// The offsets of the arrays in the order they were added, to copy into the params
+    /// struct of the kernel. `None` unless there are exactly `N` arrays.
+    pub fn offsets<const N: usize>(&self) -> Option<[u32; N]> {
+        if self.arrays.len() != N {
+            return None;
+        }
+        let mut offsets = [0; N];
+        for (offset, array) in offsets.iter_mut().zip(self.arrays.iter()) {
+            *offset = array.offset as u32;
+        }
+        Some(offsets)
+    }
+}
+
+impl LaunchConfig {
+    /// Sets [LaunchConfig::shared_mem_bytes] to [BuiltSmemLayout::bytes()].
+    pub fn with_smem_layout(mut self, layout: &BuiltSmemLayout) -> Self {
+        self.shared_mem_bytes = layout.bytes();
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smem_layout_alignment() {
+        let layout = SmemLayout::new()
+            .array::<u8>("a", 3)
+            .array::<f64>("b", 2)
+            .array::<u16>("c", 1)
+            .array_aligned::<f32>("d", 5, 16)
+            .array::<u8>("e", 1)
+            .build()
+            .unwrap();
+        assert_eq!(layout.offset("a"), Some(0));
+        assert_eq!(layout.offset("b"), Some(8));
+        assert_eq!(layout.offset("c"), Some(24));
+        assert_eq!(layout.offset("d"), Some(32));
+        assert_eq!(layout.offset("e"), Some(52));
+        assert_eq!(layout.offset("f"), None);
+        assert_eq!(layout.align(), 16);
+        assert_eq!(layout.bytes(), 64);
+        assert_eq!(layout.offsets::<5>(), Some([0, 8, 24, 32, 52]));
+        assert_eq!(layout.offsets::<4>(), None);
+        for array in layout.arrays() {
+            assert!(array.offset.is_multiple_of(array.align));
+        }
+        #[cfg(feature = "f16")]
+        {
+            let layout = SmemLayout::new()
+                .array::<half::f16>("tile_b", 3)
+                .array::<u64>("acc", 1)
+                .build()
+                .unwrap();
+            assert_eq!(layout.offset("acc"), Some(8));
+        }
+        let cfg = LaunchConfig::for_num_elems(1).with_smem_layout(&layout);
+        assert_eq!(cfg.shared_mem_bytes, 64);
+        assert_eq!(SmemLayout::new().build().unwrap().bytes(), 0);
+    }
+
+    #[test]
+    fn test_smem_layout_invalid() {
+        let invalid = Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE));
+        let dup = SmemLayout::new().array::<f32>("a", 1).array::<f32>("a", 1);
+        assert_eq!(dup.build(), invalid);
+        let align = SmemLayout::new().array_aligned::<f32>("a", 1, 12);
+        assert_eq!(align.build(), invalid);
+        let under = SmemLayout::new().array_aligned::<f64>("a", 1, 4);
+        assert_eq!(under.build(), invalid);
+        let huge = SmemLayout::new().array::<f32>("a", 1 << 30);
+        assert_eq!(huge.build(), invalid);
+        let overflow = SmemLayout::new().array::<f64>("a", usize::MAX);
+        assert_eq!(overflow.build(), invalid);
+    }
+}
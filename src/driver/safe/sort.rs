@@ -0,0 +1,434 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaFunction, CudaSlice};
+use super::device_ptr::{DevicePtr, DevicePtrMut};
+use super::ops::invalid_value;
+use super::{DeviceRepr, LaunchAsync, LaunchConfig};
+
+use std::{string::String, sync::Arc};
+
+/// Key types of [CudaDevice::sort()], [CudaDevice::sort_pairs()] and [CudaDevice::argsort()].
+///
+/// # Safety
+/// `Self` must be 4 bytes, and [SortKey::NAME] one of the key types of the sort kernels.
+pub unsafe trait SortKey: DeviceRepr {
+    /// The suffix of the sort kernels for the type.
+    const NAME: &'static str;
+}
+
+unsafe impl SortKey for u32 {
+    const NAME: &'static str = "u32";
+}
+
+unsafe impl SortKey for i32 {
+    const NAME: &'static str = "i32";
+}
+
+unsafe impl SortKey for f32 {
+    const NAME: &'static str = "f32";
+}
+
+/// Elements each block of the histogram and scatter kernels handles.
+const TILE: usize = 1024;
+
+/// Bits of the key sorted by each pass.
+const RADIX_BITS: u32 = 8;
+
+/// The key types, with the expression mapping the bits `k` of a key to an unsigned integer
+/// with the same order.
+const KEYS: [(&str, &str); 3] = [
+    ("u32", "k"),
+    ("i32", "k ^ 0x80000000u"),
+    // negative floats are reversed, so the order is the one of `f32::total_cmp`
+    ("f32", "(k & 0x80000000u) ? ~k : (k | 0x80000000u)"),
+];
+
+/// The value types the scatter kernels move along with the keys, by size. Size 0 sorts keys only.
+const VALUES: [(usize, &str); 5] = [
+    (0, "unsigned int"),
+    (1, "unsigned char"),
+    (2, "unsigned short"),
+    (4, "unsigned int"),
+    (8, "unsigned long long"),
+];
+
+const KERNEL_NAMES: &[&str] = &[
+    "radix_scan",
+    "radix_iota",
+    "radix_hist_u32",
+    "radix_hist_i32",
+    "radix_hist_f32",
+    "radix_scatter_u32_0",
+    "radix_scatter_u32_1",
+    "radix_scatter_u32_2",
+    "radix_scatter_u32_4",
+    "radix_scatter_u32_8",
+    "radix_scatter_i32_0",
+    "radix_scatter_i32_1",
+    "radix_scatter_i32_2",
+    "radix_scatter_i32_4",
+    "radix_scatter_i32_8",
+    "radix_scatter_f32_0",
+    "radix_scatter_f32_1",
+    "radix_scatter_f32_2",
+    "radix_scatter_f32_4",
+    "radix_scatter_f32_8",
+];
+
+/// The source of the sort kernels, an LSD radix sort. Each pass counts the digits of every
+/// tile, scans the counts in digit-major order to get where each tile's digits start, and
+/// moves the keys there. Keys are ranked within a tile with warp ballots, so the sort is
+/// stable.
+fn sort_src() -> String {
+    let mut src = std::format!(
+        "#define TILE {TILE}
+extern \"C\" __global__ void radix_scan(unsigned int *data, const size_t n) {{
+    __shared__ unsigned int s[1024];
+    __shared__ unsigned int carry;
+    if (threadIdx.x == 0) carry = 0;
+    for (size_t base = 0; base < n; base += 1024) {{
+        const size_t i = base + threadIdx.x;
+        const unsigned int v = i < n ? data[i] : 0;
+        s[threadIdx.x] = v;
+        __syncthreads();
+        for (unsigned int off = 1; off < 1024; off <<= 1) {{
+            const unsigned int t = threadIdx.x >= off ? s[threadIdx.x - off] : 0;
+            __syncthreads();
+            s[threadIdx.x] += t;
+            __syncthreads();
+        }}
+        if (i < n) data[i] = carry + s[threadIdx.x] - v;
+        __syncthreads();
+        if (threadIdx.x == 1023) carry += s[1023];
+        __syncthreads();
+    }}
+}}
+extern \"C\" __global__ void radix_iota(unsigned int *out, const size_t n) {{
+    for (size_t i = blockIdx.x * blockDim.x + threadIdx.x; i < n; i += (size_t)blockDim.x * gridDim.x) {{
+        out[i] = (unsigned int)i;
+    }}
+}}
+"
+    );
+    for (key, order) in KEYS {
+        src.push_str(&std::format!(
+            "__device__ __forceinline__ unsigned int radix_digit_{key}(const unsigned int k, const unsigned int shift) {{
+    return (({order}) >> shift) & 255u;
+}}
+extern \"C\" __global__ void radix_hist_{key}(const unsigned int *keys, const size_t n, const unsigned int shift, unsigned int *hist) {{
+    __shared__ unsigned int counts[256];
+    counts[threadIdx.x] = 0;
+    __syncthreads();
+    const size_t start = (size_t)blockIdx.x * TILE;
+    for (unsigned int r = 0; r < TILE / 256; r++) {{
+        const size_t i = start + r * 256 + threadIdx.x;
+        if (i < n) atomicAdd(&counts[radix_digit_{key}(keys[i], shift)], 1u);
+    }}
+    __syncthreads();
+    hist[threadIdx.x * gridDim.x + blockIdx.x] = counts[threadIdx.x];
+}}
+"
+        ));
+        for (size, word) in VALUES {
+            let move_value = if size == 0 {
+                ""
+            } else {
+                "vals_out[pos] = vals_in[i];"
+            };
+            src.push_str(&std::format!(
+                "extern \"C\" __global__ void radix_scatter_{key}_{size}(const unsigned int *keys_in, unsigned int *keys_out, const {word} *vals_in, {word} *vals_out, const size_t n, const unsigned int shift, const unsigned int *offsets) {{
+    __shared__ unsigned int base[256];
+    __shared__ unsigned int warp_counts[8][256];
+    const unsigned int tid = threadIdx.x;
+    const unsigned int lane = tid & 31u;
+    const unsigned int warp = tid >> 5;
+    base[tid] = offsets[tid * gridDim.x + blockIdx.x];
+    const size_t start = (size_t)blockIdx.x * TILE;
+    for (unsigned int r = 0; r < TILE / 256; r++) {{
+        for (unsigned int w = 0; w < 8; w++) warp_counts[w][tid] = 0;
+        __syncthreads();
+        const size_t i = start + r * 256 + tid;
+        const bool valid = i < n;
+        const unsigned int k = valid ? keys_in[i] : 0u;
+        const unsigned int d = radix_digit_{key}(k, shift);
+        unsigned int mask = __ballot_sync(0xffffffffu, valid);
+        for (unsigned int b = 0; b < 8; b++) {{
+            const unsigned int bit = (d >> b) & 1u;
+            const unsigned int votes = __ballot_sync(0xffffffffu, bit);
+            mask &= bit ? votes : ~votes;
+        }}
+        const unsigned int rank = __popc(mask & ((1u << lane) - 1u));
+        if (valid && rank == 0) warp_counts[warp][d] = __popc(mask);
+        __syncthreads();
+        if (valid) {{
+            unsigned int pos = base[d] + rank;
+            for (unsigned int w = 0; w < warp; w++) pos += warp_counts[w][d];
+            keys_out[pos] = k;
+            {move_value}
+        }}
+        __syncthreads();
+        unsigned int total = 0;
+        for (unsigned int w = 0; w < 8; w++) total += warp_counts[w][tid];
+        base[tid] += total;
+    }}
+}}
+"
+            ));
+        }
+    }
+    src
+}
+
+impl CudaDevice {
+    /// Sorts `keys` in ascending order.
+    ///
+    /// `f32` keys are ordered like [f32::total_cmp()]: `-0.0` before `0.0`, NaNs with the
+    /// sign bit set before `-inf`, and the others, e.g. [f32::NAN], after `inf`.
+    ///
+    /// The sort is a radix sort over 4 passes on the device's stream, with kernels compiled
+    /// with nvrtc on first use and loaded into a module named `cudarc_sort`. It takes a
+    /// buffer as large as `keys` from [CudaDevice::scratch_arena()].
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] for more than [u32::MAX] keys.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let mut keys = dev.htod_sync_copy(&[3.0f32, -1.0, 2.0]).unwrap();
+    /// dev.sort(&mut keys).unwrap();
+    /// assert_eq!(dev.dtoh_sync_copy(&keys).unwrap(), [-1.0, 2.0, 3.0]);
+    /// ```
+    pub fn sort<K: SortKey, Keys: DevicePtrMut<K>>(
+        self: &Arc<Self>,
+        keys: &mut Keys,
+    ) -> Result<(), result::DriverError> {
+        let n = keys.len();
+        unsafe { self.radix_sort::<K>(*keys.device_ptr_mut(), None, n) }
+    }
+
+    /// Sorts `keys` like [CudaDevice::sort()], moving `values[i]` along with `keys[i]`.
+    /// Equal keys keep the order of their values.
+    ///
+    /// `V` is moved bitwise and can be any type of 1, 2, 4 or 8 bytes.
+    ///
+    /// Returns [sys::CUresult::CUDA_ERROR_INVALID_VALUE] if the lengths differ or `V` has
+    /// another size.
+    pub fn sort_pairs<K: SortKey, V: DeviceRepr, Keys: DevicePtrMut<K>, Values: DevicePtrMut<V>>(
+        self: &Arc<Self>,
+        keys: &mut Keys,
+        values: &mut Values,
+    ) -> Result<(), result::DriverError> {
+        let n = keys.len();
+        let size = std::mem::size_of::<V>();
+        if values.len() != n || size == 0 || !VALUES.iter().any(|&(s, _)| s == size) {
+            return Err(invalid_value());
+        }
+        let values = (*values.device_ptr_mut(), size);
+        unsafe { self.radix_sort::<K>(*keys.device_ptr_mut(), Some(values), n) }
+    }
+
+    /// The indices that sort `keys`, without modifying them: `keys[out[0]]` is the smallest
+    /// key. Equal keys are in the order of their indices. See [CudaDevice::sort()] for the
+    /// order of `f32` keys.
+    pub fn argsort<K: SortKey, Keys: DevicePtr<K>>(
+        self: &Arc<Self>,
+        keys: &Keys,
+    ) -> Result<CudaSlice<u32>, result::DriverError> {
+        let n = keys.len();
+        if u32::try_from(n).is_err() {
+            return Err(invalid_value());
+        }
+        let mut indices = unsafe { self.alloc::<u32>(n) }?;
+        if n == 0 {
+            return Ok(indices);
+        }
+        let sorted = self.scratch(n * std::mem::size_of::<K>())?;
+        let sorted = *sorted.device_ptr();
+        let num_bytes = n * std::mem::size_of::<K>();
+        unsafe { result::memcpy_dtod_async(sorted, *keys.device_ptr(), num_bytes, self.stream) }?;
+        let iota = self.sort_function("radix_iota")?;
+        unsafe {
+            iota.launch(
+                self.default_stream(),
+                LaunchConfig::for_num_elems(n.min(1 << 24) as u32),
+                (&mut indices, n),
+            )
+        }?;
+        let values = (*indices.device_ptr_mut(), std::mem::size_of::<u32>());
+        unsafe { self.radix_sort::<K>(sorted, Some(values), n) }?;
+        Ok(indices)
+    }
+
+    /// Sorts the `n` keys at `keys`, and the values of the given size at `values`.
+    ///
+    /// # Safety
+    /// `keys` and `values` must hold `n` elements.
+    unsafe fn radix_sort<K: SortKey>(
+        self: &Arc<Self>,
+        keys: sys::CUdeviceptr,
+        values: Option<(sys::CUdeviceptr, usize)>,
+        n: usize,
+    ) -> Result<(), result::DriverError> {
+        if u32::try_from(n).is_err() {
+            return Err(invalid_value());
+        }
+        if n <= 1 {
+            return Ok(());
+        }
+        let value_size = values.map_or(0, |(_, size)| size);
+        let hist_f = self.sort_function(&std::format!("radix_hist_{}", K::NAME))?;
+        let scan_f = self.sort_function("radix_scan")?;
+        let scatter_f =
+            self.sort_function(&std::format!("radix_scatter_{}_{value_size}", K::NAME))?;
+
+        let num_blocks = n.div_ceil(TILE);
+        let hist_len = num_blocks << RADIX_BITS;
+        let hist = self.scratch(hist_len * std::mem::size_of::<u32>())?;
+        let tmp_keys = self.scratch(n * std::mem::size_of::<u32>())?;
+        let tmp_values = match values {
+            Some(_) => Some(self.scratch(n * value_size)?),
+            None => None,
+        };
+
+        let hist = *hist.device_ptr();
+        let mut keys = [keys, *tmp_keys.device_ptr()];
+        let mut vals = [
+            values.map_or(0, |(ptr, _)| ptr),
+            tmp_values.as_ref().map_or(0, |buf| *buf.device_ptr()),
+        ];
+        let tiles = LaunchConfig {
+            grid_dim: (num_blocks as u32, 1, 1),
+            block_dim: (1 << RADIX_BITS, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        let scan = LaunchConfig {
+            grid_dim: (1, 1, 1),
+            block_dim: (1024, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        // an even number of passes, so the result ends up back in `keys`
+        for shift in (0..32).step_by(RADIX_BITS as usize) {
+            hist_f.clone().launch(
+                self.default_stream(),
+                tiles,
+                (keys[0], n, shift as u32, hist),
+            )?;
+            scan_f
+                .clone()
+                .launch(self.default_stream(), scan, (hist, hist_len))?;
+            scatter_f.clone().launch(
+                self.default_stream(),
+                tiles,
+                (keys[0], keys[1], vals[0], vals[1], n, shift as u32, hist),
+            )?;
+            keys.swap(0, 1);
+            vals.swap(0, 1);
+        }
+        Ok(())
+    }
+
+    fn sort_function(self: &Arc<Self>, name: &str) -> Result<CudaFunction, result::DriverError> {
+        self.load_builtin("cudarc_sort", sort_src, KERNEL_NAMES, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// xorshift64, so the tests don't need a rng dependency.
+    fn random(n: usize, mut state: u64) -> Vec<u32> {
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kernel_names() {
+        let src = sort_src();
+        for name in KERNEL_NAMES {
+            assert!(src.contains(&std::format!("void {name}(")), "{name}");
+        }
+        assert_eq!(KERNEL_NAMES.len(), 2 + KEYS.len() * (1 + VALUES.len()));
+    }
+
+    #[test]
+    fn test_sort_u32_i32() {
+        let dev = CudaDevice::new(0).unwrap();
+        for n in [0, 1, 2, 1000, 1025, 3 * TILE + 17, 3_000_017] {
+            let mut host = random(n, n as u64 + 1);
+            // few distinct digits in some passes
+            host.iter_mut().step_by(3).for_each(|k| *k &= 0xff00_00ff);
+            let mut keys = dev.htod_sync_copy(&host).unwrap();
+            dev.sort(&mut keys).unwrap();
+            host.sort();
+            assert_eq!(dev.dtoh_sync_copy(&keys).unwrap(), host, "n = {n}");
+
+            let mut host: Vec<i32> = random(n, n as u64 + 2)
+                .into_iter()
+                .map(|k| k as i32)
+                .collect();
+            let mut keys = dev.htod_sync_copy(&host).unwrap();
+            dev.sort(&mut keys).unwrap();
+            host.sort();
+            assert_eq!(dev.dtoh_sync_copy(&keys).unwrap(), host, "n = {n}");
+        }
+    }
+
+    #[test]
+    fn test_sort_f32_total_order() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut host: Vec<f32> = random(100_003, 7)
+            .into_iter()
+            .map(|k| (k as i32) as f32 / 1e6)
+            .collect();
+        host.extend([
+            f32::NAN,
+            -f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            0.0,
+            -0.0,
+            f32::MIN_POSITIVE / 2.0,
+        ]);
+        let mut keys = dev.htod_sync_copy(&host).unwrap();
+        dev.sort(&mut keys).unwrap();
+        host.sort_by(f32::total_cmp);
+        let sorted = dev.dtoh_sync_copy(&keys).unwrap();
+        let bits = |v: &[f32]| v.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&sorted), bits(&host));
+        assert!(sorted[0].is_nan() && sorted.last().unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_argsort_and_pairs_are_stable() {
+        let dev = CudaDevice::new(0).unwrap();
+        let n = 2_000_003;
+        // many duplicates, so stability matters
+        let host: Vec<u32> = random(n, 11).into_iter().map(|k| k % 1000).collect();
+        let keys = dev.htod_sync_copy(&host).unwrap();
+        let indices = dev.argsort(&keys).unwrap();
+        let mut expected: Vec<u32> = (0..n as u32).collect();
+        expected.sort_by_key(|&i| host[i as usize]);
+        assert_eq!(dev.dtoh_sync_copy(&indices).unwrap(), expected);
+        // the keys are untouched
+        assert_eq!(dev.dtoh_sync_copy(&keys).unwrap(), host);
+
+        let mut keys = dev.htod_sync_copy(&host).unwrap();
+        let values: Vec<f64> = (0..n).map(|i| i as f64).collect();
+        let mut values = dev.htod_sync_copy(&values).unwrap();
+        dev.sort_pairs(&mut keys, &mut values).unwrap();
+        let values = dev.dtoh_sync_copy(&values).unwrap();
+        let expected: Vec<f64> = expected.iter().map(|&i| i as f64).collect();
+        assert_eq!(values, expected);
+
+        let mut short = dev.alloc_zeros::<u16>(n - 1).unwrap();
+        assert!(dev.sort_pairs(&mut keys, &mut short).is_err());
+    }
+}
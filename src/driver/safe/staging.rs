@@ -0,0 +1,275 @@
+use crate::driver::{result, sys};
+
+use super::alloc::StagingBuffer;
+use super::core::CudaDevice;
+use super::perf_hints::is_pageable;
+
+use core::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec::Vec;
+
+#[cfg(feature = "no-std")]
+use spin::RwLock;
+#[cfg(not(feature = "no-std"))]
+use std::sync::RwLock;
+
+/// The default of [CudaDevice::set_staging_pool()].
+pub(crate) const DEFAULT_STAGING_POOL_BYTES: usize = 64 << 20;
+
+/// Size of each page-locked buffer of the staging pool. Each copy uses two, so that
+/// copying between a buffer and pageable memory overlaps with the copy of the other.
+const STAGING_CHUNK_BYTES: usize = 8 << 20;
+
+/// Copies of pageable memory smaller than this are left to the driver, which stages
+/// them itself about as fast.
+const STAGING_MIN_BYTES: usize = 1 << 20;
+
+/// The page-locked buffers behind [CudaDevice::set_staging_pool()].
+#[derive(Debug)]
+pub(crate) struct StagingPool {
+    capacity: AtomicUsize,
+    /// Buffers not used by a copy, least recently used first.
+    idle: RwLock<Vec<StagingBuffer>>,
+}
+
+impl Default for StagingPool {
+    fn default() -> Self {
+        Self {
+            capacity: AtomicUsize::new(DEFAULT_STAGING_POOL_BYTES),
+            idle: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl StagingPool {
+    /// Frees the idle buffers, which needs the context of the device to be bound.
+    pub(crate) fn clear(&mut self) {
+        let idle = RwLock::get_mut(&mut self.idle);
+        #[cfg(not(feature = "no-std"))]
+        let idle = idle.unwrap();
+        idle.clear();
+    }
+
+    /// Forgets the idle buffers without freeing them, after the reset of their context
+    /// freed them.
+    pub(crate) fn forget(&mut self) {
+        let idle = RwLock::get_mut(&mut self.idle);
+        #[cfg(not(feature = "no-std"))]
+        let idle = idle.unwrap();
+        core::mem::forget(core::mem::take(idle));
+    }
+
+    /// Frees least recently used buffers until at most `capacity` bytes are left.
+    fn trim(&self, capacity: usize) {
+        let evicted = {
+            #[allow(unused_mut)]
+            let mut idle = self.idle.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut idle = idle.unwrap();
+            let mut kept = idle.len() * STAGING_CHUNK_BYTES;
+            let mut num_evicted = 0;
+            while kept > capacity {
+                kept -= STAGING_CHUNK_BYTES;
+                num_evicted += 1;
+            }
+            idle.drain(..num_evicted).collect::<Vec<_>>()
+        };
+        // freed without holding the lock, since it waits for their last copies
+        drop(evicted);
+    }
+}
+
+impl CudaDevice {
+    /// Sets how many bytes of page-locked staging buffers the device keeps around, 64MB by
+    /// default. 0 disables staging, and frees the buffers.
+    ///
+    /// [CudaDevice::htod_sync_copy()], [CudaDevice::htod_copy()], [CudaDevice::dtoh_sync_copy()]
+    /// and their `_into` variants copy pageable host memory of 1MB or more through two of
+    /// these buffers in 8MB chunks, so that copying a chunk between pageable memory and one
+    /// buffer overlaps with the copy of the other buffer to or from the device. Copies to
+    /// or from page-locked memory, e.g. a [super::PinnedBuffer], are done directly.
+    ///
+    /// Each copy in flight uses 16MB, so the default is enough for 4 threads copying at once.
+    /// Buffers beyond the pool size are allocated for the copy and freed after it, or if the
+    /// pool is that small, the copy is left to the driver.
+    pub fn set_staging_pool(&self, num_bytes: usize) {
+        self.staging_pool
+            .capacity
+            .store(num_bytes, Ordering::Relaxed);
+        if let Ok(_ctx) = self.bind_scoped() {
+            self.staging_pool.trim(num_bytes);
+        }
+    }
+
+    /// The size set by [CudaDevice::set_staging_pool()].
+    pub fn staging_pool(&self) -> usize {
+        self.staging_pool.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of page-locked buffers currently idle in the pool.
+    #[cfg(test)]
+    pub(crate) fn staging_pool_resident(&self) -> usize {
+        #[allow(unused_mut)]
+        let idle = self.staging_pool.idle.read();
+        #[cfg(not(feature = "no-std"))]
+        let idle = idle.unwrap();
+        idle.len() * STAGING_CHUNK_BYTES
+    }
+
+    /// Two buffers for a staged copy of `num_bytes` from or to `host`, or `None` if the
+    /// copy should be done directly.
+    fn take_staging(&self, host: *const c_void, num_bytes: usize) -> Option<[StagingBuffer; 2]> {
+        if num_bytes < STAGING_MIN_BYTES
+            || self.staging_pool() < 2 * STAGING_CHUNK_BYTES
+            || !is_pageable(host)
+        {
+            return None;
+        }
+        let (a, b) = {
+            #[allow(unused_mut)]
+            let mut idle = self.staging_pool.idle.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut idle = idle.unwrap();
+            (idle.pop(), idle.pop())
+        };
+        // if page-locked memory runs out, the driver can still copy directly
+        let a = a.or_else(|| StagingBuffer::new(STAGING_CHUNK_BYTES).ok())?;
+        let b = b.or_else(|| StagingBuffer::new(STAGING_CHUNK_BYTES).ok())?;
+        Some([a, b])
+    }
+
+    /// Returns buffers of a staged copy, whose last copies may still be in flight.
+    fn return_staging(&self, buffers: [StagingBuffer; 2]) {
+        {
+            #[allow(unused_mut)]
+            let mut idle = self.staging_pool.idle.write();
+            #[cfg(not(feature = "no-std"))]
+            let mut idle = idle.unwrap();
+            idle.extend(buffers);
+        }
+        self.staging_pool.trim(self.staging_pool());
+    }
+
+    /// Copies `num_bytes` from pageable `src` to `dst` on the device's stream through the
+    /// staging pool. Returns `false` without copying if the copy should be done directly.
+    /// The copy has been enqueued, but not necessarily completed, when this returns.
+    ///
+    /// # Safety
+    /// `src` must hold `num_bytes`, and `dst` must be device memory of `num_bytes`. The
+    /// context must be bound.
+    pub(crate) unsafe fn staged_htod(
+        &self,
+        dst: sys::CUdeviceptr,
+        src: *const u8,
+        num_bytes: usize,
+    ) -> Result<bool, result::DriverError> {
+        let Some(buffers) = self.take_staging(src as *const c_void, num_bytes) else {
+            return Ok(false);
+        };
+        let res = (|| {
+            for (i, offset) in (0..num_bytes).step_by(STAGING_CHUNK_BYTES).enumerate() {
+                let len = STAGING_CHUNK_BYTES.min(num_bytes - offset);
+                let buf = &buffers[i % 2];
+                // the copy of two chunks ago out of this buffer
+                result::event::synchronize(buf.event)?;
+                core::ptr::copy_nonoverlapping(src.add(offset), buf.ptr as *mut u8, len);
+                let host = std::slice::from_raw_parts(buf.ptr as *const u8, len);
+                result::memcpy_htod_async(dst + offset as u64, host, self.stream)?;
+                result::event::record(buf.event, self.stream)?;
+            }
+            Ok(())
+        })();
+        self.return_staging(buffers);
+        res.map(|_| true)
+    }
+
+    /// Copies `num_bytes` from `src` on the device to pageable `dst` through the staging
+    /// pool, see [CudaDevice::staged_htod()]. The copy has completed when this returns `true`.
+    ///
+    /// # Safety
+    /// `dst` must hold `num_bytes`, and `src` must be device memory of `num_bytes`. The
+    /// context must be bound.
+    pub(crate) unsafe fn staged_dtoh(
+        &self,
+        dst: *mut u8,
+        src: sys::CUdeviceptr,
+        num_bytes: usize,
+    ) -> Result<bool, result::DriverError> {
+        let Some(buffers) = self.take_staging(dst as *const c_void, num_bytes) else {
+            return Ok(false);
+        };
+        // drains the chunk at `offset` from the buffer it was copied into
+        let drain = |buf: &StagingBuffer, offset: usize| {
+            let len = STAGING_CHUNK_BYTES.min(num_bytes - offset);
+            result::event::synchronize(buf.event)?;
+            core::ptr::copy_nonoverlapping(buf.ptr as *const u8, dst.add(offset), len);
+            Ok::<_, result::DriverError>(())
+        };
+        let res = (|| {
+            let mut previous = None;
+            for (i, offset) in (0..num_bytes).step_by(STAGING_CHUNK_BYTES).enumerate() {
+                let len = STAGING_CHUNK_BYTES.min(num_bytes - offset);
+                let buf = &buffers[i % 2];
+                let host = std::slice::from_raw_parts_mut(buf.ptr as *mut u8, len);
+                result::memcpy_dtoh_async(host, src + offset as u64, self.stream)?;
+                result::event::record(buf.event, self.stream)?;
+                if let Some((j, previous)) = previous.replace((i, offset)) {
+                    drain(&buffers[j % 2], previous)?;
+                }
+            }
+            if let Some((j, previous)) = previous {
+                drain(&buffers[j % 2], previous)?;
+            }
+            Ok(())
+        })();
+        self.return_staging(buffers);
+        res.map(|_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::DeviceSlice;
+    use std::vec;
+
+    #[test]
+    fn test_staged_copies_round_trip() {
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(dev.staging_pool(), DEFAULT_STAGING_POOL_BYTES);
+        // not a multiple of the chunk size, so the last chunk is partial
+        let len = (2 * STAGING_CHUNK_BYTES + 12_345) / 4;
+        let host: Vec<u32> = (0..len as u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+
+        let a = dev.htod_sync_copy(&host).unwrap();
+        assert_eq!(dev.staging_pool_resident(), 2 * STAGING_CHUNK_BYTES);
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), host);
+        let b = dev.htod_copy(host.clone()).unwrap();
+        let mut out = vec![0u32; len];
+        dev.dtoh_sync_copy_into(&b, &mut out).unwrap();
+        assert_eq!(out, host);
+        assert_eq!(dev.staging_pool_resident(), 2 * STAGING_CHUNK_BYTES);
+
+        dev.set_staging_pool(0);
+        assert_eq!(dev.staging_pool_resident(), 0);
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), host);
+        assert_eq!(dev.staging_pool_resident(), 0);
+    }
+
+    #[test]
+    fn test_small_and_pinned_copies_are_direct() {
+        let dev = CudaDevice::new(0).unwrap();
+        let small = dev.htod_sync_copy(&[1.0f32; 1024]).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&small).unwrap(), [1.0; 1024]);
+
+        let len = STAGING_MIN_BYTES;
+        let mut pinned = dev.alloc_pinned::<u8>(len).unwrap();
+        pinned.as_mut_slice().fill(7);
+        let a = dev.htod_sync_copy(pinned.as_slice()).unwrap();
+        dev.dtoh_sync_copy_into(&a, pinned.as_mut_slice()).unwrap();
+        assert_eq!(a.len(), len);
+        assert_eq!(dev.staging_pool_resident(), 0);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaSlice, CudaStream};
+use super::device_ptr::DeviceSlice;
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The event recorded after the last write to a [CudaSlice] while stream tracking was
+/// enabled, and the stream it was recorded on. See [CudaDevice::set_stream_tracking()].
+///
+/// Returned by [DeviceSlice::last_write()] and [super::DeviceRepr::tracked_access()], so
+/// wrappers around a [CudaSlice] can forward them.
+#[derive(Debug, Default)]
+pub struct LastWrite {
+    event: AtomicPtr<sys::CUevent_st>,
+    stream: AtomicPtr<sys::CUstream_st>,
+}
+
+/// The [super::DeviceRepr::tracked_access()] of each param of a launch, if stream tracking
+/// is enabled.
+pub(crate) type TrackedParams<'a> = Option<&'a [Option<(&'a LastWrite, bool)>]>;
+
+impl LastWrite {
+    /// Makes `stream` wait for the last write, unless it was on `stream` already.
+    pub(crate) fn wait(&self, stream: sys::CUstream) -> Result<(), DriverError> {
+        let event = self.event.load(Ordering::Acquire);
+        if event.is_null() || self.stream.load(Ordering::Acquire) == stream {
+            return Ok(());
+        }
+        unsafe {
+            result::stream::wait_event(
+                stream,
+                event,
+                sys::CUevent_wait_flags::CU_EVENT_WAIT_DEFAULT,
+            )
+        }
+    }
+
+    /// Records a write enqueued on `stream`, reusing the event of the previous one.
+    pub(crate) fn record(&self, stream: sys::CUstream) -> Result<(), DriverError> {
+        let mut event = self.event.load(Ordering::Acquire);
+        if event.is_null() {
+            event = result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?;
+            self.event.store(event, Ordering::Release);
+        }
+        unsafe { result::event::record(event, stream) }?;
+        self.stream.store(stream, Ordering::Release);
+        Ok(())
+    }
+
+    /// Orders `stream` after the last write and destroys the event, before the memory is freed
+    /// on `stream`.
+    pub(crate) fn release(&mut self, stream: sys::CUstream, context: &'static str) {
+        self.wait(stream).or_handle(context);
+        let event = core::mem::replace(self.event.get_mut(), core::ptr::null_mut());
+        if !event.is_null() {
+            unsafe { result::event::destroy(event) }.or_handle(context);
+        }
+    }
+
+    fn event(&self) -> Option<sys::CUevent> {
+        let event = self.event.load(Ordering::Acquire);
+        (!event.is_null()).then_some(event)
+    }
+}
+
+impl CudaDevice {
+    /// Enables or disables stream tracking, which is off by default.
+    ///
+    /// While enabled, every write to a [CudaSlice] by a kernel launch (through `&mut CudaSlice`)
+    /// or by one of the copy and memset methods of [CudaDevice] records an event in the slice.
+    /// Any later read or write of the slice enqueued on a different stream waits for that
+    /// event with `cuStreamWaitEvent` first, so a buffer written on one stream and read on
+    /// another doesn't race. Dropping the slice waits for it too.
+    ///
+    /// Only writes after writes and reads after writes are ordered, a write on one stream
+    /// can still race with an earlier read on another. [super::CudaView]s are not tracked,
+    /// use [CudaSlice::make_available_on()] for them.
+    ///
+    /// Costs an atomic load per launch and copy while disabled, and an event record per
+    /// write while enabled.
+    pub fn set_stream_tracking(&self, enabled: bool) {
+        self.stream_tracking.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether [CudaDevice::set_stream_tracking()] is enabled.
+    pub fn stream_tracking(&self) -> bool {
+        self.stream_tracking.load(Ordering::Relaxed)
+    }
+
+    /// Makes `stream` wait for the last tracked write to `slice`, before it is read or
+    /// written on `stream`. Does nothing if tracking is disabled.
+    pub(crate) fn wait_for_write<T>(
+        &self,
+        stream: sys::CUstream,
+        slice: &impl DeviceSlice<T>,
+    ) -> Result<(), DriverError> {
+        match slice.last_write() {
+            Some(last_write) if self.stream_tracking() => last_write.wait(stream),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a write to `slice` just enqueued on `stream`. Does nothing if tracking is
+    /// disabled.
+    pub(crate) fn record_write<T>(
+        &self,
+        stream: sys::CUstream,
+        slice: &impl DeviceSlice<T>,
+    ) -> Result<(), DriverError> {
+        match slice.last_write() {
+            Some(last_write) if self.stream_tracking() => last_write.record(stream),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs `launch` on `stream` after waiting for the last writes to the `tracked` params,
+    /// then records a write to the ones it may write. The context of `stream` must already be
+    /// bound to the calling thread.
+    pub(crate) fn track_params(
+        &self,
+        stream: sys::CUstream,
+        tracked: TrackedParams,
+        launch: impl FnOnce() -> Result<(), DriverError>,
+    ) -> Result<(), DriverError> {
+        let Some(tracked) = tracked else {
+            return launch();
+        };
+        for (last_write, _) in tracked.iter().flatten() {
+            last_write.wait(stream)?;
+        }
+        launch()?;
+        for (last_write, _) in tracked.iter().flatten().filter(|(_, write)| *write) {
+            last_write.record(stream)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> CudaSlice<T> {
+    /// The event recorded after the last write to this slice while
+    /// [CudaDevice::set_stream_tracking()] was enabled. The event is owned by the slice.
+    pub fn last_write_event(&self) -> Option<sys::CUevent> {
+        self.last_write.event()
+    }
+
+    /// Makes `stream` wait for the last tracked write to this slice. Use it before reading
+    /// [super::CudaView]s of the slice, or memory the crate doesn't know is the slice, on
+    /// `stream`.
+    pub fn make_available_on(&self, stream: &CudaStream) -> Result<(), DriverError> {
+        let _ctx = stream.bind_scoped()?;
+        self.last_write.wait(stream.stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+
+    const SRC: &str = "
+extern \"C\" __global__ void slow_fill(float *x, float value, int n) {
+    long long start = clock64();
+    while (clock64() - start < 100000000) {}
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) { x[i] = value; }
+}
+
+extern \"C\" __global__ void copy(const float *src, float *dst, int n) {
+    int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) { dst[i] = src[i]; }
+}
+";
+
+    #[test]
+    fn test_stream_tracking_orders_read_after_write() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(
+            compile_ptx(SRC).unwrap(),
+            "tracking",
+            &["slow_fill", "copy"],
+        )
+        .unwrap();
+        let fill = dev.get_func("tracking", "slow_fill").unwrap();
+        let copy = dev.get_func("tracking", "copy").unwrap();
+        let n = 256;
+        let cfg = LaunchConfig::for_num_elems(n as u32);
+        let writer = dev.fork_default_stream().unwrap();
+        let reader = dev.fork_default_stream().unwrap();
+
+        dev.set_stream_tracking(true);
+        assert!(dev.stream_tracking());
+        let mut a = dev.alloc_zeros::<f32>(n).unwrap();
+        let mut b = dev.alloc_zeros::<f32>(n).unwrap();
+        dev.synchronize().unwrap();
+        assert!(a.last_write_event().is_some());
+
+        // without tracking the copy on `reader` would run while `slow_fill` spins, and see
+        // the zeros
+        unsafe {
+            fill.clone()
+                .launch(&writer, cfg, (&mut a, 1.0f32, n as i32))
+        }
+        .unwrap();
+        unsafe { copy.clone().launch(&reader, cfg, (&a, &mut b, n as i32)) }.unwrap();
+        // reading `b` on the default stream waits for the copy
+        assert_eq!(dev.dtoh_sync_copy(&b).unwrap(), std::vec![1.0; n]);
+
+        // manual control, with tracking off for the launches
+        unsafe {
+            fill.clone()
+                .launch(&writer, cfg, (&mut a, 2.0f32, n as i32))
+        }
+        .unwrap();
+        dev.set_stream_tracking(false);
+        a.make_available_on(&reader).unwrap();
+        unsafe { copy.launch(&reader, cfg, (&a, &mut b, n as i32)) }.unwrap();
+        dev.wait_for(&reader).unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&b).unwrap(), std::vec![2.0; n]);
+    }
+
+    #[test]
+    fn test_stream_tracking_off_by_default() {
+        let dev = CudaDevice::new(0).unwrap();
+        assert!(!dev.stream_tracking());
+        let a = dev.alloc_zeros::<f32>(4).unwrap();
+        assert_eq!(a.last_write_event(), None);
+    }
+}
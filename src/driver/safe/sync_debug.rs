@@ -0,0 +1,125 @@
+use super::core::CudaDevice;
+
+use std::panic::Location;
+use std::sync::atomic::Ordering;
+
+/// What [CudaDevice] does when the crate performs an implicit synchronization,
+/// see [CudaDevice::set_sync_debug()].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncDebug {
+    /// Implicit synchronizations are not reported.
+    #[default]
+    Off,
+    /// Emits a `tracing` warning with target `cudarc::sync` if the `tracing` feature is enabled,
+    /// and otherwise prints to stderr. Does nothing with neither `tracing` nor `std`.
+    Warn,
+    /// Panics at the implicit synchronization.
+    Panic,
+}
+
+impl SyncDebug {
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            SyncDebug::Off => 0,
+            SyncDebug::Warn => 1,
+            SyncDebug::Panic => 2,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => SyncDebug::Warn,
+            2 => SyncDebug::Panic,
+            _ => SyncDebug::Off,
+        }
+    }
+}
+
+impl CudaDevice {
+    /// Reports every synchronization the crate performs implicitly on this device, so that
+    /// hidden syncs that break up overlap between the host and the device can be found.
+    ///
+    /// These are:
+    /// 1. Dropping a [super::CudaSlice] if the device doesn't support async allocations, which
+    ///    blocks until all work on the device has completed. The location reported is where
+    ///    the slice was allocated.
+    /// 2. [CudaDevice::htod_copy()] and [CudaDevice::htod_copy_into()], which copy from
+    ///    pageable memory and so block until the copy has completed.
+    /// 3. [CudaDevice::load_ptx()] and [CudaDevice::load_ptx_all()], which synchronize the context.
+    ///
+    /// Methods named `*_sync*` and [CudaDevice::synchronize()] are explicit, and are not reported.
+    pub fn set_sync_debug(&self, mode: SyncDebug) {
+        self.sync_debug.store(mode.to_u8(), Ordering::Relaxed);
+    }
+
+    /// The current [SyncDebug] mode, [SyncDebug::Off] by default.
+    pub fn sync_debug(&self) -> SyncDebug {
+        SyncDebug::from_u8(self.sync_debug.load(Ordering::Relaxed))
+    }
+
+    /// Reports an implicit synchronization done by `op`, called from `location`.
+    pub(crate) fn implicit_sync(&self, op: &'static str, location: &'static Location<'static>) {
+        match self.sync_debug() {
+            SyncDebug::Off => (),
+            SyncDebug::Warn => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    target: "cudarc::sync",
+                    op,
+                    location = %location,
+                    ordinal = self.ordinal(),
+                    "implicit synchronize"
+                );
+                #[cfg(all(not(feature = "tracing"), feature = "std"))]
+                std::eprintln!(
+                    "cudarc: implicit synchronize on device {} in {op} at {location}",
+                    self.ordinal()
+                );
+            }
+            SyncDebug::Panic => {
+                // e.g. a slice dropped while unwinding, panicking again would abort
+                #[cfg(feature = "std")]
+                if std::thread::panicking() {
+                    return;
+                }
+                panic!(
+                    "implicit synchronize on device {} in {op} at {location}",
+                    self.ordinal()
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+
+    #[test]
+    fn test_sync_debug_mode_round_trip() {
+        for mode in [SyncDebug::Off, SyncDebug::Warn, SyncDebug::Panic] {
+            assert_eq!(SyncDebug::from_u8(mode.to_u8()), mode);
+        }
+    }
+
+    #[test]
+    fn test_sync_debug_panics_at_caller() {
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(dev.sync_debug(), SyncDebug::Off);
+        dev.set_sync_debug(SyncDebug::Panic);
+        let line = line!() + 2;
+        let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = dev.htod_copy(vec![1.0f32; 16]);
+        }))
+        .unwrap_err();
+        let msg = err.downcast_ref::<std::string::String>().unwrap();
+        assert!(msg.contains("htod_copy"), "{msg}");
+        assert!(msg.contains(&std::format!("{}:{line}:", file!())), "{msg}");
+
+        dev.set_sync_debug(SyncDebug::Warn);
+        let a = dev.htod_copy(vec![1.0f32; 16]).unwrap();
+        dev.set_sync_debug(SyncDebug::Off);
+        drop(a);
+    }
+}
@@ -0,0 +1,441 @@
+use crate::driver::{result, sys};
+
+use super::core::{CudaDevice, CudaStream};
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use std::{boxed::Box, string::String, sync::Arc, vec::Vec};
+
+/// Errors of [TaskGraph::execute()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskGraphError {
+    /// The dependencies have a cycle. Holds the names of the tasks that are on a cycle or
+    /// depend on one, in the order they were added. Nothing was run.
+    Cycle(Vec<String>),
+    /// Creating streams or events, or one of the tasks, failed. Tasks after the failed one
+    /// in the schedule were not run.
+    Driver(DriverError),
+}
+
+impl From<DriverError> for TaskGraphError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for TaskGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TaskGraphError {}
+
+/// A task added to a [TaskGraph].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// The position of the task in the order it was added, which is also its position in
+    /// the result of [TaskGraph::execute()].
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// How a task of a [TaskGraph] was run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskRun {
+    /// Which of the streams of [TaskGraph::execute()] the task was enqueued on.
+    pub stream: usize,
+    /// With [TaskGraph::with_timing()], the milliseconds from the start of the graph to when
+    /// the task started and ended on the device.
+    pub start_ms: Option<f32>,
+    pub end_ms: Option<f32>,
+}
+
+impl TaskRun {
+    /// The device time the task took, with [TaskGraph::with_timing()].
+    pub fn elapsed_ms(&self) -> Option<f32> {
+        Some(self.end_ms? - self.start_ms?)
+    }
+}
+
+type TaskFn<'a> = Box<dyn FnOnce(&CudaStream) -> Result<(), DriverError> + 'a>;
+
+struct Task<'a> {
+    name: String,
+    deps: Vec<usize>,
+    run: TaskFn<'a>,
+}
+
+/// Host side tasks with dependencies between them, which [TaskGraph::execute()] spreads
+/// over a number of streams.
+///
+/// Each task is a closure that enqueues work on the [CudaStream] it is given, e.g. with
+/// [CudaDevice::htod_copy_on_stream()] or [super::LaunchAsync::launch()]. A task
+/// starts on the device only after all work its dependencies enqueued has completed, while
+/// independent tasks may overlap on different streams. The closures run one at a time, so
+/// a buffer one task writes and another reads can be shared through a `RefCell`:
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let x = std::cell::RefCell::new(dev.alloc_zeros::<f32>(1024).unwrap());
+/// let mut graph = TaskGraph::new();
+/// let init = graph.add("init", &[], |s| dev.memset_zeros_on_stream(&mut *x.borrow_mut(), s));
+/// let left = graph.add("left", &[init], |s| Ok(()));
+/// let right = graph.add("right", &[init], |s| Ok(()));
+/// graph.add("join", &[left, right], |s| Ok(()));
+/// let runs = graph.with_timing(true).execute(&dev, 2).unwrap();
+/// println!("left took {:?}ms", runs[left.index()].elapsed_ms());
+/// ```
+///
+/// Unlike a [super::CudaGraph], the tasks run on the host once, when the graph is executed,
+/// so they can borrow buffers and use any api that takes a stream.
+pub struct TaskGraph<'a> {
+    tasks: Vec<Task<'a>>,
+    timing: bool,
+}
+
+impl core::fmt::Debug for TaskGraph<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TaskGraph")
+            .field(
+                "tasks",
+                &self
+                    .tasks
+                    .iter()
+                    .map(|t| (&t.name, &t.deps))
+                    .collect::<Vec<_>>(),
+            )
+            .field("timing", &self.timing)
+            .finish()
+    }
+}
+
+impl Default for TaskGraph<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> TaskGraph<'a> {
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            timing: false,
+        }
+    }
+
+    /// Adds a task that runs after `deps`. More dependencies, including on tasks added
+    /// later, can be added with [TaskGraph::add_dependency()].
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        deps: &[TaskId],
+        run: impl FnOnce(&CudaStream) -> Result<(), DriverError> + 'a,
+    ) -> TaskId {
+        let id = TaskId(self.tasks.len());
+        self.tasks.push(Task {
+            name: name.into(),
+            deps: Vec::new(),
+            run: Box::new(run),
+        });
+        for &dep in deps {
+            self.add_dependency(id, dep);
+        }
+        id
+    }
+
+    /// Makes `task` run after `dep`.
+    ///
+    /// # Panics
+    /// If either isn't a task of this graph.
+    pub fn add_dependency(&mut self, task: TaskId, dep: TaskId) {
+        assert!(dep.0 < self.tasks.len(), "{dep:?} is not in the graph");
+        let deps = &mut self.tasks[task.0].deps;
+        if !deps.contains(&dep.0) {
+            deps.push(dep.0);
+        }
+    }
+
+    /// Records events around each task, so that [TaskGraph::execute()] returns when each
+    /// started and ended. Executing then waits for the tasks to complete.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// The number of tasks added.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Runs the tasks in an order that respects their dependencies, spread over
+    /// `num_streams` streams forked from the device's stream. A task continues on the stream
+    /// of a dependency if it was the last task there, and otherwise goes to the next stream
+    /// in turn, which waits on an event for each dependency on another stream.
+    ///
+    /// The streams start after the work already on the device's stream, which in turn waits
+    /// for all tasks when this returns. Without [TaskGraph::with_timing()] this doesn't
+    /// wait for the tasks to complete.
+    ///
+    /// Returns how each task was run, indexed by [TaskId::index()]. Fails with
+    /// [TaskGraphError::Cycle] if the dependencies have a cycle, before running any task.
+    pub fn execute(
+        self,
+        device: &Arc<CudaDevice>,
+        num_streams: usize,
+    ) -> Result<Vec<TaskRun>, TaskGraphError> {
+        let order = self.schedule()?;
+        if num_streams == 0 {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE).into());
+        }
+        let timing = self.timing;
+        let _ctx = device.bind_scoped()?;
+        let mut events = Events(Vec::new());
+        let origin = if timing {
+            let origin = events.create(true)?;
+            // recorded before forking, so that it precedes every task
+            unsafe { result::event::record(origin, device.stream) }?;
+            Some(origin)
+        } else {
+            None
+        };
+        let streams = (0..num_streams)
+            .map(|_| device.fork_default_stream())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut tasks: Vec<Option<Task<'a>>> = self.tasks.into_iter().map(Some).collect();
+        let mut stream_of = std::vec![0; tasks.len()];
+        let mut starts = std::vec![None; tasks.len()];
+        let mut ends = std::vec![core::ptr::null_mut(); tasks.len()];
+        let mut last_on_stream = std::vec![None; num_streams];
+        let mut next_stream = 0;
+        for &t in order.iter() {
+            let task = tasks[t].take().unwrap();
+            let continued = task
+                .deps
+                .iter()
+                .find(|&&d| last_on_stream[stream_of[d]] == Some(d))
+                .map(|&d| stream_of[d]);
+            let s = continued.unwrap_or_else(|| {
+                next_stream += 1;
+                (next_stream - 1) % num_streams
+            });
+            stream_of[t] = s;
+            last_on_stream[s] = Some(t);
+            let stream = &streams[s];
+
+            for &d in task.deps.iter().filter(|&&d| stream_of[d] != s) {
+                unsafe {
+                    result::stream::wait_event(
+                        stream.stream,
+                        ends[d],
+                        sys::CUevent_wait_flags::CU_EVENT_WAIT_DEFAULT,
+                    )
+                }?;
+            }
+            if timing {
+                let start = events.create(true)?;
+                unsafe { result::event::record(start, stream.stream) }?;
+                starts[t] = Some(start);
+            }
+            (task.run)(stream)?;
+            // the task may have bound another context
+            let _ctx = device.bind_scoped()?;
+            let end = events.create(timing)?;
+            unsafe { result::event::record(end, stream.stream) }?;
+            ends[t] = end;
+        }
+
+        let mut runs = Vec::with_capacity(stream_of.len());
+        for (t, &stream) in stream_of.iter().enumerate() {
+            let (start_ms, end_ms) = match (origin, starts[t]) {
+                (Some(origin), Some(start)) => unsafe {
+                    result::event::synchronize(ends[t])?;
+                    (
+                        Some(result::event::elapsed(origin, start)?),
+                        Some(result::event::elapsed(origin, ends[t])?),
+                    )
+                },
+                _ => (None, None),
+            };
+            runs.push(TaskRun {
+                stream,
+                start_ms,
+                end_ms,
+            });
+        }
+        Ok(runs)
+    }
+
+    /// The order to run the tasks in, earlier added tasks first among those whose
+    /// dependencies have run.
+    fn schedule(&self) -> Result<Vec<usize>, TaskGraphError> {
+        let mut num_deps: Vec<usize> = self.tasks.iter().map(|t| t.deps.len()).collect();
+        let mut dependents = std::vec![Vec::new(); self.tasks.len()];
+        for (t, task) in self.tasks.iter().enumerate() {
+            for &d in task.deps.iter() {
+                dependents[d].push(t);
+            }
+        }
+        let mut ready: std::collections::BTreeSet<usize> = (0..self.tasks.len())
+            .filter(|&t| num_deps[t] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.tasks.len());
+        while let Some(t) = ready.pop_first() {
+            order.push(t);
+            for &dependent in dependents[t].iter() {
+                num_deps[dependent] -= 1;
+                if num_deps[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+        if order.len() < self.tasks.len() {
+            return Err(TaskGraphError::Cycle(
+                self.tasks
+                    .iter()
+                    .zip(num_deps)
+                    .filter(|(_, n)| *n > 0)
+                    .map(|(t, _)| t.name.clone())
+                    .collect(),
+            ));
+        }
+        Ok(order)
+    }
+}
+
+/// Events created by [TaskGraph::execute()], destroyed once it returns. Work waiting on
+/// them keeps waiting, since destroying doesn't affect waits already enqueued.
+struct Events(Vec<sys::CUevent>);
+
+impl Events {
+    fn create(&mut self, timing: bool) -> Result<sys::CUevent, DriverError> {
+        let flags = if timing {
+            sys::CUevent_flags::CU_EVENT_DEFAULT
+        } else {
+            sys::CUevent_flags::CU_EVENT_DISABLE_TIMING
+        };
+        let event = result::event::create(flags)?;
+        self.0.push(event);
+        Ok(event)
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        for &event in self.0.iter() {
+            unsafe { result::event::destroy(event) }.or_handle("TaskGraph::execute");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+    use crate::nvrtc::compile_ptx;
+    use core::cell::RefCell;
+
+    fn noop(_: &CudaStream) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_order() {
+        let mut graph = TaskGraph::new();
+        let d = graph.add("d", &[], noop);
+        let a = graph.add("a", &[], noop);
+        let b = graph.add("b", &[a], noop);
+        graph.add_dependency(d, b);
+        graph.add("c", &[a, d], noop);
+        assert_eq!(graph.schedule().unwrap(), [1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn test_cycle_rejected() {
+        let mut graph = TaskGraph::new();
+        let a = graph.add("a", &[], noop);
+        let b = graph.add("b", &[a], noop);
+        let c = graph.add("c", &[b], noop);
+        graph.add("d", &[c], noop);
+        graph.add("e", &[], noop);
+        graph.add_dependency(a, c);
+        assert_eq!(
+            graph.schedule(),
+            Err(TaskGraphError::Cycle(
+                ["a", "b", "c", "d"].map(Into::into).into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_execute_rejects_cycle() {
+        let mut graph = TaskGraph::new();
+        let a = graph.add("a", &[], noop);
+        graph.add_dependency(a, a);
+        let dev = CudaDevice::new(0).unwrap();
+        assert_eq!(
+            graph.execute(&dev, 2),
+            Err(TaskGraphError::Cycle(std::vec!["a".into()]))
+        );
+    }
+
+    const SPIN_CU: &str = "
+extern \"C\" __global__ void spin(const int *in, int *out, int add, long long cycles) {
+    long long start = clock64();
+    while (clock64() - start < cycles) { }
+    out[0] = in[0] + add;
+}";
+
+    #[test]
+    fn test_diamond() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(compile_ptx(SPIN_CU).unwrap(), "task_graph", &["spin"])
+            .unwrap();
+        let spin = dev.get_func("task_graph", "spin").unwrap();
+        let cfg = LaunchConfig::for_num_elems(1);
+        let cycles = 20_000_000i64;
+
+        let mut pinned = dev.alloc_pinned::<i32>(1).unwrap();
+        pinned.as_mut_slice()[0] = 5;
+        let [x, left, right, out] =
+            [(); 4].map(|_| RefCell::new(dev.alloc_zeros::<i32>(1).unwrap()));
+        let run = |s: &CudaStream, src: &RefCell<_>, dst: &RefCell<_>, add: i32, cycles: i64| unsafe {
+            spin.clone().launch(
+                s,
+                cfg,
+                (&*src.borrow(), &mut *dst.borrow_mut(), add, cycles),
+            )
+        };
+        let mut graph = TaskGraph::new();
+        let a = graph.add("upload", &[], |s| unsafe {
+            dev.htod_copy_on_stream(&pinned, &mut *x.borrow_mut(), s)
+        });
+        let b = graph.add("left", &[a], |s| run(s, &x, &left, 1, cycles));
+        let c = graph.add("right", &[a], |s| run(s, &x, &right, 2, cycles));
+        let d = graph.add("join", &[b, c], |s| {
+            run(s, &left, &out, 0, 0)?;
+            run(s, &right, &left, 0, 0)
+        });
+        let runs = graph.with_timing(true).execute(&dev, 2).unwrap();
+
+        assert_eq!(dev.dtoh_sync_copy(&*out.borrow()).unwrap(), [6]);
+        assert_eq!(dev.dtoh_sync_copy(&*left.borrow()).unwrap(), [7]);
+        let [a, b, c, d] = [a, b, c, d].map(|t| runs[t.index()]);
+        assert_eq!((a.stream, b.stream, c.stream, d.stream), (0, 0, 1, 0));
+        assert!(b.start_ms >= a.end_ms && c.start_ms >= a.end_ms, "{runs:?}");
+        assert!(d.start_ms >= b.end_ms && d.start_ms >= c.end_ms, "{runs:?}");
+        // the branches overlap
+        assert!(b.start_ms < c.end_ms && c.start_ms < b.end_ms, "{runs:?}");
+    }
+}
@@ -0,0 +1,268 @@
+use crate::driver::sys;
+#[cfg(feature = "std")]
+use crate::nvrtc::PtxCache;
+use crate::nvrtc::{compile_key, compile_ptx_with_opts, CompileError, CompileOptions};
+
+use super::core::{CudaDevice, CudaFunction};
+use super::DriverError;
+
+use std::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+/// Error from [KernelTemplate].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A `${NAME}` placeholder in the source was not [KernelTemplate::set()].
+    UnsetPlaceholder(String),
+    /// A key was [KernelTemplate::set()] that doesn't appear in the source.
+    UnknownKey(String),
+    /// A `${` at this byte offset is not followed by an identifier and `}`.
+    InvalidPlaceholder(usize),
+    /// The substituted source failed to compile.
+    Compile(CompileError),
+    /// Loading the compiled module failed.
+    Driver(DriverError),
+    /// [KernelTemplate::compile()] needs the source to contain exactly one kernel,
+    /// use [KernelTemplate::compile_func()] instead. Holds the kernels found.
+    AmbiguousKernel(Vec<String>),
+}
+
+impl From<CompileError> for TemplateError {
+    fn from(value: CompileError) -> Self {
+        Self::Compile(value)
+    }
+}
+
+impl From<DriverError> for TemplateError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TemplateError {}
+
+/// Kernel source with `${NAME}` placeholders, which are substituted before compiling with
+/// nvrtc. This makes variants of a kernel that only differ by a constant (e.g. a block size
+/// or element type) without formatting strings by hand.
+///
+/// Every placeholder must be set, and every key set must appear in the source. Compiled
+/// variants are loaded into the device under a name derived from their source and options,
+/// so compiling the same variant again just returns the loaded function.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let f = KernelTemplate::new(
+///     "extern \"C\" __global__ void fill(${T} *x) { x[threadIdx.x] = ${VALUE}; }",
+/// )
+/// .set("T", "float")
+/// .set("VALUE", 2.5)
+/// .compile(&dev, Default::default())
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct KernelTemplate {
+    src: String,
+    values: BTreeMap<String, String>,
+    #[cfg(feature = "std")]
+    cache: Option<PtxCache>,
+}
+
+impl KernelTemplate {
+    pub fn new<S: Into<String>>(src: S) -> Self {
+        Self {
+            src: src.into(),
+            values: BTreeMap::new(),
+            #[cfg(feature = "std")]
+            cache: None,
+        }
+    }
+
+    /// Substitutes `value` for every `${name}` in the source.
+    pub fn set<V: core::fmt::Display>(mut self, name: &str, value: V) -> Self {
+        self.values.insert(name.into(), std::format!("{value}"));
+        self
+    }
+
+    /// Stores the compiled ptx in `cache`, so variants aren't recompiled on the next run.
+    #[cfg(feature = "std")]
+    pub fn with_ptx_cache(mut self, cache: PtxCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The source with all placeholders substituted.
+    pub fn render(&self) -> Result<String, TemplateError> {
+        let mut out = String::with_capacity(self.src.len());
+        let mut used = Vec::new();
+        let mut rest = self.src.as_str();
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            let offset = self.src.len() - rest.len() + start;
+            let after = &rest[start + 2..];
+            let name = after
+                .find('}')
+                .map(|end| &after[..end])
+                .filter(|name| is_identifier(name))
+                .ok_or(TemplateError::InvalidPlaceholder(offset))?;
+            let value = self
+                .values
+                .get(name)
+                .ok_or_else(|| TemplateError::UnsetPlaceholder(name.into()))?;
+            out.push_str(value);
+            used.push(name);
+            rest = &after[name.len() + 1..];
+        }
+        out.push_str(rest);
+        if let Some(key) = self.values.keys().find(|key| !used.contains(&key.as_str())) {
+            return Err(TemplateError::UnknownKey(key.clone()));
+        }
+        Ok(out)
+    }
+
+    /// Compiles the variant and returns its only kernel, see [TemplateError::AmbiguousKernel].
+    pub fn compile(
+        &self,
+        device: &Arc<CudaDevice>,
+        opts: CompileOptions,
+    ) -> Result<CudaFunction, TemplateError> {
+        let module_name = self.load(device, opts)?;
+        let mut names: Vec<String> = device
+            .module_functions(&module_name)?
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        if names.len() != 1 {
+            return Err(TemplateError::AmbiguousKernel(names));
+        }
+        let name = names.pop().unwrap();
+        Ok(device.get_func(&module_name, &name).unwrap())
+    }
+
+    /// Compiles the variant and returns its kernel named `func_name`.
+    pub fn compile_func(
+        &self,
+        device: &Arc<CudaDevice>,
+        func_name: &str,
+        opts: CompileOptions,
+    ) -> Result<CudaFunction, TemplateError> {
+        let module_name = self.load(device, opts)?;
+        device
+            .get_func(&module_name, func_name)
+            .ok_or(TemplateError::Driver(DriverError::Cuda(
+                sys::CUresult::CUDA_ERROR_NOT_FOUND,
+            )))
+    }
+
+    /// Loads the variant into `device` unless it already is, returning its module name.
+    fn load(
+        &self,
+        device: &Arc<CudaDevice>,
+        opts: CompileOptions,
+    ) -> Result<String, TemplateError> {
+        let src = self.render()?;
+        let module_name = std::format!("template_{:016x}", compile_key(&src, &opts));
+        if device.module_functions(&module_name).is_ok() {
+            return Ok(module_name);
+        }
+        #[cfg(feature = "std")]
+        let ptx = match &self.cache {
+            Some(cache) => cache.compile(src, opts)?,
+            None => compile_ptx_with_opts(src, opts)?,
+        };
+        #[cfg(not(feature = "std"))]
+        let ptx = compile_ptx_with_opts(src, opts)?;
+        device.load_ptx_all(ptx, &module_name)?;
+        Ok(module_name)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{LaunchAsync, LaunchConfig};
+
+    const SRC: &str = "extern \"C\" __global__ void scale(${T} *x, size_t n) {
+    size_t i = blockIdx.x * ${BLOCK} + threadIdx.x;
+    if (i < n) { x[i] *= (${T})${FACTOR}; }
+}";
+
+    #[test]
+    fn test_render() {
+        let t = KernelTemplate::new(SRC)
+            .set("T", "float")
+            .set("BLOCK", 128)
+            .set("FACTOR", 3);
+        let src = t.render().unwrap();
+        assert!(src.contains("void scale(float *x"));
+        assert!(src.contains("blockIdx.x * 128 + threadIdx.x"));
+        assert!(src.contains("x[i] *= (float)3;"));
+        assert!(!src.contains("${"));
+
+        let unset = KernelTemplate::new(SRC).set("T", "float").set("BLOCK", 128);
+        assert_eq!(
+            unset.render(),
+            Err(TemplateError::UnsetPlaceholder("FACTOR".into()))
+        );
+        assert_eq!(
+            t.clone().set("UNROLL", 4).render(),
+            Err(TemplateError::UnknownKey("UNROLL".into()))
+        );
+        assert_eq!(
+            KernelTemplate::new("int x = ${1};").render(),
+            Err(TemplateError::InvalidPlaceholder(8))
+        );
+        assert_eq!(
+            KernelTemplate::new("int x = ${X;").render(),
+            Err(TemplateError::InvalidPlaceholder(8))
+        );
+    }
+
+    #[test]
+    fn test_compile_variants() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut x = dev.htod_sync_copy(&[1.0f32; 300]).unwrap();
+        for (block, factor) in [(128u32, 2), (256, 3), (128, 2)] {
+            let f = KernelTemplate::new(SRC)
+                .set("T", "float")
+                .set("BLOCK", block)
+                .set("FACTOR", factor)
+                .compile(&dev, Default::default())
+                .unwrap();
+            let cfg = LaunchConfig {
+                grid_dim: (300u32.div_ceil(block), 1, 1),
+                block_dim: (block, 1, 1),
+                shared_mem_bytes: 0,
+            };
+            unsafe { f.launch(dev.default_stream(), cfg, (&mut x, 300usize)) }.unwrap();
+        }
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), [12.0; 300]);
+
+        // the repeated variant was not loaded again
+        let modules = dev.modules.read();
+        #[cfg(not(feature = "no-std"))]
+        let modules = modules.unwrap();
+        assert_eq!(
+            modules
+                .keys()
+                .filter(|m| m.starts_with("template_"))
+                .count(),
+            2
+        );
+    }
+}
@@ -1,12 +1,97 @@
-use super::{CudaDevice, DriverError};
+use super::error_handler::handle_error;
+use super::{ContextKind, CudaDevice, DriverError};
 
-use crate::driver::result;
+use crate::driver::{result, sys};
+
+use core::marker::PhantomData;
+
+/// Keeps the context of a [CudaDevice] current for the rest of a cudarc call, see
+/// [CudaDevice::bind_scoped()]. Pops the context on drop if it was pushed.
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct CtxScope {
+    pushed: Option<sys::CUcontext>,
+    /// Contexts stacks are per thread.
+    not_send: PhantomData<*const ()>,
+}
+
+impl CtxScope {
+    /// A scope that leaves the thread's context stack alone.
+    pub(crate) fn unchanged() -> Self {
+        Self {
+            pushed: None,
+            not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for CtxScope {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.pushed {
+            let popped = result::ctx::pop_current();
+            debug_assert!(
+                popped.as_ref().map_or(true, |&popped| popped == ctx),
+                "the context pushed by a cudarc call was not on top when popping it"
+            );
+            if let Err(err) = popped {
+                handle_error(err, "CtxScope::drop");
+            }
+        }
+    }
+}
 
 impl CudaDevice {
     /// Binds the device to the calling thread. You must call this before
     /// using the device on a separate thread!
+    ///
+    /// For a context that isn't the primary context, see [CudaDevice::context_kind()], this
+    /// does nothing if the context is already current, e.g. because it was pushed with
+    /// [CudaDevice::push_context()].
+    ///
+    /// Fails with [DriverError::ContextPoisoned] once the device [CudaDevice::is_poisoned()].
     pub fn bind_to_thread(&self) -> Result<(), DriverError> {
-        unsafe { result::ctx::set_current(self.cu_primary_ctx) }
+        self.check_poisoned()?;
+        if self.ctx_kind != ContextKind::Primary && result::ctx::get_current()? == Some(self.cu_ctx)
+        {
+            // replacing it would pop a pushed context off the thread's stack
+            return Ok(());
+        }
+        unsafe { result::ctx::set_current(self.cu_ctx) }
+    }
+
+    /// Makes the device's context current for a cudarc call, until the returned scope is
+    /// dropped. The primary context is bound like [CudaDevice::bind_to_thread()] does.
+    /// Other contexts are pushed if another context is current, and popped again by the
+    /// scope, so that calls leave the caller's context current.
+    pub(crate) fn bind_scoped(&self) -> Result<CtxScope, DriverError> {
+        if self.ctx_kind == ContextKind::Primary {
+            self.bind_to_thread()?;
+            return Ok(CtxScope::unchanged());
+        }
+        self.check_poisoned()?;
+        match result::ctx::get_current()? {
+            Some(ctx) if ctx == self.cu_ctx => Ok(CtxScope::unchanged()),
+            Some(_) => {
+                unsafe { result::ctx::push_current(self.cu_ctx) }?;
+                Ok(CtxScope {
+                    pushed: Some(self.cu_ctx),
+                    not_send: PhantomData,
+                })
+            }
+            None => {
+                unsafe { result::ctx::set_current(self.cu_ctx) }?;
+                Ok(CtxScope::unchanged())
+            }
+        }
+    }
+
+    /// [CudaDevice::bind_scoped()] for `Drop` impls, which pass errors to the
+    /// [super::ErrorHandler].
+    pub(crate) fn bind_scoped_or_handle(&self, op: &'static str) -> CtxScope {
+        self.bind_scoped().unwrap_or_else(|err| {
+            handle_error(err, op);
+            CtxScope::unchanged()
+        })
     }
 }
 
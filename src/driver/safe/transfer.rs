@@ -0,0 +1,154 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::{CudaDevice, CudaSlice, CudaStream};
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use std::{sync::Arc, vec::Vec};
+
+impl CudaDevice {
+    /// Starts copying `src` to a new [CudaSlice] without copying it into a pinned buffer first.
+    ///
+    /// The memory of `src` is page-locked in place, and copied on a separate stream, so the host
+    /// can do other work while the upload is in flight. Call [PendingTransfer::wait()] to get the
+    /// [CudaSlice], which is ordered after the copy on the default stream.
+    ///
+    /// If `src` shares pages with memory that is already registered, the copy is done from
+    /// pageable memory instead, which may block until it has completed.
+    ///
+    /// ```rust
+    /// # use cudarc::driver::*;
+    /// let dev = CudaDevice::new(0).unwrap();
+    /// let pending = dev.htod_transfer(vec![1.0f32; 1 << 20]).unwrap();
+    /// // ... other host work ...
+    /// let a = pending.wait().unwrap();
+    /// ```
+    #[track_caller]
+    pub fn htod_transfer<T: DeviceRepr>(
+        self: &Arc<Self>,
+        src: Vec<T>,
+    ) -> Result<PendingTransfer<T>, DriverError> {
+        let dst = unsafe { self.alloc(src.len()) }?;
+        let stream = self.fork_default_stream()?;
+        let event = result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?;
+        let mut transfer = PendingTransfer {
+            dst: Some(dst),
+            src,
+            registered: false,
+            event,
+            stream: Some(stream),
+        };
+        let num_bytes = std::mem::size_of_val(transfer.src.as_slice());
+        if num_bytes > 0 {
+            let host_ptr = transfer.src.as_mut_ptr() as *mut std::ffi::c_void;
+            match unsafe { result::host_register(host_ptr, num_bytes, 0) } {
+                Ok(()) => transfer.registered = true,
+                Err(DriverError::Cuda(
+                    sys::CUresult::CUDA_ERROR_HOST_MEMORY_ALREADY_REGISTERED,
+                )) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        if !transfer.registered {
+            let host_ptr = transfer.src.as_ptr() as *const std::ffi::c_void;
+            self.check_pageable("htod_transfer", host_ptr, std::panic::Location::caller());
+        }
+        let stream = transfer.stream.as_ref().unwrap().stream;
+        let dst_ptr = transfer.dst.as_ref().unwrap().cu_device_ptr;
+        unsafe {
+            result::memcpy_htod_async(dst_ptr, &transfer.src, stream)?;
+            result::event::record(transfer.event, stream)?;
+        }
+        Ok(transfer)
+    }
+}
+
+/// An upload started by [CudaDevice::htod_transfer()].
+///
+/// Owns the source [Vec] until the copy has completed. Dropping it without calling
+/// [PendingTransfer::wait()] blocks until the copy has completed.
+#[derive(Debug)]
+pub struct PendingTransfer<T> {
+    dst: Option<CudaSlice<T>>,
+    src: Vec<T>,
+    registered: bool,
+    event: sys::CUevent,
+    stream: Option<CudaStream>,
+}
+
+unsafe impl<T: Send> Send for PendingTransfer<T> {}
+unsafe impl<T: Sync> Sync for PendingTransfer<T> {}
+
+impl<T> PendingTransfer<T> {
+    /// Whether the copy has completed, in which case [PendingTransfer::wait()] doesn't block.
+    pub fn is_complete(&self) -> Result<bool, DriverError> {
+        unsafe { result::event::query(self.event) }
+    }
+
+    /// Returns the [CudaSlice] if the copy has completed, and the transfer if it hasn't yet.
+    pub fn try_complete(self) -> Result<Result<CudaSlice<T>, Self>, DriverError> {
+        if self.is_complete()? {
+            self.wait().map(Ok)
+        } else {
+            Ok(Err(self))
+        }
+    }
+
+    /// Blocks until the copy has completed, and returns the [CudaSlice] after
+    /// unregistering and dropping the source [Vec].
+    pub fn wait(mut self) -> Result<CudaSlice<T>, DriverError> {
+        unsafe { result::event::synchronize(self.event) }?;
+        let dst = self.dst.take().unwrap();
+        // the default stream waits for the copy when the stream is dropped
+        drop(self);
+        Ok(dst)
+    }
+}
+
+impl<T> Drop for PendingTransfer<T> {
+    fn drop(&mut self) {
+        let _ctx = self
+            .stream
+            .as_ref()
+            .map(|stream| stream.bind_scoped_or_handle("PendingTransfer::drop"));
+        // the copy must complete before the memory is unregistered and freed
+        unsafe { result::event::synchronize(self.event) }.or_handle("PendingTransfer::drop");
+        if self.registered {
+            let host_ptr = self.src.as_mut_ptr() as *mut std::ffi::c_void;
+            unsafe { result::host_unregister(host_ptr) }.or_handle("PendingTransfer::drop");
+        }
+        unsafe { result::event::destroy(self.event) }.or_handle("PendingTransfer::drop");
+        self.stream.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_htod_transfer() {
+        let dev = CudaDevice::new(0).unwrap();
+        let src: Vec<u32> = (0..1 << 20).collect();
+        let pending = dev.htod_transfer(src.clone()).unwrap();
+        let a = pending.wait().unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&a).unwrap(), src);
+
+        let mut pending = dev.htod_transfer(src.clone()).unwrap();
+        let b = loop {
+            match pending.try_complete().unwrap() {
+                Ok(b) => break b,
+                Err(p) => pending = p,
+            }
+        };
+        assert_eq!(dev.dtoh_sync_copy(&b).unwrap(), src);
+
+        let empty = dev.htod_transfer(Vec::<f32>::new()).unwrap();
+        assert_eq!(empty.wait().unwrap().len, 0);
+
+        // dropped without waiting
+        drop(dev.htod_transfer(src).unwrap());
+        dev.synchronize().unwrap();
+    }
+}
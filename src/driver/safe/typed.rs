@@ -0,0 +1,326 @@
+use crate::driver::{result, sys};
+
+use super::alloc::DeviceRepr;
+use super::core::{CudaFunction, CudaSlice, CudaView, CudaViewMut, StreamRef};
+use super::launch::{LaunchAsync, LaunchConfig, LaunchConfigEx};
+use super::DriverError;
+
+use core::marker::PhantomData;
+use std::vec::Vec;
+
+/// The type a kernel declares one of its params as, the elements of the signature of a
+/// [TypedFunction]. Pointers stand for device memory, `*const T` for e.g. a `&CudaSlice<T>`
+/// and `*mut T` for a `&mut CudaSlice<T>`, see [KernelArg].
+///
+/// Implement it, along with `KernelArg<Self>`, for `#[repr(C)]` structs passed by value.
+///
+/// # Safety
+/// [KernelParam::SIZE] must be the size the kernel declares the param with.
+pub unsafe trait KernelParam: Sized {
+    const SIZE: usize = core::mem::size_of::<Self>();
+}
+
+/// A value that can be passed for a param declared as `P`.
+///
+/// # Safety
+/// [DeviceRepr::as_kernel_param()] must point to a value of the layout of `P`.
+pub unsafe trait KernelArg<P: KernelParam>: DeviceRepr {}
+
+/// A tuple of [KernelParam]s, the params of a [TypedFunction] in order.
+pub trait KernelSignature {
+    /// The size in bytes of each param.
+    fn param_sizes() -> Vec<usize>;
+}
+
+macro_rules! impl_scalar_param {
+    ($($T:ty),*) => {
+        $(
+            unsafe impl KernelParam for $T {}
+            unsafe impl KernelArg<$T> for $T {}
+        )*
+    };
+}
+
+impl_scalar_param!(bool, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+#[cfg(feature = "f16")]
+impl_scalar_param!(half::f16, half::bf16);
+
+unsafe impl<T> KernelParam for *const T {}
+unsafe impl<T> KernelParam for *mut T {}
+
+unsafe impl<T: DeviceRepr> KernelArg<*const T> for &CudaSlice<T> {}
+unsafe impl<T: DeviceRepr> KernelArg<*const T> for &mut CudaSlice<T> {}
+unsafe impl<T: DeviceRepr> KernelArg<*const T> for &CudaView<'_, T> {}
+unsafe impl<T: DeviceRepr> KernelArg<*const T> for &mut CudaViewMut<'_, T> {}
+unsafe impl<T: DeviceRepr> KernelArg<*mut T> for &mut CudaSlice<T> {}
+unsafe impl<T: DeviceRepr> KernelArg<*mut T> for &mut CudaViewMut<'_, T> {}
+// a raw `CUdeviceptr` can stand for either, like a pointer in the kernel's own code
+unsafe impl<T> KernelArg<*const T> for sys::CUdeviceptr {}
+unsafe impl<T> KernelArg<*mut T> for sys::CUdeviceptr {}
+
+/// A [CudaFunction] whose params have been checked against the signature `Sig`, see
+/// [CudaFunction::typed()].
+///
+/// It implements [LaunchAsync] for tuples of arguments of the same length as `Sig`, each of
+/// which implements [KernelArg] for its param, so passing the wrong number of arguments,
+/// or an argument of the wrong type, doesn't compile:
+///
+/// ```rust
+/// # use cudarc::{driver::*, nvrtc::*};
+/// let dev = CudaDevice::new(0).unwrap();
+/// let src = "extern \"C\" __global__ void scale(unsigned int n, const float *x, float *y) {
+///     unsigned int i = blockIdx.x * blockDim.x + threadIdx.x;
+///     if (i < n) { y[i] = 2.0f * x[i]; }
+/// }";
+/// dev.load_ptx(compile_ptx(src).unwrap(), "module", &["scale"]).unwrap();
+/// let f = dev
+///     .get_func("module", "scale")
+///     .unwrap()
+///     .typed::<(u32, *const f32, *mut f32)>()
+///     .unwrap();
+/// let x = dev.htod_copy(vec![1.0f32; 100]).unwrap();
+/// let mut y = dev.alloc_zeros::<f32>(100).unwrap();
+/// unsafe { f.launch(dev.default_stream(), LaunchConfig::for_num_elems(100), (100u32, &x, &mut y)) }.unwrap();
+/// ```
+///
+/// ```compile_fail
+/// # use cudarc::driver::*;
+/// # fn wrong_order(f: TypedFunction<(u32, *const f32, *mut f32)>, dev: &CudaDevice, x: &CudaSlice<f32>, y: &mut CudaSlice<f32>) {
+/// unsafe { f.launch(dev.default_stream(), LaunchConfig::for_num_elems(100), (100u32, y, x)) }.unwrap();
+/// # }
+/// ```
+///
+/// Launching is as unsafe as with [CudaFunction], the signature doesn't keep the kernel
+/// from accessing memory out of bounds.
+#[derive(Debug)]
+pub struct TypedFunction<Sig> {
+    func: CudaFunction,
+    signature: PhantomData<fn(Sig)>,
+}
+
+impl<Sig> Clone for TypedFunction<Sig> {
+    fn clone(&self) -> Self {
+        Self {
+            func: self.func.clone(),
+            signature: PhantomData,
+        }
+    }
+}
+
+impl<Sig> TypedFunction<Sig> {
+    pub fn function(&self) -> &CudaFunction {
+        &self.func
+    }
+
+    pub fn into_function(self) -> CudaFunction {
+        self.func
+    }
+}
+
+impl CudaFunction {
+    /// The size in bytes of each param the kernel declares, or `None` if it isn't known.
+    ///
+    /// Queried from the driver on cuda 12.4+, and otherwise parsed from the ptx source
+    /// when the module was loaded with [super::CudaDevice::load_ptx()] from ptx text.
+    pub fn param_sizes(&self) -> Result<Option<Vec<usize>>, DriverError> {
+        #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+        {
+            let mut sizes = Vec::new();
+            loop {
+                match unsafe { result::function::get_param_info(self.cu_function, sizes.len()) } {
+                    Ok((_, size)) => sizes.push(size),
+                    Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_INVALID_VALUE)) => {
+                        return Ok(Some(sizes))
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        #[allow(unreachable_code)]
+        Ok(self.param_sizes.as_deref().map(<[usize]>::to_vec))
+    }
+
+    /// Checks that the kernel declares as many params as `Sig`, with the same sizes, and
+    /// wraps it into a [TypedFunction] that can only be launched with arguments matching `Sig`.
+    ///
+    /// Fails with [result::InvalidLaunch::ParamCount] or [result::InvalidLaunch::ParamSize]
+    /// on a mismatch, and with [sys::CUresult::CUDA_ERROR_NOT_SUPPORTED] if
+    /// [CudaFunction::param_sizes()] aren't known, in which case
+    /// [CudaFunction::typed_unchecked()] can be used.
+    ///
+    /// Only sizes are checked, so e.g. a `float` param matches `u32` as well as `f32`.
+    pub fn typed<Sig: KernelSignature>(self) -> Result<TypedFunction<Sig>, DriverError> {
+        let _ctx = self.device.bind_scoped()?;
+        let expected = self
+            .param_sizes()?
+            .ok_or(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED))?;
+        let found = Sig::param_sizes();
+        if expected.len() != found.len() {
+            return Err(result::InvalidLaunch::ParamCount {
+                expected: expected.len(),
+                found: found.len(),
+            }
+            .into());
+        }
+        for (param, (&expected, &found)) in expected.iter().zip(found.iter()).enumerate() {
+            if expected != found {
+                return Err(result::InvalidLaunch::ParamSize {
+                    param,
+                    expected,
+                    found,
+                }
+                .into());
+            }
+        }
+        Ok(unsafe { self.typed_unchecked() })
+    }
+
+    /// Like [CudaFunction::typed()], without checking the signature.
+    ///
+    /// # Safety
+    /// The kernel must declare params matching `Sig`.
+    pub unsafe fn typed_unchecked<Sig: KernelSignature>(self) -> TypedFunction<Sig> {
+        TypedFunction {
+            func: self,
+            signature: PhantomData,
+        }
+    }
+}
+
+macro_rules! impl_typed_launch {
+    ($($P:ident: $A:ident),*) => {
+impl<$($P: KernelParam),*> KernelSignature for ($($P, )*) {
+    fn param_sizes() -> Vec<usize> {
+        std::vec![$($P::SIZE),*]
+    }
+}
+
+unsafe impl<$($P: KernelParam, $A: KernelArg<$P>),*> LaunchAsync<($($A, )*)>
+    for TypedFunction<($($P, )*)>
+{
+    #[inline(always)]
+    unsafe fn launch<'s>(
+        self,
+        stream: impl Into<StreamRef<'s>>,
+        cfg: LaunchConfig,
+        args: ($($A, )*),
+    ) -> Result<(), DriverError> {
+        self.func.launch(stream, cfg, args)
+    }
+
+    #[inline(always)]
+    unsafe fn launch_unchecked<'s>(
+        self,
+        stream: impl Into<StreamRef<'s>>,
+        cfg: LaunchConfig,
+        args: ($($A, )*),
+    ) -> Result<(), DriverError> {
+        self.func.launch_unchecked(stream, cfg, args)
+    }
+
+    #[inline(always)]
+    unsafe fn launch_cooperative<'s>(
+        self,
+        stream: impl Into<StreamRef<'s>>,
+        cfg: LaunchConfig,
+        args: ($($A, )*),
+    ) -> Result<(), DriverError> {
+        self.func.launch_cooperative(stream, cfg, args)
+    }
+
+    #[inline(always)]
+    unsafe fn launch_ex<'s>(
+        self,
+        stream: impl Into<StreamRef<'s>>,
+        cfg: LaunchConfigEx,
+        args: ($($A, )*),
+    ) -> Result<(), DriverError> {
+        self.func.launch_ex(stream, cfg, args)
+    }
+}
+    };
+}
+
+impl_typed_launch!(A: AA);
+impl_typed_launch!(A: AA, B: BA);
+impl_typed_launch!(A: AA, B: BA, C: CA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA, F: FA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA, F: FA, G: GA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA, F: FA, G: GA, H: HA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA, F: FA, G: GA, H: HA, I: IA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA, F: FA, G: GA, H: HA, I: IA, J: JA);
+impl_typed_launch!(A: AA, B: BA, C: CA, D: DA, E: EA, F: FA, G: GA, H: HA, I: IA, J: JA, K: KA);
+impl_typed_launch!(
+    A: AA, B: BA, C: CA, D: DA, E: EA, F: FA, G: GA, H: HA, I: IA, J: JA, K: KA, L: LA
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{result::InvalidLaunch, CudaDevice};
+    use crate::nvrtc::compile_ptx;
+    use std::vec;
+
+    const SCALE_CU: &str = "
+extern \"C\" __global__ void scale(unsigned int n, const float *x, float *y) {
+    unsigned int i = blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) {
+        y[i] = 2.0f * x[i];
+    }
+}";
+
+    #[test]
+    fn test_signature_sizes() {
+        assert_eq!(<(u32, *const f32, *mut f32)>::param_sizes(), [4, 8, 8]);
+        assert_eq!(<(u8, f64, i16)>::param_sizes(), [1, 8, 2]);
+    }
+
+    #[test]
+    fn test_typed_launch() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(compile_ptx(SCALE_CU).unwrap(), "typed", &["scale"])
+            .unwrap();
+        let f = dev.get_func("typed", "scale").unwrap();
+        assert_eq!(f.param_sizes().unwrap(), Some(vec![4, 8, 8]));
+
+        let f = f.typed::<(u32, *const f32, *mut f32)>().unwrap();
+        let x = dev.htod_copy(vec![1.5f32; 100]).unwrap();
+        let mut y = dev.alloc_zeros::<f32>(100).unwrap();
+        unsafe {
+            f.launch(
+                dev.default_stream(),
+                LaunchConfig::for_num_elems(100),
+                (100u32, &x, &mut y),
+            )
+        }
+        .unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&y).unwrap(), [3.0; 100]);
+    }
+
+    #[test]
+    fn test_typed_mismatch() {
+        let dev = CudaDevice::new(0).unwrap();
+        dev.load_ptx(compile_ptx(SCALE_CU).unwrap(), "typed", &["scale"])
+            .unwrap();
+        let f = dev.get_func("typed", "scale").unwrap();
+        assert_eq!(
+            f.clone()
+                .typed::<(u64, *const f32, *mut f32)>()
+                .unwrap_err(),
+            DriverError::InvalidLaunch(InvalidLaunch::ParamSize {
+                param: 0,
+                expected: 4,
+                found: 8,
+            })
+        );
+        assert_eq!(
+            f.typed::<(u32, *const f32)>().unwrap_err(),
+            DriverError::InvalidLaunch(InvalidLaunch::ParamCount {
+                expected: 3,
+                found: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use crate::driver::{result, sys};
+
+use super::alloc::{DeviceRepr, ValidAsZeroBits};
+use super::core::{CudaDevice, CudaSlice, CudaStream};
+use super::error_handler::OrHandle;
+use super::DriverError;
+
+use std::{collections::VecDeque, sync::Arc, vec::Vec};
+
+/// The most events a [CudaDevice] keeps around for [WorkToken]s.
+const EVENT_POOL_MAX: usize = 64;
+
+/// A point in the work of a stream, created by [CudaStream::token()] or
+/// [CudaDevice::token()]. Complete once all work enqueued on the stream before it has
+/// completed.
+///
+/// The event behind the token is reused by later tokens once it is dropped.
+#[derive(Debug)]
+pub struct WorkToken {
+    event: sys::CUevent,
+    device: Arc<CudaDevice>,
+    /// Whether `event` belongs to `device`'s primary context, and goes back to its pool.
+    pooled: bool,
+}
+
+unsafe impl Send for WorkToken {}
+unsafe impl Sync for WorkToken {}
+
+impl WorkToken {
+    /// Whether the work before the token has completed, without blocking.
+    pub fn is_complete(&self) -> Result<bool, DriverError> {
+        unsafe { result::event::query(self.event) }
+    }
+
+    /// Blocks until the work before the token has completed.
+    pub fn wait(&self) -> Result<(), DriverError> {
+        unsafe { result::event::synchronize(self.event) }
+    }
+
+    /// The event recorded for the token. It is owned by the token.
+    pub fn event(&self) -> sys::CUevent {
+        self.event
+    }
+}
+
+impl Drop for WorkToken {
+    fn drop(&mut self) {
+        let event = std::mem::replace(&mut self.event, std::ptr::null_mut());
+        if self.pooled {
+            self.device.return_event(event);
+        } else {
+            unsafe { result::event::destroy(event) }.or_handle("WorkToken::drop");
+        }
+    }
+}
+
+impl CudaDevice {
+    /// A [WorkToken] at the current point of the device's stream.
+    pub fn token(self: &Arc<Self>) -> Result<WorkToken, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        let token = WorkToken {
+            event: self.take_event()?,
+            device: self.clone(),
+            pooled: true,
+        };
+        unsafe { result::event::record(token.event, self.stream) }?;
+        Ok(token)
+    }
+
+    /// Takes an event from the pool, or creates one. The context must be bound.
+    pub(crate) fn take_event(&self) -> Result<sys::CUevent, DriverError> {
+        #[allow(unused_mut)]
+        let mut pool = self.event_pool.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut pool = pool.unwrap();
+        match pool.pop() {
+            Some(event) => Ok(event),
+            None => result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING),
+        }
+    }
+
+    pub(crate) fn return_event(&self, event: sys::CUevent) {
+        #[allow(unused_mut)]
+        let mut pool = self.event_pool.write();
+        #[cfg(not(feature = "no-std"))]
+        let mut pool = pool.unwrap();
+        if pool.len() < EVENT_POOL_MAX {
+            pool.push(event);
+            return;
+        }
+        drop(pool);
+        unsafe { result::event::destroy(event) }.or_handle("WorkToken::drop");
+    }
+
+    /// Allocates a [BufferRing] of `count` zeroed buffers of `len` elements.
+    pub fn alloc_ring<T: ValidAsZeroBits + DeviceRepr>(
+        self: &Arc<Self>,
+        count: usize,
+        len: usize,
+        policy: AcquirePolicy,
+    ) -> Result<BufferRing<T>, DriverError> {
+        let buffers = (0..count)
+            .map(|_| self.alloc_zeros(len))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(BufferRing::new(buffers, policy))
+    }
+}
+
+impl CudaStream {
+    /// A [WorkToken] at the current point of the stream.
+    pub fn token(&self) -> Result<WorkToken, DriverError> {
+        let _ctx = self.bind_scoped()?;
+        // events have to be created in the context of the stream
+        #[cfg(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060"))]
+        let pooled = self.green_ctx.is_none();
+        #[cfg(not(any(feature = "cuda-12040", feature = "cuda-12050", feature = "cuda-12060")))]
+        let pooled = true;
+        let token = WorkToken {
+            event: if pooled {
+                self.device.take_event()?
+            } else {
+                result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)?
+            },
+            device: self.device.clone(),
+            pooled,
+        };
+        unsafe { result::event::record(token.event, self.stream) }?;
+        Ok(token)
+    }
+}
+
+/// What [BufferRing::acquire()] does if no buffer is free yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquirePolicy {
+    /// Block until the buffer released first is free.
+    Block,
+    /// Return [sys::CUresult::CUDA_ERROR_NOT_READY].
+    Error,
+}
+
+/// A set of reusable device buffers, each handed out again once the work using it
+/// has completed.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let mut ring = dev.alloc_ring::<f32>(3, 1024, AcquirePolicy::Block).unwrap();
+/// for _ in 0..10 {
+///     let mut buf = ring.acquire().unwrap();
+///     dev.memset_zeros(&mut buf).unwrap();
+///     // ... launch kernels using `buf` on the device's stream ...
+///     ring.release(buf).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BufferRing<T> {
+    /// The released buffers in the order they were released, with the token they are free
+    /// after.
+    free: VecDeque<(CudaSlice<T>, Option<WorkToken>)>,
+    policy: AcquirePolicy,
+    /// The number of buffers acquired and not released yet.
+    acquired: usize,
+}
+
+impl<T> BufferRing<T> {
+    /// A ring of `buffers`, which are free immediately.
+    pub fn new(buffers: Vec<CudaSlice<T>>, policy: AcquirePolicy) -> Self {
+        Self {
+            free: buffers.into_iter().map(|buf| (buf, None)).collect(),
+            policy,
+            acquired: 0,
+        }
+    }
+
+    /// The number of buffers of the ring, the released ones plus the acquired ones that are
+    /// not released yet. An acquired buffer that is dropped instead of released stays
+    /// counted.
+    pub fn count(&self) -> usize {
+        self.free.len() + self.acquired
+    }
+
+    /// The number of buffers that are released, free or not.
+    pub fn released(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Takes a buffer whose work has completed, preferring the one released first.
+    ///
+    /// If none has, blocks on the one released first or returns
+    /// [sys::CUresult::CUDA_ERROR_NOT_READY], depending on the [AcquirePolicy]. Returns
+    /// [sys::CUresult::CUDA_ERROR_NOT_READY] with either policy if all buffers are
+    /// acquired.
+    pub fn acquire(&mut self) -> Result<CudaSlice<T>, DriverError> {
+        let not_ready = DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_READY);
+        let buf = match self.reap()? {
+            Some(i) => self.free.remove(i).unwrap().0,
+            None => match self.policy {
+                AcquirePolicy::Block => {
+                    let (buf, token) = self.free.pop_front().ok_or(not_ready)?;
+                    if let Some(Err(e)) = token.as_ref().map(WorkToken::wait) {
+                        self.free.push_front((buf, token));
+                        return Err(e);
+                    }
+                    buf
+                }
+                AcquirePolicy::Error => return Err(not_ready),
+            },
+        };
+        self.acquired += 1;
+        Ok(buf)
+    }
+
+    /// Drops the tokens of the released buffers whose work has completed, so that their
+    /// events are reused, and returns the index of the first free buffer.
+    fn reap(&mut self) -> Result<Option<usize>, DriverError> {
+        let mut first_free = None;
+        for (i, (_, token)) in self.free.iter_mut().enumerate() {
+            if let Some(t) = token {
+                if !t.is_complete()? {
+                    continue;
+                }
+                *token = None;
+            }
+            first_free.get_or_insert(i);
+        }
+        Ok(first_free)
+    }
+
+    /// Returns `buf` to the ring, free once the work enqueued on the device's stream so far
+    /// has completed.
+    pub fn release(&mut self, buf: CudaSlice<T>) -> Result<(), DriverError> {
+        let token = buf.device.token()?;
+        self.release_after(buf, token);
+        Ok(())
+    }
+
+    /// [BufferRing::release()] for a buffer used on `stream`.
+    pub fn release_on(
+        &mut self,
+        buf: CudaSlice<T>,
+        stream: &CudaStream,
+    ) -> Result<(), DriverError> {
+        let token = stream.token()?;
+        self.release_after(buf, token);
+        Ok(())
+    }
+
+    /// Returns `buf` to the ring, free once `token` is complete.
+    pub fn release_after(&mut self, buf: CudaSlice<T>, token: WorkToken) {
+        // the ring isn't acquired from while work is enqueued, so tokens would pile up
+        let _ = self.reap();
+        self.acquired = self.acquired.saturating_sub(1);
+        self.free.push_back((buf, Some(token)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::DevicePtr;
+
+    #[test]
+    fn test_token_reuses_events() {
+        let dev = CudaDevice::new(0).unwrap();
+        let token = dev.token().unwrap();
+        token.wait().unwrap();
+        assert!(token.is_complete().unwrap());
+        let event = token.event();
+        drop(token);
+        let stream = dev.fork_default_stream().unwrap();
+        let token = stream.token().unwrap();
+        assert_eq!(token.event(), event);
+        token.wait().unwrap();
+    }
+
+    #[test]
+    fn test_buffer_ring() {
+        let dev = CudaDevice::new(0).unwrap();
+        let mut ring = dev.alloc_ring::<f32>(2, 16, AcquirePolicy::Error).unwrap();
+        let a = ring.acquire().unwrap();
+        let b = ring.acquire().unwrap();
+        assert_eq!(ring.released(), 0);
+        assert_eq!(
+            ring.acquire().unwrap_err(),
+            DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_READY)
+        );
+
+        let a_ptr = *a.device_ptr();
+        ring.release(a).unwrap();
+        dev.synchronize().unwrap();
+        assert_eq!(*ring.acquire().unwrap().device_ptr(), a_ptr);
+        ring.release(b).unwrap();
+        assert_eq!(ring.count(), 2);
+        assert_eq!(ring.released(), 1);
+
+        // tokens of completed work are dropped, and their events reused
+        dev.synchronize().unwrap();
+        let c = ring.acquire().unwrap();
+        assert_eq!(ring.count(), 2);
+        ring.release(c).unwrap();
+        dev.synchronize().unwrap();
+        ring.release(dev.alloc_zeros(16).unwrap()).unwrap();
+        assert_eq!(ring.released(), 2);
+        assert_eq!(ring.free.iter().filter(|(_, t)| t.is_some()).count(), 1);
+    }
+}
@@ -11,6 +11,8 @@ bindgen \
   --allowlist-var="^CU.*" \
   --allowlist-function="^cu.*" \
   --default-enum-style=rust \
+  --newtype-enum="^cudaError_enum$" \
+  --no-debug="^cudaError_enum$" \
   --no-doc-comments \
   --with-derive-default \
   --with-derive-eq \
@@ -71,3 +71,18 @@ pub unsafe fn lib() -> &'static Lib {
         crate::panic_no_lib_found(lib_name, &choices);
     })
 }
+
+/// The library [lib()] loads its functions from, to look functions up by name.
+pub unsafe fn library() -> &'static libloading::Library {
+    static LIBRARY: std::sync::OnceLock<libloading::Library> = std::sync::OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let lib_name = "cuda";
+        let choices = [lib_name, "nvcuda"];
+        for choice in choices {
+            if let Ok(lib) = libloading::Library::new(libloading::library_filename(choice)) {
+                return lib;
+            }
+        }
+        crate::panic_no_lib_found(lib_name, &choices);
+    })
+}
@@ -0,0 +1,3 @@
+//! Loading model files straight into device memory.
+
+pub mod safetensors;
@@ -0,0 +1,746 @@
+//! Loads [safetensors](https://github.com/huggingface/safetensors) files into [CudaSlice]s.
+//!
+//! [load_safetensors()] uploads every tensor of a file, [SafeTensors] uploads them one at
+//! a time:
+//!
+//! ```rust,no_run
+//! # use cudarc::{driver::*, io::safetensors::*};
+//! let dev = CudaDevice::new(0).unwrap();
+//! let file = SafeTensors::read("model.safetensors").unwrap();
+//! let (weight, dtype, shape) = file.load_tensor(&dev, "lm_head.weight").unwrap();
+//! let bias: CudaSlice<f32> = file.load_typed(&dev, "lm_head.bias").unwrap();
+//! ```
+
+use crate::driver::{
+    result,
+    safe::error_handler::{log_library_error, OrHandle},
+    sys, CudaDevice, CudaSlice, DeviceRepr, DriverError,
+};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+
+/// The largest header accepted, the same limit as the reference implementation.
+const MAX_HEADER_LEN: usize = 100 << 20;
+
+/// The type of the elements of a tensor, named like in the header.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Dtype {
+    Bool,
+    U8,
+    I8,
+    F8E5M2,
+    F8E4M3,
+    I16,
+    U16,
+    F16,
+    BF16,
+    I32,
+    U32,
+    F32,
+    F64,
+    I64,
+    U64,
+}
+
+impl Dtype {
+    /// Parses the name of the dtype in the header, e.g. `"BF16"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "BOOL" => Self::Bool,
+            "U8" => Self::U8,
+            "I8" => Self::I8,
+            "F8_E5M2" => Self::F8E5M2,
+            "F8_E4M3" => Self::F8E4M3,
+            "I16" => Self::I16,
+            "U16" => Self::U16,
+            "F16" => Self::F16,
+            "BF16" => Self::BF16,
+            "I32" => Self::I32,
+            "U32" => Self::U32,
+            "F32" => Self::F32,
+            "F64" => Self::F64,
+            "I64" => Self::I64,
+            "U64" => Self::U64,
+            _ => return None,
+        })
+    }
+
+    /// The name of the dtype in the header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bool => "BOOL",
+            Self::U8 => "U8",
+            Self::I8 => "I8",
+            Self::F8E5M2 => "F8_E5M2",
+            Self::F8E4M3 => "F8_E4M3",
+            Self::I16 => "I16",
+            Self::U16 => "U16",
+            Self::F16 => "F16",
+            Self::BF16 => "BF16",
+            Self::I32 => "I32",
+            Self::U32 => "U32",
+            Self::F32 => "F32",
+            Self::F64 => "F64",
+            Self::I64 => "I64",
+            Self::U64 => "U64",
+        }
+    }
+
+    /// The bytes of one element.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Bool | Self::U8 | Self::I8 | Self::F8E5M2 | Self::F8E4M3 => 1,
+            Self::I16 | Self::U16 | Self::F16 | Self::BF16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 | Self::I64 | Self::U64 => 8,
+        }
+    }
+}
+
+/// A rust type with the layout of a [Dtype], for [SafeTensors::load_typed()].
+///
+/// `bool` isn't one, since any byte but 0 and 1 of a [Dtype::Bool] tensor would be an
+/// invalid `bool`. Those tensors are loaded as bytes with [SafeTensors::load_tensor()].
+///
+/// # Safety
+/// The type must have the size of [SafetensorsDtype::DTYPE], and any bit pattern of that
+/// dtype must be a valid value of it.
+pub unsafe trait SafetensorsDtype: DeviceRepr {
+    const DTYPE: Dtype;
+}
+
+unsafe impl SafetensorsDtype for u8 {
+    const DTYPE: Dtype = Dtype::U8;
+}
+unsafe impl SafetensorsDtype for i8 {
+    const DTYPE: Dtype = Dtype::I8;
+}
+unsafe impl SafetensorsDtype for i16 {
+    const DTYPE: Dtype = Dtype::I16;
+}
+unsafe impl SafetensorsDtype for u16 {
+    const DTYPE: Dtype = Dtype::U16;
+}
+#[cfg(feature = "f16")]
+unsafe impl SafetensorsDtype for half::f16 {
+    const DTYPE: Dtype = Dtype::F16;
+}
+#[cfg(feature = "f16")]
+unsafe impl SafetensorsDtype for half::bf16 {
+    const DTYPE: Dtype = Dtype::BF16;
+}
+unsafe impl SafetensorsDtype for i32 {
+    const DTYPE: Dtype = Dtype::I32;
+}
+unsafe impl SafetensorsDtype for u32 {
+    const DTYPE: Dtype = Dtype::U32;
+}
+unsafe impl SafetensorsDtype for f32 {
+    const DTYPE: Dtype = Dtype::F32;
+}
+unsafe impl SafetensorsDtype for f64 {
+    const DTYPE: Dtype = Dtype::F64;
+}
+unsafe impl SafetensorsDtype for i64 {
+    const DTYPE: Dtype = Dtype::I64;
+}
+unsafe impl SafetensorsDtype for u64 {
+    const DTYPE: Dtype = Dtype::U64;
+}
+
+/// An error of opening or loading a safetensors file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SafetensorsError {
+    /// Opening or mapping the file failed.
+    Io(std::io::ErrorKind),
+    /// The header isn't valid JSON, or isn't laid out like a safetensors header.
+    InvalidHeader(&'static str),
+    /// The tensor has a dtype this module doesn't know.
+    UnknownDtype {
+        name: String,
+        dtype: String,
+    },
+    /// The data offsets of the tensor don't fit its shape and dtype, or lie outside the file.
+    InvalidOffsets(String),
+    /// There is no tensor of this name in the file.
+    NotFound(String),
+    /// [SafeTensors::load_typed()] was called with a type of another dtype.
+    DtypeMismatch {
+        expected: Dtype,
+        found: Dtype,
+    },
+    Driver(DriverError),
+}
+
+impl From<DriverError> for SafetensorsError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+impl From<std::io::Error> for SafetensorsError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value.kind())
+    }
+}
+
+impl std::fmt::Display for SafetensorsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for SafetensorsError {}
+
+/// A tensor of a [SafeTensors] file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TensorInfo {
+    pub dtype: Dtype,
+    pub shape: Vec<usize>,
+    /// Where the bytes of the tensor start and end, relative to the end of the header.
+    pub data_offsets: (usize, usize),
+}
+
+/// Each tensor of a file by name, as a [CudaSlice] of its bytes with its dtype and shape.
+pub type LoadedTensors = HashMap<String, (CudaSlice<u8>, Dtype, Vec<usize>)>;
+
+/// Loads every tensor of the safetensors file at `path` into a [CudaSlice] of its bytes,
+/// with its dtype and shape. See [SafeTensors::load_all()].
+///
+/// The file is read with [SafeTensors::read()], and pinned with [SafeTensors::pin()] if the
+/// device supports it, so the copies run asynchronously. Otherwise they are copied from
+/// pageable memory.
+pub fn load_safetensors(
+    device: &Arc<CudaDevice>,
+    path: impl AsRef<Path>,
+) -> Result<LoadedTensors, SafetensorsError> {
+    let mut file = SafeTensors::read(path)?;
+    // pinning only makes the copies faster, so they work without it
+    let _ = file.pin(device);
+    file.load_all(device)
+}
+
+/// A safetensors file in host memory, whose tensors are uploaded on demand.
+#[derive(Debug)]
+pub struct SafeTensors {
+    mapping: Mapping,
+    /// Where the header ends and the data of the first tensor starts.
+    data_start: usize,
+    tensors: BTreeMap<String, TensorInfo>,
+    metadata: BTreeMap<String, String>,
+    /// The device whose context the memory was registered in by [SafeTensors::pin()].
+    pinned: Option<Arc<CudaDevice>>,
+}
+
+unsafe impl Send for SafeTensors {}
+unsafe impl Sync for SafeTensors {}
+
+impl SafeTensors {
+    /// Reads the file at `path` into memory, and parses its header.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SafetensorsError> {
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut std::fs::File::open(path)?, &mut bytes)?;
+        Self::new(Mapping::Read(bytes))
+    }
+
+    /// Maps the file at `path` into memory, and parses its header. Unlike
+    /// [SafeTensors::read()], its bytes are only read when a tensor is loaded.
+    ///
+    /// # Safety
+    /// The file must not be written to or truncated while the [SafeTensors] lives, in this
+    /// process or any other, since the mapping would change under the parsed header and the
+    /// `&[u8]` it is read through.
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, SafetensorsError> {
+        let file = std::fs::File::open(path)?;
+        Self::new(Mapping::new(&file)?)
+    }
+
+    fn new(mapping: Mapping) -> Result<Self, SafetensorsError> {
+        let (data_start, tensors, metadata) = parse_file(mapping.as_slice())?;
+        Ok(Self {
+            mapping,
+            data_start,
+            tensors,
+            metadata,
+            pinned: None,
+        })
+    }
+
+    /// Page-locks the file's memory with `cuMemHostRegister`, so that uploads from it are
+    /// asynchronous. For a file of [SafeTensors::open()], this reads all of it into memory.
+    ///
+    /// Fails if the device can't register read only memory, see
+    /// [sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_READ_ONLY_HOST_REGISTER_SUPPORTED].
+    /// Does nothing if the file is pinned already.
+    pub fn pin(&mut self, device: &Arc<CudaDevice>) -> Result<(), SafetensorsError> {
+        if self.pinned.is_some() {
+            return Ok(());
+        }
+        let attrib = sys::CUdevice_attribute::CU_DEVICE_ATTRIBUTE_READ_ONLY_HOST_REGISTER_SUPPORTED;
+        if device.attribute(attrib)? == 0 {
+            return Err(DriverError::Cuda(sys::CUresult::CUDA_ERROR_NOT_SUPPORTED).into());
+        }
+        let _ctx = device.bind_scoped()?;
+        let bytes = self.mapping.as_slice();
+        let flags = sys::CU_MEMHOSTREGISTER_PORTABLE | sys::CU_MEMHOSTREGISTER_READ_ONLY;
+        unsafe { result::host_register(bytes.as_ptr() as *mut _, bytes.len(), flags) }?;
+        self.pinned = Some(device.clone());
+        Ok(())
+    }
+
+    /// Whether [SafeTensors::pin()] succeeded.
+    pub fn is_pinned(&self) -> bool {
+        self.pinned.is_some()
+    }
+
+    /// The names of the tensors, in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tensors.keys().map(|name| name.as_str())
+    }
+
+    /// The tensor `name`, without loading it.
+    pub fn info(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensors.get(name)
+    }
+
+    /// The `__metadata__` of the header.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Loads the tensor `name` into a [CudaSlice] of its bytes, with its dtype and shape.
+    pub fn load_tensor(
+        &self,
+        device: &Arc<CudaDevice>,
+        name: &str,
+    ) -> Result<(CudaSlice<u8>, Dtype, Vec<usize>), SafetensorsError> {
+        let info = self.find(name)?;
+        let slice = self.upload(device, &[info])?.pop().unwrap();
+        Ok((slice, info.dtype, info.shape.clone()))
+    }
+
+    /// Loads the tensor `name` into a [CudaSlice] of its elements, which fails with
+    /// [SafetensorsError::DtypeMismatch] if they aren't of type `T`.
+    pub fn load_typed<T: SafetensorsDtype>(
+        &self,
+        device: &Arc<CudaDevice>,
+        name: &str,
+    ) -> Result<CudaSlice<T>, SafetensorsError> {
+        let info = self.find(name)?;
+        if info.dtype != T::DTYPE {
+            return Err(SafetensorsError::DtypeMismatch {
+                expected: T::DTYPE,
+                found: info.dtype,
+            });
+        }
+        let numel = info.shape.iter().product();
+        let dst = unsafe { device.alloc::<T>(numel) }?;
+        self.copy_all(device, &[(info, dst.cu_device_ptr)])?;
+        Ok(dst)
+    }
+
+    /// Loads every tensor like [SafeTensors::load_tensor()], with the copies enqueued
+    /// back to back on a transfer stream.
+    pub fn load_all(&self, device: &Arc<CudaDevice>) -> Result<LoadedTensors, SafetensorsError> {
+        let infos: Vec<_> = self.tensors.values().collect();
+        let slices = self.upload(device, &infos)?;
+        Ok(self
+            .tensors
+            .iter()
+            .zip(slices)
+            .map(|((name, info), slice)| (name.clone(), (slice, info.dtype, info.shape.clone())))
+            .collect())
+    }
+
+    fn find(&self, name: &str) -> Result<&TensorInfo, SafetensorsError> {
+        self.tensors
+            .get(name)
+            .ok_or_else(|| SafetensorsError::NotFound(name.into()))
+    }
+
+    /// Allocates a slice for each of `infos`, and copies their bytes into them.
+    fn upload(
+        &self,
+        device: &Arc<CudaDevice>,
+        infos: &[&TensorInfo],
+    ) -> Result<Vec<CudaSlice<u8>>, SafetensorsError> {
+        let slices = infos
+            .iter()
+            .map(|info| unsafe { device.alloc::<u8>(info.data_offsets.1 - info.data_offsets.0) })
+            .collect::<Result<Vec<_>, _>>()?;
+        let copies: Vec<_> = infos
+            .iter()
+            .zip(&slices)
+            .map(|(info, slice)| (*info, slice.cu_device_ptr))
+            .collect();
+        self.copy_all(device, &copies)?;
+        Ok(slices)
+    }
+
+    /// Copies the bytes of each tensor to its device pointer on a transfer stream, and waits
+    /// for the copies, which read from the mapping.
+    fn copy_all(
+        &self,
+        device: &Arc<CudaDevice>,
+        copies: &[(&TensorInfo, sys::CUdeviceptr)],
+    ) -> Result<(), SafetensorsError> {
+        // forked after the allocations, so the copies are ordered after them
+        let stream = device.fork_default_stream()?;
+        let data = &self.mapping.as_slice()[self.data_start..];
+        for (info, dst) in copies {
+            let (begin, end) = info.data_offsets;
+            if begin < end {
+                unsafe { result::memcpy_htod_async(*dst, &data[begin..end], stream.stream) }?;
+            }
+        }
+        unsafe { result::stream::synchronize(stream.stream) }?;
+        Ok(())
+    }
+}
+
+impl Drop for SafeTensors {
+    fn drop(&mut self) {
+        if let Some(device) = self.pinned.take() {
+            let ptr = self.mapping.as_slice().as_ptr() as *mut _;
+            let _ctx = device.bind_scoped_or_handle("SafeTensors::drop");
+            unsafe { result::host_unregister(ptr) }.or_handle("SafeTensors::drop");
+        }
+    }
+}
+
+/// The bytes of a file, mapped into memory where possible.
+#[derive(Debug)]
+enum Mapping {
+    #[cfg(unix)]
+    Mmap {
+        ptr: *mut core::ffi::c_void,
+        len: usize,
+    },
+    Read(Vec<u8>),
+}
+
+impl Mapping {
+    fn new(file: &std::fs::File) -> Result<Self, SafetensorsError> {
+        #[cfg(unix)]
+        {
+            let len = file.metadata()?.len() as usize;
+            // empty files can't be mapped
+            if len > 0 {
+                let fd = std::os::unix::io::AsRawFd::as_raw_fd(file);
+                let ptr = unsafe {
+                    libc::mmap(
+                        std::ptr::null_mut(),
+                        len,
+                        libc::PROT_READ,
+                        libc::MAP_PRIVATE,
+                        fd,
+                        0,
+                    )
+                };
+                if ptr == libc::MAP_FAILED {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                return Ok(Self::Mmap { ptr, len });
+            }
+        }
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut &*file, &mut bytes)?;
+        Ok(Self::Read(bytes))
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            #[cfg(unix)]
+            Self::Mmap { ptr, len } => unsafe {
+                std::slice::from_raw_parts(*ptr as *const u8, *len)
+            },
+            Self::Read(bytes) => bytes,
+        }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Self::Mmap { ptr, len } = self {
+            if unsafe { libc::munmap(*ptr, *len) } != 0 {
+                let err = std::io::Error::last_os_error();
+                log_library_error(&err, "Mapping::drop");
+            }
+        }
+    }
+}
+
+type Header = (
+    usize,
+    BTreeMap<String, TensorInfo>,
+    BTreeMap<String, String>,
+);
+
+/// The entry of a tensor in the header.
+#[derive(serde::Deserialize)]
+struct RawTensor {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+/// Parses the header of the file `bytes`, and returns where the data starts, the tensors,
+/// and the metadata.
+///
+/// The header needn't be padded to a multiple of 8 bytes, so the data of the tensors may
+/// be unaligned in the file. It is only ever copied byte for byte, into allocations aligned
+/// for any dtype.
+fn parse_file(bytes: &[u8]) -> Result<Header, SafetensorsError> {
+    let len_bytes = bytes
+        .get(..8)
+        .ok_or(SafetensorsError::InvalidHeader("file too short"))?;
+    let header_len = u64::from_le_bytes(len_bytes.try_into().unwrap());
+    if header_len > MAX_HEADER_LEN as u64 || header_len > (bytes.len() - 8) as u64 {
+        return Err(SafetensorsError::InvalidHeader("header length"));
+    }
+    let data_start = 8 + header_len as usize;
+    let data_len = bytes.len() - data_start;
+    let header = std::str::from_utf8(&bytes[8..data_start])
+        .map_err(|_| SafetensorsError::InvalidHeader("not utf-8"))?;
+
+    let entries: BTreeMap<String, serde_json::Value> = serde_json::from_str(header)
+        .map_err(|_| SafetensorsError::InvalidHeader("not a json object"))?;
+    let mut tensors = BTreeMap::new();
+    let mut metadata = BTreeMap::new();
+    for (name, value) in entries {
+        if name == "__metadata__" {
+            metadata = serde_json::from_value(value)
+                .map_err(|_| SafetensorsError::InvalidHeader("__metadata__"))?;
+        } else {
+            let raw = serde_json::from_value(value)
+                .map_err(|_| SafetensorsError::InvalidHeader("tensor fields"))?;
+            let info = parse_tensor(&name, raw, data_len)?;
+            tensors.insert(name, info);
+        }
+    }
+    Ok((data_start, tensors, metadata))
+}
+
+fn parse_tensor(
+    name: &str,
+    raw: RawTensor,
+    data_len: usize,
+) -> Result<TensorInfo, SafetensorsError> {
+    let dtype = Dtype::from_name(&raw.dtype).ok_or_else(|| SafetensorsError::UnknownDtype {
+        name: name.into(),
+        dtype: raw.dtype.clone(),
+    })?;
+    let (begin, end) = raw.data_offsets;
+    let num_bytes = raw
+        .shape
+        .iter()
+        .try_fold(dtype.size(), |acc, &dim| acc.checked_mul(dim));
+    if begin > end || end > data_len || num_bytes != Some(end - begin) {
+        return Err(SafetensorsError::InvalidOffsets(name.into()));
+    }
+    Ok(TensorInfo {
+        dtype,
+        shape: raw.shape,
+        data_offsets: (begin, end),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the tensors `(name, dtype, shape, bytes)` to a safetensors file, with the header
+    /// padded to `align` bytes.
+    fn write_file(
+        name: &str,
+        tensors: &[(&str, &str, &[usize], &[u8])],
+        align: usize,
+    ) -> std::path::PathBuf {
+        let mut header = std::string::String::from("{\"__metadata__\":{\"format\":\"pt\"}");
+        let mut data = Vec::new();
+        for (name, dtype, shape, bytes) in tensors {
+            let shape: Vec<_> = shape.iter().map(|d| d.to_string()).collect();
+            header += &std::format!(
+                ",\"{name}\":{{\"dtype\":\"{dtype}\",\"shape\":[{}],\"data_offsets\":[{},{}]}}",
+                shape.join(","),
+                data.len(),
+                data.len() + bytes.len()
+            );
+            data.extend_from_slice(bytes);
+        }
+        header.push('}');
+        while (8 + header.len()) % align != 0 {
+            header.push(' ');
+        }
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend_from_slice(header.as_bytes());
+        file.extend_from_slice(&data);
+        let path = std::env::temp_dir().join(std::format!(
+            "cudarc-safetensors-{name}-{}.safetensors",
+            std::process::id()
+        ));
+        std::fs::write(&path, file).unwrap();
+        path
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let a = f32_bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        // unpadded, so the tensors are unaligned in the file
+        let path = write_file(
+            "parse",
+            &[
+                ("a", "F32", &[2, 3], &a),
+                ("b\\u00e9", "U8", &[3], &[7, 8, 9]),
+            ],
+            1,
+        );
+        // mapped here, read in test_round_trip
+        let file = unsafe { SafeTensors::open(&path) }.unwrap();
+        assert_eq!(file.names().collect::<Vec<_>>(), ["a", "bé"]);
+        assert_eq!(
+            file.info("a"),
+            Some(&TensorInfo {
+                dtype: Dtype::F32,
+                shape: std::vec![2, 3],
+                data_offsets: (0, 24),
+            })
+        );
+        assert_eq!(file.info("bé").unwrap().data_offsets, (24, 27));
+        assert_eq!(
+            file.metadata().get("format").map(|s| s.as_str()),
+            Some("pt")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let parse = |bytes: &[u8]| parse_file(bytes).map(|_| ());
+        assert_eq!(
+            parse(&[1, 0]),
+            Err(SafetensorsError::InvalidHeader("file too short"))
+        );
+        let with_header = |header: &str, data: usize| {
+            let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+            bytes.extend_from_slice(header.as_bytes());
+            bytes.resize(bytes.len() + data, 0);
+            parse(&bytes)
+        };
+        assert_eq!(with_header("{}", 0), Ok(()));
+        assert_eq!(
+            with_header(
+                "{\"a\":{\"dtype\":\"F32\",\"shape\":[2],\"data_offsets\":[0,8]}}",
+                7
+            ),
+            Err(SafetensorsError::InvalidOffsets("a".into()))
+        );
+        assert_eq!(
+            with_header(
+                "{\"a\":{\"dtype\":\"F32\",\"shape\":[3],\"data_offsets\":[0,8]}}",
+                8
+            ),
+            Err(SafetensorsError::InvalidOffsets("a".into()))
+        );
+        assert_eq!(
+            with_header(
+                "{\"a\":{\"dtype\":\"F4\",\"shape\":[2],\"data_offsets\":[0,1]}}",
+                8
+            ),
+            Err(SafetensorsError::UnknownDtype {
+                name: "a".into(),
+                dtype: "F4".into()
+            })
+        );
+        assert_eq!(
+            with_header(
+                "{\"a\":{\"dtype\":\"F32\",\"shape\":[-2],\"data_offsets\":[0,8]}}",
+                8
+            ),
+            Err(SafetensorsError::InvalidHeader("tensor fields"))
+        );
+        assert!(with_header("{\"a\":", 0).is_err());
+        assert!(with_header("[] x", 0).is_err());
+        let mut huge = u64::MAX.to_le_bytes().to_vec();
+        huge.extend_from_slice(b"{}");
+        assert_eq!(
+            parse(&huge),
+            Err(SafetensorsError::InvalidHeader("header length"))
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dev = CudaDevice::new(0).unwrap();
+        let a = f32_bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let b: Vec<u8> = [-1i64, 5].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let tensors: [(&str, &str, &[usize], &[u8]); 4] = [
+            ("a", "F32", &[2, 3], &a),
+            ("b", "I64", &[2], &b),
+            ("c", "U8", &[1, 3], &[7, 8, 9]),
+            ("empty", "F32", &[0, 4], &[]),
+        ];
+        for align in [1, 8] {
+            let path = write_file("round_trip", &tensors, align);
+            let loaded = load_safetensors(&dev, &path).unwrap();
+            assert_eq!(loaded.len(), 4);
+            for (name, dtype, shape, bytes) in tensors {
+                let (slice, d, s) = &loaded[name];
+                assert_eq!(d.name(), dtype);
+                assert_eq!(s, shape);
+                assert_eq!(dev.dtoh_sync_copy(slice).unwrap(), bytes);
+            }
+
+            // the test owns the file, so nothing writes to it while it is mapped
+            let mapped = unsafe { SafeTensors::open(&path) }.unwrap();
+            for file in [SafeTensors::read(&path).unwrap(), mapped] {
+                let (c, dtype, shape) = file.load_tensor(&dev, "c").unwrap();
+                assert_eq!((dtype, shape), (Dtype::U8, std::vec![1, 3]));
+                assert_eq!(dev.dtoh_sync_copy(&c).unwrap(), [7, 8, 9]);
+                let a: CudaSlice<f32> = file.load_typed(&dev, "a").unwrap();
+                assert_eq!(
+                    dev.dtoh_sync_copy(&a).unwrap(),
+                    [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+                );
+                assert_eq!(
+                    file.load_typed::<f32>(&dev, "b").unwrap_err(),
+                    SafetensorsError::DtypeMismatch {
+                        expected: Dtype::F32,
+                        found: Dtype::I64
+                    }
+                );
+                assert_eq!(
+                    file.load_tensor(&dev, "d").unwrap_err(),
+                    SafetensorsError::NotFound("d".into())
+                );
+            }
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[cfg(feature = "f16")]
+    #[test]
+    fn test_load_half() {
+        let dev = CudaDevice::new(0).unwrap();
+        let values = [half::bf16::from_f32(1.5), half::bf16::from_f32(-2.0)];
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let path = write_file("half", &[("x", "BF16", &[2], &bytes)], 8);
+        let mut file = SafeTensors::read(&path).unwrap();
+        let _ = file.pin(&dev);
+        let x: CudaSlice<half::bf16> = file.load_typed(&dev, "x").unwrap();
+        assert_eq!(dev.dtoh_sync_copy(&x).unwrap(), values);
+        drop(file);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -19,6 +19,8 @@
 //! | nvrtc | [nvrtc::safe] | [nvrtc::result] | [nvrtc::sys] |
 //! | curand | [curand::safe] | [curand::result] | [curand::sys] |
 //! | cudnn | - | [cudnn::result] | [cudnn::sys] |
+//! | cufile | [cufile::safe] | [cufile::result] | [cufile::sys] |
+//! | nvml | [nvml::safe] | [nvml::result] | [nvml::sys] |
 //!
 //! # Core Concepts
 //!
@@ -77,23 +79,39 @@ extern crate alloc;
 #[cfg(feature = "no-std")]
 extern crate no_std_compat as std;
 
+#[cfg(feature = "driver")]
+pub mod bench;
+#[cfg(feature = "build")]
+pub mod build;
 #[cfg(feature = "cublas")]
 pub mod cublas;
 #[cfg(feature = "cublaslt")]
 pub mod cublaslt;
 #[cfg(feature = "cudnn")]
 pub mod cudnn;
+#[cfg(feature = "cufile")]
+pub mod cufile;
 #[cfg(feature = "curand")]
 pub mod curand;
 #[cfg(feature = "driver")]
 pub mod driver;
+#[cfg(feature = "nvrtc")]
+#[cfg(feature = "safetensors")]
+pub mod io;
 #[cfg(feature = "nccl")]
 pub mod nccl;
+#[cfg(feature = "nvml")]
+pub mod nvml;
 #[cfg(feature = "nvrtc")]
 pub mod nvrtc;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub mod types;
 
+#[cfg(feature = "driver")]
+pub use driver::{set_error_handler, ErrorHandler};
+
 pub(crate) fn panic_no_lib_found<S: std::fmt::Debug>(lib_name: &str, choices: &[S]) -> ! {
     panic!("Unable to dynamically load the \"{lib_name}\" shared library - searched for library names: {choices:?}. Ensure that `LD_LIBRARY_PATH` has the correct path to the installed library. If the shared library is present on the system under a different name than one of those listed above, please open a GitHub issue.");
 }
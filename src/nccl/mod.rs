@@ -1,9 +1,13 @@
 //! Wrappers around the [NCCL API](https://docs.nvidia.com/deeplearning/nccl/user-guide/docs/index.html)
 //! in three levels. See crate documentation for description of each.
 
+#[cfg(feature = "std")]
+pub mod rendezvous;
 pub mod result;
 pub mod safe;
 #[allow(warnings)]
 pub mod sys;
 
+#[cfg(feature = "std")]
+pub use rendezvous::{FileRendezvous, Rendezvous, RendezvousError, TcpRendezvous};
 pub use safe::*;
@@ -0,0 +1,365 @@
+//! Distributing the nccl [Id] between processes with a [Rendezvous], see [Comm::init_distributed()].
+
+use super::{result, safe::Comm, safe::Id};
+use crate::driver::CudaDevice;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const ID_BYTES: usize = 128;
+
+/// Errors from a [Rendezvous] or [Comm::init_distributed()].
+#[derive(Debug)]
+pub enum RendezvousError {
+    /// Reading or writing the id failed.
+    Io(std::io::Error),
+    /// [Rendezvous::fetch()] did not find an id before its timeout.
+    Timeout,
+    /// Creating the id or the [Comm] failed.
+    Nccl(result::NcclError),
+}
+
+impl std::fmt::Display for RendezvousError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "rendezvous io error: {e}"),
+            Self::Timeout => write!(f, "timed out waiting for the nccl id"),
+            Self::Nccl(e) => write!(f, "{e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RendezvousError {}
+
+impl From<std::io::Error> for RendezvousError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<result::NcclError> for RendezvousError {
+    fn from(e: result::NcclError) -> Self {
+        Self::Nccl(e)
+    }
+}
+
+/// Distributes the [Id] created by rank 0 to all other ranks.
+pub trait Rendezvous {
+    /// Called by rank 0 to make `id` available to the other ranks.
+    fn publish(&self, id: &Id) -> Result<(), RendezvousError>;
+
+    /// Called by all other ranks, blocks until the id is published or a timeout.
+    fn fetch(&self) -> Result<Id, RendezvousError>;
+}
+
+fn id_to_bytes(id: &Id) -> [u8; ID_BYTES] {
+    id.internal().map(|c| c as u8)
+}
+
+fn id_from_bytes(bytes: [u8; ID_BYTES]) -> Id {
+    Id::uninit(bytes.map(|b| b as ::core::ffi::c_char))
+}
+
+/// Retries `f` every `interval` until it returns `Some` or `timeout` has passed.
+fn retry<T>(
+    timeout: Duration,
+    interval: Duration,
+    mut f: impl FnMut() -> Option<T>,
+) -> Result<T, RendezvousError> {
+    let start = Instant::now();
+    loop {
+        if let Some(t) = f() {
+            return Ok(t);
+        }
+        if start.elapsed() >= timeout {
+            return Err(RendezvousError::Timeout);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// A [Rendezvous] through a file, for multiple processes on a single node.
+///
+/// The file is written atomically by [Rendezvous::publish()]. A file left over from an
+/// earlier run would be picked up by [Rendezvous::fetch()], so use a fresh path per run.
+#[derive(Debug, Clone)]
+pub struct FileRendezvous {
+    path: PathBuf,
+    timeout: Duration,
+    retry_interval: Duration,
+}
+
+impl FileRendezvous {
+    /// Waits for up to 60 seconds, checking every 50ms.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            timeout: Duration::from_secs(60),
+            retry_interval: Duration::from_millis(50),
+        }
+    }
+
+    /// How long [Rendezvous::fetch()] waits for the file.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How often [Rendezvous::fetch()] checks for the file.
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+}
+
+impl Rendezvous for FileRendezvous {
+    fn publish(&self, id: &Id) -> Result<(), RendezvousError> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(std::format!(".{}.tmp", std::process::id()));
+        std::fs::write(&tmp, id_to_bytes(id))?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn fetch(&self) -> Result<Id, RendezvousError> {
+        let bytes = retry(self.timeout, self.retry_interval, || {
+            let bytes = std::fs::read(&self.path).ok()?;
+            bytes.try_into().ok()
+        })?;
+        Ok(id_from_bytes(bytes))
+    }
+}
+
+/// A [Rendezvous] through a TCP connection to rank 0.
+///
+/// [Rendezvous::publish()] listens on the address and serves the id from a background
+/// thread until the [TcpRendezvous] is dropped. [Rendezvous::fetch()] retries connecting
+/// until rank 0 is listening.
+#[derive(Debug)]
+pub struct TcpRendezvous {
+    addr: SocketAddr,
+    timeout: Duration,
+    retry_interval: Duration,
+    server: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>>,
+}
+
+impl TcpRendezvous {
+    /// The address rank 0 listens on, and all other ranks connect to. Waits for up to
+    /// 60 seconds, retrying every 50ms.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            timeout: Duration::from_secs(60),
+            retry_interval: Duration::from_millis(50),
+            server: Mutex::new(None),
+        }
+    }
+
+    /// How long [Rendezvous::fetch()] waits for rank 0.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How often [Rendezvous::fetch()] retries connecting.
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+}
+
+impl Rendezvous for TcpRendezvous {
+    fn publish(&self, id: &Id) -> Result<(), RendezvousError> {
+        // publishing again replaces the previous id
+        if let Some((stop, handle)) = self.server.lock().unwrap().take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+        }
+        let listener = TcpListener::bind(self.addr)?;
+        listener.set_nonblocking(true)?;
+        let bytes = id_to_bytes(id);
+        let stop = Arc::new(AtomicBool::new(false));
+        let interval = self.retry_interval;
+        let handle = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((mut stream, _)) => {
+                            // a client that went away will retry
+                            let _ = stream
+                                .set_nonblocking(false)
+                                .and_then(|_| stream.write_all(&bytes));
+                        }
+                        Err(_) => std::thread::sleep(interval),
+                    }
+                }
+            }
+        });
+        *self.server.lock().unwrap() = Some((stop, handle));
+        Ok(())
+    }
+
+    fn fetch(&self) -> Result<Id, RendezvousError> {
+        let bytes = retry(self.timeout, self.retry_interval, || {
+            let mut stream = TcpStream::connect_timeout(&self.addr, self.retry_interval).ok()?;
+            let mut bytes = [0; ID_BYTES];
+            stream.read_exact(&mut bytes).ok()?;
+            Some(bytes)
+        })?;
+        Ok(id_from_bytes(bytes))
+    }
+}
+
+impl Drop for TcpRendezvous {
+    fn drop(&mut self) {
+        if let Some((stop, handle)) = self.server.get_mut().unwrap().take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Comm {
+    /// Creates the [Comm] for `rank` out of `world_size` ranks, where rank 0 creates the [Id]
+    /// and shares it with the other ranks through `rendezvous`.
+    ///
+    /// ```no_run
+    /// # use cudarc::driver::CudaDevice;
+    /// # use cudarc::nccl::{Comm, FileRendezvous};
+    /// let rank: usize = std::env::var("RANK").unwrap().parse().unwrap();
+    /// let dev = CudaDevice::new(rank).unwrap();
+    /// let rendezvous = FileRendezvous::new("/tmp/nccl-id");
+    /// let comm = Comm::init_distributed(dev, rank, 2, &rendezvous).unwrap();
+    /// ```
+    pub fn init_distributed<R: Rendezvous + ?Sized>(
+        device: Arc<CudaDevice>,
+        rank: usize,
+        world_size: usize,
+        rendezvous: &R,
+    ) -> Result<Self, RendezvousError> {
+        let id = if rank == 0 {
+            let id = Id::new()?;
+            rendezvous.publish(&id)?;
+            id
+        } else {
+            rendezvous.fetch()?
+        };
+        Ok(Comm::from_rank(device, rank, world_size, id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nccl::ReduceOp;
+    use std::process::Command;
+    use std::vec;
+
+    fn test_id() -> Id {
+        let mut internal = [0; ID_BYTES];
+        for (i, c) in internal.iter_mut().enumerate() {
+            *c = i as ::core::ffi::c_char;
+        }
+        Id::uninit(internal)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(std::format!("cudarc-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn test_file_rendezvous() {
+        let path = temp_path("nccl-file-rendezvous");
+        let _ = std::fs::remove_file(&path);
+        let rendezvous = FileRendezvous::new(&path).with_retry_interval(Duration::from_millis(1));
+
+        let fetcher = std::thread::spawn({
+            let rendezvous = rendezvous.clone();
+            move || rendezvous.fetch().unwrap()
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        rendezvous.publish(&test_id()).unwrap();
+        assert_eq!(fetcher.join().unwrap().internal(), test_id().internal());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_rendezvous_timeout() {
+        let rendezvous = FileRendezvous::new(temp_path("nccl-missing"))
+            .with_timeout(Duration::from_millis(20))
+            .with_retry_interval(Duration::from_millis(1));
+        assert!(matches!(rendezvous.fetch(), Err(RendezvousError::Timeout)));
+    }
+
+    #[test]
+    fn test_tcp_rendezvous() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = TcpRendezvous::new(addr);
+        let fetchers: std::vec::Vec<_> = (0..3)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    TcpRendezvous::new(addr)
+                        .with_timeout(Duration::from_secs(5))
+                        .with_retry_interval(Duration::from_millis(1))
+                        .fetch()
+                        .unwrap()
+                })
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(20));
+        server.publish(&test_id()).unwrap();
+        for fetcher in fetchers {
+            assert_eq!(fetcher.join().unwrap().internal(), test_id().internal());
+        }
+    }
+
+    const RANK_VAR: &str = "CUDARC_TEST_RENDEZVOUS_RANK";
+    const PATH_VAR: &str = "CUDARC_TEST_RENDEZVOUS_PATH";
+
+    #[test]
+    fn test_init_distributed_two_processes() {
+        if let Ok(rank) = std::env::var(RANK_VAR) {
+            // one of the spawned ranks, both share device 0
+            let rank: usize = rank.parse().unwrap();
+            let rendezvous = FileRendezvous::new(std::env::var(PATH_VAR).unwrap());
+            let dev = CudaDevice::new(0).unwrap();
+            let comm = Comm::init_distributed(dev.clone(), rank, 2, &rendezvous).unwrap();
+            let mut buf = dev.htod_copy(vec![(rank + 1) as f32; 4]).unwrap();
+            comm.all_reduce_in_place(dev.default_stream(), &mut buf, &ReduceOp::Sum)
+                .unwrap();
+            assert_eq!(dev.dtoh_sync_copy(&buf).unwrap(), [3.0; 4]);
+            return;
+        }
+
+        let path = temp_path("nccl-init-distributed");
+        let _ = std::fs::remove_file(&path);
+        let exe = std::env::current_exe().unwrap();
+        let children: std::vec::Vec<_> = (0..2)
+            .map(|rank| {
+                Command::new(&exe)
+                    .args([
+                        "--exact",
+                        "nccl::rendezvous::tests::test_init_distributed_two_processes",
+                    ])
+                    .env(RANK_VAR, rank.to_string())
+                    .env(PATH_VAR, &path)
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -355,7 +355,7 @@ mod tests {
             devs.push(dev);
         }
         let mut comms = vec![std::ptr::null_mut(); n_devices];
-        let ordinals: Vec<_> = devs.iter().map(|d| d.ordinal as i32).collect();
+        let ordinals: Vec<_> = devs.iter().map(|d| d.physical_ordinal as i32).collect();
         unsafe {
             comm_init_all(comms.as_mut_ptr(), n_devices as i32, ordinals.as_ptr()).unwrap();
 
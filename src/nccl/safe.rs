@@ -1,5 +1,5 @@
 use super::{result, sys};
-use crate::driver::{CudaDevice, DevicePtr, DevicePtrMut};
+use crate::driver::{CudaDevice, DevicePtr, DevicePtrMut, StreamRef};
 use std::mem::MaybeUninit;
 use std::ptr;
 use std::{sync::Arc, vec, vec::Vec};
@@ -106,16 +106,15 @@ impl Comm {
     ///     let dev = comm.device();
     ///     let slice = dev.htod_copy(vec![(i + 1) as f32 * 1.0; n]).unwrap();
     ///     let mut slice_receive = dev.alloc_zeros::<f32>(n).unwrap();
-    ///     comm.all_reduce(&slice, &mut slice_receive, &ReduceOp::Sum)
+    ///     comm.all_reduce(dev.default_stream(), &slice, &mut slice_receive, &ReduceOp::Sum)
     ///         .unwrap();
-
     /// });
     /// group_start().unwrap();
     /// ```
     pub fn from_devices(devices: Vec<Arc<CudaDevice>>) -> Result<Vec<Self>, result::NcclError> {
         let n_devices = devices.len();
         let mut comms = vec![std::ptr::null_mut(); n_devices];
-        let ordinals: Vec<_> = devices.iter().map(|d| d.ordinal as i32).collect();
+        let ordinals: Vec<_> = devices.iter().map(|d| d.physical_ordinal as i32).collect();
         unsafe {
             result::comm_init_all(comms.as_mut_ptr(), n_devices as i32, ordinals.as_ptr())?;
         }
@@ -168,11 +167,11 @@ impl Comm {
     /// let comm = Comm::from_rank(dev.clone(), rank, n_devices, id).unwrap();
     /// let slice = dev.htod_copy(vec![(rank + 1) as f32 * 1.0; n]).unwrap();
     /// let mut slice_receive = dev.alloc_zeros::<f32>(n).unwrap();
-    /// comm.all_reduce(&slice, &mut slice_receive, &ReduceOp::Sum)
+    /// comm.all_reduce(dev.default_stream(), &slice, &mut slice_receive, &ReduceOp::Sum)
     ///     .unwrap();
-
+    ///
     /// let out = dev.dtoh_sync_copy(&slice_receive).unwrap();
-
+    ///
     /// assert_eq!(out, vec![(n_devices * (n_devices + 1)) as f32 / 2.0; n]);
     /// ```
     pub fn from_rank(
@@ -204,11 +203,36 @@ impl Comm {
 }
 
 impl Comm {
-    pub fn send<S: DevicePtr<T>, T: NcclType>(
+    /// Whether collectives can be captured into a [crate::driver::CudaGraph], by enqueueing
+    /// them on a stream between [crate::driver::CudaStream::begin_capture()] and
+    /// [crate::driver::CudaStream::end_capture()]. nccl supports it since 2.9.
+    ///
+    /// None of the collectives synchronize with the host, so they only need to be passed
+    /// the capturing stream.
+    pub fn supports_graph_capture() -> bool {
+        result::get_nccl_version().is_ok_and(|version| version >= 20900)
+    }
+
+    /// `stream` as nccl takes it. It has to be a stream of the context of the comm's device,
+    /// a stream of another [CudaDevice] on the same GPU doesn't do.
+    fn nccl_stream<'s>(
+        &self,
+        stream: impl Into<StreamRef<'s>>,
+    ) -> Result<sys::cudaStream_t, result::NcclError> {
+        let stream = stream.into();
+        if stream.device().cu_ctx != self.device.cu_ctx {
+            return Err(result::NcclError(sys::ncclResult_t::ncclInvalidArgument));
+        }
+        Ok(stream.cu_stream() as sys::cudaStream_t)
+    }
+
+    pub fn send<'s, S: DevicePtr<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         data: &S,
         peer: i32,
     ) -> Result<(), result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::send(
                 *data.device_ptr() as *mut _,
@@ -216,17 +240,19 @@ impl Comm {
                 T::as_nccl_type(),
                 peer,
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )?;
         }
         Ok(())
     }
 
-    pub fn recv<R: DevicePtrMut<T>, T: NcclType>(
+    pub fn recv<'s, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         buff: &mut R,
         peer: i32,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::recv(
                 *buff.device_ptr_mut() as *mut _,
@@ -234,17 +260,19 @@ impl Comm {
                 T::as_nccl_type(),
                 peer,
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn broadcast<S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+    pub fn broadcast<'s, S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         sendbuff: &Option<S>,
         recvbuff: &mut R,
         root: i32,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             let send_ptr = match sendbuff {
                 Some(buffer) => *buffer.device_ptr() as *mut _,
@@ -257,16 +285,18 @@ impl Comm {
                 T::as_nccl_type(),
                 root,
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn broadcast_in_place<R: DevicePtrMut<T>, T: NcclType>(
+    pub fn broadcast_in_place<'s, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         recvbuff: &mut R,
         root: i32,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::broadcast(
                 *recvbuff.device_ptr_mut() as *const _,
@@ -275,16 +305,18 @@ impl Comm {
                 T::as_nccl_type(),
                 root,
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn all_gather<S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+    pub fn all_gather<'s, S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         sendbuff: &S,
         recvbuff: &mut R,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::all_gather(
                 *sendbuff.device_ptr() as *mut _,
@@ -292,17 +324,74 @@ impl Comm {
                 sendbuff.len(),
                 T::as_nccl_type(),
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn all_reduce<S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+    /// Like [Comm::all_gather()], but each rank sends a different number of elements:
+    /// `counts[r]` elements from rank `r` are gathered into `recvbuff` after those of ranks
+    /// `0..r`. Every rank must pass the same `counts`, and `sendbuff` must hold
+    /// `counts[self.rank()]` elements.
+    ///
+    /// nccl has no native all-gatherv, so this is one `ncclBroadcast` per rank inside a
+    /// group. It has more latency than [Comm::all_gather()] for the same amount of data, so
+    /// prefer padding to equal counts when the counts are close.
+    ///
+    /// Returns `ncclInvalidArgument` if `counts` doesn't have one count per rank, or the
+    /// lengths of `sendbuff` and `recvbuff` don't match it.
+    pub fn all_gather_varlen<'s, S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+        &self,
+        stream: impl Into<StreamRef<'s>>,
+        sendbuff: &S,
+        counts: &[usize],
+        recvbuff: &mut R,
+    ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
+        if counts.len() != self.world_size
+            || sendbuff.len() != counts[self.rank]
+            || recvbuff.len() != counts.iter().sum::<usize>()
+        {
+            return Err(result::NcclError(sys::ncclResult_t::ncclInvalidArgument));
+        }
+        let recv_ptr = *recvbuff.device_ptr_mut();
+        group_start()?;
+        let mut offset = 0;
+        let res = counts.iter().enumerate().try_for_each(|(root, &count)| {
+            let send_ptr = if root == self.rank {
+                *sendbuff.device_ptr() as *const _
+            } else {
+                ptr::null()
+            };
+            let dst = recv_ptr + (offset * std::mem::size_of::<T>()) as u64;
+            offset += count;
+            unsafe {
+                result::broadcast(
+                    send_ptr,
+                    dst as *mut _,
+                    count,
+                    T::as_nccl_type(),
+                    root as i32,
+                    self.comm,
+                    stream,
+                )
+            }
+            .map(|_| ())
+        });
+        // the group must be closed even if enqueueing a broadcast failed
+        let end = group_end();
+        res?;
+        end
+    }
+
+    pub fn all_reduce<'s, S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         sendbuff: &S,
         recvbuff: &mut R,
         reduce_op: &ReduceOp,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::all_reduce(
                 *sendbuff.device_ptr() as *mut _,
@@ -311,18 +400,44 @@ impl Comm {
                 T::as_nccl_type(),
                 convert_to_nccl_reduce_op(reduce_op),
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn reduce<S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+    /// Reduces `buff` across all ranks, writing the result back into `buff`. This uses the same
+    /// buffer for sending & receiving, which nccl supports without an extra allocation.
+    ///
+    /// `buff` may be a view, e.g. a bucket of a larger flat buffer of gradients.
+    pub fn all_reduce_in_place<'s, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
+        buff: &mut R,
+        reduce_op: &ReduceOp,
+    ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
+        unsafe {
+            result::all_reduce(
+                *buff.device_ptr_mut() as *const _,
+                *buff.device_ptr_mut() as *mut _,
+                buff.len(),
+                T::as_nccl_type(),
+                convert_to_nccl_reduce_op(reduce_op),
+                self.comm,
+                stream,
+            )
+        }
+    }
+
+    pub fn reduce<'s, S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+        &self,
+        stream: impl Into<StreamRef<'s>>,
         sendbuff: &S,
         recvbuff: &mut R,
         reduce_op: &ReduceOp,
         root: i32,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::reduce(
                 *sendbuff.device_ptr() as *mut _,
@@ -332,17 +447,19 @@ impl Comm {
                 convert_to_nccl_reduce_op(reduce_op),
                 root,
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn reduce_in_place<R: DevicePtrMut<T>, T: NcclType>(
+    pub fn reduce_in_place<'s, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         recvbuff: &mut R,
         reduce_op: &ReduceOp,
         root: i32,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::reduce(
                 *recvbuff.device_ptr_mut() as *mut _,
@@ -352,17 +469,19 @@ impl Comm {
                 convert_to_nccl_reduce_op(reduce_op),
                 root,
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
 
-    pub fn reduce_scatter<S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
+    pub fn reduce_scatter<'s, S: DevicePtr<T>, R: DevicePtrMut<T>, T: NcclType>(
         &self,
+        stream: impl Into<StreamRef<'s>>,
         sendbuff: &S,
         recvbuff: &mut R,
         reduce_op: &ReduceOp,
     ) -> Result<result::NcclStatus, result::NcclError> {
+        let stream = self.nccl_stream(stream)?;
         unsafe {
             result::reduce_scatter(
                 *sendbuff.device_ptr() as *mut _,
@@ -371,7 +490,7 @@ impl Comm {
                 T::as_nccl_type(),
                 convert_to_nccl_reduce_op(reduce_op),
                 self.comm,
-                self.device.stream as *mut _,
+                stream,
             )
         }
     }
@@ -410,8 +529,13 @@ mod tests {
                     let comm = Comm::from_rank(dev.clone(), i, n_devices, id).unwrap();
                     let slice = dev.htod_copy(vec![(i + 1) as f32 * 1.0; n]).unwrap();
                     let mut slice_receive = dev.alloc_zeros::<f32>(n).unwrap();
-                    comm.all_reduce(&slice, &mut slice_receive, &ReduceOp::Sum)
-                        .unwrap();
+                    comm.all_reduce(
+                        dev.default_stream(),
+                        &slice,
+                        &mut slice_receive,
+                        &ReduceOp::Sum,
+                    )
+                    .unwrap();
 
                     let out = dev.dtoh_sync_copy(&slice_receive).unwrap();
 
@@ -441,8 +565,13 @@ mod tests {
                     let slice_view = slice.slice(..);
                     let mut slice_receive_view = slice_receive.slice_mut(..);
 
-                    comm.all_reduce(&slice_view, &mut slice_receive_view, &ReduceOp::Sum)
-                        .unwrap();
+                    comm.all_reduce(
+                        dev.default_stream(),
+                        &slice_view,
+                        &mut slice_receive_view,
+                        &ReduceOp::Sum,
+                    )
+                    .unwrap();
 
                     let out = dev.dtoh_sync_copy(&slice_receive).unwrap();
 
@@ -454,4 +583,154 @@ mod tests {
             t.join().unwrap()
         }
     }
+
+    #[test]
+    #[ignore = "needs two or more GPUs"]
+    fn test_all_gather_varlen() {
+        let n_devices = CudaDevice::count().unwrap() as usize;
+        // with a single rank every count is the rank's own
+        assert!(n_devices >= 2, "only {n_devices} GPUs");
+        let id = Id::new().unwrap();
+        // rank `r` sends `r + 1` copies of `r`
+        let counts: Vec<usize> = (1..=n_devices).collect();
+        let threads: Vec<_> = (0..n_devices)
+            .map(|i| {
+                let counts = counts.clone();
+                std::thread::spawn(move || {
+                    let dev = CudaDevice::new(i).unwrap();
+                    let comm = Comm::from_rank(dev.clone(), i, n_devices, id).unwrap();
+                    let send = dev.htod_copy(vec![i as f32; counts[i]]).unwrap();
+                    let total = counts.iter().sum();
+                    let mut recv = dev.alloc_zeros::<f32>(total).unwrap();
+                    comm.all_gather_varlen(dev.default_stream(), &send, &counts, &mut recv)
+                        .unwrap();
+
+                    let expected: Vec<f32> = counts
+                        .iter()
+                        .enumerate()
+                        .flat_map(|(r, &c)| vec![r as f32; c])
+                        .collect();
+                    assert_eq!(dev.dtoh_sync_copy(&recv).unwrap(), expected);
+
+                    let mut short = dev.alloc_zeros::<f32>(total - 1).unwrap();
+                    assert!(matches!(
+                        comm.all_gather_varlen(dev.default_stream(), &send, &counts, &mut short),
+                        Err(result::NcclError(sys::ncclResult_t::ncclInvalidArgument))
+                    ));
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_all_reduce_in_place_buckets() {
+        let n = 2;
+        let n_devices = CudaDevice::count().unwrap() as usize;
+        let id = Id::new().unwrap();
+        let threads: Vec<_> = (0..n_devices)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let dev = CudaDevice::new(i).unwrap();
+                    let comm = Comm::from_rank(dev.clone(), i, n_devices, id).unwrap();
+                    let mut grads = dev.htod_copy(vec![(i + 1) as f32 * 1.0; 2 * n]).unwrap();
+                    let (mut bucket0, mut bucket1) = grads.split_at_mut(n);
+
+                    group_start().unwrap();
+                    comm.all_reduce_in_place(dev.default_stream(), &mut bucket0, &ReduceOp::Sum)
+                        .unwrap();
+                    comm.all_reduce_in_place(dev.default_stream(), &mut bucket1, &ReduceOp::Max)
+                        .unwrap();
+                    group_end().unwrap();
+
+                    let out = dev.dtoh_sync_copy(&grads).unwrap();
+
+                    let sum = (n_devices * (n_devices + 1)) as f32 / 2.0;
+                    let max = n_devices as f32;
+                    assert_eq!(out, [vec![sum; n], vec![max; n]].concat());
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap()
+        }
+    }
+
+    #[test]
+    #[ignore = "needs two or more GPUs"]
+    fn test_all_reduce_graph_capture() {
+        use crate::driver::sys::CUstreamCaptureMode;
+
+        assert!(Comm::supports_graph_capture());
+        let n_devices = CudaDevice::count().unwrap();
+        assert!(n_devices >= 2, "only {n_devices} GPUs");
+        let n = 4;
+        let devices: Vec<_> = (0..2).map(|i| CudaDevice::new(i).unwrap()).collect();
+        let comms = Comm::from_devices(devices.clone()).unwrap();
+        let streams: Vec<_> = devices
+            .iter()
+            .map(|dev| dev.fork_default_stream().unwrap())
+            .collect();
+        let mut sends: Vec<_> = devices
+            .iter()
+            .map(|dev| dev.alloc_zeros::<f32>(n).unwrap())
+            .collect();
+        let mut recvs: Vec<_> = devices
+            .iter()
+            .map(|dev| dev.alloc_zeros::<f32>(n).unwrap())
+            .collect();
+        for dev in devices.iter() {
+            dev.synchronize().unwrap();
+        }
+
+        // a stream of the other device is rejected before reaching nccl
+        assert!(matches!(
+            comms[0].all_reduce(&streams[1], &sends[0], &mut recvs[0], &ReduceOp::Sum),
+            Err(result::NcclError(sys::ncclResult_t::ncclInvalidArgument))
+        ));
+        // so is one of another context on the same GPU
+        let owned = CudaDevice::new_with_owned_context(0, 0).unwrap();
+        assert!(matches!(
+            comms[0].all_reduce(
+                owned.default_stream(),
+                &sends[0],
+                &mut recvs[0],
+                &ReduceOp::Sum
+            ),
+            Err(result::NcclError(sys::ncclResult_t::ncclInvalidArgument))
+        ));
+
+        for stream in streams.iter() {
+            stream
+                .begin_capture(CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_RELAXED)
+                .unwrap();
+        }
+        group_start().unwrap();
+        for (i, comm) in comms.iter().enumerate() {
+            comm.all_reduce(&streams[i], &sends[i], &mut recvs[i], &ReduceOp::Sum)
+                .unwrap();
+        }
+        group_end().unwrap();
+        let graphs: Vec<_> = streams
+            .iter()
+            .map(|stream| stream.end_capture().unwrap())
+            .collect();
+
+        for round in 0..3 {
+            for (i, dev) in devices.iter().enumerate() {
+                dev.htod_sync_copy_into(&vec![(round * 10 + i) as f32; n], &mut sends[i])
+                    .unwrap();
+            }
+            for (graph, stream) in graphs.iter().zip(streams.iter()) {
+                unsafe { graph.launch_on_stream(stream) }.unwrap();
+            }
+            for (i, dev) in devices.iter().enumerate() {
+                dev.wait_for(&streams[i]).unwrap();
+                let expected = (2 * round * 10 + 1) as f32;
+                assert_eq!(dev.dtoh_sync_copy(&recvs[i]).unwrap(), vec![expected; n]);
+            }
+        }
+    }
 }
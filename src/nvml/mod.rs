@@ -0,0 +1,13 @@
+//! Wrappers around the [NVML API](https://docs.nvidia.com/deploy/nvml-api/index.html)
+//! in three levels. See crate documentation for description of each.
+//!
+//! [crate::driver::CudaDevice::nvml()] finds the [NvmlDevice] of a device for reading its
+//! temperature, power and clocks, and [TelemetryPoller] reports them as they change.
+
+pub mod result;
+pub mod safe;
+#[allow(warnings)]
+pub mod sys;
+
+pub use result::NvmlError;
+pub use safe::*;
@@ -0,0 +1,286 @@
+use super::sys::{self, lib};
+use crate::driver::DriverError;
+
+use core::ffi::{c_char, c_uint, CStr};
+use core::mem::MaybeUninit;
+use std::string::String;
+
+/// An error of an NVML call, or of associating an NVML device with a
+/// [crate::driver::CudaDevice].
+#[derive(Clone, PartialEq, Eq)]
+pub enum NvmlError {
+    Nvml(sys::nvmlReturn_t),
+    /// The loaded libnvidia-ml doesn't match the version of the kernel driver, which
+    /// happens after the driver is upgraded until the kernel module is reloaded.
+    LibraryVersionMismatch,
+    /// NVML and the CUDA driver API report different CUDA driver versions, as
+    /// `1000 * major + 10 * minor`, so they were loaded from different installs.
+    DriverVersionMismatch {
+        nvml: i32,
+        driver: i32,
+    },
+    /// NVML has no device with the UUID of the [crate::driver::CudaDevice].
+    DeviceNotFound,
+    Driver(DriverError),
+}
+
+impl sys::nvmlReturn_t {
+    /// Transforms into a [Result] of [NvmlError]
+    pub fn result(self) -> Result<(), NvmlError> {
+        match self {
+            sys::nvmlReturn_t::NVML_SUCCESS => Ok(()),
+            sys::nvmlReturn_t::NVML_ERROR_LIB_RM_VERSION_MISMATCH => {
+                Err(NvmlError::LibraryVersionMismatch)
+            }
+            _ => Err(NvmlError::Nvml(self)),
+        }
+    }
+}
+
+impl NvmlError {
+    /// The error string of NVML for its errors.
+    pub fn error_string(&self) -> Option<&'static str> {
+        let code = match self {
+            Self::Nvml(code) => *code,
+            Self::LibraryVersionMismatch => sys::nvmlReturn_t::NVML_ERROR_LIB_RM_VERSION_MISMATCH,
+            _ => return None,
+        };
+        let msg = unsafe { sys::try_lib()?.nvmlErrorString(code) };
+        if msg.is_null() {
+            return None;
+        }
+        unsafe { CStr::from_ptr(msg) }.to_str().ok()
+    }
+}
+
+impl From<DriverError> for NvmlError {
+    fn from(value: DriverError) -> Self {
+        Self::Driver(value)
+    }
+}
+
+impl std::fmt::Debug for NvmlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nvml(code) => match self.error_string() {
+                Some(msg) => write!(f, "NvmlError({code:?}, {msg:?})"),
+                None => write!(f, "NvmlError({code:?})"),
+            },
+            Self::LibraryVersionMismatch => write!(
+                f,
+                "NvmlError(LibraryVersionMismatch): libnvidia-ml doesn't match the loaded kernel driver, reload the nvidia kernel module or reboot"
+            ),
+            Self::DriverVersionMismatch { nvml, driver } => write!(
+                f,
+                "NvmlError(DriverVersionMismatch): NVML reports CUDA driver {}.{} but the driver API reports {}.{}",
+                nvml / 1000,
+                (nvml % 1000) / 10,
+                driver / 1000,
+                (driver % 1000) / 10
+            ),
+            Self::DeviceNotFound => write!(f, "NvmlError(DeviceNotFound)"),
+            Self::Driver(err) => write!(f, "NvmlError({err:?})"),
+        }
+    }
+}
+
+impl std::fmt::Display for NvmlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl std::error::Error for NvmlError {}
+
+/// Initializes NVML. Calls are reference counted, each must be matched by a [shutdown()].
+/// Fails with [sys::nvmlReturn_t::NVML_ERROR_LIBRARY_NOT_FOUND] if libnvidia-ml isn't
+/// installed. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlInitializationAndCleanup.html)
+pub fn init() -> Result<(), NvmlError> {
+    match unsafe { sys::try_lib() } {
+        Some(lib) => unsafe { lib.nvmlInit_v2() }.result(),
+        None => Err(NvmlError::Nvml(
+            sys::nvmlReturn_t::NVML_ERROR_LIBRARY_NOT_FOUND,
+        )),
+    }
+}
+
+/// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlInitializationAndCleanup.html)
+///
+/// # Safety
+/// Must match a successful [init()], device handles are invalid once the last one is
+/// shut down.
+pub unsafe fn shutdown() -> Result<(), NvmlError> {
+    lib().nvmlShutdown().result()
+}
+
+/// Reads a string NVML writes into a buffer of `len` bytes.
+unsafe fn read_string(
+    len: u32,
+    f: impl FnOnce(*mut c_char, c_uint) -> sys::nvmlReturn_t,
+) -> Result<String, NvmlError> {
+    let mut buf = std::vec![0 as c_char; len as usize];
+    f(buf.as_mut_ptr(), len).result()?;
+    Ok(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+}
+
+pub mod system {
+    use super::*;
+
+    /// The version of the kernel driver, e.g. `"550.54.15"`. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlSystemQueries.html)
+    pub fn get_driver_version() -> Result<String, NvmlError> {
+        unsafe {
+            read_string(sys::NVML_SYSTEM_DRIVER_VERSION_BUFFER_SIZE, |buf, len| {
+                lib().nvmlSystemGetDriverVersion(buf, len)
+            })
+        }
+    }
+
+    /// The CUDA driver version as `1000 * major + 10 * minor`, like
+    /// [crate::driver::result::driver_version()]. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlSystemQueries.html)
+    pub fn get_cuda_driver_version() -> Result<i32, NvmlError> {
+        let mut version = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .nvmlSystemGetCudaDriverVersion_v2(version.as_mut_ptr())
+                .result()?;
+            Ok(version.assume_init())
+        }
+    }
+}
+
+pub mod device {
+    use super::*;
+
+    /// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    pub fn get_count() -> Result<u32, NvmlError> {
+        let mut count = MaybeUninit::uninit();
+        unsafe {
+            lib().nvmlDeviceGetCount_v2(count.as_mut_ptr()).result()?;
+            Ok(count.assume_init())
+        }
+    }
+
+    /// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    pub fn get_handle_by_index(index: u32) -> Result<sys::nvmlDevice_t, NvmlError> {
+        let mut device = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .nvmlDeviceGetHandleByIndex_v2(index, device.as_mut_ptr())
+                .result()?;
+            Ok(device.assume_init())
+        }
+    }
+
+    /// `uuid` is formatted like `"GPU-5c3a1d2e-..."`. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    pub fn get_handle_by_uuid(uuid: &CStr) -> Result<sys::nvmlDevice_t, NvmlError> {
+        let mut device = MaybeUninit::uninit();
+        unsafe {
+            lib()
+                .nvmlDeviceGetHandleByUUID(uuid.as_ptr(), device.as_mut_ptr())
+                .result()?;
+            Ok(device.assume_init())
+        }
+    }
+
+    /// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_uuid(device: sys::nvmlDevice_t) -> Result<String, NvmlError> {
+        read_string(sys::NVML_DEVICE_UUID_V2_BUFFER_SIZE, |buf, len| {
+            lib().nvmlDeviceGetUUID(device, buf, len)
+        })
+    }
+
+    /// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_name(device: sys::nvmlDevice_t) -> Result<String, NvmlError> {
+        read_string(sys::NVML_DEVICE_NAME_V2_BUFFER_SIZE, |buf, len| {
+            lib().nvmlDeviceGetName(device, buf, len)
+        })
+    }
+
+    /// In degrees celsius. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_temperature(
+        device: sys::nvmlDevice_t,
+        sensor: sys::nvmlTemperatureSensors_t,
+    ) -> Result<u32, NvmlError> {
+        let mut temp = MaybeUninit::uninit();
+        lib()
+            .nvmlDeviceGetTemperature(device, sensor, temp.as_mut_ptr())
+            .result()?;
+        Ok(temp.assume_init())
+    }
+
+    /// In milliwatts. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_power_usage(device: sys::nvmlDevice_t) -> Result<u32, NvmlError> {
+        let mut power = MaybeUninit::uninit();
+        lib()
+            .nvmlDeviceGetPowerUsage(device, power.as_mut_ptr())
+            .result()?;
+        Ok(power.assume_init())
+    }
+
+    /// In milliwatts. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_enforced_power_limit(device: sys::nvmlDevice_t) -> Result<u32, NvmlError> {
+        let mut limit = MaybeUninit::uninit();
+        lib()
+            .nvmlDeviceGetEnforcedPowerLimit(device, limit.as_mut_ptr())
+            .result()?;
+        Ok(limit.assume_init())
+    }
+
+    /// In MHz. See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_clock_info(
+        device: sys::nvmlDevice_t,
+        clock: sys::nvmlClockType_t,
+    ) -> Result<u32, NvmlError> {
+        let mut mhz = MaybeUninit::uninit();
+        lib()
+            .nvmlDeviceGetClockInfo(device, clock, mhz.as_mut_ptr())
+            .result()?;
+        Ok(mhz.assume_init())
+    }
+
+    /// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_utilization_rates(
+        device: sys::nvmlDevice_t,
+    ) -> Result<sys::nvmlUtilization_t, NvmlError> {
+        let mut utilization = MaybeUninit::uninit();
+        lib()
+            .nvmlDeviceGetUtilizationRates(device, utilization.as_mut_ptr())
+            .result()?;
+        Ok(utilization.assume_init())
+    }
+
+    /// See [nvidia docs](https://docs.nvidia.com/deploy/nvml-api/group__nvmlDeviceQueries.html)
+    ///
+    /// # Safety
+    /// `device` must be a valid handle.
+    pub unsafe fn get_memory_info(
+        device: sys::nvmlDevice_t,
+    ) -> Result<sys::nvmlMemory_t, NvmlError> {
+        let mut memory = MaybeUninit::uninit();
+        lib()
+            .nvmlDeviceGetMemoryInfo(device, memory.as_mut_ptr())
+            .result()?;
+        Ok(memory.assume_init())
+    }
+}
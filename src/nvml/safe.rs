@@ -0,0 +1,417 @@
+use super::result::{self, NvmlError};
+use super::sys;
+use crate::driver::{result as driver_result, safe::error_handler::log_library_error, CudaDevice};
+
+use std::ffi::CString;
+use std::string::String;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// An initialized NVML, which is shut down when the last [Arc] of it and the
+/// [NvmlDevice]s it returned are dropped.
+#[derive(Debug)]
+pub struct Nvml(());
+
+impl Nvml {
+    /// Initializes NVML. Fails with [NvmlError::LibraryVersionMismatch] if libnvidia-ml
+    /// doesn't match the kernel driver.
+    pub fn init() -> Result<Arc<Self>, NvmlError> {
+        result::init()?;
+        Ok(Arc::new(Self(())))
+    }
+
+    /// The version of the kernel driver, e.g. `"550.54.15"`.
+    pub fn driver_version(&self) -> Result<String, NvmlError> {
+        result::system::get_driver_version()
+    }
+
+    /// The CUDA driver version as `1000 * major + 10 * minor`.
+    pub fn cuda_driver_version(&self) -> Result<i32, NvmlError> {
+        result::system::get_cuda_driver_version()
+    }
+
+    pub fn device_count(&self) -> Result<u32, NvmlError> {
+        result::device::get_count()
+    }
+
+    /// The device at `index`, which is **not** necessarily the device of the same
+    /// ordinal for the driver API, see [CudaDevice::nvml()] for that.
+    pub fn device_by_index(self: &Arc<Self>, index: u32) -> Result<NvmlDevice, NvmlError> {
+        let handle = result::device::get_handle_by_index(index)?;
+        Ok(NvmlDevice {
+            handle,
+            nvml: self.clone(),
+        })
+    }
+
+    /// The device whose UUID is `uuid`, formatted like [NvmlDevice::uuid()].
+    pub fn device_by_uuid(self: &Arc<Self>, uuid: &str) -> Result<NvmlDevice, NvmlError> {
+        let uuid = CString::new(uuid)
+            .map_err(|_| NvmlError::Nvml(sys::nvmlReturn_t::NVML_ERROR_INVALID_ARGUMENT))?;
+        let handle = result::device::get_handle_by_uuid(&uuid)?;
+        Ok(NvmlDevice {
+            handle,
+            nvml: self.clone(),
+        })
+    }
+}
+
+impl Drop for Nvml {
+    fn drop(&mut self) {
+        // nothing can be done about a failed shutdown but to report it
+        if let Err(err) = unsafe { result::shutdown() } {
+            log_library_error(&err, "Nvml::drop");
+        }
+    }
+}
+
+/// A device as seen by NVML, for reading its telemetry.
+#[derive(Debug, Clone)]
+pub struct NvmlDevice {
+    handle: sys::nvmlDevice_t,
+    nvml: Arc<Nvml>,
+}
+
+// NVML is thread safe, and the handle stays valid until NVML is shut down.
+unsafe impl Send for NvmlDevice {}
+unsafe impl Sync for NvmlDevice {}
+
+/// How busy a device was over the last sample period, in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Utilization {
+    /// Of time a kernel was running.
+    pub gpu: u32,
+    /// Of time device memory was read or written.
+    pub memory: u32,
+}
+
+/// Memory of a device, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryInfo {
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+}
+
+/// A snapshot of [NvmlDevice::telemetry()]. Values the device doesn't report are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Telemetry {
+    /// In degrees celsius.
+    pub temperature: Option<u32>,
+    /// In milliwatts.
+    pub power_usage: Option<u32>,
+    /// In milliwatts.
+    pub enforced_power_limit: Option<u32>,
+    /// In MHz.
+    pub sm_clock: Option<u32>,
+    /// In MHz.
+    pub memory_clock: Option<u32>,
+    pub utilization: Option<Utilization>,
+    pub memory: Option<MemoryInfo>,
+}
+
+impl NvmlDevice {
+    /// The raw handle, valid while `self` is alive.
+    pub fn handle(&self) -> sys::nvmlDevice_t {
+        self.handle
+    }
+
+    pub fn nvml(&self) -> &Arc<Nvml> {
+        &self.nvml
+    }
+
+    /// Formatted like `"GPU-5c3a1d2e-8f4b-..."`.
+    pub fn uuid(&self) -> Result<String, NvmlError> {
+        unsafe { result::device::get_uuid(self.handle) }
+    }
+
+    pub fn name(&self) -> Result<String, NvmlError> {
+        unsafe { result::device::get_name(self.handle) }
+    }
+
+    /// The temperature of the GPU die, in degrees celsius.
+    pub fn temperature(&self) -> Result<u32, NvmlError> {
+        unsafe {
+            result::device::get_temperature(
+                self.handle,
+                sys::nvmlTemperatureSensors_t::NVML_TEMPERATURE_GPU,
+            )
+        }
+    }
+
+    /// The power draw of the whole board, in milliwatts.
+    pub fn power_usage(&self) -> Result<u32, NvmlError> {
+        unsafe { result::device::get_power_usage(self.handle) }
+    }
+
+    /// The power limit the device is held to, in milliwatts.
+    pub fn enforced_power_limit(&self) -> Result<u32, NvmlError> {
+        unsafe { result::device::get_enforced_power_limit(self.handle) }
+    }
+
+    /// The current SM clock, in MHz.
+    pub fn sm_clock(&self) -> Result<u32, NvmlError> {
+        unsafe { result::device::get_clock_info(self.handle, sys::nvmlClockType_t::NVML_CLOCK_SM) }
+    }
+
+    /// The current memory clock, in MHz.
+    pub fn memory_clock(&self) -> Result<u32, NvmlError> {
+        unsafe { result::device::get_clock_info(self.handle, sys::nvmlClockType_t::NVML_CLOCK_MEM) }
+    }
+
+    pub fn utilization(&self) -> Result<Utilization, NvmlError> {
+        let sys::nvmlUtilization_t { gpu, memory } =
+            unsafe { result::device::get_utilization_rates(self.handle) }?;
+        Ok(Utilization { gpu, memory })
+    }
+
+    pub fn memory_info(&self) -> Result<MemoryInfo, NvmlError> {
+        let sys::nvmlMemory_t { total, free, used } =
+            unsafe { result::device::get_memory_info(self.handle) }?;
+        Ok(MemoryInfo { total, free, used })
+    }
+
+    /// Reads all of the getters above. Values the device doesn't support, or that need
+    /// more permissions, are `None`; other errors fail the snapshot.
+    pub fn telemetry(&self) -> Result<Telemetry, NvmlError> {
+        Ok(Telemetry {
+            temperature: supported(self.temperature())?,
+            power_usage: supported(self.power_usage())?,
+            enforced_power_limit: supported(self.enforced_power_limit())?,
+            sm_clock: supported(self.sm_clock())?,
+            memory_clock: supported(self.memory_clock())?,
+            utilization: supported(self.utilization())?,
+            memory: supported(self.memory_info())?,
+        })
+    }
+}
+
+fn supported<T>(res: Result<T, NvmlError>) -> Result<Option<T>, NvmlError> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(NvmlError::Nvml(
+            sys::nvmlReturn_t::NVML_ERROR_NOT_SUPPORTED
+            | sys::nvmlReturn_t::NVML_ERROR_NO_PERMISSION,
+        )) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Formats a driver API uuid the way NVML does.
+fn format_uuid(bytes: [u8; 16]) -> String {
+    let mut uuid = String::from("GPU-");
+    for (i, b) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            uuid.push('-');
+        }
+        uuid.push_str(&std::format!("{b:02x}"));
+    }
+    uuid
+}
+
+impl CudaDevice {
+    /// The [NvmlDevice] of this device, matched by UUID since NVML orders devices by
+    /// PCI bus id regardless of `CUDA_VISIBLE_DEVICES`.
+    ///
+    /// Initializes NVML, which is shut down once the returned device is dropped. Fails
+    /// with [NvmlError::DriverVersionMismatch] if NVML and the driver API were loaded
+    /// from different driver installs.
+    pub fn nvml(&self) -> Result<NvmlDevice, NvmlError> {
+        let nvml = Nvml::init()?;
+        let driver = driver_result::driver_version()?;
+        let nvml_version = nvml.cuda_driver_version()?;
+        if nvml_version != driver {
+            return Err(NvmlError::DriverVersionMismatch {
+                nvml: nvml_version,
+                driver,
+            });
+        }
+        let uuid = format_uuid(driver_result::device::get_uuid(self.cu_device)?);
+        nvml.device_by_uuid(&uuid).map_err(|err| match err {
+            NvmlError::Nvml(sys::nvmlReturn_t::NVML_ERROR_NOT_FOUND) => NvmlError::DeviceNotFound,
+            err => err,
+        })
+    }
+}
+
+type TelemetryCallback = std::boxed::Box<dyn FnMut(&Result<Telemetry, NvmlError>) + Send>;
+
+#[derive(Debug, Default)]
+struct PollerState {
+    stopped: bool,
+    latest: Option<Result<Telemetry, NvmlError>>,
+}
+
+/// Reads the [Telemetry] of a device every `interval` on a background thread, and calls
+/// a callback whenever it differs from the previous reading. Stops when dropped.
+///
+/// ```rust
+/// # use cudarc::driver::*;
+/// # use std::time::Duration;
+/// let dev = CudaDevice::new(0).unwrap();
+/// let poller = cudarc::nvml::TelemetryPoller::start(
+///     dev.nvml().unwrap(),
+///     Duration::from_millis(100),
+///     |telemetry| println!("{telemetry:?}"),
+/// );
+/// std::thread::sleep(Duration::from_millis(250));
+/// poller.stop();
+/// ```
+#[derive(Debug)]
+pub struct TelemetryPoller {
+    state: Arc<(Mutex<PollerState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TelemetryPoller {
+    /// Reads `device` right away, then every `interval`. `on_change` is called with the
+    /// first reading, and then with each one that differs from the one before.
+    pub fn start(
+        device: NvmlDevice,
+        interval: Duration,
+        on_change: impl FnMut(&Result<Telemetry, NvmlError>) + Send + 'static,
+    ) -> Self {
+        let state = Arc::new((Mutex::new(PollerState::default()), Condvar::new()));
+        let thread_state = state.clone();
+        let mut on_change: TelemetryCallback = std::boxed::Box::new(on_change);
+        let thread = std::thread::spawn(move || {
+            let (lock, cvar) = &*thread_state;
+            loop {
+                let reading = device.telemetry();
+                let mut state = lock.lock().unwrap();
+                if state.stopped {
+                    return;
+                }
+                let changed = state.latest.as_ref() != Some(&reading);
+                state.latest = Some(reading.clone());
+                if changed {
+                    // not holding the lock, so the callback may call `latest()`
+                    drop(state);
+                    on_change(&reading);
+                    state = lock.lock().unwrap();
+                }
+                let (state, _) = cvar
+                    .wait_timeout_while(state, interval, |state| !state.stopped)
+                    .unwrap();
+                if state.stopped {
+                    return;
+                }
+            }
+        });
+        Self {
+            state,
+            thread: Some(thread),
+        }
+    }
+
+    /// The last reading, or `None` before the first one.
+    pub fn latest(&self) -> Option<Result<Telemetry, NvmlError>> {
+        self.state.0.lock().unwrap().latest.clone()
+    }
+
+    /// Stops polling, and waits for a running callback to return.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().stopped = true;
+        cvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for TelemetryPoller {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_uuid() {
+        let bytes = [
+            0x5c, 0x3a, 0x1d, 0x2e, 0x8f, 0x4b, 0x11, 0xee, 0x90, 0x01, 0xab, 0xcd, 0xef, 0x01,
+            0x23, 0x45,
+        ];
+        assert_eq!(
+            format_uuid(bytes),
+            "GPU-5c3a1d2e-8f4b-11ee-9001-abcdef012345"
+        );
+    }
+
+    #[test]
+    fn test_version_mismatch_message() {
+        let err = NvmlError::DriverVersionMismatch {
+            nvml: 12040,
+            driver: 12060,
+        };
+        let msg = std::format!("{err}");
+        assert!(msg.contains("12.4") && msg.contains("12.6"), "{msg}");
+        assert_eq!(
+            sys::nvmlReturn_t::NVML_ERROR_LIB_RM_VERSION_MISMATCH.result(),
+            Err(NvmlError::LibraryVersionMismatch)
+        );
+    }
+
+    #[test]
+    fn test_device_matches_uuid() {
+        let dev = CudaDevice::new(0).unwrap();
+        let nvml_dev = dev.nvml().unwrap();
+        let uuid = format_uuid(driver_result::device::get_uuid(dev.cu_device).unwrap());
+        assert_eq!(nvml_dev.uuid().unwrap(), uuid);
+        assert!(!nvml_dev.name().unwrap().is_empty());
+
+        let nvml = nvml_dev.nvml().clone();
+        let count = nvml.device_count().unwrap();
+        let by_index = (0..count)
+            .map(|i| nvml.device_by_index(i).unwrap().uuid().unwrap())
+            .filter(|u| *u == uuid)
+            .count();
+        assert_eq!(by_index, 1);
+        assert_eq!(
+            nvml.device_by_uuid("GPU-not-a-device").unwrap_err(),
+            NvmlError::Nvml(sys::nvmlReturn_t::NVML_ERROR_NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn test_telemetry() {
+        let dev = CudaDevice::new(0).unwrap();
+        let telemetry = dev.nvml().unwrap().telemetry().unwrap();
+        let memory = telemetry.memory.unwrap();
+        let total = unsafe { driver_result::device::total_mem(dev.cu_device) }.unwrap();
+        assert_eq!(memory.total, total as u64);
+        assert!(memory.used <= memory.total);
+        if let Some(utilization) = telemetry.utilization {
+            assert!(utilization.gpu <= 100 && utilization.memory <= 100);
+        }
+    }
+
+    #[test]
+    fn test_poller_reports_first_reading() {
+        let dev = CudaDevice::new(0).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let poller = TelemetryPoller::start(
+            dev.nvml().unwrap(),
+            Duration::from_millis(10),
+            move |reading| {
+                let _ = tx.send(reading.clone());
+            },
+        );
+        let first = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(first.is_ok());
+        assert!(poller.latest().is_some());
+        poller.stop();
+    }
+}
@@ -0,0 +1,404 @@
+//! Bindings to the parts of `nvml.h` used by [super::safe]. The NVML API these use is the
+//! same for every supported driver, so there is a single set of bindings.
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+pub enum nvmlReturn_t {
+    NVML_SUCCESS = 0,
+    NVML_ERROR_UNINITIALIZED = 1,
+    NVML_ERROR_INVALID_ARGUMENT = 2,
+    NVML_ERROR_NOT_SUPPORTED = 3,
+    NVML_ERROR_NO_PERMISSION = 4,
+    NVML_ERROR_ALREADY_INITIALIZED = 5,
+    NVML_ERROR_NOT_FOUND = 6,
+    NVML_ERROR_INSUFFICIENT_SIZE = 7,
+    NVML_ERROR_INSUFFICIENT_POWER = 8,
+    NVML_ERROR_DRIVER_NOT_LOADED = 9,
+    NVML_ERROR_TIMEOUT = 10,
+    NVML_ERROR_IRQ_ISSUE = 11,
+    NVML_ERROR_LIBRARY_NOT_FOUND = 12,
+    NVML_ERROR_FUNCTION_NOT_FOUND = 13,
+    NVML_ERROR_CORRUPTED_INFOROM = 14,
+    NVML_ERROR_GPU_IS_LOST = 15,
+    NVML_ERROR_RESET_REQUIRED = 16,
+    NVML_ERROR_OPERATING_SYSTEM = 17,
+    NVML_ERROR_LIB_RM_VERSION_MISMATCH = 18,
+    NVML_ERROR_IN_USE = 19,
+    NVML_ERROR_MEMORY = 20,
+    NVML_ERROR_NO_DATA = 21,
+    NVML_ERROR_VGPU_ECC_NOT_SUPPORTED = 22,
+    NVML_ERROR_INSUFFICIENT_RESOURCES = 23,
+    NVML_ERROR_FREQ_NOT_SUPPORTED = 24,
+    NVML_ERROR_ARGUMENT_VERSION_MISMATCH = 25,
+    NVML_ERROR_DEPRECATED = 26,
+    NVML_ERROR_NOT_READY = 27,
+    NVML_ERROR_GPU_NOT_FOUND = 28,
+    NVML_ERROR_INVALID_STATE = 29,
+    NVML_ERROR_UNKNOWN = 999,
+}
+
+pub type nvmlDevice_t = *mut ::core::ffi::c_void;
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+pub enum nvmlTemperatureSensors_t {
+    NVML_TEMPERATURE_GPU = 0,
+}
+
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+pub enum nvmlClockType_t {
+    NVML_CLOCK_GRAPHICS = 0,
+    NVML_CLOCK_SM = 1,
+    NVML_CLOCK_MEM = 2,
+    NVML_CLOCK_VIDEO = 3,
+}
+
+/// Percent of time over the last sample period that the device was busy.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct nvmlUtilization_t {
+    pub gpu: ::core::ffi::c_uint,
+    pub memory: ::core::ffi::c_uint,
+}
+
+/// Memory of the device, in bytes.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct nvmlMemory_t {
+    pub total: ::core::ffi::c_ulonglong,
+    pub free: ::core::ffi::c_ulonglong,
+    pub used: ::core::ffi::c_ulonglong,
+}
+
+/// Buffer sizes from `nvml.h`.
+pub const NVML_DEVICE_UUID_V2_BUFFER_SIZE: u32 = 96;
+pub const NVML_DEVICE_NAME_V2_BUFFER_SIZE: u32 = 96;
+pub const NVML_SYSTEM_DRIVER_VERSION_BUFFER_SIZE: u32 = 80;
+
+pub struct Lib {
+    __library: ::libloading::Library,
+    pub nvmlInit_v2: Result<unsafe extern "C" fn() -> nvmlReturn_t, ::libloading::Error>,
+    pub nvmlShutdown: Result<unsafe extern "C" fn() -> nvmlReturn_t, ::libloading::Error>,
+    pub nvmlErrorString: Result<
+        unsafe extern "C" fn(result: nvmlReturn_t) -> *const ::core::ffi::c_char,
+        ::libloading::Error,
+    >,
+    pub nvmlSystemGetDriverVersion: Result<
+        unsafe extern "C" fn(
+            version: *mut ::core::ffi::c_char,
+            length: ::core::ffi::c_uint,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlSystemGetCudaDriverVersion_v2: Result<
+        unsafe extern "C" fn(cudaDriverVersion: *mut ::core::ffi::c_int) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetCount_v2: Result<
+        unsafe extern "C" fn(deviceCount: *mut ::core::ffi::c_uint) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetHandleByIndex_v2: Result<
+        unsafe extern "C" fn(index: ::core::ffi::c_uint, device: *mut nvmlDevice_t) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetHandleByUUID: Result<
+        unsafe extern "C" fn(
+            uuid: *const ::core::ffi::c_char,
+            device: *mut nvmlDevice_t,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetUUID: Result<
+        unsafe extern "C" fn(
+            device: nvmlDevice_t,
+            uuid: *mut ::core::ffi::c_char,
+            length: ::core::ffi::c_uint,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetName: Result<
+        unsafe extern "C" fn(
+            device: nvmlDevice_t,
+            name: *mut ::core::ffi::c_char,
+            length: ::core::ffi::c_uint,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetTemperature: Result<
+        unsafe extern "C" fn(
+            device: nvmlDevice_t,
+            sensorType: nvmlTemperatureSensors_t,
+            temp: *mut ::core::ffi::c_uint,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetPowerUsage: Result<
+        unsafe extern "C" fn(device: nvmlDevice_t, power: *mut ::core::ffi::c_uint) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetEnforcedPowerLimit: Result<
+        unsafe extern "C" fn(device: nvmlDevice_t, limit: *mut ::core::ffi::c_uint) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetClockInfo: Result<
+        unsafe extern "C" fn(
+            device: nvmlDevice_t,
+            type_: nvmlClockType_t,
+            clock: *mut ::core::ffi::c_uint,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetUtilizationRates: Result<
+        unsafe extern "C" fn(
+            device: nvmlDevice_t,
+            utilization: *mut nvmlUtilization_t,
+        ) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+    pub nvmlDeviceGetMemoryInfo: Result<
+        unsafe extern "C" fn(device: nvmlDevice_t, memory: *mut nvmlMemory_t) -> nvmlReturn_t,
+        ::libloading::Error,
+    >,
+}
+
+impl Lib {
+    pub unsafe fn new<P>(path: P) -> Result<Self, ::libloading::Error>
+    where
+        P: AsRef<::std::ffi::OsStr>,
+    {
+        let library = ::libloading::Library::new(path)?;
+        Self::from_library(library)
+    }
+    pub unsafe fn from_library<L>(library: L) -> Result<Self, ::libloading::Error>
+    where
+        L: Into<::libloading::Library>,
+    {
+        let __library = library.into();
+        let nvmlInit_v2 = __library.get(b"nvmlInit_v2\0").map(|sym| *sym);
+        let nvmlShutdown = __library.get(b"nvmlShutdown\0").map(|sym| *sym);
+        let nvmlErrorString = __library.get(b"nvmlErrorString\0").map(|sym| *sym);
+        let nvmlSystemGetDriverVersion = __library
+            .get(b"nvmlSystemGetDriverVersion\0")
+            .map(|sym| *sym);
+        let nvmlSystemGetCudaDriverVersion_v2 = __library
+            .get(b"nvmlSystemGetCudaDriverVersion_v2\0")
+            .map(|sym| *sym);
+        let nvmlDeviceGetCount_v2 = __library.get(b"nvmlDeviceGetCount_v2\0").map(|sym| *sym);
+        let nvmlDeviceGetHandleByIndex_v2 = __library
+            .get(b"nvmlDeviceGetHandleByIndex_v2\0")
+            .map(|sym| *sym);
+        let nvmlDeviceGetHandleByUUID = __library
+            .get(b"nvmlDeviceGetHandleByUUID\0")
+            .map(|sym| *sym);
+        let nvmlDeviceGetUUID = __library.get(b"nvmlDeviceGetUUID\0").map(|sym| *sym);
+        let nvmlDeviceGetName = __library.get(b"nvmlDeviceGetName\0").map(|sym| *sym);
+        let nvmlDeviceGetTemperature = __library.get(b"nvmlDeviceGetTemperature\0").map(|sym| *sym);
+        let nvmlDeviceGetPowerUsage = __library.get(b"nvmlDeviceGetPowerUsage\0").map(|sym| *sym);
+        let nvmlDeviceGetEnforcedPowerLimit = __library
+            .get(b"nvmlDeviceGetEnforcedPowerLimit\0")
+            .map(|sym| *sym);
+        let nvmlDeviceGetClockInfo = __library.get(b"nvmlDeviceGetClockInfo\0").map(|sym| *sym);
+        let nvmlDeviceGetUtilizationRates = __library
+            .get(b"nvmlDeviceGetUtilizationRates\0")
+            .map(|sym| *sym);
+        let nvmlDeviceGetMemoryInfo = __library.get(b"nvmlDeviceGetMemoryInfo\0").map(|sym| *sym);
+        Ok(Lib {
+            __library,
+            nvmlInit_v2,
+            nvmlShutdown,
+            nvmlErrorString,
+            nvmlSystemGetDriverVersion,
+            nvmlSystemGetCudaDriverVersion_v2,
+            nvmlDeviceGetCount_v2,
+            nvmlDeviceGetHandleByIndex_v2,
+            nvmlDeviceGetHandleByUUID,
+            nvmlDeviceGetUUID,
+            nvmlDeviceGetName,
+            nvmlDeviceGetTemperature,
+            nvmlDeviceGetPowerUsage,
+            nvmlDeviceGetEnforcedPowerLimit,
+            nvmlDeviceGetClockInfo,
+            nvmlDeviceGetUtilizationRates,
+            nvmlDeviceGetMemoryInfo,
+        })
+    }
+    pub unsafe fn nvmlInit_v2(&self) -> nvmlReturn_t {
+        (self
+            .nvmlInit_v2
+            .as_ref()
+            .expect("Expected function, got error."))()
+    }
+    pub unsafe fn nvmlShutdown(&self) -> nvmlReturn_t {
+        (self
+            .nvmlShutdown
+            .as_ref()
+            .expect("Expected function, got error."))()
+    }
+    pub unsafe fn nvmlErrorString(&self, result: nvmlReturn_t) -> *const ::core::ffi::c_char {
+        (self
+            .nvmlErrorString
+            .as_ref()
+            .expect("Expected function, got error."))(result)
+    }
+    pub unsafe fn nvmlSystemGetDriverVersion(
+        &self,
+        version: *mut ::core::ffi::c_char,
+        length: ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlSystemGetDriverVersion
+            .as_ref()
+            .expect("Expected function, got error."))(version, length)
+    }
+    pub unsafe fn nvmlSystemGetCudaDriverVersion_v2(
+        &self,
+        cudaDriverVersion: *mut ::core::ffi::c_int,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlSystemGetCudaDriverVersion_v2
+            .as_ref()
+            .expect("Expected function, got error."))(cudaDriverVersion)
+    }
+    pub unsafe fn nvmlDeviceGetCount_v2(
+        &self,
+        deviceCount: *mut ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetCount_v2
+            .as_ref()
+            .expect("Expected function, got error."))(deviceCount)
+    }
+    pub unsafe fn nvmlDeviceGetHandleByIndex_v2(
+        &self,
+        index: ::core::ffi::c_uint,
+        device: *mut nvmlDevice_t,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetHandleByIndex_v2
+            .as_ref()
+            .expect("Expected function, got error."))(index, device)
+    }
+    pub unsafe fn nvmlDeviceGetHandleByUUID(
+        &self,
+        uuid: *const ::core::ffi::c_char,
+        device: *mut nvmlDevice_t,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetHandleByUUID
+            .as_ref()
+            .expect("Expected function, got error."))(uuid, device)
+    }
+    pub unsafe fn nvmlDeviceGetUUID(
+        &self,
+        device: nvmlDevice_t,
+        uuid: *mut ::core::ffi::c_char,
+        length: ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetUUID
+            .as_ref()
+            .expect("Expected function, got error."))(device, uuid, length)
+    }
+    pub unsafe fn nvmlDeviceGetName(
+        &self,
+        device: nvmlDevice_t,
+        name: *mut ::core::ffi::c_char,
+        length: ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetName
+            .as_ref()
+            .expect("Expected function, got error."))(device, name, length)
+    }
+    pub unsafe fn nvmlDeviceGetTemperature(
+        &self,
+        device: nvmlDevice_t,
+        sensorType: nvmlTemperatureSensors_t,
+        temp: *mut ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetTemperature
+            .as_ref()
+            .expect("Expected function, got error."))(device, sensorType, temp)
+    }
+    pub unsafe fn nvmlDeviceGetPowerUsage(
+        &self,
+        device: nvmlDevice_t,
+        power: *mut ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetPowerUsage
+            .as_ref()
+            .expect("Expected function, got error."))(device, power)
+    }
+    pub unsafe fn nvmlDeviceGetEnforcedPowerLimit(
+        &self,
+        device: nvmlDevice_t,
+        limit: *mut ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetEnforcedPowerLimit
+            .as_ref()
+            .expect("Expected function, got error."))(device, limit)
+    }
+    pub unsafe fn nvmlDeviceGetClockInfo(
+        &self,
+        device: nvmlDevice_t,
+        type_: nvmlClockType_t,
+        clock: *mut ::core::ffi::c_uint,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetClockInfo
+            .as_ref()
+            .expect("Expected function, got error."))(device, type_, clock)
+    }
+    pub unsafe fn nvmlDeviceGetUtilizationRates(
+        &self,
+        device: nvmlDevice_t,
+        utilization: *mut nvmlUtilization_t,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetUtilizationRates
+            .as_ref()
+            .expect("Expected function, got error."))(device, utilization)
+    }
+    pub unsafe fn nvmlDeviceGetMemoryInfo(
+        &self,
+        device: nvmlDevice_t,
+        memory: *mut nvmlMemory_t,
+    ) -> nvmlReturn_t {
+        (self
+            .nvmlDeviceGetMemoryInfo
+            .as_ref()
+            .expect("Expected function, got error."))(device, memory)
+    }
+}
+
+/// libnvidia-ml is installed with the driver rather than the toolkit, and only versioned
+/// by its soname.
+const LIB_NAMES: &[&str] = &["libnvidia-ml.so", "libnvidia-ml.so.1", "nvml.dll"];
+
+/// The NVML library, or `None` if it isn't installed.
+pub unsafe fn try_lib() -> Option<&'static Lib> {
+    static LIB: std::sync::OnceLock<Option<Lib>> = std::sync::OnceLock::new();
+    LIB.get_or_init(|| {
+        let choices = crate::get_lib_name_candidates("nvidia-ml");
+        let extra = LIB_NAMES
+            .iter()
+            .map(|name| std::string::String::from(*name));
+        choices
+            .into_iter()
+            .chain(extra)
+            .find_map(|choice| Lib::new(choice).ok())
+    })
+    .as_ref()
+}
+
+pub unsafe fn lib() -> &'static Lib {
+    match try_lib() {
+        Some(lib) => lib,
+        None => crate::panic_no_lib_found("nvidia-ml", LIB_NAMES),
+    }
+}
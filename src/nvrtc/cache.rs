@@ -0,0 +1,524 @@
+//! An on-disk cache of compiled [Ptx], so kernels compiled at runtime aren't recompiled
+//! on every run.
+//!
+//! The cache can be shared by many processes, e.g. all the ranks of a training job on a
+//! shared filesystem:
+//! - entries are written to a temporary file which is then renamed over the entry, so
+//!   readers see either the old or the new entry, never a partial one.
+//! - every entry starts with a header holding its length and a checksum, so truncated or
+//!   corrupted entries are detected and ignored instead of being loaded.
+//! - writers of an entry take an advisory lock on a `.lock` file next to it, with
+//!   [std::fs::File::lock()], i.e. `flock` on Unix and `LockFileEx` on Windows, so a kernel
+//!   is compiled by one process while the others wait for its result. Locks are released
+//!   when the process holding them exits; a lock held for longer than the lock timeout is
+//!   considered stale and ignored.
+//!
+//! The same entry format is used by the files of `cudnn::AlgoCache::save()`.
+
+use super::safe::{compile_key, compile_ptx_with_opts, CompileError, CompileOptions, Ptx};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    string::String,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime},
+    vec::Vec,
+};
+
+/// The start of every entry, followed by the length of the payload and its checksum as
+/// little endian `u64`s.
+const MAGIC: [u8; 8] = *b"cudarc\0\x01";
+const HEADER_LEN: usize = 24;
+
+/// How long [PtxCache] waits for the lock of an entry by default.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a contended lock is retried.
+const LOCK_POLL: Duration = Duration::from_millis(5);
+
+/// FNV-1a, which is enough to detect truncated and corrupted entries.
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// `payload` prefixed with its header.
+pub(crate) fn encode_entry(payload: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(HEADER_LEN + payload.len());
+    entry.extend_from_slice(&MAGIC);
+    entry.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    entry.extend_from_slice(&checksum(payload).to_le_bytes());
+    entry.extend_from_slice(payload);
+    entry
+}
+
+/// The payload of `entry`, or `None` if its header is missing or doesn't match it.
+pub(crate) fn decode_entry(entry: &[u8]) -> Option<&[u8]> {
+    if entry.len() < HEADER_LEN || entry[..8] != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+    let sum = u64::from_le_bytes(entry[16..24].try_into().unwrap());
+    let payload = &entry[HEADER_LEN..];
+    (payload.len() as u64 == len && checksum(payload) == sum).then_some(payload)
+}
+
+/// The payload of the entry at `path`, or `None` if there is none or it's corrupted.
+pub(crate) fn read_entry(path: &Path) -> Option<Vec<u8>> {
+    let entry = fs::read(path).ok()?;
+    decode_entry(&entry).map(|payload| payload.to_vec())
+}
+
+/// Atomically replaces the entry at `path` with `payload`, creating its directory.
+pub(crate) fn write_entry(path: &Path, payload: &[u8]) -> io::Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = PathBuf::from(std::format!(
+        "{}.{}.{}.tmp",
+        path.display(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = fs::write(&tmp, encode_entry(payload)).and_then(|_| fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+/// The lock file of the entry at `path`.
+pub(crate) fn lock_path(path: &Path) -> PathBuf {
+    PathBuf::from(std::format!("{}.lock", path.display()))
+}
+
+/// Whether the file at `path` was last modified more than `timeout` ago.
+pub(crate) fn is_stale(path: &Path, timeout: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > timeout)
+}
+
+/// Checks the entry at `path` with `is_valid`, and removes it if it fails. A failing entry
+/// is checked again while holding its lock, since a writer may be replacing it.
+pub(crate) fn verify_entry(
+    path: &Path,
+    timeout: Duration,
+    is_valid: impl Fn(&[u8]) -> bool,
+    report: &mut CacheReport,
+) -> io::Result<()> {
+    let check = || read_entry(path).is_some_and(|payload| is_valid(&payload));
+    if check() {
+        report.valid += 1;
+        return Ok(());
+    }
+    let _lock = EntryLock::acquire(&lock_path(path), timeout)?;
+    if check() {
+        report.valid += 1;
+    } else if fs::remove_file(path).is_ok() {
+        report.corrupt += 1;
+    }
+    Ok(())
+}
+
+/// An exclusive advisory lock on a file, released on drop.
+///
+/// Lock files are never removed: a process could be holding the lock of the removed file
+/// while another one locks a new file at the same path.
+#[derive(Debug)]
+pub(crate) struct EntryLock {
+    #[allow(unused)]
+    file: fs::File,
+}
+
+impl EntryLock {
+    /// Locks the file at `path`, creating it and its directory. Returns `None` if it's
+    /// still locked by another process or thread after `timeout`.
+    pub(crate) fn acquire(path: &Path, timeout: Duration) -> io::Result<Option<Self>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        let start = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Some(Self { file })),
+                Err(fs::TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    std::thread::sleep(LOCK_POLL)
+                }
+                Err(fs::TryLockError::WouldBlock) => return Ok(None),
+                Err(fs::TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+/// What [PtxCache::verify()] found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CacheReport {
+    /// Entries that passed their checksum.
+    pub valid: usize,
+    /// Truncated or corrupted entries, which were removed.
+    pub corrupt: usize,
+    /// Temporary files left behind by writers that died, which were removed.
+    pub stale: usize,
+}
+
+/// A directory of compiled [Ptx], keyed by the source, the [CompileOptions] and the nvrtc
+/// version. Changing any of them changes the key, so those entries never go stale.
+///
+/// Headers found through [CompileOptions::include_paths] are not part of the key, only their
+/// paths are: after editing a header, bump something in the source (e.g. a version comment)
+/// or [PtxCache::purge()] the cache, or the kernel compiled against the old header is loaded.
+///
+/// It's safe to share between processes, see the [module docs](self).
+///
+/// ```rust
+/// # use cudarc::nvrtc::*;
+/// let cache = PtxCache::new(std::env::temp_dir().join("cudarc-ptx"));
+/// let ptx = cache
+///     .compile("extern \"C\" __global__ void kernel() { }", Default::default())
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PtxCache {
+    dir: PathBuf,
+    lock_timeout: Duration,
+}
+
+impl PtxCache {
+    /// A cache stored in `dir`, which is created when the first entry is written.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for another process compiling the same entry before compiling it
+    /// anyway, [DEFAULT_LOCK_TIMEOUT] by default. Also the age after which
+    /// [PtxCache::verify()] removes temporary files.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// The directory the cache is stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cached [Ptx] for `src` and `opts`, compiling and storing it if there is none.
+    ///
+    /// Failing to read or write the cache is not an error, the source is compiled instead.
+    pub fn compile<S: AsRef<str>>(
+        &self,
+        src: S,
+        opts: CompileOptions,
+    ) -> Result<Ptx, CompileError> {
+        let path = self.path(compile_key(src.as_ref(), &opts));
+        if let Some(ptx) = Self::read(&path) {
+            return Ok(ptx);
+        }
+        let _lock = EntryLock::acquire(&lock_path(&path), self.lock_timeout)
+            .ok()
+            .flatten();
+        // another process may have compiled it while we waited for the lock
+        if let Some(ptx) = Self::read(&path) {
+            return Ok(ptx);
+        }
+        let ptx = compile_ptx_with_opts(src, opts)?;
+        if let Ok(src) = ptx.to_src() {
+            let _ = write_entry(&path, src.as_bytes());
+        }
+        Ok(ptx)
+    }
+
+    /// Checks the checksum of every entry, removing the ones that fail it, and the
+    /// temporary files older than the lock timeout.
+    pub fn verify(&self) -> io::Result<CacheReport> {
+        let mut report = CacheReport::default();
+        for path in self.files()? {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.ends_with(".tmp") {
+                if is_stale(&path, self.lock_timeout) && fs::remove_file(&path).is_ok() {
+                    report.stale += 1;
+                }
+            } else if name.ends_with(".ptx") {
+                verify_entry(&path, self.lock_timeout, |_| true, &mut report)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Removes every entry, returning how many there were. Processes compiling at the same
+    /// time may still add theirs. The `.lock` files are kept, see [EntryLock].
+    pub fn purge(&self) -> io::Result<usize> {
+        let mut removed = 0;
+        for path in self.files()? {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let is_entry = name.ends_with(".ptx");
+            if is_entry || name.ends_with(".tmp") {
+                match fs::remove_file(&path) {
+                    Ok(()) => removed += is_entry as usize,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    fn read(path: &Path) -> Option<Ptx> {
+        let ptx = String::from_utf8(read_entry(path)?).ok()?;
+        Some(Ptx::from_src(ptx))
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(std::format!("{key:016x}.ptx"))
+    }
+
+    /// The files of the cache directory, if it exists.
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        match fs::read_dir(&self.dir) {
+            Ok(dir) => dir.map(|entry| entry.map(|e| e.path())).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(std::format!("cudarc-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_ptx_cache_reuses_entries() {
+        let dir = temp_dir("ptx-cache");
+        let cache = PtxCache::new(&dir);
+        let src = "extern \"C\" __global__ void cached(float *x) { x[0] = 1.0f; }";
+
+        let ptx = cache.compile(src, Default::default()).unwrap();
+        let entries = || {
+            fs::read_dir(&dir)
+                .unwrap()
+                .filter(|e| e.as_ref().unwrap().path().extension().unwrap() == "ptx")
+                .count()
+        };
+        assert_eq!(entries(), 1);
+        let cached = cache.compile(src, Default::default()).unwrap();
+        assert_eq!(ptx.to_src().unwrap(), cached.to_src().unwrap());
+
+        let opts = CompileOptions {
+            ftz: Some(true),
+            ..Default::default()
+        };
+        cache.compile(src, opts).unwrap();
+        assert_eq!(entries(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_entry_checksums() {
+        let payload = b"// ptx\n.version 8.0\n";
+        let entry = encode_entry(payload);
+        assert_eq!(decode_entry(&entry), Some(&payload[..]));
+        assert_eq!(decode_entry(&encode_entry(b"")), Some(&b""[..]));
+        for len in 0..entry.len() {
+            assert_eq!(decode_entry(&entry[..len]), None, "{len}");
+        }
+        let mut flipped = entry.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(decode_entry(&flipped), None);
+        let mut longer = entry.clone();
+        longer.push(0);
+        assert_eq!(decode_entry(&longer), None);
+        assert_eq!(decode_entry(payload), None);
+    }
+
+    #[test]
+    fn test_entry_lock_timeout() {
+        let dir = temp_dir("entry-lock");
+        let path = dir.join("a.ptx.lock");
+        let held = EntryLock::acquire(&path, Duration::ZERO).unwrap().unwrap();
+        let start = Instant::now();
+        let stale = EntryLock::acquire(&path, Duration::from_millis(50)).unwrap();
+        assert!(stale.is_none());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let waiter = std::thread::spawn(move || {
+            EntryLock::acquire(&path, Duration::from_secs(10))
+                .unwrap()
+                .is_some()
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        drop(held);
+        assert!(waiter.join().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A payload that can only be read back whole: its length and bytes depend on `seed`.
+    fn payload(seed: usize) -> Vec<u8> {
+        std::vec![seed as u8; 1000 + seed * 4099]
+    }
+
+    fn check_payload(bytes: &[u8]) {
+        let seed = bytes[0] as usize;
+        assert_eq!(bytes.len(), 1000 + seed * 4099, "truncated entry");
+        assert!(bytes.iter().all(|&b| b as usize == seed), "mixed entry");
+    }
+
+    /// Writes and reads back the same entry from many threads at once.
+    fn hammer(path: &Path, seeds: std::ops::Range<usize>, rounds: usize) -> usize {
+        let done = Arc::new(AtomicBool::new(false));
+        let reads = Arc::new(AtomicUsize::new(0));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let (path, done, reads) = (path.to_path_buf(), done.clone(), reads.clone());
+                std::thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        // the raw file is never partial either, thanks to the rename
+                        if let Ok(raw) = fs::read(&path) {
+                            let entry = decode_entry(&raw).expect("corrupt entry");
+                            check_payload(entry);
+                            reads.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+            })
+            .collect();
+        let writers: Vec<_> = seeds
+            .map(|seed| {
+                let path = path.to_path_buf();
+                std::thread::spawn(move || {
+                    for _ in 0..rounds {
+                        let _lock = EntryLock::acquire(&lock_path(&path), DEFAULT_LOCK_TIMEOUT)
+                            .unwrap()
+                            .unwrap();
+                        write_entry(&path, &payload(seed)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        check_payload(&read_entry(path).unwrap());
+        reads.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_concurrent_writers_and_readers() {
+        let dir = temp_dir("entry-hammer");
+        let path = dir.join("0123456789abcdef.ptx");
+        write_entry(&path, &payload(0)).unwrap();
+        assert!(hammer(&path, 1..9, 20) > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Re-runs itself in 4 child processes, which hammer the entry its parent created.
+    #[test]
+    fn test_concurrent_processes() {
+        if let Some(path) = std::env::var_os("CUDARC_CACHE_HAMMER_PATH") {
+            let seed: usize = std::env::var("CUDARC_CACHE_HAMMER_SEED")
+                .unwrap()
+                .parse()
+                .unwrap();
+            hammer(Path::new(&path), seed..seed + 2, 20);
+            return;
+        }
+        let dir = temp_dir("entry-processes");
+        let path = dir.join("0123456789abcdef.ptx");
+        write_entry(&path, &payload(0)).unwrap();
+        let children: Vec<_> = (0..4)
+            .map(|i| {
+                std::process::Command::new(std::env::current_exe().unwrap())
+                    .args(["--exact", "nvrtc::cache::tests::test_concurrent_processes"])
+                    .env("CUDARC_CACHE_HAMMER_PATH", &path)
+                    .env("CUDARC_CACHE_HAMMER_SEED", std::format!("{}", 1 + 2 * i))
+                    .stdout(std::process::Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+        let entry = read_entry(&path).unwrap();
+        check_payload(&entry);
+        assert_ne!(entry[0], 0, "the children didn't write");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_and_purge() {
+        let dir = temp_dir("ptx-verify");
+        let cache = PtxCache::new(&dir).with_lock_timeout(Duration::from_secs(1));
+        assert_eq!(cache.verify().unwrap(), CacheReport::default());
+        assert_eq!(cache.purge().unwrap(), 0);
+
+        for key in 0..3 {
+            write_entry(&cache.path(key), b"// ptx").unwrap();
+        }
+        // truncated by a writer that bypassed the cache
+        let truncated = encode_entry(b"// ptx that was cut short");
+        fs::write(cache.path(3), &truncated[..30]).unwrap();
+        fs::write(cache.path(4), "// an entry of an older version").unwrap();
+        let tmp = dir.join("0000000000000000.ptx.1.0.tmp");
+        fs::write(&tmp, &truncated[..10]).unwrap();
+        let fresh_tmp = dir.join("0000000000000000.ptx.2.0.tmp");
+        fs::write(&fresh_tmp, &truncated[..10]).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(&tmp)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let report = cache.verify().unwrap();
+        assert_eq!(
+            report,
+            CacheReport {
+                valid: 3,
+                corrupt: 2,
+                stale: 1,
+            }
+        );
+        assert!(!tmp.exists() && fresh_tmp.exists());
+        assert!(!cache.path(3).exists() && cache.path(2).exists());
+        assert_eq!(cache.verify().unwrap().corrupt, 0);
+
+        assert_eq!(cache.purge().unwrap(), 3);
+        let left: Vec<_> = cache.files().unwrap();
+        assert_eq!(left.len(), 2);
+        assert!(left.iter().all(|p| p.extension().unwrap() == "lock"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+//! Decompressors for [Ptx::from_compressed()](super::Ptx::from_compressed), calling into the
+//! system's `libzstd` and `libz`. They are loaded at runtime like the cuda libraries, so
+//! neither is needed at build time.
+
+use super::safe::{Compression, PtxError};
+
+use std::vec::Vec;
+
+impl Compression {
+    /// Decompresses all of `bytes`.
+    #[cfg_attr(not(any(feature = "zstd", feature = "gzip")), allow(unused_variables))]
+    pub(crate) fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>, PtxError> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::decompress(bytes),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => gzip::decompress(bytes),
+        }
+    }
+}
+
+#[cfg(any(feature = "zstd", feature = "gzip"))]
+fn load_library(compression: Compression, names: &[&str]) -> Result<libloading::Library, PtxError> {
+    names
+        .iter()
+        .find_map(|name| unsafe { libloading::Library::new(name) }.ok())
+        .ok_or(PtxError::DecompressorNotFound(compression))
+}
+
+/// Grows `out` so that it has spare capacity, returning it.
+#[cfg(any(feature = "zstd", feature = "gzip"))]
+fn spare(out: &mut Vec<u8>) -> &mut [core::mem::MaybeUninit<u8>] {
+    if out.len() == out.capacity() {
+        out.reserve(out.capacity().max(4096));
+    }
+    out.spare_capacity_mut()
+}
+
+#[cfg(feature = "zstd")]
+mod zstd {
+    use super::{load_library, spare, Compression, PtxError};
+
+    use core::ffi::{c_uint, c_void};
+    use std::vec::Vec;
+
+    #[repr(C)]
+    struct InBuffer {
+        src: *const c_void,
+        size: usize,
+        pos: usize,
+    }
+
+    #[repr(C)]
+    struct OutBuffer {
+        dst: *mut c_void,
+        size: usize,
+        pos: usize,
+    }
+
+    type CreateDCtx = unsafe extern "C" fn() -> *mut c_void;
+    type FreeDCtx = unsafe extern "C" fn(*mut c_void) -> usize;
+    type DecompressStream =
+        unsafe extern "C" fn(*mut c_void, *mut OutBuffer, *mut InBuffer) -> usize;
+    type IsError = unsafe extern "C" fn(usize) -> c_uint;
+
+    pub(super) fn decompress(bytes: &[u8]) -> Result<Vec<u8>, PtxError> {
+        let not_found = PtxError::DecompressorNotFound(Compression::Zstd);
+        let lib = load_library(
+            Compression::Zstd,
+            &[
+                "libzstd.so.1",
+                "libzstd.so",
+                "libzstd.1.dylib",
+                "libzstd.dylib",
+                "zstd.dll",
+                "libzstd.dll",
+            ],
+        )?;
+        let (create, free, decompress_stream, is_error) = unsafe {
+            (
+                *lib.get::<CreateDCtx>(b"ZSTD_createDCtx\0")
+                    .map_err(|_| not_found)?,
+                *lib.get::<FreeDCtx>(b"ZSTD_freeDCtx\0")
+                    .map_err(|_| not_found)?,
+                *lib.get::<DecompressStream>(b"ZSTD_decompressStream\0")
+                    .map_err(|_| not_found)?,
+                *lib.get::<IsError>(b"ZSTD_isError\0")
+                    .map_err(|_| not_found)?,
+            )
+        };
+
+        let ctx = unsafe { create() };
+        if ctx.is_null() {
+            return Err(not_found);
+        }
+        let mut input = InBuffer {
+            src: bytes.as_ptr() as *const c_void,
+            size: bytes.len(),
+            pos: 0,
+        };
+        let mut out = Vec::with_capacity(bytes.len() * 4);
+        let res = loop {
+            let buf = spare(&mut out);
+            let mut output = OutBuffer {
+                dst: buf.as_mut_ptr() as *mut c_void,
+                size: buf.len(),
+                pos: 0,
+            };
+            let ret = unsafe { decompress_stream(ctx, &mut output, &mut input) };
+            if unsafe { is_error(ret) } != 0 {
+                break Err(PtxError::Corrupt(Compression::Zstd));
+            }
+            unsafe { out.set_len(out.len() + output.pos) };
+            if input.pos == input.size {
+                if ret == 0 {
+                    break Ok(out);
+                }
+                // the frame isn't finished, but there is no input left to finish it
+                if output.pos < output.size {
+                    break Err(PtxError::Corrupt(Compression::Zstd));
+                }
+            }
+        };
+        unsafe { free(ctx) };
+        res
+    }
+}
+
+#[cfg(feature = "gzip")]
+mod gzip {
+    use super::{load_library, spare, Compression, PtxError};
+
+    use core::ffi::{c_char, c_int, c_uint, c_ulong, c_void};
+    use std::vec::Vec;
+
+    /// `z_stream` from `zlib.h`.
+    #[repr(C)]
+    struct ZStream {
+        next_in: *const u8,
+        avail_in: c_uint,
+        total_in: c_ulong,
+        next_out: *mut u8,
+        avail_out: c_uint,
+        total_out: c_ulong,
+        msg: *const c_char,
+        state: *mut c_void,
+        zalloc: *const c_void,
+        zfree: *const c_void,
+        opaque: *mut c_void,
+        data_type: c_int,
+        adler: c_ulong,
+        reserved: c_ulong,
+    }
+
+    type Version = unsafe extern "C" fn() -> *const c_char;
+    type InflateInit2 = unsafe extern "C" fn(*mut ZStream, c_int, *const c_char, c_int) -> c_int;
+    type Inflate = unsafe extern "C" fn(*mut ZStream, c_int) -> c_int;
+    type InflateEnd = unsafe extern "C" fn(*mut ZStream) -> c_int;
+
+    const Z_OK: c_int = 0;
+    const Z_STREAM_END: c_int = 1;
+    const Z_BUF_ERROR: c_int = -5;
+    const Z_NO_FLUSH: c_int = 0;
+    /// The largest window, plus 32 to detect gzip and zlib headers.
+    const WINDOW_BITS: c_int = 15 + 32;
+
+    pub(super) fn decompress(bytes: &[u8]) -> Result<Vec<u8>, PtxError> {
+        let not_found = PtxError::DecompressorNotFound(Compression::Gzip);
+        let lib = load_library(
+            Compression::Gzip,
+            &[
+                "libz.so.1",
+                "libz.so",
+                "libz.1.dylib",
+                "libz.dylib",
+                "zlib1.dll",
+                "zlib.dll",
+            ],
+        )?;
+        let (version, inflate_init2, inflate, inflate_end) = unsafe {
+            (
+                *lib.get::<Version>(b"zlibVersion\0")
+                    .map_err(|_| not_found)?,
+                *lib.get::<InflateInit2>(b"inflateInit2_\0")
+                    .map_err(|_| not_found)?,
+                *lib.get::<Inflate>(b"inflate\0").map_err(|_| not_found)?,
+                *lib.get::<InflateEnd>(b"inflateEnd\0")
+                    .map_err(|_| not_found)?,
+            )
+        };
+        // `bytes.len()` doesn't fit in `avail_in`
+        let Ok(avail_in) = c_uint::try_from(bytes.len()) else {
+            return Err(PtxError::Corrupt(Compression::Gzip));
+        };
+
+        let mut stream: ZStream = unsafe { core::mem::zeroed() };
+        stream.next_in = bytes.as_ptr();
+        stream.avail_in = avail_in;
+        let ret = unsafe {
+            inflate_init2(
+                &mut stream,
+                WINDOW_BITS,
+                version(),
+                core::mem::size_of::<ZStream>() as c_int,
+            )
+        };
+        if ret != Z_OK {
+            return Err(not_found);
+        }
+        let mut out = Vec::with_capacity(bytes.len() * 4);
+        let res = loop {
+            let buf = spare(&mut out);
+            let avail_out = buf.len().min(c_uint::MAX as usize) as c_uint;
+            stream.next_out = buf.as_mut_ptr() as *mut u8;
+            stream.avail_out = avail_out;
+            let ret = unsafe { inflate(&mut stream, Z_NO_FLUSH) };
+            unsafe { out.set_len(out.len() + (avail_out - stream.avail_out) as usize) };
+            match ret {
+                Z_STREAM_END => break Ok(out),
+                Z_OK => (),
+                // no progress because `out` was full, which the next iteration fixes
+                Z_BUF_ERROR if stream.avail_out == 0 => (),
+                _ => break Err(PtxError::Corrupt(Compression::Gzip)),
+            }
+        };
+        unsafe { inflate_end(&mut stream) };
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let src = "hello ptx\n".repeat(1000);
+        // `src` compressed with `zstd`
+        const DATA: &[u8] = &[
+            0x28, 0xb5, 0x2f, 0xfd, 0x60, 0x10, 0x26, 0x95, 0x00, 0x00, 0x50, 0x68, 0x65, 0x6c,
+            0x6c, 0x6f, 0x20, 0x70, 0x74, 0x78, 0x0a, 0x01, 0x00, 0x03, 0xa7, 0x7c, 0x41, 0x02,
+        ];
+        let out = Compression::Zstd.decompress(DATA).unwrap();
+        assert_eq!(std::string::String::from_utf8(out).unwrap(), src);
+        assert_eq!(
+            Compression::Zstd.decompress(&DATA[..DATA.len() - 5]),
+            Err(PtxError::Corrupt(Compression::Zstd))
+        );
+        assert_eq!(
+            Compression::Zstd.decompress(b"not zstd"),
+            Err(PtxError::Corrupt(Compression::Zstd))
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        let src = "hello ptx\n".repeat(1000);
+        // `src` compressed with `gzip`
+        const DATA: &[u8] = &[
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xed, 0xc6, 0xb1, 0x09,
+            0x00, 0x20, 0x0c, 0x00, 0xb0, 0xdd, 0x2b, 0x7c, 0x4e, 0xe8, 0x50, 0xd0, 0xa1, 0x43,
+            0xcf, 0xef, 0x1d, 0x42, 0x32, 0x25, 0x4e, 0xe6, 0xdd, 0xaf, 0x7a, 0x85, 0x99, 0x99,
+            0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99, 0x99,
+            0x99, 0x99, 0xd9, 0xb7, 0x1b, 0xeb, 0x04, 0x92, 0xfc, 0x10, 0x27, 0x00, 0x00,
+        ];
+        let out = Compression::Gzip.decompress(DATA).unwrap();
+        assert_eq!(std::string::String::from_utf8(out).unwrap(), src);
+        assert_eq!(
+            Compression::Gzip.decompress(&DATA[..DATA.len() - 4]),
+            Err(PtxError::Corrupt(Compression::Gzip))
+        );
+        assert_eq!(
+            Compression::Gzip.decompress(b"not gzip"),
+            Err(PtxError::Corrupt(Compression::Gzip))
+        );
+    }
+}
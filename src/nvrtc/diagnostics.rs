@@ -0,0 +1,355 @@
+//! Parsing of the nvrtc program log into [Diagnostic]s, see [CompileError::diagnostics()]
+//! and [compile_ptx_verbose()](super::compile_ptx_verbose).
+//!
+//! nvrtc reports diagnostics like `nvcc` does, e.g.
+//! ```text
+//! default_program(3): error: identifier "foo" is undefined
+//!       x[0] = foo;
+//!              ^
+//! ```
+//! Since CUDA 12 the offending source line and a caret are printed below each diagnostic,
+//! template errors are followed by `detected during ...` context lines, and warnings carry
+//! a number like `#177-D`. None of that is required, so logs of older versions parse too.
+
+use super::safe::CompileError;
+
+use std::{string::String, vec::Vec};
+
+/// How severe a [Diagnostic] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// A `catastrophic error` or `fatal error`, which stops compilation.
+    Fatal,
+    Error,
+    Warning,
+    Remark,
+    /// Context of another diagnostic, only found in [Diagnostic::notes].
+    Note,
+}
+
+/// A single message of the nvrtc program log.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// The file the diagnostic points into, `default_program` for the source passed to
+    /// nvrtc. `None` for notes without a location and messages about the whole program.
+    pub file: Option<String>,
+    /// 1-based line in `file`.
+    pub line: Option<u32>,
+    /// 1-based column in `line`, only known if nvrtc printed a caret under the source line.
+    pub column: Option<u32>,
+    pub severity: Severity,
+    /// The diagnostic number, e.g. `177-D`, which can be passed to `--diag-suppress`.
+    pub code: Option<String>,
+    /// The message, with continuation lines such as `argument types are: ...` joined by
+    /// `\n`.
+    pub message: String,
+    /// Notes attached to the diagnostic, e.g. the template instantiation it was found in.
+    pub notes: Vec<Diagnostic>,
+}
+
+impl CompileError {
+    /// The diagnostics of the program log, empty if the error isn't
+    /// [CompileError::CompileError].
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self.log() {
+            Some(log) => parse_diagnostics(&log.to_string_lossy()),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Parses a nvrtc program log. Lines that aren't part of any diagnostic, like the
+/// `1 error detected in the compilation of ...` summary, are skipped.
+pub fn parse_diagnostics(log: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = log.lines().map(|l| l.trim_end()).collect();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if line.is_empty() || is_summary(line) {
+            continue;
+        }
+
+        if let Some(diagnostic) = parse_header(line) {
+            match diagnostics.last_mut() {
+                Some(parent) if diagnostic.severity == Severity::Note => {
+                    parent.notes.push(diagnostic)
+                }
+                _ => diagnostics.push(diagnostic),
+            }
+            continue;
+        }
+
+        let Some(last) = diagnostics.last_mut() else {
+            continue;
+        };
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("detected during") {
+            let mut note = context_note(trimmed);
+            // older versions put the location on its own line, as `(14): here`
+            if let Some((file, line)) = lines.get(i).and_then(|next| parse_here(next)) {
+                note.file = file.or_else(|| last.file.clone());
+                note.line = Some(line);
+                i += 1;
+            } else if note.line.is_some() && note.file.is_none() {
+                note.file = last.file.clone();
+            }
+            last.notes.push(note);
+        } else if trimmed == "^" {
+            let target = innermost(last);
+            // the source line above the caret is indented by two spaces
+            let caret = line.len() - trimmed.len();
+            if target.column.is_none() && caret >= 2 {
+                target.column = Some((caret - 1) as u32);
+            }
+        } else if lines.get(i).is_some_and(|next| next.trim() == "^") {
+            // the source line the caret points into
+        } else if line.starts_with(char::is_whitespace) {
+            let target = innermost(last);
+            target.message.push('\n');
+            target.message.push_str(trimmed);
+        }
+    }
+    diagnostics
+}
+
+/// The last note of `diagnostic`, or itself if it has none.
+fn innermost(diagnostic: &mut Diagnostic) -> &mut Diagnostic {
+    if diagnostic.notes.is_empty() {
+        diagnostic
+    } else {
+        diagnostic.notes.last_mut().unwrap()
+    }
+}
+
+fn is_summary(line: &str) -> bool {
+    line.contains(" detected in the compilation of ") || line == "Compilation terminated."
+}
+
+/// Parses `file(line): severity #code: message`, where the location and code are
+/// optional.
+fn parse_header(line: &str) -> Option<Diagnostic> {
+    let (file, line_no, rest) = match split_location(line) {
+        Some((file, line_no, rest)) => (file, Some(line_no), rest),
+        None => (None, None, line),
+    };
+
+    let lower = rest.to_ascii_lowercase();
+    let (severity, len) = [
+        ("catastrophic error", Severity::Fatal),
+        ("fatal error", Severity::Fatal),
+        ("error", Severity::Error),
+        ("warning", Severity::Warning),
+        ("remark", Severity::Remark),
+        ("note", Severity::Note),
+    ]
+    .into_iter()
+    .find(|(name, _)| lower.starts_with(name))
+    .map(|(name, severity)| (severity, name.len()))?;
+
+    let rest = &rest[len..];
+    let (code, rest) = match rest.strip_prefix(" #") {
+        Some(rest) => {
+            let end = rest.find(':')?;
+            (Some(String::from(&rest[..end])), &rest[end..])
+        }
+        None => (None, rest),
+    };
+    let message = rest.strip_prefix(':')?.trim();
+    Some(Diagnostic {
+        file,
+        line: line_no,
+        column: None,
+        severity,
+        code,
+        message: message.into(),
+        notes: Vec::new(),
+    })
+}
+
+/// Splits `file(line): rest`. The file may be empty, or contain parentheses itself.
+fn split_location(line: &str) -> Option<(Option<String>, u32, &str)> {
+    let mut search = line;
+    while let Some(open) = search.find('(') {
+        let offset = line.len() - search.len();
+        let after = &search[open + 1..];
+        if let Some(close) = after.find("): ") {
+            if let Ok(line_no) = after[..close].parse::<u32>() {
+                let file = &line[..offset + open];
+                let file = (!file.is_empty()).then(|| String::from(file));
+                return Some((file, line_no, &after[close + 3..]));
+            }
+        }
+        search = after;
+    }
+    None
+}
+
+/// A `default_program(14): here` line following a context note.
+fn parse_here(line: &str) -> Option<(Option<String>, u32)> {
+    let (file, line_no, rest) = split_location(line.trim_start())?;
+    (rest.trim() == "here").then_some((file, line_no))
+}
+
+/// A note for `detected during instantiation of "..." at line 14 of default_program`.
+fn context_note(text: &str) -> Diagnostic {
+    let mut note = Diagnostic {
+        file: None,
+        line: None,
+        column: None,
+        severity: Severity::Note,
+        code: None,
+        message: text.into(),
+        notes: Vec::new(),
+    };
+    if let Some(at) = text.rfind(" at line ") {
+        let location = &text[at + " at line ".len()..];
+        let (line_no, file) = match location.split_once(" of ") {
+            Some((line_no, file)) => (line_no, Some(file)),
+            None => (location, None),
+        };
+        if let Ok(line_no) = line_no.parse() {
+            note.line = Some(line_no);
+            note.file = file.map(String::from);
+            note.message = text[..at].into();
+        }
+    }
+    note
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Captured from nvrtc 11.8.
+    const LOG_11_8: &str = r#"default_program(4): warning #177-D: variable "unused" was declared but never referenced
+
+default_program(5): error: identifier "undefined_var" is undefined
+
+default_program(11): error: no instance of function template "scale" matches the argument list
+            argument types are: (float *, int *)
+          detected during instantiation of "void launch<T>(T *) [with T=float]"
+(16): here
+
+2 errors detected in the compilation of "default_program".
+"#;
+
+    /// Captured from nvrtc 12.4.
+    const LOG_12_4: &str = r#"default_program(4): warning #177-D: variable "unused" was declared but never referenced
+      int unused;
+          ^
+
+Remark: The warnings can be suppressed with "-diag-suppress <warning-number>"
+
+default_program(5): error: identifier "undefined_var" is undefined
+      x[0] = undefined_var;
+             ^
+
+default_program(11): error: no instance of function template "scale" matches the argument list
+            argument types are: (float *, int *)
+      scale(x, y);
+      ^
+          detected during instantiation of "void launch<T>(T *) [with T=float]" at line 16
+
+2 errors detected in the compilation of "default_program".
+"#;
+
+    #[test]
+    fn test_parse_log_11_8() {
+        let diagnostics = parse_diagnostics(LOG_11_8);
+        assert_eq!(diagnostics.len(), 3);
+
+        let warning = &diagnostics[0];
+        assert_eq!(warning.file.as_deref(), Some("default_program"));
+        assert_eq!(warning.line, Some(4));
+        assert_eq!(warning.column, None);
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.code.as_deref(), Some("177-D"));
+        assert_eq!(
+            warning.message,
+            "variable \"unused\" was declared but never referenced"
+        );
+
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(diagnostics[1].code, None);
+        assert_eq!(diagnostics[1].line, Some(5));
+
+        let template = &diagnostics[2];
+        assert_eq!(
+            template.message,
+            "no instance of function template \"scale\" matches the argument list\nargument types are: (float *, int *)"
+        );
+        assert_eq!(template.notes.len(), 1);
+        let note = &template.notes[0];
+        assert_eq!(note.severity, Severity::Note);
+        assert_eq!(note.file.as_deref(), Some("default_program"));
+        assert_eq!(note.line, Some(16));
+        assert!(note.message.starts_with("detected during instantiation of"));
+    }
+
+    #[test]
+    fn test_parse_log_12_4() {
+        let diagnostics = parse_diagnostics(LOG_12_4);
+        assert_eq!(diagnostics.len(), 4);
+
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].column, Some(9));
+
+        let remark = &diagnostics[1];
+        assert_eq!(remark.severity, Severity::Remark);
+        assert_eq!(remark.file, None);
+        assert_eq!(remark.line, None);
+
+        assert_eq!(
+            diagnostics[2].message,
+            "identifier \"undefined_var\" is undefined"
+        );
+        assert_eq!(diagnostics[2].column, Some(12));
+
+        let template = &diagnostics[3];
+        assert_eq!(template.column, Some(5));
+        assert_eq!(
+            template.message,
+            "no instance of function template \"scale\" matches the argument list\nargument types are: (float *, int *)"
+        );
+        let note = &template.notes[0];
+        assert_eq!(note.line, Some(16));
+        assert_eq!(note.file.as_deref(), Some("default_program"));
+        assert_eq!(
+            note.message,
+            "detected during instantiation of \"void launch<T>(T *) [with T=float]\""
+        );
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let fatal = parse_header(
+            "/usr/include/my (lib)/x.cuh(2): catastrophic error: cannot open source file \"y.h\"",
+        )
+        .unwrap();
+        assert_eq!(fatal.file.as_deref(), Some("/usr/include/my (lib)/x.cuh"));
+        assert_eq!(fatal.line, Some(2));
+        assert_eq!(fatal.severity, Severity::Fatal);
+
+        let note = parse_header("default_program(3): note #3326-D: declared here").unwrap();
+        assert_eq!(note.severity, Severity::Note);
+        assert_eq!(note.code.as_deref(), Some("3326-D"));
+        assert_eq!(note.message, "declared here");
+
+        assert!(parse_header("1 error detected in the compilation of \"x\".").is_none());
+        assert!(parse_header("      x[0] = foo;").is_none());
+        assert!(parse_header("(16): here").is_none());
+    }
+
+    #[test]
+    fn test_compile_error_diagnostics() {
+        let err =
+            crate::nvrtc::compile_ptx("extern \"C\" __global__ void k() { foo(); }").unwrap_err();
+        let diagnostics = err.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].line, Some(1));
+        assert!(err.log().is_some());
+    }
+}
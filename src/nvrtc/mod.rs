@@ -1,11 +1,19 @@
 //! Wrappers around the [Nvidia Runtime Compilation (nvrtc) API](https://docs.nvidia.com/cuda/nvrtc/index.html),
 //! in three levels. See crate documentation for description of each.
 //!
-//! Call [compile_ptx()] or [compile_ptx_with_opts()].
+//! Call [compile_ptx()] or [compile_ptx_with_opts()], or [compile_ptx_verbose()] to also
+//! get the warnings.
 
+#[cfg(feature = "std")]
+pub mod cache;
+mod compression;
+mod diagnostics;
 pub mod result;
 pub mod safe;
 #[allow(warnings)]
 pub mod sys;
 
+#[cfg(feature = "std")]
+pub use cache::{CacheReport, PtxCache};
+pub use diagnostics::{parse_diagnostics, Diagnostic, Severity};
 pub use safe::*;
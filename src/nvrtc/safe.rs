@@ -2,21 +2,29 @@
 //!
 //! Call [compile_ptx()] or [compile_ptx_with_opts()].
 
+use super::diagnostics::{parse_diagnostics, Diagnostic};
 use super::{result, sys};
 
 use core::ffi::{c_char, CStr};
 use std::ffi::CString;
-use std::{borrow::ToOwned, path::PathBuf, string::String, vec::Vec};
+use std::{
+    borrow::{Cow, ToOwned},
+    path::PathBuf,
+    string::String,
+    vec::Vec,
+};
 
 /// An opaque structure representing a compiled PTX program
 /// output from [compile_ptx()] or [compile_ptx_with_opts()].
 ///
-/// Can also be created from a [Ptx::from_file] and [Ptx::from_src]
+/// Can also be created from a [Ptx::from_file], [Ptx::from_src] and [Ptx::from_compressed]
 #[derive(Debug, Clone)]
 pub struct Ptx(pub(crate) PtxKind);
 
 impl Ptx {
-    /// Creates a Ptx from a pre-compiled .ptx file.
+    /// Creates a Ptx from a pre-compiled .ptx file. The file isn't opened here, but each
+    /// time the ptx is loaded or [Ptx::to_src()] is called; reading it fails with
+    /// [PtxError::Io].
     pub fn from_file<P: Into<PathBuf>>(path: P) -> Self {
         Self(PtxKind::File(path.into()))
     }
@@ -27,27 +35,124 @@ impl Ptx {
         Self(PtxKind::Src(src.into()))
     }
 
-    /// Get the compiled source as a string.
-    pub fn to_src(&self) -> String {
+    /// Creates a Ptx from compressed ptx, cubin or fatbin, e.g. embedded with
+    /// `include_bytes!`. It is decompressed when it is loaded, fails with [PtxError::Corrupt]
+    /// if `bytes` aren't valid `compression` data.
+    ///
+    /// Decompression calls into the system's `libzstd` or `libz`, which are loaded at
+    /// runtime like the cuda libraries.
+    pub fn from_compressed<B: Into<Cow<'static, [u8]>>>(
+        bytes: B,
+        compression: Compression,
+    ) -> Self {
+        Self(PtxKind::Compressed(bytes.into(), compression))
+    }
+
+    /// Get the compiled source as a string. Reads the file of a [Ptx::from_file()] and
+    /// decompresses a [Ptx::from_compressed()], failing like loading them would.
+    pub fn to_src(&self) -> Result<String, PtxError> {
         match &self.0 {
-            PtxKind::Image(bytes) => unsafe { CStr::from_ptr(bytes.as_ptr()) }
+            PtxKind::Image(bytes) => Ok(unsafe { CStr::from_ptr(bytes.as_ptr()) }
                 .to_str()
                 .expect("Unable to convert bytes to str.")
-                .to_owned(),
-            PtxKind::Src(src) => src.clone(),
-            PtxKind::File(path) => {
-                std::fs::read_to_string(path).expect("Unable to read ptx from file.")
-            }
+                .to_owned()),
+            PtxKind::Src(src) => Ok(src.clone()),
+            #[cfg(feature = "no-std")]
+            PtxKind::File(path) => std::fs::read_to_string(path).map_err(|e| PtxError::Io {
+                path: std::format!("{}", path.display()).into(),
+                kind: e.kind(),
+            }),
+            _ => self.clone().read()?.to_src(),
         }
     }
+
+    /// Reads the file of a [Ptx::from_file()] and decompresses a [Ptx::from_compressed()], so
+    /// that the contents are in memory when they're passed to the driver.
+    pub(crate) fn read(self) -> Result<Self, PtxError> {
+        let bytes = match self.0 {
+            #[cfg(not(feature = "no-std"))]
+            PtxKind::File(path) => std::fs::read(&path).map_err(|e| PtxError::Io {
+                path: path.display().to_string().into(),
+                kind: e.kind(),
+            })?,
+            PtxKind::Compressed(bytes, compression) => compression.decompress(&bytes)?,
+            kind => return Ok(Self(kind)),
+        };
+        let image = bytes.into_iter().map(|b| b as c_char).chain([0]).collect();
+        Ok(Self(PtxKind::Image(image)))
+    }
+
+    /// The bytes to pass to the driver: the text of the ptx with a trailing nul, or the
+    /// cubin or fatbin image, which may contain nuls itself. Call [Ptx::read()] first.
+    pub(crate) fn into_image(self) -> Result<Vec<u8>, PtxError> {
+        Ok(match self.0 {
+            PtxKind::Image(image) => image.into_iter().map(|c| c as u8).collect(),
+            PtxKind::Src(src) => src.into_bytes().into_iter().chain([0]).collect(),
+            kind => Self(kind)
+                .to_src()?
+                .into_bytes()
+                .into_iter()
+                .chain([0])
+                .collect(),
+        })
+    }
 }
 
+/// The format of the bytes passed to [Ptx::from_compressed()]. Each variant needs the
+/// feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// A zstd frame, decompressed with `libzstd`.
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// A gzip (or zlib) stream, decompressed with `libz`.
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// Why the contents of a [Ptx] couldn't be read before loading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PtxError {
+    /// Reading the file of a [Ptx::from_file()] at `path` failed.
+    Io {
+        path: std::sync::Arc<str>,
+        kind: std::io::ErrorKind,
+    },
+    /// The bytes of a [Ptx::from_compressed()] are corrupted or truncated.
+    Corrupt(Compression),
+    /// The library used to decompress a [Ptx::from_compressed()] couldn't be loaded.
+    DecompressorNotFound(Compression),
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for PtxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PtxError {}
+
 impl<S: Into<String>> From<S> for Ptx {
     fn from(value: S) -> Self {
         Self::from_src(value)
     }
 }
 
+/// Includes the `.ptx` written to `OUT_DIR` by [crate::build::Builder] for `$name`,
+/// the stem of the compiled `.cu` file, as a [Ptx].
+///
+/// ```ignore
+/// dev.load_ptx(cudarc::load_compiled_ptx!("reduce"), "reduce", &["reduce"])?;
+/// ```
+#[macro_export]
+macro_rules! load_compiled_ptx {
+    ($name:literal) => {
+        $crate::nvrtc::Ptx::from_src(include_str!(concat!(env!("OUT_DIR"), "/", $name, ".ptx")))
+    };
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum PtxKind {
     /// An image created by [compile_ptx]
@@ -58,6 +163,9 @@ pub(crate) enum PtxKind {
 
     /// Path to a compiled ptx
     File(PathBuf),
+
+    /// Compressed ptx, cubin or fatbin
+    Compressed(Cow<'static, [u8]>, Compression),
 }
 
 /// Calls [compile_ptx_with_opts] with no options. `src` is the source string
@@ -92,6 +200,61 @@ pub fn compile_ptx_with_opts<S: AsRef<str>>(
     prog.compile(opts)
 }
 
+/// A [Ptx] along with the warnings nvrtc reported while compiling it, see
+/// [compile_ptx_verbose()].
+#[derive(Debug, Clone)]
+pub struct CompiledPtx {
+    pub ptx: Ptx,
+    /// Every diagnostic of the program log, which can only be warnings and remarks as
+    /// compilation succeeded.
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// [compile_ptx_with_opts()], also returning the warnings that it drops.
+///
+/// Example:
+/// ```rust
+/// # use cudarc::nvrtc::*;
+/// let src = "extern \"C\" __global__ void kernel() { int unused; }";
+/// let compiled = compile_ptx_verbose(src, Default::default()).unwrap();
+/// for warning in &compiled.warnings {
+///     println!("{:?}:{:?}: {}", warning.file, warning.line, warning.message);
+/// }
+/// ```
+pub fn compile_ptx_verbose<S: AsRef<str>>(
+    src: S,
+    opts: CompileOptions,
+) -> Result<CompiledPtx, CompileError> {
+    let prog = Program::create(src)?;
+    let (ptx, log) = prog.compile_with_log(opts)?;
+    Ok(CompiledPtx {
+        ptx,
+        warnings: parse_diagnostics(&log.to_string_lossy()),
+    })
+}
+
+/// A hash of the source, options and nvrtc version that [compile_ptx_with_opts()] is called
+/// with, stable across runs so it can key on-disk caches. The contents of included headers
+/// are not hashed, as which files nvrtc opens isn't known before compiling.
+pub(crate) fn compile_key(src: &str, opts: &CompileOptions) -> u64 {
+    // FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut write = |bytes: &[u8]| {
+        for &b in bytes.iter().chain(&[0]) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    write(src.as_bytes());
+    for option in opts.clone().build() {
+        write(option.as_bytes());
+    }
+    if let Ok((major, minor)) = result::version() {
+        write(std::format!("nvrtc {major}.{minor}").as_bytes());
+    }
+    hash
+}
+
 pub(crate) struct Program {
     prog: sys::nvrtcProgram,
 }
@@ -103,22 +266,35 @@ impl Program {
     }
 
     pub(crate) fn compile(self, opts: CompileOptions) -> Result<Ptx, CompileError> {
+        self.compile_with_log(opts).map(|(ptx, _)| ptx)
+    }
+
+    /// Compiles the program, returning the log along with the ptx.
+    pub(crate) fn compile_with_log(
+        self,
+        opts: CompileOptions,
+    ) -> Result<(Ptx, CString), CompileError> {
         let options = opts.build();
 
-        unsafe { result::compile_program(self.prog, &options) }.map_err(|e| {
-            let log_raw = unsafe { result::get_program_log(self.prog) }.unwrap();
-            let log_ptr = log_raw.as_ptr();
-            let log = unsafe { CStr::from_ptr(log_ptr) }.to_owned();
-            CompileError::CompileError {
-                nvrtc: e,
+        if let Err(nvrtc) = unsafe { result::compile_program(self.prog, &options) } {
+            return Err(CompileError::CompileError {
+                nvrtc,
                 options,
-                log,
-            }
-        })?;
+                log: self.log()?,
+            });
+        }
+        let log = self.log()?;
 
         let image = unsafe { result::get_ptx(self.prog) }.map_err(CompileError::GetPtxError)?;
 
-        Ok(Ptx(PtxKind::Image(image)))
+        Ok((Ptx(PtxKind::Image(image)), log))
+    }
+
+    fn log(&self) -> Result<CString, CompileError> {
+        let log_raw =
+            unsafe { result::get_program_log(self.prog) }.map_err(CompileError::GetLogError)?;
+        let log_ptr = log_raw.as_ptr();
+        Ok(unsafe { CStr::from_ptr(log_ptr) }.to_owned())
     }
 }
 
@@ -154,6 +330,17 @@ pub enum CompileError {
     DestroyError(result::NvrtcError),
 }
 
+impl CompileError {
+    /// The raw program log of a [CompileError::CompileError], see
+    /// [CompileError::diagnostics()] for the parsed one.
+    pub fn log(&self) -> Option<&CStr> {
+        match self {
+            Self::CompileError { log, .. } => Some(log),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(feature = "std")]
 impl std::fmt::Display for CompileError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -257,6 +444,46 @@ mod tests {
         compile_ptx_with_opts(SRC, Default::default()).unwrap();
     }
 
+    #[test]
+    fn test_compile_verbose_warnings() {
+        let src = "extern \"C\" __global__ void kernel() {\n    int unused;\n}";
+        let compiled = compile_ptx_verbose(src, Default::default()).unwrap();
+        let warning = compiled
+            .warnings
+            .iter()
+            .find(|d| d.severity == crate::nvrtc::Severity::Warning)
+            .unwrap();
+        assert_eq!(warning.line, Some(2));
+        assert!(warning.message.contains("unused"));
+        assert!(compiled.ptx.to_src().unwrap().contains(".entry kernel"));
+    }
+
+    #[test]
+    fn test_ptx_read() {
+        let err = Ptx::from_file("does/not/exist.ptx").read().unwrap_err();
+        assert_eq!(
+            err,
+            PtxError::Io {
+                path: "does/not/exist.ptx".into(),
+                kind: std::io::ErrorKind::NotFound,
+            }
+        );
+
+        let path = std::env::temp_dir().join("cudarc_test_ptx_read.ptx");
+        std::fs::write(&path, ".version 7.0").unwrap();
+        let ptx = Ptx::from_file(&path).read().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ptx.to_src().unwrap(), ".version 7.0");
+        assert_eq!(ptx.into_image().unwrap(), b".version 7.0\0");
+
+        // e.g. a cubin, which has nuls of its own
+        let path = std::env::temp_dir().join("cudarc_test_ptx_read.cubin");
+        std::fs::write(&path, b"\x7fELF\0\x01\0").unwrap();
+        let image = Ptx::from_file(&path).read().unwrap().into_image().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image, b"\x7fELF\0\x01\0\0");
+    }
+
     #[test]
     fn test_compile_options_build_none() {
         let opts: CompileOptions = Default::default();
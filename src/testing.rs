@@ -0,0 +1,352 @@
+//! Assertions for the results of kernels in tests, enabled with the `testing` feature.
+//!
+//! [assert_close()] downloads a slice and compares it elementwise with the expected values
+//! within a relative and an absolute tolerance, like `torch.testing.assert_close`. On
+//! failure it panics with the first mismatches and a histogram of the errors, see
+//! [CloseReport]. Enable the feature only for tests of a dependent crate:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! cudarc = { version = "*", features = ["testing", "f16"] }
+//! ```
+//!
+//! ```rust
+//! # use cudarc::{driver::*, testing::*};
+//! let dev = CudaDevice::new(0).unwrap();
+//! let x = dev.htod_sync_copy(&[1.0f32, 2.0, f32::NAN, f32::INFINITY]).unwrap();
+//! assert_close(&dev, &x, &[1.0, 2.000001, f32::NAN, f32::INFINITY], 1e-5, 1e-8);
+//!
+//! let report = check_close(&dev, &x, &[1.0, 2.5, f32::NAN, f32::INFINITY], 1e-5, 1e-8).unwrap();
+//! assert_eq!(report.num_mismatches, 1);
+//! println!("{report}");
+//! ```
+//!
+//! `f16` and `bf16` are compared as `f64` like the other types, and [to_f32_vec()] downloads
+//! them as `f32` for comparisons of their own.
+
+use crate::driver::{CudaDevice, CudaSlice, DevicePtr, DeviceRepr, DriverError};
+
+use core::fmt;
+use std::sync::Arc;
+use std::vec::Vec;
+
+/// How many mismatches a [CloseReport] keeps.
+pub const MAX_REPORTED_MISMATCHES: usize = 10;
+
+/// The bounds of the buckets of [CloseReport::histogram], from `1e-8` up to `1`.
+const HISTOGRAM_DECADES: [f64; 9] = [1e-8, 1e-7, 1e-6, 1e-5, 1e-4, 1e-3, 1e-2, 1e-1, 1e0];
+
+/// The number of buckets of [CloseReport::histogram]: below `1e-8`, one per decade up to
+/// `1`, `1` and above, and non-finite.
+pub const HISTOGRAM_BUCKETS: usize = HISTOGRAM_DECADES.len() + 2;
+
+/// The element types compared by [assert_close()].
+pub trait CloseDtype: DeviceRepr + Copy {
+    fn to_f64(self) -> f64;
+}
+
+impl CloseDtype for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl CloseDtype for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+#[cfg(feature = "f16")]
+impl CloseDtype for half::f16 {
+    fn to_f64(self) -> f64 {
+        half::f16::to_f64(self)
+    }
+}
+
+#[cfg(feature = "f16")]
+impl CloseDtype for half::bf16 {
+    fn to_f64(self) -> f64 {
+        half::bf16::to_f64(self)
+    }
+}
+
+/// An element of a [CloseReport] that isn't close to the expected value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch {
+    pub index: usize,
+    pub actual: f64,
+    pub expected: f64,
+}
+
+impl Mismatch {
+    pub fn abs_err(&self) -> f64 {
+        (self.actual - self.expected).abs()
+    }
+
+    /// The [Mismatch::abs_err()] relative to the expected value.
+    pub fn rel_err(&self) -> f64 {
+        self.abs_err() / self.expected.abs()
+    }
+}
+
+/// The result of comparing values elementwise, see [compare()].
+///
+/// An element is close to the expected one if `|actual - expected| <= atol + rtol *
+/// |expected|`. NaNs are only close to NaNs, and infinities to the same infinities.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseReport {
+    pub actual_len: usize,
+    pub expected_len: usize,
+    pub rtol: f64,
+    pub atol: f64,
+    /// The elements that aren't close, of the first `min(actual_len, expected_len)`.
+    pub num_mismatches: usize,
+    /// The first [MAX_REPORTED_MISMATCHES] of them.
+    pub mismatches: Vec<Mismatch>,
+    /// The largest absolute error of the finite elements, and its index.
+    pub max_abs_err: (f64, usize),
+    /// The largest relative error of the finite elements, and its index.
+    pub max_rel_err: (f64, usize),
+    /// The number of elements by absolute error: below `1e-8`, in each decade up to `1`,
+    /// `1` and above, and last where only one of the values is NaN or infinite.
+    pub histogram: [usize; HISTOGRAM_BUCKETS],
+}
+
+impl CloseReport {
+    /// Whether the lengths match and all the elements are close.
+    pub fn passed(&self) -> bool {
+        self.actual_len == self.expected_len && self.num_mismatches == 0
+    }
+}
+
+impl fmt::Display for CloseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.actual_len != self.expected_len {
+            writeln!(
+                f,
+                "{} elements, but {} were expected",
+                self.actual_len, self.expected_len
+            )?;
+        }
+        writeln!(
+            f,
+            "{} of {} elements are not within atol {:e} + rtol {:e} * |expected|",
+            self.num_mismatches,
+            self.actual_len.min(self.expected_len),
+            self.atol,
+            self.rtol
+        )?;
+        let ((abs, abs_at), (rel, rel_at)) = (self.max_abs_err, self.max_rel_err);
+        writeln!(
+            f,
+            "max abs error {abs:e} at [{abs_at}], max rel error {rel:e} at [{rel_at}]"
+        )?;
+        if !self.mismatches.is_empty() {
+            writeln!(f, "first mismatches:")?;
+        }
+        for m in self.mismatches.iter() {
+            writeln!(
+                f,
+                "  [{}] actual {:e}, expected {:e} (abs error {:e}, rel error {:e})",
+                m.index,
+                m.actual,
+                m.expected,
+                m.abs_err(),
+                m.rel_err()
+            )?;
+        }
+        writeln!(f, "abs error histogram:")?;
+        for (i, &count) in self.histogram.iter().enumerate() {
+            let bucket = match i {
+                0 => std::format!("< {:e}", HISTOGRAM_DECADES[0]),
+                _ if i < HISTOGRAM_DECADES.len() => std::format!(
+                    "{:e} .. {:e}",
+                    HISTOGRAM_DECADES[i - 1],
+                    HISTOGRAM_DECADES[i]
+                ),
+                _ if i == HISTOGRAM_DECADES.len() => {
+                    std::format!(">= {:e}", HISTOGRAM_DECADES[i - 1])
+                }
+                _ => "non-finite".into(),
+            };
+            writeln!(f, "  {bucket:>16}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares `actual` with `expected` elementwise, see [CloseReport].
+pub fn compare<T: CloseDtype>(actual: &[T], expected: &[T], rtol: f64, atol: f64) -> CloseReport {
+    let mut report = CloseReport {
+        actual_len: actual.len(),
+        expected_len: expected.len(),
+        rtol,
+        atol,
+        num_mismatches: 0,
+        mismatches: Vec::new(),
+        max_abs_err: (0.0, 0),
+        max_rel_err: (0.0, 0),
+        histogram: [0; HISTOGRAM_BUCKETS],
+    };
+    for (index, (a, e)) in actual.iter().zip(expected.iter()).enumerate() {
+        let (a, e) = (a.to_f64(), e.to_f64());
+        let close = if a.is_finite() && e.is_finite() {
+            let abs = (a - e).abs();
+            let rel = if abs == 0.0 { 0.0 } else { abs / e.abs() };
+            if abs > report.max_abs_err.0 {
+                report.max_abs_err = (abs, index);
+            }
+            if rel > report.max_rel_err.0 {
+                report.max_rel_err = (rel, index);
+            }
+            let bucket = HISTOGRAM_DECADES
+                .iter()
+                .position(|&bound| abs < bound)
+                .unwrap_or(HISTOGRAM_DECADES.len());
+            report.histogram[bucket] += 1;
+            abs <= atol + rtol * e.abs()
+        } else {
+            // equal infinities, or both NaN
+            let close = a == e || (a.is_nan() && e.is_nan());
+            let bucket = if close { 0 } else { HISTOGRAM_BUCKETS - 1 };
+            report.histogram[bucket] += 1;
+            close
+        };
+        if !close {
+            report.num_mismatches += 1;
+            if report.mismatches.len() < MAX_REPORTED_MISMATCHES {
+                report.mismatches.push(Mismatch {
+                    index,
+                    actual: a,
+                    expected: e,
+                });
+            }
+        }
+    }
+    report
+}
+
+/// Downloads `actual` and [compare()]s it with `expected`.
+pub fn check_close<T: CloseDtype, Src: DevicePtr<T>>(
+    device: &Arc<CudaDevice>,
+    actual: &Src,
+    expected: &[T],
+    rtol: f64,
+    atol: f64,
+) -> Result<CloseReport, DriverError> {
+    let actual = device.dtoh_sync_copy(actual)?;
+    Ok(compare(&actual, expected, rtol, atol))
+}
+
+/// Panics with the [CloseReport] if `actual` doesn't have the length of `expected`, or
+/// any of its elements isn't close to the expected one, see [check_close()].
+#[track_caller]
+pub fn assert_close<T: CloseDtype, Src: DevicePtr<T>>(
+    device: &Arc<CudaDevice>,
+    actual: &Src,
+    expected: &[T],
+    rtol: f64,
+    atol: f64,
+) {
+    let report = match check_close(device, actual, expected, rtol, atol) {
+        Ok(report) => report,
+        Err(e) => panic!("assert_close failed to download the values: {e:?}"),
+    };
+    if !report.passed() {
+        panic!("assert_close failed: {report}");
+    }
+}
+
+/// Downloads `slice` as `f32`s, e.g. of `f16` or `bf16` elements.
+pub fn to_f32_vec<T: CloseDtype>(slice: &CudaSlice<T>) -> Result<Vec<f32>, DriverError> {
+    let values = slice.device().dtoh_sync_copy(slice)?;
+    Ok(values.into_iter().map(|v| v.to_f64() as f32).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{format, vec};
+
+    #[test]
+    fn test_compare_tolerances() {
+        let report = compare(&[1.0f32, 2.0, 100.5], &[1.0, 2.01, 100.0], 1e-2, 0.0);
+        assert!(report.passed());
+        assert_eq!(report.max_abs_err, (0.5, 2));
+        assert_eq!(report.histogram[0], 1);
+        assert_eq!(report.histogram[6], 1);
+        assert_eq!(report.histogram[8], 1);
+
+        let report = compare(&[0.0f64, 1e-3], &[1e-9, 0.0], 0.0, 1e-6);
+        assert_eq!(report.num_mismatches, 1);
+        assert_eq!(report.mismatches[0].index, 1);
+        assert_eq!(report.histogram[0], 1);
+        assert_eq!(report.histogram[6], 1);
+        assert_eq!(report.max_rel_err, (f64::INFINITY, 1));
+    }
+
+    #[test]
+    fn test_compare_non_finite() {
+        let (nan, inf) = (f32::NAN, f32::INFINITY);
+        let actual = [nan, inf, -inf, nan, inf, 1.0];
+        let expected = [nan, inf, inf, 1.0, 1.0, nan];
+        let report = compare(&actual, &expected, 1.0, 1.0);
+        assert_eq!(report.num_mismatches, 4);
+        let indices: Vec<usize> = report.mismatches.iter().map(|m| m.index).collect();
+        assert_eq!(indices, [2, 3, 4, 5]);
+        assert_eq!(report.histogram[0], 2);
+        assert_eq!(report.histogram[HISTOGRAM_BUCKETS - 1], 4);
+        assert_eq!(report.max_abs_err, (0.0, 0));
+    }
+
+    #[test]
+    fn test_report() {
+        let expected = vec![1.0f32; 30];
+        let mut actual = expected.clone();
+        for v in actual.iter_mut().skip(5).step_by(2) {
+            *v = 1.5;
+        }
+        let report = compare(&actual[..29], &expected, 1e-3, 1e-5);
+        assert!(!report.passed());
+        assert_eq!(report.num_mismatches, 12);
+        assert_eq!(report.mismatches.len(), MAX_REPORTED_MISMATCHES);
+        assert_eq!(report.max_rel_err, (0.5, 5));
+        let printed = format!("{report}");
+        assert!(printed.contains("29 elements, but 30 were expected"));
+        assert!(printed.contains("12 of 29 elements"));
+        assert!(printed.contains("[5] actual 1.5e0, expected 1e0"));
+        assert!(!printed.contains("[25]"));
+        assert!(compare::<f32>(&[], &[1.0], 0.0, 0.0).num_mismatches == 0);
+        assert!(!compare::<f32>(&[], &[1.0], 0.0, 0.0).passed());
+    }
+
+    #[test]
+    fn test_assert_close_on_device() {
+        let dev = CudaDevice::new(0).unwrap();
+        let x = dev.htod_sync_copy(&[1.0f32, -2.0, f32::NAN]).unwrap();
+        assert_close(&dev, &x, &[1.0, -2.0, f32::NAN], 0.0, 0.0);
+        let report = check_close(&dev, &x, &[1.0, -2.5, 0.0], 1e-3, 1e-5).unwrap();
+        assert_eq!(report.num_mismatches, 2);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            assert_close(&dev, &x, &[1.0; 3], 0.0, 0.0)
+        }));
+        assert!(res.is_err());
+
+        #[cfg(feature = "f16")]
+        {
+            let values = [0.1f32, 1.0, 65504.0];
+            let x = dev
+                .htod_sync_copy(&values.map(half::f16::from_f32))
+                .unwrap();
+            assert_close(&dev, &x, &values.map(half::f16::from_f32), 0.0, 0.0);
+            assert_eq!(to_f32_vec(&x).unwrap(), [0.099975586, 1.0, 65504.0]);
+            let x = dev
+                .htod_sync_copy(&values.map(half::bf16::from_f32))
+                .unwrap();
+            let bf16 = to_f32_vec(&x).unwrap();
+            assert_close(&dev, &x, &values.map(half::bf16::from_f32), 0.0, 0.0);
+            assert!(compare(&bf16, &values, 1e-2, 0.0).passed());
+        }
+    }
+}